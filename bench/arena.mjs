@@ -0,0 +1,84 @@
+/**
+ * Result arena growth on a large diff, raw ABI.
+ *
+ *   node bench/arena.mjs            # 200k entries against pkg/diffcore.wasm
+ *   DIFFCORE_WASM=... N=50000 node bench/arena.mjs
+ *
+ * Diffs `[0,0,…]` against `[1,1,…]` (one Modified entry per element)
+ * under each `arena_growth` and prints finalize time with the
+ * `get_arena_stats` accounting. `reserve` must move the buffer at most
+ * once (the 16-byte header into the reserved block); exits non-zero
+ * otherwise.
+ */
+
+import { readFileSync } from 'fs';
+import { performance } from 'perf_hooks';
+
+const WASM_PATH = process.env.DIFFCORE_WASM ?? new URL('../pkg/diffcore.wasm', import.meta.url);
+const N = Number(process.env.N ?? 200_000);
+const RUNS = 5;
+
+const { instance } = await WebAssembly.instantiate(readFileSync(WASM_PATH), {});
+const abi = instance.exports;
+const left = new TextEncoder().encode(`[${Array(N).fill('0').join(',')}]`);
+const right = new TextEncoder().encode(`[${Array(N).fill('1').join(',')}]`);
+
+/** 33-byte config: defaults, 64 MB arena and inputs, the given growth (byte 32). */
+function config(growth) {
+    const bytes = new Uint8Array(33);
+    const view = new DataView(bytes.buffer);
+    view.setUint32(0, 64 * 1024 * 1024, true);
+    view.setUint32(4, 64 * 1024 * 1024, true);
+    view.setUint32(8, 100_000, true);
+    view.setUint16(13, 64, true);
+    view.setUint32(15, 1024, true);
+    bytes[32] = growth;
+    return bytes;
+}
+
+function run(growth) {
+    // Same scratch address the JS glue uses for the config.
+    new Uint8Array(abi.memory.buffer).set(config(growth), 1024);
+    const engine = abi.create_engine(1024, 33);
+    if (engine === 0) throw new Error('create_engine failed');
+    for (const [bytes, ptr, commit] of [
+        [left, abi.get_left_input_ptr(engine), abi.commit_left],
+        [right, abi.get_right_input_ptr(engine), abi.commit_right],
+    ]) {
+        new Uint8Array(abi.memory.buffer, ptr, bytes.length).set(bytes);
+        commit(engine, bytes.length);
+    }
+    const t = performance.now();
+    if (abi.finalize(engine) === 0) throw new Error('finalize failed');
+    const ms = performance.now() - t;
+
+    const statsPtr = abi.get_left_input_ptr(engine);
+    abi.get_arena_stats(engine, statsPtr);
+    const view = new DataView(abi.memory.buffer, statsPtr, 24);
+    const stats = {
+        ms,
+        len: abi.get_result_len(engine),
+        estimate: view.getUint32(0, true),
+        reallocations: view.getUint32(8, true),
+        moved: Number(view.getBigUint64(16, true)),
+    };
+    abi.destroy_engine(engine);
+    return stats;
+}
+
+console.log(`${N} entries, result ${(16 + N * 32) / 1e6} MB\n`);
+let failed = false;
+for (const [name, growth] of [['reserve', 0], ['doubling', 1]]) {
+    const runs = Array.from({ length: RUNS }, () => run(growth)).sort((a, b) => a.ms - b.ms);
+    const s = runs[RUNS >> 1];
+    console.log(
+        `${name.padEnd(10)} finalize ${s.ms.toFixed(2).padStart(8)} ms  ` +
+        `reallocations ${String(s.reallocations).padStart(3)}  moved ${(s.moved / 1e6).toFixed(2).padStart(6)} MB  ` +
+        `estimate ${s.estimate === s.len ? 'exact' : `${s.estimate} != ${s.len}`}`,
+    );
+    if (s.estimate !== s.len || (growth === 0 && s.reallocations > 1)) failed = true;
+}
+if (failed) {
+    console.error('\nreserve growth reallocated or the estimate was off');
+    process.exit(1);
+}
@@ -0,0 +1,63 @@
+/**
+ * Tiny-document churn: many ~1.5 KB diffs, raw ABI, no JS glue.
+ *
+ *   node bench/tiny.mjs            # 100k diffs against pkg/diffcore.wasm
+ *   DIFFCORE_WASM=... N=20000 node bench/tiny.mjs
+ *
+ * Two loops: a fresh engine per diff (create → commit → finalize →
+ * destroy, the serverless/request-handler shape) and one engine reused
+ * through clear_engine. Setup cost dominates both at this size.
+ */
+
+import { readFileSync } from 'fs';
+import { performance } from 'perf_hooks';
+
+const WASM_PATH = process.env.DIFFCORE_WASM ?? new URL('../pkg/diffcore.wasm', import.meta.url);
+const N = Number(process.env.N ?? 100_000);
+
+function doc(seed, v) {
+    const items = [];
+    for (let i = 0; i < 16; i++) {
+        items.push(`{"id":${seed + i},"name":"user-${seed + i}","active":${i % 2 === 0},"score":${(i * 7) % 100},"tags":["a","b"]}`);
+    }
+    return `{"page":${v},"items":[${items.join(',')}]}`;
+}
+
+const { instance } = await WebAssembly.instantiate(readFileSync(WASM_PATH), {});
+const abi = instance.exports;
+const enc = new TextEncoder();
+const left = enc.encode(doc(1, 1));
+const right = enc.encode(doc(1, 2));
+
+function commit(engine, side, bytes) {
+    const ptr = side === 'left' ? abi.get_left_input_ptr(engine) : abi.get_right_input_ptr(engine);
+    new Uint8Array(abi.memory.buffer, ptr, bytes.length).set(bytes);
+    return side === 'left' ? abi.commit_left(engine, bytes.length) : abi.commit_right(engine, bytes.length);
+}
+
+function diff(engine) {
+    commit(engine, 'left', left);
+    commit(engine, 'right', right);
+    if (abi.finalize(engine) === 0) throw new Error('finalize failed');
+}
+
+function time(label, run) {
+    for (let i = 0; i < Math.min(N, 1000); i++) run();
+    const t = performance.now();
+    for (let i = 0; i < N; i++) run();
+    const ms = performance.now() - t;
+    console.log(`${label.padEnd(24)} ${(ms * 1000 / N).toFixed(2).padStart(8)} µs/diff  (${ms.toFixed(0)} ms total)`);
+}
+
+console.log(`${N} diffs of ${left.length} / ${right.length} bytes\n`);
+time('create/destroy per diff', () => {
+    const engine = abi.create_engine(0, 0);
+    diff(engine);
+    abi.destroy_engine(engine);
+});
+const engine = abi.create_engine(0, 0);
+time('reused engine', () => {
+    diff(engine);
+    abi.clear_engine(engine);
+});
+abi.destroy_engine(engine);
@@ -0,0 +1,97 @@
+/**
+ * Cost of the per-entry UTF-8 flags, raw ABI.
+ *
+ *   node bench/utf8.mjs            # 50k entries against pkg/diffcore.wasm
+ *   DIFFCORE_WASM=... N=10000 node bench/utf8.mjs
+ *
+ * Diffs two arrays of N strings of the same byte length that differ
+ * everywhere, once per payload mix, and prints the median finalize time
+ * next to the payload bytes finalize validated. `ascii` takes only the
+ * SIMD scan; `multi-byte` and `invalid` fall back to the scalar check
+ * from their first non-ASCII block, so their gap to `ascii` bounds what
+ * validation adds. `numbers` (one-byte payloads) is the floor: the same
+ * entry count with next to nothing to validate.
+ */
+
+import { readFileSync } from 'fs';
+import { performance } from 'perf_hooks';
+
+const WASM_PATH = process.env.DIFFCORE_WASM ?? new URL('../pkg/diffcore.wasm', import.meta.url);
+const N = Number(process.env.N ?? 50_000);
+const RUNS = 7;
+
+const { instance } = await WebAssembly.instantiate(readFileSync(WASM_PATH), {});
+const abi = instance.exports;
+const enc = new TextEncoder();
+
+/** `[v(0),v(1),…]` as bytes; `v` returns each element's encoded bytes. */
+function array(v) {
+    const parts = [enc.encode('[')];
+    for (let i = 0; i < N; i++) parts.push(v(i), enc.encode(i + 1 < N ? ',' : ']'));
+    const out = new Uint8Array(parts.reduce((n, p) => n + p.length, 0));
+    let at = 0;
+    for (const p of parts) { out.set(p, at); at += p.length; }
+    return out;
+}
+
+/** A 96-byte string payload (quotes included) for element `i`, side `s`. */
+const string = (body) => (i, s) => enc.encode(`"${s}${String(i).padStart(7, '0')}${body}"`);
+const PAYLOADS = {
+    numbers: [(i, s) => enc.encode(String(s)), 1],
+    ascii: [string('x'.repeat(86)), 96],
+    'multi-byte': [string('é€'.repeat(17) + 'x'), 96],
+    invalid: [(i, s) => {
+        const b = string('x'.repeat(86))(i, s);
+        b[48] = 0xff;
+        return b;
+    }, 96],
+};
+
+/** 33-byte config: defaults with 64 MB arena and inputs. */
+function config() {
+    const bytes = new Uint8Array(33);
+    const view = new DataView(bytes.buffer);
+    view.setUint32(0, 64 * 1024 * 1024, true);
+    view.setUint32(4, 64 * 1024 * 1024, true);
+    view.setUint32(8, 100_000, true);
+    view.setUint16(13, 64, true);
+    view.setUint32(15, 1024, true);
+    return bytes;
+}
+
+function run(left, right) {
+    new Uint8Array(abi.memory.buffer).set(config(), 1024);
+    const engine = abi.create_engine(1024, 33);
+    if (engine === 0) throw new Error('create_engine failed');
+    for (const [bytes, ptr, commit] of [
+        [left, abi.get_left_input_ptr(engine), abi.commit_left],
+        [right, abi.get_right_input_ptr(engine), abi.commit_right],
+    ]) {
+        new Uint8Array(abi.memory.buffer, ptr, bytes.length).set(bytes);
+        commit(engine, bytes.length);
+    }
+    const t = performance.now();
+    const result = abi.finalize(engine);
+    const ms = performance.now() - t;
+    if (result === 0) throw new Error('finalize failed');
+    // Byte [3] of the first entry: 0x02 | 0x04 when both sides are invalid.
+    const flags = new Uint8Array(abi.memory.buffer, result + 16 + 3, 1)[0];
+    abi.destroy_engine(engine);
+    return { ms, flags };
+}
+
+const inputs = Object.entries(PAYLOADS).map(([name, [value, len]]) => [name, len, array((i) => value(i, 1)), array((i) => value(i, 2))]);
+// Warm up: JIT and memory growth land here, not on the first payload.
+for (const [, , left, right] of inputs) run(left, right);
+
+console.log(`${N} Modified entries per run, median of ${RUNS}\n`);
+for (const [name, len, left, right] of inputs) {
+    const runs = Array.from({ length: RUNS }, () => run(left, right)).sort((a, b) => a.ms - b.ms);
+    const { ms, flags } = runs[RUNS >> 1];
+    const validated = 2 * N * len;
+    console.log(
+        `${name.padEnd(11)} finalize ${ms.toFixed(2).padStart(8)} ms  ` +
+        `payload ${(validated / 1e6).toFixed(1).padStart(5)} MB  ` +
+        `${(ms * 1e6 / N).toFixed(0).padStart(5)} ns/entry  first entry flags 0x${flags.toString(16).padStart(2, '0')}`,
+    );
+}
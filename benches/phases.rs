@@ -0,0 +1,288 @@
+//! Per-phase native benchmarks (criterion). The build's default target is
+//! wasm, so pass the host's: `npm run bench:native`, or `cargo bench
+//! --target <host triple>`.
+//!
+//! Each phase is timed on its own, over the same fixtures, so a change
+//! shows up in the phase it touched:
+//!
+//! - `index`: structural index throughput, bytes/s of the left document.
+//! - `parse`: tokens/s over a prebuilt index.
+//! - `diff`: the join on two parsed sides, entries/s.
+//! - `finalize`: join plus write on committed sides, result bytes/s, per
+//!   output option set. The write's own share is this minus `diff`.
+//! - `end_to_end`: commit, commit, finalize and clear on one engine, for
+//!   small documents where fixed costs dominate.
+//! - `many_rights`: one left against a series of edited rights, either
+//!   retained (`commit_left_retained`, then `diff_next_right` per right)
+//!   or committed again for each, per document.
+//!
+//! Fixtures are deterministic: generated documents (`diffcore::gen`) of
+//! 10 KB to 1 MB, paired near-identical (a few edits) or divergent
+//! (another seed), the same 100 KB records under other keys (disjoint:
+//! no path in common), and the adversarial shapes: a comma flood, deep
+//! nesting and string-heavy values. Before timing anything each fixture's
+//! work counts (`get_stats`: structurals, tokens, entries) are checked
+//! against the pinned ones in `WORK`, so a change that makes a phase do
+//! more work fails here even when the time hides it. A change that is
+//! meant to alter the counts updates `WORK` with it.
+
+#[cfg(not(target_arch = "wasm32"))]
+criterion::criterion_main!(native::benches);
+
+/// Wasm builds (`clippy --all-targets`) get the bench as an empty binary.
+#[cfg(target_arch = "wasm32")]
+fn main() {}
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use criterion::{criterion_group, BatchSize, Criterion, Throughput};
+    use diffcore::bench::{self, EngineStats};
+    use diffcore::gen::{generate, mutate, GenConfig, Node, Rng};
+    use diffcore::{Engine, EngineConfig, Status};
+    use std::hint::black_box;
+
+    /// Work one default-config diff of a fixture does.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct Work {
+        /// Both sides' structural index positions.
+        structurals: u32,
+        /// Both sides' tokens.
+        tokens: u32,
+        entries: u32,
+    }
+
+    impl Work {
+        fn of(stats: EngineStats) -> Self {
+            Self {
+                structurals: stats.left.structurals + stats.right.structurals,
+                tokens: stats.left.tokens + stats.right.tokens,
+                entries: stats.added + stats.removed + stats.modified,
+            }
+        }
+    }
+
+    const WORK: [(&str, Work); 12] = [
+        ("small", Work { structurals: 198, tokens: 75, entries: 1 }),
+        ("small/identical", Work { structurals: 204, tokens: 76, entries: 0 }),
+        ("near/10k", Work { structurals: 6886, tokens: 2492, entries: 10 }),
+        ("divergent/10k", Work { structurals: 6670, tokens: 2382, entries: 1482 }),
+        ("near/100k", Work { structurals: 60844, tokens: 21405, entries: 73 }),
+        ("divergent/100k", Work { structurals: 61168, tokens: 21559, entries: 13491 }),
+        ("disjoint/100k", Work { structurals: 61130, tokens: 21508, entries: 13368 }),
+        ("near/1m", Work { structurals: 627542, tokens: 219718, entries: 10 }),
+        ("divergent/1m", Work { structurals: 627425, tokens: 219025, entries: 136547 }),
+        ("comma_flood", Work { structurals: 524292, tokens: 524294, entries: 1 }),
+        ("deep_nesting", Work { structurals: 40960, tokens: 16386, entries: 1 }),
+        ("string_heavy", Work { structurals: 74116, tokens: 1028, entries: 32 }),
+    ];
+
+    struct Fixture {
+        name: &'static str,
+        left: Vec<u8>,
+        right: Vec<u8>,
+    }
+
+    /// Generated records `{"r0":…,"r1":…}`, keyed with `prefix`, until the
+    /// document reaches `bytes`.
+    fn records(prefix: &str, bytes: usize, seed: u64) -> Node {
+        let cfg = GenConfig::default();
+        let (mut members, mut len) = (Vec::new(), 0);
+        while len < bytes {
+            let record = generate(&cfg, seed.wrapping_add(members.len() as u64));
+            len += record.to_json().len() + 8;
+            members.push((format!("{}{}", prefix, members.len()), record));
+        }
+        Node::Object(members)
+    }
+
+    fn pair(name: &'static str, left: &Node, right: &Node) -> Fixture {
+        Fixture { name, left: left.to_json().into_bytes(), right: right.to_json().into_bytes() }
+    }
+
+    fn fixtures() -> Vec<Fixture> {
+        let mut out = Vec::new();
+        let small = generate(&GenConfig { depth: 2, ..GenConfig::default() }, 7);
+        out.push(pair("small", &small, &mutate(&small, 2, 7).0));
+        out.push(pair("small/identical", &small, &small));
+        for (name, bytes) in [("10k", 10 << 10), ("100k", 100 << 10), ("1m", 1 << 20)] {
+            let doc = records("r", bytes, 42);
+            let near: &'static str = format!("near/{}", name).leak();
+            out.push(pair(near, &doc, &mutate(&doc, 8, 42).0));
+            let divergent: &'static str = format!("divergent/{}", name).leak();
+            out.push(pair(divergent, &doc, &records("r", bytes, 4242)));
+        }
+        // The same records under other keys: no path in common, so the
+        // join's overlap check proves the sides disjoint and skips the map.
+        out.push(pair("disjoint/100k", &records("r", 100 << 10, 42), &records("s", 100 << 10, 42)));
+
+        // `[0,0,…,0]`, the last element changed: a structural every other byte.
+        let flood = |last: &str| format!("[{}{}]", "0,".repeat(1 << 18), last).into_bytes();
+        out.push(Fixture { name: "comma_flood", left: flood("0"), right: flood("1") });
+
+        // `{"a":{"a":…1…}}`, the innermost value changed.
+        let depth = 4096;
+        let nested = |leaf: &str| format!("{}{}{}", r#"{"a":"#.repeat(depth), leaf, "}".repeat(depth)).into_bytes();
+        out.push(Fixture { name: "deep_nesting", left: nested("1"), right: nested("2") });
+
+        // Long escape-dense strings, every 16th changed.
+        let mut rng = Rng::new(9);
+        let pieces = ["abc", "\\\"", "\\\\", "\\u00e9", "\\n", "xyz "];
+        let values: Vec<String> = (0..512).map(|_| (0..400).map(|_| pieces[rng.below(pieces.len() as u64) as usize]).collect()).collect();
+        let strings = |changed: bool| {
+            let members: Vec<String> = values
+                .iter()
+                .enumerate()
+                .map(|(i, v)| format!(r#""s{}":"{}{}""#, i, v, if changed && i % 16 == 0 { "~" } else { "" }))
+                .collect();
+            format!("{{{}}}", members.join(",")).into_bytes()
+        };
+        out.push(Fixture { name: "string_heavy", left: strings(false), right: strings(true) });
+        out
+    }
+
+    /// An engine sized for `f` with both sides committed.
+    fn committed(config: &EngineConfig, f: &Fixture) -> Engine {
+        let max_input_size = (2 * f.left.len().max(f.right.len())) as u32;
+        let mut engine = Engine::new(EngineConfig { max_input_size, ..config.clone() }, 0).expect("engine");
+        // SAFETY: each input buffer holds `max_input_size / 2` bytes.
+        unsafe {
+            core::ptr::copy_nonoverlapping(f.left.as_ptr(), engine.left_input_ptr(), f.left.len());
+            core::ptr::copy_nonoverlapping(f.right.as_ptr(), engine.right_input_ptr(), f.right.len());
+        }
+        assert_eq!(engine.commit_left(f.left.len() as u32), Status::Ok, "{}", f.name);
+        assert_eq!(engine.commit_right(f.right.len() as u32), Status::Ok, "{}", f.name);
+        engine
+    }
+
+    /// Check `f`'s work against `WORK`, and that the single-phase wrappers
+    /// do the work the engine counts.
+    fn check_work(f: &Fixture) {
+        let mut engine = committed(&EngineConfig::default(), f);
+        engine.finalize().expect("finalize");
+        let got = Work::of(engine.stats());
+        let want = WORK.iter().find(|(name, _)| *name == f.name).map(|&(_, w)| w);
+        assert_eq!(Some(got), want, "{}: work counts changed; update WORK if that is intended", f.name);
+        let phases = engine.phase_counters();
+        let identical = f.left == f.right;
+        assert_eq!((phases.digest_matches, phases.joins), (identical as u32, !identical as u32), "{}", f.name);
+        // Divergent records share no path below their keys either.
+        let disjoint = f.name.starts_with("disjoint") || f.name.starts_with("divergent");
+        assert_eq!(phases.disjoint_joins, disjoint as u32, "{}", f.name);
+
+        let (left, right) = (bench::index(&f.left), bench::index(&f.right));
+        assert_eq!(left.structurals() + right.structurals(), got.structurals, "{}", f.name);
+        let (left, right) = (bench::parse(&f.left, &left), bench::parse(&f.right, &right));
+        assert_eq!((left.tokens() + right.tokens()) as u32, got.tokens, "{}", f.name);
+        assert_eq!(bench::diff(&left, &right) as u32, got.entries, "{}", f.name);
+    }
+
+    fn phases(c: &mut Criterion) {
+        let fixtures = fixtures();
+        for f in &fixtures {
+            check_work(f);
+        }
+
+        let mut group = c.benchmark_group("index");
+        for f in &fixtures {
+            group.throughput(Throughput::Bytes(f.left.len() as u64));
+            group.bench_function(f.name, |b| b.iter(|| bench::index(black_box(&f.left))));
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("parse");
+        for f in &fixtures {
+            let index = bench::index(&f.left);
+            group.throughput(Throughput::Elements(bench::parse(&f.left, &index).tokens() as u64));
+            group.bench_function(f.name, |b| b.iter(|| bench::parse(black_box(&f.left), &index)));
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("diff");
+        for f in &fixtures {
+            let left = bench::parse(&f.left, &bench::index(&f.left));
+            let right = bench::parse(&f.right, &bench::index(&f.right));
+            group.throughput(Throughput::Elements(bench::diff(&left, &right) as u64));
+            group.bench_function(f.name, |b| b.iter(|| bench::diff(black_box(&left), &right)));
+        }
+        group.finish();
+
+        let outputs = [
+            ("entries", EngineConfig::default()),
+            ("rendered_paths", EngineConfig { rendered_paths: true, ..EngineConfig::default() }),
+            ("value_hashes", EngineConfig { value_hashes: true, coverage_map: true, ..EngineConfig::default() }),
+            ("numeric_deltas", EngineConfig { numeric_deltas: true, ..EngineConfig::default() }),
+        ];
+        let mut group = c.benchmark_group("finalize");
+        for f in fixtures.iter().filter(|f| f.name.ends_with("100k") || f.name == "string_heavy") {
+            for (output, config) in &outputs {
+                let mut engine = committed(config, f);
+                engine.finalize().expect("finalize");
+                group.throughput(Throughput::Bytes(engine.result_len() as u64));
+                group.bench_function(format!("{}/{}", f.name, output), |b| {
+                    // The engine goes out of the routine, so its drop is not timed.
+                    b.iter_batched(|| committed(config, f), |mut e| { e.finalize().expect("finalize"); e }, BatchSize::LargeInput)
+                });
+            }
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("end_to_end");
+        for f in fixtures.iter().filter(|f| f.name.starts_with("small")) {
+            let mut engine = committed(&EngineConfig::default(), f);
+            engine.clear();
+            group.bench_function(f.name, |b| {
+                b.iter(|| {
+                    // SAFETY: the engine was sized for this fixture.
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(f.left.as_ptr(), engine.left_input_ptr(), f.left.len());
+                        core::ptr::copy_nonoverlapping(f.right.as_ptr(), engine.right_input_ptr(), f.right.len());
+                    }
+                    engine.commit_left(f.left.len() as u32);
+                    engine.commit_right(f.right.len() as u32);
+                    black_box(engine.finalize().expect("finalize"));
+                    engine.clear();
+                })
+            });
+        }
+        group.finish();
+
+        let mut group = c.benchmark_group("many_rights");
+        let base = generate(&GenConfig::default(), 21);
+        let left = base.to_json().into_bytes();
+        let rights: Vec<Vec<u8>> = (0..16).map(|seed| mutate(&base, 3, seed).0.to_json().into_bytes()).collect();
+        let max_input_size = (2 * rights.iter().chain([&left]).map(Vec::len).max().unwrap_or(0)) as u32;
+        let mut engine = Engine::new(EngineConfig { max_input_size, ..EngineConfig::default() }, 0).expect("engine");
+        let write = |engine: &mut Engine, left: Option<&[u8]>, right: &[u8]| unsafe {
+            // SAFETY: the engine was sized for the largest document.
+            if let Some(left) = left { core::ptr::copy_nonoverlapping(left.as_ptr(), engine.left_input_ptr(), left.len()); }
+            core::ptr::copy_nonoverlapping(right.as_ptr(), engine.right_input_ptr(), right.len());
+        };
+        group.throughput(Throughput::Elements(rights.len() as u64));
+        group.bench_function("retained", |b| {
+            b.iter(|| {
+                write(&mut engine, Some(&left), &[]);
+                assert_eq!(engine.commit_left_retained(left.len() as u32), Status::Ok);
+                for right in &rights {
+                    write(&mut engine, None, right);
+                    assert_eq!(engine.diff_next_right(right.len() as u32), Status::Ok);
+                    black_box(engine.result_len());
+                }
+                engine.clear();
+            })
+        });
+        group.bench_function("recommitted", |b| {
+            b.iter(|| {
+                for right in &rights {
+                    write(&mut engine, Some(&left), right);
+                    engine.commit_left(left.len() as u32);
+                    engine.commit_right(right.len() as u32);
+                    black_box(engine.finalize().expect("finalize"));
+                    engine.clear();
+                }
+            })
+        });
+        group.finish();
+    }
+
+    criterion_group!(benches, phases);
+}
@@ -0,0 +1,38 @@
+//! Embeds the version string `version()` returns: the package version,
+//! plus `+<build id>` when one is known. The id is `DIFFCORE_BUILD_ID`
+//! if set (CI, or a source tarball without `.git`), else `git describe
+//! --always --dirty --tags`; anything outside semver's build-metadata
+//! alphabet becomes `-`.
+
+use std::env;
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    // Only paths that exist: a missing one reruns this on every build.
+    for git in [".git/HEAD", ".git/index"] {
+        if Path::new(git).exists() { println!("cargo:rerun-if-changed={}", git); }
+    }
+    println!("cargo:rerun-if-env-changed=DIFFCORE_BUILD_ID");
+
+    let id = env::var("DIFFCORE_BUILD_ID").ok().or_else(git_describe).map(|id| {
+        id.trim()
+            .split('.')
+            .filter(|part| !part.is_empty())
+            .map(|part| part.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '-' }).collect::<String>())
+            .collect::<Vec<_>>()
+            .join(".")
+    });
+    let version = env::var("CARGO_PKG_VERSION").unwrap();
+    match id.filter(|id| !id.is_empty()) {
+        Some(id) => println!("cargo:rustc-env=DIFFCORE_VERSION={}+{}", version, id),
+        None => println!("cargo:rustc-env=DIFFCORE_VERSION={}", version),
+    }
+}
+
+fn git_describe() -> Option<String> {
+    let out = Command::new("git").args(["describe", "--always", "--dirty", "--tags"]).output().ok()?;
+    if !out.status.success() { return None; }
+    String::from_utf8(out.stdout).ok()
+}
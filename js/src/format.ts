@@ -38,10 +38,16 @@ function formatScalar(v: JsonScalar | undefined, max: number): string {
     let s: string;
     if (typeof v === "string") s = JSON.stringify(v);
     else s = String(v);
-    if (s.length > max) s = s.slice(0, max - 1) + "…";
+    if (s.length > max) s = s.slice(0, charBoundary(s, max - 1)) + "…";
     return s;
 }
 
+/** The largest cut at or before `at` that keeps surrogate pairs whole. */
+function charBoundary(s: string, at: number): number {
+    const unit = s.charCodeAt(at - 1);
+    return at > 0 && unit >= 0xd800 && unit <= 0xdbff ? at - 1 : at;
+}
+
 /**
  * Render a diff as a colored, unified-style text blob suitable for `console.log`.
  *
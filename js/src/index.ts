@@ -27,12 +27,15 @@
 
 import {
     Status,
+    ErrorCode,
     ArrayDiffMode,
     DiffOp,
+    ProgressPhase,
     type DiffCoreConfig,
     type DiffEntry,
     type DiffResult,
     type JsonScalar,
+    type Progress,
     type SerializedDiffResult,
 } from "./types.js";
 
@@ -41,8 +44,11 @@ import { DiffCoreError, EngineDestroyedError, FinalizationError, InvalidJsonErro
 
 export {
     Status,
+    ErrorCode,
     DiffOp,
     ArrayDiffMode,
+    ProgressPhase,
+    BuildFlags,
     EDGE_CONFIG,
     type DiffCoreConfig,
     type DiffEntry,
@@ -51,6 +57,7 @@ export {
     type JsonScalar,
     type JsonValue,
     type JsonPatchOp,
+    type Progress,
 } from "./types.js";
 
 export { applyPatch, revertPatch, toJsonPatch } from "./patch.js";
@@ -71,6 +78,83 @@ interface WasmExports {
     destroy_engine: (enginePtr: number) => Status;
     get_last_error: (enginePtr: number) => number;
     get_last_error_len: (enginePtr: number) => number;
+    get_last_error_code: (enginePtr: number) => ErrorCode;
+    get_progress: (enginePtr: number) => number;
+    get_effective_config_json: (enginePtr: number) => number;
+    get_effective_config_json_len: (enginePtr: number) => number;
+    /** Absent from older binaries; see `checkFormatVersion`. */
+    get_format_version?: () => number;
+    /** Static build record: format version, feature bits, default limits. */
+    get_engine_info?: () => number;
+    get_engine_info_len?: () => number;
+    /** Crate version and build id, for bug reports; UTF-8 at a static address. */
+    version?: () => number;
+    version_len?: () => number;
+    /** Absent from older binaries, which get the config at a fixed address. */
+    alloc?: (len: number) => number;
+    dealloc?: (ptr: number) => Status;
+    /** Grows or shrinks a block from `alloc`; 0 when refused. */
+    realloc?: (ptr: number, oldLen: number, newLen: number) => number;
+    /** Diffs a committed side (0 left, 1 right) against its canonical form. */
+    lint?: (enginePtr: number, side: number) => Status;
+    /** Rebuilds a right document from its left one and a `self_contained` result. */
+    apply_patch?: (enginePtr: number, docPtr: number, docLen: number, patchPtr: number, patchLen: number) => Status;
+    /** Strict well-formedness check of one document; the first problem's offset in `get_last_error`. */
+    validate_json?: (enginePtr: number, docPtr: number, docLen: number) => Status;
+    /** Whitespace- and key-order-insensitive hash of a committed side; 0 when unavailable. */
+    hash_document?: (enginePtr: number, side: number) => bigint;
+    /** Commits the left side and keeps it for `diff_next_right`. */
+    commit_left_retained?: (enginePtr: number, len: number) => Status;
+    /** Commits the next right document and finalizes it against the retained left. */
+    diff_next_right?: (enginePtr: number, len: number) => Status;
+    /** Empties the last error; commits and finalize also start from a clear one. */
+    clear_error?: (enginePtr: number) => Status;
+    /** Snapshot of the retained left side, `get_left_state_len` bytes; 0 without one. */
+    export_left_state?: (enginePtr: number) => number;
+    get_left_state_len?: (enginePtr: number) => number;
+    /** Restores an `export_left_state` snapshot as a retained left side. */
+    import_left_state?: (enginePtr: number, blobPtr: number, blobLen: number) => Status;
+    /** Builds with `handles` only: the new engine's handle, which every `enginePtr` parameter takes; 0 on failure. */
+    create_engine_handle?: (configPtr: number, configLen: number) => number;
+    /** Sets one config field by its JSON key before any input; `UnknownOption` for a key this build lacks. */
+    set_option?: (enginePtr: number, keyPtr: number, keyLen: number, valuePtr: number, valueLen: number) => Status;
+    /** Checks both documents fit before copying; commits past the reservation then fail. */
+    reserve_input?: (enginePtr: number, leftLen: number, rightLen: number) => Status;
+    /** Writes the result into a host buffer, keeping no copy: its length, or `-(needed << 8 | status)`. */
+    finalize_into?: (enginePtr: number, outPtr: number, outCap: number) => bigint;
+    /** Finalizes in arena-sized segments: `NeedFlush` while `resume_finalize` has another to write. */
+    finalize_partial?: (enginePtr: number) => Status;
+    /** Writes the next segment over the last; `Ok` for the final one. */
+    resume_finalize?: (enginePtr: number) => Status;
+    /** Entries a result flagged truncated at `max_memory_bytes` left out. */
+    get_dropped_count?: (enginePtr: number) => number;
+    /** Commits and finalizes documents already in linear memory, read in place; they must outlive the result. */
+    diff_direct?: (enginePtr: number, leftPtr: number, leftLen: number, rightPtr: number, rightLen: number) => Status;
+    /** Pointer to 40 bytes of current capacities (inputs, tokens, index, paths, arena); 0 for a bad handle. */
+    get_memory_usage?: (enginePtr: number) => number;
+    /** `BuildFlags` bits: compiled with simd128, SIMD structural index. */
+    get_build_flags?: () => number;
+    /** One op's entries as a result of their own (`split_by_op`); 0 otherwise. */
+    get_result_ptr_for?: (enginePtr: number, op: DiffOp) => number;
+    get_result_len_for?: (enginePtr: number, op: DiffOp) => number;
+    /** Address of the cancel flag byte: `Atomics.store` 1 there from another worker to cancel its finalize. */
+    get_cancel_flag_ptr?: (enginePtr: number) => number;
+}
+
+/** Result format major this glue reads (`parseRawEntries`). */
+const FORMAT_MAJOR = 3;
+
+/** Refuse a wasm binary whose results this glue cannot read, before
+ *  anything is allocated in it. Binaries without the export predate the
+ *  check and write major 2, refused as such. */
+function checkFormatVersion(wasm: WasmExports): void {
+    const version = wasm.get_format_version?.() ?? 2 << 16;
+    if (version >>> 16 !== FORMAT_MAJOR) {
+        throw new DiffCoreError(
+            `diffcore: wasm binary writes result format ${version >>> 16}.${version & 0xffff}; ` +
+            `this build reads major ${FORMAT_MAJOR}`
+        );
+    }
 }
 
 const engineRegistry = new FinalizationRegistry<{
@@ -96,16 +180,48 @@ function serializeConfig(config: DiffCoreConfig): Uint8Array {
     view.setUint16(13, config.hashWindowSize ?? 64, true);
     view.setUint32(15, config.maxFullArraySize ?? 1024, true);
     view.setUint8(19, 0);
-    return new Uint8Array(buf);
+    const redact = config.redactPaths ?? [];
+    if (redact.length === 0) return new Uint8Array(buf);
+    // Patterns follow the full 36-byte layout, whose bytes 20-35 are all
+    // defaults at 0: `[u16 count]` then `[u16 len][bytes]` per pattern.
+    const patterns = redact.map((p) => new TextEncoder().encode(p));
+    const out = new Uint8Array(36 + 2 + patterns.reduce((n, p) => n + 2 + p.length, 0));
+    out.set(new Uint8Array(buf));
+    const tail = new DataView(out.buffer);
+    tail.setUint16(36, patterns.length, true);
+    let at = 38;
+    for (const p of patterns) {
+        tail.setUint16(at, p.length, true);
+        out.set(p, at + 2);
+        at += 2 + p.length;
+    }
+    return out;
+}
+
+/** Entry flag byte [3]: the engine replaced the values (`ENTRY_FLAG_REDACTED` in src/memory.rs). */
+const ENTRY_FLAG_REDACTED = 1 << 5;
+
+/** What `leftValue` / `rightValue` hold for a redacted side by default. */
+export const REDACTED_MARKER = "[REDACTED]";
+
+/** Entry byte [1]/[2]: what a value span covers (mirrors `ValueKind` in src/parser.rs). */
+enum ValueKind {
+    None = 0,
+    String = 1,
+    Literal = 2,
+    Fragment = 3,
 }
 
 interface RawEntry {
     op: DiffOp;
+    leftKind: ValueKind;
+    rightKind: ValueKind;
     pathId: bigint;
     leftOffset: number;
     leftLen: number;
     rightOffset: number;
     rightLen: number;
+    redacted: boolean;
 }
 
 function parseRawEntries(buffer: Uint8Array): { major: number; minor: number; raw: RawEntry[] } {
@@ -113,23 +229,32 @@ function parseRawEntries(buffer: Uint8Array): { major: number; minor: number; ra
     const major = view.getUint16(0, true);
     const minor = view.getUint16(2, true);
     const count = view.getUint32(4, true);
+    const flags = view.getUint32(12, true);
     const HEADER = 16;
     const ENTRY = 32;
+    // Newer writers may append a length-delimited extension to each entry
+    // (length in the core's padding word); step over it.
+    const ENTRY_EXT = 1 << 2;
     const raw: RawEntry[] = [];
+    let next = HEADER;
     for (let i = 0; i < count; i++) {
-        const off = HEADER + i * ENTRY;
+        const off = next;
         if (off + ENTRY > buffer.length) break;
+        next = off + ENTRY + (flags & ENTRY_EXT ? view.getUint32(off + 4, true) : 0);
         const op = view.getUint8(off) as DiffOp;
         const pathIdLow = view.getUint32(off + 8, true);
         const pathIdHigh = view.getUint32(off + 12, true);
         const pathId = pathIdFromU32Pair(pathIdLow, pathIdHigh);
         raw.push({
             op,
+            leftKind: view.getUint8(off + 1) as ValueKind,
+            rightKind: view.getUint8(off + 2) as ValueKind,
             pathId,
             leftOffset: view.getUint32(off + 16, true),
             leftLen: view.getUint32(off + 20, true),
             rightOffset: view.getUint32(off + 24, true),
             rightLen: view.getUint32(off + 28, true),
+            redacted: (view.getUint8(off + 3) & ENTRY_FLAG_REDACTED) !== 0,
         });
     }
     return { major, minor, raw };
@@ -171,11 +296,33 @@ function makeSerializable(entries: DiffEntry[], major: number, minor: number): (
     });
 }
 
+/**
+ * Decode a value span using the engine's kind byte. String bodies come
+ * without quotes and with escapes intact, so they are re-quoted before
+ * `JSON.parse`; literals and fragments are complete JSON already.
+ */
+function decodeSpan(slice: Uint8Array, kind: ValueKind): JsonScalar {
+    const text = new TextDecoder().decode(slice);
+    try {
+        switch (kind) {
+            case ValueKind.String:
+                return text.includes("\\") ? JSON.parse('"' + text + '"') : text;
+            case ValueKind.Literal:
+            case ValueKind.Fragment:
+                return JSON.parse(text);
+        }
+    } catch {
+        /* fall through to raw text */
+    }
+    return text;
+}
+
 function resolveEntries(
     raw: RawEntry[],
     leftBytes: Uint8Array | null,
     rightBytes: Uint8Array | null,
-    resolvePaths: boolean
+    resolvePaths: boolean,
+    redactMarker: string = REDACTED_MARKER
 ): DiffEntry[] {
     const leftIndex = resolvePaths && leftBytes ? buildPathIndex(leftBytes) : null;
     const rightIndex = resolvePaths && rightBytes ? buildPathIndex(rightBytes) : null;
@@ -184,6 +331,7 @@ function resolveEntries(
         // Engine guarantee: Modified means both sides have a leaf at this path
         // (offset/len are valid even when len === 0, e.g. empty strings).
         // Added: only right has a leaf. Removed: only left has a leaf.
+        // A root entry's "leaf" may be a whole container (kind Fragment).
         const leftPresent  = e.op === DiffOp.Modified || e.op === DiffOp.Removed;
         const rightPresent = e.op === DiffOp.Modified || e.op === DiffOp.Added;
 
@@ -193,7 +341,9 @@ function resolveEntries(
         // bug that surfaced when a root scalar's type changed.
         const leftInfo  = leftPresent  && leftIndex  ? leftIndex.byPathId.get(e.pathId)  : undefined;
         const rightInfo = rightPresent && rightIndex ? rightIndex.byPathId.get(e.pathId) : undefined;
-        const pointer = leftInfo?.pointer ?? rightInfo?.pointer;
+        // Root entries (root shape changed, see `root_change` in src/diff.rs)
+        // cover a whole container, which the leaf index does not list.
+        const pointer = leftInfo?.pointer ?? rightInfo?.pointer ?? (e.pathId === 0n ? "" : undefined);
         const path = pointer ?? `#hash:${e.pathId.toString(16).padStart(16, "0")}`;
 
         let leftValue: JsonScalar | undefined;
@@ -201,15 +351,25 @@ function resolveEntries(
         let leftSlice: Uint8Array | undefined;
         let rightSlice: Uint8Array | undefined;
 
+        if (e.redacted) {
+            // The engine kept the op and path only; no bytes to show.
+            return {
+                op: e.op,
+                path,
+                pathId: e.pathId,
+                leftValue: leftPresent ? redactMarker : undefined,
+                rightValue: rightPresent ? redactMarker : undefined,
+            };
+        }
         if (leftPresent && leftBytes) {
             leftSlice = leftBytes.subarray(e.leftOffset, e.leftOffset + e.leftLen);
             if (leftInfo) leftValue = decodeLeafValue(leftBytes, { ...leftInfo, valueOffset: e.leftOffset, valueLen: e.leftLen });
-            else leftValue = new TextDecoder().decode(leftSlice);
+            else leftValue = decodeSpan(leftSlice, e.leftKind);
         }
         if (rightPresent && rightBytes) {
             rightSlice = rightBytes.subarray(e.rightOffset, e.rightOffset + e.rightLen);
             if (rightInfo) rightValue = decodeLeafValue(rightBytes, { ...rightInfo, valueOffset: e.rightOffset, valueLen: e.rightLen });
-            else rightValue = new TextDecoder().decode(rightSlice);
+            else rightValue = decodeSpan(rightSlice, e.rightKind);
         }
 
         return {
@@ -248,6 +408,7 @@ export class DiffEngine {
     private resolvePaths: boolean;
     private ignore?: readonly string[];
     private scope?: string;
+    private redactMarker?: string;
     private leftBuffer: Uint8Array[] = [];
     private rightBuffer: Uint8Array[] = [];
     /** Per-side input capacity in bytes (the engine splits `maxInputSize` in two). */
@@ -255,14 +416,18 @@ export class DiffEngine {
 
     /** @internal use `createEngine()`. */
     constructor(wasm: WasmExports, config: DiffCoreConfig = {}) {
+        checkFormatVersion(wasm);
         this.wasm = wasm;
         this.resolvePaths = config.resolvePaths !== false;
         this.ignore = config.ignore;
         this.scope = config.scope;
+        this.redactMarker = config.redactMarker;
         this.sideCapacity = Math.floor((config.maxInputSize ?? 64 * 1024 * 1024) / 2);
         const configBytes = serializeConfig(config);
         const configPtr = this.allocAndWrite(configBytes);
         this.enginePtr = wasm.create_engine(configPtr, configBytes.length);
+        // The engine parsed the config; it keeps nothing pointing into it.
+        if (wasm.alloc && wasm.dealloc) wasm.dealloc(configPtr);
         if (this.enginePtr === 0) {
             throw new DiffCoreError("failed to create engine — config may be invalid");
         }
@@ -272,7 +437,8 @@ export class DiffEngine {
     }
 
     private allocAndWrite(data: Uint8Array): number {
-        const ptr = 1024;
+        const ptr = this.wasm.alloc && this.wasm.dealloc ? this.wasm.alloc(data.length) : 1024;
+        if (ptr === 0) throw new DiffCoreError("failed to allocate the config in wasm memory");
         new Uint8Array(this.wasm.memory.buffer).set(data, ptr);
         return ptr;
     }
@@ -343,7 +509,7 @@ export class DiffEngine {
         const { major, minor, raw } = parseRawEntries(resultCopy);
         const left = this.resolvePaths ? concatChunks(this.leftBuffer) : null;
         const right = this.resolvePaths ? concatChunks(this.rightBuffer) : null;
-        let entries = resolveEntries(raw, left, right, this.resolvePaths);
+        let entries = resolveEntries(raw, left, right, this.resolvePaths, this.redactMarker);
         entries = applyEntryFilters(entries, this.ignore, this.scope);
 
         return {
@@ -380,6 +546,34 @@ export class DiffEngine {
             new Uint8Array(this.wasm.memory.buffer, errorPtr, errorLen)
         );
     }
+
+    /** Detailed code of the last error, or `ErrorCode.None`. */
+    getLastErrorCode(): ErrorCode {
+        if (this.destroyed) return ErrorCode.None;
+        return this.wasm.get_last_error_code(this.enginePtr);
+    }
+
+    /**
+     * The engine's effective configuration as canonical JSON (sorted keys,
+     * every field, plus a `derived` object). Attach it to bug reports.
+     */
+    getEffectiveConfigJson(): string | null {
+        if (this.destroyed) return null;
+        const ptr = this.wasm.get_effective_config_json(this.enginePtr);
+        const len = this.wasm.get_effective_config_json_len(this.enginePtr);
+        if (ptr === 0) return null;
+        return new TextDecoder().decode(new Uint8Array(this.wasm.memory.buffer, ptr, len));
+    }
+
+    /**
+     * Progress of the running (or last) commit or finalize. Only useful
+     * when polled from another worker sharing this module's memory.
+     */
+    getProgress(): Progress {
+        if (this.destroyed) return { phase: ProgressPhase.Idle, permille: 0 };
+        const word = this.wasm.get_progress(this.enginePtr);
+        return { phase: word >>> 16, permille: word & 0xffff };
+    }
 }
 
 function concatChunks(chunks: Uint8Array[]): Uint8Array {
@@ -494,7 +688,7 @@ export async function diff(
 
         const result = engine.finalize();
         const { major, minor, raw } = parseRawEntries(result.raw);
-        let entries = resolveEntries(raw, leftBytes, rightBytes, resolvePaths);
+        let entries = resolveEntries(raw, leftBytes, rightBytes, resolvePaths, config.redactMarker);
         entries = applyEntryFilters(entries, config.ignore, config.scope);
         return {
             version: result.version,
@@ -14,9 +14,12 @@ const U64_MASK = 0xffffffffffffffffn;
  *  disjoint hash sub-spaces. Mirrors `fold_index_hash` in `src/path.rs`. */
 const GOLDEN = 0x9e3779b97f4a7c15n;
 
-/** Fold a UTF-8 segment (object key) into the parent hash. */
+/** Fold a UTF-8 segment (object key) into the parent hash: one step of
+ *  0x100 plus the key's byte length, then one per byte, as
+ *  `fold_segment_hash` in `src/path.rs` does, so `/a/b` and `/ab` (and the
+ *  empty key and its parent) get ids of their own. */
 export function foldSegment(parent: bigint, bytes: Uint8Array): bigint {
-    let h = parent;
+    let h = ((parent * FNV_PRIME) & U64_MASK) ^ (0x100n + BigInt(bytes.length));
     for (let i = 0; i < bytes.length; i++) {
         h = (h * FNV_PRIME) & U64_MASK;
         h ^= BigInt(bytes[i]);
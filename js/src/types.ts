@@ -14,9 +14,87 @@ export enum Status {
     InvalidHandle = 4,
     ObjectKeyLimitExceeded = 5,
     ArrayTooLarge = 6,
+    AlreadyCommitted = 7,
+    GroupQuotaExceeded = 8,
+    InProgress = 9,
+    Cancelled = 10,
+    Busy = 11,
+    Poisoned = 12,
+    TooLate = 13,
+    UnknownOption = 14,
+    ResultTruncated = 15,
     Error = 255,
 }
 
+/**
+ * Detailed error codes from `DiffEngine.getLastErrorCode()`. Ranges are
+ * reserved per subsystem (config 100–199, parse 200–299, memory 300–399,
+ * warnings 400–499, internal 900–999); numbers are stable across releases.
+ */
+export enum ErrorCode {
+    None = 0,
+    EngineSealed = 1,
+    AlreadyCommitted = 2,
+    InputExceedsBuffer = 3,
+    NotFinalized = 4,
+    LeftNotCommitted = 5,
+    NoInputCommitted = 6,
+    ConfigTooShort = 100,
+    InvalidArrayMode = 101,
+    InvalidLimits = 102,
+    InvalidWindowSize = 103,
+    UnknownConfigField = 104,
+    InvalidConfigValue = 105,
+    NotAnOutputOption = 106,
+    FeatureDisabled = 107,
+    MalformedConfigJson = 108,
+    MalformedScope = 110,
+    ScopeNotFound = 111,
+    InvalidSuppressions = 112,
+    InvalidPatch = 113,
+    UnknownOption = 115,
+    UnexpectedByte = 200,
+    IncompleteInput = 201,
+    ObjectKeyLimitExceeded = 202,
+    InvalidEscape = 203,
+    InvalidJson = 204,
+    ArenaSealed = 300,
+    MemoryLimitExceeded = 301,
+    InputLimitExceeded = 302,
+    ArrayTooLarge = 303,
+    GroupQuotaExceeded = 304,
+    OversizedEntriesSkipped = 400,
+    EscapesReplaced = 401,
+    Internal = 900,
+}
+
+/** Phase reported by `DiffEngine.getProgress()`. */
+export enum ProgressPhase {
+    Idle = 0,
+    /** Commit: structural indexing. */
+    Index = 1,
+    /** Commit: tokenizing. */
+    Parse = 2,
+    /** Finalize: matching paths. */
+    Diff = 3,
+    /** Finalize: writing entries. */
+    Write = 4,
+}
+
+/** Bits of `get_build_flags()`. */
+export enum BuildFlags {
+    /** Compiled with simd128; traps on a runtime without it. */
+    Simd128 = 1 << 0,
+    /** The structural index runs in v128 lanes. */
+    SimdIndex = 1 << 1,
+}
+
+/** Progress within the current phase; `permille` runs 0–1000. */
+export interface Progress {
+    phase: ProgressPhase;
+    permille: number;
+}
+
 /** Array diff strategy. */
 export enum ArrayDiffMode {
     /** Position-based only (fast, no reorder detection). */
@@ -36,15 +114,15 @@ export enum DiffOp {
 
 /** Engine configuration with capability limits. */
 export interface DiffCoreConfig {
-    /** Maximum memory for result arena (bytes). Default: 32MB. */
+    /** Maximum memory for result arena (bytes). Default: 32MB. Minimum: 1024. */
     maxMemoryBytes?: number;
-    /** Maximum total input size (bytes). Default: 64MB. */
+    /** Maximum total input size (bytes). Default: 64MB. Minimum: 256. */
     maxInputSize?: number;
     /** Maximum object keys to buffer. Default: 100,000. */
     maxObjectKeys?: number;
     /** Array diff strategy. Default: Index. */
     arrayDiffMode?: ArrayDiffMode;
-    /** Hash window size for HashWindow mode. Default: 64. */
+    /** Hash window size for HashWindow mode. Default: 64. At most maxFullArraySize. */
     hashWindowSize?: number;
     /** Maximum array size for Full mode. Default: 1024. */
     maxFullArraySize?: number;
@@ -75,6 +153,21 @@ export interface DiffCoreConfig {
      * ```
      */
     scope?: string;
+    /**
+     * Values under these paths never leave the engine: a matching entry
+     * keeps its op and path, and each value it has becomes `redactMarker`
+     * (in `formatDiff` and `toJsonPatch` output too). Patterns are dotted:
+     * `*` is any one key or index, `**` any depth, and a match covers
+     * everything under it.
+     *
+     * @example
+     * ```ts
+     * await diff(a, b, { redactPaths: [".credentials.*", "**.password"] });
+     * ```
+     */
+    redactPaths?: readonly string[];
+    /** Value shown for a redacted side. Default: `"[REDACTED]"`. */
+    redactMarker?: string;
 }
 
 /** Edge-runtime-optimized config. */
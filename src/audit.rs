@@ -0,0 +1,210 @@
+//! Opt-in log of the calls made on an engine, for postmortems.
+//!
+//! With `audit_log_len` set, every export that can change an engine's
+//! state or result appends a record: creation, scopes, output options,
+//! suppressions, the entry transform, config changes, commits and pushes, finalize,
+//! span index, trim and clear. Read-only
+//! getters are not logged. The log is a ring of the last
+//! `audit_log_len` records; `seq` numbers every record since creation, so
+//! a gap before the oldest one shows how many were overwritten.
+//! `clear_engine` keeps the log, since the calls before a clear are often
+//! the ones a postmortem needs.
+//!
+//! Finalize writes the log so far into the result as
+//! `SECTION_AUDIT_LOG` (the finalize itself is logged after it returns),
+//! so a result a host kept carries the calls that produced it;
+//! `get_audit_log` reads the live log.
+//!
+//! Record (16 bytes, little-endian): `[u32 seq][u8 op][u8 status]
+//! [u16 reserved][u32 arg][u32 error_code]`. `op` is an [`AuditOp`],
+//! `status` the `Status` returned, `arg` the call's length argument (see
+//! each op) and `error_code` the `ErrorCode` of a failing status, 0 on
+//! `Ok`.
+
+use alloc::vec::Vec;
+
+/// The logged export.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuditOp {
+    /// `create_engine` / `create_engine_in_group`; `arg` is the config length.
+    Create = 1,
+    /// `arg` is the committed length.
+    CommitLeft = 2,
+    CommitRight = 3,
+    /// `arg` is the pointer length.
+    SetLeftScope = 4,
+    SetRightScope = 5,
+    /// `arg` is the record bytes length.
+    SetOutputOptions = 6,
+    /// `arg` is the loaded result's length.
+    LoadSuppressions = 7,
+    /// `arg` is the result length, 0 if finalize failed.
+    Finalize = 8,
+    /// `arg` is the span index length.
+    BuildSpanIndex = 9,
+    Clear = 10,
+    /// `three_way` only; `arg` is the committed length.
+    CommitThird = 11,
+    /// `arg` is the chunk length.
+    PushLeft = 12,
+    PushRight = 13,
+    /// `arg` is the trim level.
+    Trim = 14,
+    /// `arg` is 1 when a transform was set, 0 when it was removed.
+    SetEntryTransform = 15,
+    /// `arg` is the config length.
+    SetConfig = 16,
+    /// `arg` is the linted side, 0 left or 1 right.
+    Lint = 17,
+    /// `arg` is the patch length.
+    ApplyPatch = 18,
+    /// `arg` is the document length.
+    ValidateJson = 19,
+    /// `arg` is the committed length.
+    CommitLeftRetained = 20,
+    /// `arg` is the right side's length.
+    DiffNextRight = 21,
+    /// `arg` is the blob length.
+    ImportLeftState = 22,
+    /// `arg` is the value length.
+    SetOption = 23,
+    /// `arg` is the left length.
+    ReserveInput = 24,
+    /// `arg` is the bytes written to the host's buffer.
+    FinalizeInto = 25,
+    /// `arg` is the segment's length.
+    FinalizePartial = 26,
+    /// `arg` is the segment's length.
+    ResumeFinalize = 27,
+    /// `arg` is the left length.
+    DiffDirect = 28,
+}
+
+impl AuditOp {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        use AuditOp::*;
+        [
+            Create, CommitLeft, CommitRight, SetLeftScope, SetRightScope, SetOutputOptions, LoadSuppressions,
+            Finalize, BuildSpanIndex, Clear, CommitThird, PushLeft, PushRight,
+            Trim, SetEntryTransform, SetConfig, Lint, ApplyPatch, ValidateJson, CommitLeftRetained, DiffNextRight, ImportLeftState,
+            SetOption, ReserveInput, FinalizeInto, FinalizePartial, ResumeFinalize, DiffDirect,
+        ]
+            .get((v as usize).wrapping_sub(1))
+            .copied()
+    }
+}
+
+/// One logged call. `op` stays raw so a reader keeps records of ops it
+/// does not know.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuditRecord {
+    pub seq: u32,
+    pub op: u8,
+    pub status: u8,
+    pub arg: u32,
+    pub error_code: u32,
+}
+
+impl AuditRecord {
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(&self.seq.to_le_bytes());
+        buf[4] = self.op;
+        buf[5] = self.status;
+        buf[8..12].copy_from_slice(&self.arg.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.error_code.to_le_bytes());
+        buf
+    }
+
+    /// Decode the first 16 bytes of `bytes`, which must hold them.
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        let word = |at: usize| u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap());
+        Self { seq: word(0), op: bytes[4], status: bytes[5], arg: word(8), error_code: word(12) }
+    }
+}
+
+/// Ring of the last `len` records.
+pub struct AuditLog {
+    records: Vec<AuditRecord>,
+    len: usize,
+    /// Slot of the oldest record once the ring is full.
+    head: usize,
+    next_seq: u32,
+}
+
+impl AuditLog {
+    pub fn new(len: u16) -> Self {
+        Self { records: Vec::with_capacity(len as usize), len: len as usize, head: 0, next_seq: 0 }
+    }
+
+    /// Records kept before the oldest is overwritten.
+    pub fn capacity(&self) -> u16 { self.len as u16 }
+
+    pub fn record(&mut self, op: AuditOp, arg: u32, status: u8, error_code: u32) {
+        let record = AuditRecord { seq: self.next_seq, op: op as u8, status, arg, error_code };
+        self.next_seq = self.next_seq.wrapping_add(1);
+        if self.records.len() < self.len {
+            self.records.push(record);
+        } else if self.len != 0 {
+            self.records[self.head] = record;
+            self.head = (self.head + 1) % self.len;
+        }
+    }
+
+    /// Records held, oldest first.
+    pub fn records(&self) -> impl Iterator<Item = &AuditRecord> + '_ {
+        let (newer, older) = self.records.split_at(self.head);
+        older.iter().chain(newer)
+    }
+
+    pub fn byte_len(&self) -> usize { self.records.len() * AuditRecord::SIZE }
+
+    /// Serialized records, oldest first, skipping the oldest `skip`.
+    pub fn to_bytes(&self, skip: usize) -> Vec<u8> {
+        self.records().skip(skip).flat_map(|r| r.to_bytes()).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn seqs(log: &AuditLog) -> Vec<u32> {
+        log.records().map(|r| r.seq).collect()
+    }
+
+    #[test]
+    fn ring_keeps_the_newest_records_in_order() {
+        let mut log = AuditLog::new(3);
+        log.record(AuditOp::Create, 33, 0, 0);
+        log.record(AuditOp::CommitLeft, 7, 0, 0);
+        assert_eq!(seqs(&log), [0, 1]);
+        for len in 0..5 {
+            log.record(AuditOp::CommitRight, len, 7, 1004);
+        }
+        assert_eq!(seqs(&log), [4, 5, 6]);
+        assert_eq!(log.records().map(|r| r.arg).collect::<Vec<_>>(), [2, 3, 4]);
+        assert_eq!(log.byte_len(), 3 * AuditRecord::SIZE);
+
+        let bytes = log.to_bytes(1);
+        assert_eq!(bytes.len(), 2 * AuditRecord::SIZE);
+        let first = AuditRecord::from_bytes(&bytes);
+        assert_eq!(first, AuditRecord { seq: 5, op: AuditOp::CommitRight as u8, status: 7, arg: 3, error_code: 1004 });
+        assert_eq!(AuditOp::from_u8(first.op), Some(AuditOp::CommitRight));
+    }
+
+    #[test]
+    fn op_codes_round_trip() {
+        for v in 0..=u8::MAX {
+            if let Some(op) = AuditOp::from_u8(v) {
+                assert_eq!(op as u8, v);
+            }
+        }
+        assert_eq!((AuditOp::from_u8(0), AuditOp::from_u8(29)), (None, None));
+        assert_eq!(AuditOp::from_u8(13), Some(AuditOp::PushRight));
+        assert_eq!(AuditOp::from_u8(10), Some(AuditOp::Clear));
+    }
+}
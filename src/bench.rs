@@ -0,0 +1,55 @@
+//! Single-phase entry points for `benches/phases.rs` (feature `gen`).
+//!
+//! The engine runs index, parse, join and write back to back behind one
+//! `finalize`; a bench of one phase needs what the earlier phases left.
+//! These wrap the internal phases as the engine calls them, with the
+//! default config, without making the phases themselves public. The write
+//! phase has no wrapper: the benches time it as `Engine::finalize` on
+//! committed sides.
+
+use crate::config::EngineConfig;
+use crate::diff::compute_compact_diff_reporting;
+use crate::parser::CompactParser;
+use crate::simd_index::StructuralIndex;
+
+pub use crate::engine::PhaseCounters;
+pub use crate::stats::{EngineStats, SideStats};
+
+/// One side's structural index.
+pub struct Indexed(StructuralIndex);
+
+impl Indexed {
+    /// Positions in the index, as `get_stats` counts them.
+    pub fn structurals(&self) -> u32 {
+        self.0.masks.iter().map(|m| m.count_ones()).sum()
+    }
+}
+
+/// One side's token stream.
+pub struct Parsed(CompactParser);
+
+impl Parsed {
+    pub fn tokens(&self) -> usize { self.0.tokens().len() }
+}
+
+pub fn index(json: &[u8]) -> Indexed {
+    let mut index = StructuralIndex::new();
+    index.build(json);
+    Indexed(index)
+}
+
+/// Parse `json` over its `index`. Panics on malformed input: bench
+/// fixtures are valid by construction.
+pub fn parse(json: &[u8], index: &Indexed) -> Parsed {
+    let config = EngineConfig::default();
+    let mut parser = CompactParser::new(config.max_object_keys, config.compute_mode);
+    if let Err(e) = parser.parse_with_index(json, &index.0) {
+        panic!("bench fixture does not parse: {}", e);
+    }
+    Parsed(parser)
+}
+
+/// The join finalize runs on two parsed sides; its entry count.
+pub fn diff(left: &Parsed, right: &Parsed) -> usize {
+    compute_compact_diff_reporting(&left.0, &right.0, None).len()
+}
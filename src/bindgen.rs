@@ -0,0 +1,121 @@
+//! `DiffEngine`, a `#[wasm_bindgen]` class over [`Engine`] for browser
+//! hosts that would rather not write the pointer glue of
+//! `js/src/index.ts` (feature `bindgen`):
+//!
+//! ```js
+//! const engine = new DiffEngine();          // or new DiffEngine(configJson)
+//! engine.pushLeft(left);
+//! engine.pushRight(right);
+//! const result = engine.finalize();         // the result buffer, copied
+//! for (const { op, path, left, right } of engine.entries()) { ... }
+//! engine.free();
+//! ```
+//!
+//! Each method is the `Engine` call its export makes, audited the same
+//! way; a failure throws an `Error` with the engine's message. The class
+//! owns its engine, so it never goes through `create_engine` or the
+//! handle registry. Without the feature nothing here is compiled and the
+//! raw exports are the whole ABI.
+
+use crate::audit::AuditOp;
+use crate::config::EngineConfig;
+use crate::diff::DiffOp;
+use crate::engine::{Engine, Side};
+use crate::status::Status;
+use crate::ENGINE_MAGIC;
+use alloc::string::String;
+use alloc::vec::Vec;
+use wasm_bindgen::prelude::*;
+
+#[wasm_bindgen]
+pub struct DiffEngine {
+    engine: Engine,
+}
+
+#[wasm_bindgen]
+impl DiffEngine {
+    /// An engine with `configJson` (the `get_effective_config_json` form,
+    /// missing keys defaulted), or the default config.
+    #[wasm_bindgen(constructor)]
+    pub fn new(config_json: Option<String>) -> Result<DiffEngine, JsError> {
+        let config = match config_json {
+            Some(json) => EngineConfig::from_json(&json).map_err(|e| JsError::new(&e.to_string()))?,
+            None => EngineConfig::default(),
+        };
+        let mut engine = Engine::new(config, ENGINE_MAGIC).map_err(|e| JsError::new(&e.to_string()))?;
+        engine.audit(AuditOp::Create, 0, Status::Ok);
+        Ok(DiffEngine { engine })
+    }
+
+    /// Append a chunk to the left document, as `push_left`.
+    #[wasm_bindgen(js_name = pushLeft)]
+    pub fn push_left(&mut self, chunk: &[u8]) -> Result<(), JsError> {
+        self.push(Side::Left, AuditOp::PushLeft, chunk)
+    }
+
+    /// Append a chunk to the right document, as `push_right`.
+    #[wasm_bindgen(js_name = pushRight)]
+    pub fn push_right(&mut self, chunk: &[u8]) -> Result<(), JsError> {
+        self.push(Side::Right, AuditOp::PushRight, chunk)
+    }
+
+    /// Diff what was pushed; the result buffer, copied out of wasm memory
+    /// so it stays valid after the engine is freed.
+    pub fn finalize(&mut self) -> Result<Vec<u8>, JsError> {
+        match self.engine.finalize() {
+            Ok(result) => {
+                let len = self.engine.result_len();
+                self.engine.audit(AuditOp::Finalize, len, Status::Ok);
+                Ok(unsafe { core::slice::from_raw_parts(result, len as usize) }.to_vec())
+            }
+            Err(e) => {
+                self.engine.audit(AuditOp::Finalize, 0, Status::Error);
+                Err(JsError::new(&e.to_string()))
+            }
+        }
+    }
+
+    /// The finalized entries as `{ op, path, left, right }` objects: `op`
+    /// is `"added"`, `"removed"` or `"modified"`, `path` the JSON Pointer,
+    /// `left` and `right` the raw values (absent for a missing side).
+    /// Empty before `finalize`.
+    pub fn entries(&mut self) -> JsValue {
+        let entries = js_sys::Array::new();
+        for i in 0..self.engine.entry_count() {
+            let entry = js_sys::Object::new();
+            let op = match self.engine.entry_op(i) {
+                Some(op) if op == DiffOp::Added as u8 => "added",
+                Some(op) if op == DiffOp::Removed as u8 => "removed",
+                _ => "modified",
+            };
+            set(&entry, "op", Some(op.as_bytes()));
+            set(&entry, "path", self.engine.entry_path(i));
+            set(&entry, "left", self.engine.entry_value(Side::Left, i));
+            set(&entry, "right", self.engine.entry_value(Side::Right, i));
+            entries.push(&entry);
+        }
+        entries.into()
+    }
+}
+
+impl DiffEngine {
+    fn push(&mut self, side: Side, op: AuditOp, chunk: &[u8]) -> Result<(), JsError> {
+        let status = self.engine.push(side, chunk);
+        self.engine.audit(op, chunk.len() as u32, status);
+        match (status, self.engine.last_error_len()) {
+            (Status::Ok, _) => Ok(()),
+            (_, 0) => Err(JsError::new(&alloc::format!("{:?}", status))),
+            (_, len) => {
+                let message = unsafe { core::slice::from_raw_parts(self.engine.last_error_ptr(), len as usize) };
+                Err(JsError::new(&String::from_utf8_lossy(message)))
+            }
+        }
+    }
+}
+
+/// `object[key] = value` as a string, left unset for `None`.
+fn set(object: &js_sys::Object, key: &str, value: Option<&[u8]>) {
+    if let Some(value) = value {
+        let _ = js_sys::Reflect::set(object, &key.into(), &String::from_utf8_lossy(value).as_ref().into());
+    }
+}
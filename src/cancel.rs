@@ -0,0 +1,31 @@
+//! Abandoning a finalize (`cancel`).
+//!
+//! A host that no longer wants a diff (the user navigated away) sets the
+//! engine's flag: with `cancel` between `finalize_step` calls, or, from
+//! another worker sharing the module's memory while a finalize runs,
+//! with an `Atomics.store` of 1 to the byte at `get_cancel_flag_ptr`
+//! (no call into the module, so no second reference to the engine
+//! while finalize holds it). The diff join checks it every
+//! `STRIDE_TOKENS` token visits and stops; finalize then writes the
+//! entries found so far, sets `HEADER_FLAG_CANCELLED` and fails with
+//! `Cancelled`. A flag set before finalize cancels the next one;
+//! `clear_engine` drops it.
+//!
+//! One relaxed atomic, like the progress word: a cancel lands within a
+//! stride, not at an exact token.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Default)]
+pub struct CancelFlag(AtomicBool);
+
+impl CancelFlag {
+    pub fn set(&self) { self.0.store(true, Ordering::Relaxed); }
+
+    pub fn is_set(&self) -> bool { self.0.load(Ordering::Relaxed) }
+
+    pub fn reset(&self) { self.0.store(false, Ordering::Relaxed); }
+
+    /// The flag's byte (`AtomicBool` is one byte; nonzero is set).
+    pub fn as_ptr(&self) -> *const u8 { self.0.as_ptr().cast_const().cast() }
+}
@@ -1,5 +1,10 @@
 //! Engine configuration with capability-based limits.
 
+use crate::path_pattern::PathPattern;
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+
 /// Array diff mode determines how arrays are compared.
 #[repr(u8)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
@@ -28,6 +33,190 @@ pub enum ComputeMode {
     Streaming = 3,
 }
 
+impl ComputeMode {
+    /// Name used in the JSON config form.
+    pub fn name(self) -> &'static str {
+        match self {
+            ComputeMode::Latency => "latency",
+            ComputeMode::Throughput => "throughput",
+            ComputeMode::Edge => "edge",
+            ComputeMode::Streaming => "streaming",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "latency" => Some(ComputeMode::Latency),
+            "throughput" => Some(ComputeMode::Throughput),
+            "edge" => Some(ComputeMode::Edge),
+            "streaming" => Some(ComputeMode::Streaming),
+            _ => None,
+        }
+    }
+}
+
+/// What finalize does with an entry larger than `max_entry_bytes`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OversizePolicy {
+    /// Drop the entry and count it as skipped.
+    #[default]
+    Skip = 0,
+    /// Shorten the value spans until the entry fits and flag it truncated.
+    Truncate = 1,
+}
+
+impl OversizePolicy {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(OversizePolicy::Skip),
+            1 => Some(OversizePolicy::Truncate),
+            _ => None,
+        }
+    }
+
+    /// Name used in the JSON config form.
+    pub fn name(self) -> &'static str {
+        match self {
+            OversizePolicy::Skip => "skip",
+            OversizePolicy::Truncate => "truncate",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "skip" => Some(OversizePolicy::Skip),
+            "truncate" => Some(OversizePolicy::Truncate),
+            _ => None,
+        }
+    }
+}
+
+/// Order in which finalize writes entries.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EntryOrder {
+    /// Engine order: Modified/Added in right-document order, then Removed
+    /// in left-document order.
+    #[default]
+    DocumentOrder = 0,
+    /// By rendered JSON Pointer bytes, so a subtree's changes are adjacent.
+    /// Bytewise: `/a/10` sorts before `/a/9`. Ties keep engine order.
+    PathLexicographic = 1,
+    /// By op (Added, Removed, Modified), then as `PathLexicographic`.
+    OpThenPath = 2,
+}
+
+impl EntryOrder {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(EntryOrder::DocumentOrder),
+            1 => Some(EntryOrder::PathLexicographic),
+            2 => Some(EntryOrder::OpThenPath),
+            _ => None,
+        }
+    }
+
+    /// Name used in the JSON config form.
+    pub fn name(self) -> &'static str {
+        match self {
+            EntryOrder::DocumentOrder => "document_order",
+            EntryOrder::PathLexicographic => "path_lexicographic",
+            EntryOrder::OpThenPath => "op_then_path",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "document_order" => Some(EntryOrder::DocumentOrder),
+            "path_lexicographic" => Some(EntryOrder::PathLexicographic),
+            "op_then_path" => Some(EntryOrder::OpThenPath),
+            _ => None,
+        }
+    }
+}
+
+/// How `top_k` ranks entries; higher scores win. See `rank.rs`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Significance {
+    /// Absolute difference of the two value spans' lengths (an absent
+    /// side counts as 0).
+    #[default]
+    ByteDelta = 0,
+    /// Changes under the entry's parent container, the entry included.
+    SubtreeChangeCount = 1,
+    /// Shallower paths first: `2^32 / (depth + 1)`.
+    PathDepthInverse = 2,
+}
+
+impl Significance {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Significance::ByteDelta),
+            1 => Some(Significance::SubtreeChangeCount),
+            2 => Some(Significance::PathDepthInverse),
+            _ => None,
+        }
+    }
+
+    /// Name used in the JSON config form.
+    pub fn name(self) -> &'static str {
+        match self {
+            Significance::ByteDelta => "byte_delta",
+            Significance::SubtreeChangeCount => "subtree_change_count",
+            Significance::PathDepthInverse => "path_depth_inverse",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "byte_delta" => Some(Significance::ByteDelta),
+            "subtree_change_count" => Some(Significance::SubtreeChangeCount),
+            "path_depth_inverse" => Some(Significance::PathDepthInverse),
+            _ => None,
+        }
+    }
+}
+
+/// How the result arena grows while finalize writes. See `memory.rs`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ArenaGrowth {
+    /// Size the whole result before writing and reserve it once; when
+    /// that exceeds `max_memory_bytes`, grow in fixed chunks up to it.
+    #[default]
+    Reserve = 0,
+    /// Let the buffer double as entries are written.
+    Doubling = 1,
+}
+
+impl ArenaGrowth {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(ArenaGrowth::Reserve),
+            1 => Some(ArenaGrowth::Doubling),
+            _ => None,
+        }
+    }
+
+    /// Name used in the JSON config form.
+    pub fn name(self) -> &'static str {
+        match self {
+            ArenaGrowth::Reserve => "reserve",
+            ArenaGrowth::Doubling => "doubling",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "reserve" => Some(ArenaGrowth::Reserve),
+            "doubling" => Some(ArenaGrowth::Doubling),
+            _ => None,
+        }
+    }
+}
+
 impl ArrayDiffMode {
     pub fn from_u8(v: u8) -> Option<Self> {
         match v {
@@ -37,6 +226,24 @@ impl ArrayDiffMode {
             _ => None,
         }
     }
+
+    /// Name used in the JSON config form.
+    pub fn name(self) -> &'static str {
+        match self {
+            ArrayDiffMode::Index => "index",
+            ArrayDiffMode::HashWindow => "hash_window",
+            ArrayDiffMode::Full => "full",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "index" => Some(ArrayDiffMode::Index),
+            "hash_window" => Some(ArrayDiffMode::HashWindow),
+            "full" => Some(ArrayDiffMode::Full),
+            _ => None,
+        }
+    }
 }
 
 /// Engine configuration with explicit capability limits.
@@ -44,10 +251,12 @@ impl ArrayDiffMode {
 /// All limits are enforced with hard failure (no silent truncation).
 #[derive(Debug, Clone)]
 pub struct EngineConfig {
-    /// Maximum memory for result arena (bytes). Default: 32MB.
+    /// Maximum memory for result arena (bytes). Default: 32MB. At least
+    /// `MIN_MEMORY_BYTES`.
     pub max_memory_bytes: u32,
 
-    /// Maximum total input size (left + right). Default: 64MB.
+    /// Maximum total input size (left + right). Default: 64MB. At least
+    /// `MIN_INPUT_SIZE`.
     pub max_input_size: u32,
 
     /// Maximum object keys to buffer for late-arriving key handling.
@@ -57,7 +266,8 @@ pub struct EngineConfig {
     /// Array diff strategy.
     pub array_diff_mode: ArrayDiffMode,
 
-    /// Hash window size for HashWindow mode. Default: 64.
+    /// Hash window size for HashWindow mode. Default: 64. Between 1 and
+    /// `max_full_array_size`.
     pub hash_window_size: u16,
 
     /// Maximum array size for Full mode. Larger arrays fall back to Index.
@@ -65,18 +275,209 @@ pub struct EngineConfig {
 
     /// Optimization target.
     pub compute_mode: ComputeMode,
+
+    /// Maximum size of a single entry: its fixed record plus the value bytes
+    /// it references. 0 = unlimited. Default: 0.
+    pub max_entry_bytes: u32,
+
+    /// Handling of entries over `max_entry_bytes`.
+    pub oversize_policy: OversizePolicy,
+
+    /// Longest string or literal compared as a value. A longer one (a
+    /// multi-megabyte digit string) still hashes, but compares by its raw
+    /// bytes only: normalization, numeric deltas and lint leave it as
+    /// written, and a result cuts its side to this length, flagged
+    /// `ENTRY_FLAG_OVERSIZED_PRIMITIVE`, with the full length in the value
+    /// lengths section. 0 = unlimited. Default: `DEFAULT_MAX_PRIMITIVE_BYTES`.
+    pub max_primitive_bytes: u32,
+
+    /// A second commit on the same side discards the first parse and
+    /// re-parses instead of returning `Status::AlreadyCommitted`.
+    /// Default: false.
+    pub recommit_replaces: bool,
+
+    /// Fail commit on undecodable string escapes (bad `\u`, lone
+    /// surrogates) instead of comparing them as U+FFFD. Default: false.
+    pub strict_escapes: bool,
+
+    /// Entry order in the result. Default: `DocumentOrder`.
+    pub entry_order: EntryOrder,
+
+    /// Append the coverage section (left byte ranges with no counterpart
+    /// on the right) after the entries. Default: false.
+    pub coverage_map: bool,
+
+    /// Hash each input while committing it and append the input digest
+    /// section (see `digest.rs`). Default: false.
+    pub input_digests: bool,
+
+    /// Append the value hash section (each entry's right value hash), so
+    /// the result can be loaded into a later engine as suppressions.
+    /// Default: false.
+    pub value_hashes: bool,
+
+    /// Write only the `top_k` most significant entries, most significant
+    /// first, instead of every entry in `entry_order`. 0 = off. Default: 0.
+    pub top_k: u32,
+
+    /// Ranking metric for `top_k`. Default: `ByteDelta`.
+    pub significance: Significance,
+
+    /// Parse the right side only at paths the committed left side has,
+    /// so additions are never tokenized or hashed. The result then holds
+    /// no Added entries and carries `HEADER_FLAG_RIGHT_RESTRICTED`. The
+    /// left side must be committed first. Default: false.
+    pub right_restricted_to_left: bool,
+
+    /// Compare structure only: a Modified entry is written only when the
+    /// value's JSON type changed (see `diff::Shape`); Added and Removed
+    /// are unaffected. Default: false.
+    pub shape_only: bool,
+
+    /// Let finalize run with neither side committed, returning an empty
+    /// result flagged `HEADER_FLAG_NO_INPUT`. Off, that finalize fails
+    /// with "no input committed" so a forgotten commit is not read as "no
+    /// differences". Default: false.
+    pub allow_empty_side: bool,
+
+    /// Result arena growth. Default: `Reserve`.
+    pub arena_growth: ArenaGrowth,
+
+    /// Keep a log of the last this-many calls on the engine and write it
+    /// into each result (see `audit.rs`). 0 = off. Default: 0.
+    pub audit_log_len: u16,
+
+    /// Diff the left against two rights, the control (`commit_right`) and
+    /// the canary (`commit_third`), and report how their changes agree
+    /// (see `three_way.rs`). `max_input_size` is then split three ways.
+    /// Default: false.
+    pub three_way: bool,
+
+    /// For Modified entries whose sides are both finite numbers, append
+    /// the delta (right minus left) as an `f64` in the numeric delta
+    /// section and flag the entry (see `numeric.rs`). Default: false.
+    pub numeric_deltas: bool,
+
+    /// Append each entry's JSON Pointer in the path section, front-coded
+    /// against the previous entry's path when the entry order sorts by
+    /// path (see `memory.rs`). Default: false.
+    pub rendered_paths: bool,
+
+    /// Compare numbers by value: `1`, `1.0` and `10e-1` are equal (see
+    /// `normalize.rs`). Default: false.
+    pub normalize_numbers: bool,
+
+    /// Compare strings with their escapes decoded: `"\u00e9"` equals
+    /// `"é"`. Default: false.
+    pub normalize_strings: bool,
+
+    /// Compare strings ASCII case-insensitively. Keys are still
+    /// case-sensitive. Default: false.
+    pub ignore_case: bool,
+
+    /// Number the written entries 0, 1, 2... in entry order, after every
+    /// decision about which entries are kept and in what order, in the
+    /// entry sequence section (see `memory.rs`). Default: false.
+    pub entry_sequence: bool,
+
+    /// Write the patch section: the edits that turn the left document
+    /// into the right, each carrying its right value's text, so the
+    /// result rebuilds the right document without either input resident
+    /// (`apply_patch`, see `patch.rs`). Default: false.
+    pub self_contained: bool,
+
+    /// Path patterns (see `path_pattern.rs`) whose values never reach the
+    /// result: a matching entry keeps its op and path, each present side
+    /// becomes the redaction marker and the entry is flagged
+    /// `ENTRY_FLAG_REDACTED`. Default: empty.
+    pub redact_paths: Vec<String>,
+
+    /// Also copy each op's entries into a result of their own, read with
+    /// `get_result_ptr_for` / `get_result_len_for` (see `memory.rs`).
+    /// The classic result is written as always. Default: false.
+    pub split_by_op: bool,
 }
 
+/// Config flag bits (byte 25).
+pub const FLAG_RECOMMIT_REPLACES: u8 = 1 << 0;
+pub const FLAG_STRICT_ESCAPES: u8 = 1 << 1;
+pub const FLAG_COVERAGE_MAP: u8 = 1 << 2;
+pub const FLAG_INPUT_DIGESTS: u8 = 1 << 3;
+pub const FLAG_VALUE_HASHES: u8 = 1 << 4;
+pub const FLAG_RIGHT_RESTRICTED: u8 = 1 << 5;
+pub const FLAG_SHAPE_ONLY: u8 = 1 << 6;
+pub const FLAG_ALLOW_EMPTY_SIDE: u8 = 1 << 7;
+
+/// Config flag bits (byte 35).
+pub const FLAG2_THREE_WAY: u8 = 1 << 0;
+pub const FLAG2_NUMERIC_DELTAS: u8 = 1 << 1;
+pub const FLAG2_RENDERED_PATHS: u8 = 1 << 2;
+pub const FLAG2_NORMALIZE_NUMBERS: u8 = 1 << 3;
+pub const FLAG2_NORMALIZE_STRINGS: u8 = 1 << 4;
+pub const FLAG2_IGNORE_CASE: u8 = 1 << 5;
+pub const FLAG2_ENTRY_SEQUENCE: u8 = 1 << 6;
+pub const FLAG2_SELF_CONTAINED: u8 = 1 << 7;
+
+/// Config flag bits (the byte after `max_primitive_bytes` in the tail).
+pub const FLAG3_SPLIT_BY_OP: u8 = 1 << 0;
+
+/// Smallest accepted `max_memory_bytes`: the result header, its
+/// sections and a few entries. A smaller arena could only ever seal an
+/// empty result with every write failing `LimitExceeded`.
+pub const MIN_MEMORY_BYTES: u32 = 1024;
+/// Smallest accepted `max_input_size`, split across the inputs.
+pub const MIN_INPUT_SIZE: u32 = 256;
+
+/// Length of the original 20-byte config layout, still accepted as-is.
+pub const CONFIG_LEN_V1: usize = 20;
+/// Length of the current config layout.
+pub const CONFIG_LEN: usize = 36;
+
+/// Limits `EngineConfig::default()` starts from, also reported by
+/// `get_engine_info`.
+pub const DEFAULT_MAX_MEMORY_BYTES: u32 = 32 * 1024 * 1024; // 32MB
+pub const DEFAULT_MAX_INPUT_SIZE: u32 = 64 * 1024 * 1024; // 64MB
+pub const DEFAULT_MAX_OBJECT_KEYS: u32 = 100_000;
+pub const DEFAULT_HASH_WINDOW_SIZE: u16 = 64;
+pub const DEFAULT_MAX_FULL_ARRAY_SIZE: u32 = 1024;
+pub const DEFAULT_MAX_PRIMITIVE_BYTES: u32 = 4 * 1024 * 1024; // 4MB
+
 impl Default for EngineConfig {
     fn default() -> Self {
         Self {
-            max_memory_bytes: 32 * 1024 * 1024,      // 32MB
-            max_input_size: 64 * 1024 * 1024,        // 64MB
-            max_object_keys: 100_000,
+            max_memory_bytes: DEFAULT_MAX_MEMORY_BYTES,
+            max_input_size: DEFAULT_MAX_INPUT_SIZE,
+            max_object_keys: DEFAULT_MAX_OBJECT_KEYS,
             array_diff_mode: ArrayDiffMode::Index,
-            hash_window_size: 64,
-            max_full_array_size: 1024,
+            hash_window_size: DEFAULT_HASH_WINDOW_SIZE,
+            max_full_array_size: DEFAULT_MAX_FULL_ARRAY_SIZE,
             compute_mode: ComputeMode::Latency,
+            max_entry_bytes: 0,
+            oversize_policy: OversizePolicy::Skip,
+            max_primitive_bytes: DEFAULT_MAX_PRIMITIVE_BYTES,
+            recommit_replaces: false,
+            strict_escapes: false,
+            entry_order: EntryOrder::DocumentOrder,
+            coverage_map: false,
+            input_digests: false,
+            value_hashes: false,
+            top_k: 0,
+            significance: Significance::ByteDelta,
+            right_restricted_to_left: false,
+            shape_only: false,
+            allow_empty_side: false,
+            arena_growth: ArenaGrowth::Reserve,
+            audit_log_len: 0,
+            three_way: false,
+            numeric_deltas: false,
+            rendered_paths: false,
+            normalize_numbers: false,
+            normalize_strings: false,
+            ignore_case: false,
+            entry_sequence: false,
+            self_contained: false,
+            redact_paths: Vec::new(),
+            split_by_op: false,
         }
     }
 }
@@ -85,13 +486,39 @@ impl EngineConfig {
     /// Create config optimized for edge runtimes (lower memory limits).
     pub fn edge() -> Self {
         Self {
-            max_memory_bytes: 16 * 1024 * 1024,      // 16MB
-            max_input_size: 32 * 1024 * 1024,        // 32MB
+            max_memory_bytes: (16 * 1024 * 1024).max(MIN_MEMORY_BYTES), // 16MB
+            max_input_size: (32 * 1024 * 1024).max(MIN_INPUT_SIZE),     // 32MB
             max_object_keys: 50_000,
             array_diff_mode: ArrayDiffMode::Index,
             hash_window_size: 32,
             max_full_array_size: 512,
             compute_mode: ComputeMode::Edge,
+            max_entry_bytes: 0,
+            oversize_policy: OversizePolicy::Skip,
+            max_primitive_bytes: 1024 * 1024,
+            recommit_replaces: false,
+            strict_escapes: false,
+            entry_order: EntryOrder::DocumentOrder,
+            coverage_map: false,
+            input_digests: false,
+            value_hashes: false,
+            top_k: 0,
+            significance: Significance::ByteDelta,
+            right_restricted_to_left: false,
+            shape_only: false,
+            allow_empty_side: false,
+            arena_growth: ArenaGrowth::Reserve,
+            audit_log_len: 0,
+            three_way: false,
+            numeric_deltas: false,
+            rendered_paths: false,
+            normalize_numbers: false,
+            normalize_strings: false,
+            ignore_case: false,
+            entry_sequence: false,
+            self_contained: false,
+            redact_paths: Vec::new(),
+            split_by_op: false,
         }
     }
 
@@ -106,9 +533,26 @@ impl EngineConfig {
     /// [u16 hash_window_size]    (13-14)
     /// [u32 max_full_array_size] (15-18)
     /// [u8  compute_mode]        (19)
+    /// [u32 max_entry_bytes]     (20-23)  optional
+    /// [u8  oversize_policy]     (24)     optional
+    /// [u8  flags]               (25)     optional, FLAG_* bits
+    /// [u8  entry_order]         (26)     optional
+    /// [u32 top_k]               (27-30)  optional, with the next byte
+    /// [u8  significance]        (31)     optional
+    /// [u8  arena_growth]        (32)     optional
+    /// [u16 audit_log_len]       (33-34)  optional
+    /// [u8  flags2]              (35)     optional, FLAG2_* bits
+    /// [u16 redact_count]        (36-37)  optional, then per pattern:
+    ///   [u16 len][len bytes]             UTF-8
+    /// [u32 max_primitive_bytes]          optional, after the patterns
+    /// [u8  flags3]                       optional, FLAG3_* bits, after it
     /// ```
+    ///
+    /// A 20-byte config (the original layout) gets defaults for the
+    /// optional fields. The result must pass [`EngineConfig::check_limits`]
+    /// and every redaction pattern must compile.
     pub fn from_bytes(bytes: &[u8]) -> Result<Self, ConfigError> {
-        if bytes.len() < 20 {
+        if bytes.len() < CONFIG_LEN_V1 {
             return Err(ConfigError::TooShort);
         }
 
@@ -128,16 +572,42 @@ impl EngineConfig {
             _ => return Err(ConfigError::InvalidLimits), // Reuse for invalid mode
         };
 
-        // Validate bounds
-        if max_memory_bytes == 0 || max_input_size == 0 {
-            return Err(ConfigError::InvalidLimits);
-        }
-
-        if hash_window_size == 0 {
-            return Err(ConfigError::InvalidWindowSize);
-        }
+        let (max_entry_bytes, oversize_policy) = if bytes.len() >= 25 {
+            (
+                u32::from_le_bytes([bytes[20], bytes[21], bytes[22], bytes[23]]),
+                OversizePolicy::from_u8(bytes[24]).ok_or(ConfigError::InvalidLimits)?,
+            )
+        } else {
+            (0, OversizePolicy::Skip)
+        };
+        let flags = bytes.get(25).copied().unwrap_or(0);
+        let entry_order = match bytes.get(26) {
+            Some(&b) => EntryOrder::from_u8(b).ok_or(ConfigError::InvalidLimits)?,
+            None => EntryOrder::DocumentOrder,
+        };
+        let (top_k, significance) = if bytes.len() >= 32 {
+            (
+                u32::from_le_bytes([bytes[27], bytes[28], bytes[29], bytes[30]]),
+                Significance::from_u8(bytes[31]).ok_or(ConfigError::InvalidLimits)?,
+            )
+        } else {
+            (0, Significance::ByteDelta)
+        };
+        let arena_growth = match bytes.get(32) {
+            Some(&b) => ArenaGrowth::from_u8(b).ok_or(ConfigError::InvalidLimits)?,
+            None => ArenaGrowth::Reserve,
+        };
+        let audit_log_len = match bytes.get(33..35) {
+            Some(b) => u16::from_le_bytes([b[0], b[1]]),
+            None => 0,
+        };
+        let flags2 = bytes.get(35).copied().unwrap_or(0);
+        let (redact_paths, max_primitive_bytes, flags3) = match bytes.get(CONFIG_LEN..) {
+            Some(tail) if !tail.is_empty() => read_tail(tail).ok_or(ConfigError::InvalidValue(ConfigField::RedactPaths))?,
+            _ => (Vec::new(), DEFAULT_MAX_PRIMITIVE_BYTES, 0),
+        };
 
-        Ok(Self {
+        let config = Self {
             max_memory_bytes,
             max_input_size,
             max_object_keys,
@@ -145,12 +615,66 @@ impl EngineConfig {
             hash_window_size,
             max_full_array_size,
             compute_mode,
-        })
+            max_entry_bytes,
+            oversize_policy,
+            max_primitive_bytes,
+            recommit_replaces: flags & FLAG_RECOMMIT_REPLACES != 0,
+            strict_escapes: flags & FLAG_STRICT_ESCAPES != 0,
+            entry_order,
+            coverage_map: flags & FLAG_COVERAGE_MAP != 0,
+            input_digests: flags & FLAG_INPUT_DIGESTS != 0,
+            value_hashes: flags & FLAG_VALUE_HASHES != 0,
+            top_k,
+            significance,
+            right_restricted_to_left: flags & FLAG_RIGHT_RESTRICTED != 0,
+            shape_only: flags & FLAG_SHAPE_ONLY != 0,
+            allow_empty_side: flags & FLAG_ALLOW_EMPTY_SIDE != 0,
+            arena_growth,
+            audit_log_len,
+            three_way: flags2 & FLAG2_THREE_WAY != 0,
+            numeric_deltas: flags2 & FLAG2_NUMERIC_DELTAS != 0,
+            rendered_paths: flags2 & FLAG2_RENDERED_PATHS != 0,
+            normalize_numbers: flags2 & FLAG2_NORMALIZE_NUMBERS != 0,
+            normalize_strings: flags2 & FLAG2_NORMALIZE_STRINGS != 0,
+            ignore_case: flags2 & FLAG2_IGNORE_CASE != 0,
+            entry_sequence: flags2 & FLAG2_ENTRY_SEQUENCE != 0,
+            self_contained: flags2 & FLAG2_SELF_CONTAINED != 0,
+            redact_paths,
+            split_by_op: flags3 & FLAG3_SPLIT_BY_OP != 0,
+        };
+        config.check_limits()?;
+        config.redact_patterns()?;
+        Ok(config)
+    }
+
+    /// `redact_paths`, compiled; `InvalidValue(RedactPaths)` for the
+    /// first that does not compile.
+    pub fn redact_patterns(&self) -> Result<Vec<PathPattern>, ConfigError> {
+        self.redact_paths
+            .iter()
+            .map(|p| PathPattern::parse(p.as_bytes()).ok_or(ConfigError::InvalidValue(ConfigField::RedactPaths)))
+            .collect()
     }
 
-    /// Serialize configuration to binary format.
-    pub fn to_bytes(&self) -> [u8; 20] {
-        let mut buf = [0u8; 20];
+    /// Reject limits no engine can work within: an arena below
+    /// `MIN_MEMORY_BYTES` or inputs below `MIN_INPUT_SIZE`
+    /// (`InvalidLimits`), and a hash window of 0 or wider than
+    /// `max_full_array_size` (`InvalidWindowSize`). Parsing checks this;
+    /// a config built in Rust and handed to `Engine::new` is taken as is.
+    pub fn check_limits(&self) -> Result<(), ConfigError> {
+        if self.max_memory_bytes < MIN_MEMORY_BYTES || self.max_input_size < MIN_INPUT_SIZE {
+            return Err(ConfigError::InvalidLimits);
+        }
+        if self.hash_window_size == 0 || u32::from(self.hash_window_size) > self.max_full_array_size {
+            return Err(ConfigError::InvalidWindowSize);
+        }
+        Ok(())
+    }
+
+    /// Serialize configuration to binary format: `CONFIG_LEN` bytes, plus
+    /// the pattern tail when `redact_paths` is not empty.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut buf = alloc::vec![0u8; CONFIG_LEN];
         buf[0..4].copy_from_slice(&self.max_memory_bytes.to_le_bytes());
         buf[4..8].copy_from_slice(&self.max_input_size.to_le_bytes());
         buf[8..12].copy_from_slice(&self.max_object_keys.to_le_bytes());
@@ -158,14 +682,412 @@ impl EngineConfig {
         buf[13..15].copy_from_slice(&self.hash_window_size.to_le_bytes());
         buf[15..19].copy_from_slice(&self.max_full_array_size.to_le_bytes());
         buf[19] = self.compute_mode as u8;
+        buf[20..24].copy_from_slice(&self.max_entry_bytes.to_le_bytes());
+        buf[24] = self.oversize_policy as u8;
+        if self.recommit_replaces { buf[25] |= FLAG_RECOMMIT_REPLACES; }
+        if self.strict_escapes { buf[25] |= FLAG_STRICT_ESCAPES; }
+        if self.coverage_map { buf[25] |= FLAG_COVERAGE_MAP; }
+        if self.input_digests { buf[25] |= FLAG_INPUT_DIGESTS; }
+        if self.value_hashes { buf[25] |= FLAG_VALUE_HASHES; }
+        if self.right_restricted_to_left { buf[25] |= FLAG_RIGHT_RESTRICTED; }
+        if self.shape_only { buf[25] |= FLAG_SHAPE_ONLY; }
+        if self.allow_empty_side { buf[25] |= FLAG_ALLOW_EMPTY_SIDE; }
+        buf[26] = self.entry_order as u8;
+        buf[27..31].copy_from_slice(&self.top_k.to_le_bytes());
+        buf[31] = self.significance as u8;
+        buf[32] = self.arena_growth as u8;
+        buf[33..35].copy_from_slice(&self.audit_log_len.to_le_bytes());
+        if self.three_way { buf[35] |= FLAG2_THREE_WAY; }
+        if self.numeric_deltas { buf[35] |= FLAG2_NUMERIC_DELTAS; }
+        if self.rendered_paths { buf[35] |= FLAG2_RENDERED_PATHS; }
+        if self.normalize_numbers { buf[35] |= FLAG2_NORMALIZE_NUMBERS; }
+        if self.normalize_strings { buf[35] |= FLAG2_NORMALIZE_STRINGS; }
+        if self.ignore_case { buf[35] |= FLAG2_IGNORE_CASE; }
+        if self.entry_sequence { buf[35] |= FLAG2_ENTRY_SEQUENCE; }
+        if self.self_contained { buf[35] |= FLAG2_SELF_CONTAINED; }
+        let flags3 = if self.split_by_op { FLAG3_SPLIT_BY_OP } else { 0 };
+        let primitive_cap = self.max_primitive_bytes != DEFAULT_MAX_PRIMITIVE_BYTES || flags3 != 0;
+        if !self.redact_paths.is_empty() || primitive_cap {
+            buf.extend_from_slice(&(self.redact_paths.len() as u16).to_le_bytes());
+            for p in &self.redact_paths {
+                buf.extend_from_slice(&(p.len() as u16).to_le_bytes());
+                buf.extend_from_slice(p.as_bytes());
+            }
+        }
+        if primitive_cap {
+            buf.extend_from_slice(&self.max_primitive_bytes.to_le_bytes());
+        }
+        if flags3 != 0 { buf.push(flags3); }
         buf
     }
 }
 
+/// The tail of the binary form: the patterns its count announces, each
+/// valid UTF-8, then optionally `max_primitive_bytes` and after it the
+/// `FLAG3_*` byte. `None` for anything else.
+fn read_tail(tail: &[u8]) -> Option<(Vec<String>, u32, u8)> {
+    let mut at = 0usize;
+    let mut take = |n: usize| {
+        let bytes = tail.get(at..at.checked_add(n)?)?;
+        at += n;
+        Some(bytes)
+    };
+    let u16_of = |b: &[u8]| u16::from_le_bytes([b[0], b[1]]) as usize;
+    let count = u16_of(take(2)?);
+    let mut out = Vec::with_capacity(count);
+    for _ in 0..count {
+        let len = u16_of(take(2)?);
+        out.push(String::from(core::str::from_utf8(take(len)?).ok()?));
+    }
+    let (max_primitive_bytes, flags3) = match tail.len() - at {
+        0 => (DEFAULT_MAX_PRIMITIVE_BYTES, 0),
+        4 => (u32::from_le_bytes(tail[at..].try_into().ok()?), 0),
+        5 => (u32::from_le_bytes(tail[at..at + 4].try_into().ok()?), tail[at + 4]),
+        _ => return None,
+    };
+    Some((out, max_primitive_bytes, flags3))
+}
+
+/// `EngineConfig` fields, as named in `set_output_options` records.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConfigField {
+    MaxMemoryBytes = 0,
+    MaxInputSize = 1,
+    MaxObjectKeys = 2,
+    ArrayDiffMode = 3,
+    HashWindowSize = 4,
+    MaxFullArraySize = 5,
+    ComputeMode = 6,
+    MaxEntryBytes = 7,
+    OversizePolicy = 8,
+    RecommitReplaces = 9,
+    StrictEscapes = 10,
+    EntryOrder = 11,
+    CoverageMap = 12,
+    InputDigests = 13,
+    ValueHashes = 14,
+    TopK = 15,
+    Significance = 16,
+    RightRestrictedToLeft = 17,
+    ShapeOnly = 18,
+    AllowEmptySide = 19,
+    ArenaGrowth = 20,
+    AuditLogLen = 21,
+    ThreeWay = 22,
+    NumericDeltas = 23,
+    RenderedPaths = 24,
+    NormalizeNumbers = 25,
+    NormalizeStrings = 26,
+    IgnoreCase = 27,
+    RedactPaths = 28,
+    EntrySequence = 29,
+    MaxPrimitiveBytes = 30,
+    SelfContained = 31,
+    SplitByOp = 32,
+}
+
+impl ConfigField {
+    pub fn from_u8(v: u8) -> Option<Self> {
+        use ConfigField::*;
+        [
+            MaxMemoryBytes, MaxInputSize, MaxObjectKeys, ArrayDiffMode, HashWindowSize,
+            MaxFullArraySize, ComputeMode, MaxEntryBytes, OversizePolicy, RecommitReplaces,
+            StrictEscapes, EntryOrder, CoverageMap, InputDigests, ValueHashes, TopK, Significance,
+            RightRestrictedToLeft, ShapeOnly, AllowEmptySide, ArenaGrowth, AuditLogLen,
+            ThreeWay, NumericDeltas, RenderedPaths, NormalizeNumbers, NormalizeStrings, IgnoreCase,
+            RedactPaths, EntrySequence, MaxPrimitiveBytes, SelfContained, SplitByOp,
+        ]
+        .get(v as usize)
+        .copied()
+    }
+
+    /// The `EngineConfig` field name.
+    pub fn name(self) -> &'static str {
+        match self {
+            ConfigField::MaxMemoryBytes => "max_memory_bytes",
+            ConfigField::MaxInputSize => "max_input_size",
+            ConfigField::MaxObjectKeys => "max_object_keys",
+            ConfigField::ArrayDiffMode => "array_diff_mode",
+            ConfigField::HashWindowSize => "hash_window_size",
+            ConfigField::MaxFullArraySize => "max_full_array_size",
+            ConfigField::ComputeMode => "compute_mode",
+            ConfigField::MaxEntryBytes => "max_entry_bytes",
+            ConfigField::OversizePolicy => "oversize_policy",
+            ConfigField::RecommitReplaces => "recommit_replaces",
+            ConfigField::StrictEscapes => "strict_escapes",
+            ConfigField::EntryOrder => "entry_order",
+            ConfigField::CoverageMap => "coverage_map",
+            ConfigField::InputDigests => "input_digests",
+            ConfigField::ValueHashes => "value_hashes",
+            ConfigField::TopK => "top_k",
+            ConfigField::Significance => "significance",
+            ConfigField::RightRestrictedToLeft => "right_restricted_to_left",
+            ConfigField::ShapeOnly => "shape_only",
+            ConfigField::AllowEmptySide => "allow_empty_side",
+            ConfigField::ArenaGrowth => "arena_growth",
+            ConfigField::AuditLogLen => "audit_log_len",
+            ConfigField::ThreeWay => "three_way",
+            ConfigField::NumericDeltas => "numeric_deltas",
+            ConfigField::RenderedPaths => "rendered_paths",
+            ConfigField::NormalizeNumbers => "normalize_numbers",
+            ConfigField::NormalizeStrings => "normalize_strings",
+            ConfigField::IgnoreCase => "ignore_case",
+            ConfigField::RedactPaths => "redact_paths",
+            ConfigField::EntrySequence => "entry_sequence",
+            ConfigField::MaxPrimitiveBytes => "max_primitive_bytes",
+            ConfigField::SelfContained => "self_contained",
+            ConfigField::SplitByOp => "split_by_op",
+        }
+    }
+}
+
+/// The finalize-time subset of `EngineConfig`: options that only shape the
+/// result, so a host can still change them after committing input.
+///
+/// Precedence: the creation-time config supplies every value;
+/// `set_output_options` overrides the fields it names, last write wins.
+/// `clear_engine` drops the overrides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OutputOptions {
+    pub max_entry_bytes: u32,
+    pub oversize_policy: OversizePolicy,
+    pub entry_order: EntryOrder,
+    pub coverage_map: bool,
+    pub value_hashes: bool,
+    pub top_k: u32,
+    pub significance: Significance,
+    pub shape_only: bool,
+    pub numeric_deltas: bool,
+    pub rendered_paths: bool,
+    pub normalize_numbers: bool,
+    pub normalize_strings: bool,
+    pub ignore_case: bool,
+    pub entry_sequence: bool,
+    pub self_contained: bool,
+    pub split_by_op: bool,
+}
+
+impl OutputOptions {
+    /// Size of one `[u8 field][u32 value]` record.
+    pub const RECORD_LEN: usize = 5;
+
+    pub fn from_config(config: &EngineConfig) -> Self {
+        Self {
+            max_entry_bytes: config.max_entry_bytes,
+            oversize_policy: config.oversize_policy,
+            entry_order: config.entry_order,
+            coverage_map: config.coverage_map,
+            value_hashes: config.value_hashes,
+            top_k: config.top_k,
+            significance: config.significance,
+            shape_only: config.shape_only,
+            numeric_deltas: config.numeric_deltas,
+            rendered_paths: config.rendered_paths,
+            normalize_numbers: config.normalize_numbers,
+            normalize_strings: config.normalize_strings,
+            ignore_case: config.ignore_case,
+            entry_sequence: config.entry_sequence,
+            self_contained: config.self_contained,
+            split_by_op: config.split_by_op,
+        }
+    }
+
+    /// Whether finalize needs rendered paths (path orders, `top_k` ties,
+    /// the path section).
+    pub fn needs_paths(&self) -> bool {
+        self.entry_order != EntryOrder::DocumentOrder || self.top_k != 0 || self.rendered_paths
+    }
+
+    /// Whether entries come out sorted by path, so the path section is
+    /// front-coded: a path order without `top_k` ranking on top.
+    pub fn paths_sorted(&self) -> bool {
+        self.entry_order != EntryOrder::DocumentOrder && self.top_k == 0
+    }
+
+    /// Layer override records over `self`.
+    ///
+    /// Format: zero or more records of `[u8 ConfigField][u32 value]`
+    /// (little-endian). All-or-nothing: any bad record rejects the blob.
+    /// Fields that affect parsing or limits are refused by name.
+    pub fn with_records(mut self, bytes: &[u8]) -> Result<Self, ConfigError> {
+        if !bytes.len().is_multiple_of(Self::RECORD_LEN) {
+            return Err(ConfigError::TooShort);
+        }
+        for record in bytes.chunks_exact(Self::RECORD_LEN) {
+            let field = ConfigField::from_u8(record[0]).ok_or(ConfigError::UnknownField(record[0]))?;
+            let value = u32::from_le_bytes([record[1], record[2], record[3], record[4]]);
+            let small = u8::try_from(value).ok();
+            match field {
+                ConfigField::MaxEntryBytes => self.max_entry_bytes = value,
+                ConfigField::OversizePolicy => {
+                    self.oversize_policy = small
+                        .and_then(OversizePolicy::from_u8)
+                        .ok_or(ConfigError::InvalidValue(field))?;
+                }
+                ConfigField::EntryOrder => {
+                    self.entry_order = small
+                        .and_then(EntryOrder::from_u8)
+                        .ok_or(ConfigError::InvalidValue(field))?;
+                }
+                ConfigField::TopK => self.top_k = value,
+                ConfigField::Significance => {
+                    self.significance = small
+                        .and_then(Significance::from_u8)
+                        .ok_or(ConfigError::InvalidValue(field))?;
+                }
+                ConfigField::CoverageMap
+                | ConfigField::ValueHashes
+                | ConfigField::ShapeOnly
+                | ConfigField::NumericDeltas
+                | ConfigField::RenderedPaths
+                | ConfigField::NormalizeNumbers
+                | ConfigField::NormalizeStrings
+                | ConfigField::IgnoreCase
+                | ConfigField::EntrySequence
+                | ConfigField::SelfContained
+                | ConfigField::SplitByOp => {
+                    let on = match value {
+                        0 => false,
+                        1 => true,
+                        _ => return Err(ConfigError::InvalidValue(field)),
+                    };
+                    match field {
+                        ConfigField::CoverageMap => self.coverage_map = on,
+                        ConfigField::ValueHashes => self.value_hashes = on,
+                        ConfigField::NumericDeltas => self.numeric_deltas = on,
+                        ConfigField::RenderedPaths => self.rendered_paths = on,
+                        ConfigField::NormalizeNumbers => self.normalize_numbers = on,
+                        ConfigField::NormalizeStrings => self.normalize_strings = on,
+                        ConfigField::IgnoreCase => self.ignore_case = on,
+                        ConfigField::EntrySequence => self.entry_sequence = on,
+                        ConfigField::SelfContained => self.self_contained = on,
+                        ConfigField::SplitByOp => self.split_by_op = on,
+                        _ => self.shape_only = on,
+                    }
+                }
+                _ => return Err(ConfigError::NotAnOutputOption(field)),
+            }
+        }
+        Ok(self)
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ConfigError {
     TooShort,
     InvalidArrayMode,
     InvalidLimits,
     InvalidWindowSize,
+    /// Record names a field id outside `ConfigField`.
+    UnknownField(u8),
+    /// Record value out of range for its field.
+    InvalidValue(ConfigField),
+    /// Field is fixed at creation; it affects parsing or limits.
+    NotAnOutputOption(ConfigField),
+    /// JSON config form is malformed or names an unknown key, at this
+    /// byte offset.
+    MalformedJson(u32),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::TooShort => write!(f, "config truncated"),
+            ConfigError::InvalidArrayMode => write!(f, "invalid array_diff_mode"),
+            ConfigError::InvalidLimits => write!(
+                f,
+                "invalid limits (max_memory_bytes must be at least {}, max_input_size at least {})",
+                MIN_MEMORY_BYTES, MIN_INPUT_SIZE
+            ),
+            ConfigError::InvalidWindowSize => {
+                write!(f, "invalid hash_window_size (must be between 1 and max_full_array_size)")
+            }
+            ConfigError::UnknownField(id) => write!(f, "unknown config field {}", id),
+            ConfigError::InvalidValue(field) => write!(f, "invalid value for {}", field.name()),
+            ConfigError::NotAnOutputOption(field) => {
+                write!(f, "{} is fixed at engine creation (affects parsing or limits)", field.name())
+            }
+            ConfigError::MalformedJson(at) => write!(f, "malformed config JSON at byte {}", at),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::string::ToString;
+
+    fn parse(config: EngineConfig) -> Result<EngineConfig, ConfigError> {
+        EngineConfig::from_bytes(&config.to_bytes())
+    }
+
+    #[test]
+    fn rejects_limits_below_the_floors() {
+        let base = EngineConfig::default();
+        for max_memory_bytes in [0, 8, 16 + 32, MIN_MEMORY_BYTES - 1] {
+            let config = EngineConfig { max_memory_bytes, ..base.clone() };
+            assert_eq!(parse(config).unwrap_err(), ConfigError::InvalidLimits, "{}", max_memory_bytes);
+        }
+        for max_input_size in [0, MIN_INPUT_SIZE - 1] {
+            let config = EngineConfig { max_input_size, ..base.clone() };
+            assert_eq!(parse(config).unwrap_err(), ConfigError::InvalidLimits, "{}", max_input_size);
+        }
+        let text = ConfigError::InvalidLimits.to_string();
+        assert!(text.contains("1024") && text.contains("256"), "{}", text);
+
+        let floor = EngineConfig { max_memory_bytes: MIN_MEMORY_BYTES, max_input_size: MIN_INPUT_SIZE, ..base };
+        assert_eq!(parse(floor.clone()).unwrap().to_bytes(), floor.to_bytes());
+    }
+
+    #[test]
+    fn rejects_a_hash_window_wider_than_full_arrays() {
+        let base = EngineConfig::default();
+        assert_eq!(parse(EngineConfig { hash_window_size: 0, ..base.clone() }).unwrap_err(), ConfigError::InvalidWindowSize);
+        let wide = EngineConfig { hash_window_size: 65, max_full_array_size: 64, ..base.clone() };
+        assert_eq!(parse(wide).unwrap_err(), ConfigError::InvalidWindowSize);
+        let equal = EngineConfig { hash_window_size: 64, max_full_array_size: 64, ..base };
+        assert!(parse(equal).is_ok());
+    }
+
+    #[test]
+    fn presets_pass_their_own_checks() {
+        for preset in [EngineConfig::default(), EngineConfig::edge()] {
+            assert_eq!(preset.check_limits(), Ok(()));
+            assert!(preset.max_memory_bytes >= MIN_MEMORY_BYTES && preset.max_input_size >= MIN_INPUT_SIZE);
+            assert_eq!(parse(preset.clone()).unwrap().to_bytes(), preset.to_bytes());
+        }
+        // The original 20-byte layout goes through the same checks.
+        let mut v1 = EngineConfig::edge().to_bytes();
+        v1[0..4].copy_from_slice(&512u32.to_le_bytes());
+        assert_eq!(EngineConfig::from_bytes(&v1[..CONFIG_LEN_V1]).unwrap_err(), ConfigError::InvalidLimits);
+    }
+
+    #[test]
+    fn redact_paths_ride_after_the_fixed_layout() {
+        let config = EngineConfig { redact_paths: alloc::vec!["**.password".to_string(), "a.*".to_string()], ..EngineConfig::default() };
+        let bytes = config.to_bytes();
+        assert_eq!(bytes[CONFIG_LEN..], *b"\x02\x00\x0b\x00**.password\x03\x00a.*");
+        assert_eq!(parse(config.clone()).unwrap().redact_paths, config.redact_paths);
+        assert_eq!(EngineConfig::default().to_bytes().len(), CONFIG_LEN);
+
+        let invalid = ConfigError::InvalidValue(ConfigField::RedactPaths);
+        assert_eq!(EngineConfig::from_bytes(&bytes[..bytes.len() - 1]).unwrap_err(), invalid, "short");
+        assert_eq!(EngineConfig::from_bytes(&[&bytes[..], b"x"].concat()).unwrap_err(), invalid, "trailing bytes");
+        let bad = EngineConfig { redact_paths: alloc::vec!["a..b".to_string()], ..EngineConfig::default() };
+        assert_eq!(parse(bad).unwrap_err(), invalid);
+
+        // A non-default primitive cap follows the patterns, even none.
+        let capped = EngineConfig { max_primitive_bytes: 1 << 16, ..EngineConfig::default() };
+        assert_eq!(capped.to_bytes()[CONFIG_LEN..], [0, 0, 0, 0, 1, 0]);
+        assert_eq!(parse(capped).unwrap().max_primitive_bytes, 1 << 16);
+        let both = parse(EngineConfig { max_primitive_bytes: 0, ..config.clone() }).unwrap();
+        assert_eq!((both.redact_paths, both.max_primitive_bytes), (config.redact_paths, 0));
+
+        // The FLAG3 byte follows the cap, written even at its default.
+        let split = EngineConfig { split_by_op: true, ..EngineConfig::default() };
+        assert_eq!(split.to_bytes()[CONFIG_LEN..], [0, 0, 0, 0, 0x40, 0, FLAG3_SPLIT_BY_OP]);
+        let back = parse(split).unwrap();
+        assert_eq!((back.split_by_op, back.max_primitive_bytes), (true, DEFAULT_MAX_PRIMITIVE_BYTES));
+    }
 }
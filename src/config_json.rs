@@ -0,0 +1,483 @@
+//! Canonical JSON form of `EngineConfig`, for logs and bug reports.
+//!
+//! `to_json` writes every field as one flat object with keys sorted
+//! bytewise and no whitespace, so equal configs give equal strings. Enums
+//! are written by name (`"hash_window"`), `redact_paths` as an array of
+//! strings, everything else as a JSON number or boolean.
+//!
+//! `from_json` reads that form back. Missing keys take their defaults;
+//! unknown keys are rejected, except `derived`, which the engine adds to
+//! its effective config (values computed from the config, e.g. the
+//! per-side input capacity) and which is skipped on the way in. The
+//! result is validated exactly as `from_bytes` validates the binary form.
+//! `with_option` reads one member the same way, for `set_option`.
+
+use crate::config::{
+    ArenaGrowth, ArrayDiffMode, ComputeMode, ConfigError, ConfigField, EngineConfig, EntryOrder, OversizePolicy, Significance,
+};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+impl EngineConfig {
+    /// Canonical JSON of every field; see the module docs.
+    pub fn to_json(&self) -> String {
+        self.to_json_with(Vec::new())
+    }
+
+    /// `to_json` plus extra pre-rendered members, sorted in with the rest.
+    pub(crate) fn to_json_with(&self, mut members: Vec<(&'static str, String)>) -> String {
+        // Exhaustive: a new field fails to compile until it is written here
+        // (and read back in `from_json`).
+        let EngineConfig {
+            max_memory_bytes,
+            max_input_size,
+            max_object_keys,
+            array_diff_mode,
+            hash_window_size,
+            max_full_array_size,
+            compute_mode,
+            max_entry_bytes,
+            oversize_policy,
+            max_primitive_bytes,
+            recommit_replaces,
+            strict_escapes,
+            entry_order,
+            coverage_map,
+            input_digests,
+            value_hashes,
+            top_k,
+            significance,
+            right_restricted_to_left,
+            shape_only,
+            allow_empty_side,
+            arena_growth,
+            audit_log_len,
+            three_way,
+            numeric_deltas,
+            rendered_paths,
+            normalize_numbers,
+            normalize_strings,
+            ignore_case,
+            entry_sequence,
+            self_contained,
+            redact_paths,
+            split_by_op,
+        } = self;
+        let string = |s: &str| format!("\"{}\"", s);
+        // Patterns hold no byte that would need escaping (`path_pattern.rs`).
+        let strings = |v: &[String]| format!("[{}]", v.iter().map(|s| string(s)).collect::<Vec<_>>().join(","));
+        members.extend([
+            ("max_memory_bytes", format!("{}", max_memory_bytes)),
+            ("max_input_size", format!("{}", max_input_size)),
+            ("max_object_keys", format!("{}", max_object_keys)),
+            ("array_diff_mode", string(array_diff_mode.name())),
+            ("hash_window_size", format!("{}", hash_window_size)),
+            ("max_full_array_size", format!("{}", max_full_array_size)),
+            ("compute_mode", string(compute_mode.name())),
+            ("max_entry_bytes", format!("{}", max_entry_bytes)),
+            ("oversize_policy", string(oversize_policy.name())),
+            ("max_primitive_bytes", format!("{}", max_primitive_bytes)),
+            ("recommit_replaces", format!("{}", recommit_replaces)),
+            ("strict_escapes", format!("{}", strict_escapes)),
+            ("entry_order", string(entry_order.name())),
+            ("coverage_map", format!("{}", coverage_map)),
+            ("input_digests", format!("{}", input_digests)),
+            ("value_hashes", format!("{}", value_hashes)),
+            ("top_k", format!("{}", top_k)),
+            ("significance", string(significance.name())),
+            ("right_restricted_to_left", format!("{}", right_restricted_to_left)),
+            ("shape_only", format!("{}", shape_only)),
+            ("allow_empty_side", format!("{}", allow_empty_side)),
+            ("arena_growth", string(arena_growth.name())),
+            ("audit_log_len", format!("{}", audit_log_len)),
+            ("three_way", format!("{}", three_way)),
+            ("numeric_deltas", format!("{}", numeric_deltas)),
+            ("rendered_paths", format!("{}", rendered_paths)),
+            ("normalize_numbers", format!("{}", normalize_numbers)),
+            ("normalize_strings", format!("{}", normalize_strings)),
+            ("ignore_case", format!("{}", ignore_case)),
+            ("entry_sequence", format!("{}", entry_sequence)),
+            ("self_contained", format!("{}", self_contained)),
+            ("redact_paths", strings(redact_paths)),
+            ("split_by_op", format!("{}", split_by_op)),
+        ]);
+        members.sort_unstable_by_key(|&(key, _)| key);
+
+        let mut out = String::from("{");
+        for (i, (key, value)) in members.iter().enumerate() {
+            if i > 0 { out.push(','); }
+            out.push_str(&format!("\"{}\":{}", key, value));
+        }
+        out.push('}');
+        out
+    }
+
+    /// Parse the form written by `to_json`; see the module docs.
+    pub fn from_json(json: &str) -> Result<Self, ConfigError> {
+        let mut r = Reader { bytes: json.as_bytes(), pos: 0 };
+        let mut c = EngineConfig::default();
+        r.expect(b'{')?;
+        if !r.eat(b'}') {
+            loop {
+                let at = r.pos;
+                let key = r.string()?;
+                r.expect(b':')?;
+                match key {
+                    "derived" => r.skip_object()?,
+                    _ if c.read_member(key, &mut r)? => {}
+                    _ => return Err(ConfigError::MalformedJson(at as u32)),
+                }
+                if r.eat(b'}') { break; }
+                r.expect(b',')?;
+            }
+        }
+        r.skip_ws();
+        if r.pos != r.bytes.len() {
+            return Err(ConfigError::MalformedJson(r.pos as u32));
+        }
+        Self::from_bytes(&c.to_bytes())
+    }
+
+    /// This config with option `key` set from `value` (`set_option`):
+    /// the member's JSON value, quotes optional for a string (`full`,
+    /// `"full"`, `64`, `true`, `[".a.*"]`). `Ok(None)` when `key` is not a
+    /// field; otherwise validated as `from_json` validates.
+    pub fn with_option(&self, key: &str, value: &[u8]) -> Result<Option<Self>, ConfigError> {
+        let bare = !value.is_empty()
+            && !matches!(value, b"true" | b"false")
+            && !value.iter().all(u8::is_ascii_digit)
+            && !matches!(value[0], b'"' | b'[');
+        let quoted;
+        let value = if bare {
+            quoted = [&b"\""[..], value, b"\""].concat();
+            &quoted[..]
+        } else {
+            value
+        };
+        let mut r = Reader { bytes: value, pos: 0 };
+        let mut c = self.clone();
+        if !c.read_member(key, &mut r)? { return Ok(None); }
+        r.skip_ws();
+        if r.pos != r.bytes.len() {
+            return Err(ConfigError::MalformedJson(r.pos as u32));
+        }
+        Self::from_bytes(&c.to_bytes()).map(Some)
+    }
+
+    /// Read the value of member `key` from `r` into its field; false for
+    /// a key that is not a field.
+    fn read_member(&mut self, key: &str, r: &mut Reader<'_>) -> Result<bool, ConfigError> {
+        match key {
+            "max_memory_bytes" => self.max_memory_bytes = r.number(ConfigField::MaxMemoryBytes)?,
+            "max_input_size" => self.max_input_size = r.number(ConfigField::MaxInputSize)?,
+            "max_object_keys" => self.max_object_keys = r.number(ConfigField::MaxObjectKeys)?,
+            "array_diff_mode" => {
+                self.array_diff_mode = r.named(ConfigField::ArrayDiffMode, ArrayDiffMode::from_name)?;
+            }
+            "hash_window_size" => {
+                let v = r.number(ConfigField::HashWindowSize)?;
+                self.hash_window_size = u16::try_from(v).map_err(|_| ConfigError::InvalidValue(ConfigField::HashWindowSize))?;
+            }
+            "max_full_array_size" => self.max_full_array_size = r.number(ConfigField::MaxFullArraySize)?,
+            "compute_mode" => self.compute_mode = r.named(ConfigField::ComputeMode, ComputeMode::from_name)?,
+            "max_entry_bytes" => self.max_entry_bytes = r.number(ConfigField::MaxEntryBytes)?,
+            "oversize_policy" => {
+                self.oversize_policy = r.named(ConfigField::OversizePolicy, OversizePolicy::from_name)?;
+            }
+            "max_primitive_bytes" => self.max_primitive_bytes = r.number(ConfigField::MaxPrimitiveBytes)?,
+            "recommit_replaces" => self.recommit_replaces = r.boolean(ConfigField::RecommitReplaces)?,
+            "strict_escapes" => self.strict_escapes = r.boolean(ConfigField::StrictEscapes)?,
+            "entry_order" => self.entry_order = r.named(ConfigField::EntryOrder, EntryOrder::from_name)?,
+            "coverage_map" => self.coverage_map = r.boolean(ConfigField::CoverageMap)?,
+            "input_digests" => self.input_digests = r.boolean(ConfigField::InputDigests)?,
+            "value_hashes" => self.value_hashes = r.boolean(ConfigField::ValueHashes)?,
+            "top_k" => self.top_k = r.number(ConfigField::TopK)?,
+            "significance" => {
+                self.significance = r.named(ConfigField::Significance, Significance::from_name)?;
+            }
+            "right_restricted_to_left" => {
+                self.right_restricted_to_left = r.boolean(ConfigField::RightRestrictedToLeft)?;
+            }
+            "shape_only" => self.shape_only = r.boolean(ConfigField::ShapeOnly)?,
+            "allow_empty_side" => self.allow_empty_side = r.boolean(ConfigField::AllowEmptySide)?,
+            "arena_growth" => self.arena_growth = r.named(ConfigField::ArenaGrowth, ArenaGrowth::from_name)?,
+            "audit_log_len" => {
+                let v = r.number(ConfigField::AuditLogLen)?;
+                self.audit_log_len = u16::try_from(v).map_err(|_| ConfigError::InvalidValue(ConfigField::AuditLogLen))?;
+            }
+            "three_way" => self.three_way = r.boolean(ConfigField::ThreeWay)?,
+            "numeric_deltas" => self.numeric_deltas = r.boolean(ConfigField::NumericDeltas)?,
+            "rendered_paths" => self.rendered_paths = r.boolean(ConfigField::RenderedPaths)?,
+            "normalize_numbers" => self.normalize_numbers = r.boolean(ConfigField::NormalizeNumbers)?,
+            "normalize_strings" => self.normalize_strings = r.boolean(ConfigField::NormalizeStrings)?,
+            "ignore_case" => self.ignore_case = r.boolean(ConfigField::IgnoreCase)?,
+            "entry_sequence" => self.entry_sequence = r.boolean(ConfigField::EntrySequence)?,
+            "self_contained" => self.self_contained = r.boolean(ConfigField::SelfContained)?,
+            "redact_paths" => self.redact_paths = r.strings(ConfigField::RedactPaths)?,
+            "split_by_op" => self.split_by_op = r.boolean(ConfigField::SplitByOp)?,
+            _ => return Ok(false),
+        }
+        Ok(true)
+    }
+}
+
+/// Just enough JSON for the flat `to_json` object: no string escapes, no
+/// fractions or signs, arrays of strings, one level of nesting (skipped).
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn skip_ws(&mut self) {
+        while self.bytes.get(self.pos).is_some_and(|b| b" \t\r\n".contains(b)) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, byte: u8) -> bool {
+        self.skip_ws();
+        let hit = self.bytes.get(self.pos) == Some(&byte);
+        if hit { self.pos += 1; }
+        hit
+    }
+
+    fn expect(&mut self, byte: u8) -> Result<(), ConfigError> {
+        if self.eat(byte) { Ok(()) } else { Err(ConfigError::MalformedJson(self.pos as u32)) }
+    }
+
+    fn string(&mut self) -> Result<&'a str, ConfigError> {
+        self.expect(b'"')?;
+        let start = self.pos;
+        let len = self.bytes[start..]
+            .iter()
+            .position(|&b| b == b'"' || b == b'\\')
+            .filter(|&n| self.bytes[start + n] == b'"')
+            .ok_or(ConfigError::MalformedJson(start as u32))?;
+        self.pos = start + len + 1;
+        core::str::from_utf8(&self.bytes[start..start + len]).map_err(|_| ConfigError::MalformedJson(start as u32))
+    }
+
+    fn number(&mut self, field: ConfigField) -> Result<u32, ConfigError> {
+        self.skip_ws();
+        let start = self.pos;
+        while self.bytes.get(self.pos).is_some_and(u8::is_ascii_digit) {
+            self.pos += 1;
+        }
+        let digits = core::str::from_utf8(&self.bytes[start..self.pos]).unwrap_or("");
+        digits.parse().map_err(|_| ConfigError::InvalidValue(field))
+    }
+
+    fn boolean(&mut self, field: ConfigField) -> Result<bool, ConfigError> {
+        self.skip_ws();
+        for (word, value) in [(&b"true"[..], true), (&b"false"[..], false)] {
+            if self.bytes[self.pos..].starts_with(word) {
+                self.pos += word.len();
+                return Ok(value);
+            }
+        }
+        Err(ConfigError::InvalidValue(field))
+    }
+
+    fn strings(&mut self, field: ConfigField) -> Result<Vec<String>, ConfigError> {
+        self.expect(b'[').map_err(|_| ConfigError::InvalidValue(field))?;
+        let mut out = Vec::new();
+        if self.eat(b']') { return Ok(out); }
+        loop {
+            out.push(String::from(self.string()?));
+            if self.eat(b']') { return Ok(out); }
+            self.expect(b',')?;
+        }
+    }
+
+    fn named<T>(&mut self, field: ConfigField, from_name: fn(&str) -> Option<T>) -> Result<T, ConfigError> {
+        let name = self.string().map_err(|_| ConfigError::InvalidValue(field))?;
+        from_name(name).ok_or(ConfigError::InvalidValue(field))
+    }
+
+    /// Skip a flat object of string, number or boolean members.
+    fn skip_object(&mut self) -> Result<(), ConfigError> {
+        self.expect(b'{')?;
+        if self.eat(b'}') { return Ok(()); }
+        loop {
+            self.string()?;
+            self.expect(b':')?;
+            self.skip_ws();
+            match self.bytes.get(self.pos) {
+                Some(b'"') => { self.string()?; }
+                Some(_) => {
+                    let start = self.pos;
+                    while self.bytes.get(self.pos).is_some_and(u8::is_ascii_alphanumeric) {
+                        self.pos += 1;
+                    }
+                    if self.pos == start { return Err(ConfigError::MalformedJson(start as u32)); }
+                }
+                None => return Err(ConfigError::MalformedJson(self.pos as u32)),
+            }
+            if self.eat(b'}') { return Ok(()); }
+            self.expect(b',')?;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn default_config_snapshot() {
+        // Update together with the serializer when a field is added.
+        assert_eq!(
+            EngineConfig::default().to_json(),
+            concat!(
+                r#"{"allow_empty_side":false,"arena_growth":"reserve","array_diff_mode":"index","audit_log_len":0,"#,
+                r#""compute_mode":"latency","coverage_map":false,"#,
+                r#""entry_order":"document_order","entry_sequence":false,"hash_window_size":64,"ignore_case":false,"input_digests":false,"#,
+                r#""max_entry_bytes":0,"max_full_array_size":1024,"max_input_size":67108864,"#,
+                r#""max_memory_bytes":33554432,"max_object_keys":100000,"max_primitive_bytes":4194304,"#,
+                r#""normalize_numbers":false,"#,
+                r#""normalize_strings":false,"numeric_deltas":false,"oversize_policy":"skip","#,
+                r#""recommit_replaces":false,"redact_paths":[],"rendered_paths":false,"#,
+                r#""right_restricted_to_left":false,"self_contained":false,"shape_only":false,"#,
+                r#""significance":"byte_delta","split_by_op":false,"strict_escapes":false,"three_way":false,"top_k":0,"#,
+                r#""value_hashes":false}"#,
+            )
+        );
+    }
+
+    #[test]
+    fn round_trips_every_field() {
+        let config = EngineConfig {
+            max_memory_bytes: 1 << 20,
+            max_input_size: 12345,
+            max_object_keys: 7,
+            array_diff_mode: ArrayDiffMode::HashWindow,
+            hash_window_size: 65535,
+            max_full_array_size: 65535,
+            compute_mode: ComputeMode::Streaming,
+            max_entry_bytes: 4096,
+            oversize_policy: OversizePolicy::Truncate,
+            max_primitive_bytes: 300,
+            recommit_replaces: true,
+            strict_escapes: true,
+            entry_order: EntryOrder::OpThenPath,
+            coverage_map: true,
+            input_digests: true,
+            value_hashes: true,
+            top_k: 20,
+            significance: Significance::PathDepthInverse,
+            right_restricted_to_left: true,
+            shape_only: true,
+            allow_empty_side: true,
+            arena_growth: ArenaGrowth::Doubling,
+            audit_log_len: 512,
+            three_way: true,
+            numeric_deltas: true,
+            rendered_paths: true,
+            normalize_numbers: true,
+            normalize_strings: true,
+            ignore_case: true,
+            entry_sequence: true,
+            self_contained: true,
+            redact_paths: alloc::vec![".credentials.*".into(), "**.password".into()],
+            split_by_op: true,
+        };
+        let json = config.to_json();
+        assert!(json.contains(r#""redact_paths":[".credentials.*","**.password"]"#), "{}", json);
+        let back = EngineConfig::from_json(&json).unwrap();
+        assert_eq!(back.to_bytes(), config.to_bytes());
+        assert_eq!(back.to_json(), json);
+
+        let edge = EngineConfig::edge();
+        assert_eq!(EngineConfig::from_json(&edge.to_json()).unwrap().to_bytes(), edge.to_bytes());
+    }
+
+    #[test]
+    fn options_set_every_field() {
+        let options: [(&str, &str); 33] = [
+            ("max_memory_bytes", "1048576"),
+            ("max_input_size", "12345"),
+            ("max_object_keys", "7"),
+            ("array_diff_mode", "hash_window"),
+            ("max_full_array_size", "65535"),
+            ("hash_window_size", "65535"),
+            ("compute_mode", "\"streaming\""),
+            ("max_entry_bytes", "4096"),
+            ("oversize_policy", "truncate"),
+            ("max_primitive_bytes", "300"),
+            ("recommit_replaces", "true"),
+            ("strict_escapes", "true"),
+            ("entry_order", "op_then_path"),
+            ("coverage_map", "true"),
+            ("input_digests", "true"),
+            ("value_hashes", "true"),
+            ("top_k", "20"),
+            ("significance", "path_depth_inverse"),
+            ("right_restricted_to_left", "true"),
+            ("shape_only", "true"),
+            ("allow_empty_side", "true"),
+            ("arena_growth", "doubling"),
+            ("audit_log_len", "512"),
+            ("three_way", "true"),
+            ("numeric_deltas", "true"),
+            ("rendered_paths", "true"),
+            ("normalize_numbers", "true"),
+            ("normalize_strings", "true"),
+            ("ignore_case", "true"),
+            ("entry_sequence", "true"),
+            ("self_contained", "true"),
+            ("redact_paths", r#"[".credentials.*", "**.password"]"#),
+            ("split_by_op", "true"),
+        ];
+        // One option per member of the JSON form.
+        assert_eq!(options.len(), EngineConfig::default().to_json().matches("\":").count());
+        let mut config = EngineConfig::default();
+        for (key, value) in options {
+            config = config.with_option(key, value.as_bytes()).unwrap_or_else(|e| panic!("{}: {:?}", key, e)).unwrap();
+        }
+        let json = config.to_json();
+        for (key, value) in options {
+            let value = if value == "true" || value.starts_with(|c: char| c.is_ascii_digit() || c == '"' || c == '[') {
+                value.replace(", ", ",")
+            } else {
+                format!("\"{}\"", value)
+            };
+            assert!(json.contains(&format!("\"{}\":{}", key, value)), "{} in {}", key, json);
+        }
+
+        let base = EngineConfig::default();
+        assert!(base.with_option("numeric_epsilon", b"1e-9").unwrap().is_none());
+        assert!(base.with_option("derived", b"{}").unwrap().is_none());
+        let err = |key: &str, value: &[u8]| base.with_option(key, value).unwrap_err();
+        assert_eq!(err("array_diff_mode", b"fastest"), ConfigError::InvalidValue(ConfigField::ArrayDiffMode));
+        assert_eq!(err("max_object_keys", b"-1"), ConfigError::InvalidValue(ConfigField::MaxObjectKeys));
+        assert_eq!(err("shape_only", b"yes"), ConfigError::InvalidValue(ConfigField::ShapeOnly));
+        assert_eq!(err("max_memory_bytes", b"0"), ConfigError::InvalidLimits);
+        assert_eq!(err("top_k", b"1 2"), ConfigError::InvalidValue(ConfigField::TopK));
+        assert_eq!(err("redact_paths", br#"[".a"] x"#), ConfigError::MalformedJson(7));
+    }
+
+    #[test]
+    fn reads_derived_members_and_whitespace() {
+        let json = r#" { "max_object_keys" : 5 , "derived" : {"input_capacity_per_side":10,"paranoid":false} } "#;
+        let config = EngineConfig::from_json(json).unwrap();
+        assert_eq!(config.max_object_keys, 5);
+        assert_eq!(config.to_bytes()[12..], EngineConfig::default().to_bytes()[12..]);
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let err = |json: &str| EngineConfig::from_json(json).unwrap_err();
+        assert_eq!(err(r#"{"max_object_keyz":5}"#), ConfigError::MalformedJson(1));
+        assert_eq!(err(r#"{"hash_window_size":65536}"#), ConfigError::InvalidValue(ConfigField::HashWindowSize));
+        assert_eq!(err(r#"{"audit_log_len":65536}"#), ConfigError::InvalidValue(ConfigField::AuditLogLen));
+        assert_eq!(err(r#"{"entry_order":"random"}"#), ConfigError::InvalidValue(ConfigField::EntryOrder));
+        assert_eq!(err(r#"{"coverage_map":1}"#), ConfigError::InvalidValue(ConfigField::CoverageMap));
+        assert_eq!(err(r#"{"max_memory_bytes":0}"#), ConfigError::InvalidLimits);
+        assert_eq!(err(r#"{"redact_paths":"a"}"#), ConfigError::InvalidValue(ConfigField::RedactPaths));
+        assert_eq!(err(r#"{"redact_paths":["a..b"]}"#), ConfigError::InvalidValue(ConfigField::RedactPaths));
+        assert_eq!(err(r#"{} x"#), ConfigError::MalformedJson(3));
+        assert_eq!(err(r#"{"a\"":1}"#), ConfigError::MalformedJson(2));
+    }
+}
@@ -0,0 +1,58 @@
+//! Left-document coverage: the byte ranges of the left input that have no
+//! counterpart on the right, for strikethrough rendering in audits.
+//!
+//! A left value is uncovered when its path does not occur on the right at
+//! all (exactly the Removed entries). A left container whose path is
+//! absent contributes its whole extent, bracket to bracket, instead of one
+//! range per leaf. String ranges include their quotes. Object keys are not
+//! part of any value's range.
+
+use crate::parser::{CompactEvent, CompactParser, ValueKind};
+use crate::FxHashSet;
+use alloc::vec::Vec;
+
+/// Sorted, merged `[start, end)` ranges; touching ranges are joined.
+pub fn uncovered_ranges(left: &CompactParser, right: &CompactParser) -> Vec<[u32; 2]> {
+    let on_right: FxHashSet<_> = right.tokens().iter().map(|t| t.path_id).collect();
+
+    let mut ranges = Vec::new();
+    // Open containers since (and including) the outermost absent one.
+    let mut absent_depth = 0usize;
+    let mut absent_start = 0;
+    for t in left.tokens() {
+        match t.event {
+            CompactEvent::StartObject | CompactEvent::StartArray => {
+                if absent_depth > 0 {
+                    absent_depth += 1;
+                } else if !on_right.contains(&t.path_id) {
+                    absent_depth = 1;
+                    absent_start = t.raw_offset;
+                }
+            }
+            CompactEvent::EndObject | CompactEvent::EndArray => {
+                if absent_depth > 0 {
+                    absent_depth -= 1;
+                    if absent_depth == 0 {
+                        ranges.push([absent_start, t.raw_end()]);
+                    }
+                }
+            }
+            CompactEvent::Value => {
+                if absent_depth == 0 && !on_right.contains(&t.path_id) {
+                    let quote = (t.kind == ValueKind::String) as u32;
+                    ranges.push([t.raw_offset.saturating_sub(quote), t.raw_end().saturating_add(quote)]);
+                }
+            }
+        }
+    }
+
+    ranges.sort_unstable();
+    let mut merged: Vec<[u32; 2]> = Vec::with_capacity(ranges.len());
+    for r in ranges {
+        match merged.last_mut() {
+            Some(last) if r[0] <= last[1] => last[1] = last[1].max(r[1]),
+            _ => merged.push(r),
+        }
+    }
+    merged
+}
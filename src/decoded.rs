@@ -0,0 +1,176 @@
+//! Owned, serde-serializable form of a result, for native hosts that hand
+//! diffs to their own APIs or storage (feature `serde`).
+//!
+//! [`RawEntry`] borrows from the result and refers to its values by span;
+//! [`DecodedEntry`] resolves those spans against the inputs and copies
+//! everything out, so it outlives both. Built by
+//! [`ResultReader::decode`] or entry by entry with [`DecodedEntry::from_raw`].
+
+use crate::parser::ValueKind;
+use crate::reader::{RawEntry, ReadError, ResultReader};
+use alloc::string::String;
+use alloc::vec::Vec;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodedOp {
+    Added,
+    Removed,
+    Modified,
+    /// An op code from a newer minor version, kept as written.
+    Unknown(u8),
+}
+
+impl From<u8> for DecodedOp {
+    fn from(op: u8) -> Self {
+        match op {
+            0 => DecodedOp::Added,
+            1 => DecodedOp::Removed,
+            2 => DecodedOp::Modified,
+            other => DecodedOp::Unknown(other),
+        }
+    }
+}
+
+/// One side's value. `Json` when the payload parses as JSON (string
+/// bodies with their escapes decoded); `Raw` when it does not, e.g. a
+/// fragment cut by truncation, with invalid UTF-8 replaced by U+FFFD. A
+/// truncated or redacted string stays `Json`: check the entry's flags.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodedValue {
+    Json(serde_json::Value),
+    Raw(String),
+}
+
+impl DecodedValue {
+    fn from_bytes(kind: u8, bytes: &[u8]) -> Self {
+        let parsed = if kind == ValueKind::String as u8 {
+            serde_json::from_slice(&[&b"\""[..], bytes, b"\""].concat())
+        } else {
+            serde_json::from_slice(bytes)
+        };
+        parsed.map_or_else(|_| DecodedValue::Raw(String::from_utf8_lossy(bytes).into_owned()), DecodedValue::Json)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecodedEntry {
+    pub op: DecodedOp,
+    pub path_id: u64,
+    /// The JSON Pointer, if the result has the path section
+    /// (`rendered_paths`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub left: Option<DecodedValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub right: Option<DecodedValue>,
+    /// Entry flags as written (`ENTRY_FLAG_*`).
+    pub flags: u8,
+}
+
+impl DecodedEntry {
+    /// Resolve `entry` against the inputs the result was computed from;
+    /// fails as [`RawEntry::values`] does.
+    pub fn from_raw(entry: &RawEntry<'_>, path: Option<&[u8]>, left: &[u8], right: &[u8]) -> Result<Self, ReadError> {
+        let [l, r] = entry.values(left, right)?;
+        Ok(DecodedEntry {
+            op: entry.op.into(),
+            path_id: entry.path_id,
+            path: path.map(|p| String::from_utf8_lossy(p).into_owned()),
+            left: l.map(|b| DecodedValue::from_bytes(entry.left_kind, b)),
+            right: r.map(|b| DecodedValue::from_bytes(entry.right_kind, b)),
+            flags: entry.flags,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DecodedDiff {
+    pub version: (u16, u16),
+    /// Header flags as written (`HEADER_FLAG_*`).
+    pub flags: u32,
+    pub entries: Vec<DecodedEntry>,
+}
+
+impl ResultReader<'_> {
+    /// Every entry resolved against the committed inputs and copied out.
+    pub fn decode(&self, left: &[u8], right: &[u8]) -> Result<DecodedDiff, ReadError> {
+        let paths = self.paths();
+        let entries = self
+            .entries()
+            .enumerate()
+            .map(|(i, e)| DecodedEntry::from_raw(&e, paths.as_ref().map(|p| p[i].as_slice()), left, right))
+            .collect::<Result<_, _>>()?;
+        Ok(DecodedDiff { version: self.version(), flags: self.flags(), entries })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::OversizePolicy;
+    use crate::memory::{ENTRY_FLAG_REDACTED, ENTRY_FLAG_TRUNCATED};
+    use crate::{Engine, EngineConfig};
+
+    fn run(config: EngineConfig, left: &[u8], right: &[u8]) -> Vec<u8> {
+        let mut e = Engine::new(config, 1).unwrap();
+        unsafe {
+            core::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            core::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        e.commit_left(left.len() as u32);
+        e.commit_right(right.len() as u32);
+        let ptr = e.finalize().unwrap();
+        unsafe { core::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec()
+    }
+
+    #[test]
+    fn decoded_results_round_trip_through_json() {
+        let left = br#"{"a":"x\u00e9","b":[1,true],"gone":null,"t":-1.25e-7,"long":"0123456789abcdef"}"#;
+        let right = br#"{"a":"yy","b":[1,false],"new":2.5,"t":123,"long":"fedcba9876543210"}"#;
+        let config = EngineConfig {
+            max_input_size: 1 << 16,
+            rendered_paths: true,
+            max_entry_bytes: 42,
+            oversize_policy: OversizePolicy::Truncate,
+            ..Default::default()
+        };
+        let out = run(config, left, right);
+        let diff = ResultReader::parse(&out).unwrap().decode(left, right).unwrap();
+
+        let json = serde_json::to_string(&diff).unwrap();
+        let back: DecodedDiff = serde_json::from_str(&json).unwrap();
+        assert_eq!(back, diff);
+
+        let at = |p: &str| diff.entries.iter().find(|e| e.path.as_deref() == Some(p)).unwrap();
+        let json = |v: serde_json::Value| Some(DecodedValue::Json(v));
+        assert_eq!((at("/a").op, &at("/a").left, &at("/a").right), (DecodedOp::Modified, &json("x\u{e9}".into()), &json("yy".into())));
+        assert_eq!((at("/new").op, &at("/new").left, &at("/new").right), (DecodedOp::Added, &None, &json(2.5.into())));
+        assert!(serde_json::to_value(at("/new")).unwrap().get("left").is_none(), "absent sides are omitted");
+        assert_eq!((at("/gone").op, &at("/gone").left), (DecodedOp::Removed, &json(serde_json::Value::Null)));
+        // Cut to fit `max_entry_bytes`: a string stays a string, a number
+        // is no longer JSON and is kept as text.
+        let (long, t) = (at("/long"), at("/t"));
+        assert_eq!((long.flags & ENTRY_FLAG_TRUNCATED, &long.left), (ENTRY_FLAG_TRUNCATED, &json("01234".into())));
+        assert_eq!((t.flags & ENTRY_FLAG_TRUNCATED, &t.left), (ENTRY_FLAG_TRUNCATED, &Some(DecodedValue::Raw("-1.25e-".into()))));
+    }
+
+    #[test]
+    fn flags_and_missing_fields_survive_a_round_trip() {
+        let entry = DecodedEntry {
+            op: DecodedOp::Unknown(9),
+            path_id: u64::MAX,
+            path: None,
+            left: Some(DecodedValue::Raw(String::new())),
+            right: None,
+            flags: ENTRY_FLAG_REDACTED | ENTRY_FLAG_TRUNCATED,
+        };
+        let diff = DecodedDiff { version: (2, 99), flags: 1 << 31, entries: alloc::vec![entry] };
+        let json = serde_json::to_string(&diff).unwrap();
+        assert_eq!(serde_json::from_str::<DecodedDiff>(&json).unwrap(), diff);
+        assert_eq!(serde_json::from_str::<DecodedOp>(r#""removed""#).unwrap(), DecodedOp::Removed);
+    }
+}
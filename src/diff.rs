@@ -1,6 +1,9 @@
-use crate::parser::{CompactParser, CompactEvent};
-use crate::path::PathId;
-use rustc_hash::FxHashMap;
+use crate::cancel::CancelFlag;
+use crate::parser::{CompactEvent, CompactParser, ValueKind};
+use crate::path::{PathId, ROOT_PATH_ID};
+use crate::progress::{Phase, Progress, STRIDE_TOKENS};
+use crate::{FxHashMap, FxHashSet};
+use alloc::vec::Vec;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 #[repr(u8)]
@@ -10,85 +13,1024 @@ pub enum DiffOp {
     Modified = 2,
 }
 
+/// A value span `(offset, len, kind)` into one side's input.
+pub type ValueSpan = (u32, u32, ValueKind);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DiffEntry {
     pub op: DiffOp,
     pub path_id: PathId,
-    pub left_val: Option<(u32, u32)>,
-    pub right_val: Option<(u32, u32)>,
+    pub left_val: Option<ValueSpan>,
+    pub right_val: Option<ValueSpan>,
+}
+
+impl DiffEntry {
+    /// Bytes a host touches to materialize this entry: the fixed record plus
+    /// both referenced value spans.
+    pub fn footprint(&self) -> u64 {
+        let span = |v: Option<ValueSpan>| v.map_or(0, |(_, len, _)| len as u64);
+        crate::memory::ENTRY_SIZE as u64 + span(self.left_val) + span(self.right_val)
+    }
+
+    /// Shorten the value spans so `footprint() <= max`. The left side keeps
+    /// at most half of the value budget unless the right side needs less.
+    /// Each cut backs off to a UTF-8 boundary of the payload in its input
+    /// (`utf8::floor_boundary`), so a valid payload stays valid.
+    pub fn truncate_to(&mut self, max: u32, left: &[u8], right: &[u8]) {
+        let budget = (max as u64).saturating_sub(crate::memory::ENTRY_SIZE as u64);
+        let ll = self.left_val.map_or(0, |(_, l, _)| l as u64);
+        let rl = self.right_val.map_or(0, |(_, l, _)| l as u64);
+        let keep_l = ll.min((budget / 2).max(budget.saturating_sub(rl)));
+        let keep_r = rl.min(budget - keep_l);
+        if let Some(v) = self.left_val.as_mut() { cut(v, keep_l, left); }
+        if let Some(v) = self.right_val.as_mut() { cut(v, keep_r, right); }
+    }
+
+    /// Which sides are a string or literal longer than `max` bytes
+    /// (`max_primitive_bytes`, 0 for no cap), left then right.
+    pub fn oversized_primitives(&self, max: u32) -> (bool, bool) {
+        let over = |v: Option<ValueSpan>| {
+            max != 0 && v.is_some_and(|(_, len, kind)| matches!(kind, ValueKind::String | ValueKind::Literal) && len > max)
+        };
+        (over(self.left_val), over(self.right_val))
+    }
+
+    /// `footprint` once `cap_primitives(max)` has cut the oversized sides,
+    /// before any UTF-8 back-off.
+    pub fn capped_footprint(&self, max: u32) -> u64 {
+        let (l, r) = self.oversized_primitives(max);
+        let cut = |over: bool, v: Option<ValueSpan>| match (over, v) {
+            (true, Some((_, len, _))) => len as u64 - max as u64,
+            _ => 0,
+        };
+        self.footprint() - cut(l, self.left_val) - cut(r, self.right_val)
+    }
+
+    /// Cut each side `oversized_primitives` reports to `max` bytes, backing
+    /// off to a UTF-8 boundary as `truncate_to` does. True if any was cut.
+    pub fn cap_primitives(&mut self, max: u32, left: &[u8], right: &[u8]) -> bool {
+        let (l, r) = self.oversized_primitives(max);
+        if let (true, Some(v)) = (l, self.left_val.as_mut()) { cut(v, max as u64, left); }
+        if let (true, Some(v)) = (r, self.right_val.as_mut()) { cut(v, max as u64, right); }
+        l || r
+    }
+}
+
+/// Shorten `v` to at most `keep` bytes, at a UTF-8 boundary of its
+/// payload in `input`.
+fn cut(v: &mut ValueSpan, keep: u64, input: &[u8]) {
+    let payload = input.get(v.0 as usize..(v.0 as usize).saturating_add(v.1 as usize));
+    v.1 = payload.map_or(keep as usize, |p| crate::utf8::floor_boundary(p, keep as usize)) as u32;
+}
+
+/// JSON type of a value, compared by `shape_only`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shape {
+    Null,
+    Bool,
+    Number,
+    String,
+    Object,
+    Array,
+}
+
+impl Shape {
+    /// Type of the value `span` covers in `input`: the kind separates
+    /// strings, the first byte tells literals and fragments apart.
+    pub fn of(span: ValueSpan, input: &[u8]) -> Self {
+        let (offset, _, kind) = span;
+        let first = input.get(offset as usize).copied().unwrap_or(0);
+        match (kind, first) {
+            (ValueKind::String, _) => Shape::String,
+            (_, b'{') => Shape::Object,
+            (_, b'[') => Shape::Array,
+            (_, b't' | b'f') => Shape::Bool,
+            (_, b'n') => Shape::Null,
+            _ => Shape::Number,
+        }
+    }
+}
+
+impl DiffEntry {
+    /// Whether this entry is a structural change: any Added or Removed,
+    /// or a Modified whose two sides differ in [`Shape`].
+    pub fn changes_shape(&self, left: &[u8], right: &[u8]) -> bool {
+        match (self.left_val, self.right_val) {
+            (Some(l), Some(r)) => Shape::of(l, left) != Shape::of(r, right),
+            _ => true,
+        }
+    }
+
+    /// `ENTRY_FLAG_*_INVALID_UTF8` bits for the payloads as they stand
+    /// (after any truncation). A span outside its input counts as invalid.
+    pub fn utf8_flags(&self, left: &[u8], right: &[u8]) -> u8 {
+        use crate::memory::{ENTRY_FLAG_LEFT_INVALID_UTF8, ENTRY_FLAG_RIGHT_INVALID_UTF8};
+        let valid = |v: Option<ValueSpan>, input: &[u8]| {
+            v.is_none_or(|(offset, len, _)| {
+                let end = (offset as usize).saturating_add(len as usize);
+                input.get(offset as usize..end).is_some_and(crate::utf8::is_valid)
+            })
+        };
+        let mut flags = 0;
+        if !valid(self.left_val, left) { flags |= ENTRY_FLAG_LEFT_INVALID_UTF8; }
+        if !valid(self.right_val, right) { flags |= ENTRY_FLAG_RIGHT_INVALID_UTF8; }
+        flags
+    }
+
+    /// Right minus left of a Modified entry between two numbers, read
+    /// from the full spans, so call it before `truncate_to`.
+    pub fn numeric_delta(&self, left: &[u8], right: &[u8]) -> Option<crate::numeric::Delta> {
+        if self.op != DiffOp::Modified { return None; }
+        let span = |offset: u32, len: u32| offset as usize..(offset as usize).saturating_add(len as usize);
+        match (self.left_val?, self.right_val?) {
+            ((lo, ll, ValueKind::Literal), (ro, rl, ValueKind::Literal)) => {
+                crate::numeric::delta(left.get(span(lo, ll))?, right.get(span(ro, rl))?)
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Shape of a document's root, read from its first token. An empty (or
+/// all-whitespace) input, or a scope that matched nothing, is `Missing`.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RootKind {
+    Missing = 0,
+    Object = 1,
+    Array = 2,
+    Scalar = 3,
+}
+
+pub fn root_kind(p: &CompactParser) -> RootKind {
+    match p.tokens().first().map(|t| t.event) {
+        None => RootKind::Missing,
+        Some(CompactEvent::StartObject) => RootKind::Object,
+        Some(CompactEvent::StartArray) => RootKind::Array,
+        Some(_) => RootKind::Scalar,
+    }
+}
+
+/// The whole root as a value span: a scalar's own span, or a container's
+/// extent (start bracket through the last token) as a `Fragment`.
+fn root_span(p: &CompactParser) -> Option<ValueSpan> {
+    let first = p.tokens().first()?;
+    if first.event == CompactEvent::Value {
+        return Some((first.raw_offset, first.raw_len, first.kind));
+    }
+    let last = p.tokens().last()?;
+    Some((first.raw_offset, last.raw_end().saturating_sub(first.raw_offset), ValueKind::Fragment))
+}
+
+/// The single root entry for documents whose roots differ in shape, or
+/// `None` when the roots match and the leaf diff applies.
+///
+/// | left \ right | missing | `{}`/object | `[]`/array | scalar |
+/// |---------------|---------|-------------|------------|--------|
+/// | missing       | —       | Added root  | Added root | Added root |
+/// | `{}`/object   | Removed root | leaves | Modified root | Modified root |
+/// | `[]`/array    | Removed root | Modified root | leaves | Modified root |
+/// | scalar        | Removed root | Modified root | Modified root | leaves |
+///
+/// "leaves" is the per-value diff: `{}` vs `{}` and `[]` vs `[]` are
+/// empty, `{}` vs `{"a":1}` is one Added, and two scalars are one
+/// Modified at the root or nothing. Root entries carry the whole document
+/// on each present side. Every strategy applies this table first.
+pub fn root_change(left: &CompactParser, right: &CompactParser) -> Option<DiffEntry> {
+    if root_kind(left) == root_kind(right) {
+        return None;
+    }
+    let (left_val, right_val) = (root_span(left), root_span(right));
+    let op = match (left_val, right_val) {
+        (None, _) => DiffOp::Added,
+        (_, None) => DiffOp::Removed,
+        _ => DiffOp::Modified,
+    };
+    Some(DiffEntry { op, path_id: ROOT_PATH_ID, left_val, right_val })
+}
+
+/// `hits` marker for a right value with no left slot (Added).
+const NO_MATCH: u32 = u32::MAX;
+
+/// Overlap check bloom bits per left token.
+const BLOOM_BITS_PER_TOKEN: usize = 16;
+
+/// The overlap check gives up, and the join builds its map, once more
+/// than one right value in this many (plus a few) hits the bloom.
+const CANDIDATE_SHARE: usize = 32;
+
+/// A matched left value's comparison fields, staged in match order.
+#[derive(Clone, Copy)]
+struct Staged {
+    hash: u64,
+    offset: u32,
+    len: u32,
+    kind: ValueKind,
+}
+
+impl Default for Staged {
+    fn default() -> Self {
+        Self { hash: 0, offset: 0, len: 0, kind: ValueKind::None }
+    }
 }
 
 /// Silicon Path Diff Engine: Optimized for 64-bit sparse path IDs.
+///
+/// Hash-join strategy. Duplicate paths follow these rules, which every other
+/// strategy must reproduce exactly: the *last* left occurrence of a path is
+/// the one compared against, every right occurrence is compared on its own,
+/// and every left occurrence of a path absent on the right is Removed.
+#[cfg(test)]
 pub fn compute_compact_diff_v2(
     left: &CompactParser,
     right: &CompactParser,
 ) -> Vec<DiffEntry> {
+    compute_compact_diff_reporting(left, right, None)
+}
+
+/// [`compute_compact_diff_v2`], reporting `Phase::Diff` progress every
+/// `STRIDE_TOKENS` over the join's passes. Does not mark the phase
+/// finished.
+pub fn compute_compact_diff_reporting(
+    left: &CompactParser,
+    right: &CompactParser,
+    progress: Option<&Progress>,
+) -> Vec<DiffEntry> {
+    let mut join = DiffJoin::new(left, right);
+    join.step(left, right, usize::MAX, progress, None);
+    join.into_entries()
+}
+
+/// The hash join of [`compute_compact_diff_v2`], resumable: each
+/// [`DiffJoin::step`] visits up to a budget of tokens and returns, so a
+/// host can finalize a large diff in slices (`finalize_step`). Stepping
+/// with any budgets gives the same entries, in the same order, as one
+/// unbounded step; the parsers must not change in between.
+///
+/// First an overlap check, which proves most documents that share no
+/// value path disjoint without building any map:
+///
+/// - Bloom: every left token, setting two bits per value path (path ids
+///   are hashes already) in a filter of `BLOOM_BITS_PER_TOKEN` bits per
+///   token.
+/// - Probe: right tokens, collecting the value paths the bloom may hold
+///   as candidates. Past one candidate in `CANDIDATE_SHARE` right values
+///   the documents are taken to overlap and the join proper starts.
+/// - Verify (only with candidates): every left token, exact against the
+///   candidates. A hit also starts the join proper.
+///
+/// Disjoint documents then skip to pass 3, every right value Added, and
+/// pass 4, every left value Removed, with no map and no lookups. Else the
+/// passes, in token visits:
+///
+/// 1. Left map: every left token, mapping Value paths to their last
+///    left occurrence. Each slot also records whether any right token
+///    hit this path, which replaces a second right-side map for Removed
+///    detection.
+/// 2. Right lookup: every right token, resolving each value to its left
+///    slot (map only, no left token reads). Match `m` is the m-th right
+///    value with a left slot. At the end the left fetches are sorted by
+///    left index and each matched left token's comparison fields are
+///    staged at its match number, sequentially.
+/// 3. Emit: every right token again, alongside the staging buffer,
+///    writing Added and Modified entries in right order.
+/// 4. Removed: every left token, unless every distinct left path was hit
+///    (pure additive or in-place edits, the common case).
+///
+/// Left tokens are read in index order instead of in right-document
+/// order: on reordered documents the unsorted fetches are cache misses
+/// that dominate; the sort costs less.
+///
+/// Progress counts every pass the join may run, the overlap check's
+/// three and the four above, and skips over those it does not.
+pub struct DiffJoin {
+    stage: JoinStage,
+    bloom: Vec<u64>,
+    candidates: FxHashSet<PathId>,
+    /// The overlap check proved the documents share no value path.
+    disjoint: bool,
+    left_map: FxHashMap<PathId, (usize, bool)>,
+    matched_paths: usize,
+    hits: Vec<u32>,
+    fetches: Vec<u64>,
+    staged: Vec<Staged>,
+    diffs: Vec<DiffEntry>,
+}
+
+/// Where a [`DiffJoin`] resumes: the pass and its next token index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum JoinStage {
+    Bloom(usize),
+    Probe(usize),
+    Verify(usize),
+    LeftMap(usize),
+    RightLookup(usize),
+    /// Next right token, and the number of right values emitted so far.
+    Emit { token: usize, value: usize },
+    Removed(usize),
+    Done,
+}
+
+impl DiffJoin {
+    pub fn new(left: &CompactParser, right: &CompactParser) -> Self {
+        let mut join = Self {
+            stage: JoinStage::Bloom(0),
+            bloom: Vec::new(),
+            candidates: FxHashSet::default(),
+            disjoint: false,
+            left_map: FxHashMap::default(),
+            matched_paths: 0,
+            hits: Vec::new(),
+            fetches: Vec::new(),
+            staged: Vec::new(),
+            diffs: Vec::new(),
+        };
+        if let Some(entry) = root_change(left, right) {
+            join.diffs.push(entry);
+            join.stage = JoinStage::Done;
+        } else {
+            let words = (left.tokens().len() * BLOOM_BITS_PER_TOKEN / 64).next_power_of_two();
+            join.bloom = alloc::vec![0; words];
+            join.diffs = Vec::with_capacity(128);
+        }
+        join
+    }
+
+    /// Whether the overlap check found the documents disjoint, so the
+    /// join ran without a map.
+    pub fn disjoint(&self) -> bool { self.disjoint }
+
+    /// The two bloom bits of `path`: word and bit of each.
+    fn bloom_bits(&self, path: PathId) -> [(usize, u64); 2] {
+        let mask = self.bloom.len() * 64 - 1;
+        [path.0 as usize & mask, (path.0 >> 32) as usize & mask].map(|bit| (bit / 64, 1 << (bit % 64)))
+    }
+
+    /// End the overlap check: the join proper if `overlap`, else the
+    /// map-free emit.
+    fn end_overlap_check(&mut self, overlap: bool, left: usize, right: usize) {
+        self.bloom = Vec::new();
+        self.candidates = FxHashSet::default();
+        if overlap {
+            self.left_map = FxHashMap::with_capacity_and_hasher(left / 2, Default::default());
+            self.hits = Vec::with_capacity(right);
+            self.stage = JoinStage::LeftMap(0);
+        } else {
+            self.disjoint = true;
+            self.stage = JoinStage::Emit { token: 0, value: 0 };
+        }
+    }
+
+    /// Visit up to `budget` tokens (at least one) of `left` and `right`,
+    /// the parsers the join was created with. True once the join is done.
+    ///
+    /// A set `cancel` is checked every `STRIDE_TOKENS` visits of a pass
+    /// and ends the step early, with the join still resumable.
+    pub fn step(
+        &mut self,
+        left: &CompactParser,
+        right: &CompactParser,
+        budget: usize,
+        progress: Option<&Progress>,
+        cancel: Option<&CancelFlag>,
+    ) -> bool {
+        let (lt, rt) = (left.tokens(), right.tokens());
+        let total = self.visited_at(JoinStage::Done, lt.len(), rt.len());
+        let report = |done: usize| {
+            if done.is_multiple_of(STRIDE_TOKENS) {
+                if let Some(p) = progress { p.report(Phase::Diff, done, total); }
+            }
+        };
+        let mut budget = budget.max(1);
+        // Tokens `start..` of a pass of `len`, as far as the budget goes
+        // and no further than the next stride, where `cancel` is checked.
+        let take = |start: usize, len: usize, budget: &mut usize| {
+            let stride_end = (start / STRIDE_TOKENS + 1) * STRIDE_TOKENS;
+            let end = start.saturating_add(*budget).min(len).min(stride_end);
+            *budget -= end - start;
+            start..end
+        };
+        loop {
+            match self.stage {
+                JoinStage::Bloom(start) => {
+                    let range = take(start, lt.len(), &mut budget);
+                    let end = range.end;
+                    for idx in range {
+                        report(idx);
+                        let t = &lt[idx];
+                        if t.event == CompactEvent::Value {
+                            for (word, bit) in self.bloom_bits(t.path_id) { self.bloom[word] |= bit; }
+                        }
+                    }
+                    self.stage = if end == lt.len() { JoinStage::Probe(0) } else { JoinStage::Bloom(end) };
+                }
+                JoinStage::Probe(start) => {
+                    let range = take(start, rt.len(), &mut budget);
+                    let end = range.end;
+                    let limit = rt.len() / CANDIDATE_SHARE + 64;
+                    let mut overlap = false;
+                    for idx in range {
+                        report(lt.len() + idx);
+                        let t = &rt[idx];
+                        if t.event != CompactEvent::Value { continue; }
+                        if self.bloom_bits(t.path_id).iter().all(|&(word, bit)| self.bloom[word] & bit != 0) {
+                            self.candidates.insert(t.path_id);
+                            if self.candidates.len() > limit {
+                                overlap = true;
+                                break;
+                            }
+                        }
+                    }
+                    if overlap {
+                        self.end_overlap_check(true, lt.len(), rt.len());
+                    } else if end < rt.len() {
+                        self.stage = JoinStage::Probe(end);
+                    } else if self.candidates.is_empty() {
+                        self.end_overlap_check(false, lt.len(), rt.len());
+                    } else {
+                        self.stage = JoinStage::Verify(0);
+                    }
+                }
+                JoinStage::Verify(start) => {
+                    let range = take(start, lt.len(), &mut budget);
+                    let end = range.end;
+                    let mut overlap = false;
+                    for idx in range {
+                        report(lt.len() + rt.len() + idx);
+                        let t = &lt[idx];
+                        if t.event == CompactEvent::Value && self.candidates.contains(&t.path_id) {
+                            overlap = true;
+                            break;
+                        }
+                    }
+                    if overlap || end == lt.len() {
+                        self.end_overlap_check(overlap, lt.len(), rt.len());
+                    } else {
+                        self.stage = JoinStage::Verify(end);
+                    }
+                }
+                JoinStage::LeftMap(start) => {
+                    let range = take(start, lt.len(), &mut budget);
+                    let end = range.end;
+                    for idx in range {
+                        report(2 * lt.len() + rt.len() + idx);
+                        let t = &lt[idx];
+                        if t.event == CompactEvent::Value {
+                            self.left_map.insert(t.path_id, (idx, false));
+                        }
+                    }
+                    self.stage = if end == lt.len() { JoinStage::RightLookup(0) } else { JoinStage::LeftMap(end) };
+                }
+                JoinStage::RightLookup(start) => {
+                    let range = take(start, rt.len(), &mut budget);
+                    let end = range.end;
+                    for idx in range {
+                        report(3 * lt.len() + rt.len() + idx);
+                        let t = &rt[idx];
+                        if t.event != CompactEvent::Value { continue; }
+                        match self.left_map.get_mut(&t.path_id) {
+                            Some((lt_idx, seen)) => {
+                                if !*seen {
+                                    *seen = true;
+                                    self.matched_paths += 1;
+                                }
+                                self.hits.push(self.fetches.len() as u32);
+                                self.fetches.push((*lt_idx as u64) << 32 | self.fetches.len() as u64);
+                            }
+                            None => self.hits.push(NO_MATCH),
+                        }
+                    }
+                    if end < rt.len() {
+                        self.stage = JoinStage::RightLookup(end);
+                    } else {
+                        self.stage_left_fetches(left);
+                        self.stage = JoinStage::Emit { token: 0, value: 0 };
+                    }
+                }
+                JoinStage::Emit { token, mut value } => {
+                    let range = take(token, rt.len(), &mut budget);
+                    let end = range.end;
+                    for idx in range {
+                        report(3 * lt.len() + 2 * rt.len() + idx);
+                        let t = &rt[idx];
+                        if t.event != CompactEvent::Value { continue; }
+                        let right_val = Some((t.raw_offset, t.raw_len, t.kind));
+                        if self.disjoint {
+                            self.diffs.push(DiffEntry { op: DiffOp::Added, path_id: t.path_id, left_val: None, right_val });
+                            continue;
+                        }
+                        let hit = self.hits[value];
+                        value += 1;
+                        if hit == NO_MATCH {
+                            self.diffs.push(DiffEntry { op: DiffOp::Added, path_id: t.path_id, left_val: None, right_val });
+                            continue;
+                        }
+                        let l = &self.staged[hit as usize];
+                        // String bodies are hashed without their quotes, so `"1"`
+                        // and `1` share a hash; the kind tells them apart.
+                        if l.hash != right.compare_hash(idx) || l.kind != t.kind {
+                            let left_val = Some((l.offset, l.len, l.kind));
+                            self.diffs.push(DiffEntry { op: DiffOp::Modified, path_id: t.path_id, left_val, right_val });
+                        }
+                    }
+                    self.stage = if end < rt.len() {
+                        JoinStage::Emit { token: end, value }
+                    } else if !self.disjoint && self.matched_paths == self.left_map.len() {
+                        JoinStage::Done
+                    } else {
+                        JoinStage::Removed(0)
+                    };
+                }
+                JoinStage::Removed(start) => {
+                    let range = take(start, lt.len(), &mut budget);
+                    let end = range.end;
+                    for idx in range {
+                        report(3 * lt.len() + 3 * rt.len() + idx);
+                        let t = &lt[idx];
+                        if t.event != CompactEvent::Value { continue; }
+                        if self.disjoint || !self.left_map.get(&t.path_id).is_some_and(|&(_, seen)| seen) {
+                            let left_val = Some((t.raw_offset, t.raw_len, t.kind));
+                            self.diffs.push(DiffEntry { op: DiffOp::Removed, path_id: t.path_id, left_val, right_val: None });
+                        }
+                    }
+                    self.stage = if end == lt.len() { JoinStage::Done } else { JoinStage::Removed(end) };
+                }
+                JoinStage::Done => {}
+            }
+            if self.stage == JoinStage::Done { return true; }
+            if budget == 0 || cancel.is_some_and(CancelFlag::is_set) {
+                // Where a host polling between steps sees the join.
+                if let Some(p) = progress { p.report(Phase::Diff, self.visited(lt.len(), rt.len()), total); }
+                return false;
+            }
+        }
+    }
+
+    /// Token visits so far, counting the passes as `step` reports them.
+    fn visited(&self, left: usize, right: usize) -> usize { self.visited_at(self.stage, left, right) }
+
+    /// Token visits of every pass before `stage`, and into it.
+    fn visited_at(&self, stage: JoinStage, left: usize, right: usize) -> usize {
+        match stage {
+            JoinStage::Bloom(i) => i,
+            JoinStage::Probe(i) => left + i,
+            JoinStage::Verify(i) => left + right + i,
+            JoinStage::LeftMap(i) => 2 * left + right + i,
+            JoinStage::RightLookup(i) => 3 * left + right + i,
+            JoinStage::Emit { token, .. } => 3 * left + 2 * right + token,
+            JoinStage::Removed(i) => 3 * left + 3 * right + i,
+            JoinStage::Done => 4 * left + 3 * right,
+        }
+    }
+
+    /// Stage each matched left token's comparison fields at its match
+    /// number, fetching in left index order.
+    fn stage_left_fetches(&mut self, left: &CompactParser) {
+        let mut fetches = core::mem::take(&mut self.fetches);
+        fetches.sort_unstable();
+        self.staged = alloc::vec![Staged::default(); fetches.len()];
+        for f in &fetches {
+            let idx = (f >> 32) as usize;
+            let t = &left.tokens()[idx];
+            let hash = left.compare_hash(idx);
+            self.staged[*f as u32 as usize] = Staged { hash, offset: t.raw_offset, len: t.raw_len, kind: t.kind };
+        }
+    }
+
+    /// The entries, once the join is done.
+    pub fn into_entries(self) -> Vec<DiffEntry> { self.diffs }
+}
+
+/// Sort-merge strategy: stable-sorts both sides' value tokens by path and
+/// walks the two runs in lockstep. Produces the same entry *set* as
+/// [`compute_compact_diff_v2`] (order differs); see [`normalize_entries`].
+#[cfg(any(test, feature = "paranoid"))]
+pub fn compute_sort_merge_diff(
+    left: &CompactParser,
+    right: &CompactParser,
+) -> Vec<DiffEntry> {
+    if let Some(entry) = root_change(left, right) {
+        return alloc::vec![entry];
+    }
     let mut diffs = Vec::with_capacity(128);
-    
-    // Create an O(1) lookup map for the Left parser's path hashes.
-    // In Silicon Path, we move the hash map cost to the diff phase
-    // where we only handle Value tokens, not every byte of structural noise.
-    let mut left_map = FxHashMap::with_capacity_and_hasher(
-        left.tokens().len() / 2, 
-        Default::default()
-    );
-
-    for (idx, lt) in left.tokens().iter().enumerate() {
-        if lt.event == CompactEvent::Value {
-            left_map.insert(lt.path_id, idx);
-        }
-    }
-
-    // Modified & Added Detection
-    for rt in right.tokens() {
-        if rt.event != CompactEvent::Value { continue; }
-        
-        match left_map.get(&rt.path_id) {
-            Some(&lt_idx) => {
-                let lt = &left.tokens()[lt_idx];
-                if lt.value_hash != rt.value_hash {
+
+    let collect = |p: &CompactParser| {
+        let mut v: Vec<usize> = p.tokens().iter().enumerate()
+            .filter(|(_, t)| t.event == CompactEvent::Value)
+            .map(|(i, _)| i)
+            .collect();
+        // Stable: document order is kept within a run of equal paths.
+        v.sort_by_key(|&i| p.tokens()[i].path_id);
+        v
+    };
+    let l_idx = collect(left);
+    let r_idx = collect(right);
+    let lt = left.tokens();
+    let rt = right.tokens();
+
+    let (mut i, mut j) = (0, 0);
+    while i < l_idx.len() || j < r_idx.len() {
+        let lp = l_idx.get(i).map(|&k| lt[k].path_id);
+        let rp = r_idx.get(j).map(|&k| rt[k].path_id);
+        let path = match (lp, rp) {
+            (Some(a), Some(b)) => a.min(b),
+            (Some(a), None) => a,
+            (None, Some(b)) => b,
+            (None, None) => break,
+        };
+
+        let l_start = i;
+        while i < l_idx.len() && lt[l_idx[i]].path_id == path { i += 1; }
+        let r_start = j;
+        while j < r_idx.len() && rt[r_idx[j]].path_id == path { j += 1; }
+        let l_run = &l_idx[l_start..i];
+        let r_run = &r_idx[r_start..j];
+
+        match l_run.last() {
+            None => {
+                for &k in r_run {
+                    let r = &rt[k];
                     diffs.push(DiffEntry {
-                        op: DiffOp::Modified,
-                        path_id: rt.path_id,
-                        left_val: Some((lt.raw_offset, lt.raw_len)),
-                        right_val: Some((rt.raw_offset, rt.raw_len)),
+                        op: DiffOp::Added,
+                        path_id: path,
+                        left_val: None,
+                        right_val: Some((r.raw_offset, r.raw_len, r.kind)),
                     });
                 }
             }
-            None => {
-                diffs.push(DiffEntry {
-                    op: DiffOp::Added,
-                    path_id: rt.path_id,
-                    left_val: None,
-                    right_val: Some((rt.raw_offset, rt.raw_len)),
-                });
+            Some(_) if r_run.is_empty() => {
+                for &k in l_run {
+                    let l = &lt[k];
+                    diffs.push(DiffEntry {
+                        op: DiffOp::Removed,
+                        path_id: path,
+                        left_val: Some((l.raw_offset, l.raw_len, l.kind)),
+                        right_val: None,
+                    });
+                }
+            }
+            Some(&lk) => {
+                let l = &lt[lk];
+                for &k in r_run {
+                    let r = &rt[k];
+                    if left.compare_hash(lk) != right.compare_hash(k) || l.kind != r.kind {
+                        diffs.push(DiffEntry {
+                            op: DiffOp::Modified,
+                            path_id: path,
+                            left_val: Some((l.raw_offset, l.raw_len, l.kind)),
+                            right_val: Some((r.raw_offset, r.raw_len, r.kind)),
+                        });
+                    }
+                }
             }
         }
     }
 
-    // Removed Detection
-    // For extreme performance, we build a Right map only if strictly necessary,
-    // but a symmetric check is safer for v1.
-    let mut right_map = FxHashMap::with_capacity_and_hasher(
-        right.tokens().len() / 2, 
-        Default::default()
-    );
-    for rt in right.tokens() {
-        if rt.event == CompactEvent::Value {
-            right_map.insert(rt.path_id, ());
+    diffs
+}
+
+/// Sort entries into a canonical order (path, op, left span, right span) so
+/// the output of different strategies can be compared for equality.
+#[cfg(any(test, feature = "paranoid"))]
+pub fn normalize_entries(entries: &mut [DiffEntry]) {
+    let span_key = |v: Option<ValueSpan>| v.map(|(o, l, k)| (o, l, k as u8));
+    entries.sort_unstable_by_key(|e| {
+        (e.path_id, e.op as u8, span_key(e.left_val), span_key(e.right_val))
+    });
+}
+
+/// Run both strategies and fail if their normalized entry sets differ.
+///
+/// Returns the hash-join output (in its native order) on agreement.
+#[cfg(test)]
+pub fn paranoid_compare(
+    left: &CompactParser,
+    right: &CompactParser,
+) -> Result<Vec<DiffEntry>, crate::error::EngineError> {
+    let primary = compute_compact_diff_v2(left, right);
+    paranoid_check(left, right, &primary)?;
+    Ok(primary)
+}
+
+/// Fail if `primary`, a finished hash join's entries, and the sort-merge
+/// strategy's differ as sets.
+#[cfg(any(test, feature = "paranoid"))]
+pub fn paranoid_check(
+    left: &CompactParser,
+    right: &CompactParser,
+    primary: &[DiffEntry],
+) -> Result<(), crate::error::EngineError> {
+    let mut a = primary.to_vec();
+    let mut b = compute_sort_merge_diff(left, right);
+    normalize_entries(&mut a);
+    normalize_entries(&mut b);
+    if a != b {
+        return Err(crate::error::EngineError::Internal(alloc::format!(
+            "diff strategy divergence: hash-join {} entries, sort-merge {} entries",
+            a.len(),
+            b.len()
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ComputeMode;
+    use crate::gen::{generate, mutate, mutate_arrays, EditKind, GenConfig};
+    use crate::simd_index::StructuralIndex;
+
+    /// Hand-picked pairs that exercise duplicate paths, escapes, type
+    /// changes and root scalars. Divergences found by the generator below
+    /// should be minimized and appended here.
+    const CORPUS: &[(&str, &str)] = &[
+        (r#"{"a":1}"#, r#"{"a":2}"#),
+        (r#"{"a":1,"a":2}"#, r#"{"a":2}"#),
+        (r#"{"a":1}"#, r#"{"a":1,"a":3,"a":1}"#),
+        (r#"{"a":1,"a":1}"#, r#"{"b":1}"#),
+        (r#"{"a":"1"}"#, r#"{"a":1}"#),
+        (r#"{"s":"x\"y"}"#, r#"{"s":"x\"z"}"#),
+        (r#"[1,[2,3],{"k":[]}]"#, r#"[1,[3,2],{"k":[4]}]"#),
+        ("42", "43"),
+        ("null", r#"{"a":null}"#),
+        ("[]", "[1]"),
+        ("", r#"{"a":1}"#),
+        (r#"{"a":1,"b":[1]}"#, r#"{"a":1,"b":[1,2],"c":3}"#),
+        (r#"{"a":1,"b":2,"a":3}"#, r#"{"a":1}"#),
+        (r#"{"a":1,"b":2,"c":3}"#, r#"{"c":4,"x":0,"a":5,"b":2}"#),
+        ("{}", "[]"),
+        (r#"{"a":1}"#, "[1]"),
+        ("[1,2]", ""),
+    ];
+
+    fn parse(json: &[u8]) -> CompactParser {
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+        let mut p = CompactParser::new(u32::MAX, ComputeMode::Latency);
+        let _ = p.parse_with_index(json, &idx);
+        p
+    }
+
+    fn diverges(l: &[u8], r: &[u8]) -> bool {
+        paranoid_compare(&parse(l), &parse(r)).is_err()
+    }
+
+    /// Greedy byte-deletion shrink. The parser is lenient, so any byte
+    /// string is a valid reproducer even if it is no longer JSON.
+    fn minimize(mut l: Vec<u8>, mut r: Vec<u8>) -> (String, String) {
+        for side in 0..2 {
+            let mut i = 0;
+            loop {
+                let buf = if side == 0 { &l } else { &r };
+                if i >= buf.len() { break; }
+                let mut cand = buf.clone();
+                cand.remove(i);
+                let still = if side == 0 { diverges(&cand, &r) } else { diverges(&l, &cand) };
+                if still {
+                    if side == 0 { l = cand } else { r = cand }
+                } else {
+                    i += 1;
+                }
+            }
         }
+        (String::from_utf8_lossy(&l).into_owned(), String::from_utf8_lossy(&r).into_owned())
+    }
+
+    #[test]
+    fn staged_fetches_keep_right_document_order() {
+        let (l, r) = (parse(br#"{"a":1,"b":2,"c":3}"#), parse(br#"{"c":4,"x":0,"a":5,"b":2}"#));
+        let entries = compute_compact_diff_v2(&l, &r);
+        let got: Vec<_> = entries.iter().map(|e| (e.op, e.right_val.map(|v| v.0))).collect();
+        assert_eq!(got, [
+            (DiffOp::Modified, Some(5)),
+            (DiffOp::Added, Some(11)),
+            (DiffOp::Modified, Some(17)),
+        ]);
+        // The left side of each Modified is the matching left value.
+        assert_eq!(entries[0].left_val.map(|v| v.0), Some(17));
+        assert_eq!(entries[2].left_val.map(|v| v.0), Some(5));
+    }
+
+    #[test]
+    fn footprint_and_truncation_hold_at_u32_max_spans() {
+        let span = Some((u32::MAX - 1, u32::MAX, ValueKind::String));
+        let mut d = DiffEntry { op: DiffOp::Modified, path_id: ROOT_PATH_ID, left_val: span, right_val: span };
+        assert_eq!(d.footprint(), crate::memory::ENTRY_SIZE as u64 + 2 * u32::MAX as u64);
+        d.truncate_to(u32::MAX, &[], &[]);
+        assert!(d.footprint() <= u32::MAX as u64);
+        d.truncate_to(0, &[], &[]);
+        assert_eq!(d.footprint(), crate::memory::ENTRY_SIZE as u64);
     }
 
-    for lt in left.tokens() {
-        if lt.event != CompactEvent::Value { continue; }
-        if !right_map.contains_key(&lt.path_id) {
-            diffs.push(DiffEntry {
-                op: DiffOp::Removed,
-                path_id: lt.path_id,
-                left_val: Some((lt.raw_offset, lt.raw_len)),
-                right_val: None,
-            });
+    #[test]
+    fn only_primitives_over_the_cap_are_cut() {
+        let left = br#"[123456789,"short"]"#;
+        let right = br#"{"a":[1,2,3,4,5,6]}"#;
+        let mut d = DiffEntry {
+            op: DiffOp::Modified,
+            path_id: ROOT_PATH_ID,
+            left_val: Some((1, 9, ValueKind::Literal)),
+            right_val: Some((5, 13, ValueKind::Fragment)),
+        };
+        assert_eq!(d.oversized_primitives(0), (false, false), "0 caps nothing");
+        assert_eq!(d.oversized_primitives(9), (false, false));
+        assert_eq!(d.oversized_primitives(4), (true, false), "containers are never primitives");
+        let capped = d.capped_footprint(4);
+        assert!(d.cap_primitives(4, left, right));
+        assert_eq!((d.left_val, d.right_val), (Some((1, 4, ValueKind::Literal)), Some((5, 13, ValueKind::Fragment))));
+        assert_eq!(d.footprint(), capped);
+        assert!(!d.cap_primitives(4, left, right));
+    }
+
+    #[test]
+    fn truncation_never_splits_a_character() {
+        // 2-, 3- and 4-byte characters, cut at every byte of the budget.
+        for ch in ["é", "€", "𝄞"] {
+            let value = format!("ab{}", ch.repeat(8));
+            let (left, right) = (value.as_bytes(), value.as_bytes());
+            let span = Some((0, value.len() as u32, ValueKind::String));
+            for keep in 0..=value.len() {
+                let mut d = DiffEntry { op: DiffOp::Modified, path_id: ROOT_PATH_ID, left_val: span, right_val: None };
+                d.truncate_to((crate::memory::ENTRY_SIZE + keep) as u32, left, right);
+                let len = d.left_val.unwrap().1 as usize;
+                assert!(keep - len < ch.len() && core::str::from_utf8(&left[..len]).is_ok(), "{:?} keep {}: {}", ch, keep, len);
+            }
         }
     }
 
-    diffs
+    #[test]
+    fn root_shape_truth_table() {
+        // One document per row/column of the `root_change` table.
+        let docs: [&[u8]; 6] = [b"", b"{}", b"[]", b" 42 ", br#"{"a":1}"#, b"[1,2]"];
+        use DiffOp::{Added as A, Modified as M, Removed as R};
+        // Expected (op, left text, right text) per cell; `None` text = absent side.
+        type Cell = &'static [(DiffOp, Option<&'static str>, Option<&'static str>)];
+        let table: [[Cell; 6]; 6] = [
+            // left: missing
+            [&[], &[(A, None, Some("{}"))], &[(A, None, Some("[]"))], &[(A, None, Some("42"))],
+             &[(A, None, Some(r#"{"a":1}"#))], &[(A, None, Some("[1,2]"))]],
+            // left: {}
+            [&[(R, Some("{}"), None)], &[], &[(M, Some("{}"), Some("[]"))], &[(M, Some("{}"), Some("42"))],
+             &[(A, None, Some("1"))], &[(M, Some("{}"), Some("[1,2]"))]],
+            // left: []
+            [&[(R, Some("[]"), None)], &[(M, Some("[]"), Some("{}"))], &[], &[(M, Some("[]"), Some("42"))],
+             &[(M, Some("[]"), Some(r#"{"a":1}"#))], &[(A, None, Some("1")), (A, None, Some("2"))]],
+            // left: 42
+            [&[(R, Some("42"), None)], &[(M, Some("42"), Some("{}"))], &[(M, Some("42"), Some("[]"))], &[],
+             &[(M, Some("42"), Some(r#"{"a":1}"#))], &[(M, Some("42"), Some("[1,2]"))]],
+            // left: {"a":1}
+            [&[(R, Some(r#"{"a":1}"#), None)], &[(R, Some("1"), None)], &[(M, Some(r#"{"a":1}"#), Some("[]"))],
+             &[(M, Some(r#"{"a":1}"#), Some("42"))], &[], &[(M, Some(r#"{"a":1}"#), Some("[1,2]"))]],
+            // left: [1,2]
+            [&[(R, Some("[1,2]"), None)], &[(M, Some("[1,2]"), Some("{}"))], &[(R, Some("1"), None), (R, Some("2"), None)],
+             &[(M, Some("[1,2]"), Some("42"))], &[(M, Some("[1,2]"), Some(r#"{"a":1}"#))], &[]],
+        ];
+        for (i, l) in docs.iter().enumerate() {
+            for (j, r) in docs.iter().enumerate() {
+                let (lp, rp) = (parse(l), parse(r));
+                let text = |doc: &[u8], v: Option<ValueSpan>| {
+                    v.map(|(o, n, _)| core::str::from_utf8(&doc[o as usize..(o + n) as usize]).unwrap().to_string())
+                };
+                for entries in [compute_compact_diff_v2(&lp, &rp), compute_sort_merge_diff(&lp, &rp)] {
+                    // Sort-merge emits in path-hash order; compare as sets.
+                    let mut got: Vec<_> = entries.iter().map(|e| (e.op as u8, text(l, e.left_val), text(r, e.right_val))).collect();
+                    let mut want: Vec<_> = table[i][j].iter().map(|&(op, a, b)| (op as u8, a.map(String::from), b.map(String::from))).collect();
+                    got.sort();
+                    want.sort();
+                    assert_eq!(got, want, "{:?} vs {:?}", core::str::from_utf8(l), core::str::from_utf8(r));
+                    let root_entry = root_kind(&lp) != root_kind(&rp);
+                    assert!(entries.iter().all(|e| (e.path_id == ROOT_PATH_ID) == (root_entry || i == 3)));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn strategies_agree_on_corpus() {
+        for (l, r) in CORPUS {
+            assert!(!diverges(l.as_bytes(), r.as_bytes()), "divergence on {l:?} vs {r:?}");
+        }
+    }
+
+    #[test]
+    fn strategies_agree_on_generated_cases() {
+        let cfg = GenConfig::adversarial();
+        for seed in 1..=10_000u64 {
+            let doc = generate(&cfg, seed);
+            let (edited, _) = mutate(&doc, 1 + (seed % 4) as usize, seed);
+            let (left, right) = (doc.to_json(), edited.to_json());
+            if diverges(left.as_bytes(), right.as_bytes()) {
+                let (l, r) = minimize(left.into_bytes(), right.into_bytes());
+                panic!("seed {seed}: strategies diverge; minimized reproducer: {l:?} vs {r:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn stepped_joins_match_one_shot() {
+        let cfg = GenConfig::adversarial();
+        let mut cases: Vec<(Vec<u8>, Vec<u8>)> = CORPUS.iter().map(|(l, r)| (l.as_bytes().to_vec(), r.as_bytes().to_vec())).collect();
+        cases.push((br#"{"a":1,"b":{"c":[1,2]}}"#.to_vec(), br#"{"x":1,"y":[{"z":null}]}"#.to_vec()));
+        for seed in 1..=200u64 {
+            let doc = generate(&cfg, seed);
+            let (edited, _) = mutate(&doc, 1 + (seed % 4) as usize, seed);
+            cases.push((doc.to_json().into_bytes(), edited.to_json().into_bytes()));
+        }
+        for (i, (l, r)) in cases.iter().enumerate() {
+            let (lp, rp) = (parse(l), parse(r));
+            let want = compute_compact_diff_v2(&lp, &rp);
+            for budget in [0, 1, 3, 64] {
+                let mut join = DiffJoin::new(&lp, &rp);
+                let mut steps = 1;
+                while !join.step(&lp, &rp, budget, None, None) { steps += 1; }
+                assert_eq!(join.into_entries(), want, "case {} budget {}", i, budget);
+                // Each step but the last visits the whole budget; seven
+                // passes bound the visits.
+                let visits = 4 * lp.tokens().len() + 3 * rp.tokens().len();
+                assert!(steps <= visits / budget.max(1) + 5, "case {} budget {}: {} steps", i, budget, steps);
+            }
+        }
+    }
+
+    #[test]
+    fn disjoint_documents_skip_the_map() {
+        let keys = |prefix: &str| {
+            let fields: Vec<String> = (0..500).map(|i| format!(r#""{}{}":{{"v":{},"t":[true,null]}}"#, prefix, i, i)).collect();
+            format!("{{{}}}", fields.join(",")).into_bytes()
+        };
+        let (lp, rp) = (parse(&keys("r")), parse(&keys("s")));
+        let mut join = DiffJoin::new(&lp, &rp);
+        assert!(join.step(&lp, &rp, usize::MAX, None, None));
+        assert!(join.disjoint());
+        let entries = join.into_entries();
+        assert_eq!(entries.len(), 2 * 1500);
+        assert_eq!(entries.iter().filter(|e| e.op == DiffOp::Added).count(), 1500);
+        // One shared path among many takes the full join.
+        let shared = String::from_utf8(keys("s")).unwrap().replacen("s499", "r499", 1);
+        let sp = parse(shared.as_bytes());
+        let mut join = DiffJoin::new(&lp, &sp);
+        assert!(join.step(&lp, &sp, usize::MAX, None, None));
+        assert!(!join.disjoint());
+        assert_eq!(join.into_entries().len(), 2 * 1497);
+    }
+
+    /// Where every left path found a right match the join skips the
+    /// Removed pass; running it anyway must add nothing, for duplicate
+    /// paths and scoped documents too.
+    #[test]
+    fn skipping_the_removed_pass_matches_running_it() {
+        let parse_scoped = |json: &[u8], scope: &[u8]| {
+            let mut idx = StructuralIndex::new();
+            idx.build(json);
+            let mut p = CompactParser::new(u32::MAX, ComputeMode::Latency);
+            assert!(p.set_scope(scope));
+            let _ = p.parse_with_index(json, &idx);
+            p
+        };
+        let mut cases: Vec<(Vec<u8>, Vec<u8>)> = CORPUS.iter().map(|(l, r)| (l.as_bytes().to_vec(), r.as_bytes().to_vec())).collect();
+        cases.push((br#"{"a":1,"a":2,"b":[1,1]}"#.to_vec(), br#"{"a":3,"b":[1,2],"a":2}"#.to_vec()));
+        for seed in 1..=200u64 {
+            let cfg = if seed % 2 == 0 { GenConfig::adversarial() } else { GenConfig::default() };
+            let doc = generate(&cfg, seed);
+            let (edited, _) = mutate(&doc, 1 + (seed % 4) as usize, seed);
+            let (changed, _) = mutate_arrays(&doc, 1 + (seed % 3) as usize, &[EditKind::Change], seed);
+            for other in [edited, changed] {
+                cases.push((doc.to_json().into_bytes(), other.to_json().into_bytes()));
+            }
+        }
+        let mut skipped = 0;
+        for (i, (l, r)) in cases.iter().enumerate() {
+            let scoped = (format!(r#"{{"s":{},"t":1}}"#, String::from_utf8_lossy(l)), format!(r#"{{"t":2,"s":{}}}"#, String::from_utf8_lossy(r)));
+            for (l, r, scope) in [(&l[..], &r[..], &b""[..]), (scoped.0.as_bytes(), scoped.1.as_bytes(), b"/s")] {
+                let (lp, rp) = (parse_scoped(l, scope), parse_scoped(r, scope));
+                let mut join = DiffJoin::new(&lp, &rp);
+                assert!(join.step(&lp, &rp, usize::MAX, None, None));
+                let took_skip = root_change(&lp, &rp).is_none() && !join.disjoint && join.matched_paths == join.left_map.len();
+                if !took_skip { continue; }
+                skipped += 1;
+                let skip = join.diffs.clone();
+                assert!(paranoid_check(&lp, &rp, &skip).is_ok(), "case {} scope {:?}", i, scope);
+                join.stage = JoinStage::Removed(0);
+                assert!(join.step(&lp, &rp, usize::MAX, None, None));
+                assert_eq!(join.into_entries(), skip, "case {} scope {:?}", i, scope);
+            }
+        }
+        assert!(skipped >= cases.len() / 2, "only {} of {} runs took the skip", skipped, 2 * cases.len());
+    }
+
+    #[test]
+    fn a_set_cancel_stops_the_join_at_a_stride() {
+        let doc = |v: &str| format!("[{}]", alloc::vec![v; 3 * STRIDE_TOKENS].join(",")).into_bytes();
+        let (lp, rp) = (parse(&doc("0")), parse(&doc("1")));
+        let cancel = CancelFlag::default();
+        cancel.set();
+        let mut join = DiffJoin::new(&lp, &rp);
+        assert!(!join.step(&lp, &rp, usize::MAX, None, Some(&cancel)));
+        assert_eq!(join.visited(lp.tokens().len(), rp.tokens().len()), STRIDE_TOKENS);
+        // Reset, it resumes where it stopped.
+        cancel.reset();
+        assert!(join.step(&lp, &rp, usize::MAX, None, Some(&cancel)));
+        assert_eq!(join.into_entries(), compute_compact_diff_v2(&lp, &rp));
+    }
 }
@@ -0,0 +1,184 @@
+//! Input digests: a 128-bit fingerprint of each committed input, so a
+//! stored result can be tied to the exact bytes that produced it.
+//!
+//! Construction (wrapping arithmetic, little-endian words):
+//!
+//! - Two 64-bit lanes start at `SEED_A` and `SEED_B`.
+//! - Each 16-byte stripe `[w0][w1]` runs one xxHash64 round per lane,
+//!   `a = round(a, w0)` and `b = round(b, w1)`, where
+//!   `round(acc, w) = rotl(acc + w * P2, 31) * P1`.
+//! - A final partial stripe is zero-padded to 16 bytes.
+//! - Finish: `a ^= len`, `b ^= rotl(len, 32)`, then the MurmurHash3 x64
+//!   mix `a += b; b += a; a = fmix(a); b = fmix(b); a += b; b += a`.
+//! - The digest is `a` then `b`, 16 bytes.
+//!
+//! Not cryptographic: it identifies inputs and catches corruption, but
+//! does not resist a deliberately crafted collision.
+//!
+//! The engine feeds stripes from the structural index build
+//! (`StructuralIndex::build_reporting`), so hashing shares its pass over
+//! the input.
+
+/// Digest size in bytes.
+pub const DIGEST_LEN: usize = 16;
+/// Bytes absorbed per round.
+pub const STRIPE: usize = 16;
+
+pub type Digest = [u8; DIGEST_LEN];
+
+const P1: u64 = 0x9E37_79B1_85EB_CA87;
+const P2: u64 = 0xC2B2_AE3D_27D4_EB4F;
+const SEED_A: u64 = 0x6A09_E667_F3BC_C908;
+const SEED_B: u64 = 0xBB67_AE85_84CA_A73B;
+
+/// Streaming state. Feed whole stripes with `update`, then `finish`.
+#[derive(Debug, Clone, Copy)]
+pub struct InputHasher {
+    a: u64,
+    b: u64,
+    len: u64,
+}
+
+impl InputHasher {
+    pub fn new() -> Self {
+        Self { a: SEED_A, b: SEED_B, len: 0 }
+    }
+
+    /// Absorb whole stripes; `bytes.len()` must be a multiple of `STRIPE`.
+    #[inline(always)]
+    pub fn update(&mut self, bytes: &[u8]) {
+        debug_assert!(bytes.len().is_multiple_of(STRIPE));
+        for stripe in bytes.chunks_exact(STRIPE) {
+            self.stripe(stripe.try_into().unwrap());
+        }
+        self.len += bytes.len() as u64;
+    }
+
+    /// Absorb the remaining input (any length) and produce the digest.
+    pub fn finish(mut self, tail: &[u8]) -> Digest {
+        let whole = tail.len() / STRIPE * STRIPE;
+        self.update(&tail[..whole]);
+        let rest = &tail[whole..];
+        if !rest.is_empty() {
+            let mut padded = [0u8; STRIPE];
+            padded[..rest.len()].copy_from_slice(rest);
+            self.stripe(&padded);
+            self.len += rest.len() as u64;
+        }
+
+        let mut a = self.a ^ self.len;
+        let mut b = self.b ^ self.len.rotate_left(32);
+        a = a.wrapping_add(b);
+        b = b.wrapping_add(a);
+        a = fmix(a);
+        b = fmix(b);
+        a = a.wrapping_add(b);
+        b = b.wrapping_add(a);
+
+        let mut out = [0u8; DIGEST_LEN];
+        out[..8].copy_from_slice(&a.to_le_bytes());
+        out[8..].copy_from_slice(&b.to_le_bytes());
+        out
+    }
+
+    #[inline(always)]
+    fn stripe(&mut self, s: &[u8; STRIPE]) {
+        let w0 = u64::from_le_bytes(s[..8].try_into().unwrap());
+        let w1 = u64::from_le_bytes(s[8..].try_into().unwrap());
+        self.a = round(self.a, w0);
+        self.b = round(self.b, w1);
+    }
+}
+
+/// Digest of a whole input in one call.
+pub fn digest(bytes: &[u8]) -> Digest {
+    InputHasher::new().finish(bytes)
+}
+
+#[inline(always)]
+fn round(acc: u64, w: u64) -> u64 {
+    acc.wrapping_add(w.wrapping_mul(P2)).rotate_left(31).wrapping_mul(P1)
+}
+
+/// MurmurHash3 64-bit finalizer.
+#[inline(always)]
+pub fn fmix(mut k: u64) -> u64 {
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xFF51_AFD7_ED55_8CCD);
+    k ^= k >> 33;
+    k = k.wrapping_mul(0xC4CE_B9FE_1A85_EC53);
+    k ^ (k >> 33)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Byte-at-a-time restatement of the module docs, sharing no code
+    /// with the streaming implementation.
+    fn reference(input: &[u8]) -> Digest {
+        let mut padded = input.to_vec();
+        while !padded.len().is_multiple_of(16) {
+            padded.push(0);
+        }
+        let (mut a, mut b) = (SEED_A, SEED_B);
+        for s in padded.chunks(16) {
+            let mut w = [0u64; 2];
+            for (i, &byte) in s.iter().enumerate() {
+                w[i / 8] |= (byte as u64) << (8 * (i % 8));
+            }
+            a = a.wrapping_add(w[0].wrapping_mul(P2)).rotate_left(31).wrapping_mul(P1);
+            b = b.wrapping_add(w[1].wrapping_mul(P2)).rotate_left(31).wrapping_mul(P1);
+        }
+        let len = input.len() as u64;
+        let (mut a, mut b) = (a ^ len, b ^ len.rotate_left(32));
+        a = a.wrapping_add(b);
+        b = b.wrapping_add(a);
+        let mix = |mut k: u64| {
+            for m in [0xFF51_AFD7_ED55_8CCDu64, 0xC4CE_B9FE_1A85_EC53] {
+                k ^= k >> 33;
+                k = k.wrapping_mul(m);
+            }
+            k ^ (k >> 33)
+        };
+        a = mix(a);
+        b = mix(b);
+        a = a.wrapping_add(b);
+        b = b.wrapping_add(a);
+        let mut out = [0u8; 16];
+        out[..8].copy_from_slice(&a.to_le_bytes());
+        out[8..].copy_from_slice(&b.to_le_bytes());
+        out
+    }
+
+    fn hex(d: Digest) -> String {
+        d.iter().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn matches_reference_at_every_tail_length() {
+        let input: Vec<u8> = (0..200u32).map(|i| (i * 37 % 251) as u8).collect();
+        for n in 0..input.len() {
+            assert_eq!(digest(&input[..n]), reference(&input[..n]), "len {}", n);
+        }
+    }
+
+    #[test]
+    fn streamed_stripes_match_one_shot() {
+        let input = br#"{"users":[{"id":1,"name":"ada"},{"id":2,"name":"grace"}],"total":2}"#;
+        let mut h = InputHasher::new();
+        let split = input.len() / 64 * 64;
+        for block in input[..split].chunks(64) {
+            h.update(block);
+        }
+        assert_eq!(h.finish(&input[split..]), digest(input));
+    }
+
+    #[test]
+    fn pinned_digests() {
+        assert_eq!(hex(digest(b"")), "0609fd22bc82f1c04e0e373c2877befd");
+        assert_eq!(hex(digest(br#"{"a":1}"#)), "45a60597a90aabeeb7c663057bb32a28");
+        // Trailing zero bytes are not confused with padding.
+        assert_ne!(digest(b"ab"), digest(b"ab\0"));
+    }
+}
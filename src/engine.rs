@@ -1,100 +1,3839 @@
-use crate::memory::ResultArena;
-use crate::diff::compute_compact_diff_v2;
+use crate::memory::{ArenaStats, InputBuf, MemoryReport, MemoryUsage, ResultArena, FORMAT_VERSION};
+use crate::diff::compute_compact_diff_reporting;
 use crate::error::{ErrorBuffer, EngineError};
-use crate::parser::CompactParser;
+use crate::parser::{CompactEvent, CompactParser, ParseError, ValueKind};
 use crate::status::Status;
-use crate::config::EngineConfig;
+use crate::config::{ArenaGrowth, EngineConfig, EntryOrder, OutputOptions, OversizePolicy};
+use crate::diff::{DiffEntry, DiffJoin, DiffOp, ValueSpan};
+use crate::numeric::Delta;
+use crate::normalize::Normalization;
+use crate::path::{path_records_len, write_path_record, PathId};
+use crate::path_pattern::PathPattern;
+use crate::memory::{
+    ENTRY_FLAG_DELTA_INEXACT, ENTRY_FLAG_FORMATTING, ENTRY_FLAG_NUMERIC_DELTA, ENTRY_FLAG_OVERSIZED_PRIMITIVE, ENTRY_FLAG_REDACTED, ENTRY_FLAG_TRUNCATED, HEADER_FLAG_CANCELLED, HEADER_FLAG_ENTRY_SEQUENCE, HEADER_FLAG_IDENTICAL, HEADER_FLAG_LINT, HEADER_FLAG_NO_INPUT, HEADER_FLAG_PARTIAL, HEADER_FLAG_PATCHED, HEADER_FLAG_SECTIONS, HEADER_FLAG_TRUNCATED, HEADER_FLAG_PATHS_FRONT_CODED, HEADER_FLAG_RIGHT_RESTRICTED,
+    SECTION_ANNOTATIONS, SECTION_AUDIT_LOG, SECTION_CANARY, SECTION_COVERAGE, SECTION_DOCUMENT, SECTION_ENTRY_SEQUENCE, SECTION_INPUT_DIGESTS, SECTION_NUMERIC_DELTAS, SECTION_PATCH, SECTION_PATHS, SECTION_VALUE_HASHES, SECTION_VALUE_LENGTHS,
+};
+use crate::audit::{AuditLog, AuditOp};
+use crate::cancel::CancelFlag;
+use crate::left_state::StateError;
+use crate::lifecycle::{EngineState, Op};
+use crate::patch::PatchError;
+use crate::three_way::CanarySide;
+use crate::trace;
+use crate::reader::ResultReader;
+use crate::index_pool::PooledIndex;
+use crate::simd_index::StructuralIndex;
+use crate::transform::{EntryTransform, Outcome};
+use crate::{FxHashMap, FxHashSet};
+use crate::digest::{digest, Digest};
+use crate::progress::{Phase, Progress, STRIDE_TOKENS};
+use crate::stats::{EngineStats, SideStats};
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Side {
+    Left,
+    Right,
+}
 
 pub struct Engine {
     magic: u32,
+    config: EngineConfig,
+    /// Finalize-time options: `config` plus `set_output_options` overrides.
+    output: OutputOptions,
     arena: ResultArena,
     left_parser: CompactParser,
     right_parser: CompactParser,
     error: ErrorBuffer,
-    sealed: bool,
-    left_input: Vec<u8>,
-    right_input: Vec<u8>,
-    left_index: crate::simd_index::StructuralIndex,
-    right_index: crate::simd_index::StructuralIndex,
+    /// Where the engine is in its lifecycle; see `lifecycle.rs`.
+    state: EngineState,
+    left_input: InputBuf,
+    right_input: InputBuf,
+    /// Committed input lengths, kept for a path-recording re-parse.
+    left_len: u32,
+    right_len: u32,
+    /// Bytes `push` staged per side, committed by `finalize`.
+    left_pushed: u32,
+    right_pushed: u32,
+    /// Left and right lengths promised by `reserve_input`; commits and
+    /// pushes past them fail. `None` allows each buffer's capacity.
+    reserved: Option<[u32; 2]>,
+    /// `finalize_into` wrote the result to the host and kept no copy:
+    /// sealed, with no result, until a clear.
+    handed_off: bool,
+    /// `finalize_partial` is writing the result: a full arena ends a
+    /// segment instead of the result.
+    segmented: bool,
+    /// Where the next segment starts, while `resume_finalize` has one
+    /// to write.
+    pending_write: Option<PendingWrite>,
+    /// The left side was committed with `commit_left_retained`: each
+    /// `diff_next_right` diffs a new right against it.
+    left_retained: bool,
+    /// Bytes the last `trim` released, for `arena_stats`.
+    trimmed_bytes: u32,
+    /// Work counters of the current diff (`stats.rs`), and the bytes
+    /// `stats_bytes` last wrote for the host.
+    stats: EngineStats,
+    stats_buf: [u8; EngineStats::SIZE],
+    /// What `memory_usage_bytes` last wrote for the host.
+    usage_buf: [u8; MemoryUsage::SIZE],
+    /// Checked out of the group's pool (`index_pool.rs`) for the commit
+    /// in progress; `None` between commits.
+    index: Option<PooledIndex>,
+    /// Entries dropped by `OversizePolicy::Skip` in the last finalize.
+    skipped_entries: u32,
+    /// Entries the last finalize had no room for under `max_memory_bytes`.
+    dropped_entries: u32,
+    /// Parser/index high-water marks; arena marks live in the arena.
+    peaks: MemoryReport,
+    /// Per-side scope pointers (empty = whole document).
+    left_scope: Vec<u8>,
+    right_scope: Vec<u8>,
+    /// Built on demand after finalize by `build_span_index`.
+    span_index: Vec<u8>,
+    /// Tenant group and the bytes currently charged to it.
+    group: Option<u32>,
+    group_bytes: u64,
+    phases: PhaseCounters,
+    /// Left and right input digests, when `input_digests` is configured.
+    digests: Option<[Digest; 2]>,
+    /// Polled by the host during long calls; see `progress.rs`.
+    progress: Progress,
+    /// Set by the host to abandon a finalize; see `cancel.rs`.
+    cancel: CancelFlag,
+    /// Scratch for `effective_config_json`.
+    config_json: Vec<u8>,
+    /// Scratch for `export_left_state`.
+    left_state: Vec<u8>,
+    /// Scratch for `entry_path`, and the entry it holds the path of.
+    entry_path: Vec<u8>,
+    entry_path_of: Option<u32>,
+    /// Scratch for `resolve_symbol`, and the path id it holds.
+    symbol_buffer: Vec<u8>,
+    symbol_of: Option<u64>,
+    /// `batch_resolve_symbols`' table for the current result, once built.
+    symbol_table: Option<Vec<u8>>,
+    /// Entries loaded by `load_suppressions`; see `SuppressionKey`.
+    suppressions: FxHashSet<SuppressionKey>,
+    /// Entries dropped by `suppressions` in the last finalize.
+    suppressed_entries: u32,
+    /// Entries that reached ordering / `top_k` selection in the last
+    /// finalize, i.e. before the `top_k` cut.
+    ranked_total: u32,
+    /// Result length the last finalize sized its arena for.
+    result_estimate: u32,
+    /// Call log, when `audit_log_len` is set; kept across `clear`.
+    audit: Option<AuditLog>,
+    /// The canary input, when `three_way` is set.
+    third: Option<ThirdInput>,
+    /// The join of a finalize in progress (`finalize_step`); `None`
+    /// before the first step and once the result is written.
+    join: Option<DiffJoin>,
+    /// During a `lint`: the entries for the objects the canonical form
+    /// reordered, which the join cannot see. Taken by the result write.
+    lint: Option<Vec<DiffEntry>>,
+    /// Host callback run on every entry in finalize (`transform.rs`);
+    /// `clear` keeps it, like the config.
+    transform: Option<EntryTransform>,
+    /// The copies of paths and values the transform is shown.
+    transform_scratch: Vec<u8>,
+    /// Annotation and redaction of each entry the transform kept in the
+    /// last finalize, by path id.
+    transformed: FxHashMap<u64, (u32, bool)>,
+    /// `config.redact_paths`, compiled.
+    redact: Vec<PathPattern>,
+}
+
+/// The third input slot of `three_way` mode: the canary, diffed against
+/// the left like the right side is.
+struct ThirdInput {
+    input: InputBuf,
+    len: u32,
+    parser: CompactParser,
+    committed: bool,
+}
+
+/// What the result write prepared for its entry loop, kept between the
+/// segments of a `finalize_partial` while more are pending.
+struct PendingWrite {
+    /// Ordered, filtered and transformed; `next` is the first not yet
+    /// written or skipped.
+    diffs: Vec<DiffEntry>,
+    next: usize,
+    /// Entries written by earlier segments: the entry sequence and the
+    /// rendered paths continue from it.
+    written: usize,
+    /// Paths of the entries that will be written, in order.
+    paths: Vec<Vec<u8>>,
+    canary: Option<FxHashMap<PathId, CanarySide>>,
+    linting: bool,
+    cuts_primitives: bool,
+    front_coded: bool,
+    /// The sections that cover the whole diff rather than its entries,
+    /// written with the last segment.
+    coverage: Vec<u8>,
+    digests: Option<Vec<u8>>,
+    patch: Option<Vec<u8>>,
+    audit: Option<Vec<u8>>,
+}
+
+/// `(path_id, op, right kind, right value hash)`. Path ids are hashes of
+/// the rendered path, so they match across engines and stand in for the
+/// path itself.
+type SuppressionKey = (u64, u8, u8, u64);
+
+/// Which path each finalize took, and what the sides cost, counted over
+/// the engine's lifetime (`clear` keeps them).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PhaseCounters {
+    /// Finalizes short-circuited by a token digest match.
+    pub digest_matches: u32,
+    /// Finalizes that ran the full diff join.
+    pub joins: u32,
+    /// Sides parsed: one per commit, plus each re-parse that records
+    /// paths.
+    pub parses: u32,
+    /// Sides a finalize ran the comparison hash pass over
+    /// (`normalize.rs`); none with every comparison setting off.
+    pub hash_passes: u32,
+    /// Joins whose sides shared no value path, run without a map.
+    pub disjoint_joins: u32,
 }
 
 impl Engine {
     pub fn new(config: EngineConfig, magic: u32) -> Result<Self, EngineError> {
-        let input_cap = (config.max_input_size / 2) as usize;
+        // `max_input_size` bounds all inputs together.
+        let sides = if config.three_way { 3 } else { 2 };
+        let input_cap = (config.max_input_size / sides) as usize;
+        let redact = config.redact_patterns().map_err(EngineError::Config)?;
+        let record_paths = OutputOptions::from_config(&config).needs_paths() || !redact.is_empty();
+        let third = config.three_way.then(|| ThirdInput {
+            input: InputBuf::with_capacity(input_cap),
+            len: 0,
+            parser: new_parser(&config, record_paths),
+            committed: false,
+        });
         Ok(Self {
             magic,
+            output: OutputOptions::from_config(&config),
             arena: ResultArena::new(config.max_memory_bytes),
-            left_parser: CompactParser::new(config.max_object_keys, config.compute_mode),
-            right_parser: CompactParser::new(config.max_object_keys, config.compute_mode),
+            left_parser: new_parser(&config, record_paths),
+            right_parser: new_parser(&config, record_paths),
             error: ErrorBuffer::new(),
-            sealed: false,
-            left_input: Vec::with_capacity(input_cap),
-            right_input: Vec::with_capacity(input_cap),
-            left_index: crate::simd_index::StructuralIndex::new(),
-            right_index: crate::simd_index::StructuralIndex::new(),
+            state: EngineState::Created,
+            left_input: InputBuf::with_capacity(input_cap),
+            right_input: InputBuf::with_capacity(input_cap),
+            left_len: 0,
+            right_len: 0,
+            left_pushed: 0,
+            reserved: None,
+            handed_off: false,
+            segmented: false,
+            pending_write: None,
+            right_pushed: 0,
+            left_retained: false,
+            trimmed_bytes: 0,
+            stats: EngineStats::default(),
+            stats_buf: [0; EngineStats::SIZE],
+            usage_buf: [0; MemoryUsage::SIZE],
+            index: None,
+            skipped_entries: 0,
+            dropped_entries: 0,
+            peaks: MemoryReport::default(),
+            left_scope: Vec::new(),
+            right_scope: Vec::new(),
+            span_index: Vec::new(),
+            group: None,
+            group_bytes: 0,
+            phases: PhaseCounters::default(),
+            digests: config.input_digests.then(|| [digest(&[]); 2]),
+            progress: Progress::new(),
+            cancel: CancelFlag::default(),
+            config_json: Vec::new(),
+            left_state: Vec::new(),
+            entry_path: Vec::new(),
+            entry_path_of: None,
+            symbol_buffer: Vec::new(),
+            symbol_of: None,
+            symbol_table: None,
+            suppressions: FxHashSet::default(),
+            suppressed_entries: 0,
+            ranked_total: 0,
+            result_estimate: 0,
+            audit: (config.audit_log_len != 0).then(|| AuditLog::new(config.audit_log_len)),
+            third,
+            join: None,
+            lint: None,
+            transform: None,
+            transform_scratch: Vec::new(),
+            transformed: FxHashMap::default(),
+            redact,
+            config,
         })
     }
 
+    /// Create an engine accounted into `group`; fails if the group cannot
+    /// take one more engine with this engine's reservation.
+    pub fn new_in_group(config: EngineConfig, magic: u32, group: u32) -> Result<Self, EngineError> {
+        let mut engine = Self::new(config, magic)?;
+        let bytes = engine.footprint();
+        if !crate::group::join(group, bytes) {
+            return Err(EngineError::GroupQuotaExceeded);
+        }
+        engine.group = Some(group);
+        engine.group_bytes = bytes;
+        Ok(engine)
+    }
+
     pub fn magic(&self) -> u32 { self.magic }
     pub fn clear_magic(&mut self) { self.magic = 0; }
 
     pub fn commit_left(&mut self, len: u32) -> Status {
-        if self.sealed { return Status::EngineSealed; }
+        let _span = trace::span!("commit", side = "left", bytes = len);
+        self.error.clear();
+        self.left_pushed = 0;
+        let status = self.commit_left_indexed(len);
+        self.release_index();
+        status
+    }
+
+    fn commit_left_indexed(&mut self, len: u32) -> Status {
+        let next = match self.state.next(Op::Commit(Side::Left)) {
+            Ok(next) => next,
+            Err(e) => return self.refuse(e),
+        };
         // `len` is supplied by the host. Reading past the buffer's allocated
         // capacity would be out-of-bounds — reject instead of trusting it.
-        if len as usize > self.left_input.capacity() { return Status::Error; }
-        let bytes = unsafe { std::slice::from_raw_parts(self.left_input.as_ptr(), len as usize) };
-        self.left_index.build(bytes);
-        match self.left_parser.parse_with_index(bytes, &self.left_index) {
-            Ok(_) => Status::Ok,
-            Err(_) => Status::Error,
+        let capacity = self.input_limit(Side::Left);
+        if len as usize > capacity {
+            return self.fail(EngineError::InputExceedsBuffer { len, capacity }, Status::Error);
+        }
+        // Parsing appends to the token stream, so a second commit would
+        // double every token. Refuse, or start the side over if configured.
+        if self.state.committed(Side::Left) {
+            if !self.config.recommit_replaces {
+                return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+            }
+            self.left_parser.clear();
+        }
+        self.state = next;
+        self.left_len = len;
+        self.left_retained = false;
+        self.left_input.materialize();
+        let bytes = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), len as usize) };
+        let mut index = crate::index_pool::checkout(self.group);
+        let indexing = trace::span!("index", bytes = len, structurals = tracing::field::Empty);
+        let built = index.build_reporting(bytes, self.digests.is_some(), &self.progress);
+        if let (Some(d), Some(built)) = (self.digests.as_mut(), built) { d[0] = built; }
+        if let Err(status) = self.check_index(&index) { return status; }
+        trace::record!(indexing, "structurals", index.masks.iter().map(|m| m.count_ones()).sum::<u32>());
+        trace::exit!(indexing);
+        let parsing = trace::span!("parse", tokens = tracing::field::Empty, oversized = tracing::field::Empty);
+        let parsed = self.left_parser.parse_reporting(bytes, &index, Some(&self.progress));
+        self.phases.parses += 1;
+        self.progress.finish(Phase::Parse);
+        self.peaks.left_peak_token_bytes = self.peaks.left_peak_token_bytes.max(token_bytes(&self.left_parser));
+        self.peaks.left_peak_index_bytes = self.peaks.left_peak_index_bytes.max(index_bytes(&index));
+        self.stats.left = SideStats::of(&index, &self.left_parser, self.config.max_primitive_bytes);
+        trace::record!(parsing, "tokens", self.stats.left.tokens);
+        trace::record!(parsing, "oversized", self.stats.left.oversized);
+        trace::exit!(parsing);
+        // Charged to the group with the tokens until `release_index`.
+        self.index = Some(index);
+        if let Err(status) = self.settle_group() {
+            self.left_parser.clear();
+            return status;
         }
+        if parsed.is_ok() && !self.left_parser.scope_found() {
+            return self.scope_missing(Side::Left);
+        }
+        let warnings = self.left_parser.escape_warnings();
+        self.parse_status(parsed, warnings)
     }
 
     pub fn commit_right(&mut self, len: u32) -> Status {
-        if self.sealed { return Status::EngineSealed; }
-        if len as usize > self.right_input.capacity() { return Status::Error; }
-        let bytes = unsafe { std::slice::from_raw_parts(self.right_input.as_ptr(), len as usize) };
-        self.right_index.build(bytes);
-        match self.right_parser.parse_with_index(bytes, &self.right_index) {
-            Ok(_) => Status::Ok,
-            Err(_) => Status::Error,
+        let _span = trace::span!("commit", side = "right", bytes = len);
+        self.error.clear();
+        self.right_pushed = 0;
+        let status = self.commit_right_indexed(len);
+        self.release_index();
+        status
+    }
+
+    fn commit_right_indexed(&mut self, len: u32) -> Status {
+        let next = match self.state.next(Op::Commit(Side::Right)) {
+            Ok(next) => next,
+            Err(e) => return self.refuse(e),
+        };
+        let capacity = self.input_limit(Side::Right);
+        if len as usize > capacity {
+            return self.fail(EngineError::InputExceedsBuffer { len, capacity }, Status::Error);
+        }
+        if self.state.committed(Side::Right) {
+            if !self.config.recommit_replaces {
+                return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+            }
+            self.right_parser.clear();
+        }
+        // The restriction is the left paths as of this commit; a later
+        // left recommit does not re-parse the right side.
+        if self.config.right_restricted_to_left {
+            if !self.state.committed(Side::Left) {
+                return self.fail(EngineError::LeftNotCommitted, Status::Error);
+            }
+            self.right_parser.set_restriction(Some(self.left_parser.path_ids()));
+        }
+        self.state = next;
+        self.right_len = len;
+        self.right_input.materialize();
+        let bytes = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), len as usize) };
+        let mut index = crate::index_pool::checkout(self.group);
+        let indexing = trace::span!("index", bytes = len, structurals = tracing::field::Empty);
+        let built = index.build_reporting(bytes, self.digests.is_some(), &self.progress);
+        if let (Some(d), Some(built)) = (self.digests.as_mut(), built) { d[1] = built; }
+        if let Err(status) = self.check_index(&index) { return status; }
+        trace::record!(indexing, "structurals", index.masks.iter().map(|m| m.count_ones()).sum::<u32>());
+        trace::exit!(indexing);
+        let parsing = trace::span!("parse", tokens = tracing::field::Empty, oversized = tracing::field::Empty);
+        let parsed = self.right_parser.parse_reporting(bytes, &index, Some(&self.progress));
+        self.phases.parses += 1;
+        self.progress.finish(Phase::Parse);
+        self.peaks.right_peak_token_bytes = self.peaks.right_peak_token_bytes.max(token_bytes(&self.right_parser));
+        self.peaks.right_peak_index_bytes = self.peaks.right_peak_index_bytes.max(index_bytes(&index));
+        self.stats.right = SideStats::of(&index, &self.right_parser, self.config.max_primitive_bytes);
+        trace::record!(parsing, "tokens", self.stats.right.tokens);
+        trace::record!(parsing, "oversized", self.stats.right.oversized);
+        trace::exit!(parsing);
+        self.index = Some(index);
+        if let Err(status) = self.settle_group() {
+            self.right_parser.clear();
+            return status;
+        }
+        if parsed.is_ok() && !self.right_parser.scope_found() {
+            return self.scope_missing(Side::Right);
+        }
+        let warnings = self.right_parser.escape_warnings();
+        self.parse_status(parsed, warnings)
+    }
+
+    /// Commit the left side as `commit_left` does, and keep it for a
+    /// series of `diff_next_right` calls: its tokens, path interner and
+    /// comparison settings stay, so one baseline is parsed once however
+    /// many documents are diffed against it. A later `commit_left` that
+    /// goes ahead, or a `clear`, ends the series; a refused one does not.
+    pub fn commit_left_retained(&mut self, len: u32) -> Status {
+        let status = self.commit_left(len);
+        self.left_retained = status.is_ok();
+        status
+    }
+
+    /// Diff `len` bytes of the right input against the retained left:
+    /// drop the previous right side and result (nothing else: the
+    /// options, scopes, suppressions and entry transform stay), commit
+    /// the right side and finalize, whole (a `finalize_partial` before
+    /// does not carry over). The result is the one a fresh engine with
+    /// the same settings would write for the pair, and reads as any
+    /// result (`ResultTruncated` when truncated). Fails with
+    /// `LeftNotRetained` without `commit_left_retained`, and otherwise as
+    /// `commit_right` or `finalize`; a failed commit leaves the left
+    /// retained for the next call.
+    pub fn diff_next_right(&mut self, len: u32) -> Status {
+        if self.state == EngineState::Poisoned { return self.refuse(EngineError::Poisoned); }
+        if !self.left_retained { return self.fail(EngineError::LeftNotRetained, Status::Error); }
+        self.reset_right();
+        let status = self.commit_right(len);
+        if !status.is_ok() { return status; }
+        match self.finalize() {
+            Ok(_) => self.segment_status(),
+            Err(e) => Status::from(&e),
+        }
+    }
+
+    /// Snapshot the retained left side (`left_state.rs`) into a buffer
+    /// the engine owns, `left_state_len` bytes, valid until the next call,
+    /// `clear` or `trim`. Null, with the error recorded, without a left
+    /// from `commit_left_retained` or `import_left_state`.
+    pub fn export_left_state(&mut self) -> *const u8 {
+        if self.state == EngineState::Poisoned {
+            self.refuse(EngineError::Poisoned);
+            return core::ptr::null();
+        }
+        if !self.left_retained {
+            self.fail(EngineError::LeftNotRetained, Status::Error);
+            return core::ptr::null();
+        }
+        let input = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), self.left_len as usize) };
+        self.left_state = crate::left_state::encode(&self.left_parser, input, &self.stats.left, self.config.strict_escapes, &self.left_scope);
+        self.left_state.as_ptr()
+    }
+
+    pub fn left_state_len(&self) -> u32 { self.left_state.len() as u32 }
+
+    /// Restore a left side from `export_left_state`'s `blob`, as
+    /// `commit_left_retained` of the same document would leave it, without
+    /// parsing: the document goes back into the left input buffer and its
+    /// tokens into the parser. The engine must take a left commit now,
+    /// with the same `strict_escapes` and left scope the blob was parsed
+    /// with. A blob from another build (snapshot or result format), cut
+    /// short or damaged fails with `InvalidLeftState` and changes nothing.
+    pub fn import_left_state(&mut self, blob: &[u8]) -> Status {
+        let next = match self.state.next(Op::Commit(Side::Left)) {
+            Ok(next) => next,
+            Err(e) => return self.refuse(e),
+        };
+        if self.state.committed(Side::Left) && !self.config.recommit_replaces {
+            return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+        }
+        let state = match crate::left_state::decode(blob) {
+            Ok(state) => state,
+            Err(e) => return self.fail(EngineError::InvalidLeftState(e), Status::Error),
+        };
+        let scope_hash = crate::parser::hash_bytes_simd(&self.left_scope);
+        if state.strict_escapes != self.config.strict_escapes || state.scope_hash != scope_hash {
+            return self.fail(EngineError::InvalidLeftState(StateError::Settings), Status::Error);
+        }
+        let capacity = self.left_input.capacity();
+        if state.input.len() > capacity {
+            return self.fail(EngineError::InputExceedsBuffer { len: state.input.len() as u32, capacity }, Status::Error);
+        }
+        if !self.left_parser.restore(state.tokens, state.paths, state.escape_warnings, state.digest) {
+            return self.fail(EngineError::InvalidLeftState(StateError::Digest), Status::Error);
+        }
+        self.left_pushed = 0;
+        unsafe { core::ptr::copy_nonoverlapping(state.input.as_ptr(), self.left_input.as_mut_ptr(), state.input.len()) };
+        self.state = next;
+        self.left_len = state.input.len() as u32;
+        self.left_retained = true;
+        self.stats.left = state.stats;
+        if let Some(d) = self.digests.as_mut() { d[0] = digest(state.input); }
+        self.peaks.left_peak_token_bytes = self.peaks.left_peak_token_bytes.max(token_bytes(&self.left_parser));
+        if let Err(status) = self.settle_group() {
+            self.left_parser.clear();
+            self.left_retained = false;
+            return status;
+        }
+        Status::Ok
+    }
+
+    /// `clear`, for the right side and the result only.
+    fn reset_right(&mut self) {
+        self.reset_result();
+        self.right_parser.clear();
+        self.state = EngineState::LeftCommitted;
+        self.right_len = 0;
+        self.right_pushed = 0;
+        self.stats = EngineStats { left: self.stats.left, ..EngineStats::default() };
+        if let Some(d) = self.digests.as_mut() { d[1] = digest(&[]); }
+        if let Some(third) = self.third.as_mut() {
+            third.parser.clear();
+            third.committed = false;
+            third.len = 0;
+        }
+        let _ = self.settle_group();
+    }
+
+    /// Drop the result and everything kept about it, for `clear` and
+    /// `reset_right` alike.
+    fn reset_result(&mut self) {
+        self.arena.clear();
+        self.join = None;
+        self.lint = None;
+        self.handed_off = false;
+        self.segmented = false;
+        self.pending_write = None;
+        self.skipped_entries = 0;
+        self.dropped_entries = 0;
+        self.suppressed_entries = 0;
+        self.transformed.clear();
+        self.ranked_total = 0;
+        self.result_estimate = 0;
+        self.span_index.clear();
+        self.entry_path_of = None;
+        self.symbol_of = None;
+        self.symbol_table = None;
+        self.error.clear();
+        self.progress.reset();
+        self.cancel.reset();
+    }
+
+    /// Append `chunk` to a side's input, for hosts that stream a document
+    /// instead of writing it whole. Chunks may split anywhere, inside a
+    /// string, escape or number included: nothing is indexed or parsed
+    /// until `finalize` commits the side with everything pushed, so
+    /// offsets stay relative to the whole document and the result is the
+    /// one a single commit of the same bytes gives. An explicit commit
+    /// of the side takes over from the pushes instead (the pushed bytes
+    /// are already in its input buffer). A committed side takes no more
+    /// pushes, even with `recommit_replaces`; commit it again instead.
+    pub fn push(&mut self, side: Side, chunk: &[u8]) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        let committed = self.state.committed(side);
+        let limit = self.input_limit(side);
+        let (input, pushed) = match side {
+            Side::Left => (&mut self.left_input, &mut self.left_pushed),
+            Side::Right => (&mut self.right_input, &mut self.right_pushed),
+        };
+        if committed { return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted); }
+        let at = *pushed as usize;
+        if chunk.len() > limit - at {
+            return self.fail(EngineError::InputLimitExceeded, Status::InputLimitExceeded);
+        }
+        // SAFETY: `at + chunk.len()` is within the buffer's capacity.
+        unsafe { core::ptr::copy_nonoverlapping(chunk.as_ptr(), input.as_mut_ptr().add(at), chunk.len()) };
+        *pushed += chunk.len() as u32;
+        Status::Ok
+    }
+
+    /// Promise inputs of at most `left_len` and `right_len` bytes
+    /// (`reserve_input`), checked against the buffers now instead of at
+    /// commit: `InputLimitExceeded` if either does not fit. Afterwards a
+    /// commit or push past its side's reservation fails as one past the
+    /// buffer does. Same timing as `set_config`; a new config or a clear
+    /// drops it.
+    pub fn reserve_input(&mut self, left_len: u32, right_len: u32) -> Status {
+        if let Err(e) = self.state.next(Op::Configure) { return self.refuse(e); }
+        if self.left_pushed != 0 || self.right_pushed != 0 {
+            return self.refuse(EngineError::TooLate);
+        }
+        if left_len as usize > self.left_input.capacity() || right_len as usize > self.right_input.capacity() {
+            return self.fail(EngineError::InputLimitExceeded, Status::InputLimitExceeded);
+        }
+        self.reserved = Some([left_len, right_len]);
+        Status::Ok
+    }
+
+    /// Diff the host's `left` and `right` bytes where they lie, never
+    /// copied into the input buffers (`diff_direct`): commit both, then
+    /// finalize. The result's value spans point into them, so they are
+    /// read until a clear; each still counts against its side's capacity.
+    /// `Ok` or `ResultTruncated` with the result written; otherwise the
+    /// failing step's status and error, the engine cleared unless that
+    /// step left a result (`Cancelled`). Needs a fresh or cleared engine,
+    /// like `set_config`.
+    ///
+    /// # Safety
+    /// Each pointer must be valid for reads of its length, and the bytes
+    /// unchanged, until the engine is cleared or destroyed.
+    pub unsafe fn diff_direct(&mut self, left: (*const u8, u32), right: (*const u8, u32)) -> Status {
+        if let Err(e) = self.state.next(Op::Configure) { return self.refuse(e); }
+        if self.left_pushed != 0 || self.right_pushed != 0 {
+            return self.refuse(EngineError::TooLate);
+        }
+        self.left_input.read_from(left.0);
+        self.right_input.read_from(right.0);
+        let mut status = self.commit_left(left.1);
+        if status == Status::Ok { status = self.commit_right(right.1); }
+        if status == Status::Ok {
+            status = match self.finalize() {
+                Ok(_) if self.result_truncated() => Status::ResultTruncated,
+                Ok(_) => Status::Ok,
+                Err(e) => Status::from(&e),
+            };
+        }
+        // Nothing may go on reading them without a result to show for it.
+        if self.state != EngineState::Sealed { self.clear(); }
+        status
+    }
+
+    /// Most bytes `side` may commit: its reservation, else its capacity.
+    fn input_limit(&self, side: Side) -> usize {
+        match (side, self.reserved) {
+            (Side::Left, Some([left, _])) => left as usize,
+            (Side::Right, Some([_, right])) => right as usize,
+            (Side::Left, None) => self.left_input.capacity(),
+            (Side::Right, None) => self.right_input.capacity(),
+        }
+    }
+
+    /// Commit what `push` staged, left first (a right restriction reads
+    /// it). A failed commit fails finalize with that commit's error.
+    fn commit_pushed(&mut self) -> Result<(), EngineError> {
+        for side in [Side::Left, Side::Right] {
+            let status = match side {
+                Side::Left if self.left_pushed > 0 => self.commit_left(self.left_pushed),
+                Side::Right if self.right_pushed > 0 => self.commit_right(self.right_pushed),
+                _ => continue,
+            };
+            if status != Status::Ok {
+                let pushed = || EngineError::Internal(String::from("pushed commit failed"));
+                return Err(self.error.error().cloned().unwrap_or_else(pushed));
+            }
+        }
+        Ok(())
+    }
+
+    /// Commit the canary input of `three_way` mode; see `commit_right`,
+    /// whose scope and restriction it shares.
+    pub fn commit_third(&mut self, len: u32) -> Status {
+        let _span = trace::span!("commit", side = "third", bytes = len);
+        let status = self.commit_third_indexed(len);
+        self.release_index();
+        status
+    }
+
+    fn commit_third_indexed(&mut self, len: u32) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        let Some((capacity, committed)) = self.third.as_ref().map(|t| (t.input.capacity(), t.committed)) else {
+            return self.fail(EngineError::ThreeWayDisabled, Status::Error);
+        };
+        if len as usize > capacity {
+            return self.fail(EngineError::InputExceedsBuffer { len, capacity }, Status::Error);
+        }
+        if committed && !self.config.recommit_replaces {
+            return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+        }
+        if self.config.right_restricted_to_left && !self.state.committed(Side::Left) {
+            return self.fail(EngineError::LeftNotCommitted, Status::Error);
+        }
+        let restriction = self.config.right_restricted_to_left.then(|| self.left_parser.path_ids());
+        let third = self.third.as_mut().expect("three_way");
+        if committed { third.parser.clear(); }
+        if restriction.is_some() { third.parser.set_restriction(restriction); }
+        third.committed = true;
+        third.len = len;
+        third.input.materialize();
+        let bytes = unsafe { core::slice::from_raw_parts(third.input.as_ptr(), len as usize) };
+        let mut index = crate::index_pool::checkout(self.group);
+        let indexing = trace::span!("index", bytes = len, structurals = tracing::field::Empty);
+        index.build_reporting(bytes, false, &self.progress);
+        if let Err(status) = self.check_index(&index) { return status; }
+        trace::record!(indexing, "structurals", index.masks.iter().map(|m| m.count_ones()).sum::<u32>());
+        trace::exit!(indexing);
+        let parsing = trace::span!("parse", tokens = tracing::field::Empty, oversized = tracing::field::Empty);
+        let third = self.third.as_mut().expect("three_way");
+        let parsed = third.parser.parse_reporting(bytes, &index, Some(&self.progress));
+        self.phases.parses += 1;
+        let (found, warnings) = (third.parser.scope_found(), third.parser.escape_warnings());
+        self.progress.finish(Phase::Parse);
+        // The canary is a second right document: the right's peaks cover it.
+        self.peaks.right_peak_token_bytes = self.peaks.right_peak_token_bytes.max(token_bytes(&third.parser));
+        self.peaks.right_peak_index_bytes = self.peaks.right_peak_index_bytes.max(index_bytes(&index));
+        self.stats.third = SideStats::of(&index, &third.parser, self.config.max_primitive_bytes);
+        trace::record!(parsing, "tokens", self.stats.third.tokens);
+        trace::record!(parsing, "oversized", self.stats.third.oversized);
+        trace::exit!(parsing);
+        self.index = Some(index);
+        if let Err(status) = self.settle_group() {
+            if let Some(third) = self.third.as_mut() { third.parser.clear(); }
+            return status;
+        }
+        if parsed.is_ok() && !found {
+            return self.scope_missing(Side::Right);
+        }
+        self.parse_status(parsed, warnings)
+    }
+
+    /// Diff only the subtree at `pointer` (RFC 6901) on one side, re-based
+    /// so it lines up with the other side's root (or scope). Must be set
+    /// before that side is committed. In `three_way` mode the right scope
+    /// applies to the canary too.
+    pub fn set_scope(&mut self, side: Side, pointer: &[u8]) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        if side == Side::Right && self.third.as_ref().is_some_and(|t| t.committed) {
+            return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+        }
+        let committed = self.state.committed(side);
+        let (parser, scope) = match side {
+            Side::Left => (&mut self.left_parser, &mut self.left_scope),
+            Side::Right => (&mut self.right_parser, &mut self.right_scope),
+        };
+        if committed { return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted); }
+        if !parser.set_scope(pointer) {
+            let pointer = String::from_utf8_lossy(pointer).into_owned();
+            return self.fail(EngineError::MalformedScope(pointer), Status::Error);
+        }
+        scope.clear();
+        scope.extend_from_slice(pointer);
+        if let (Side::Right, Some(third)) = (side, self.third.as_mut()) {
+            third.parser.set_scope(pointer);
+        }
+        Status::Ok
+    }
+
+    fn scope_missing(&mut self, side: Side) -> Status {
+        let (name, scope) = match side {
+            Side::Left => ("left", &self.left_scope),
+            Side::Right => ("right", &self.right_scope),
+        };
+        let scope = String::from_utf8_lossy(scope).into_owned();
+        self.fail(EngineError::ScopeNotFound { scope, side: name }, Status::Error)
+    }
+
+    /// Record `error` (message and code) and return `status`.
+    fn fail(&mut self, error: EngineError, status: Status) -> Status {
+        self.error.set(&error);
+        status
+    }
+
+    /// Record why the lifecycle refused a call, and return its status.
+    fn refuse(&mut self, error: EngineError) -> Status {
+        let status = Status::from(&error);
+        self.fail(error, status)
+    }
+
+    /// Record `error` and leave the engine `Poisoned`: what it was doing
+    /// left nothing a later call could rely on.
+    fn poison(&mut self, error: EngineError) -> EngineError {
+        self.error.set(&error);
+        self.state = EngineState::Poisoned;
+        self.join = None;
+        self.pending_write = None;
+        error
+    }
+
+    /// Refuse to hand the parser an index that would make its unchecked
+    /// reads go out of bounds.
+    fn check_index(&mut self, index: &StructuralIndex) -> Result<(), Status> {
+        index.validate().map_err(|pos| {
+            self.poison(EngineError::Internal(format!(
+                "structural index bit {} set past input of {} bytes",
+                pos, index.len
+            )));
+            Status::Error
+        })
+    }
+
+    /// Map a commit's parse outcome to a status (`Status::from`, so a
+    /// key limit is `ObjectKeyLimitExceeded`), recording the error or
+    /// escape-replacement warning in the error buffer.
+    fn parse_status(&mut self, parsed: Result<(), ParseError>, escape_warnings: u32) -> Status {
+        match parsed {
+            Ok(()) => {
+                if escape_warnings > 0 {
+                    self.error.set(&EngineError::EscapesReplaced(escape_warnings));
+                }
+                Status::Ok
+            }
+            Err(e) => {
+                let status = Status::from(&e);
+                self.error.set(&e.into());
+                status
+            }
         }
     }
 
+    /// Seal the engine and write the result. Idempotent once finalized;
+    /// finishes a stepped finalize in one go.
+    ///
+    /// With neither side committed this fails with `NoInputCommitted`
+    /// and leaves the engine open, unless `allow_empty_side` is set: then
+    /// the result is the empty header flagged `HEADER_FLAG_NO_INPUT` (and
+    /// `HEADER_FLAG_IDENTICAL`, as two empty sides are). A single
+    /// uncommitted side diffs as an empty document either way.
     pub fn finalize(&mut self) -> Result<*const u8, EngineError> {
-        if self.sealed { return Ok(self.arena.as_ptr()); }
-        self.sealed = true;
-
-        let diffs = compute_compact_diff_v2(&self.left_parser, &self.right_parser);
-        
-        for d in diffs {
-            if let Err(_) = self.arena.write_entry_v2(
-                d.op,
-                d.path_id,
-                d.left_val,
-                d.right_val,
-            ) {
-                self.error.set(&EngineError::MemoryLimitExceeded);
+        while !self.finalize_step(usize::MAX)? {}
+        Ok(self.arena.as_ptr())
+    }
+
+    /// `finalize`, writing the result into the host's `cap` bytes at `out`
+    /// (`finalize_into`); `Ok` with its length once it is there. The
+    /// engine then keeps no copy: it stays sealed, reports no result, and
+    /// refuses another finalize with `ResultHandedOff` until a clear.
+    ///
+    /// Too small a buffer fails with `OutputTooSmall` (`NeedFlush`) and
+    /// keeps the result, `result_len` bytes, for a retry with a larger
+    /// one, as does any failure that leaves a result (`Cancelled`).
+    /// Called once `finalize` has written a result, copies it out.
+    ///
+    /// # Safety
+    /// `out` must be valid for `cap` bytes of writes.
+    pub unsafe fn finalize_into(&mut self, out: *mut u8, cap: u32) -> Result<u32, EngineError> {
+        if !matches!(self.state, EngineState::Sealed | EngineState::Poisoned) {
+            self.arena.write_into(out, cap as usize);
+        }
+        if let Err(e) = self.finalize() {
+            self.arena.keep_owned();
+            return Err(e);
+        }
+        let len = match self.arena.take_host() {
+            Some(len) => len,
+            None if self.arena.len() <= cap => {
+                let len = self.arena.len();
+                core::ptr::copy_nonoverlapping(self.arena.as_ptr(), out, len as usize);
+                self.arena.clear();
+                self.arena.shrink_to_fit();
+                len
+            }
+            None => {
+                let e = EngineError::OutputTooSmall { needed: self.arena.len(), capacity: cap };
+                self.error.set(&e);
+                return Err(e);
+            }
+        };
+        self.handed_off = true;
+        Ok(len)
+    }
+
+    /// `finalize`, in segments no larger than the arena's limit
+    /// (`finalize_partial`): `NeedFlush` when the result holds the first
+    /// of several, flagged `HEADER_FLAG_PARTIAL`, with `resume_finalize`
+    /// writing each next one; `Ok` when it is the whole result. Only an
+    /// entry that overfills an empty segment still truncates it
+    /// (`ResultTruncated`). Once finalized, reports the current segment.
+    pub fn finalize_partial(&mut self) -> Status {
+        if !matches!(self.state, EngineState::Sealed | EngineState::Poisoned) { self.segmented = true; }
+        match self.finalize() {
+            Ok(_) => self.segment_status(),
+            Err(e) => Status::from(&e),
+        }
+    }
+
+    /// Replace the segment `finalize_partial` left with the next one:
+    /// `NeedFlush` while more follow, `Ok` for the last. The previous
+    /// segment's bytes are overwritten. Fails with `NoSegmentPending`
+    /// when none is left.
+    pub fn resume_finalize(&mut self) -> Status {
+        let _span = trace::span!("resume_finalize");
+        self.error.clear();
+        let Some(pending) = self.pending_write.take() else {
+            return self.fail(EngineError::NoSegmentPending, Status::Error);
+        };
+        // The flags that describe the whole diff carry over.
+        let flags = self.arena.header_flags() & !(HEADER_FLAG_PARTIAL | HEADER_FLAG_SECTIONS);
+        self.arena.clear();
+        self.arena.set_header_flags(flags);
+        self.handed_off = false;
+        self.write_segment(pending);
+        self.segment_status()
+    }
+
+    /// `NeedFlush` while a segment is pending, else how the last ended.
+    fn segment_status(&self) -> Status {
+        match (self.pending_write.is_some(), self.result_truncated()) {
+            (true, _) => Status::NeedFlush,
+            (false, true) => Status::ResultTruncated,
+            (false, false) => Status::Ok,
+        }
+    }
+
+    /// One slice of a finalize, `Ok(true)` once the result is written.
+    ///
+    /// The first call seals the engine, as `finalize` does; each call then
+    /// visits up to `budget` tokens of the diff join (`DiffJoin`), and the
+    /// one that completes it also filters, orders and writes the result.
+    /// Any budgets give the result one `finalize` would. In between the
+    /// engine refuses input and options but has no result yet
+    /// (`result_len` is 0). In `three_way` mode only the left/right join
+    /// is sliced; the canary's runs whole in the last step.
+    ///
+    /// A set cancel flag ends the join early: what it found so far is
+    /// written, flagged `HEADER_FLAG_CANCELLED`, and the call fails with
+    /// `Cancelled`. A failure once sealed poisons the engine.
+    pub fn finalize_step(&mut self, budget: usize) -> Result<bool, EngineError> {
+        let _span = trace::span!("finalize_step", budget);
+        self.error.clear();
+        if let Err(e) = self.state.next(Op::Finalize) {
+            self.error.set(&e);
+            return Err(e);
+        }
+        match self.state {
+            EngineState::Sealed if self.handed_off => {
+                self.error.set(&EngineError::ResultHandedOff);
+                return Err(EngineError::ResultHandedOff);
+            }
+            EngineState::Sealed => return Ok(true),
+            EngineState::Finalizing => {}
+            _ => {
+                self.start_finalize()?;
+                if self.join.is_none() { return self.finish(Vec::new()).map(|()| true); }
+            }
+        }
+        let Some(join) = self.join.as_mut() else { return Ok(true) };
+        let diffing = trace::span!("diff", done = false, entries = tracing::field::Empty);
+        if !join.step(&self.left_parser, &self.right_parser, budget, Some(&self.progress), Some(&self.cancel)) {
+            trace::exit!(diffing);
+            return if self.cancel.is_set() { self.write_cancelled() } else { Ok(false) };
+        }
+        if join.disjoint() { self.phases.disjoint_joins += 1; }
+        let diffs = self.join.take().map_or_else(Vec::new, DiffJoin::into_entries);
+        #[cfg(feature = "paranoid")]
+        if let Err(e) = crate::diff::paranoid_check(&self.left_parser, &self.right_parser, &diffs) {
+            return Err(self.poison(e));
+        }
+        trace::record!(diffing, "done", true);
+        trace::record!(diffing, "entries", diffs.len());
+        trace::exit!(diffing);
+        self.finish(diffs).map(|()| true)
+    }
+
+    /// Write the cancelled join's entries so far, flagged, and fail with
+    /// `Cancelled`.
+    fn write_cancelled(&mut self) -> Result<bool, EngineError> {
+        let diffs = self.join.take().map_or_else(Vec::new, DiffJoin::into_entries);
+        self.arena.set_header_flags(HEADER_FLAG_CANCELLED);
+        self.finish(diffs)?;
+        self.error.set(&EngineError::Cancelled);
+        Err(EngineError::Cancelled)
+    }
+
+    /// Ask the running (or next) finalize to stop; see `cancel.rs`.
+    pub fn cancel(&self) { self.cancel.set(); }
+
+    /// Where the cancel flag lives, for as long as the engine does.
+    pub fn cancel_flag_ptr(&self) -> *const u8 { self.cancel.as_ptr() }
+
+    /// Diff the committed `side` (0 left, 1 right) against its canonical
+    /// form (`lint.rs`)
+    /// and seal: the canonical text is committed as the other side, so
+    /// that side must be empty, and the result is the normal one for the
+    /// two, flagged `HEADER_FLAG_LINT`. Every entry is flagged
+    /// `ENTRY_FLAG_FORMATTING`: a value written otherwise than its
+    /// canonical form (a Modified value), or an object whose members are
+    /// out of order (a Modified object, each side spanning the whole
+    /// object). The output options apply as to any finalize, so e.g.
+    /// `normalize_numbers` hides the number entries.
+    pub fn lint(&mut self, side: u32) -> Status {
+        let (side, other) = match side {
+            0 => (Side::Left, Side::Right),
+            1 => (Side::Right, Side::Left),
+            _ => return self.fail(EngineError::InvalidSide(side), Status::Error),
+        };
+        if let Err(e) = self.state.next(Op::Commit(other)) { return self.refuse(e); }
+        if !self.state.committed(side) { return self.fail(EngineError::NoInputCommitted, Status::Error); }
+        let pushed = match other {
+            Side::Left => self.left_pushed,
+            Side::Right => self.right_pushed,
+        };
+        if self.state.committed(other) || pushed > 0 || self.third.as_ref().is_some_and(|t| t.committed) {
+            return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+        }
+        let (input, len, scope) = match side {
+            Side::Left => (&self.left_input, self.left_len, self.left_scope.clone()),
+            Side::Right => (&self.right_input, self.right_len, self.right_scope.clone()),
+        };
+        let bytes = unsafe { core::slice::from_raw_parts(input.as_ptr(), len as usize) };
+        let canonical = match crate::lint::canonicalize(bytes, self.config.max_primitive_bytes) {
+            Ok(c) => c,
+            Err(e) => {
+                let status = Status::from(&e);
+                self.error.set(&e.into());
+                return status;
+            }
+        };
+        let target = match other {
+            Side::Left => &mut self.left_input,
+            Side::Right => &mut self.right_input,
+        };
+        let (len, capacity) = (canonical.text.len(), target.capacity());
+        if len > capacity {
+            return self.fail(EngineError::InputExceedsBuffer { len: len as u32, capacity }, Status::Error);
+        }
+        // SAFETY: `len` is within the buffer's capacity.
+        unsafe { core::ptr::copy_nonoverlapping(canonical.text.as_ptr(), target.as_mut_ptr(), len) };
+        if !scope.is_empty() {
+            let status = self.set_scope(other, &scope);
+            if status != Status::Ok { return status; }
+        }
+        let status = match other {
+            Side::Left => self.commit_left(len as u32),
+            Side::Right => self.commit_right(len as u32),
+        };
+        if status != Status::Ok { return status; }
+        self.lint = Some(self.reordered_objects(side, &canonical.reordered));
+        match self.finalize() {
+            Ok(_) => Status::Ok,
+            Err(e) => Status::from(&e),
+        }
+    }
+
+    /// Lint entries for the objects of `side` starting at `starts` (input
+    /// offsets, ascending), found in the other side's tokens by path.
+    fn reordered_objects(&self, side: Side, starts: &[u32]) -> Vec<DiffEntry> {
+        let (original, canonical) = match side {
+            Side::Left => (&self.left_parser, &self.right_parser),
+            Side::Right => (&self.right_parser, &self.left_parser),
+        };
+        let mut spans: FxHashMap<PathId, ValueSpan> = FxHashMap::default();
+        for (path, span) in object_spans(canonical) { spans.insert(path, span); }
+        let mut out = Vec::new();
+        for (path, span) in object_spans(original) {
+            if starts.binary_search(&span.0).is_err() { continue; }
+            let Some(&other) = spans.get(&path) else { continue };
+            let (left_val, right_val) = match side {
+                Side::Left => (Some(span), Some(other)),
+                Side::Right => (Some(other), Some(span)),
+            };
+            out.push(DiffEntry { op: DiffOp::Modified, path_id: path, left_val, right_val });
+        }
+        out
+    }
+
+    /// Rebuild a right document from `doc`, its left document, and
+    /// `result`, a result written with `self_contained`, and seal with the
+    /// rebuilt document as the result: no entries, the text in the
+    /// document section, flagged `HEADER_FLAG_PATCHED` (see `patch.rs`).
+    /// The engine takes no input for it, so nothing may be committed or
+    /// pushed (`AlreadyCommitted`). A result without a patch section, or
+    /// a record `doc` has no place for, fails with `InvalidPatch`; a
+    /// `doc` that is not JSON with its parse error. The engine stays open
+    /// on failure.
+    pub fn apply_patch(&mut self, doc: &[u8], result: &[u8]) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        let third_committed = self.third.as_ref().is_some_and(|t| t.committed);
+        if self.state != EngineState::Created || self.left_pushed > 0 || self.right_pushed > 0 || third_committed {
+            return self.fail(EngineError::AlreadyCommitted, Status::AlreadyCommitted);
+        }
+        let invalid = |why: String| EngineError::InvalidPatch(why);
+        let patch = match ResultReader::parse(result) {
+            Ok(r) => r.patch(),
+            Err(e) => return self.fail(invalid(format!("{}", e)), Status::Error),
+        };
+        let Some(patch) = patch else {
+            return self.fail(invalid(String::from("result has no patch section (self_contained is off)")), Status::Error);
+        };
+        let text = match crate::patch::apply(doc, patch) {
+            Ok(t) => t,
+            Err(PatchError::Parse(e)) => {
+                let status = Status::from(&e);
+                self.error.set(&e.into());
+                return status;
+            }
+            Err(PatchError::Record(n, why)) => return self.fail(invalid(format!("record {}: {}", n, why)), Status::Error),
+        };
+        if self.arena.write_sections(&[(SECTION_DOCUMENT, &text)]).is_err() {
+            return self.fail(EngineError::MemoryLimitExceeded, Status::Error);
+        }
+        self.arena.set_header_flags(HEADER_FLAG_PATCHED);
+        self.arena.seal();
+        self.state = EngineState::Sealed;
+        Status::Ok
+    }
+
+    /// Structural hash of a committed side (0 left, 1 right), for hosts
+    /// that skip `finalize` when two documents hash equal: whitespace and
+    /// key order do not change it, any value does (see
+    /// `CompactParser::content_hash`). Values hash by their raw bytes, so
+    /// two documents the `normalize_*` options would call equal may still
+    /// differ. Only before finalize, with the side committed; 0 and the
+    /// error recorded otherwise.
+    pub fn hash_document(&mut self, side: u32) -> u64 {
+        let side = match side {
+            0 => Side::Left,
+            1 => Side::Right,
+            _ => {
+                self.fail(EngineError::InvalidSide(side), Status::Error);
+                return 0;
+            }
+        };
+        if let Err(e) = self.state.next(Op::Prepare) {
+            self.refuse(e);
+            return 0;
+        }
+        if !self.state.committed(side) {
+            self.fail(EngineError::NoInputCommitted, Status::Error);
+            return 0;
+        }
+        match side {
+            Side::Left => self.left_parser.content_hash(),
+            Side::Right => self.right_parser.content_hash(),
+        }
+    }
+
+    /// Check that `doc` is one well-formed JSON document (`validate.rs`),
+    /// recording where it is not as `InvalidJson`. The engine is only the
+    /// error channel: its input, options and state are untouched, so this
+    /// runs in any state but `Poisoned`.
+    pub fn validate_json(&mut self, doc: &[u8]) -> Status {
+        if self.state == EngineState::Poisoned { return self.refuse(EngineError::Poisoned); }
+        match crate::validate::validate(doc) {
+            Ok(()) => Status::Ok,
+            Err(e) => self.fail(EngineError::InvalidJson(e), Status::Error),
+        }
+    }
+
+    /// The result is written, and still here.
+    fn finalized(&self) -> bool { self.state == EngineState::Sealed && !self.handed_off }
+
+    /// Write the result and seal, or poison on failure.
+    fn finish(&mut self, diffs: Vec<DiffEntry>) -> Result<(), EngineError> {
+        match self.write_result(diffs) {
+            Ok(()) => {
+                self.state = EngineState::Sealed;
+                Ok(())
+            }
+            Err(e) => Err(self.poison(e)),
+        }
+    }
+
+    /// Seal, and start the join unless the sides are token-identical.
+    fn start_finalize(&mut self) -> Result<(), EngineError> {
+        self.commit_pushed()?;
+        let third_committed = self.third.as_ref().is_some_and(|t| t.committed);
+        let no_input = self.state == EngineState::Created && !third_committed;
+        if no_input && !self.config.allow_empty_side {
+            self.error.set(&EngineError::NoInputCommitted);
+            return Err(EngineError::NoInputCommitted);
+        }
+        self.state = EngineState::Finalizing;
+        if no_input { self.arena.set_header_flags(HEADER_FLAG_NO_INPUT); }
+
+        // Pipeline: compute → shape filter → suppress → entry transform → order (or `top_k` selection, see
+        // `rank.rs`) → per-entry size policy → write. Size
+        // policy runs after ordering so which entries survive a full arena
+        // follows the requested order.
+        //
+        // Token-identical sides (equal count and digest, e.g. pretty-printed
+        // vs minified) are defined to have no differences: the join is
+        // skipped and the header says so. This also covers duplicate keys,
+        // where the join would report earlier duplicates as Modified.
+        //
+        // In `three_way` mode compute and shape filter also run against the
+        // canary, and the two entry lists are joined on path before
+        // suppression (see `three_way.rs`). The header flag then needs all
+        // three sides identical.
+        self.hash_sides();
+        let identical = same_tokens(&self.left_parser, &self.right_parser);
+        if identical && self.third.as_ref().is_none_or(|t| same_tokens(&self.left_parser, &t.parser)) {
+            self.arena.set_header_flags(HEADER_FLAG_IDENTICAL);
+        }
+        if identical {
+            self.phases.digest_matches += 1;
+            return Ok(());
+        }
+        self.phases.joins += 1;
+        match self.start_join() {
+            Ok(join) => {
+                self.join = Some(join);
+                Ok(())
+            }
+            Err(e) => Err(self.poison(e)),
+        }
+    }
+
+    /// Run the comparison hash pass (`normalize.rs`) over every side for
+    /// the current settings, so the joins compare what they say.
+    fn hash_sides(&mut self) {
+        let n = Normalization::of(&self.output);
+        let sides = [
+            (&mut self.left_parser, &self.left_input, self.left_len),
+            (&mut self.right_parser, &self.right_input, self.right_len),
+        ];
+        let third = self.third.as_mut().map(|t| (&mut t.parser, &t.input, t.len));
+        for (parser, input, len) in sides.into_iter().chain(third) {
+            let bytes = unsafe { core::slice::from_raw_parts(input.as_ptr(), len as usize) };
+            if parser.rehash(bytes, n, self.config.max_primitive_bytes) { self.phases.hash_passes += 1; }
+        }
+    }
+
+    /// Everything after the join: filter, suppress, transform, order and
+    /// write `diffs`, then seal the arena.
+    fn write_result(&mut self, mut diffs: Vec<DiffEntry>) -> Result<(), EngineError> {
+        let identical = same_tokens(&self.left_parser, &self.right_parser);
+        let lint = self.lint.take();
+        let linting = lint.is_some();
+        if let Some(objects) = lint {
+            self.arena.set_header_flags(HEADER_FLAG_LINT);
+            diffs.extend(objects);
+        }
+        self.progress.finish(Phase::Diff);
+
+        // A restricted right side has no tokens at new paths, but a left
+        // container that became a scalar still shows up as an Added value
+        // at a known path. Added detection is off in this mode, so those
+        // are dropped too.
+        if self.config.right_restricted_to_left {
+            self.arena.set_header_flags(HEADER_FLAG_RIGHT_RESTRICTED);
+            diffs.retain(|d| d.op != DiffOp::Added);
+        }
+
+        if self.output.shape_only {
+            let left = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), self.left_len as usize) };
+            let right = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), self.right_len as usize) };
+            diffs.retain(|d| d.changes_shape(left, right));
+        }
+
+        let mut canary = None;
+        if let Some(third) = self.third.as_ref() {
+            let mut canary_diffs = if same_tokens(&self.left_parser, &third.parser) {
+                Vec::new()
+            } else {
+                match self.compute_diff(&third.parser) {
+                    Ok(d) => d,
+                    Err(e) => {
+                        self.error.set(&e);
+                        return Err(e);
+                    }
+                }
+            };
+            let left = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), self.left_len as usize) };
+            let right = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), self.right_len as usize) };
+            let canary_input = unsafe { core::slice::from_raw_parts(third.input.as_ptr(), third.len as usize) };
+            if self.config.right_restricted_to_left { canary_diffs.retain(|d| d.op != DiffOp::Added); }
+            if self.output.shape_only { canary_diffs.retain(|d| d.changes_shape(left, canary_input)); }
+            let (joined, sides) = crate::three_way::join(diffs, canary_diffs, right, canary_input);
+            diffs = joined;
+            canary = Some(sides);
+        }
+
+        // Before suppression and the transform, so neither sees a
+        // redacted value.
+        if !self.redact.is_empty() {
+            self.ensure_paths();
+            for d in diffs.iter_mut() {
+                let path = self.render_path(d);
+                if !self.redact.iter().any(|p| p.matches_pointer(&path)) { continue; }
+                crate::transform::redact(d);
+                let side = canary.as_mut().and_then(|sides| sides.get_mut(&d.path_id));
+                if let Some(CanarySide { val: Some(v), .. }) = side { *v = crate::transform::MARKER; }
+            }
+        }
+
+        if !self.suppressions.is_empty() {
+            let before = diffs.len();
+            diffs.retain(|d| !self.suppressions.contains(&self.suppression_key(d)));
+            self.suppressed_entries = (before - diffs.len()) as u32;
+        }
+
+        self.ensure_paths();
+        if let Some(transform) = self.transform {
+            diffs = match self.transform_entries(transform, diffs) {
+                Ok(d) => d,
+                Err(e) => {
+                    self.error.set(&e);
+                    return Err(e);
+                }
+            };
+        }
+        self.ranked_total = diffs.len() as u32;
+        if self.output.top_k != 0 {
+            let (k, metric) = (self.output.top_k as usize, self.output.significance);
+            diffs = crate::rank::top_k(diffs, k, metric, |d| self.render_path(d));
+        } else {
+            self.order_entries(&mut diffs);
+        }
+
+        let max_entry = self.output.max_entry_bytes;
+        let max_primitive = self.config.max_primitive_bytes;
+        let skip_oversized = max_entry != 0 && self.output.oversize_policy == OversizePolicy::Skip;
+        // Sized as written, after oversized primitives are cut.
+        let skipped = |d: &DiffEntry| skip_oversized && d.capped_footprint(max_primitive) > max_entry as u64;
+        let kept = diffs.iter().filter(|d| !skipped(d)).count();
+        // Paths of the entries that will be written, in order.
+        let paths: Vec<Vec<u8>> = if self.output.rendered_paths {
+            diffs.iter().filter(|d| !skipped(d)).map(|d| self.render_path(d)).collect()
+        } else {
+            Vec::new()
+        };
+        let cuts_primitives = diffs.iter().any(|d| !skipped(d) && d.oversized_primitives(max_primitive) != (false, false));
+        let front_coded = self.output.paths_sorted();
+        let mut coverage = Vec::new();
+        if self.output.coverage_map && !identical {
+            for range in crate::coverage::uncovered_ranges(&self.left_parser, &self.right_parser) {
+                coverage.extend(range.iter().flat_map(|v| v.to_le_bytes()));
+            }
+        }
+        let digests = self.digests.map(|d| d.concat());
+        let audit = self.audit.as_ref().map(|log| log.to_bytes(0));
+        let left = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), self.left_len as usize) };
+        let right = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), self.right_len as usize) };
+        // The whole inputs, whatever the entries kept.
+        let patch = if self.output.self_contained { Some(crate::patch::diff(left, right)?) } else { None };
+        // Everything the result holds is known now: size it exactly (the
+        // section lengths in `write_sections` order) and allocate once.
+        let section_lens: Vec<usize> = [
+            self.output.coverage_map.then_some(coverage.len()),
+            digests.as_ref().map(|d| d.len()),
+            self.output.value_hashes.then_some(kept.saturating_mul(8)),
+            self.output.numeric_deltas.then_some(kept.saturating_mul(8)),
+            self.output.rendered_paths.then(|| path_records_len(&paths, front_coded)),
+            canary.as_ref().map(|_| kept.saturating_mul(CanarySide::SIZE)),
+            self.transform.map(|_| kept.saturating_mul(4)),
+            self.output.entry_sequence.then_some(kept.saturating_mul(4)),
+            cuts_primitives.then_some(kept.saturating_mul(8)),
+            patch.as_ref().map(|p| p.len()),
+            audit.as_ref().map(|a| a.len()),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let estimate = ResultArena::estimate_len(kept, &section_lens);
+        self.result_estimate = u32::try_from(estimate).unwrap_or(u32::MAX);
+        if self.config.arena_growth == ArenaGrowth::Reserve {
+            self.arena.reserve(estimate);
+        }
+
+        self.write_segment(PendingWrite {
+            diffs,
+            next: 0,
+            written: 0,
+            paths,
+            canary,
+            linting,
+            cuts_primitives,
+            front_coded,
+            coverage,
+            digests,
+            patch,
+            audit,
+        });
+        Ok(())
+    }
+
+    /// Write entries from `p.next` on, and the sections for them, then
+    /// seal. Normally that is every entry, and a full arena drops the
+    /// rest, counted in `dropped_entries` and flagged
+    /// `HEADER_FLAG_TRUNCATED` (`ResultTruncated`). Under `finalize_partial`, the entry
+    /// (or the whole-diff sections) that would overfill a segment holding
+    /// entries ends it instead: it is sealed flagged `HEADER_FLAG_PARTIAL`
+    /// and `p` kept for `resume_finalize`.
+    fn write_segment(&mut self, mut p: PendingWrite) {
+        let writing = trace::span!(
+            "write",
+            entries = p.diffs.len() - p.next,
+            written = tracing::field::Empty,
+            bytes = tracing::field::Empty,
+            skipped = tracing::field::Empty,
+            full = tracing::field::Empty,
+        );
+        let max_entry = self.output.max_entry_bytes;
+        let max_primitive = self.config.max_primitive_bytes;
+        let skip_oversized = max_entry != 0 && self.output.oversize_policy == OversizePolicy::Skip;
+        // Sized as written, after oversized primitives are cut.
+        let skipped = |d: &DiffEntry| skip_oversized && d.capped_footprint(max_primitive) > max_entry as u64;
+        let kept = p.diffs[p.next..].iter().filter(|d| !skipped(d)).count();
+        let left = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), self.left_len as usize) };
+        let right = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), self.right_len as usize) };
+        let (cuts_primitives, front_coded) = (p.cuts_primitives, p.front_coded);
+        // This segment's section lengths in `write_sections` order, for
+        // `n` entries and `paths` bytes of path records; the whole-diff
+        // sections only in the `last`.
+        let o = &self.output;
+        // Bytes per entry; the path records (0) vary.
+        let per_entry = [
+            o.value_hashes.then_some(8),
+            o.numeric_deltas.then_some(8),
+            o.rendered_paths.then_some(0),
+            p.canary.as_ref().map(|_| CanarySide::SIZE),
+            self.transform.map(|_| 4),
+            o.entry_sequence.then_some(4),
+            cuts_primitives.then_some(8),
+        ];
+        let (first, last_lens) = (
+            [o.coverage_map.then_some(p.coverage.len()), p.digests.as_ref().map(|d| d.len())],
+            [p.patch.as_ref().map(|p| p.len()), p.audit.as_ref().map(|a| a.len())],
+        );
+        let section_lens = move |n: usize, paths: usize, last: bool| -> Vec<usize> {
+            let whole = |lens: [Option<usize>; 2]| lens.into_iter().flatten().filter(move |_| last);
+            let entries = per_entry.into_iter().flatten().map(|size| if size == 0 { paths } else { n * size });
+            whole(first).chain(entries).chain(whole(last_lens)).collect()
+        };
+        let max_size = self.arena.max_size() as usize;
+        let fits = |n: usize, paths: usize, last: bool| ResultArena::estimate_len(n, &section_lens(n, paths, last)) <= max_size;
+
+        let mut full = false;
+        // Ends this segment with more to come.
+        let mut partial = false;
+        // Never more than the arena could hold, whatever `kept` is.
+        let hashes_cap = if self.output.value_hashes { kept.saturating_mul(8).min(max_size) } else { 0 };
+        let mut value_hashes = Vec::with_capacity(hashes_cap);
+        let deltas_cap = if self.output.numeric_deltas { kept.saturating_mul(8).min(max_size) } else { 0 };
+        let mut numeric_deltas = Vec::with_capacity(deltas_cap);
+        let mut path_records = Vec::new();
+        let mut path_record = Vec::new();
+        let mut annotations = Vec::new();
+        let mut sequence = Vec::new();
+        let mut value_lengths = Vec::new();
+        // Entries in this segment; `p.written` counts the earlier ones.
+        let mut written = 0usize;
+        let mut canary_sides = Vec::new();
+        let total = p.diffs.len();
+        while p.next < total {
+            let i = p.next;
+            if i.is_multiple_of(STRIDE_TOKENS) { self.progress.report(Phase::Write, i, total); }
+            let mut d = p.diffs[i].clone();
+            let mut flags = 0;
+            let hash = if self.output.value_hashes { self.suppression_key(&d).3 } else { 0 };
+            // An oversized primitive compares by its raw bytes only, so it
+            // gets no delta, and is cut before the entry size policy.
+            let oversized = d.oversized_primitives(max_primitive) != (false, false);
+            let delta = if self.output.numeric_deltas && !oversized { d.numeric_delta(left, right) } else { None };
+            let uncut = [d.left_val, d.right_val].map(|v| v.map_or(0, |(_, len, _)| len));
+            let footprint = d.capped_footprint(max_primitive);
+            if d.cap_primitives(max_primitive, left, right) {
+                flags |= ENTRY_FLAG_TRUNCATED | ENTRY_FLAG_OVERSIZED_PRIMITIVE;
+            }
+            // An oversized entry is a per-entry problem: handle it and move
+            // on. Only a genuinely full arena ends the loop.
+            if max_entry != 0 && footprint > max_entry as u64 {
+                match self.output.oversize_policy {
+                    OversizePolicy::Skip => {
+                        self.skipped_entries += 1;
+                        p.next += 1;
+                        continue;
+                    }
+                    OversizePolicy::Truncate => {
+                        d.truncate_to(max_entry, left, right);
+                        flags |= ENTRY_FLAG_TRUNCATED;
+                    }
+                }
+            }
+            path_record.clear();
+            if self.output.rendered_paths {
+                // Each segment's records decode on their own.
+                let prev = (front_coded && written > 0).then(|| p.paths[p.written - 1].as_slice());
+                write_path_record(prev, &p.paths[p.written], &mut path_record);
+            }
+            if self.segmented && written > 0 && !fits(written + 1, path_records.len() + path_record.len(), false) {
+                partial = true;
+                break;
+            }
+            flags |= d.utf8_flags(left, right);
+            let (annotation, redacted) = match self.transform {
+                Some(_) => self.transformed.get(&d.path_id.0).copied().unwrap_or_default(),
+                None => (0, false),
+            };
+            if redacted || crate::transform::is_redacted(&d) { flags |= ENTRY_FLAG_REDACTED; }
+            if p.linting { flags |= ENTRY_FLAG_FORMATTING; }
+            match delta {
+                Some(Delta::Exact(_)) => flags |= ENTRY_FLAG_NUMERIC_DELTA,
+                Some(Delta::Inexact) => flags |= ENTRY_FLAG_DELTA_INEXACT,
+                None => {}
+            }
+            if self.arena.write_entry_v2(d.op, d.path_id, d.left_val, d.right_val, flags).is_err() {
+                full = true;
                 break;
             }
+            p.next += 1;
+            if self.output.value_hashes { value_hashes.extend_from_slice(&hash.to_le_bytes()); }
+            if self.output.numeric_deltas {
+                let value = match delta { Some(Delta::Exact(v)) => v, _ => 0.0 };
+                numeric_deltas.extend_from_slice(&value.to_le_bytes());
+            }
+            path_records.extend_from_slice(&path_record);
+            if self.transform.is_some() { annotations.extend_from_slice(&annotation.to_le_bytes()); }
+            if self.output.entry_sequence { sequence.extend_from_slice(&(p.written as u32).to_le_bytes()); }
+            if cuts_primitives { value_lengths.extend(uncut.iter().flat_map(|len| len.to_le_bytes())); }
+            written += 1;
+            p.written += 1;
+            // `join` gave every entry a side.
+            if let Some(sides) = &p.canary { canary_sides.extend_from_slice(&sides[&d.path_id].to_bytes()); }
+        }
+        // The whole-diff sections that would not fit after these entries
+        // get a segment of their own.
+        if self.segmented && !partial && !full && written > 0 && !fits(written, path_records.len(), true) {
+            partial = true;
+        }
+
+        let mut sections: Vec<(u32, &[u8])> = Vec::new();
+        if self.output.coverage_map && !partial { sections.push((SECTION_COVERAGE, &p.coverage)); }
+        if let Some(d) = p.digests.as_ref().filter(|_| !partial) { sections.push((SECTION_INPUT_DIGESTS, d)); }
+        if self.output.value_hashes { sections.push((SECTION_VALUE_HASHES, &value_hashes)); }
+        if self.output.numeric_deltas { sections.push((SECTION_NUMERIC_DELTAS, &numeric_deltas)); }
+        if self.output.rendered_paths {
+            sections.push((SECTION_PATHS, &path_records));
+            if front_coded { self.arena.set_header_flags(HEADER_FLAG_PATHS_FRONT_CODED); }
+        }
+        if p.canary.is_some() { sections.push((SECTION_CANARY, &canary_sides)); }
+        if self.transform.is_some() { sections.push((SECTION_ANNOTATIONS, &annotations)); }
+        if self.output.entry_sequence {
+            sections.push((SECTION_ENTRY_SEQUENCE, &sequence));
+            self.arena.set_header_flags(HEADER_FLAG_ENTRY_SEQUENCE);
+        }
+        if cuts_primitives { sections.push((SECTION_VALUE_LENGTHS, &value_lengths)); }
+        if let Some(patch) = p.patch.as_ref().filter(|_| !partial) { sections.push((SECTION_PATCH, patch)); }
+        if let Some(a) = p.audit.as_ref().filter(|_| !partial) { sections.push((SECTION_AUDIT_LOG, a)); }
+        if self.arena.write_sections(&sections).is_err() {
+            full = true;
+        }
+        if full {
+            // What the loop did not reach, bar oversized skips.
+            self.dropped_entries = p.diffs[p.next..].iter().filter(|d| !skipped(d)).count() as u32;
+            self.error.set(&EngineError::ResultTruncated { limit: max_size as u32, dropped: self.dropped_entries });
         }
 
+        if self.skipped_entries > 0 && !full && !partial {
+            self.error.set(&EngineError::OversizedEntriesSkipped(self.skipped_entries));
+        }
+
+        if partial { self.arena.set_header_flags(HEADER_FLAG_PARTIAL); }
         self.arena.seal();
-        Ok(self.arena.as_ptr())
+        if self.output.split_by_op { self.arena.split_by_op(); }
+        self.stats.record_result(self.arena.entries(), self.arena.len());
+        if !partial { self.progress.finish(Phase::Write); }
+        trace::record!(writing, "written", written);
+        trace::record!(writing, "bytes", self.arena.len());
+        trace::record!(writing, "skipped", self.skipped_entries);
+        trace::record!(writing, "full", full);
+        self.pending_write = partial.then_some(p);
     }
 
-    pub fn clear(&mut self) {
-        self.arena.clear();
-        self.left_parser.clear();
-        self.right_parser.clear();
-        self.sealed = false;
+    /// The left/right join, resumable (`finalize_step`). Under `paranoid`
+    /// its entries are checked against the other strategy once it is done.
+    fn start_join(&self) -> Result<DiffJoin, EngineError> {
+        Ok(DiffJoin::new(&self.left_parser, &self.right_parser))
     }
 
-    pub fn left_input_ptr(&mut self) -> *mut u8 { self.left_input.as_mut_ptr() }
-    pub fn right_input_ptr(&mut self) -> *mut u8 { self.right_input.as_mut_ptr() }
+    /// Diff the left side against `right` (the canary), checked against
+    /// the other strategy under `paranoid`.
+    fn compute_diff(&self, right: &CompactParser) -> Result<Vec<DiffEntry>, EngineError> {
+        let diffs = compute_compact_diff_reporting(&self.left_parser, right, Some(&self.progress));
+        #[cfg(feature = "paranoid")]
+        crate::diff::paranoid_check(&self.left_parser, right, &diffs)?;
+        Ok(diffs)
+    }
 
-    pub fn result_len(&self) -> u32 { self.arena.len() }
-    pub fn last_error_len(&self) -> u32 { self.error.len() }
-    pub fn last_error_ptr(&self) -> *const u8 { self.error.as_ptr() }
+    /// Show each entry to the host's transform (`transform.rs`) and keep,
+    /// drop or redact it as told. What it kept is noted in `transformed`
+    /// for the write loop.
+    fn transform_entries(&mut self, transform: EntryTransform, diffs: Vec<DiffEntry>) -> Result<Vec<DiffEntry>, EngineError> {
+        let left = unsafe { core::slice::from_raw_parts(self.left_input.as_ptr(), self.left_len as usize) };
+        let right = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), self.right_len as usize) };
+        self.transformed.clear();
+        let mut scratch = core::mem::take(&mut self.transform_scratch);
+        let mut kept = Vec::with_capacity(diffs.len());
+        let mut outcome = Ok(());
+        for mut d in diffs {
+            let path = self.render_path(&d);
+            match crate::transform::call(transform, &mut scratch, &d, &path, left, right) {
+                Ok(Outcome::Drop) => {}
+                Ok(Outcome::Keep { annotation }) => {
+                    self.transformed.insert(d.path_id.0, (annotation, false));
+                    kept.push(d);
+                }
+                Ok(Outcome::Redact { annotation }) => {
+                    crate::transform::redact(&mut d);
+                    self.transformed.insert(d.path_id.0, (annotation, true));
+                    kept.push(d);
+                }
+                Err(code) => {
+                    outcome = Err(EngineError::InvalidTransformResult(code));
+                    break;
+                }
+            }
+        }
+        self.transform_scratch = scratch;
+        outcome.map(|()| kept)
+    }
+
+    /// Set or remove the entry transform. Allowed until finalize; `clear`
+    /// keeps it. Sides committed from now on record paths for it.
+    pub fn set_entry_transform(&mut self, transform: Option<EntryTransform>) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        self.transform = transform;
+        let record = self.records_paths();
+        if !self.state.committed(Side::Left) { self.left_parser.set_record_paths(record); }
+        if !self.state.committed(Side::Right) { self.right_parser.set_record_paths(record); }
+        Status::Ok
+    }
+
+    /// Whether parses record paths: the output renders or orders by them,
+    /// or the entry transform is shown them.
+    fn records_paths(&self) -> bool { self.output.needs_paths() || self.transform.is_some() || !self.redact.is_empty() }
+
+    /// Add every entry of `result` (a finalized result with value hashes)
+    /// to the suppression set. Allowed until finalize.
+    pub fn load_suppressions(&mut self, result: &[u8]) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        let invalid = |why: String| EngineError::InvalidSuppressions(why);
+        let reader = match ResultReader::parse(result) {
+            Ok(r) => r,
+            Err(e) => return self.fail(invalid(format!("{}", e)), Status::Error),
+        };
+        let hashes = match reader.value_hashes() {
+            Some(h) if h.len() == reader.entry_count() => h,
+            Some(_) => return self.fail(invalid(String::from("value hash count differs from entry count")), Status::Error),
+            None => return self.fail(invalid(String::from("result has no value hash section")), Status::Error),
+        };
+        for (e, hash) in reader.entries().zip(hashes) {
+            self.suppressions.insert((e.path_id, e.op, e.right_kind, hash));
+        }
+        Status::Ok
+    }
+
+    pub fn suppressed_entries(&self) -> u32 { self.suppressed_entries }
+
+    pub fn dropped_entries(&self) -> u32 { self.dropped_entries }
+
+    /// The result is written but hit `max_memory_bytes`
+    /// (`HEADER_FLAG_TRUNCATED`).
+    pub fn result_truncated(&self) -> bool { self.finalized() && self.arena.header_flags() & HEADER_FLAG_TRUNCATED != 0 }
+
+    /// Log an export call, if the audit log is on. `status` is what the
+    /// export returned; the error code is taken only for a failure, as
+    /// the buffer still holds an earlier one after a success.
+    pub fn audit(&mut self, op: AuditOp, arg: u32, status: Status) {
+        if let Some(log) = self.audit.as_mut() {
+            let code = if status.is_ok() { 0 } else { self.error.code() };
+            log.record(op, arg, status as u8, code);
+        }
+    }
+
+    /// The audit log's records, oldest first, skipping the oldest `skip`;
+    /// empty when the log is off.
+    pub fn audit_log(&self, skip: usize) -> Vec<u8> {
+        self.audit.as_ref().map_or_else(Vec::new, |log| log.to_bytes(skip))
+    }
+
+    /// Bytes `audit_log(0)` would return.
+    pub fn audit_log_len(&self) -> usize { self.audit.as_ref().map_or(0, AuditLog::byte_len) }
+
+    /// Entries the last finalize ranked or ordered, before any `top_k` cut.
+    pub fn ranked_total(&self) -> u32 { self.ranked_total }
+
+    /// Arena growth since the last clear, with the exact result length the
+    /// last finalize computed before writing. That differs from
+    /// `result_len` only when the arena filled up.
+    pub fn arena_stats(&self) -> ArenaStats { self.arena.stats(self.result_estimate, self.trimmed_bytes) }
+
+    /// Match key of an entry against loaded suppressions. The hash covers
+    /// the whole right value, so it is the same whether or not the entry
+    /// is later truncated. A redacted value hashes to `REDACTED_HASH`.
+    fn suppression_key(&self, d: &DiffEntry) -> SuppressionKey {
+        let (kind, hash) = match d.right_val {
+            Some(v @ (_, _, kind)) if v == crate::transform::MARKER => (kind as u8, crate::transform::REDACTED_HASH),
+            Some((offset, len, kind)) => {
+                let right = unsafe { core::slice::from_raw_parts(self.right_input.as_ptr(), self.right_len as usize) };
+                let (start, end) = (offset as usize, (offset as usize).saturating_add(len as usize));
+                (kind as u8, crate::parser::hash_bytes_simd(right.get(start..end).unwrap_or_default()))
+            }
+            None => (0, 0),
+        };
+        (d.path_id.0, d.op as u8, kind, hash)
+    }
+
+    /// Override finalize-time options; see `OutputOptions` for the record
+    /// format and precedence. Allowed until finalize.
+    pub fn set_output_options(&mut self, bytes: &[u8]) -> Status {
+        if let Err(e) = self.state.next(Op::Prepare) { return self.refuse(e); }
+        match self.output.with_records(bytes) {
+            Ok(output) => {
+                self.output = output;
+                let record = self.records_paths();
+                if !self.state.committed(Side::Left) { self.left_parser.set_record_paths(record); }
+                if !self.state.committed(Side::Right) { self.right_parser.set_record_paths(record); }
+                Status::Ok
+            }
+            Err(e) => {
+                self.error.set(&EngineError::Config(e));
+                Status::Error
+            }
+        }
+    }
+
+    /// Replace the config before any input arrives (`set_config`), keeping
+    /// the allocations the new limits still fit. Input buffers are
+    /// reallocated only if their capacity changes; the result arena and
+    /// parsers take the new limits in place. Scopes, suppressions and the
+    /// entry transform are kept; `set_output_options` overrides give way
+    /// to the new config's options, and a different `audit_log_len`
+    /// starts a new log. In a group, a config whose footprint
+    /// does not fit is refused and the old one stays.
+    ///
+    /// `bytes` is a binary config as `create_engine` reads it; empty means
+    /// the default.
+    pub fn set_config(&mut self, bytes: &[u8]) -> Status {
+        self.reconfigure(|_| {
+            let config = if bytes.is_empty() { Ok(EngineConfig::default()) } else { EngineConfig::from_bytes(bytes) };
+            config.map_err(EngineError::Config)
+        })
+    }
+
+    /// Set one config field by name (`set_option`), as `set_config` with
+    /// the current config and that field changed: same timing, same
+    /// validation. `key` is the field's name in the JSON config form and
+    /// `value` its value there (`EngineConfig::with_option`); a key that
+    /// names no field fails with `UnknownOption`.
+    pub fn set_option(&mut self, key: &[u8], value: &[u8]) -> Status {
+        self.reconfigure(|config| {
+            let unknown = || EngineError::UnknownOption(String::from_utf8_lossy(key).into_owned());
+            let key = core::str::from_utf8(key).map_err(|_| unknown())?;
+            config.with_option(key, value).map_err(EngineError::Config)?.ok_or_else(unknown)
+        })
+    }
+
+    /// The body of `set_config` and `set_option`: `build` makes the new
+    /// config from the current one.
+    fn reconfigure(&mut self, build: impl FnOnce(&EngineConfig) -> Result<EngineConfig, EngineError>) -> Status {
+        if let Err(e) = self.state.next(Op::Configure) { return self.refuse(e); }
+        if self.left_pushed != 0 || self.right_pushed != 0 || self.third.as_ref().is_some_and(|t| t.committed) {
+            return self.refuse(EngineError::TooLate);
+        }
+        let built = build(&self.config).and_then(|c| c.redact_patterns().map(|r| (c, r)).map_err(EngineError::Config));
+        let (config, redact) = match built {
+            Ok(parsed) => parsed,
+            Err(e) => return self.refuse(e),
+        };
+        let old = core::mem::replace(&mut self.config, config);
+        let old_redact = core::mem::replace(&mut self.redact, redact);
+        self.apply_config();
+        if let Err(status) = self.settle_group() {
+            self.config = old;
+            self.redact = old_redact;
+            self.apply_config();
+            return status;
+        }
+        self.reserved = None;
+        let len = self.config.audit_log_len;
+        if len == 0 {
+            self.audit = None;
+        } else if self.audit.as_ref().is_none_or(|log| log.capacity() != len) {
+            self.audit = Some(AuditLog::new(len));
+        }
+        Status::Ok
+    }
+
+    /// Bring everything `new` derived from the config in line with
+    /// `self.config` and `self.redact`.
+    fn apply_config(&mut self) {
+        let config = &self.config;
+        let sides = if config.three_way { 3 } else { 2 };
+        let input_cap = (config.max_input_size / sides) as usize;
+        if self.left_input.capacity() != input_cap {
+            self.left_input = InputBuf::with_capacity(input_cap);
+            self.right_input = InputBuf::with_capacity(input_cap);
+        }
+        self.output = OutputOptions::from_config(config);
+        self.arena.set_max_size(config.max_memory_bytes);
+        self.digests = config.input_digests.then(|| [digest(&[]); 2]);
+        if !config.three_way {
+            self.third = None;
+        } else if self.third.as_ref().is_none_or(|t| t.input.capacity() != input_cap) {
+            let mut parser = new_parser(config, false);
+            parser.set_scope(&self.right_scope);
+            self.third = Some(ThirdInput { input: InputBuf::with_capacity(input_cap), len: 0, parser, committed: false });
+        }
+        let record = self.records_paths();
+        let config = &self.config;
+        let third = self.third.as_mut().map(|t| &mut t.parser);
+        for parser in [&mut self.left_parser, &mut self.right_parser].into_iter().chain(third) {
+            parser.set_limits(config.max_object_keys, config.compute_mode);
+            parser.set_strict_escapes(config.strict_escapes);
+            parser.set_record_paths(record);
+        }
+    }
+
+    /// Path orders, `top_k` and the entry transform need rendered paths. A
+    /// side parsed without recording them (order overridden or transform
+    /// set after its commit) is parsed again.
+    fn ensure_paths(&mut self) {
+        if self.records_paths() { self.record_paths(); }
+    }
+
+    /// Re-parse every side parsed without recording paths, recording them.
+    fn record_paths(&mut self) {
+        let group = self.group;
+        let mut index = None;
+        let sides = [
+            (&mut self.left_parser, &self.left_input, self.left_len),
+            (&mut self.right_parser, &self.right_input, self.right_len),
+        ];
+        let third = self.third.as_mut().map(|t| (&mut t.parser, &t.input, t.len));
+        for (parser, input, len) in sides.into_iter().chain(third) {
+            if parser.records_paths() { continue; }
+            let bytes = unsafe { core::slice::from_raw_parts(input.as_ptr(), len as usize) };
+            let index = index.get_or_insert_with(|| crate::index_pool::checkout(group));
+            parser.clear();
+            parser.set_record_paths(true);
+            index.build(bytes);
+            // Same bytes, same outcome as the original commit.
+            let _ = parser.parse_with_index(bytes, index);
+            self.phases.parses += 1;
+        }
+    }
+
+    /// Sort entries per the entry order. Path keys are rendered once per
+    /// entry; ties fall back to engine order (the sort is stable).
+    fn order_entries(&self, diffs: &mut [DiffEntry]) {
+        let op_rank = |op: DiffOp| match self.output.entry_order {
+            EntryOrder::OpThenPath => op as u8,
+            _ => 0,
+        };
+        match self.output.entry_order {
+            EntryOrder::DocumentOrder => {}
+            EntryOrder::PathLexicographic | EntryOrder::OpThenPath => {
+                diffs.sort_by_cached_key(|d| (op_rank(d.op), self.render_path(d)));
+            }
+        }
+    }
+
+    /// JSON Pointer bytes for an entry's path, from whichever side has it.
+    fn render_path(&self, d: &DiffEntry) -> Vec<u8> { self.render_path_id(d.op, d.path_id) }
+
+    fn render_path_id(&self, op: DiffOp, path_id: PathId) -> Vec<u8> {
+        let primary = if op == DiffOp::Removed { &self.left_parser } else { &self.right_parser };
+        // A path only the canary added is in neither.
+        let canary = || self.third.as_ref()?.parser.paths().render(path_id);
+        primary.paths().render(path_id).or_else(canary).unwrap_or_default()
+    }
+
+    pub fn clear(&mut self) {
+        self.reset_result();
+        self.left_retained = false;
+        self.left_input.detach();
+        self.right_input.detach();
+        self.left_parser.clear();
+        self.right_parser.clear();
+        self.state = EngineState::Created;
+        self.left_len = 0;
+        self.right_len = 0;
+        self.left_pushed = 0;
+        self.right_pushed = 0;
+        self.reserved = None;
+        self.suppressions.clear();
+        self.peaks = MemoryReport::default();
+        self.stats = EngineStats::default();
+        self.output = OutputOptions::from_config(&self.config);
+        let record = self.records_paths();
+        self.left_parser.set_record_paths(record);
+        self.right_parser.set_record_paths(record);
+        self.left_scope.clear();
+        self.right_scope.clear();
+        if let Some(d) = self.digests.as_mut() { *d = [digest(&[]); 2]; }
+        self.left_parser.set_scope(b"");
+        self.right_parser.set_scope(b"");
+        self.right_parser.set_restriction(None);
+        if let Some(third) = self.third.as_mut() {
+            third.parser.clear();
+            third.parser.set_record_paths(record);
+            third.parser.set_scope(b"");
+            third.parser.set_restriction(None);
+            third.committed = false;
+            third.len = 0;
+        }
+        let _ = self.settle_group();
+    }
+
+    /// Give memory back between uses (`trim_engine`). The bytes released
+    /// are reported by `arena_stats`. Levels:
+    ///
+    /// - 0: shrink every buffer to what it holds now. A sealed result
+    ///   stays where the host read it, so the arena waits for `clear`.
+    /// - 1: also drop what is rebuilt on demand: the span index, the
+    ///   effective-config JSON, the left state snapshot, the last entry
+    ///   path and resolved symbol and the symbol table (pointers to any of them go stale).
+    /// - 2: back to a fresh engine: `clear`, then every buffer as `new`
+    ///   makes it. The config, the input buffers (their addresses are the
+    ///   host's), group membership and the audit log remain.
+    ///
+    /// Wasm linear memory never shrinks; what is released goes back to
+    /// the allocator for the next growth, here or in another engine.
+    pub fn trim(&mut self, level: u32) -> Status {
+        if level > 2 { return self.fail(EngineError::InvalidTrimLevel(level), Status::Error); }
+        let before = self.held_bytes();
+        if level == 2 {
+            self.clear();
+            let record = self.records_paths();
+            self.left_parser = new_parser(&self.config, record);
+            self.right_parser = new_parser(&self.config, record);
+            if let Some(third) = self.third.as_mut() { third.parser = new_parser(&self.config, record); }
+            self.arena = ResultArena::new(self.config.max_memory_bytes);
+            self.suppressions = FxHashSet::default();
+            self.transformed = FxHashMap::default();
+            self.transform_scratch = Vec::new();
+            self.left_scope = Vec::new();
+            self.right_scope = Vec::new();
+        } else {
+            for parser in [&mut self.left_parser, &mut self.right_parser] { parser.shrink_to_fit(); }
+            if let Some(third) = self.third.as_mut() { third.parser.shrink_to_fit(); }
+            if self.state.is_open() { self.arena.shrink_to_fit(); }
+            self.suppressions.shrink_to_fit();
+            self.transformed.shrink_to_fit();
+            self.transform_scratch.shrink_to_fit();
+            self.left_scope.shrink_to_fit();
+            self.right_scope.shrink_to_fit();
+        }
+        if level >= 1 {
+            self.span_index = Vec::new();
+            self.config_json = Vec::new();
+            self.left_state = Vec::new();
+            self.entry_path = Vec::new();
+            self.entry_path_of = None;
+            self.symbol_buffer = Vec::new();
+            self.symbol_of = None;
+            self.symbol_table = None;
+        } else {
+            self.span_index.shrink_to_fit();
+            self.config_json.shrink_to_fit();
+            self.left_state.shrink_to_fit();
+            self.entry_path.shrink_to_fit();
+            self.symbol_buffer.shrink_to_fit();
+            if let Some(table) = self.symbol_table.as_mut() { table.shrink_to_fit(); }
+        }
+        self.trimmed_bytes = u32::try_from(before.saturating_sub(self.held_bytes())).unwrap_or(u32::MAX);
+        Status::Ok
+    }
+
+    /// Heap `trim` can give back: everything but the input buffers.
+    fn held_bytes(&self) -> usize {
+        let parsers = [Some(&self.left_parser), Some(&self.right_parser), self.third.as_ref().map(|t| &t.parser)];
+        parsers.into_iter().flatten().map(CompactParser::capacity_bytes).sum::<usize>()
+            + self.arena.capacity_bytes()
+            + self.arena.channel_bytes()
+            + self.span_index.capacity()
+            + self.config_json.capacity()
+            + self.left_state.capacity()
+            + self.entry_path.capacity()
+            + self.symbol_buffer.capacity()
+            + self.symbol_table.as_ref().map_or(0, Vec::capacity)
+            + self.left_scope.capacity()
+            + self.right_scope.capacity()
+            + self.suppressions.capacity() * core::mem::size_of::<SuppressionKey>()
+            + self.transformed.capacity() * core::mem::size_of::<(u64, (u32, bool))>()
+            + self.transform_scratch.capacity()
+    }
+
+    /// Bytes accounted to the group: the fixed reservation (input buffers
+    /// and the arena limit) plus both sides' token memory and, during a
+    /// commit, the checked-out index.
+    fn footprint(&self) -> u64 {
+        let third = self.third.as_ref().map_or(0, |t| t.input.capacity() as u64 + token_bytes(&t.parser) as u64);
+        let tokens = token_bytes(&self.left_parser) as u64 + token_bytes(&self.right_parser) as u64;
+        let index = self.index.as_deref().map_or(0, index_bytes) as u64;
+        let reserved = self.left_input.capacity() as u64 + self.right_input.capacity() as u64 + third;
+        reserved + self.config.max_memory_bytes as u64 + tokens + index
+    }
+
+    /// Return the commit's index to the pool and its bytes to the group.
+    fn release_index(&mut self) {
+        if self.index.take().is_some() {
+            let _ = self.settle_group();
+        }
+    }
+
+    /// Bring the group's charge in line with `footprint`. Growth that does
+    /// not fit is refused and leaves the charge unchanged.
+    fn settle_group(&mut self) -> Result<(), Status> {
+        let Some(group) = self.group else { return Ok(()) };
+        let now = self.footprint();
+        if now > self.group_bytes {
+            if !crate::group::grow(group, now - self.group_bytes) {
+                self.error.set(&EngineError::GroupQuotaExceeded);
+                return Err(Status::GroupQuotaExceeded);
+            }
+        } else {
+            crate::group::shrink(group, self.group_bytes - now);
+        }
+        self.group_bytes = now;
+        Ok(())
+    }
+
+    /// Index the finalized entries by right-document span; see
+    /// `span_index.rs` for the layout and the Removed anchoring rule.
+    pub fn build_span_index(&mut self) -> Status {
+        if let Err(e) = self.state.next(Op::ReadResult) { return self.refuse(e); }
+        self.span_index = crate::span_index::build(&self.arena, &self.left_parser, &self.right_parser);
+        Status::Ok
+    }
+
+    pub fn span_index_ptr(&self) -> *const u8 { self.span_index.as_ptr() }
+    pub fn span_index_len(&self) -> u32 { self.span_index.len() as u32 }
+
+    pub fn phase_counters(&self) -> PhaseCounters { self.phases }
+
+    /// Render the effective config (see `config_json.rs`) into the
+    /// scratch buffer: creation config with output overrides applied,
+    /// plus values derived from it under `derived`.
+    pub fn effective_config_json(&mut self) -> *const u8 {
+        let OutputOptions {
+            max_entry_bytes,
+            oversize_policy,
+            entry_order,
+            coverage_map,
+            value_hashes,
+            top_k,
+            significance,
+            shape_only,
+            numeric_deltas,
+            rendered_paths,
+            normalize_numbers,
+            normalize_strings,
+            ignore_case,
+            entry_sequence,
+            self_contained,
+            split_by_op,
+        } = self.output;
+        let effective = EngineConfig {
+            max_entry_bytes,
+            oversize_policy,
+            entry_order,
+            coverage_map,
+            value_hashes,
+            top_k,
+            significance,
+            shape_only,
+            numeric_deltas,
+            rendered_paths,
+            normalize_numbers,
+            normalize_strings,
+            ignore_case,
+            entry_sequence,
+            self_contained,
+            split_by_op,
+            ..self.config.clone()
+        };
+        let derived = format!(
+            r#"{{"input_capacity_per_side":{},"paranoid":{}}}"#,
+            self.left_input.capacity(),
+            cfg!(feature = "paranoid"),
+        );
+        self.config_json = effective.to_json_with(alloc::vec![("derived", derived)]).into_bytes();
+        self.config_json.as_ptr()
+    }
+
+    pub fn config_json_len(&self) -> u32 { self.config_json.len() as u32 }
+
+    /// The work counters of the current diff (`stats.rs`).
+    pub fn stats(&self) -> EngineStats { self.stats }
+
+    /// Write the work counters (`stats.rs`) to a buffer the engine owns
+    /// and return it; valid until the next call, `clear` aside.
+    pub fn stats_bytes(&mut self) -> *const u8 {
+        self.stats_buf = self.stats.to_bytes();
+        self.stats_buf.as_ptr()
+    }
+
+    /// Current progress word, `phase << 16 | permille` (see `progress.rs`).
+    pub fn progress(&self) -> u32 { self.progress.load() }
+
+    /// Left then right input digest; fails unless `input_digests` is set.
+    pub fn input_digests(&mut self) -> Result<[Digest; 2], Status> {
+        match self.digests {
+            Some(d) => Ok(d),
+            None => Err(self.fail(EngineError::InputDigestsDisabled, Status::Error)),
+        }
+    }
+
+    pub fn memory_report(&self) -> MemoryReport {
+        MemoryReport {
+            arena_limit_bytes: self.arena.max_size(),
+            arena_peak_bytes: self.arena.peak_len(),
+            arena_largest_entry_bytes: self.arena.largest_entry(),
+            arena_padding_bytes: self.arena.padding_bytes(),
+            ..self.peaks
+        }
+    }
+
+    /// Capacities held now, from the collections themselves.
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let bytes = |n: usize| u32::try_from(n).unwrap_or(u32::MAX);
+        let third = self.third.as_ref();
+        let parsers = [Some(&self.left_parser), Some(&self.right_parser), third.map(|t| &t.parser)];
+        MemoryUsage {
+            left_input_bytes: bytes(self.left_input.allocated_bytes()),
+            right_input_bytes: bytes(self.right_input.allocated_bytes()),
+            third_input_bytes: bytes(third.map_or(0, |t| t.input.allocated_bytes())),
+            left_token_bytes: bytes(self.left_parser.token_capacity_bytes()),
+            right_token_bytes: bytes(self.right_parser.token_capacity_bytes()),
+            third_token_bytes: bytes(third.map_or(0, |t| t.parser.token_capacity_bytes())),
+            index_bytes: bytes(self.index.as_deref().map_or(0, |i| i.masks.capacity() * 8)),
+            interner_bytes: bytes(parsers.into_iter().flatten().map(CompactParser::path_table_bytes).sum()),
+            arena_len: self.arena.len(),
+            arena_capacity_bytes: bytes(self.arena.capacity_bytes() + self.arena.channel_bytes()),
+        }
+    }
+
+    /// `memory_usage` written to a buffer the engine owns.
+    pub fn memory_usage_bytes(&mut self) -> *const u8 {
+        self.usage_buf = self.memory_usage().to_bytes();
+        self.usage_buf.as_ptr()
+    }
+
+    pub fn left_input_ptr(&mut self) -> *mut u8 { self.left_input.as_mut_ptr() }
+    pub fn right_input_ptr(&mut self) -> *mut u8 { self.right_input.as_mut_ptr() }
+    /// Null unless `three_way` is set.
+    pub fn third_input_ptr(&mut self) -> *mut u8 {
+        self.third.as_mut().map_or(core::ptr::null_mut(), |t| t.input.as_mut_ptr())
+    }
+
+    /// Result length in bytes; 0 until finalize succeeds.
+    pub fn result_len(&self) -> u32 { if self.finalized() { self.arena.len() } else { 0 } }
+    /// `op`'s channel of a `split_by_op` result. Engine-owned, so still
+    /// here after `finalize_into` handed the result itself off.
+    pub fn result_for(&self, op: u8) -> Option<&[u8]> {
+        if self.state != EngineState::Sealed { return None; }
+        self.arena.channel(op)
+    }
+    /// Entry `i`'s record; `None` before finalize or past the last entry.
+    fn result_entry(&self, i: u32) -> Option<&[u8]> {
+        if !self.finalized() { return None; }
+        self.arena.entry(i)
+    }
+
+    /// Entry `i`'s op (`DiffOp`).
+    pub fn entry_op(&self, i: u32) -> Option<u8> { self.result_entry(i).map(|e| e[0]) }
+
+    /// The value bytes of `side` of entry `i`, in that side's input;
+    /// `None` for an absent side (kind 0). A redacted side is empty.
+    pub fn entry_value(&self, side: Side, i: u32) -> Option<&[u8]> {
+        let e = self.result_entry(i)?;
+        let (kind, at, input, len) = match side {
+            Side::Left => (e[1], 16, &self.left_input, self.left_len),
+            Side::Right => (e[2], 24, &self.right_input, self.right_len),
+        };
+        if kind == ValueKind::None as u8 { return None; }
+        let word = |at: usize| u32::from_le_bytes([e[at], e[at + 1], e[at + 2], e[at + 3]]) as usize;
+        let bytes = unsafe { core::slice::from_raw_parts(input.as_ptr(), len as usize) };
+        bytes.get(word(at)..word(at).checked_add(word(at + 4))?)
+    }
+
+    /// Entry `i`'s JSON Pointer, rendered into a scratch buffer that holds
+    /// one path at a time. The first call on a result parsed without
+    /// recording paths re-parses the sides to record them.
+    pub fn entry_path(&mut self, i: u32) -> Option<&[u8]> {
+        let e = self.result_entry(i)?;
+        if self.entry_path_of != Some(i) {
+            let op = match e[0] {
+                op if op == DiffOp::Added as u8 => DiffOp::Added,
+                op if op == DiffOp::Removed as u8 => DiffOp::Removed,
+                _ => DiffOp::Modified,
+            };
+            let path_id = PathId(u64::from_le_bytes(e[8..16].try_into().unwrap()));
+            self.record_paths();
+            self.entry_path = self.render_path_id(op, path_id);
+            self.entry_path_of = Some(i);
+        }
+        Some(&self.entry_path)
+    }
+
+    /// `path_id`, as found in the result's entries, rendered dotted
+    /// (`PathTable::render_dotted`) into a scratch buffer that holds one
+    /// symbol at a time. `None` before finalize and for an id no side has;
+    /// the first call on a result parsed without recording paths
+    /// re-parses the sides, as `entry_path` does.
+    pub fn resolve_symbol(&mut self, path_id: u64) -> Option<&[u8]> {
+        if !self.finalized() { return None; }
+        if self.symbol_of != Some(path_id) {
+            self.record_paths();
+            let id = PathId(path_id);
+            let third = self.third.as_ref().map(|t| &t.parser);
+            let dotted = [&self.left_parser, &self.right_parser].into_iter().chain(third).find_map(|p| p.paths().render_dotted(id))?;
+            self.symbol_buffer = dotted;
+            self.symbol_of = Some(path_id);
+        }
+        Some(&self.symbol_buffer)
+    }
+
+    /// Every distinct path id in the result with its `resolve_symbol`
+    /// rendering, as `[u64 path id][u32 len][len bytes]` records (little
+    /// endian) in the order the ids first appear in the entries. Only the
+    /// paths the result holds are in it, not every path the sides parsed.
+    /// Built by the first call after finalize and kept until `clear`;
+    /// `None` before finalize. Records paths on demand, as `entry_path`
+    /// does.
+    pub fn batch_resolve_symbols(&mut self) -> Option<&[u8]> {
+        if !self.finalized() { return None; }
+        if self.symbol_table.is_none() {
+            self.record_paths();
+            let third = self.third.as_ref().map(|t| &t.parser);
+            let parsers: Vec<&CompactParser> = [&self.left_parser, &self.right_parser].into_iter().chain(third).collect();
+            let mut seen = FxHashSet::default();
+            let mut table = Vec::new();
+            for i in 0..self.arena.entry_count() {
+                let Some(e) = self.arena.entry(i) else { break };
+                let id = u64::from_le_bytes(e[8..16].try_into().unwrap());
+                if !seen.insert(id) { continue; }
+                let Some(dotted) = parsers.iter().find_map(|p| p.paths().render_dotted(PathId(id))) else { continue };
+                table.extend_from_slice(&id.to_le_bytes());
+                table.extend_from_slice(&(dotted.len() as u32).to_le_bytes());
+                table.extend_from_slice(&dotted);
+            }
+            self.symbol_table = Some(table);
+        }
+        self.symbol_table.as_deref()
+    }
+
+    /// A page of the result (`ResultArena::page`); `None` before finalize.
+    pub fn result_page(&self, offset: u32, max_len: u32) -> Option<&[u8]> {
+        if !self.finalized() { return None; }
+        self.arena.page(offset as usize, max_len as usize)
+    }
+    /// Format version of this engine's results, packed as
+    /// [`FORMAT_VERSION`]. Every engine writes the current one today; a
+    /// config that picks another output format would answer here.
+    pub fn format_version(&self) -> u32 { FORMAT_VERSION }
+    /// Entries in the result, as its header says; 0 until finalize succeeds.
+    pub fn entry_count(&self) -> u32 { if self.finalized() { self.arena.entry_count() } else { 0 } }
+    pub fn last_error_len(&self) -> u32 { self.error.len() }
+    pub fn last_error_ptr(&self) -> *const u8 { self.error.as_ptr() }
+    pub fn last_error_code(&self) -> u32 { self.error.code() }
+    pub fn clear_error(&mut self) { self.error.clear(); }
+}
+
+impl Drop for Engine {
+    fn drop(&mut self) {
+        if let Some(group) = self.group {
+            crate::group::leave(group, self.group_bytes);
+        }
+    }
+}
+
+/// Each object's path and span (`{` through `}`, as a fragment), in
+/// closing order.
+fn object_spans(parser: &CompactParser) -> Vec<(PathId, ValueSpan)> {
+    let mut open = Vec::new();
+    let mut out = Vec::new();
+    for t in parser.tokens() {
+        match t.event {
+            CompactEvent::StartObject => open.push(t.raw_offset),
+            CompactEvent::EndObject => {
+                let Some(start) = open.pop() else { continue };
+                out.push((t.path_id, (start, t.raw_end() - start, ValueKind::Fragment)));
+            }
+            _ => {}
+        }
+    }
+    out
+}
+
+/// Whether two sides parsed to the same tokens (count and digest), so
+/// their diff is empty.
+fn same_tokens(left: &CompactParser, right: &CompactParser) -> bool {
+    left.tokens().len() == right.tokens().len() && left.digest() == right.digest()
+}
+
+/// Bytes held by one side's token stream, saturating at `u32::MAX`.
+fn token_bytes(parser: &CompactParser) -> u32 {
+    u32::try_from(core::mem::size_of_val(parser.tokens())).unwrap_or(u32::MAX)
+}
+
+/// A parser set up the way `config` asks.
+fn new_parser(config: &EngineConfig, record_paths: bool) -> CompactParser {
+    let mut parser = CompactParser::new(config.max_object_keys, config.compute_mode);
+    parser.set_strict_escapes(config.strict_escapes);
+    parser.set_record_paths(record_paths);
+    parser
+}
+
+/// Bytes held by a structural bitmask, saturating at `u32::MAX`.
+fn index_bytes(index: &StructuralIndex) -> u32 {
+    u32::try_from(core::mem::size_of_val(index.masks.as_slice())).unwrap_or(u32::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn small_config() -> EngineConfig {
+        EngineConfig { max_input_size: 1 << 20, ..EngineConfig::default() }
+    }
+
+    fn run(config: EngineConfig, left: &[u8], right: &[u8]) -> (Engine, Vec<u8>) {
+        let mut e = Engine::new(config, 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec();
+        (e, out)
+    }
+
+    fn entry_count(result: &[u8]) -> u32 {
+        u32::from_le_bytes(result[4..8].try_into().unwrap())
+    }
+
+    fn error_text(e: &Engine) -> String {
+        let bytes = unsafe { std::slice::from_raw_parts(e.last_error_ptr(), e.last_error_len() as usize) };
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    fn big_pair() -> (Vec<u8>, Vec<u8>) {
+        let left = format!(r#"{{"big":"{}","a":1}}"#, "x".repeat(1000));
+        let right = format!(r#"{{"big":"{}","a":2}}"#, "y".repeat(1000));
+        (left.into_bytes(), right.into_bytes())
+    }
+
+    #[test]
+    fn oversized_entry_skipped_later_entries_kept() {
+        let (l, r) = big_pair();
+        let config = EngineConfig { max_entry_bytes: 200, ..small_config() };
+        let (e, out) = run(config, &l, &r);
+        assert_eq!(entry_count(&out), 1);
+        // The surviving entry is `a` (right value `2`, one byte long).
+        assert_eq!(u32::from_le_bytes(out[16 + 28..16 + 32].try_into().unwrap()), 1);
+        assert_eq!(error_text(&e), "1 entries exceeding max_entry_bytes skipped");
+    }
+
+    #[test]
+    fn oversized_entry_truncated_and_flagged() {
+        let (l, r) = big_pair();
+        let config = EngineConfig {
+            max_entry_bytes: 200,
+            oversize_policy: OversizePolicy::Truncate,
+            ..small_config()
+        };
+        let (_, out) = run(config, &l, &r);
+        assert_eq!(entry_count(&out), 2);
+        let first = &out[16..48];
+        assert_eq!(first[3], ENTRY_FLAG_TRUNCATED);
+        let ll = u32::from_le_bytes(first[20..24].try_into().unwrap());
+        let rl = u32::from_le_bytes(first[28..32].try_into().unwrap());
+        assert!(32 + ll + rl <= 200);
+        assert_eq!(out[48 + 3], 0);
+    }
+
+    fn commit_left_bytes(e: &mut Engine, data: &[u8]) -> Status {
+        unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), e.left_input_ptr(), data.len()) };
+        e.commit_left(data.len() as u32)
+    }
+
+    #[test]
+    fn double_commit_rejected_first_parse_kept() {
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":1}"#), Status::Ok);
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":1}"#), Status::AlreadyCommitted);
+        assert_eq!(e.left_parser.tokens().len(), 3);
+    }
+
+    #[test]
+    fn recommit_replaces_matches_single_commit() {
+        let config = EngineConfig { recommit_replaces: true, ..small_config() };
+        let (_, single) = run(config.clone(), br#"{"a":2,"b":3}"#, br#"{"a":1}"#);
+
+        let mut e = Engine::new(config, 1).unwrap();
+        assert_eq!(commit_left_bytes(&mut e, br#"{"z":[1,2,3]}"#), Status::Ok);
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":2,"b":3}"#), Status::Ok);
+        let right = br#"{"a":1}"#;
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let replaced = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) };
+        assert_eq!(replaced, &single[..]);
+    }
+
+    /// Ranges of the coverage section, which must be the only section.
+    fn coverage(out: &[u8]) -> Vec<(u32, u32)> {
+        let reader = crate::reader::ResultReader::parse(out).unwrap();
+        reader.coverage().unwrap().into_iter().map(|[a, b]| (a, b)).collect()
+    }
+
+    #[test]
+    fn coverage_map_merges_removed_ranges() {
+        let config = EngineConfig { coverage_map: true, ..small_config() };
+        let text = |doc: &[u8], (a, b): (u32, u32)| String::from_utf8(doc[a as usize..b as usize].to_vec()).unwrap();
+
+        // Scattered leaves stay separate; neighbours in an array merge only
+        // where their ranges touch.
+        let left = br#"{"a":1,"b":"two","c":3,"d":[7,8,9]}"#;
+        let (_, out) = run(config.clone(), left, br#"{"c":3,"d":[7]}"#);
+        let ranges: Vec<_> = coverage(&out).into_iter().map(|r| text(left, r)).collect();
+        assert_eq!(ranges, ["1", "\"two\"", "8", "9"]);
+
+        // A vanished subtree is one range, bracket to bracket.
+        let left = br#"{"keep":1,"gone":{"x":[1,2],"y":{"z":null}}}"#;
+        let (_, out) = run(config.clone(), left, br#"{"keep":1}"#);
+        let ranges: Vec<_> = coverage(&out).into_iter().map(|r| text(left, r)).collect();
+        assert_eq!(ranges, [r#"{"x":[1,2],"y":{"z":null}}"#]);
+
+        let (_, out) = run(config.clone(), br#"{"a":1}"#, br#"{"a":2,"b":3}"#);
+        assert_eq!(coverage(&out), []);
+        let (_, out) = run(small_config(), br#"{"a":1}"#, br#"{}"#);
+        assert_eq!(out.len(), 16 + 32, "no section unless enabled");
+    }
+
+    #[test]
+    fn memory_report_tracks_peaks_and_clears() {
+        let (mut e, _) = run(small_config(), br#"{"a":1}"#, br#"{"a":22}"#);
+        let token = std::mem::size_of::<crate::parser::CompactToken>() as u32;
+        let r = e.memory_report();
+        assert_eq!(r.arena_limit_bytes, 32 * 1024 * 1024);
+        assert_eq!(r.arena_peak_bytes, 16 + 32);
+        assert_eq!(r.arena_largest_entry_bytes, 32 + 1 + 2);
+        assert_eq!(r.arena_padding_bytes, 4);
+        assert_eq!(r.left_peak_token_bytes, 3 * token);
+        assert_eq!(r.right_peak_token_bytes, 3 * token);
+        assert_eq!(r.left_peak_index_bytes, 8);
+        assert_eq!(r.right_peak_index_bytes, 8);
+
+        e.clear();
+        let cleared = MemoryReport {
+            arena_limit_bytes: 32 * 1024 * 1024,
+            arena_peak_bytes: 16,
+            ..MemoryReport::default()
+        };
+        assert_eq!(e.memory_report(), cleared);
+    }
+
+    #[test]
+    fn three_way_peaks_and_stats_count_the_canary() {
+        let token = std::mem::size_of::<crate::parser::CompactToken>() as u32;
+        let canary = format!("[{}]", (0..100).map(|i| i.to_string()).collect::<Vec<_>>().join(","));
+        let (e, _) = run_three_way(small_config(), br#"{"a":1}"#, br#"{"a":22}"#, canary.as_bytes());
+        let third = e.stats().third;
+        assert_eq!((third.bytes_indexed, third.tokens, third.values), (canary.len() as u32, 102, 100));
+        assert_eq!(e.stats().right.tokens, 3);
+        let r = e.memory_report();
+        assert_eq!(r.right_peak_token_bytes, 102 * token);
+        assert_eq!(r.right_peak_index_bytes, canary.len().div_ceil(64) as u32 * 8);
+        assert_eq!((r.left_peak_token_bytes, r.left_peak_index_bytes), (3 * token, 8));
+    }
+
+    #[test]
+    fn strict_escapes_fail_commit_with_offset() {
+        let config = EngineConfig { strict_escapes: true, ..small_config() };
+        let mut e = Engine::new(config, 1).unwrap();
+        assert_eq!(commit_left_bytes(&mut e, br#"{"ok":"\uD83D\uDE00","bad":"x\uD800"}"#), Status::Error);
+        let msg = error_text(&e);
+        assert!(msg.starts_with("parse error: unpaired surrogate at byte 29 "), "{msg}");
+    }
+
+    #[test]
+    fn strict_escapes_checked_in_keys() {
+        let config = EngineConfig { strict_escapes: true, ..small_config() };
+        let mut e = Engine::new(config, 1).unwrap();
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":{"k\u12G4":1}}"#), Status::Error);
+        assert!(error_text(&e).contains("non-hex digit in \\u escape at byte 8 "));
+    }
+
+    #[test]
+    fn lenient_escapes_compare_as_replacement_char() {
+        let (left, right) = (br#"{"s":"\uD800"}"#, br#"{"s":"\uDBFF"}"#);
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        // The warning is the commit's; finalize starts a fresh error.
+        assert_eq!(error_text(&e), "1 invalid string escapes replaced with U+FFFD");
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) };
+        assert_eq!(entry_count(out), 0);
+        assert_eq!(e.last_error_len(), 0);
+    }
+
+    fn entry_paths(out: &[u8]) -> Vec<u64> {
+        (0..entry_count(out) as usize)
+            .map(|i| u64::from_le_bytes(out[16 + i * 32 + 8..16 + i * 32 + 16].try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn entry_orders_follow_documented_sequence() {
+        use crate::path::{fold_index_hash, fold_segment_hash, ROOT_PATH_ID};
+        let key = |p, k: &str| fold_segment_hash(p, k.as_bytes());
+        let a = key(ROOT_PATH_ID, "a");
+        let (ax, ay, az) = (key(a, "x").0, key(a, "y").0, key(a, "z").0);
+        let b = key(ROOT_PATH_ID, "b").0;
+        let c1 = fold_index_hash(key(ROOT_PATH_ID, "c"), 1).0;
+
+        let left = br#"{"b":1,"a":{"y":1,"x":1},"c":[1,2]}"#;
+        let right = br#"{"a":{"x":2,"z":1},"b":2,"c":[1]}"#;
+        let expect = [
+            (EntryOrder::DocumentOrder, [ax, az, b, ay, c1]),
+            (EntryOrder::PathLexicographic, [ax, ay, az, b, c1]),
+            (EntryOrder::OpThenPath, [az, ay, c1, ax, b]),
+        ];
+        for (entry_order, paths) in expect {
+            for _ in 0..2 {
+                let config = EngineConfig { entry_order, ..small_config() };
+                let (_, out) = run(config, left, right);
+                assert_eq!(entry_paths(&out), paths, "{entry_order:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn output_options_reorder_after_commit() {
+        use crate::config::ConfigField;
+        let left = br#"{"b":1,"a":{"y":1,"x":1},"c":[1,2]}"#;
+        let right = br#"{"a":{"x":2,"z":1},"b":2,"c":[1]}"#;
+        let (_, lexicographic) = run(EngineConfig { entry_order: EntryOrder::PathLexicographic, ..small_config() }, left, right);
+
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(commit_left_bytes(&mut e, left), Status::Ok);
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let order = [ConfigField::EntryOrder as u8, EntryOrder::PathLexicographic as u8, 0, 0, 0];
+        assert_eq!(e.set_output_options(&order), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) };
+        assert_eq!(entry_paths(out), entry_paths(&lexicographic));
+        assert_eq!(e.set_output_options(&order), Status::EngineSealed);
+
+        e.clear();
+        assert_eq!(e.output, OutputOptions::from_config(&small_config()));
+    }
+
+    #[test]
+    fn output_options_reject_parse_fields_by_name() {
+        use crate::config::ConfigField;
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":1}"#), Status::Ok);
+        let records = [
+            ConfigField::MaxEntryBytes as u8, 64, 0, 0, 0,
+            ConfigField::MaxObjectKeys as u8, 10, 0, 0, 0,
+        ];
+        assert_eq!(e.set_output_options(&records), Status::Error);
+        assert_eq!(
+            error_text(&e),
+            "invalid config: max_object_keys is fixed at engine creation (affects parsing or limits)"
+        );
+        // All-or-nothing: the valid record before it was not applied.
+        assert_eq!(e.output.max_entry_bytes, 0);
+        assert_eq!(e.set_output_options(&[ConfigField::OversizePolicy as u8, 9, 0, 0, 0]), Status::Error);
+        assert_eq!(error_text(&e), "invalid config: invalid value for oversize_policy");
+        assert_eq!(e.set_output_options(&[ConfigField::MaxEntryBytes as u8]), Status::Error);
+    }
+
+    fn span_records(e: &Engine) -> Vec<(u32, u32, u32)> {
+        let bytes = unsafe { std::slice::from_raw_parts(e.span_index_ptr(), e.span_index_len() as usize) };
+        bytes.chunks_exact(12)
+            .map(|r| {
+                let f = |i: usize| u32::from_le_bytes(r[i..i + 4].try_into().unwrap());
+                (f(0), f(4), f(8))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn span_index_sorted_with_removed_anchored() {
+        let left = br#"{"a":1,"gone":2,"b":{"x":1,"y":2},"c":3}"#;
+        let right = br#"{"c":4,"a":1,"b":{"x":9},"new":true}"#;
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.build_span_index(), Status::Error);
+        assert_eq!(commit_left_bytes(&mut e, left), Status::Ok);
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec();
+        assert_eq!(e.build_span_index(), Status::Ok);
+
+        // Engine order: c, b/x, new (right order), then gone, b/y (left).
+        let ops: Vec<u8> = (0..entry_count(&out) as usize).map(|i| out[16 + i * 32]).collect();
+        assert_eq!(ops, [DiffOp::Modified as u8, DiffOp::Modified as u8, DiffOp::Added as u8,
+                         DiffOp::Removed as u8, DiffOp::Removed as u8]);
+        let spans = span_records(&e);
+        assert!(spans.windows(2).all(|w| w[0] <= w[1]));
+        // `gone` follows `a` (ends at 12 on the right); `b/y` follows `b/x`
+        // (ends at 23).
+        assert_eq!(spans, [(5, 1, 0), (12, 0, 3), (22, 1, 1), (23, 0, 4), (31, 4, 2)]);
+
+        // Lookup: the last record starting at or before an offset.
+        let at = |off: u32| spans[spans.partition_point(|s| s.0 <= off) - 1].2;
+        assert_eq!(at(5), 0);
+        assert_eq!(at(22), 1);
+        assert_eq!(at(33), 2);
+
+        e.clear();
+        assert_eq!(e.span_index_len(), 0);
+    }
+
+    #[test]
+    fn left_scope_aligns_wrapped_with_unwrapped() {
+        use crate::path::{fold_index_hash, fold_segment_hash, ROOT_PATH_ID};
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.set_scope(Side::Left, b"/users"), Status::Ok);
+        let left = br#"{"meta":1,"users":[{"n":"a"},{"n":"b"}],"z":0}"#;
+        let right = br#"[{"n":"a"},{"n":"c"}]"#;
+        assert_eq!(commit_left_bytes(&mut e, left), Status::Ok);
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) };
+        let n1 = fold_segment_hash(fold_index_hash(ROOT_PATH_ID, 1), b"n").0;
+        assert_eq!(entry_paths(out), vec![n1]);
+        assert_eq!(out[16], DiffOp::Modified as u8);
+    }
+
+    #[test]
+    fn empty_keys_are_paths_of_their_own() {
+        use crate::path::{fold_segment_hash, ROOT_PATH_ID};
+        let config = EngineConfig { rendered_paths: true, ..small_config() };
+        let diff = |left: &[u8], right: &[u8]| {
+            let (_, out) = run(config.clone(), left, right);
+            let reader = ResultReader::parse(&out).unwrap();
+            let ops: Vec<(u8, u64)> = reader.entries().map(|e| (e.op, e.path_id)).collect();
+            (ops, reader.paths().unwrap())
+        };
+        let empty = fold_segment_hash(ROOT_PATH_ID, b"");
+        let a = fold_segment_hash(ROOT_PATH_ID, b"a");
+        let a_empty = fold_segment_hash(a, b"");
+        let (added, removed, modified) = (DiffOp::Added as u8, DiffOp::Removed as u8, DiffOp::Modified as u8);
+        assert!(empty != ROOT_PATH_ID && a_empty != a);
+
+        // At the root the empty key renders as `/`, the root as `""`.
+        assert_eq!(diff(br#"{"":1}"#, br#"{"":2}"#), (vec![(modified, empty.0)], vec![b"/".to_vec()]));
+        assert_eq!(diff(br#"{"":1,"x":[]}"#, br#"{"x":[],"":1}"#).0, []);
+        assert_eq!(diff(br#"{"a":{"":1,"b":2}}"#, br#"{"a":{"":3,"b":2}}"#), (vec![(modified, a_empty.0)], vec![b"/a/".to_vec()]));
+        // `/a` the value and `/a/` its member no longer share an id.
+        let expected = (vec![(added, a.0), (removed, a_empty.0)], vec![b"/a".to_vec(), b"/a/".to_vec()]);
+        assert_eq!(diff(br#"{"a":{"":1}}"#, br#"{"a":1}"#), expected);
+
+        // Scopes address it as RFC 6901 does.
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.set_scope(Side::Left, b"/a/"), Status::Ok);
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":{"":{"k":1},"k":2}}"#), Status::Ok);
+        let right = br#"{"k":3}"#;
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) };
+        assert_eq!(entry_paths(out), vec![fold_segment_hash(ROOT_PATH_ID, b"k").0]);
+    }
+
+    #[test]
+    fn nested_and_joined_keys_are_different_paths() {
+        let config = EngineConfig { rendered_paths: true, ..small_config() };
+        let (_, out) = run(config, br#"{"a":{"b":1}}"#, br#"{"ab":1}"#);
+        let reader = ResultReader::parse(&out).unwrap();
+        let ops: Vec<u8> = reader.entries().map(|e| e.op).collect();
+        assert_eq!(ops, [DiffOp::Added as u8, DiffOp::Removed as u8]);
+        assert_eq!(reader.paths().unwrap(), [b"/ab".to_vec(), b"/a/b".to_vec()]);
+    }
+
+    #[test]
+    fn missing_scope_errors_cleanly() {
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.set_scope(Side::Right, b"/nope/0"), Status::Ok);
+        let doc = br#"{"a":[1]}"#;
+        unsafe { std::ptr::copy_nonoverlapping(doc.as_ptr(), e.right_input_ptr(), doc.len()) };
+        assert_eq!(e.commit_right(doc.len() as u32), Status::Error);
+        assert_eq!(error_text(&e), "invalid config: scope /nope/0 not found in right document");
+        assert_eq!(e.set_scope(Side::Left, b"bad"), Status::Error);
+    }
+
+    #[test]
+    fn scalar_scope_target_becomes_root_value() {
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.set_scope(Side::Left, b"/a/1"), Status::Ok);
+        assert_eq!(commit_left_bytes(&mut e, br#"{"a":[5,6]}"#), Status::Ok);
+        let values: Vec<_> = e.left_parser.tokens().iter()
+            .filter(|t| t.event == crate::parser::CompactEvent::Value)
+            .map(|t| (t.path_id, t.raw_offset))
+            .collect();
+        assert_eq!(values, vec![(crate::path::ROOT_PATH_ID, 8)]);
+    }
+
+    #[test]
+    fn full_arena_still_terminates() {
+        let config = EngineConfig { max_memory_bytes: 16 + 32, ..small_config() };
+        let (e, out) = run(config, br#"{"a":1,"b":1,"c":1}"#, br#"{"a":2,"b":2,"c":2}"#);
+        assert_eq!(entry_count(&out), 1);
+        assert_eq!(error_text(&e), "result truncated at max_memory_bytes (48): 2 entries dropped");
+    }
+
+    fn header_flags(result: &[u8]) -> u32 {
+        u32::from_le_bytes(result[12..16].try_into().unwrap())
+    }
+
+    const MINIFIED: &[u8] = br#"{"users":[{"id":1,"tags":["a","b"]},{"id":2,"tags":[]}],"ok":true}"#;
+    const PRETTY: &[u8] = br#"{
+  "users": [
+    { "id": 1, "tags": [ "a", "b" ] },
+    { "id": 2, "tags": [] }
+  ],
+  "ok": true
+}"#;
+
+    #[test]
+    fn whitespace_only_difference_short_circuits() {
+        let (e, out) = run(small_config(), MINIFIED, PRETTY);
+        assert_eq!(entry_count(&out), 0);
+        assert_eq!(header_flags(&out), HEADER_FLAG_IDENTICAL);
+        assert_eq!(e.phase_counters(), PhaseCounters { digest_matches: 1, joins: 0, parses: 2, hash_passes: 0, disjoint_joins: 0 });
+    }
+
+    #[test]
+    fn one_value_change_defeats_the_digest() {
+        let changed = String::from_utf8(PRETTY.to_vec()).unwrap().replace("\"id\": 2", "\"id\": 3");
+        let (e, out) = run(small_config(), MINIFIED, changed.as_bytes());
+        assert_eq!(entry_count(&out), 1);
+        assert_eq!(header_flags(&out), 0);
+        assert_eq!(e.phase_counters(), PhaseCounters { digest_matches: 0, joins: 1, parses: 2, hash_passes: 0, disjoint_joins: 0 });
+    }
+
+    #[test]
+    fn documents_sharing_no_path_join_without_a_map() {
+        let (e, out) = run(small_config(), br#"{"a":1,"b":[true,null]}"#, br#"{"c":1,"d":{"e":"f"}}"#);
+        assert_eq!(entry_count(&out), 5);
+        assert_eq!(e.phase_counters().disjoint_joins, 1);
+        let (e, _) = run(small_config(), br#"{"a":1,"b":2}"#, br#"{"a":2,"c":3}"#);
+        assert_eq!(e.phase_counters().disjoint_joins, 0);
+    }
+
+    #[test]
+    fn input_digests_recorded_in_result() {
+        let config = EngineConfig { input_digests: true, coverage_map: true, ..small_config() };
+        let left = br#"{"a":1,"b":[true,null]}"#;
+        let right = PRETTY;
+        let (mut e, out) = run(config.clone(), left, right);
+        let want = [digest(left), digest(right)];
+        assert_eq!(e.input_digests(), Ok(want));
+
+        let reader = crate::reader::ResultReader::parse(&out).unwrap();
+        assert_eq!(reader.input_digests(), Some(want));
+        assert_eq!(reader.unknown_sections().count(), 0);
+
+        // Any single byte change moves that side's digest only.
+        let edited = br#"{"a":2,"b":[true,null]}"#;
+        let (mut e2, _) = run(config, edited, right);
+        let [l2, r2] = e2.input_digests().unwrap();
+        assert_ne!(l2, want[0]);
+        assert_eq!(r2, want[1]);
+
+        // The result is self-contained; clearing the engine resets it.
+        e.clear();
+        assert_eq!(e.input_digests(), Ok([digest(&[]); 2]));
+    }
+
+    fn config_json(e: &mut Engine) -> String {
+        let ptr = e.effective_config_json();
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, e.config_json_len() as usize) };
+        String::from_utf8(bytes.to_vec()).unwrap()
+    }
+
+    #[test]
+    fn effective_config_shows_derived_and_overridden_values() {
+        let config = EngineConfig { max_input_size: 1001, ..small_config() };
+        let mut e = Engine::new(config.clone(), 1).unwrap();
+        let json = config_json(&mut e);
+        // The odd input limit is split down to 500 bytes per side.
+        assert!(json.contains(r#""derived":{"input_capacity_per_side":500,"#), "{}", json);
+        assert!(json.contains(r#""max_input_size":1001,"#));
+        assert_eq!(EngineConfig::from_json(&json).unwrap().to_bytes(), config.to_bytes());
+
+        // Output overrides are part of the effective config; clear drops them.
+        let record = [crate::config::ConfigField::EntryOrder as u8, 2, 0, 0, 0];
+        assert_eq!(e.set_output_options(&record), Status::Ok);
+        assert!(config_json(&mut e).contains(r#""entry_order":"op_then_path""#));
+        e.clear();
+        assert!(config_json(&mut e).contains(r#""entry_order":"document_order""#));
+    }
+
+    fn assert_rises_to(trace: &[u32], last: u32) {
+        assert!(trace.windows(2).all(|w| w[0] <= w[1]), "not monotonic: {:?}", trace);
+        assert_eq!(trace.last(), Some(&last));
+    }
+
+    #[test]
+    fn progress_rises_through_each_call_and_resets() {
+        use crate::progress::word;
+        let doc = |bump: u32| {
+            let fields: Vec<String> = (0..20_000u32).map(|i| format!(r#""k{}":{}"#, i, i + bump)).collect();
+            format!("{{{}}}", fields.join(",")).into_bytes()
+        };
+        let (left, right) = (doc(0), doc(1));
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.progress(), 0);
+
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        let trace = e.progress.take_trace();
+        assert_rises_to(&trace, word(Phase::Parse, 1000));
+        // Intermediate strides are reported, not just phase ends.
+        assert!(trace.iter().any(|&w| w > word(Phase::Index, 0) && w < word(Phase::Index, 1000)));
+        assert!(trace.iter().any(|&w| w > word(Phase::Parse, 0) && w < word(Phase::Parse, 1000)));
+
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        assert_rises_to(&e.progress.take_trace(), word(Phase::Parse, 1000));
+
+        e.finalize().unwrap();
+        let trace = e.progress.take_trace();
+        assert_rises_to(&trace, word(Phase::Write, 1000));
+        assert!(trace.iter().any(|&w| w > word(Phase::Diff, 0) && w < word(Phase::Diff, 1000)));
+        assert!(trace.iter().any(|&w| w > word(Phase::Write, 0) && w < word(Phase::Write, 1000)));
+        assert_eq!(e.progress(), word(Phase::Write, 1000));
+
+        e.clear();
+        assert_eq!(e.progress(), 0);
+    }
+
+    #[test]
+    fn input_digests_off_by_default() {
+        let (mut e, out) = run(small_config(), br#"{"a":1}"#, br#"{"a":2}"#);
+        assert_eq!(out.len(), 16 + 32);
+        assert_eq!(e.input_digests(), Err(Status::Error));
+        assert_eq!(e.last_error_code(), crate::error::ErrorCode::FeatureDisabled as u32);
+    }
+
+    /// `run`, with `suppressions` loaded before the commits.
+    fn run_suppressed(config: EngineConfig, suppressions: &[u8], left: &[u8], right: &[u8]) -> (Engine, Vec<u8>) {
+        let mut e = Engine::new(config, 1).unwrap();
+        assert_eq!(e.load_suppressions(suppressions), Status::Ok);
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec();
+        (e, out)
+    }
+
+    #[test]
+    fn own_result_suppresses_every_entry() {
+        let config = EngineConfig { value_hashes: true, ..small_config() };
+        let left = br#"{"a":1,"b":"x","c":[1,2],"gone":true}"#;
+        let right = br#"{"a":2,"b":"y","c":[1,3,4],"new":null}"#;
+        let (_, base) = run(config.clone(), left, right);
+        let reader = crate::reader::ResultReader::parse(&base).unwrap();
+        assert_eq!(reader.value_hashes().map(|h| h.len()), Some(reader.entry_count()));
+        assert!(reader.entry_count() >= 5);
+
+        let (e, out) = run_suppressed(config, &base, left, right);
+        assert_eq!(entry_count(&out), 0);
+        assert_eq!(e.suppressed_entries(), entry_count(&base));
+    }
+
+    #[test]
+    fn only_new_changes_survive_suppression() {
+        let config = EngineConfig { value_hashes: true, ..small_config() };
+        let left = br#"{"a":1,"b":"x","c":3}"#;
+        let (_, base) = run(config.clone(), left, br#"{"a":2,"b":"x","c":3}"#);
+
+        // `a` changed the same way again; `c` is new.
+        let (e, out) = run_suppressed(config.clone(), &base, left, br#"{"a":2,"b":"x","c":4}"#);
+        assert_eq!(e.suppressed_entries(), 1);
+        let reader = crate::reader::ResultReader::parse(&out).unwrap();
+        let right = br#"{"a":2,"b":"x","c":4}"#;
+        let values: Vec<_> = reader.entries().map(|r| r.values(left, right).unwrap()[1]).collect();
+        assert_eq!(values, [Some(&b"4"[..])]);
+
+        // Same path and op but a different right value is not suppressed.
+        let (e, out) = run_suppressed(config, &base, left, br#"{"a":5,"b":"x","c":3}"#);
+        assert_eq!((e.suppressed_entries(), entry_count(&out)), (0, 1));
+    }
+
+    #[test]
+    fn load_suppressions_rejects_unusable_results() {
+        let left = br#"{"a":1}"#;
+        let right = br#"{"a":2}"#;
+        let (_, plain) = run(small_config(), left, right);
+        let (_, hashed) = run(EngineConfig { value_hashes: true, ..small_config() }, left, right);
+        let mut other_major = hashed.clone();
+        other_major[0..2].copy_from_slice(&2u16.to_le_bytes());
+
+        for (bad, why) in [
+            (&plain[..], "no value hash section"),
+            (&other_major[..], "major version 2"),
+            (&hashed[..10], "truncated"),
+        ] {
+            let mut e = Engine::new(small_config(), 1).unwrap();
+            assert_eq!(e.load_suppressions(bad), Status::Error);
+            assert_eq!(e.last_error_code(), crate::error::ErrorCode::InvalidSuppressions as u32);
+            assert!(error_text(&e).contains(why), "{}", error_text(&e));
+        }
+
+        let (mut e, _) = run(small_config(), left, right);
+        assert_eq!(e.load_suppressions(&hashed), Status::EngineSealed);
+        e.clear();
+        assert_eq!(e.load_suppressions(&hashed), Status::Ok);
+    }
+
+    #[test]
+    fn top_k_writes_winners_by_significance() {
+        let left = br#"{"a":"xx","b":1,"c":{"d":"0123456789"},"e":[1,2,3],"f":"abcd"}"#;
+        let right = br#"{"a":"xxxxxx","b":2,"c":{"d":"0"},"e":[1,2],"f":"abcd","g":"1234"}"#;
+        let config = EngineConfig { top_k: 3, ..small_config() };
+        let (e, out) = run(config.clone(), left, right);
+        let reader = crate::reader::ResultReader::parse(&out).unwrap();
+        let values: Vec<_> = reader
+            .entries()
+            .map(|r| r.values(left, right).unwrap().map(|v| v.map(|b| core::str::from_utf8(b).unwrap())))
+            .collect();
+        // Deltas: /c/d 9, /a 4, /g 4, /e/2 1, /b 0. /a beats /g on path order.
+        assert_eq!(values, [
+            [Some("0123456789"), Some("0")],
+            [Some("xx"), Some("xxxxxx")],
+            [None, Some("1234")],
+        ]);
+        assert_eq!(e.ranked_total(), 5);
+
+        // Shallowest first, then by path; `top_k` overrides `entry_order`.
+        let config = EngineConfig {
+            significance: crate::config::Significance::PathDepthInverse,
+            entry_order: EntryOrder::OpThenPath,
+            ..config
+        };
+        let (_, out) = run(config, left, right);
+        assert_eq!(entry_paths(&out).len(), 3);
+        let reader = crate::reader::ResultReader::parse(&out).unwrap();
+        let rights: Vec<_> = reader.entries().map(|r| r.values(left, right).unwrap()[1]).collect();
+        assert_eq!(rights, [Some(&b"xxxxxx"[..]), Some(b"2"), Some(b"1234")]);
+    }
+
+    #[test]
+    fn top_k_set_after_commit_renders_paths() {
+        let left = br#"{"a":1,"b":"long value"}"#;
+        let right = br#"{"a":2,"b":"x"}"#;
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        // top_k (field 15) = 1.
+        assert_eq!(e.set_output_options(&[15, 1, 0, 0, 0]), Status::Ok);
+        e.finalize().unwrap();
+        assert_eq!((e.result_len() as usize, e.ranked_total()), (16 + 32, 2));
+        let out = unsafe { std::slice::from_raw_parts(e.arena.as_ptr(), e.result_len() as usize) };
+        assert_eq!(crate::reader::ResultReader::parse(out).unwrap().entries().next().unwrap().right, (12, 1));
+    }
+
+    const RESTRICT_LEFT: &[u8] = br#"{"a":1,"b":[1,2],"c":{"d":1},"gone":true}"#;
+    const RESTRICT_RIGHT: &[u8] = br#"{"a":2,"b":[1,2,3],"c":{"d":1,"e":[4,5]},"new":{"x":[1,2]}}"#;
+
+    fn ops_and_paths(out: &[u8]) -> Vec<(u8, u64)> {
+        let reader = crate::reader::ResultReader::parse(out).unwrap();
+        reader.entries().map(|e| (e.op, e.path_id)).collect()
+    }
+
+    #[test]
+    fn right_restricted_reports_only_modified_and_removed() {
+        let config = EngineConfig { right_restricted_to_left: true, ..small_config() };
+        let (e, out) = run(config, RESTRICT_LEFT, RESTRICT_RIGHT);
+        let entries = ops_and_paths(&out);
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().all(|&(op, _)| op == DiffOp::Modified as u8 || op == DiffOp::Removed as u8));
+        assert_ne!(header_flags(&out) & HEADER_FLAG_RIGHT_RESTRICTED, 0);
+
+        // Every right token sits at a left path; each path emits at most a
+        // start and an end token.
+        let left_paths = e.left_parser.path_ids();
+        assert!(e.right_parser.tokens().iter().all(|t| left_paths.contains(&t.path_id)));
+        assert!(e.right_parser.tokens().len() <= 2 * left_paths.len());
+    }
+
+    #[test]
+    fn right_restriction_off_detects_additions() {
+        let (restricted, restricted_out) = run(
+            EngineConfig { right_restricted_to_left: true, ..small_config() },
+            RESTRICT_LEFT,
+            RESTRICT_RIGHT,
+        );
+        let (e, out) = run(small_config(), RESTRICT_LEFT, RESTRICT_RIGHT);
+        // Additions under `/b`, `/c` and `/new` come back; everything else
+        // matches the restricted result.
+        let (added, rest): (Vec<_>, Vec<_>) =
+            ops_and_paths(&out).into_iter().partition(|&(op, _)| op == DiffOp::Added as u8);
+        assert!(added.len() >= 3);
+        assert_eq!(rest, ops_and_paths(&restricted_out));
+        assert_eq!(header_flags(&out) & HEADER_FLAG_RIGHT_RESTRICTED, 0);
+        assert!(e.right_parser.tokens().len() > restricted.right_parser.tokens().len());
+    }
+
+    #[test]
+    fn right_restriction_needs_left_first() {
+        let config = EngineConfig { right_restricted_to_left: true, ..small_config() };
+        let mut e = Engine::new(config, 1).unwrap();
+        assert_eq!(e.commit_right(0), Status::Error);
+        assert_eq!(e.error.code(), crate::error::ErrorCode::LeftNotCommitted as u32);
+        assert_eq!(e.state, EngineState::Created);
+    }
+
+    fn shape_ops(left: &[u8], right: &[u8]) -> Vec<u8> {
+        let (_, out) = run(EngineConfig { shape_only: true, ..small_config() }, left, right);
+        let mut ops: Vec<u8> = ops_and_paths(&out).into_iter().map(|(op, _)| op).collect();
+        ops.sort_unstable();
+        ops
+    }
+
+    #[test]
+    fn shape_only_ignores_same_type_value_changes() {
+        let left = br#"{"a":1,"b":"x","c":[1,true],"d":null,"e":{"f":-2.5}}"#;
+        let right = br#"{"a":2,"b":"y","c":[3,false],"d":null,"e":{"f":7e3}}"#;
+        assert!(shape_ops(left, right).is_empty());
+        // The same documents differ in five values without the mode.
+        let (_, out) = run(small_config(), left, right);
+        assert_eq!(ops_and_paths(&out).len(), 5);
+    }
+
+    #[test]
+    fn shape_only_reports_type_and_key_changes() {
+        use DiffOp::{Added as A, Modified as M, Removed as R};
+        assert_eq!(shape_ops(br#"{"a":1}"#, br#"{"a":"1"}"#), [M as u8]);
+        assert_eq!(shape_ops(br#"{"a":true}"#, br#"{"a":null}"#), [M as u8]);
+        assert_eq!(shape_ops(br#"{"a":1,"b":2}"#, br#"{"a":5,"c":3}"#), [A as u8, R as u8]);
+        // Arrays compare element by element under the index array mode.
+        assert_eq!(shape_ops(b"[1,2,3]", br#"[4,"x",6,7]"#), [A as u8, M as u8]);
+        assert_eq!(shape_ops(b"[1,2]", b"[9]"), [R as u8]);
+        assert_eq!(shape_ops(b"1", b"2"), [0u8; 0]);
+    }
+
+    #[test]
+    fn shape_only_as_output_option() {
+        let (left, right) = (br#"{"a":1}"#, br#"{"a":2}"#);
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        // shape_only (field 18) on.
+        assert_eq!(e.set_output_options(&[18, 1, 0, 0, 0]), Status::Ok);
+        e.finalize().unwrap();
+        assert_eq!(e.result_len(), 16);
+    }
+
+    #[test]
+    fn small_inputs_diff_like_large_ones() {
+        use crate::gen::{generate, mutate, GenConfig};
+        use crate::parser::SMALL_INPUT_BYTES;
+        // Trailing whitespace takes a side past the small-input threshold
+        // without moving any value, so the results must match byte for byte.
+        let pad = |doc: &[u8]| [doc, &[b' '; SMALL_INPUT_BYTES][..]].concat();
+        for seed in 0..50 {
+            let doc = generate(&GenConfig::adversarial(), seed);
+            let (edited, _) = mutate(&doc, 3, seed);
+            let (left, right) = (doc.to_json().into_bytes(), edited.to_json().into_bytes());
+            assert!(left.len().max(right.len()) < SMALL_INPUT_BYTES);
+            let (_, small) = run(small_config(), &left, &right);
+            let (_, large) = run(small_config(), &pad(&left), &pad(&right));
+            assert_eq!(small, large, "seed {}", seed);
+        }
+    }
+
+    #[test]
+    fn finalize_for_every_commit_combination() {
+        use crate::error::ErrorCode;
+        let (left, right) = (b"[1,2]", b"[1,3]");
+        for allow in [false, true] {
+            for (l, r) in [(false, false), (true, false), (false, true), (true, true)] {
+                let config = EngineConfig { allow_empty_side: allow, ..small_config() };
+                let mut e = Engine::new(config, 1).unwrap();
+                unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+                if l { assert_eq!(commit_left_bytes(&mut e, left), Status::Ok); }
+                if r { assert_eq!(e.commit_right(right.len() as u32), Status::Ok); }
+                assert_eq!(e.result_len(), 0);
+
+                let case = (allow, l, r);
+                let finalized = e.finalize();
+                if !l && !r && !allow {
+                    assert!(finalized.is_err(), "{:?}", case);
+                    assert_eq!(e.error.code(), ErrorCode::NoInputCommitted as u32);
+                    assert_eq!((e.result_len(), e.state), (0, EngineState::Created));
+                    continue;
+                }
+                let out = unsafe { std::slice::from_raw_parts(finalized.unwrap(), e.result_len() as usize) };
+                let ops: Vec<u8> = ops_and_paths(out).into_iter().map(|(op, _)| op).collect();
+                let (want, no_input): (&[DiffOp], bool) = match (l, r) {
+                    (false, false) => (&[], true),
+                    (true, false) => (&[DiffOp::Removed], false),
+                    (false, true) => (&[DiffOp::Added], false),
+                    (true, true) => (&[DiffOp::Modified], false),
+                };
+                let want: Vec<u8> = want.iter().map(|&op| op as u8).collect();
+                assert_eq!(ops, want, "{:?}", case);
+                assert_eq!(header_flags(out) & HEADER_FLAG_NO_INPUT != 0, no_input, "{:?}", case);
+            }
+        }
+    }
+
+    /// `[n zeros]` against `[n ones]`: `n` Modified entries.
+    fn changed_array(n: usize) -> (Vec<u8>, Vec<u8>) {
+        let array = |v: &str| format!("[{}]", vec![v; n].join(",")).into_bytes();
+        (array("0"), array("1"))
+    }
+
+    #[test]
+    fn result_estimate_is_the_exact_length() {
+        use crate::config::ArenaGrowth;
+        use crate::gen::{generate, mutate, GenConfig};
+        let (l, r) = big_pair();
+        let configs = [
+            small_config(),
+            EngineConfig { coverage_map: true, input_digests: true, value_hashes: true, ..small_config() },
+            EngineConfig { max_entry_bytes: 200, value_hashes: true, ..small_config() },
+            EngineConfig { max_entry_bytes: 200, oversize_policy: OversizePolicy::Truncate, ..small_config() },
+            EngineConfig { top_k: 2, coverage_map: true, ..small_config() },
+            EngineConfig { arena_growth: ArenaGrowth::Doubling, value_hashes: true, ..small_config() },
+        ];
+        for (i, config) in configs.into_iter().enumerate() {
+            let mut pairs = vec![(l.clone(), r.clone()), (l.clone(), l.clone())];
+            for seed in 0..20 {
+                let doc = generate(&GenConfig::adversarial(), seed);
+                let (edited, _) = mutate(&doc, 5, seed);
+                pairs.push((doc.to_json().into_bytes(), edited.to_json().into_bytes()));
+            }
+            for (j, (left, right)) in pairs.iter().enumerate() {
+                let (e, out) = run(config.clone(), left, right);
+                let stats = e.arena_stats();
+                assert_eq!(stats.result_estimate_bytes as usize, out.len(), "config {} pair {}", i, j);
+                if config.arena_growth == ArenaGrowth::Reserve {
+                    assert!(stats.reallocations <= 1, "config {} pair {}", i, j);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn reserve_moves_only_the_header_on_a_large_diff() {
+        use crate::config::ArenaGrowth;
+        let (left, right) = changed_array(50_000);
+        let (e, reserved) = run(small_config(), &left, &right);
+        let stats = e.arena_stats();
+        assert_eq!(reserved.len(), 16 + 50_000 * 32);
+        assert_eq!((stats.reallocations, stats.moved_bytes), (1, 16));
+        assert_eq!(stats.capacity_bytes as usize, reserved.len());
+
+        let config = EngineConfig { arena_growth: ArenaGrowth::Doubling, ..small_config() };
+        let (e, doubled) = run(config, &left, &right);
+        let stats = e.arena_stats();
+        assert_eq!(doubled, reserved);
+        assert!(stats.reallocations > 10, "{:?}", stats);
+        assert!(stats.moved_bytes > reserved.len() as u64 / 2, "{:?}", stats);
+        assert_eq!(stats.result_estimate_bytes as usize, doubled.len());
+    }
+
+    #[test]
+    fn capped_estimate_grows_in_chunks_to_the_limit() {
+        use crate::memory::ARENA_CHUNK;
+        let (left, right) = changed_array(50_000);
+        let limit = ARENA_CHUNK + ARENA_CHUNK / 2;
+        let config = EngineConfig { max_memory_bytes: limit as u32, ..small_config() };
+        let (e, out) = run(config, &left, &right);
+        let stats = e.arena_stats();
+        assert_eq!(e.error.code(), crate::error::ErrorCode::MemoryLimitExceeded as u32);
+        assert_eq!(stats.result_estimate_bytes as usize, 16 + 50_000 * 32);
+        assert!(out.len() <= limit);
+        // One chunk, then the rest of the way to the limit; never past it.
+        assert_eq!(stats.capacity_bytes as usize, limit);
+        assert_eq!(stats.reallocations, 2);
+    }
+
+    #[test]
+    fn commits_share_the_group_index_pool() {
+        use crate::index_pool::stats;
+        let group = 7101;
+        let (left, right) = (br#"{"a":[1,2,3],"b":"x"}"#, br#"{"a":[1,2,4],"c":"y"}"#);
+        let (_, expected) = run(small_config(), left, right);
+
+        let mut engines: Vec<Engine> =
+            (0..3).map(|_| Engine::new_in_group(small_config(), 1, group).unwrap()).collect();
+        for (i, e) in engines.iter_mut().enumerate() {
+            unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+            assert_eq!(commit_left_bytes(e, left), Status::Ok);
+            assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+            assert!(e.index.is_none(), "an idle engine holds no index");
+            // Every commit after the first reuses the index the last one returned.
+            assert_eq!(stats(Some(group)).reuses, 2 * i as u32 + 1);
+            let out = unsafe { std::slice::from_raw_parts(e.finalize().unwrap(), e.result_len() as usize) };
+            assert_eq!(out, &expected[..], "engine {}", i);
+        }
+        assert_eq!(stats(Some(group)).idle, 1);
+
+        // A pooled index that held a longer document leaves nothing behind.
+        let mut e = Engine::new_in_group(small_config(), 1, group).unwrap();
+        let long = format!(r#"{{"a":[{}]}}"#, vec!["1"; 1000].join(","));
+        assert_eq!(commit_left_bytes(&mut e, long.as_bytes()), Status::Ok);
+        e.clear();
+        assert_eq!(commit_left_bytes(&mut e, left), Status::Ok);
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let out = unsafe { std::slice::from_raw_parts(e.finalize().unwrap(), e.result_len() as usize) };
+        assert_eq!(out, &expected[..]);
+    }
+
+    fn run_three_way(config: EngineConfig, left: &[u8], control: &[u8], canary: &[u8]) -> (Engine, Vec<u8>) {
+        let mut e = Engine::new(EngineConfig { three_way: true, ..config }, 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(control.as_ptr(), e.right_input_ptr(), control.len());
+            std::ptr::copy_nonoverlapping(canary.as_ptr(), e.third_input_ptr(), canary.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(control.len() as u32), Status::Ok);
+        assert_eq!(e.commit_third(canary.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        let out = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec();
+        (e, out)
+    }
+
+    #[test]
+    fn three_way_flags_agreement_per_path() {
+        use crate::path::{fold_segment_hash, ROOT_PATH_ID};
+        use crate::three_way::{Agreement, NO_CANARY_OP};
+        let key = |k: &str| fold_segment_hash(ROOT_PATH_ID, k.as_bytes()).0;
+        let left = br#"{"a":1,"b":2,"c":3,"d":4}"#;
+        let control = br#"{"a":9,"b":2,"c":5,"d":8}"#;
+        let canary = br#"{"a":9,"b":7,"c":6,"d":4,"e":0}"#;
+        let (_, out) = run_three_way(small_config(), left, control, canary);
+
+        let reader = ResultReader::parse(&out).unwrap();
+        let sides = reader.canary().unwrap();
+        let rows: Vec<_> = reader.entries().zip(&sides).map(|(e, s)| (e.path_id, e.op, s.agreement, s.canary_op)).collect();
+        let (modified, added) = (DiffOp::Modified as u8, DiffOp::Added as u8);
+        assert_eq!(rows, [
+            (key("a"), modified, Agreement::Agree as u8, modified),
+            (key("c"), modified, Agreement::Disagree as u8, modified),
+            (key("d"), modified, Agreement::ControlOnly as u8, NO_CANARY_OP),
+            (key("b"), modified, Agreement::CanaryOnly as u8, modified),
+            (key("e"), added, Agreement::CanaryOnly as u8, added),
+        ]);
+
+        // Canary spans point into the third input; control spans stay
+        // against the right, and a canary-only entry has none.
+        let value = |(offset, len): (u32, u32), input: &[u8]| input[offset as usize..(offset + len) as usize].to_vec();
+        assert_eq!([1, 3, 4].map(|i| value(sides[i].canary, canary)), [b"6".to_vec(), b"7".to_vec(), b"0".to_vec()]);
+        let entries: Vec<_> = reader.entries().collect();
+        assert_eq!(value(entries[1].right, control), b"5");
+        assert_eq!(entries[3].right_kind, 0);
+        assert_eq!(ResultArena::estimate_len(5, &[5 * CanarySide::SIZE]), out.len());
+    }
+
+    #[test]
+    fn three_way_identical_rights_agree_everywhere() {
+        let left = br#"{"a":1,"b":[1,2]}"#;
+        let right = br#"{"a":2,"b":[1]}"#;
+        let (_, two) = run(small_config(), left, right);
+        let (_, three) = run_three_way(small_config(), left, right, right);
+        let reader = ResultReader::parse(&three).unwrap();
+        assert!(reader.canary().unwrap().iter().all(|s| s.agreement == crate::three_way::Agreement::Agree as u8));
+        assert_eq!(entry_paths(&three), entry_paths(&two));
+
+        // The identical flag needs all three sides to match.
+        let (_, out) = run_three_way(small_config(), left, left, right);
+        assert_eq!(u32::from_le_bytes(out[12..16].try_into().unwrap()) & HEADER_FLAG_IDENTICAL, 0);
+        assert_eq!(entry_count(&out), 2);
+    }
+
+    #[test]
+    fn two_document_mode_has_no_third_slot() {
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert!(e.third_input_ptr().is_null());
+        assert_eq!(e.commit_third(0), Status::Error);
+        assert_eq!(e.last_error_code(), crate::error::ErrorCode::FeatureDisabled as u32);
+        assert_eq!(e.left_input.capacity(), 1 << 19);
+
+        let (_, out) = run(small_config(), br#"{"a":1}"#, br#"{"a":2}"#);
+        assert!(ResultReader::parse(&out).unwrap().canary().is_none());
+
+        // Three documents share the same input budget.
+        let e = Engine::new(EngineConfig { three_way: true, ..small_config() }, 1).unwrap();
+        let third = e.third.as_ref().unwrap().input.capacity();
+        assert_eq!((e.left_input.capacity(), e.right_input.capacity(), third), ((1 << 20) / 3, (1 << 20) / 3, (1 << 20) / 3));
+    }
+
+    #[test]
+    fn input_buffers_are_cache_line_aligned() {
+        use crate::memory::INPUT_ALIGN;
+        // An odd capacity still gets aligned storage, rounded up to lines.
+        for config in [small_config(), EngineConfig { three_way: true, max_input_size: 1001, ..small_config() }] {
+            let mut e = Engine::new(config, 1).unwrap();
+            assert_eq!(e.left_input_ptr() as usize % INPUT_ALIGN, 0);
+            assert_eq!(e.right_input_ptr() as usize % INPUT_ALIGN, 0);
+            let third = e.third_input_ptr();
+            assert!(third.is_null() || (third as usize).is_multiple_of(INPUT_ALIGN));
+        }
+    }
+
+    #[test]
+    fn entries_flag_payloads_that_are_not_utf8() {
+        use crate::memory::{ENTRY_FLAG_LEFT_INVALID_UTF8 as LEFT, ENTRY_FLAG_RIGHT_INVALID_UTF8 as RIGHT};
+        let left = [&b"[\"plain\",\""[..], "é€𝄞".as_bytes(), b"\",\"ok\",\"x\xff\",1]"].concat();
+        let right = [&b"[\"ascii\",\""[..], "€é".as_bytes(), b"\",\"o\xc3\",\"y\xff\",2]"].concat();
+        let (_, out) = run(small_config(), &left, &right);
+        let reader = ResultReader::parse(&out).unwrap();
+        let flags: Vec<u8> = reader.entries().map(|e| e.flags).collect();
+        assert_eq!(flags, [0, 0, RIGHT, LEFT | RIGHT, 0], "ascii, multi-byte, cut sequence, stray byte, number");
+        let third = reader.entries().nth(2).unwrap();
+        assert!(third.left_utf8() && !third.right_utf8());
+
+        // The flag describes the payload as written, and truncation backs
+        // off to a character boundary rather than leave an invalid tail.
+        // 50 bytes of each span fit; the `x` shifts the right side's cut.
+        for (ch, kept) in [("é", (50, 49)), ("€", (48, 49)), ("𝄞", (48, 49))] {
+            let left = format!(r#"{{"big":"{}"}}"#, ch.repeat(100));
+            let right = format!(r#"{{"big":"x{}"}}"#, ch.repeat(100));
+            let config = EngineConfig { max_entry_bytes: 132, oversize_policy: OversizePolicy::Truncate, ..small_config() };
+            let (_, out) = run(config, left.as_bytes(), right.as_bytes());
+            let e = ResultReader::parse(&out).unwrap().entries().next().unwrap();
+            assert_eq!(e.flags, ENTRY_FLAG_TRUNCATED, "{:?}", ch);
+            assert_eq!((e.left.1, e.right.1), kept, "{:?}", ch);
+        }
+    }
+
+    #[test]
+    fn comparison_settings_apply_after_commit_without_a_reparse() {
+        use crate::config::ConfigField;
+        let left = br#"{"n":1,"big":9007199254740993,"s":"Caf\u00e9","t":"ON","u":"a\/b","k":"x"}"#;
+        let right = r#"{"n":1.0,"big":9007199254740992,"s":"Café","t":"on","u":"a/b","k":"y"}"#.as_bytes();
+        let (_, plain) = run(small_config(), left, right);
+        assert_eq!(entry_count(&plain), 6);
+
+        let settings = [
+            (ConfigField::NormalizeNumbers, EngineConfig { normalize_numbers: true, ..small_config() }, 5),
+            (ConfigField::NormalizeStrings, EngineConfig { normalize_strings: true, ..small_config() }, 4),
+            (ConfigField::IgnoreCase, EngineConfig { ignore_case: true, ..small_config() }, 5),
+        ];
+        for (field, config, entries) in settings {
+            let (from_start, want) = run(config.clone(), left, right);
+            assert_eq!(entry_count(&want), entries, "{:?}", field);
+            assert_eq!(from_start.phase_counters().parses, 2);
+
+            // Committed without the setting, switched on before finalize.
+            let mut e = Engine::new(small_config(), 1).unwrap();
+            unsafe {
+                std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+                std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+            }
+            assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+            assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+            assert_eq!(e.set_output_options(&[field as u8, 1, 0, 0, 0]), Status::Ok);
+            let ptr = e.finalize().unwrap();
+            let got = unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) };
+            assert_eq!(got, &want[..], "{:?}", field);
+            assert_eq!(e.phase_counters(), PhaseCounters { digest_matches: 0, joins: 1, parses: 2, hash_passes: 2, disjoint_joins: 0 }, "{:?}", field);
+        }
+
+        // All three at once leave the id and the other key; the entries
+        // still carry the values as written.
+        let all = EngineConfig { normalize_numbers: true, normalize_strings: true, ignore_case: true, ..small_config() };
+        let (e, out) = run(all, left, right);
+        let reader = ResultReader::parse(&out).unwrap();
+        let values: Vec<_> = reader.entries().map(|d| d.values(left, right).unwrap()).collect();
+        assert_eq!(values, [[Some(&b"9007199254740993"[..]), Some(&b"9007199254740992"[..])], [Some(&b"x"[..]), Some(&b"y"[..])]]);
+        assert_eq!(e.phase_counters().hash_passes, 2);
+    }
+
+    #[test]
+    fn numeric_deltas_ride_along_modified_numbers() {
+        let left = br#"{"up":10,"down":2.5,"big":9007199254740993,"name":"a","n":1,"gone":3,"same":7}"#;
+        let right = br#"{"up":12.25,"down":-1,"big":9007199254740999,"name":"b","n":"1","same":7,"new":4}"#;
+        let (_, plain) = run(small_config(), left, right);
+        let reader = ResultReader::parse(&plain).unwrap();
+        assert_eq!(reader.numeric_deltas(), None, "off by default");
+        assert!(reader.entries().all(|e| !e.has_numeric_delta() && !e.delta_inexact()));
+
+        let (_, out) = run(EngineConfig { numeric_deltas: true, ..small_config() }, left, right);
+        let reader = ResultReader::parse(&out).unwrap();
+        let deltas = reader.numeric_deltas().unwrap();
+        assert_eq!(deltas.len(), reader.entry_count());
+        let by_path: Vec<_> = reader.entries().zip(&deltas).map(|(e, d)| (e.op, e.delta_inexact(), *d)).collect();
+        let modified = DiffOp::Modified as u8;
+        assert_eq!(
+            by_path,
+            [
+                (modified, false, Some(2.25)),
+                (modified, false, Some(-3.5)),
+                (modified, true, None),
+                (modified, false, None),
+                (modified, false, None),
+                (DiffOp::Added as u8, false, None),
+                (DiffOp::Removed as u8, false, None),
+            ],
+            "up, down, big, name, n (number to string), new, gone"
+        );
+
+        // Truncation does not cut the delta short: it reads the full values.
+        let config = EngineConfig {
+            numeric_deltas: true,
+            max_entry_bytes: 36,
+            oversize_policy: OversizePolicy::Truncate,
+            ..small_config()
+        };
+        let (_, out) = run(config, br#"[1000.5]"#, br#"[-2000.25]"#);
+        let reader = ResultReader::parse(&out).unwrap();
+        assert_eq!(reader.entries().next().unwrap().flags, ENTRY_FLAG_TRUNCATED | ENTRY_FLAG_NUMERIC_DELTA);
+        assert_eq!(reader.numeric_deltas(), Some(vec![Some(-3000.75)]));
+    }
+
+    #[test]
+    fn entry_sequence_numbers_the_written_entries_densely() {
+        use crate::config::EntryOrder;
+        use crate::memory::{HEADER_FLAG_ENTRY_SEQUENCE, SECTION_ENTRY_SEQUENCE};
+        let left = br#"{"a":1,"b":"short","c":[1,2,3],"d":true,"gone":"x","long":"aaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"}"#;
+        let right = br#"{"a":2,"b":"other","c":[1,5],"d":false,"new":null,"long":"bbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"}"#;
+        let (_, plain) = run(small_config(), left, right);
+        let reader = ResultReader::parse(&plain).unwrap();
+        assert_eq!(reader.entry_sequence(), None, "off by default");
+        assert_eq!(reader.flags() & HEADER_FLAG_ENTRY_SEQUENCE, 0);
+
+        // Ordering, ranking and oversize skips all happen before numbering.
+        let shaped = [
+            small_config(),
+            EngineConfig { entry_order: EntryOrder::OpThenPath, ..small_config() },
+            EngineConfig { top_k: 3, ..small_config() },
+            EngineConfig { max_entry_bytes: 40, ..small_config() },
+        ];
+        for config in shaped {
+            let (_, without) = run(config.clone(), left, right);
+            let (_, out) = run(EngineConfig { entry_sequence: true, ..config.clone() }, left, right);
+            let reader = ResultReader::parse(&out).unwrap();
+            assert_ne!(reader.flags() & HEADER_FLAG_ENTRY_SEQUENCE, 0);
+            let seq = reader.entry_sequence().unwrap();
+            assert_eq!(seq, (0..reader.entry_count() as u32).collect::<Vec<_>>(), "{:?}", config);
+            assert_eq!(entry_paths(&out), entry_paths(&without), "numbering changes no entry");
+        }
+        let (_, skipped) = run(EngineConfig { entry_sequence: true, max_entry_bytes: 40, ..small_config() }, left, right);
+        assert!(entry_count(&skipped) < entry_count(&plain), "the long entry was skipped");
+        assert_eq!(ResultReader::parse(&skipped).unwrap().section(SECTION_ENTRY_SEQUENCE).unwrap().len(), 4 * entry_count(&skipped) as usize);
+    }
+
+    #[test]
+    fn oversized_primitives_compare_raw_and_come_out_cut() {
+        use crate::memory::ENTRY_FLAG_OVERSIZED_PRIMITIVE;
+        let digits = "1".repeat(100);
+        let left = format!(r#"{{"big":{}.0,"m":1,"n":1.0,"s":"x"}}"#, digits);
+        let right = format!(r#"{{"big":{},"m":2.5,"n":1,"s":"y"}}"#, digits);
+        let config = EngineConfig { max_primitive_bytes: 64, normalize_numbers: true, numeric_deltas: true, ..small_config() };
+        let (e, out) = run(config.clone(), left.as_bytes(), right.as_bytes());
+        let reader = ResultReader::parse(&out).unwrap();
+        // `n` normalizes equal; `big` is over the cap, so its raw bytes differ.
+        let entries: Vec<_> = reader.entries().collect();
+        assert_eq!(entries.len(), 3, "big, m, s");
+        assert_eq!(entries[0].flags, ENTRY_FLAG_TRUNCATED | ENTRY_FLAG_OVERSIZED_PRIMITIVE);
+        assert_eq!((entries[0].left.1, entries[0].right.1), (64, 64));
+        assert_eq!(reader.value_lengths(), Some(vec![(102, 100), (1, 3), (1, 1)]));
+        assert_eq!(reader.numeric_deltas(), Some(vec![None, Some(1.5), None]), "no delta over the cap");
+        assert_eq!([entries[1].flags, entries[2].flags], [ENTRY_FLAG_NUMERIC_DELTA, 0]);
+        assert_eq!((e.stats.left.oversized, e.stats.right.oversized), (1, 1));
+
+        // Uncapped, `big` normalizes equal too, and nothing is cut.
+        let (e, out) = run(EngineConfig { max_primitive_bytes: 0, ..config }, left.as_bytes(), right.as_bytes());
+        let reader = ResultReader::parse(&out).unwrap();
+        assert_eq!(reader.entry_count(), 2);
+        assert_eq!(reader.value_lengths(), None);
+        assert!(reader.entries().all(|e| !e.oversized_primitive()));
+        assert_eq!(e.stats.left.oversized, 0);
+    }
+
+    #[test]
+    fn self_contained_results_patch_the_whole_documents() {
+        use crate::memory::HEADER_FLAG_PATCHED;
+        let left = br#"{"keep":{"a":1},"e":{},"n":[1,2]}"#;
+        let right = br#"{"keep":{"a":2},"e":[],"n":[1]}"#;
+        let (_, plain) = run(small_config(), left, right);
+        assert_eq!(ResultReader::parse(&plain).unwrap().patch(), None, "off by default");
+
+        // `top_k` keeps one entry; the patch still covers every change,
+        // `{}` to `[]` included, which no entry reports.
+        let config = EngineConfig { self_contained: true, top_k: 1, ..small_config() };
+        let (_, out) = run(config, left, right);
+        let reader = ResultReader::parse(&out).unwrap();
+        assert_eq!(reader.entry_count(), 1);
+        let patch = reader.patch().unwrap();
+        assert_eq!(crate::patch::apply(left, patch).unwrap(), right);
+
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.apply_patch(left, &plain), Status::Error);
+        assert_eq!(e.last_error_code(), crate::error::ErrorCode::InvalidPatch as u32);
+        assert!(e.state.is_open(), "a refused patch leaves the engine open");
+        assert_eq!(e.apply_patch(left, &out), Status::Ok);
+        let result = unsafe { std::slice::from_raw_parts(e.finalize().unwrap(), e.result_len() as usize) };
+        let reader = ResultReader::parse(result).unwrap();
+        assert_eq!((reader.entry_count(), reader.flags() & HEADER_FLAG_PATCHED), (0, HEADER_FLAG_PATCHED));
+        assert_eq!(reader.document(), Some(&right[..]));
+    }
+
+    /// A Kubernetes-style deployment whose `n` env vars all change value.
+    fn deep_env(n: usize, value: &str) -> Vec<u8> {
+        let env: Vec<String> = (0..n).map(|i| format!(r#"{{"name":"VAR_{}","value":"{}{}"}}"#, i, value, i)).collect();
+        format!(r#"{{"spec":{{"template":{{"spec":{{"containers":[{{"env":[{}]}}]}}}}}}}}"#, env.join(",")).into_bytes()
+    }
+
+    #[test]
+    fn rendered_paths_rebuild_losslessly_in_every_order() {
+        use crate::config::{EntryOrder, Significance};
+        use crate::memory::HEADER_FLAG_PATHS_FRONT_CODED;
+        let left = br#"{"b":{"x":1,"y~/":[1,2]},"a":[{"k":"v"},3],"gone":true,"ab":{"c":1}}"#;
+        let right = br#"{"b":{"x":2,"y~/":[1,2,3]},"a":[{"k":"w"},4],"ab":{"c":2},"new":{"deep":[0]}}"#;
+        let (_, plain) = run(small_config(), left, right);
+        assert_eq!(ResultReader::parse(&plain).unwrap().paths(), None, "off by default");
+
+        // Document order is never front-coded, so every record is a full path.
+        let config = EngineConfig { rendered_paths: true, ..small_config() };
+        let (_, out) = run(config.clone(), left, right);
+        let reader = ResultReader::parse(&out).unwrap();
+        assert_eq!(reader.flags() & HEADER_FLAG_PATHS_FRONT_CODED, 0);
+        let expected: Vec<(u64, Vec<u8>)> = reader.entries().map(|e| e.path_id).zip(reader.paths().unwrap()).collect();
+        let rendered: Vec<&[u8]> = expected.iter().map(|(_, p)| p.as_slice()).collect();
+        assert_eq!(rendered, [&b"/b/x"[..], b"/b/y~0~1/2", b"/a/0/k", b"/a/1", b"/ab/c", b"/new/deep/0", b"/gone"]);
+        let by_id = |id: u64| &expected.iter().find(|(p, _)| *p == id).unwrap().1;
+
+        for (order, top_k, front_coded) in [
+            (EntryOrder::PathLexicographic, 0, true),
+            (EntryOrder::OpThenPath, 0, true),
+            (EntryOrder::PathLexicographic, 3, false),
+            (EntryOrder::DocumentOrder, 0, false),
+        ] {
+            let config = EngineConfig { entry_order: order, top_k, significance: Significance::ByteDelta, ..config.clone() };
+            let (_, out) = run(config, left, right);
+            let reader = ResultReader::parse(&out).unwrap();
+            assert_eq!(reader.flags() & HEADER_FLAG_PATHS_FRONT_CODED != 0, front_coded, "{:?} top_k {}", order, top_k);
+            let paths = reader.paths().unwrap();
+            assert_eq!(paths.len(), reader.entry_count());
+            for (e, path) in reader.entries().zip(&paths) {
+                assert_eq!(path, by_id(e.path_id), "{:?} top_k {}", order, top_k);
+            }
+            // The first record never leans on a previous path.
+            let body = reader.section(crate::memory::SECTION_PATHS).unwrap();
+            assert_eq!(&body[..4], &[0; 4]);
+            if order == EntryOrder::PathLexicographic && top_k == 0 {
+                assert!(paths.windows(2).all(|w| w[0] <= w[1]));
+            }
+        }
+
+        // Skipped oversized entries leave no record behind.
+        let config = EngineConfig {
+            entry_order: EntryOrder::PathLexicographic,
+            max_entry_bytes: 33,
+            ..config
+        };
+        let (_, out) = run(config, left, right);
+        let reader = ResultReader::parse(&out).unwrap();
+        let paths = reader.paths().unwrap();
+        assert!(reader.entry_count() < expected.len() && paths.len() == reader.entry_count());
+        assert!(reader.entries().zip(&paths).all(|(e, p)| p == by_id(e.path_id)));
+    }
+
+    #[test]
+    fn front_coding_halves_deep_path_sections() {
+        use crate::config::EntryOrder;
+        use crate::memory::SECTION_PATHS;
+        let (left, right) = (deep_env(500, "old"), deep_env(500, "new"));
+        let section_len = |entry_order| {
+            let config = EngineConfig { rendered_paths: true, entry_order, ..small_config() };
+            let (_, out) = run(config, &left, &right);
+            let reader = ResultReader::parse(&out).unwrap();
+            assert_eq!(reader.entry_count(), 500);
+            assert!(reader.paths().unwrap().iter().all(|p| p.starts_with(b"/spec/template/spec/containers/0/env/")));
+            reader.section(SECTION_PATHS).unwrap().len()
+        };
+        let (full, coded) = (section_len(EntryOrder::DocumentOrder), section_len(EntryOrder::PathLexicographic));
+        // Measured: 26890 bytes full, 7537 front-coded (72% smaller).
+        assert!(coded * 2 < full, "{} front-coded vs {} full", coded, full);
+    }
+
+    /// Results are cached by content: the same inputs and config must give
+    /// the same bytes whatever order the engine's hash maps iterate in.
+    #[test]
+    fn results_do_not_depend_on_hash_seed() {
+        use crate::config::{EntryOrder, Significance};
+        use crate::gen::{generate, mutate, GenConfig};
+        use crate::ordered::with_hash_seed;
+        let doc = generate(&GenConfig::adversarial(), 7);
+        let json = |seed| mutate(&doc, 12, seed).0.to_json().into_bytes();
+        let (left, right, canary) = (doc.to_json().into_bytes(), json(7), json(8));
+        let configs = [
+            EngineConfig { coverage_map: true, input_digests: true, value_hashes: true, ..small_config() },
+            EngineConfig { entry_order: EntryOrder::PathLexicographic, ..small_config() },
+            EngineConfig { top_k: 5, significance: Significance::SubtreeChangeCount, ..small_config() },
+        ];
+        let diff = |config: &Option<EngineConfig>| match config {
+            Some(config) => run(config.clone(), &left, &right).1,
+            None => run_three_way(small_config(), &left, &right, &canary).1,
+        };
+        for config in configs.map(Some).into_iter().chain([None]) {
+            let want = with_hash_seed(0, || diff(&config));
+            for seed in 1..50usize {
+                assert_eq!(with_hash_seed(seed.wrapping_mul(0x9e37_79b9), || diff(&config)), want, "{:?} seed {}", config, seed);
+            }
+        }
+    }
+
+    /// Push each side in `chunk`-byte pieces and finalize.
+    fn run_pushed(config: EngineConfig, left: &[u8], right: &[u8], chunk: usize) -> Vec<u8> {
+        let mut e = Engine::new(config, 1).unwrap();
+        for (side, doc) in [(Side::Left, left), (Side::Right, right)] {
+            for piece in doc.chunks(chunk) {
+                assert_eq!(e.push(side, piece), Status::Ok);
+            }
+        }
+        let ptr = e.finalize().unwrap();
+        unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec()
+    }
+
+    #[test]
+    fn pushed_chunks_match_a_single_commit() {
+        // Escapes, multi-byte text and numbers, so small chunks split all
+        // of them; over 128 KB a side, so 64 KB chunks split too.
+        let left = deep_env(2500, r#"a\"é\u00e9 -1.5e3 "#);
+        let right = deep_env(2501, r#"a\"é\u00e8 -1.5e3 "#);
+        assert!(left.len() > 2 * 65536, "{} bytes", left.len());
+        let config = EngineConfig { coverage_map: true, input_digests: true, rendered_paths: true, ..small_config() };
+        let (_, want) = run(config.clone(), &left, &right);
+        assert_eq!(ResultReader::parse(&want).unwrap().entries().count(), 2500 + 2, "every value, and the new name and value");
+        for chunk in [1, 7, 65536] {
+            assert_eq!(run_pushed(config.clone(), &left, &right, chunk), want, "{}-byte chunks", chunk);
+        }
+    }
+
+    #[test]
+    fn pushes_respect_capacity_commits_and_seal() {
+        let mut e = Engine::new(EngineConfig { max_input_size: 1024, ..EngineConfig::default() }, 1).unwrap();
+        assert_eq!(e.push(Side::Left, &[b' '; 500]), Status::Ok);
+        assert_eq!(e.push(Side::Left, &[b' '; 13]), Status::InputLimitExceeded, "512 bytes a side");
+        assert_eq!(e.push(Side::Left, &[b' '; 12]), Status::Ok, "a refused chunk appends nothing");
+        assert_eq!(e.left_pushed, 512);
+
+        // A commit takes over from the pushes; the side then takes no more.
+        assert_eq!(e.push(Side::Right, b"[1,"), Status::Ok);
+        assert_eq!(e.push(Side::Right, b"2]"), Status::Ok);
+        assert_eq!(e.commit_right(5), Status::Ok);
+        assert_eq!(e.right_pushed, 0);
+        assert_eq!(e.push(Side::Right, b"]"), Status::AlreadyCommitted);
+
+        // A pushed side that fails to commit fails finalize with its error.
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.set_scope(Side::Left, b"/b"), Status::Ok);
+        assert_eq!(e.push(Side::Left, br#"{"a":1}"#), Status::Ok);
+        assert!(matches!(e.finalize(), Err(EngineError::ScopeNotFound { side: "left", .. })));
+        assert_eq!(e.last_error_code(), crate::error::ErrorCode::ScopeNotFound as u32);
+        assert!(e.state.is_open());
+
+        let mut e = Engine::new(small_config(), 1).unwrap();
+        assert_eq!(e.push(Side::Right, br#"{"a":1}"#), Status::Ok);
+        assert!(e.finalize().is_ok(), "an unpushed side is empty");
+        assert_eq!(e.push(Side::Left, b"{}"), Status::EngineSealed);
+        e.clear();
+        assert_eq!(e.push(Side::Left, b"{}"), Status::Ok);
+        assert_eq!(e.left_pushed, 2);
+    }
+
+    fn diff_on(e: &mut Engine, left: &[u8], right: &[u8]) -> Vec<u8> {
+        assert_eq!(commit_left_bytes(e, left), Status::Ok);
+        unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let ptr = e.finalize().unwrap();
+        unsafe { std::slice::from_raw_parts(ptr, e.result_len() as usize) }.to_vec()
+    }
+
+    #[test]
+    fn trim_levels_release_what_they_say() {
+        let (left, right) = (deep_env(2000, "a"), deep_env(2000, "b"));
+        let config = EngineConfig { rendered_paths: true, ..small_config() };
+        let (_, want) = run(config.clone(), &left, &right);
+        let mut e = Engine::new(config.clone(), 1).unwrap();
+        assert_eq!(diff_on(&mut e, &left, &right), want);
+        assert_eq!(e.build_span_index(), Status::Ok);
+        let spans = e.span_index.clone();
+        e.effective_config_json();
+
+        // 0 on a sealed engine: buffers fit their contents, the result
+        // stays put and everything still reads.
+        let (result_ptr, arena_cap, held) = (e.arena.as_ptr(), e.arena.capacity_bytes(), e.held_bytes());
+        let token_reservation = e.left_parser.capacity_bytes();
+        assert_eq!(e.trim(0), Status::Ok);
+        assert!(e.left_parser.capacity_bytes() * 4 < token_reservation, "the up-front token reservation goes");
+        assert_eq!((e.arena.as_ptr(), e.arena.capacity_bytes()), (result_ptr, arena_cap));
+        assert_eq!(e.arena_stats().trimmed_bytes as usize, held - e.held_bytes());
+        assert!(e.arena_stats().trimmed_bytes > 0);
+        assert_eq!((e.span_index.capacity(), e.config_json_len() as usize), (spans.len(), e.config_json.capacity()));
+
+        // 1 drops the rebuildable caches, which rebuild the same.
+        assert_eq!(e.trim(1), Status::Ok);
+        assert_eq!((e.span_index.capacity(), e.config_json.capacity()), (0, 0));
+        assert_eq!(e.build_span_index(), Status::Ok);
+        assert_eq!(e.span_index, spans);
+
+        // 0 after a clear gives back the tokens and the arena.
+        e.clear();
+        assert_eq!(e.trim(0), Status::Ok);
+        assert_eq!((e.left_parser.capacity_bytes(), e.right_parser.capacity_bytes(), e.arena.capacity_bytes()), (0, 0, 16));
+        assert_eq!(diff_on(&mut e, &left, &right), want, "fully working afterwards");
+
+        // 2 is a fresh engine, down to its capacities, whatever was set.
+        e.clear();
+        assert_eq!(e.set_scope(Side::Left, b"/spec"), Status::Ok);
+        assert_eq!(e.trim(2), Status::Ok);
+        let fresh = Engine::new(config, 1).unwrap();
+        assert_eq!(e.held_bytes(), fresh.held_bytes());
+        assert!(e.arena_stats().trimmed_bytes as usize > 2000 * core::mem::size_of::<crate::parser::CompactToken>());
+        assert_eq!(diff_on(&mut e, &left, &right), want);
+
+        assert_eq!(e.trim(3), Status::Error);
+        assert_eq!(e.last_error_code(), crate::error::ErrorCode::InvalidTrimLevel as u32);
+    }
+
+    #[test]
+    fn retained_left_is_parsed_once_and_diffs_as_fresh_engines() {
+        use crate::gen::{generate, mutate, GenConfig};
+        let base = generate(&GenConfig::default(), 7);
+        let left = base.to_json().into_bytes();
+        let rights: Vec<Vec<u8>> = (0..12u64)
+            .map(|seed| match seed {
+                // Identical, empty-object and unrelated rights too.
+                0 => left.clone(),
+                1 => b"{}".to_vec(),
+                2 => generate(&GenConfig::default(), 99).to_json().into_bytes(),
+                _ => mutate(&base, seed as usize, seed).0.to_json().into_bytes(),
+            })
+            .collect();
+        let configs = [
+            small_config(),
+            EngineConfig { rendered_paths: true, value_hashes: true, normalize_numbers: true, ..small_config() },
+        ];
+        for config in configs {
+            let mut e = Engine::new(config.clone(), 1).unwrap();
+            unsafe { std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len()) };
+            assert_eq!(e.commit_left_retained(left.len() as u32), Status::Ok);
+            for (i, right) in rights.iter().enumerate() {
+                unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+                assert_eq!(e.diff_next_right(right.len() as u32), Status::Ok, "right {i}");
+                let got = unsafe { std::slice::from_raw_parts(e.finalize().unwrap(), e.result_len() as usize) };
+                assert_eq!(got, run(config.clone(), &left, right).1, "right {i}");
+            }
+            assert_eq!(e.phase_counters().parses, 1 + rights.len() as u32, "the left parsed once");
+
+            // Exported, it is not parsed again at all.
+            let blob = unsafe { std::slice::from_raw_parts(e.export_left_state(), e.left_state_len() as usize) }.to_vec();
+            let mut imported = Engine::new(config.clone(), 1).unwrap();
+            assert_eq!(imported.import_left_state(&blob), Status::Ok);
+            for (i, right) in rights.iter().enumerate() {
+                unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), imported.right_input_ptr(), right.len()) };
+                assert_eq!(imported.diff_next_right(right.len() as u32), Status::Ok, "right {i}");
+                let got = unsafe { std::slice::from_raw_parts(imported.finalize().unwrap(), imported.result_len() as usize) };
+                assert_eq!(got, run(config.clone(), &left, right).1, "right {i}");
+            }
+            assert_eq!(imported.phase_counters().parses, rights.len() as u32, "the left not parsed");
+
+            // A refused commit leaves the series as it was.
+            assert_ne!(e.commit_left(left.len() as u32), Status::Ok);
+            let right = &rights[3];
+            unsafe { std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len()) };
+            assert_eq!(e.diff_next_right(right.len() as u32), Status::Ok);
+            let got = unsafe { std::slice::from_raw_parts(e.finalize().unwrap(), e.result_len() as usize) };
+            assert_eq!(got, run(config.clone(), &left, right).1);
+
+            // A plain commit or a clear ends the series.
+            e.clear();
+            assert_eq!(e.diff_next_right(2), Status::Error);
+            assert_eq!(e.last_error_code(), crate::error::ErrorCode::LeftNotRetained as u32);
+        }
+    }
+
+    #[test]
+    fn segments_split_the_sections_with_their_entries() {
+        let config = EngineConfig {
+            max_memory_bytes: 2048,
+            rendered_paths: true,
+            entry_sequence: true,
+            input_digests: true,
+            ..small_config()
+        };
+        let left = format!("{{{}}}", (0..200).map(|k| format!(r#""key{}":{}"#, k, k)).collect::<Vec<_>>().join(","));
+        let right = left.replace(":1", ":2");
+        let (_, whole) = run(EngineConfig { max_memory_bytes: 1 << 20, ..config.clone() }, left.as_bytes(), right.as_bytes());
+        let whole = ResultReader::parse(&whole).unwrap();
+
+        let mut e = Engine::new(config, 1).unwrap();
+        unsafe {
+            std::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            std::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        assert_eq!(e.commit_left(left.len() as u32), Status::Ok);
+        assert_eq!(e.commit_right(right.len() as u32), Status::Ok);
+        let (mut paths, mut sequence, mut digests) = (Vec::new(), Vec::new(), Vec::new());
+        let mut status = e.finalize_partial();
+        loop {
+            let bytes = unsafe { std::slice::from_raw_parts(e.finalize().unwrap(), e.result_len() as usize) };
+            let segment = ResultReader::parse(bytes).unwrap();
+            assert!(bytes.len() <= 2048);
+            assert_eq!(segment.paths().unwrap().len(), segment.entry_count() as usize);
+            paths.extend(segment.paths().unwrap());
+            sequence.extend(segment.entry_sequence().unwrap());
+            digests.push(segment.input_digests());
+            if status == Status::Ok { break; }
+            assert_eq!(segment.flags() & HEADER_FLAG_PARTIAL, HEADER_FLAG_PARTIAL);
+            status = e.resume_finalize();
+        }
+        assert!(digests.len() > 2);
+        assert_eq!((paths, sequence), (whole.paths().unwrap(), (0..whole.entry_count() as u32).collect()));
+        let (last, rest) = digests.split_last().unwrap();
+        assert!(rest.iter().all(Option::is_none) && *last == whole.input_digests(), "digests only in the last");
+    }
 }
@@ -1,12 +1,277 @@
 //! Error handling for the diff engine.
 
-use std::fmt;
+use crate::config::ConfigError;
+use crate::left_state::StateError;
+use crate::memory::ArenaError;
+use crate::parser::ParseError;
+use crate::status::Status;
+use crate::validate::Invalid;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+use core::fmt;
+
+/// Detailed error codes, read by the host through `get_last_error_code`.
+///
+/// A single `u32` space shared by every subsystem, in reserved ranges:
+///
+/// | range   | subsystem                                        |
+/// |---------|--------------------------------------------------|
+/// | 0       | no error                                         |
+/// | 1–99    | engine lifecycle (sealed, recommit, buffers)     |
+/// | 100–199 | configuration and scopes (`ConfigError`)         |
+/// | 200–299 | parsing (`ParseError`)                           |
+/// | 300–399 | memory and quotas (`ArenaError`, groups)         |
+/// | 400–499 | warnings: the operation still returned `Ok`      |
+/// | 900–999 | internal invariant failures                      |
+///
+/// The numbers are part of the format contract: a code is never
+/// renumbered or reused, only added.
+#[repr(u32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorCode {
+    None = 0,
+
+    EngineSealed = 1,
+    AlreadyCommitted = 2,
+    /// Committed length exceeds the managed input buffer.
+    InputExceedsBuffer = 3,
+    /// Operation needs a finalized result.
+    NotFinalized = 4,
+    /// Right side committed before the left in `right_restricted_to_left`.
+    LeftNotCommitted = 5,
+    /// Finalize with neither side committed (see `allow_empty_side`).
+    NoInputCommitted = 6,
+    /// `trim_engine` level other than 0, 1 or 2.
+    InvalidTrimLevel = 7,
+    /// The entry transform returned a code other than keep, drop or redact.
+    InvalidTransformResult = 8,
+    /// `cancel` stopped the finalize; the result holds what it found.
+    Cancelled = 9,
+    /// Input or options while a `finalize_step` finalize is in progress.
+    Busy = 10,
+    /// Any call but `clear` after a failure that poisoned the engine.
+    Poisoned = 11,
+    /// `set_config` after input was committed or pushed.
+    TooLate = 12,
+    /// `lint` side other than 0 (left) or 1 (right).
+    InvalidSide = 13,
+    /// `diff_next_right` or `export_left_state` without a retained left
+    /// side (`commit_left_retained`, `import_left_state`).
+    LeftNotRetained = 14,
+    /// `finalize_into` with a host buffer smaller than the result.
+    OutputTooSmall = 15,
+    /// Result access after `finalize_into` handed the result to the host.
+    ResultHandedOff = 16,
+    /// `resume_finalize` with no `finalize_partial` segment left to write.
+    NoSegmentPending = 17,
+
+    ConfigTooShort = 100,
+    InvalidArrayMode = 101,
+    InvalidLimits = 102,
+    InvalidWindowSize = 103,
+    UnknownConfigField = 104,
+    InvalidConfigValue = 105,
+    NotAnOutputOption = 106,
+    /// A config-gated feature was used without enabling it.
+    FeatureDisabled = 107,
+    MalformedConfigJson = 108,
+    MalformedScope = 110,
+    ScopeNotFound = 111,
+    /// `load_suppressions` was given a result it cannot use.
+    InvalidSuppressions = 112,
+    /// `apply_patch` was given a result without a usable patch, or one
+    /// for another document.
+    InvalidPatch = 113,
+    /// `import_left_state` was given a blob it cannot restore.
+    InvalidLeftState = 114,
+    /// `set_option` was given a key that names no config field.
+    UnknownOption = 115,
+
+    UnexpectedByte = 200,
+    IncompleteInput = 201,
+    ObjectKeyLimitExceeded = 202,
+    InvalidEscape = 203,
+    /// `validate_json` found the document malformed.
+    InvalidJson = 204,
+
+    ArenaSealed = 300,
+    MemoryLimitExceeded = 301,
+    InputLimitExceeded = 302,
+    ArrayTooLarge = 303,
+    GroupQuotaExceeded = 304,
+    /// Every handle slot is taken (`handles` feature).
+    TooManyEngines = 305,
+
+    OversizedEntriesSkipped = 400,
+    EscapesReplaced = 401,
+
+    Internal = 900,
+    /// `self_test` found the build computing something else.
+    SelfTestFailed = 901,
+}
+
+impl From<&ConfigError> for ErrorCode {
+    fn from(e: &ConfigError) -> Self {
+        match e {
+            ConfigError::TooShort => ErrorCode::ConfigTooShort,
+            ConfigError::InvalidArrayMode => ErrorCode::InvalidArrayMode,
+            ConfigError::InvalidLimits => ErrorCode::InvalidLimits,
+            ConfigError::InvalidWindowSize => ErrorCode::InvalidWindowSize,
+            ConfigError::UnknownField(_) => ErrorCode::UnknownConfigField,
+            ConfigError::InvalidValue(_) => ErrorCode::InvalidConfigValue,
+            ConfigError::NotAnOutputOption(_) => ErrorCode::NotAnOutputOption,
+            ConfigError::MalformedJson(_) => ErrorCode::MalformedConfigJson,
+        }
+    }
+}
+
+impl From<&ParseError> for ErrorCode {
+    fn from(e: &ParseError) -> Self {
+        match e {
+            ParseError::UnexpectedByte(_) => ErrorCode::UnexpectedByte,
+            ParseError::IncompleteInput => ErrorCode::IncompleteInput,
+            ParseError::ObjectKeyLimitExceeded => ErrorCode::ObjectKeyLimitExceeded,
+            ParseError::InvalidEscape { .. } => ErrorCode::InvalidEscape,
+        }
+    }
+}
+
+impl From<ArenaError> for ErrorCode {
+    fn from(e: ArenaError) -> Self {
+        match e {
+            ArenaError::Sealed => ErrorCode::ArenaSealed,
+            ArenaError::LimitExceeded => ErrorCode::MemoryLimitExceeded,
+        }
+    }
+}
+
+impl From<&EngineError> for ErrorCode {
+    fn from(e: &EngineError) -> Self {
+        match e {
+            EngineError::Config(c) => c.into(),
+            EngineError::MalformedScope(_) => ErrorCode::MalformedScope,
+            EngineError::ScopeNotFound { .. } => ErrorCode::ScopeNotFound,
+            EngineError::InvalidSuppressions(_) => ErrorCode::InvalidSuppressions,
+            EngineError::InvalidPatch(_) => ErrorCode::InvalidPatch,
+            EngineError::InvalidLeftState(_) => ErrorCode::InvalidLeftState,
+            EngineError::UnknownOption(_) => ErrorCode::UnknownOption,
+            EngineError::MemoryLimitExceeded => ErrorCode::MemoryLimitExceeded,
+            EngineError::InputLimitExceeded => ErrorCode::InputLimitExceeded,
+            EngineError::ObjectKeyLimitExceeded => ErrorCode::ObjectKeyLimitExceeded,
+            EngineError::ArrayTooLarge => ErrorCode::ArrayTooLarge,
+            EngineError::EngineSealed => ErrorCode::EngineSealed,
+            EngineError::AlreadyCommitted => ErrorCode::AlreadyCommitted,
+            EngineError::InputExceedsBuffer { .. } => ErrorCode::InputExceedsBuffer,
+            EngineError::NotFinalized => ErrorCode::NotFinalized,
+            EngineError::LeftNotCommitted => ErrorCode::LeftNotCommitted,
+            EngineError::NoInputCommitted => ErrorCode::NoInputCommitted,
+            EngineError::InvalidTrimLevel(_) => ErrorCode::InvalidTrimLevel,
+            EngineError::InvalidTransformResult(_) => ErrorCode::InvalidTransformResult,
+            EngineError::Cancelled => ErrorCode::Cancelled,
+            EngineError::Busy => ErrorCode::Busy,
+            EngineError::Poisoned => ErrorCode::Poisoned,
+            EngineError::TooLate => ErrorCode::TooLate,
+            EngineError::InvalidSide(_) => ErrorCode::InvalidSide,
+            EngineError::LeftNotRetained => ErrorCode::LeftNotRetained,
+            EngineError::OutputTooSmall { .. } => ErrorCode::OutputTooSmall,
+            EngineError::ResultHandedOff => ErrorCode::ResultHandedOff,
+            EngineError::NoSegmentPending => ErrorCode::NoSegmentPending,
+            EngineError::ResultTruncated { .. } => ErrorCode::MemoryLimitExceeded,
+            EngineError::InputDigestsDisabled | EngineError::ThreeWayDisabled => ErrorCode::FeatureDisabled,
+            EngineError::GroupQuotaExceeded => ErrorCode::GroupQuotaExceeded,
+            EngineError::TooManyEngines => ErrorCode::TooManyEngines,
+            EngineError::OversizedEntriesSkipped(_) => ErrorCode::OversizedEntriesSkipped,
+            EngineError::EscapesReplaced(_) => ErrorCode::EscapesReplaced,
+            EngineError::Parse(p) => p.into(),
+            EngineError::InvalidJson(_) => ErrorCode::InvalidJson,
+            EngineError::Internal(_) => ErrorCode::Internal,
+            EngineError::SelfTest(_) => ErrorCode::SelfTestFailed,
+        }
+    }
+}
+
+/// The status a failing call returns for `e`: the specific one where
+/// `Status` has it, else `Error`. The detail is in the error code and
+/// message either way.
+impl From<&ParseError> for Status {
+    fn from(e: &ParseError) -> Self {
+        match e {
+            ParseError::ObjectKeyLimitExceeded => Status::ObjectKeyLimitExceeded,
+            ParseError::UnexpectedByte(_) | ParseError::IncompleteInput | ParseError::InvalidEscape { .. } => Status::Error,
+        }
+    }
+}
+
+/// As for `ParseError`; the warnings, which don't fail their call, are `Ok`.
+impl From<&EngineError> for Status {
+    fn from(e: &EngineError) -> Self {
+        match e {
+            EngineError::Parse(p) => p.into(),
+            EngineError::InputLimitExceeded => Status::InputLimitExceeded,
+            EngineError::ObjectKeyLimitExceeded => Status::ObjectKeyLimitExceeded,
+            EngineError::ArrayTooLarge => Status::ArrayTooLarge,
+            EngineError::EngineSealed => Status::EngineSealed,
+            EngineError::AlreadyCommitted => Status::AlreadyCommitted,
+            EngineError::GroupQuotaExceeded => Status::GroupQuotaExceeded,
+            EngineError::Cancelled => Status::Cancelled,
+            EngineError::Busy => Status::Busy,
+            EngineError::Poisoned => Status::Poisoned,
+            EngineError::TooLate => Status::TooLate,
+            EngineError::UnknownOption(_) => Status::UnknownOption,
+            EngineError::OutputTooSmall { .. } => Status::NeedFlush,
+            EngineError::ResultTruncated { .. } => Status::ResultTruncated,
+            EngineError::OversizedEntriesSkipped(_) | EngineError::EscapesReplaced(_) => Status::Ok,
+            EngineError::Config(_)
+            | EngineError::MalformedScope(_)
+            | EngineError::ScopeNotFound { .. }
+            | EngineError::InvalidSuppressions(_)
+            | EngineError::InvalidPatch(_)
+            | EngineError::InvalidLeftState(_)
+            | EngineError::InvalidJson(_)
+            | EngineError::MemoryLimitExceeded
+            | EngineError::InputExceedsBuffer { .. }
+            | EngineError::NotFinalized
+            | EngineError::LeftNotCommitted
+            | EngineError::NoInputCommitted
+            | EngineError::InvalidTrimLevel(_)
+            | EngineError::InvalidSide(_)
+            | EngineError::LeftNotRetained
+            | EngineError::ResultHandedOff
+            | EngineError::NoSegmentPending
+            | EngineError::InvalidTransformResult(_)
+            | EngineError::InputDigestsDisabled
+            | EngineError::ThreeWayDisabled
+            | EngineError::TooManyEngines
+            | EngineError::Internal(_)
+            | EngineError::SelfTest(_) => Status::Error,
+        }
+    }
+}
+
+impl From<ParseError> for EngineError {
+    fn from(e: ParseError) -> Self { EngineError::Parse(e) }
+}
 
 /// Engine errors that can occur during operation.
 #[derive(Debug, Clone)]
 pub enum EngineError {
     /// Configuration is invalid
-    InvalidConfig(String),
+    Config(ConfigError),
+    /// Scope pointer is not a valid JSON Pointer
+    MalformedScope(String),
+    /// Scope pointer is absent from the committed document
+    ScopeNotFound { scope: String, side: &'static str },
+    /// Result passed to `load_suppressions` is unreadable or lacks value
+    /// hashes
+    InvalidSuppressions(String),
+    /// Result passed to `apply_patch` has no patch section, or a record
+    /// that does not fit the document
+    InvalidPatch(String),
+    /// Blob passed to `import_left_state` is not one this build wrote, or
+    /// is damaged
+    InvalidLeftState(StateError),
+    /// `set_option` key that names no config field
+    UnknownOption(String),
     /// Memory limit exceeded
     MemoryLimitExceeded,
     /// Input size limit exceeded
@@ -17,57 +282,183 @@ pub enum EngineError {
     ArrayTooLarge,
     /// Engine has been sealed (finalized)
     EngineSealed,
+    /// This side was already committed (see `recommit_replaces`)
+    AlreadyCommitted,
+    /// Committed length exceeds the managed input buffer
+    InputExceedsBuffer { len: u32, capacity: usize },
+    /// Operation needs a finalized result
+    NotFinalized,
+    /// `right_restricted_to_left` needs the left side committed first
+    LeftNotCommitted,
+    /// Finalize with neither side committed and `allow_empty_side` off
+    NoInputCommitted,
+    /// `trim_engine` level out of range
+    InvalidTrimLevel(u32),
+    /// The entry transform returned an unknown code
+    InvalidTransformResult(u32),
+    /// `cancel` stopped the finalize
+    Cancelled,
+    /// A stepped finalize is in progress
+    Busy,
+    /// An earlier failure poisoned the engine
+    Poisoned,
+    /// `set_config` once input has arrived
+    TooLate,
+    /// `lint` side out of range
+    InvalidSide(u32),
+    /// `diff_next_right` or `export_left_state` without a retained left
+    LeftNotRetained,
+    /// `finalize_into` buffer smaller than the result
+    OutputTooSmall { needed: u32, capacity: u32 },
+    /// `finalize_into` gave the result away
+    ResultHandedOff,
+    /// `resume_finalize` past the last segment
+    NoSegmentPending,
+    /// The result hit the arena's size limit; `dropped` entries are missing
+    ResultTruncated { limit: u32, dropped: u32 },
+    /// Input digests were requested but `input_digests` is off
+    InputDigestsDisabled,
+    /// A third input was committed but `three_way` is off
+    ThreeWayDisabled,
+    /// The engine's group is at its engine or byte quota
+    GroupQuotaExceeded,
+    /// The handle registry has no free slot
+    TooManyEngines,
+    /// Entries over `max_entry_bytes` were dropped (finalize still succeeded)
+    OversizedEntriesSkipped(u32),
+    /// Undecodable string escapes were compared as U+FFFD (commit still
+    /// succeeded)
+    EscapesReplaced(u32),
     /// JSON parse error
-    ParseError(String),
+    Parse(ParseError),
+    /// `validate_json` found the document malformed
+    InvalidJson(Invalid),
     /// Internal error
     Internal(String),
+    /// Failed `self_test` checks, described
+    SelfTest(String),
 }
 
 impl fmt::Display for EngineError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
-            EngineError::InvalidConfig(msg) => write!(f, "invalid config: {}", msg),
+            EngineError::Config(e) => write!(f, "invalid config: {}", e),
+            EngineError::MalformedScope(pointer) => {
+                write!(f, "invalid config: malformed scope pointer {:?}", pointer)
+            }
+            EngineError::ScopeNotFound { scope, side } => {
+                write!(f, "invalid config: scope {} not found in {} document", scope, side)
+            }
+            EngineError::InvalidSuppressions(why) => write!(f, "invalid suppressions: {}", why),
+            EngineError::InvalidPatch(why) => write!(f, "invalid patch: {}", why),
+            EngineError::InvalidLeftState(why) => write!(f, "invalid left state: {}", why),
+            EngineError::UnknownOption(key) => write!(f, "unknown option {:?}", key),
             EngineError::MemoryLimitExceeded => write!(f, "memory limit exceeded"),
             EngineError::InputLimitExceeded => write!(f, "input size limit exceeded"),
             EngineError::ObjectKeyLimitExceeded => write!(f, "object key limit exceeded"),
             EngineError::ArrayTooLarge => write!(f, "array too large for selected diff mode"),
             EngineError::EngineSealed => write!(f, "engine sealed, no more input accepted"),
-            EngineError::ParseError(msg) => write!(f, "parse error: {}", msg),
+            EngineError::AlreadyCommitted => write!(f, "side already committed"),
+            EngineError::InputExceedsBuffer { len, capacity } => {
+                write!(f, "commit of {} bytes exceeds {} byte input buffer", len, capacity)
+            }
+            EngineError::NotFinalized => write!(f, "span index requires a finalized result"),
+            EngineError::LeftNotCommitted => {
+                write!(f, "right_restricted_to_left requires the left side committed first")
+            }
+            EngineError::NoInputCommitted => write!(f, "no input committed"),
+            EngineError::InvalidTrimLevel(level) => write!(f, "trim level {} is not 0, 1 or 2", level),
+            EngineError::InvalidTransformResult(code) => {
+                write!(f, "entry transform returned {}, not keep (0), drop (1) or redact (2)", code)
+            }
+            EngineError::Cancelled => write!(f, "finalize cancelled; the result is partial"),
+            EngineError::Busy => write!(f, "finalize in progress, no more input accepted"),
+            EngineError::Poisoned => write!(f, "engine poisoned by an earlier failure; clear it"),
+            EngineError::TooLate => write!(f, "config can only change before any input is committed or pushed"),
+            EngineError::InvalidSide(side) => write!(f, "side {} is not 0 (left) or 1 (right)", side),
+            EngineError::LeftNotRetained => {
+                write!(f, "no retained left side (commit_left_retained or import_left_state)")
+            }
+            EngineError::OutputTooSmall { needed, capacity } => {
+                write!(f, "result of {} bytes does not fit the {}-byte output buffer", needed, capacity)
+            }
+            EngineError::ResultHandedOff => write!(f, "result already written out by finalize_into; clear the engine"),
+            EngineError::NoSegmentPending => write!(f, "no result segment pending (finalize_partial)"),
+            EngineError::ResultTruncated { limit, dropped: 0 } => {
+                write!(f, "result truncated at max_memory_bytes ({}): its sections did not fit", limit)
+            }
+            EngineError::ResultTruncated { limit, dropped } => {
+                write!(f, "result truncated at max_memory_bytes ({}): {} entries dropped", limit, dropped)
+            }
+            EngineError::InputDigestsDisabled => {
+                write!(f, "input digests not recorded (input_digests is off)")
+            }
+            EngineError::ThreeWayDisabled => write!(f, "no third input (three_way is off)"),
+            EngineError::GroupQuotaExceeded => write!(f, "group quota exceeded"),
+            EngineError::TooManyEngines => write!(f, "too many engines for the handle registry"),
+            EngineError::OversizedEntriesSkipped(n) => {
+                write!(f, "{} entries exceeding max_entry_bytes skipped", n)
+            }
+            EngineError::EscapesReplaced(n) => {
+                write!(f, "{} invalid string escapes replaced with U+FFFD", n)
+            }
+            EngineError::Parse(e) => write!(f, "parse error: {}", e),
+            EngineError::InvalidJson(e) => write!(f, "invalid JSON at byte {}: {}", e.offset, e.what),
             EngineError::Internal(msg) => write!(f, "internal error: {}", msg),
+            EngineError::SelfTest(msg) => write!(f, "self-test failed: {}", msg),
         }
     }
 }
 
 /// Error buffer stored in the engine for cross-boundary access.
 ///
-/// UTF-8 encoded, null-terminated for C compatibility.
+/// UTF-8 encoded, null-terminated for C compatibility. The matching
+/// `ErrorCode` is kept alongside.
 #[derive(Debug, Default)]
 pub struct ErrorBuffer {
     buffer: Vec<u8>,
+    code: u32,
+    /// The error the message was rendered from.
+    error: Option<EngineError>,
 }
 
 impl ErrorBuffer {
-    pub fn new() -> Self {
-        Self { buffer: Vec::new() }
+    pub const fn new() -> Self {
+        Self { buffer: Vec::new(), code: ErrorCode::None as u32, error: None }
     }
 
-    /// Set error message. Ensures null termination.
+    /// Set error message and code. Ensures null termination.
     pub fn set(&mut self, error: &EngineError) {
         self.buffer.clear();
         let msg = error.to_string();
         self.buffer.extend_from_slice(msg.as_bytes());
         self.buffer.push(0); // Null terminate
+        self.code = ErrorCode::from(error) as u32;
+        self.error = Some(error.clone());
+        crate::trace::warn_event!(code = self.code, error = %msg);
     }
 
     /// Clear the error buffer.
     pub fn clear(&mut self) {
         self.buffer.clear();
+        self.code = ErrorCode::None as u32;
+        self.error = None;
+    }
+
+    /// The last error itself, for Rust callers passing it on.
+    pub fn error(&self) -> Option<&EngineError> {
+        self.error.as_ref()
+    }
+
+    /// Code of the last error (`ErrorCode::None` if empty).
+    pub fn code(&self) -> u32 {
+        self.code
     }
 
     /// Get pointer to error message (or null if empty).
     pub fn as_ptr(&self) -> *const u8 {
         if self.buffer.is_empty() {
-            std::ptr::null()
+            core::ptr::null()
         } else {
             self.buffer.as_ptr()
         }
@@ -81,8 +472,158 @@ impl ErrorBuffer {
             (self.buffer.len() - 1) as u32 // Exclude null terminator
         }
     }
+}
+
+/// Error of the last call that had no engine to hold it: a failed
+/// `create_engine*` or `self_test`. Each such call overwrites it, and
+/// clears it on success. Kept per thread, like `errno`, so a native host
+/// creating engines on several threads reads its own.
+#[cfg(feature = "std")]
+pub fn with_create_error<R>(f: impl FnOnce(&mut ErrorBuffer) -> R) -> R {
+    std::thread_local! {
+        static CREATE_ERROR: core::cell::RefCell<ErrorBuffer> = const { core::cell::RefCell::new(ErrorBuffer::new()) };
+    }
+    CREATE_ERROR.with(|e| f(&mut e.borrow_mut()))
+}
+
+#[cfg(not(feature = "std"))]
+pub fn with_create_error<R>(f: impl FnOnce(&mut ErrorBuffer) -> R) -> R {
+    static CREATE_ERROR: crate::global::Global<ErrorBuffer> = crate::global::Global::new();
+    crate::global::with_global(&CREATE_ERROR, f)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ConfigField;
+    use crate::escape::EscapeErrorKind;
+    use crate::path::PathId;
+
+    /// The numbers are a format contract: this table only ever grows.
+    #[test]
+    fn codes_are_pinned() {
+        let pinned = [
+            (ErrorCode::None, 0),
+            (ErrorCode::EngineSealed, 1),
+            (ErrorCode::AlreadyCommitted, 2),
+            (ErrorCode::InputExceedsBuffer, 3),
+            (ErrorCode::NotFinalized, 4),
+            (ErrorCode::LeftNotCommitted, 5),
+            (ErrorCode::NoInputCommitted, 6),
+            (ErrorCode::InvalidTrimLevel, 7),
+            (ErrorCode::InvalidTransformResult, 8),
+            (ErrorCode::Cancelled, 9),
+            (ErrorCode::Busy, 10),
+            (ErrorCode::Poisoned, 11),
+            (ErrorCode::TooLate, 12),
+            (ErrorCode::InvalidSide, 13),
+            (ErrorCode::LeftNotRetained, 14),
+            (ErrorCode::OutputTooSmall, 15),
+            (ErrorCode::ResultHandedOff, 16),
+            (ErrorCode::NoSegmentPending, 17),
+            (ErrorCode::ConfigTooShort, 100),
+            (ErrorCode::InvalidArrayMode, 101),
+            (ErrorCode::InvalidLimits, 102),
+            (ErrorCode::InvalidWindowSize, 103),
+            (ErrorCode::UnknownConfigField, 104),
+            (ErrorCode::InvalidConfigValue, 105),
+            (ErrorCode::NotAnOutputOption, 106),
+            (ErrorCode::FeatureDisabled, 107),
+            (ErrorCode::MalformedConfigJson, 108),
+            (ErrorCode::MalformedScope, 110),
+            (ErrorCode::ScopeNotFound, 111),
+            (ErrorCode::InvalidSuppressions, 112),
+            (ErrorCode::InvalidPatch, 113),
+            (ErrorCode::InvalidLeftState, 114),
+            (ErrorCode::UnknownOption, 115),
+            (ErrorCode::UnexpectedByte, 200),
+            (ErrorCode::IncompleteInput, 201),
+            (ErrorCode::ObjectKeyLimitExceeded, 202),
+            (ErrorCode::InvalidEscape, 203),
+            (ErrorCode::InvalidJson, 204),
+            (ErrorCode::ArenaSealed, 300),
+            (ErrorCode::MemoryLimitExceeded, 301),
+            (ErrorCode::InputLimitExceeded, 302),
+            (ErrorCode::ArrayTooLarge, 303),
+            (ErrorCode::GroupQuotaExceeded, 304),
+            (ErrorCode::TooManyEngines, 305),
+            (ErrorCode::OversizedEntriesSkipped, 400),
+            (ErrorCode::EscapesReplaced, 401),
+            (ErrorCode::Internal, 900),
+            (ErrorCode::SelfTestFailed, 901),
+        ];
+        for (code, n) in pinned {
+            assert_eq!(code as u32, n, "{:?}", code);
+        }
+    }
+
+    #[test]
+    fn subsystem_errors_land_in_their_range() {
+        let config = [
+            ConfigError::TooShort,
+            ConfigError::InvalidArrayMode,
+            ConfigError::InvalidLimits,
+            ConfigError::InvalidWindowSize,
+            ConfigError::UnknownField(99),
+            ConfigError::InvalidValue(ConfigField::EntryOrder),
+            ConfigError::NotAnOutputOption(ConfigField::MaxObjectKeys),
+        ];
+        for e in &config {
+            assert!((100..200).contains(&(ErrorCode::from(e) as u32)), "{:?}", e);
+        }
+        let parse = [
+            ParseError::UnexpectedByte(b'x'),
+            ParseError::IncompleteInput,
+            ParseError::ObjectKeyLimitExceeded,
+            ParseError::InvalidEscape { path: PathId(1), offset: 3, kind: EscapeErrorKind::NonHex },
+        ];
+        for e in &parse {
+            assert!((200..300).contains(&(ErrorCode::from(e) as u32)), "{:?}", e);
+        }
+        for e in [ArenaError::Sealed, ArenaError::LimitExceeded] {
+            assert!((300..400).contains(&(ErrorCode::from(e) as u32)), "{:?}", e);
+        }
+        // Wrapped errors keep their subsystem's code.
+        let wrapped = EngineError::Config(ConfigError::UnknownField(42));
+        assert_eq!(ErrorCode::from(&wrapped), ErrorCode::UnknownConfigField);
+        let wrapped = EngineError::Parse(ParseError::IncompleteInput);
+        assert_eq!(ErrorCode::from(&wrapped), ErrorCode::IncompleteInput);
+    }
+
+    #[test]
+    fn statuses_follow_the_error() {
+        let parse = [
+            (ParseError::UnexpectedByte(b'x'), Status::Error),
+            (ParseError::IncompleteInput, Status::Error),
+            (ParseError::ObjectKeyLimitExceeded, Status::ObjectKeyLimitExceeded),
+            (ParseError::InvalidEscape { path: PathId(1), offset: 3, kind: EscapeErrorKind::NonHex }, Status::Error),
+        ];
+        for (e, status) in parse {
+            assert_eq!(Status::from(&e), status, "{:?}", e);
+            assert_eq!(Status::from(&EngineError::from(e.clone())), status, "wrapped {:?}", e);
+        }
+        let engine = [
+            (EngineError::ArrayTooLarge, Status::ArrayTooLarge),
+            (EngineError::InputLimitExceeded, Status::InputLimitExceeded),
+            (EngineError::GroupQuotaExceeded, Status::GroupQuotaExceeded),
+            (EngineError::Cancelled, Status::Cancelled),
+            (EngineError::UnknownOption("x".into()), Status::UnknownOption),
+            (EngineError::EscapesReplaced(1), Status::Ok),
+            (EngineError::MemoryLimitExceeded, Status::Error),
+        ];
+        for (e, status) in engine {
+            assert_eq!(Status::from(&e), status, "{:?}", e);
+        }
+    }
 
-    pub fn is_empty(&self) -> bool {
-        self.buffer.is_empty()
+    #[test]
+    fn buffer_tracks_code_with_message() {
+        let mut buf = ErrorBuffer::new();
+        assert_eq!(buf.code(), 0);
+        buf.set(&EngineError::GroupQuotaExceeded);
+        assert_eq!(buf.code(), ErrorCode::GroupQuotaExceeded as u32);
+        assert_eq!(buf.len() as usize, "group quota exceeded".len());
+        buf.clear();
+        assert_eq!((buf.code(), buf.len()), (0, 0));
     }
 }
@@ -0,0 +1,209 @@
+//! Backslash-escape validation for raw string bodies.
+//!
+//! Bodies are the undecoded bytes between a string's quotes (see the span
+//! rules in `parser.rs`). The scanner finds escapes that cannot be decoded
+//! to UTF-8: unknown escape letters, `\u` with non-hex digits, a `\u` cut
+//! short by the end of the string, and unpaired UTF-16 surrogates.
+//! [`normalize`] shares the scanner and rewrites each such escape to
+//! `\uFFFD`, leaving the body valid JSON string text.
+//! [`decode`] turns such a body into the string's value, for comparing
+//! strings as decoded (`normalize_strings`).
+
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EscapeErrorKind {
+    /// `\` followed by a byte other than `"\/bfnrtu`.
+    UnknownEscape,
+    /// `\u` followed by a non-hex digit.
+    NonHex,
+    /// `\` or `\uXXXX` cut off by the end of the string.
+    Truncated,
+    /// High surrogate without a following low surrogate, or a bare low one.
+    LoneSurrogate,
+}
+
+impl fmt::Display for EscapeErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EscapeErrorKind::UnknownEscape => write!(f, "unknown escape"),
+            EscapeErrorKind::NonHex => write!(f, "non-hex digit in \\u escape"),
+            EscapeErrorKind::Truncated => write!(f, "truncated escape"),
+            EscapeErrorKind::LoneSurrogate => write!(f, "unpaired surrogate"),
+        }
+    }
+}
+
+/// An undecodable escape at `offset..offset + len` within the body.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EscapeError {
+    pub offset: usize,
+    pub len: usize,
+    pub kind: EscapeErrorKind,
+}
+
+/// Find the first undecodable escape at or after `from`.
+pub fn next_invalid(body: &[u8], from: usize) -> Option<EscapeError> {
+    let mut i = from;
+    while i < body.len() {
+        if body[i] != b'\\' {
+            i += 1;
+            continue;
+        }
+        let err = |len, kind| Some(EscapeError { offset: i, len, kind });
+        match body.get(i + 1) {
+            None => return err(1, EscapeErrorKind::Truncated),
+            Some(b'"' | b'\\' | b'/' | b'b' | b'f' | b'n' | b'r' | b't') => i += 2,
+            Some(b'u') => match read_u16(body, i) {
+                Err(e) => return Some(e),
+                Ok(0xD800..=0xDBFF) => {
+                    let low = if body.get(i + 6) == Some(&b'\\') && body.get(i + 7) == Some(&b'u') {
+                        read_u16(body, i + 6).ok()
+                    } else {
+                        None
+                    };
+                    match low {
+                        Some(0xDC00..=0xDFFF) => i += 12,
+                        _ => return err(6, EscapeErrorKind::LoneSurrogate),
+                    }
+                }
+                Ok(0xDC00..=0xDFFF) => return err(6, EscapeErrorKind::LoneSurrogate),
+                Ok(_) => i += 6,
+            },
+            Some(_) => return err(2, EscapeErrorKind::UnknownEscape),
+        }
+    }
+    None
+}
+
+/// Decode the four hex digits of the `\u` escape starting at `at`.
+fn read_u16(body: &[u8], at: usize) -> Result<u16, EscapeError> {
+    let mut v = 0u16;
+    for k in 0..4 {
+        let Some(&b) = body.get(at + 2 + k) else {
+            return Err(EscapeError { offset: at, len: 2 + k, kind: EscapeErrorKind::Truncated });
+        };
+        let d = match b {
+            b'0'..=b'9' => b - b'0',
+            b'a'..=b'f' => b - b'a' + 10,
+            b'A'..=b'F' => b - b'A' + 10,
+            _ => return Err(EscapeError { offset: at, len: 2 + k, kind: EscapeErrorKind::NonHex }),
+        };
+        v = (v << 4) | d as u16;
+    }
+    Ok(v)
+}
+
+/// Rewrite every undecodable escape to `\uFFFD`.
+///
+/// Returns `None` when the body is already clean, so callers only pay for
+/// a copy when a replacement happens.
+pub fn normalize(body: &[u8]) -> Option<(Vec<u8>, u32)> {
+    let mut err = next_invalid(body, 0)?;
+    let mut out = Vec::with_capacity(body.len() + 6);
+    let mut copied = 0;
+    let mut replaced = 0;
+    loop {
+        out.extend_from_slice(&body[copied..err.offset]);
+        out.extend_from_slice(b"\\uFFFD");
+        replaced += 1;
+        copied = err.offset + err.len;
+        match next_invalid(body, copied) {
+            Some(e) => err = e,
+            None => break,
+        }
+    }
+    out.extend_from_slice(&body[copied..]);
+    Some((out, replaced))
+}
+
+/// Append `body` to `out` with its escapes decoded to UTF-8. Expects a
+/// body with no undecodable escapes (after [`normalize`]); any it meets
+/// anyway are copied through undecoded.
+pub fn decode(body: &[u8], out: &mut Vec<u8>) {
+    let mut i = 0;
+    while i < body.len() {
+        let Some(end) = body[i..].iter().position(|&b| b == b'\\').map(|at| i + at) else {
+            out.extend_from_slice(&body[i..]);
+            return;
+        };
+        out.extend_from_slice(&body[i..end]);
+        i = end;
+        let byte = match body.get(i + 1) {
+            Some(b'b') => 0x08,
+            Some(b'f') => 0x0c,
+            Some(b'n') => b'\n',
+            Some(b'r') => b'\r',
+            Some(b't') => b'\t',
+            Some(&b @ (b'"' | b'\\' | b'/')) => b,
+            _ => {
+                let (c, len) = decode_u(body, i).unwrap_or(('\\', 1));
+                out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                i += len;
+                continue;
+            }
+        };
+        out.push(byte);
+        i += 2;
+    }
+}
+
+/// The character a valid `\u` escape (or surrogate pair) at `at` stands
+/// for, and the escape's length.
+fn decode_u(body: &[u8], at: usize) -> Option<(char, usize)> {
+    if body.get(at + 1) != Some(&b'u') { return None; }
+    let unit = read_u16(body, at).ok()?;
+    if !(0xD800..=0xDBFF).contains(&unit) {
+        return char::from_u32(unit as u32).map(|c| (c, 6));
+    }
+    if body.get(at + 6..at + 8) != Some(&b"\\u"[..]) { return None; }
+    let low = read_u16(body, at + 6).ok().filter(|low| (0xDC00..=0xDFFF).contains(low))?;
+    let c = 0x10000 + ((unit as u32 - 0xD800) << 10) + (low as u32 - 0xDC00);
+    char::from_u32(c).map(|c| (c, 12))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use EscapeErrorKind::*;
+
+    fn first(body: &[u8]) -> Option<(usize, EscapeErrorKind)> {
+        next_invalid(body, 0).map(|e| (e.offset, e.kind))
+    }
+
+    #[test]
+    fn valid_escapes_pass() {
+        assert_eq!(first(br#"a\"b\\c\/\b\f\n\r\t\u00e9\uD83D\uDE00"#), None);
+        assert_eq!(first("\u{1F600} ok".as_bytes()), None);
+    }
+
+    #[test]
+    fn invalid_forms_reported_at_backslash() {
+        assert_eq!(first(br#"ab\x"#), Some((2, UnknownEscape)));
+        assert_eq!(first(br#"a\u12G4"#), Some((1, NonHex)));
+        assert_eq!(first(br#"abc\u12"#), Some((3, Truncated)));
+        assert_eq!(first(br#"abc\"#), Some((3, Truncated)));
+        assert_eq!(first(br#"x\uD800y"#), Some((1, LoneSurrogate)));
+        assert_eq!(first(br#"\uD800A"#), Some((0, LoneSurrogate)));
+        assert_eq!(first(br#"ok\uDC00"#), Some((2, LoneSurrogate)));
+    }
+
+    #[test]
+    fn decode_yields_the_string_value() {
+        let mut out = Vec::new();
+        decode(br#"a\"b\\c\/\b\f\n\r\t\u00e9\uD83D\uDE00 ok"#, &mut out);
+        assert_eq!(out, "a\"b\\c/\u{8}\u{c}\n\r\t\u{e9}\u{1F600} ok".as_bytes());
+        out.clear();
+        decode(b"plain", &mut out);
+        assert_eq!(out, b"plain");
+    }
+
+    #[test]
+    fn normalize_replaces_only_bad_escapes() {
+        assert_eq!(normalize(br#"fine\n"#), None);
+        let (out, n) = normalize(br#"a\uD800b\u12G4\q"#).unwrap();
+        assert_eq!(out, br#"a\uFFFDb\uFFFDG4\uFFFD"#.to_vec());
+        assert_eq!(n, 3);
+    }
+}
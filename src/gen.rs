@@ -0,0 +1,527 @@
+//! Deterministic synthetic JSON documents for benchmarks and property tests.
+//!
+//! [`generate`] builds a document from a [`GenConfig`] and a seed;
+//! [`mutate`] applies N random edits and returns the paired right document
+//! together with the ground-truth [`Edit`] list; [`mutate_arrays`] does the
+//! same for edits inside arrays. Same seed, same output, on every platform. [`respace`] spreads whitespace through a compact
+//! document, for tests that parse the same tokens out of every layout.
+
+use alloc::format;
+use alloc::string::{String, ToString};
+use alloc::vec::Vec;
+
+/// xorshift64 — tiny, fast, and stable across platforms.
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        // Zero is a fixed point of xorshift; splitmix the seed first.
+        let mut z = seed.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        Self((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    /// Uniform in `0..n` (`n > 0`).
+    pub fn below(&mut self, n: u64) -> u64 {
+        self.next_u64() % n
+    }
+
+    /// Uniform in `lo..=hi`.
+    pub fn range(&mut self, lo: u32, hi: u32) -> u32 {
+        lo + self.below((hi - lo) as u64 + 1) as u32
+    }
+}
+
+/// Relative weights of leaf value types.
+#[derive(Debug, Clone, Copy)]
+pub struct ValueMix {
+    pub string: u32,
+    pub number: u32,
+    pub boolean: u32,
+    pub null: u32,
+}
+
+/// Shape parameters for [`generate`].
+#[derive(Debug, Clone)]
+pub struct GenConfig {
+    /// Maximum container nesting below the root.
+    pub depth: u32,
+    /// Object member count range.
+    pub fan_out: (u32, u32),
+    /// Array length range.
+    pub array_len: (u32, u32),
+    /// Key length range, in characters drawn from `key_alphabet`.
+    pub key_len: (u32, u32),
+    pub key_alphabet: &'static [u8],
+    /// Raw JSON string-body fragments concatenated to form string values.
+    pub string_pieces: &'static [&'static str],
+    /// Maximum pieces per string value.
+    pub string_len: u32,
+    /// Percent chance that a non-root node at remaining depth > 0 is a
+    /// container rather than a leaf.
+    pub container_pct: u32,
+    pub values: ValueMix,
+}
+
+impl Default for GenConfig {
+    fn default() -> Self {
+        Self {
+            depth: 4,
+            fan_out: (1, 8),
+            array_len: (0, 8),
+            key_len: (3, 12),
+            key_alphabet: b"abcdefghijklmnopqrstuvwxyz_",
+            string_pieces: &["a", "e", "i", "o", "u", "s", "t", " "],
+            string_len: 16,
+            container_pct: 40,
+            values: ValueMix { string: 4, number: 4, boolean: 1, null: 1 },
+        }
+    }
+}
+
+impl GenConfig {
+    /// Small, collision-heavy documents for differential testing: one-char
+    /// keys (so duplicate keys are common), empty keys, and escapes.
+    pub fn adversarial() -> Self {
+        Self {
+            depth: 3,
+            fan_out: (0, 4),
+            array_len: (0, 4),
+            key_len: (0, 1),
+            key_alphabet: b"ab0",
+            string_pieces: &["x", "1", "\\\"", "\\\\", "\\u00e9", "\\n"],
+            string_len: 3,
+            container_pct: 50,
+            values: ValueMix { string: 2, number: 2, boolean: 1, null: 1 },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Node {
+    Null,
+    Bool(bool),
+    Number(i64),
+    /// Raw string body (already JSON-escaped).
+    String(String),
+    Array(Vec<Node>),
+    /// Members in document order; keys may repeat.
+    Object(Vec<(String, Node)>),
+}
+
+impl Node {
+    pub fn to_json(&self) -> String {
+        let mut out = String::new();
+        self.write_json(&mut out);
+        out
+    }
+
+    pub fn write_json(&self, out: &mut String) {
+        match self {
+            Node::Null => out.push_str("null"),
+            Node::Bool(b) => out.push_str(if *b { "true" } else { "false" }),
+            Node::Number(n) => out.push_str(&n.to_string()),
+            Node::String(s) => {
+                out.push('"');
+                out.push_str(s);
+                out.push('"');
+            }
+            Node::Array(items) => {
+                out.push('[');
+                for (i, v) in items.iter().enumerate() {
+                    if i > 0 { out.push(','); }
+                    v.write_json(out);
+                }
+                out.push(']');
+            }
+            Node::Object(members) => {
+                out.push('{');
+                for (i, (k, v)) in members.iter().enumerate() {
+                    if i > 0 { out.push(','); }
+                    out.push('"');
+                    out.push_str(k);
+                    out.push_str("\":");
+                    v.write_json(out);
+                }
+                out.push('}');
+            }
+        }
+    }
+
+    fn is_container(&self) -> bool {
+        matches!(self, Node::Array(_) | Node::Object(_))
+    }
+}
+
+/// Generate a document. The root is always an object.
+pub fn generate(cfg: &GenConfig, seed: u64) -> Node {
+    let mut rng = Rng::new(seed);
+    gen_object(cfg, &mut rng, cfg.depth)
+}
+
+fn gen_node(cfg: &GenConfig, rng: &mut Rng, depth: u32) -> Node {
+    if depth > 0 && rng.below(100) < cfg.container_pct as u64 {
+        if rng.below(2) == 0 {
+            gen_object(cfg, rng, depth - 1)
+        } else {
+            let n = rng.range(cfg.array_len.0, cfg.array_len.1);
+            Node::Array((0..n).map(|_| gen_node(cfg, rng, depth - 1)).collect())
+        }
+    } else {
+        gen_leaf(cfg, rng)
+    }
+}
+
+fn gen_object(cfg: &GenConfig, rng: &mut Rng, depth: u32) -> Node {
+    let n = rng.range(cfg.fan_out.0, cfg.fan_out.1);
+    Node::Object((0..n).map(|_| (gen_key(cfg, rng), gen_node(cfg, rng, depth))).collect())
+}
+
+fn gen_key(cfg: &GenConfig, rng: &mut Rng) -> String {
+    let n = rng.range(cfg.key_len.0, cfg.key_len.1);
+    (0..n)
+        .map(|_| cfg.key_alphabet[rng.below(cfg.key_alphabet.len() as u64) as usize] as char)
+        .collect()
+}
+
+fn gen_leaf(cfg: &GenConfig, rng: &mut Rng) -> Node {
+    let m = cfg.values;
+    let total = (m.string + m.number + m.boolean + m.null).max(1) as u64;
+    let mut pick = rng.below(total) as u32;
+    if pick < m.string {
+        let n = rng.range(0, cfg.string_len);
+        let body = (0..n)
+            .map(|_| cfg.string_pieces[rng.below(cfg.string_pieces.len() as u64) as usize])
+            .collect();
+        return Node::String(body);
+    }
+    pick -= m.string;
+    if pick < m.number {
+        return Node::Number(rng.below(2001) as i64 - 1000);
+    }
+    pick -= m.number;
+    if pick < m.boolean {
+        return Node::Bool(rng.below(2) == 0);
+    }
+    Node::Null
+}
+
+/// One ground-truth edit. Paths are JSON Pointers into the document the
+/// edit was applied to (i.e. after all previous edits).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Edit {
+    /// Leaf at `path` got a different value.
+    Change { path: String },
+    /// New member inserted at `path`.
+    Add { path: String },
+    /// Member at `path` deleted (with its whole subtree).
+    Remove { path: String },
+    /// Member moved from `from` to `to`, value unchanged.
+    Move { from: String, to: String },
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Member(usize),
+    Index(usize),
+}
+
+/// Apply `edits` random edits to a copy of `doc`.
+///
+/// Adds, removes and moves only touch object members so array indices of
+/// unrelated elements stay put and the edit list is exact. Edits that find
+/// no eligible target are skipped, so the list may be shorter than asked.
+pub fn mutate(doc: &Node, edits: usize, seed: u64) -> (Node, Vec<Edit>) {
+    let mut rng = Rng::new(seed ^ 0xD1FF_C0AE);
+    let mut out = doc.clone();
+    let mut log = Vec::with_capacity(edits);
+    let mut fresh = 0u32;
+
+    for _ in 0..edits {
+        match rng.below(4) {
+            0 => {
+                let leaves = collect(&out, &mut Vec::new(), &|n| !n.is_container());
+                if leaves.is_empty() { continue; }
+                let path = &leaves[rng.below(leaves.len() as u64) as usize];
+                let node = node_mut(&mut out, path);
+                *node = match node {
+                    Node::Number(n) => Node::Number(*n + 1),
+                    Node::String(s) => Node::String(format!("{s}~")),
+                    Node::Bool(b) => Node::Bool(!*b),
+                    _ => Node::Number(0),
+                };
+                log.push(Edit::Change { path: pointer(&out, path) });
+            }
+            1 => {
+                let objects = collect(&out, &mut Vec::new(), &|n| matches!(n, Node::Object(_)));
+                let path = &objects[rng.below(objects.len() as u64) as usize];
+                fresh += 1;
+                let key = format!("new{fresh}");
+                let Node::Object(members) = node_mut(&mut out, path) else { unreachable!() };
+                let at = rng.below(members.len() as u64 + 1) as usize;
+                members.insert(at, (key, Node::Number(fresh as i64)));
+                let mut p = path.clone();
+                p.push(Step::Member(at));
+                log.push(Edit::Add { path: pointer(&out, &p) });
+            }
+            2 | 3 => {
+                let members = collect_members(&out);
+                if members.is_empty() { continue; }
+                let from = members[rng.below(members.len() as u64) as usize].clone();
+                let from_ptr = pointer(&out, &from);
+                let (key, value) = take_member(&mut out, &from);
+                if rng.below(2) == 0 {
+                    log.push(Edit::Remove { path: from_ptr });
+                    continue;
+                }
+                let objects = collect(&out, &mut Vec::new(), &|n| matches!(n, Node::Object(_)));
+                let dest = &objects[rng.below(objects.len() as u64) as usize];
+                let Node::Object(m) = node_mut(&mut out, dest) else { unreachable!() };
+                m.push((key, value));
+                let mut p = dest.clone();
+                p.push(Step::Member(m.len() - 1));
+                log.push(Edit::Move { from: from_ptr, to: pointer(&out, &p) });
+            }
+            _ => unreachable!(),
+        }
+    }
+    (out, log)
+}
+
+/// Kind of edit [`mutate_arrays`] may make.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EditKind {
+    Change,
+    Add,
+    Remove,
+    Move,
+}
+
+/// Apply up to `edits` random edits of `kinds` to array elements of a copy
+/// of `doc`.
+///
+/// Only arrays of leaves are edited, each at most once, so no edit shifts
+/// another's indices: `Remove` and `Move::from` point into `doc`, `Add`,
+/// `Change` and `Move::to` into the result. Inserted and changed values
+/// are fresh strings, equal to nothing else in the document. Stops early
+/// when no eligible array is left.
+pub fn mutate_arrays(doc: &Node, edits: usize, kinds: &[EditKind], seed: u64) -> (Node, Vec<Edit>) {
+    let mut rng = Rng::new(seed ^ 0xA22A_7ED1);
+    let mut out = doc.clone();
+    let mut log = Vec::with_capacity(edits);
+    let mut arrays = collect(&out, &mut Vec::new(), &|n| match n {
+        Node::Array(items) => !items.iter().any(Node::is_container),
+        _ => false,
+    });
+    let mut fresh = 0u32;
+
+    while log.len() < edits {
+        let kind = kinds[rng.below(kinds.len() as u64) as usize];
+        let eligible: Vec<usize> = (0..arrays.len())
+            .filter(|&a| {
+                let Node::Array(items) = node_mut(&mut out, &arrays[a]) else { unreachable!() };
+                match kind {
+                    EditKind::Add => true,
+                    EditKind::Change | EditKind::Remove => !items.is_empty(),
+                    EditKind::Move => items.len() > 1,
+                }
+            })
+            .collect();
+        if eligible.is_empty() { break; }
+        let path = arrays.swap_remove(eligible[rng.below(eligible.len() as u64) as usize]);
+        let Node::Array(items) = node_mut(&mut out, &path) else { unreachable!() };
+        let at = |i: usize| {
+            let mut p = path.clone();
+            p.push(Step::Index(i));
+            p
+        };
+        fresh += 1;
+        match kind {
+            EditKind::Change => {
+                let i = rng.below(items.len() as u64) as usize;
+                items[i] = Node::String(format!("changed{fresh}"));
+                log.push(Edit::Change { path: pointer(&out, &at(i)) });
+            }
+            EditKind::Add => {
+                let i = rng.below(items.len() as u64 + 1) as usize;
+                items.insert(i, Node::String(format!("added{fresh}")));
+                log.push(Edit::Add { path: pointer(&out, &at(i)) });
+            }
+            EditKind::Remove => {
+                let i = rng.below(items.len() as u64) as usize;
+                let from = pointer(doc, &at(i));
+                items.remove(i);
+                log.push(Edit::Remove { path: from });
+            }
+            EditKind::Move => {
+                let i = rng.below(items.len() as u64) as usize;
+                let j = (i + 1 + rng.below(items.len() as u64 - 1) as usize) % items.len();
+                let from = pointer(doc, &at(i));
+                let item = items.remove(i);
+                items.insert(j, item);
+                log.push(Edit::Move { from, to: pointer(&out, &at(j)) });
+            }
+        }
+    }
+    (out, log)
+}
+
+fn collect(node: &Node, at: &mut Vec<Step>, want: &dyn Fn(&Node) -> bool) -> Vec<Vec<Step>> {
+    let mut found = Vec::new();
+    if want(node) { found.push(at.clone()); }
+    match node {
+        Node::Array(items) => {
+            for (i, v) in items.iter().enumerate() {
+                at.push(Step::Index(i));
+                found.extend(collect(v, at, want));
+                at.pop();
+            }
+        }
+        Node::Object(members) => {
+            for (i, (_, v)) in members.iter().enumerate() {
+                at.push(Step::Member(i));
+                found.extend(collect(v, at, want));
+                at.pop();
+            }
+        }
+        _ => {}
+    }
+    found
+}
+
+fn collect_members(doc: &Node) -> Vec<Vec<Step>> {
+    collect(doc, &mut Vec::new(), &|_| true)
+        .into_iter()
+        .filter(|p| matches!(p.last(), Some(Step::Member(_))))
+        .collect()
+}
+
+fn node_mut<'a>(mut node: &'a mut Node, path: &[Step]) -> &'a mut Node {
+    for step in path {
+        node = match (node, *step) {
+            (Node::Array(items), Step::Index(i)) => &mut items[i],
+            (Node::Object(members), Step::Member(i)) => &mut members[i].1,
+            _ => unreachable!("stale path"),
+        };
+    }
+    node
+}
+
+fn take_member(doc: &mut Node, path: &[Step]) -> (String, Node) {
+    let (last, parent) = path.split_last().expect("member path");
+    let Step::Member(i) = *last else { unreachable!() };
+    let Node::Object(members) = node_mut(doc, parent) else { unreachable!() };
+    members.remove(i)
+}
+
+/// Render a step path as a JSON Pointer (RFC 6901), escaping `~` and `/`.
+fn pointer(doc: &Node, path: &[Step]) -> String {
+    let mut out = String::new();
+    let mut node = doc;
+    for step in path {
+        out.push('/');
+        node = match (node, *step) {
+            (Node::Array(items), Step::Index(i)) => {
+                out.push_str(&i.to_string());
+                &items[i]
+            }
+            (Node::Object(members), Step::Member(i)) => {
+                out.push_str(&members[i].0.replace('~', "~0").replace('/', "~1"));
+                &members[i].1
+            }
+            _ => unreachable!("stale path"),
+        };
+    }
+    out
+}
+
+/// The four whitespace bytes JSON allows, and CRLF.
+pub const WHITESPACE: [&[u8]; 5] = [b" ", b"\t", b"\n", b"\r", b"\r\n"];
+
+/// Offsets of compact `json` where whitespace may go: around the root and
+/// between any two tokens, never inside a string or a literal.
+pub fn whitespace_gaps(json: &[u8]) -> Vec<usize> {
+    let literal = |b: u8| !matches!(b, b'{' | b'}' | b'[' | b']' | b':' | b',' | b'"');
+    let mut gaps = Vec::new();
+    let mut in_string = false;
+    let mut escaped = false;
+    for i in 0..=json.len() {
+        if !in_string {
+            let inside_literal = i > 0 && i < json.len() && literal(json[i - 1]) && literal(json[i]);
+            if !inside_literal { gaps.push(i); }
+        }
+        let Some(&b) = json.get(i) else { break };
+        if in_string && escaped {
+            escaped = false;
+        } else if in_string && b == b'\\' {
+            escaped = true;
+        } else if b == b'"' {
+            in_string = !in_string;
+        }
+    }
+    gaps
+}
+
+/// `json` with `ws[k % ws.len()]` inserted at the `k`-th of `gaps`
+/// (ascending offsets), and the new offset of each byte of `json`.
+pub fn respace(json: &[u8], gaps: &[usize], ws: &[&[u8]]) -> (Vec<u8>, Vec<usize>) {
+    let mut out = Vec::with_capacity(json.len() + gaps.len() * 2);
+    let mut moved = Vec::with_capacity(json.len());
+    let mut next = gaps.iter().enumerate().peekable();
+    for i in 0..=json.len() {
+        while let Some((k, _)) = next.next_if(|&(_, &g)| g == i) {
+            out.extend_from_slice(ws[k % ws.len()]);
+        }
+        if let Some(&b) = json.get(i) {
+            moved.push(out.len());
+            out.push(b);
+        }
+    }
+    (out, moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_document() {
+        let cfg = GenConfig::default();
+        assert_eq!(generate(&cfg, 7).to_json(), generate(&cfg, 7).to_json());
+        assert_ne!(generate(&cfg, 7).to_json(), generate(&cfg, 8).to_json());
+    }
+
+    #[test]
+    fn mutate_reports_ground_truth() {
+        let doc = generate(&GenConfig::default(), 3);
+        let (right, edits) = mutate(&doc, 10, 3);
+        assert!(!edits.is_empty());
+        assert_ne!(doc, right);
+        assert_eq!(mutate(&doc, 10, 3), (right, edits));
+    }
+
+    #[test]
+    fn mutate_arrays_edits_each_array_once() {
+        let doc = Node::Object(vec![
+            ("a".to_string(), Node::Array(vec![Node::Number(1), Node::Number(2), Node::Number(3)])),
+            ("b".to_string(), Node::Array(vec![Node::Array(vec![Node::Null])])),
+        ]);
+        // `b` holds a container, so only `/a` and `/b/0` are editable.
+        let (right, edits) = mutate_arrays(&doc, 5, &[EditKind::Move], 1);
+        assert_eq!(edits.len(), 1);
+        let Edit::Move { from, to } = &edits[0] else { panic!("{:?}", edits) };
+        assert!(from.starts_with("/a/") && to.starts_with("/a/") && from != to);
+        assert_ne!(doc, right);
+        let (_, edits) = mutate_arrays(&doc, 5, &[EditKind::Add], 1);
+        assert_eq!(edits.len(), 2);
+    }
+}
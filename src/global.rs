@@ -0,0 +1,35 @@
+//! Process-wide state: the engine handle registry, tenant groups, index
+//! pools and, without std, the creation error.
+//!
+//! Each is a static [`Global`], created empty and defaulted on first use,
+//! and reached only through [`with_global`]: behind a mutex with std, a
+//! bare cell without it.
+
+#[cfg(feature = "std")]
+type Cell<T> = std::sync::Mutex<Option<T>>;
+#[cfg(not(feature = "std"))]
+type Cell<T> = core::cell::RefCell<Option<T>>;
+
+pub struct Global<T>(Cell<T>);
+
+// SAFETY: without std there are no threads to share it with; wasm
+// exports run one at a time. With std the mutex is `Sync` already.
+#[cfg(not(feature = "std"))]
+unsafe impl<T> Sync for Global<T> {}
+
+impl<T> Global<T> {
+    pub const fn new() -> Self {
+        Self(Cell::new(None))
+    }
+}
+
+/// Run `f` on the value in `global`, defaulting it first if unused. `f`
+/// must not reach the same global again; move anything costly to drop out
+/// of the closure so the lock is held only for the bookkeeping.
+pub fn with_global<T: Default, R>(global: &Global<T>, f: impl FnOnce(&mut T) -> R) -> R {
+    #[cfg(feature = "std")]
+    let mut value = global.0.lock().unwrap_or_else(|e| e.into_inner());
+    #[cfg(not(feature = "std"))]
+    let mut value = global.0.borrow_mut();
+    f(value.get_or_insert_with(T::default))
+}
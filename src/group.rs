@@ -0,0 +1,114 @@
+//! Per-tenant quotas for hosts that serve many tenants from one instance.
+//!
+//! Engines created with `create_engine_in_group` are accounted into their
+//! group: each counts against `max_engines`, and its bytes against
+//! `max_total_bytes`. An engine's bytes are a fixed reservation taken at
+//! creation (input buffers plus its full arena limit) plus the token
+//! memory of its committed sides and, while a commit runs, the structural
+//! index it checked out of the group's pool (`index_pool.rs`; idle pooled
+//! indexes are not charged). Creation or a commit that would go
+//! over fails with `Status::GroupQuotaExceeded`; clear and destroy give the
+//! bytes back. A group without limits is accounted but unlimited.
+
+use crate::global::{with_global, Global};
+use crate::FxHashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GroupLimits {
+    pub max_engines: u32,
+    pub max_total_bytes: u64,
+}
+
+impl Default for GroupLimits {
+    fn default() -> Self {
+        Self { max_engines: u32::MAX, max_total_bytes: u64::MAX }
+    }
+}
+
+/// Current accounting for one group.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GroupUsage {
+    pub engines: u32,
+    pub bytes: u64,
+}
+
+impl GroupUsage {
+    pub const SIZE: usize = 12;
+
+    /// `[u32 engines][u64 bytes]`, little-endian.
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(&self.engines.to_le_bytes());
+        buf[4..12].copy_from_slice(&self.bytes.to_le_bytes());
+        buf
+    }
+}
+
+#[derive(Debug, Default)]
+struct Group {
+    limits: GroupLimits,
+    usage: GroupUsage,
+}
+
+type Groups = FxHashMap<u32, Group>;
+
+static GROUPS: Global<Groups> = Global::new();
+
+fn with_groups<R>(f: impl FnOnce(&mut Groups) -> R) -> R {
+    with_global(&GROUPS, f)
+}
+
+pub fn set_limits(id: u32, limits: GroupLimits) {
+    with_groups(|g| g.entry(id).or_default().limits = limits);
+}
+
+pub fn usage(id: u32) -> GroupUsage {
+    with_groups(|g| g.get(&id).map(|group| group.usage).unwrap_or_default())
+}
+
+/// Add one engine holding `bytes`, if both fit.
+pub fn join(id: u32, bytes: u64) -> bool {
+    with_groups(|g| {
+        let group = g.entry(id).or_default();
+        let usage = group.usage;
+        if usage.engines >= group.limits.max_engines || !fits(group, bytes) {
+            return false;
+        }
+        group.usage = GroupUsage { engines: usage.engines + 1, bytes: usage.bytes.saturating_add(bytes) };
+        true
+    })
+}
+
+/// Charge `bytes` more to an existing member, if they fit.
+pub fn grow(id: u32, bytes: u64) -> bool {
+    with_groups(|g| {
+        let group = g.entry(id).or_default();
+        if !fits(group, bytes) {
+            return false;
+        }
+        group.usage.bytes = group.usage.bytes.saturating_add(bytes);
+        true
+    })
+}
+
+pub fn shrink(id: u32, bytes: u64) {
+    with_groups(|g| {
+        if let Some(group) = g.get_mut(&id) {
+            group.usage.bytes = group.usage.bytes.saturating_sub(bytes);
+        }
+    });
+}
+
+/// Remove one engine and everything it was charged.
+pub fn leave(id: u32, bytes: u64) {
+    with_groups(|g| {
+        if let Some(group) = g.get_mut(&id) {
+            group.usage.engines = group.usage.engines.saturating_sub(1);
+            group.usage.bytes = group.usage.bytes.saturating_sub(bytes);
+        }
+    });
+}
+
+fn fits(group: &Group, bytes: u64) -> bool {
+    group.usage.bytes.saturating_add(bytes) <= group.limits.max_total_bytes
+}
@@ -0,0 +1,133 @@
+//! Integer handles in place of engine addresses, behind the `handles`
+//! feature.
+//!
+//! By default an engine crosses the ABI as its address, checked by its
+//! magic word alone: a stale pointer into freed memory that was reused
+//! for another engine passes that check. With `handles`, the
+//! `create_engine*` exports register the engine here and return a handle
+//! instead, and every export resolves its `engine_ptr` argument through
+//! the registry before touching memory. Parameters keep their pointer
+//! type, so the ABI is the same; hosts only stop treating the value as an
+//! address. `create_engine_handle` returns it as a `u32`.
+//!
+//! A handle is a slot index (low 16 bits) and the slot's generation (high
+//! 16 bits, never 0, so no handle is 0). Destroying an engine frees its
+//! slot and bumps the generation: its handle, and any integer the
+//! registry did not issue, resolve to nothing, and the export returns
+//! `InvalidHandle`. A slot whose generation would wrap is retired rather
+//! than reused, so a destroyed handle never resolves again.
+
+use crate::engine::Engine;
+use crate::error::EngineError;
+use crate::global::{with_global, Global};
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+
+/// Live engines one registry holds at most.
+pub const MAX_ENGINES: usize = 1 << 16;
+
+struct Slot {
+    generation: u16,
+    engine: Option<Box<Engine>>,
+}
+
+#[derive(Default)]
+struct Registry {
+    slots: Vec<Slot>,
+    /// Free slot indexes, reused last-freed first.
+    free: Vec<u16>,
+}
+
+// SAFETY: an engine is only reached through the exports, which the
+// pointer API already lets a host call from any thread; the registry
+// adds no sharing of its own.
+unsafe impl Send for Registry {}
+
+static REGISTRY: Global<Registry> = Global::new();
+
+fn with_registry<R>(f: impl FnOnce(&mut Registry) -> R) -> R {
+    with_global(&REGISTRY, f)
+}
+
+fn split(handle: usize) -> Option<(usize, u16)> {
+    let handle = u32::try_from(handle).ok()?;
+    let generation = (handle >> 16) as u16;
+    (generation != 0).then_some(((handle & 0xFFFF) as usize, generation))
+}
+
+/// Register `engine`; its handle, or `TooManyEngines` with every slot
+/// live or retired.
+pub fn insert(engine: Engine) -> Result<u32, EngineError> {
+    with_registry(|r| {
+        let index = match r.free.pop() {
+            Some(index) => index as usize,
+            None if r.slots.len() < MAX_ENGINES => {
+                r.slots.push(Slot { generation: 1, engine: None });
+                r.slots.len() - 1
+            }
+            None => return Err(EngineError::TooManyEngines),
+        };
+        let slot = &mut r.slots[index];
+        slot.engine = Some(Box::new(engine));
+        Ok(u32::from(slot.generation) << 16 | index as u32)
+    })
+}
+
+/// The engine `handle` names, if it is live. The box stays put until
+/// `remove`, so the pointer outlives the registry lock.
+pub fn resolve(handle: usize) -> Option<*mut Engine> {
+    let (index, generation) = split(handle)?;
+    with_registry(|r| {
+        let slot = r.slots.get_mut(index).filter(|s| s.generation == generation)?;
+        slot.engine.as_deref_mut().map(|e| e as *mut Engine)
+    })
+}
+
+/// Take the engine `handle` names out of the registry, ending the handle.
+pub fn remove(handle: usize) -> Option<Box<Engine>> {
+    let (index, generation) = split(handle)?;
+    with_registry(|r| {
+        let slot = r.slots.get_mut(index).filter(|s| s.generation == generation)?;
+        let engine = slot.engine.take()?;
+        if let Some(next) = slot.generation.checked_add(1) {
+            slot.generation = next;
+            r.free.push(index as u16);
+        }
+        Some(engine)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+
+    fn engine() -> Engine { Engine::new(EngineConfig::default(), 0).unwrap() }
+
+    /// One test, as the registry is shared by the whole test binary.
+    #[test]
+    fn handles_end_with_their_engine() {
+        let a = insert(engine()).unwrap() as usize;
+        assert!(resolve(a).is_some());
+        assert!(remove(a).is_some());
+        assert!(resolve(a).is_none() && remove(a).is_none());
+        // The slot comes back under a new generation.
+        let b = insert(engine()).unwrap() as usize;
+        assert_eq!(b & 0xFFFF, a & 0xFFFF);
+        assert_ne!(b, a);
+        assert!(resolve(a).is_none());
+        for forged in [0, 1, 0xFFFF, usize::MAX, b + (1 << 20)] {
+            assert!(resolve(forged).is_none(), "{forged:#x}");
+        }
+
+        // A slot at the last generation is retired once freed.
+        let index = b & 0xFFFF;
+        with_registry(|r| r.slots[index].generation = u16::MAX);
+        let last = (u16::MAX as usize) << 16 | index;
+        assert!(remove(last).is_some());
+        assert!(resolve(last).is_none());
+        let next = insert(engine()).unwrap() as usize;
+        assert_ne!(next & 0xFFFF, index);
+        assert!(remove(next).is_some());
+    }
+}
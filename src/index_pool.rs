@@ -0,0 +1,204 @@
+//! Structural indexes shared across engines.
+//!
+//! An engine needs a `StructuralIndex` only while a commit builds it and
+//! parses against it, so engines check one out for the commit and hand it
+//! back afterwards instead of keeping two each. A host running hundreds of
+//! mostly idle engines then holds a few indexes' memory, not hundreds.
+//!
+//! One pool per group (`create_engine_in_group`); engines outside any
+//! group share their own. Checkout takes an idle index if the pool has
+//! one, else allocates a fresh one, so an empty pool is never an error.
+//! Returned indexes stay idle, grown capacity included, up to the pool's
+//! `max_idle` (default [`DEFAULT_MAX_IDLE`]); past that they are freed.
+//! Every index is cleared on checkout, so nothing from one engine's input
+//! is visible to the next.
+//!
+//! Idle indexes are not charged to the group's `max_total_bytes`; a
+//! checked-out one is, as part of the committing engine's footprint.
+
+use crate::global::{with_global, Global};
+use crate::simd_index::StructuralIndex;
+use crate::FxHashMap;
+use alloc::vec::Vec;
+
+/// Idle indexes a pool keeps unless `set_max_idle` says otherwise.
+pub const DEFAULT_MAX_IDLE: u32 = 4;
+
+/// Pool counters since the pool was first used.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// Indexes waiting in the pool.
+    pub idle: u32,
+    pub checkouts: u32,
+    /// Checkouts served from the pool rather than freshly allocated.
+    pub reuses: u32,
+    /// Returns freed because the pool was at `max_idle`.
+    pub discards: u32,
+}
+
+impl PoolStats {
+    pub const SIZE: usize = 16;
+
+    /// `[u32 idle][u32 checkouts][u32 reuses][u32 discards]`, little-endian.
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        for (chunk, v) in buf.chunks_exact_mut(4).zip([self.idle, self.checkouts, self.reuses, self.discards]) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        buf
+    }
+}
+
+struct Pool {
+    idle: Vec<StructuralIndex>,
+    max_idle: u32,
+    stats: PoolStats,
+}
+
+impl Default for Pool {
+    fn default() -> Self {
+        Self { idle: Vec::new(), max_idle: DEFAULT_MAX_IDLE, stats: PoolStats::default() }
+    }
+}
+
+/// Keyed by group id; `None` is the pool of ungrouped engines.
+type Pools = FxHashMap<Option<u32>, Pool>;
+
+static POOLS: Global<Pools> = Global::new();
+
+// Indexes are moved out of the closure before they are cleared or
+// dropped.
+fn with_pools<R>(f: impl FnOnce(&mut Pools) -> R) -> R {
+    with_global(&POOLS, f)
+}
+
+/// An index checked out of `pool`; dropping it returns it.
+pub struct PooledIndex {
+    index: StructuralIndex,
+    pool: Option<u32>,
+}
+
+/// Take an index from `pool`, or allocate one if it has none idle.
+pub fn checkout(pool: Option<u32>) -> PooledIndex {
+    let idle = with_pools(|p| {
+        let pool = p.entry(pool).or_default();
+        let idle = pool.idle.pop();
+        pool.stats.checkouts += 1;
+        pool.stats.reuses += idle.is_some() as u32;
+        pool.stats.idle = pool.idle.len() as u32;
+        idle
+    });
+    let mut index = idle.unwrap_or_default();
+    index.clear();
+    PooledIndex { index, pool }
+}
+
+/// Cap `pool`'s idle indexes at `max_idle`, freeing any over it now.
+pub fn set_max_idle(pool: Option<u32>, max_idle: u32) {
+    let freed = with_pools(|p| {
+        let pool = p.entry(pool).or_default();
+        pool.max_idle = max_idle;
+        let keep = pool.idle.len().min(max_idle as usize);
+        let freed = pool.idle.split_off(keep);
+        pool.stats.idle = keep as u32;
+        freed
+    });
+    drop(freed);
+}
+
+pub fn stats(pool: Option<u32>) -> PoolStats {
+    with_pools(|p| p.get(&pool).map(|pool| pool.stats).unwrap_or_default())
+}
+
+impl Drop for PooledIndex {
+    fn drop(&mut self) {
+        let index = core::mem::take(&mut self.index);
+        let discarded = with_pools(|p| {
+            let pool = p.entry(self.pool).or_default();
+            if pool.idle.len() < pool.max_idle as usize {
+                pool.idle.push(index);
+                pool.stats.idle = pool.idle.len() as u32;
+                None
+            } else {
+                pool.stats.discards += 1;
+                Some(index)
+            }
+        });
+        drop(discarded);
+    }
+}
+
+impl core::ops::Deref for PooledIndex {
+    type Target = StructuralIndex;
+    fn deref(&self) -> &StructuralIndex { &self.index }
+}
+
+impl core::ops::DerefMut for PooledIndex {
+    fn deref_mut(&mut self) -> &mut StructuralIndex { &mut self.index }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Pools are process-wide: each test uses its own group ids.
+
+    #[test]
+    fn returned_index_is_reused_and_cleared() {
+        let pool = Some(7001);
+        let mut a = checkout(pool);
+        a.build(br#"{"a":[1,2,3]}"#);
+        let grown = a.masks.capacity();
+        assert!(grown > 0 && a.len > 0);
+        drop(a);
+        assert_eq!(stats(pool), PoolStats { idle: 1, checkouts: 1, reuses: 0, discards: 0 });
+
+        let b = checkout(pool);
+        assert_eq!((b.masks.len(), b.len), (0, 0), "no state from the last engine");
+        assert_eq!(b.masks.capacity(), grown, "same allocation");
+        assert_eq!(stats(pool), PoolStats { idle: 0, checkouts: 2, reuses: 1, discards: 0 });
+    }
+
+    #[test]
+    fn exhausted_pool_allocates_and_cap_frees_extras() {
+        let pool = Some(7002);
+        set_max_idle(pool, 2);
+        let held: Vec<PooledIndex> = (0..5).map(|_| checkout(pool)).collect();
+        assert_eq!(stats(pool).reuses, 0);
+        drop(held);
+        assert_eq!(stats(pool), PoolStats { idle: 2, checkouts: 5, reuses: 0, discards: 3 });
+
+        set_max_idle(pool, 0);
+        assert_eq!(stats(pool).idle, 0);
+        drop(checkout(pool));
+        assert_eq!(stats(pool), PoolStats { idle: 0, checkouts: 6, reuses: 0, discards: 4 });
+        assert_eq!(stats(Some(7003)), PoolStats::default(), "pools are per group");
+    }
+
+    #[test]
+    fn parallel_checkouts_keep_counts_consistent() {
+        let pool = Some(7004);
+        let (threads, rounds) = (8, 200);
+        let work = move || {
+            for i in 0..rounds {
+                let mut index = checkout(pool);
+                assert_eq!(index.len, 0);
+                index.build(&alloc::vec![b' '; 64 * (i % 4 + 1)]);
+            }
+        };
+        // wasm32-wasip1 has no threads; there the same work runs inline.
+        let spawned: Vec<_> = (0..threads).filter_map(|_| std::thread::Builder::new().spawn(work).ok()).collect();
+        let inline = threads - spawned.len();
+        for handle in spawned {
+            handle.join().unwrap();
+        }
+        for _ in 0..inline {
+            work();
+        }
+        let s = stats(pool);
+        assert_eq!(s.checkouts, (threads * rounds) as u32);
+        assert!(s.idle <= DEFAULT_MAX_IDLE);
+        // Every index came back: kept idle, or freed at the cap.
+        assert_eq!(s.checkouts - s.reuses, s.idle + s.discards);
+    }
+}
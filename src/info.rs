@@ -0,0 +1,297 @@
+//! Build description for hosts: which optional export groups this binary
+//! has, the result format it writes and the limits a default config
+//! starts from, readable before any engine exists. A host that ships
+//! against several binaries checks a `FEATURE_*` bit instead of probing
+//! for each export by name.
+//!
+//! Record (40 bytes, little-endian): `[u16 info_version][u16 len]
+//! [u32 format_version][u64 features][u32 max_memory_bytes]
+//! [u32 max_input_size][u32 max_object_keys][u32 max_full_array_size]
+//! [u16 hash_window_size][u8 simd][u8 reserved][u32 max_primitive_bytes]`.
+//!
+//! - `info_version`: `INFO_VERSION`; fields are only ever appended, and
+//!   `len` covers the whole record, so a reader skips what it doesn't know.
+//! - `format_version`: as `get_format_version`.
+//! - `features`: `FEATURE_*` bits; bits not defined here are 0.
+//! - the limits: `EngineConfig::default()`.
+//! - `simd`: 1 when the structural index was built for `simd128`.
+//!
+//! `get_build_flags` answers the SIMD question alone, as `BUILD_FLAG_*`
+//! bits, for a loader choosing between a `simd128` and a scalar binary.
+
+use crate::config::{
+    DEFAULT_HASH_WINDOW_SIZE, DEFAULT_MAX_FULL_ARRAY_SIZE, DEFAULT_MAX_INPUT_SIZE, DEFAULT_MAX_MEMORY_BYTES,
+    DEFAULT_MAX_OBJECT_KEYS, DEFAULT_MAX_PRIMITIVE_BYTES,
+};
+use crate::memory::FORMAT_VERSION;
+
+pub const INFO_VERSION: u16 = 1;
+pub const INFO_LEN: usize = 40;
+
+/// `push_left` / `push_right`.
+pub const FEATURE_STREAMING: u64 = 1 << 0;
+/// `finalize_step`, `cancel`, `get_progress`.
+pub const FEATURE_STEPPED: u64 = 1 << 1;
+/// `commit_third`, `get_third_input_ptr`.
+pub const FEATURE_THREE_WAY: u64 = 1 << 2;
+/// Engine groups and their index pools.
+pub const FEATURE_GROUPS: u64 = 1 << 3;
+/// `resolve_symbol` and the symbol table.
+pub const FEATURE_SYMBOLS: u64 = 1 << 4;
+/// Per-entry accessors (`get_entry_*`).
+pub const FEATURE_ENTRY_ACCESSORS: u64 = 1 << 5;
+/// `get_result_page`.
+pub const FEATURE_RESULT_PAGES: u64 = 1 << 6;
+/// `set_left_scope` / `set_right_scope`.
+pub const FEATURE_SCOPES: u64 = 1 << 7;
+/// `set_output_options`.
+pub const FEATURE_OUTPUT_OPTIONS: u64 = 1 << 8;
+/// `set_config`.
+pub const FEATURE_SET_CONFIG: u64 = 1 << 9;
+/// `load_suppressions`, `get_suppressed_count`.
+pub const FEATURE_SUPPRESSIONS: u64 = 1 << 10;
+/// `set_entry_transform`.
+pub const FEATURE_ENTRY_TRANSFORM: u64 = 1 << 11;
+/// `get_ranked_total`.
+pub const FEATURE_RANKING: u64 = 1 << 12;
+/// `get_audit_log`.
+pub const FEATURE_AUDIT_LOG: u64 = 1 << 13;
+/// `build_span_index` and its buffer.
+pub const FEATURE_SPAN_INDEX: u64 = 1 << 14;
+/// `probe`.
+pub const FEATURE_PROBE: u64 = 1 << 15;
+/// `alloc`, `realloc`, `dealloc` and their aligned forms.
+pub const FEATURE_ALLOC: u64 = 1 << 16;
+/// `get_input_digests`.
+pub const FEATURE_INPUT_DIGESTS: u64 = 1 << 17;
+/// `get_stats`, `get_arena_stats`, `get_memory_report`.
+pub const FEATURE_STATS: u64 = 1 << 18;
+/// `get_effective_config_json`.
+pub const FEATURE_EFFECTIVE_CONFIG: u64 = 1 << 19;
+/// `trim_engine`.
+pub const FEATURE_TRIM: u64 = 1 << 20;
+/// `lint`.
+pub const FEATURE_LINT: u64 = 1 << 21;
+/// `self_test`.
+pub const FEATURE_SELF_TEST: u64 = 1 << 22;
+/// Built with the `paranoid` cross-checks; no exports of its own.
+pub const FEATURE_PARANOID: u64 = 1 << 23;
+/// `apply_patch`, and the `self_contained` patch section it reads.
+pub const FEATURE_APPLY_PATCH: u64 = 1 << 24;
+/// `validate_json`.
+pub const FEATURE_VALIDATE: u64 = 1 << 25;
+/// `hash_document`.
+pub const FEATURE_HASH_DOCUMENT: u64 = 1 << 26;
+/// `commit_left_retained`, `diff_next_right`.
+pub const FEATURE_RETAINED_LEFT: u64 = 1 << 27;
+/// `clear_error`.
+pub const FEATURE_CLEAR_ERROR: u64 = 1 << 28;
+/// `export_left_state`, `get_left_state_len`, `import_left_state`.
+pub const FEATURE_LEFT_STATE: u64 = 1 << 29;
+/// Built with `handles`: engines cross the ABI as registry handles, and
+/// `create_engine_handle` returns one as an integer.
+pub const FEATURE_HANDLES: u64 = 1 << 30;
+/// `version`, `version_len`.
+pub const FEATURE_VERSION: u64 = 1 << 31;
+/// `set_option`.
+pub const FEATURE_SET_OPTION: u64 = 1 << 32;
+/// `reserve_input`.
+pub const FEATURE_RESERVE_INPUT: u64 = 1 << 33;
+/// `finalize_into`.
+pub const FEATURE_FINALIZE_INTO: u64 = 1 << 34;
+/// `finalize_partial` and `resume_finalize`.
+pub const FEATURE_PARTIAL_FINALIZE: u64 = 1 << 35;
+/// `get_dropped_count`, `HEADER_FLAG_TRUNCATED` and `ResultTruncated`.
+pub const FEATURE_TRUNCATION_REPORT: u64 = 1 << 36;
+/// `diff_direct`.
+pub const FEATURE_DIFF_DIRECT: u64 = 1 << 37;
+/// `get_memory_usage`.
+pub const FEATURE_MEMORY_USAGE: u64 = 1 << 38;
+/// `get_build_flags`.
+pub const FEATURE_BUILD_FLAGS: u64 = 1 << 39;
+/// `split_by_op` and its `get_result_ptr_for` / `get_result_len_for`.
+pub const FEATURE_RESULT_CHANNELS: u64 = 1 << 40;
+/// `get_cancel_flag_ptr`.
+pub const FEATURE_CANCEL_FLAG: u64 = 1 << 41;
+
+/// This build's version: the package version, plus `+<build id>` (git
+/// describe output, or `DIFFCORE_BUILD_ID`) when `build.rs` found one.
+pub const VERSION: &str = env!("DIFFCORE_VERSION");
+
+/// Each feature bit and the exports it promises.
+pub const EXPORTS: &[(u64, &[&str])] = &[
+    (FEATURE_STREAMING, &["push_left", "push_right"]),
+    (FEATURE_STEPPED, &["finalize_step", "cancel", "get_progress"]),
+    (FEATURE_THREE_WAY, &["commit_third", "get_third_input_ptr"]),
+    (FEATURE_GROUPS, &["create_engine_in_group", "set_group_limits", "get_group_usage", "set_index_pool_limit", "get_index_pool_stats"]),
+    (FEATURE_SYMBOLS, &["resolve_symbol", "resolve_symbol_len", "get_symbol_table", "get_symbol_table_len"]),
+    (FEATURE_ENTRY_ACCESSORS, &[
+        "get_entry_count", "get_entry_op", "get_entry_path", "get_entry_path_len",
+        "get_entry_left", "get_entry_left_len", "get_entry_right", "get_entry_right_len",
+    ]),
+    (FEATURE_RESULT_PAGES, &["get_result_page"]),
+    (FEATURE_SCOPES, &["set_left_scope", "set_right_scope"]),
+    (FEATURE_OUTPUT_OPTIONS, &["set_output_options"]),
+    (FEATURE_SET_CONFIG, &["set_config"]),
+    (FEATURE_SUPPRESSIONS, &["load_suppressions", "get_suppressed_count"]),
+    (FEATURE_ENTRY_TRANSFORM, &["set_entry_transform"]),
+    (FEATURE_RANKING, &["get_ranked_total"]),
+    (FEATURE_AUDIT_LOG, &["get_audit_log"]),
+    (FEATURE_SPAN_INDEX, &["build_span_index", "get_span_index_ptr", "get_span_index_len"]),
+    (FEATURE_PROBE, &["probe"]),
+    (FEATURE_ALLOC, &["alloc", "alloc_aligned", "realloc", "dealloc", "dealloc_aligned"]),
+    (FEATURE_INPUT_DIGESTS, &["get_input_digests"]),
+    (FEATURE_STATS, &["get_stats", "get_stats_len", "get_arena_stats", "get_memory_report"]),
+    (FEATURE_EFFECTIVE_CONFIG, &["get_effective_config_json", "get_effective_config_json_len"]),
+    (FEATURE_TRIM, &["trim_engine"]),
+    (FEATURE_LINT, &["lint"]),
+    (FEATURE_SELF_TEST, &["self_test"]),
+    (FEATURE_PARANOID, &[]),
+    (FEATURE_APPLY_PATCH, &["apply_patch"]),
+    (FEATURE_VALIDATE, &["validate_json"]),
+    (FEATURE_HASH_DOCUMENT, &["hash_document"]),
+    (FEATURE_RETAINED_LEFT, &["commit_left_retained", "diff_next_right"]),
+    (FEATURE_CLEAR_ERROR, &["clear_error"]),
+    (FEATURE_LEFT_STATE, &["export_left_state", "get_left_state_len", "import_left_state"]),
+    (FEATURE_HANDLES, &["create_engine_handle"]),
+    (FEATURE_VERSION, &["version", "version_len"]),
+    (FEATURE_SET_OPTION, &["set_option"]),
+    (FEATURE_RESERVE_INPUT, &["reserve_input"]),
+    (FEATURE_FINALIZE_INTO, &["finalize_into"]),
+    (FEATURE_PARTIAL_FINALIZE, &["finalize_partial", "resume_finalize"]),
+    (FEATURE_TRUNCATION_REPORT, &["get_dropped_count"]),
+    (FEATURE_DIFF_DIRECT, &["diff_direct"]),
+    (FEATURE_MEMORY_USAGE, &["get_memory_usage"]),
+    (FEATURE_BUILD_FLAGS, &["get_build_flags"]),
+    (FEATURE_RESULT_CHANNELS, &["get_result_ptr_for", "get_result_len_for"]),
+    (FEATURE_CANCEL_FLAG, &["get_cancel_flag_ptr"]),
+];
+
+/// Features this build has: every export group, plus the build flags.
+pub const FEATURES: u64 = FEATURE_STREAMING
+    | FEATURE_STEPPED
+    | FEATURE_THREE_WAY
+    | FEATURE_GROUPS
+    | FEATURE_SYMBOLS
+    | FEATURE_ENTRY_ACCESSORS
+    | FEATURE_RESULT_PAGES
+    | FEATURE_SCOPES
+    | FEATURE_OUTPUT_OPTIONS
+    | FEATURE_SET_CONFIG
+    | FEATURE_SUPPRESSIONS
+    | FEATURE_ENTRY_TRANSFORM
+    | FEATURE_RANKING
+    | FEATURE_AUDIT_LOG
+    | FEATURE_SPAN_INDEX
+    | FEATURE_PROBE
+    | FEATURE_ALLOC
+    | FEATURE_INPUT_DIGESTS
+    | FEATURE_STATS
+    | FEATURE_EFFECTIVE_CONFIG
+    | FEATURE_TRIM
+    | FEATURE_LINT
+    | FEATURE_SELF_TEST
+    | FEATURE_APPLY_PATCH
+    | FEATURE_VALIDATE
+    | FEATURE_HASH_DOCUMENT
+    | FEATURE_RETAINED_LEFT
+    | FEATURE_CLEAR_ERROR
+    | FEATURE_LEFT_STATE
+    | FEATURE_VERSION
+    | FEATURE_SET_OPTION
+    | FEATURE_RESERVE_INPUT
+    | FEATURE_FINALIZE_INTO
+    | FEATURE_PARTIAL_FINALIZE
+    | FEATURE_TRUNCATION_REPORT
+    | FEATURE_DIFF_DIRECT
+    | FEATURE_MEMORY_USAGE
+    | FEATURE_BUILD_FLAGS
+    | FEATURE_RESULT_CHANNELS
+    | FEATURE_CANCEL_FLAG
+    | if cfg!(feature = "paranoid") { FEATURE_PARANOID } else { 0 }
+    | if cfg!(feature = "handles") { FEATURE_HANDLES } else { 0 };
+
+/// Compiled with `simd128`: the module needs a runtime that supports it.
+pub const BUILD_FLAG_SIMD128: u32 = 1 << 0;
+/// The structural index runs in `v128` lanes rather than a byte at a time.
+pub const BUILD_FLAG_SIMD_INDEX: u32 = 1 << 1;
+
+/// What `get_build_flags` returns.
+pub const BUILD_FLAGS: u32 = if cfg!(target_feature = "simd128") { BUILD_FLAG_SIMD128 } else { 0 }
+    | if crate::simd_index::SIMD_INDEX { BUILD_FLAG_SIMD_INDEX } else { 0 };
+
+/// The record, fixed at compile time.
+pub static ENGINE_INFO: [u8; INFO_LEN] = engine_info();
+
+const fn engine_info() -> [u8; INFO_LEN] {
+    let mut buf = [0u8; INFO_LEN];
+    put(&mut buf, 0, &INFO_VERSION.to_le_bytes());
+    put(&mut buf, 2, &(INFO_LEN as u16).to_le_bytes());
+    put(&mut buf, 4, &FORMAT_VERSION.to_le_bytes());
+    put(&mut buf, 8, &FEATURES.to_le_bytes());
+    put(&mut buf, 16, &DEFAULT_MAX_MEMORY_BYTES.to_le_bytes());
+    put(&mut buf, 20, &DEFAULT_MAX_INPUT_SIZE.to_le_bytes());
+    put(&mut buf, 24, &DEFAULT_MAX_OBJECT_KEYS.to_le_bytes());
+    put(&mut buf, 28, &DEFAULT_MAX_FULL_ARRAY_SIZE.to_le_bytes());
+    put(&mut buf, 32, &DEFAULT_HASH_WINDOW_SIZE.to_le_bytes());
+    buf[34] = crate::simd_index::SIMD_INDEX as u8;
+    put(&mut buf, 36, &DEFAULT_MAX_PRIMITIVE_BYTES.to_le_bytes());
+    buf
+}
+
+const fn put(buf: &mut [u8; INFO_LEN], at: usize, bytes: &[u8]) {
+    let mut i = 0;
+    while i < bytes.len() {
+        buf[at + i] = bytes[i];
+        i += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::EngineConfig;
+
+    /// Every export a set bit promises is defined, unmangled, in `lib.rs`.
+    #[test]
+    fn set_features_have_their_exports() {
+        let lib = include_str!("lib.rs");
+        for &(bit, names) in EXPORTS {
+            if FEATURES & bit == 0 {
+                continue;
+            }
+            for name in names {
+                let def = format!("pub extern \"C\" fn {name}(");
+                let at = lib.find(&def).unwrap_or_else(|| panic!("feature {bit:#x} promises `{name}`, which lib.rs lacks"));
+                let attr = lib[..at].trim_end().lines().last().unwrap_or("");
+                assert!(attr.contains("no_mangle"), "`{name}` is not exported unmangled");
+            }
+        }
+    }
+
+    #[test]
+    fn each_feature_bit_is_listed_once() {
+        let mut seen = 0u64;
+        for &(bit, _) in EXPORTS {
+            assert_eq!(bit.count_ones(), 1);
+            assert_eq!(seen & bit, 0, "{bit:#x} listed twice");
+            seen |= bit;
+        }
+        assert_eq!(FEATURES & !seen, 0, "FEATURES sets a bit EXPORTS does not list");
+    }
+
+    #[test]
+    fn record_matches_the_default_config() {
+        let d = EngineConfig::default();
+        let u32_at = |at: usize| u32::from_le_bytes(ENGINE_INFO[at..at + 4].try_into().unwrap());
+        assert_eq!(u16::from_le_bytes([ENGINE_INFO[2], ENGINE_INFO[3]]) as usize, INFO_LEN);
+        assert_eq!(u32_at(4), FORMAT_VERSION);
+        assert_eq!(u64::from_le_bytes(ENGINE_INFO[8..16].try_into().unwrap()), FEATURES);
+        assert_eq!(
+            [u32_at(16), u32_at(20), u32_at(24), u32_at(28)],
+            [d.max_memory_bytes, d.max_input_size, d.max_object_keys, d.max_full_array_size]
+        );
+        assert_eq!(u16::from_le_bytes([ENGINE_INFO[32], ENGINE_INFO[33]]), d.hash_window_size);
+        assert_eq!(u32_at(36), d.max_primitive_bytes);
+    }
+}
@@ -0,0 +1,250 @@
+//! Snapshot of a retained left side, for `export_left_state` and
+//! `import_left_state`: a host diffing many documents against one
+//! baseline keeps the parse across engines, and across restarts of the
+//! process holding them, instead of parsing the baseline again.
+//!
+//! Layout, little-endian:
+//!
+//! | offset | size | field                                                  |
+//! |--------|------|--------------------------------------------------------|
+//! | 0      | 4    | magic `DCLS`                                           |
+//! | 4      | 2    | snapshot version ([`VERSION`])                         |
+//! | 6      | 2    | flags: bit 0 paths recorded, bit 1 strict escapes      |
+//! | 8      | 4    | result format version (`FORMAT_VERSION`)               |
+//! | 12     | 4    | document length                                        |
+//! | 16     | 4    | token count                                            |
+//! | 20     | 4    | path link count                                        |
+//! | 24     | 4    | escapes replaced while parsing                         |
+//! | 28     | 20   | `SideStats`, its five counters in declaration order    |
+//! | 48     | 8    | token digest (`CompactParser::digest`)                 |
+//! | 56     | 8    | `hash_bytes_simd` of the left scope pointer            |
+//! | 64     | 26×n | tokens: path id, event, kind, value hash, offset, len  |
+//! |        |      | links: child, parent, then 0 + key length + key bytes, |
+//! |        |      | or 1 + array index                                     |
+//! |        |      | the document                                           |
+//!
+//! Token hashes and path ids are only pinned per result format, so a
+//! blob is read by the build that wrote it and rejected by any other,
+//! never migrated. Reading checks every length against the blob, every
+//! token span against the document and the digest against the tokens:
+//! a truncated or damaged blob fails instead of diffing wrongly.
+
+use crate::memory::FORMAT_VERSION;
+use crate::parser::{CompactEvent, CompactParser, CompactToken, ValueKind};
+use crate::path::{PathId, PathSegment, PathTable};
+use crate::stats::SideStats;
+use alloc::vec::Vec;
+use core::fmt;
+
+pub const MAGIC: [u8; 4] = *b"DCLS";
+/// Snapshot layout version; bumped on any change to the table above.
+pub const VERSION: u16 = 1;
+const HEADER: usize = 64;
+const TOKEN: usize = 26;
+const FLAG_PATHS: u16 = 1 << 0;
+const FLAG_STRICT_ESCAPES: u16 = 1 << 1;
+
+/// Why a blob cannot be imported.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StateError {
+    /// Not a left-state snapshot.
+    NotASnapshot,
+    /// Written with another snapshot layout.
+    Version(u16),
+    /// Written by a build with another result format.
+    Format(u32),
+    /// Shorter than its own lengths say.
+    Truncated,
+    /// Bytes past the document.
+    TrailingBytes,
+    /// Token `n` has an unknown event or kind, or a span past the document.
+    Token(u32),
+    /// Path link `n` has an unknown segment kind.
+    Link(u32),
+    /// The tokens do not digest to the recorded digest.
+    Digest,
+    /// Parsed with other `strict_escapes` or left scope than the engine
+    /// importing it.
+    Settings,
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::NotASnapshot => write!(f, "not a left state snapshot"),
+            StateError::Version(v) => write!(f, "snapshot version {} is not {}", v, VERSION),
+            StateError::Format(v) => {
+                write!(f, "written for result format {}.{}, not {}.{}", v >> 16, v & 0xFFFF, FORMAT_VERSION >> 16, FORMAT_VERSION & 0xFFFF)
+            }
+            StateError::Truncated => write!(f, "snapshot truncated"),
+            StateError::TrailingBytes => write!(f, "bytes after the snapshot"),
+            StateError::Token(n) => write!(f, "token {} is malformed", n),
+            StateError::Link(n) => write!(f, "path link {} is malformed", n),
+            StateError::Digest => write!(f, "tokens do not match their digest"),
+            StateError::Settings => write!(f, "parsed with other strict_escapes or left scope"),
+        }
+    }
+}
+
+/// A snapshot read back; `input` borrows the blob.
+pub struct LeftState<'a> {
+    pub input: &'a [u8],
+    pub tokens: Vec<CompactToken>,
+    /// `None` when the side was parsed without recording paths.
+    pub paths: Option<PathTable>,
+    pub escape_warnings: u32,
+    pub stats: SideStats,
+    pub digest: u64,
+    pub strict_escapes: bool,
+    pub scope_hash: u64,
+}
+
+/// Snapshot `parser`'s document, parsed from `input` with `stats`,
+/// `strict_escapes` and left scope `scope`.
+pub fn encode(parser: &CompactParser, input: &[u8], stats: &SideStats, strict_escapes: bool, scope: &[u8]) -> Vec<u8> {
+    let tokens = parser.tokens();
+    let links = if parser.records_paths() { parser.paths().links() } else { Vec::new() };
+    let mut flags = 0;
+    if parser.records_paths() { flags |= FLAG_PATHS; }
+    if strict_escapes { flags |= FLAG_STRICT_ESCAPES; }
+
+    let mut out = Vec::with_capacity(HEADER + tokens.len() * TOKEN + links.len() * 24 + input.len());
+    out.extend_from_slice(&MAGIC);
+    out.extend_from_slice(&VERSION.to_le_bytes());
+    out.extend_from_slice(&flags.to_le_bytes());
+    for word in [
+        FORMAT_VERSION,
+        input.len() as u32,
+        tokens.len() as u32,
+        links.len() as u32,
+        parser.escape_warnings(),
+        stats.bytes_indexed,
+        stats.structurals,
+        stats.tokens,
+        stats.values,
+        stats.oversized,
+    ] {
+        out.extend_from_slice(&word.to_le_bytes());
+    }
+    out.extend_from_slice(&parser.digest().to_le_bytes());
+    out.extend_from_slice(&crate::parser::hash_bytes_simd(scope).to_le_bytes());
+    for t in tokens {
+        out.extend_from_slice(&t.path_id.0.to_le_bytes());
+        out.push(t.event as u8);
+        out.push(t.kind as u8);
+        out.extend_from_slice(&t.value_hash.to_le_bytes());
+        out.extend_from_slice(&t.raw_offset.to_le_bytes());
+        out.extend_from_slice(&t.raw_len.to_le_bytes());
+    }
+    for (child, parent, segment) in links {
+        out.extend_from_slice(&child.0.to_le_bytes());
+        out.extend_from_slice(&parent.0.to_le_bytes());
+        match segment {
+            PathSegment::Key(key) => {
+                out.push(0);
+                out.extend_from_slice(&(key.len() as u32).to_le_bytes());
+                out.extend_from_slice(key);
+            }
+            PathSegment::Index(i) => {
+                out.push(1);
+                out.extend_from_slice(&i.to_le_bytes());
+            }
+        }
+    }
+    out.extend_from_slice(input);
+    out
+}
+
+/// Bytes of a blob, taken front to back.
+struct Cursor<'a> {
+    blob: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+        let end = self.at.checked_add(n).filter(|&end| end <= self.blob.len()).ok_or(StateError::Truncated)?;
+        let bytes = &self.blob[self.at..end];
+        self.at = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, StateError> { Ok(self.take(1)?[0]) }
+    fn half(&mut self) -> Result<u16, StateError> { Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap())) }
+    fn word(&mut self) -> Result<u32, StateError> { Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap())) }
+    fn dword(&mut self) -> Result<u64, StateError> { Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap())) }
+}
+
+/// Read a blob `encode` wrote. The digest is checked by the caller, once
+/// the tokens are in a parser (`CompactParser::restore`).
+pub fn decode(blob: &[u8]) -> Result<LeftState<'_>, StateError> {
+    let mut c = Cursor { blob, at: 0 };
+    if c.take(4).map_err(|_| StateError::NotASnapshot)? != MAGIC { return Err(StateError::NotASnapshot); }
+    let version = c.half()?;
+    if version != VERSION { return Err(StateError::Version(version)); }
+    let flags = c.half()?;
+    let format = c.word()?;
+    if format != FORMAT_VERSION { return Err(StateError::Format(format)); }
+    let [input_len, token_count, link_count, escape_warnings] = [c.word()?, c.word()?, c.word()?, c.word()?];
+    let stats = SideStats {
+        bytes_indexed: c.word()?,
+        structurals: c.word()?,
+        tokens: c.word()?,
+        values: c.word()?,
+        oversized: c.word()?,
+    };
+    let digest = c.dword()?;
+    let scope_hash = c.dword()?;
+
+    // Checked before reserving, so a lying count cannot allocate.
+    if (token_count as usize).saturating_mul(TOKEN) > blob.len() - c.at { return Err(StateError::Truncated); }
+    let mut tokens = Vec::with_capacity(token_count as usize);
+    for n in 0..token_count {
+        let path_id = PathId(c.dword()?);
+        let event = match c.byte()? {
+            0 => CompactEvent::StartObject,
+            1 => CompactEvent::EndObject,
+            2 => CompactEvent::StartArray,
+            3 => CompactEvent::EndArray,
+            4 => CompactEvent::Value,
+            _ => return Err(StateError::Token(n)),
+        };
+        let kind = match c.byte()? {
+            0 => ValueKind::None,
+            1 => ValueKind::String,
+            2 => ValueKind::Literal,
+            3 => ValueKind::Fragment,
+            _ => return Err(StateError::Token(n)),
+        };
+        let token = CompactToken { path_id, event, kind, value_hash: c.dword()?, raw_offset: c.word()?, raw_len: c.word()? };
+        if token.raw_offset.checked_add(token.raw_len).is_none_or(|end| end > input_len) { return Err(StateError::Token(n)); }
+        tokens.push(token);
+    }
+
+    let mut paths = (flags & FLAG_PATHS != 0).then(PathTable::default);
+    for n in 0..link_count {
+        let (child, parent) = (PathId(c.dword()?), PathId(c.dword()?));
+        let segment = match c.byte()? {
+            0 => {
+                let len = c.word()? as usize;
+                PathSegment::Key(c.take(len)?.into())
+            }
+            1 => PathSegment::Index(c.word()?),
+            _ => return Err(StateError::Link(n)),
+        };
+        if let Some(paths) = paths.as_mut() { paths.record(child, parent, || segment); }
+    }
+
+    let input = c.take(input_len as usize)?;
+    if c.at != blob.len() { return Err(StateError::TrailingBytes); }
+    Ok(LeftState {
+        input,
+        tokens,
+        paths,
+        escape_warnings,
+        stats,
+        digest,
+        strict_escapes: flags & FLAG_STRICT_ESCAPES != 0,
+        scope_hash,
+    })
+}
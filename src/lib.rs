@@ -2,24 +2,114 @@
 //!
 //! This module provides the WebAssembly entry points for the diff engine.
 //! All functions are designed for zero-copy operation on WASM linear memory.
+//!
+//! Builds without std (`--no-default-features --features alloc`) for
+//! runtimes that provide no WASI; only `alloc` is required then.
+//!
+//! Exports that read or write host memory through a pointer argument stay
+//! safe `extern "C"` fns: their callers are wasm hosts, which an `unsafe`
+//! marker never reaches. Each says in its doc which bytes the pointer
+//! must cover, rejects null where it can, and allows
+//! `clippy::not_unsafe_ptr_arg_deref` with a `SAFETY` comment on the access.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 
+#[cfg(not(any(feature = "std", feature = "alloc")))]
+compile_error!("diffcore needs either the `std` (default) or the `alloc` feature");
 
+extern crate alloc;
+
+mod audit;
+#[cfg(feature = "gen")]
+pub mod bench;
+#[cfg(feature = "bindgen")]
+mod bindgen;
+mod cancel;
 mod config;
+mod config_json;
+mod coverage;
+#[cfg(feature = "serde")]
+mod decoded;
 mod diff;
+mod digest;
 mod engine;
 mod error;
+mod escape;
+mod global;
+mod group;
+#[cfg(feature = "handles")]
+mod handles;
+mod index_pool;
+pub mod info;
+mod left_state;
+mod lifecycle;
+mod lint;
+#[cfg(any(test, feature = "gen"))]
+pub mod gen;
 mod memory;
+mod normalize;
+mod numeric;
+mod ordered;
 mod parser;
+mod patch;
 mod path;
+mod path_pattern;
+mod probe;
+mod progress;
+mod rank;
+pub mod reader;
+mod self_test;
 mod simd_index;
+mod span_index;
+mod stats;
 mod status;
+mod three_way;
+mod trace;
+mod transform;
+mod utf8;
+mod validate;
 
+pub use audit::{AuditOp, AuditRecord};
 pub use config::{ArrayDiffMode, EngineConfig};
+pub use error::ErrorCode;
 pub use status::Status;
+pub use transform::{EntryTransform, TRANSFORM_DROP, TRANSFORM_KEEP, TRANSFORM_REDACT};
+
+pub use engine::{Engine, Side};
+#[cfg(feature = "bindgen")]
+pub use bindgen::DiffEngine;
+#[cfg(not(feature = "handles"))]
+use alloc::boxed::Box;
+use error::EngineError;
+use core::ptr;
 
-use engine::Engine;
-use std::ptr;
+/// Hash map and set used throughout: std's map in the default build, hashbrown's
+/// (the same implementation) under `alloc`. Iterating one where the order
+/// can reach the output goes through `ordered.rs`.
+#[cfg(feature = "std")]
+pub(crate) type FxHashMap<K, V> = std::collections::HashMap<K, V, FxState>;
+#[cfg(feature = "std")]
+pub(crate) type FxHashSet<T> = std::collections::HashSet<T, FxState>;
+#[cfg(not(feature = "std"))]
+pub(crate) type FxHashMap<K, V> = hashbrown::HashMap<K, V, FxState>;
+#[cfg(not(feature = "std"))]
+pub(crate) type FxHashSet<T> = hashbrown::HashSet<T, FxState>;
+/// Test builds seed the hasher per thread to shake out order dependence.
+#[cfg(not(test))]
+pub(crate) type FxState = rustc_hash::FxBuildHasher;
+#[cfg(test)]
+pub(crate) type FxState = ordered::SeededFx;
+
+#[cfg(not(feature = "std"))]
+#[global_allocator]
+static ALLOC: dlmalloc::GlobalDlmalloc = dlmalloc::GlobalDlmalloc;
+
+/// Without std there is no unwinding or message printing: trap.
+#[cfg(all(not(feature = "std"), not(test)))]
+#[panic_handler]
+fn panic(_: &core::panic::PanicInfo) -> ! {
+    core::arch::wasm32::unreachable()
+}
 
 /// Magic header for engine validation (0xD1FFC0RE)
 const ENGINE_MAGIC: u32 = 0xD1FF_C0AE;
@@ -27,6 +117,12 @@ const ENGINE_MAGIC: u32 = 0xD1FF_C0AE;
 // ============================================================================
 // WASM EXPORTS - Minimal ABI Surface
 // ============================================================================
+//
+// Calls out of lifecycle order fail alike through every export (see
+// `lifecycle.rs`): input and options return `EngineSealed` after
+// finalize and `Busy` while a `finalize_step` finalize is under way, and
+// after a failure that poisons the engine every call but
+// `clear_engine` and `trim_engine` returns `Poisoned`.
 
 /// Create a new diff engine with the given configuration.
 ///
@@ -35,25 +131,261 @@ const ENGINE_MAGIC: u32 = 0xD1FF_C0AE;
 /// * `config_len` - Length of configuration bytes
 ///
 /// # Returns
-/// Pointer to the engine, or null on failure.
+/// Pointer to the engine, or null on failure (why in `get_create_error`).
+/// With the `handles` feature, its handle in the pointer's place.
 #[no_mangle]
 pub extern "C" fn create_engine(config_ptr: *const u8, config_len: u32) -> *mut Engine {
-    let config = if config_ptr.is_null() || config_len == 0 {
-        EngineConfig::default()
-    } else {
-        let config_slice = unsafe { std::slice::from_raw_parts(config_ptr, config_len as usize) };
-        match EngineConfig::from_bytes(config_slice) {
-            Ok(c) => c,
-            Err(_) => return ptr::null_mut(),
-        }
-    };
+    let engine = read_config(config_ptr, config_len).and_then(|config| Engine::new(config, ENGINE_MAGIC));
+    box_engine(engine, config_len)
+}
 
-    match Engine::new(config, ENGINE_MAGIC) {
-        Ok(engine) => Box::into_raw(Box::new(engine)),
-        Err(_) => ptr::null_mut(),
+/// `create_engine` for hosts built against the `handles` feature: the
+/// engine's handle (see `handles.rs`) as an integer, or 0 on failure (why
+/// in `get_create_error`). Every export taking an `engine_ptr` takes it.
+#[cfg(feature = "handles")]
+#[no_mangle]
+pub extern "C" fn create_engine_handle(config_ptr: *const u8, config_len: u32) -> u32 {
+    create_engine(config_ptr, config_len) as usize as u32
+}
+
+/// Create an engine accounted into a tenant group (see `group.rs`).
+///
+/// Returns null if the config is invalid or the group is at its engine or
+/// byte quota (why in `get_create_error`).
+#[no_mangle]
+pub extern "C" fn create_engine_in_group(group_id: u32, config_ptr: *const u8, config_len: u32) -> *mut Engine {
+    let engine = read_config(config_ptr, config_len)
+        .and_then(|config| Engine::new_in_group(config, ENGINE_MAGIC, group_id));
+    box_engine(engine, config_len)
+}
+
+/// Pointer to the error of the last call with no engine to hold it (a
+/// failed `create_engine*` or `self_test`), or null if that call
+/// succeeded. The length follows from `get_create_error_len`.
+#[no_mangle]
+pub extern "C" fn get_create_error() -> *const u8 {
+    error::with_create_error(|e| e.as_ptr())
+}
+
+/// Length of the `get_create_error` message (UTF-8, null-terminated).
+#[no_mangle]
+pub extern "C" fn get_create_error_len() -> u32 {
+    error::with_create_error(|e| e.len())
+}
+
+/// `ErrorCode` of the `get_create_error` message, or 0 if none.
+#[no_mangle]
+pub extern "C" fn get_create_error_code() -> u32 {
+    error::with_create_error(|e| e.code())
+}
+
+/// Check this build against fixtures embedded in it: value hashes, the
+/// structural index, a canned diff and the result format version (see
+/// `self_test.rs`). Returns 0 when all pass, else the `SELF_TEST_*` bits
+/// of the failed checks, with what they got in `get_create_error`
+/// (`ErrorCode::SelfTestFailed`). Needs no engine; safe to call at any
+/// time, e.g. as a readiness probe right after instantiation.
+#[no_mangle]
+pub extern "C" fn self_test() -> u32 {
+    let (failed, details) = self_test::run();
+    error::with_create_error(|e| match failed {
+        0 => e.clear(),
+        _ => e.set(&EngineError::SelfTest(details)),
+    });
+    failed
+}
+
+/// Result format version this build writes: major in the high 16 bits,
+/// minor in the low 16, as in every result header. Needs no engine, so a
+/// host can refuse an incompatible binary before allocating anything.
+#[no_mangle]
+pub extern "C" fn get_format_version() -> u32 {
+    memory::FORMAT_VERSION
+}
+
+/// Describe this build without an engine: format version, a `FEATURE_*`
+/// bitmask of the optional export groups present, the default config's
+/// limits and whether SIMD was compiled in. A static record (layout in
+/// `info.rs`), valid for the module's lifetime; length from
+/// `get_engine_info_len`.
+#[no_mangle]
+pub extern "C" fn get_engine_info() -> *const u8 {
+    info::ENGINE_INFO.as_ptr()
+}
+
+/// How this module was compiled (`BUILD_FLAG_*` in `info.rs`): bit 0 set
+/// when built with `simd128`, so it traps on a runtime without it; bit 1
+/// when the structural index uses those instructions rather than the
+/// scalar path. Needs no engine; a host compares it with what its runtime
+/// supports and loads the other binary, or warns, on a mismatch.
+#[no_mangle]
+pub extern "C" fn get_build_flags() -> u32 {
+    info::BUILD_FLAGS
+}
+
+/// Length of the `get_engine_info` record.
+#[no_mangle]
+pub extern "C" fn get_engine_info_len() -> u32 {
+    info::ENGINE_INFO.len() as u32
+}
+
+/// This build's version as UTF-8, `version_len` bytes: the crate version,
+/// then `+` and a git-describe build id when one was known at build time
+/// (`0.1.0+v0.1.0-12-g3c7e2b6-dirty`). Static bytes; needs no engine.
+#[no_mangle]
+pub extern "C" fn version() -> *const u8 {
+    info::VERSION.as_ptr()
+}
+
+/// Length of the `version` string.
+#[no_mangle]
+pub extern "C" fn version_len() -> u32 {
+    info::VERSION.len() as u32
+}
+
+/// Format version of the results `engine_ptr` writes, packed as
+/// `get_format_version`; 0 for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_engine_format_version(engine_ptr: *const Engine) -> u32 {
+    match validate_engine_const(engine_ptr) {
+        Some(e) => e.format_version(),
+        None => 0,
+    }
+}
+
+/// Set a group's caps. Applies to later creations and commits; engines
+/// already over a lowered cap keep running.
+#[no_mangle]
+pub extern "C" fn set_group_limits(group_id: u32, max_engines: u32, max_total_bytes: u32) -> Status {
+    group::set_limits(group_id, group::GroupLimits { max_engines, max_total_bytes: max_total_bytes as u64 });
+    Status::Ok
+}
+
+/// Write a group's usage (`[u32 engines][u64 bytes]`, 12 bytes) to `out_ptr`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_group_usage(group_id: u32, out_ptr: *mut u8) -> Status {
+    if out_ptr.is_null() {
+        return Status::Error;
+    }
+    let bytes = group::usage(group_id).to_bytes();
+    // SAFETY: the host passes 12 writable bytes at `out_ptr`.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+    Status::Ok
+}
+
+/// Cap the structural indexes a group's pool keeps idle between commits
+/// (default 4); extras are freed now. See `index_pool.rs`.
+#[no_mangle]
+pub extern "C" fn set_index_pool_limit(group_id: u32, max_idle: u32) -> Status {
+    index_pool::set_max_idle(Some(group_id), max_idle);
+    Status::Ok
+}
+
+/// Write a group's index pool counters (`[u32 idle][u32 checkouts]
+/// [u32 reuses][u32 discards]`, 16 bytes) to `out_ptr`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_index_pool_stats(group_id: u32, out_ptr: *mut u8) -> Status {
+    if out_ptr.is_null() {
+        return Status::Error;
+    }
+    let bytes = index_pool::stats(Some(group_id)).to_bytes();
+    // SAFETY: the host passes 16 writable bytes at `out_ptr`.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+    Status::Ok
+}
+
+/// Probe the first bytes of a document without an engine: root kind,
+/// top-level item count, nesting depth and escapes, for picking a config
+/// before committing. `ptr`/`len` may be any prefix, cut anywhere. Writes
+/// a 16-byte record (layout in `probe.rs`) to `out_ptr`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn probe(ptr: *const u8, len: u32, out_ptr: *mut u8) -> Status {
+    if out_ptr.is_null() || (ptr.is_null() && len != 0) {
+        return Status::Error;
+    }
+    // SAFETY: the host passes `len` readable bytes at a non-null `ptr`.
+    let prefix = if len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(ptr, len as usize) } };
+    let bytes = probe::probe(prefix).to_bytes();
+    // SAFETY: the host passes 16 writable bytes at `out_ptr`.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+    Status::Ok
+}
+
+/// Allocate `len` bytes in linear memory for the host to write into, e.g.
+/// a config before `create_engine`. Free with `dealloc`. Null for `len`
+/// 0 or out of memory. The block is 16-byte aligned.
+#[no_mangle]
+pub extern "C" fn alloc(len: u32) -> *mut u8 {
+    memory::alloc_block(len as usize, 1)
+}
+
+/// Allocate as `alloc`, aligned to `align` (a power of two), for
+/// host-side staging buffers that SIMD code loads from. The engine's own
+/// input buffers are 64-byte aligned already. Null for `len` 0, a bad
+/// `align`, or out of memory. Free with `dealloc`.
+#[no_mangle]
+pub extern "C" fn alloc_aligned(len: u32, align: u32) -> *mut u8 {
+    memory::alloc_block(len as usize, align as usize)
+}
+
+/// Grow or shrink a block from `alloc` or `alloc_aligned` to `new_len`
+/// bytes, keeping its alignment and contents up to the shorter length,
+/// e.g. a host scratch buffer filling up with streamed chunks before
+/// `push_left`. Returns the block, possibly moved; null for a null `ptr`,
+/// `new_len` 0 or out of memory, and `ptr` is still valid then. The
+/// block records its own size, so `old_len` is only a caller hint.
+///
+/// Exported unmangled on wasm32 only: natively it would replace libc's
+/// `realloc`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[cfg_attr(target_arch = "wasm32", no_mangle)]
+pub extern "C" fn realloc(ptr: *mut u8, _old_len: u32, new_len: u32) -> *mut u8 {
+    if ptr.is_null() { return ptr::null_mut(); }
+    // SAFETY: the caller passes back a live block from `alloc*`.
+    unsafe { memory::realloc_block(ptr, new_len as usize) }
+}
+
+/// Free a block from `alloc` or `alloc_aligned`. The block records its own
+/// size, so no length is needed. `Error` for a null `ptr`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn dealloc(ptr: *mut u8) -> Status {
+    // SAFETY: the caller passes back a live block from `alloc*`.
+    match unsafe { memory::dealloc_block(ptr) } {
+        true => Status::Ok,
+        false => Status::Error,
     }
 }
 
+/// Compatibility form of `dealloc`, for hosts that still pass the `len`
+/// and `align` they allocated with: both are ignored.
+#[no_mangle]
+pub extern "C" fn dealloc_aligned(ptr: *mut u8, _len: u32, _align: u32) -> Status {
+    dealloc(ptr)
+}
+
+/// Append `len` bytes at `ptr` to the left input: the streaming
+/// alternative to writing the whole document and calling `commit_left`.
+/// `ptr` must cover `len` readable bytes, and may be null only with a
+/// `len` of 0 (`Error` otherwise); they are copied before the call returns.
+/// Chunks may split the document anywhere; `finalize` commits the side
+/// with everything pushed. `InputLimitExceeded` once the side's input
+/// buffer would overflow, `AlreadyCommitted` after a commit.
+#[no_mangle]
+pub extern "C" fn push_left(engine_ptr: *mut Engine, ptr: *const u8, len: u32) -> Status {
+    push(engine_ptr, Side::Left, ptr, len)
+}
+
+/// Append a chunk to the right input; see `push_left`, whose rules for
+/// `ptr` and `len` apply.
+#[no_mangle]
+pub extern "C" fn push_right(engine_ptr: *mut Engine, ptr: *const u8, len: u32) -> Status {
+    push(engine_ptr, Side::Right, ptr, len)
+}
+
 /// Signal that N bytes have been written into the managed left input buffer.
 #[no_mangle]
 pub extern "C" fn commit_left(engine_ptr: *mut Engine, len: u32) -> Status {
@@ -61,7 +393,91 @@ pub extern "C" fn commit_left(engine_ptr: *mut Engine, len: u32) -> Status {
         Some(e) => e,
         None => return Status::InvalidHandle,
     };
-    engine.commit_left(len)
+    let status = engine.commit_left(len);
+    engine.audit(AuditOp::CommitLeft, len, status);
+    status
+}
+
+/// Commit the left input as `commit_left` does, and keep it as the
+/// baseline for `diff_next_right`: one left, many rights, the left parsed
+/// once. Returns what `commit_left` returns.
+#[no_mangle]
+pub extern "C" fn commit_left_retained(engine_ptr: *mut Engine, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.commit_left_retained(len);
+    engine.audit(AuditOp::CommitLeftRetained, len, status);
+    status
+}
+
+/// Diff the next document against the retained left: write it to the
+/// right input buffer, then call this with its length. The previous
+/// right side and result are dropped (copy a result out before the next
+/// call), the right side committed and the engine finalized; on `Ok`
+/// the result is read as after `finalize` (`get_result_len`, the
+/// accessors; `finalize` returns its pointer). Options, scopes,
+/// suppressions and the entry transform carry over from call to call.
+///
+/// Returns `Error` with code `LeftNotRetained` before
+/// `commit_left_retained` (or after `clear` or a plain `commit_left`),
+/// and otherwise the status of the commit or the finalize.
+#[no_mangle]
+pub extern "C" fn diff_next_right(engine_ptr: *mut Engine, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.diff_next_right(len);
+    engine.audit(AuditOp::DiffNextRight, len, status);
+    status
+}
+
+/// Snapshot the retained left side, so another engine (in this process
+/// or a later one) can diff against it without parsing it again; see
+/// `left_state.rs` for the layout. Returns a pointer to the blob,
+/// `get_left_state_len` bytes, valid until the next call, `clear_engine`
+/// or `trim_engine`; copy it out to keep it. Null with `LeftNotRetained`
+/// before `commit_left_retained` or `import_left_state`.
+#[no_mangle]
+pub extern "C" fn export_left_state(engine_ptr: *mut Engine) -> *const u8 {
+    match validate_engine(engine_ptr) {
+        Some(e) => e.export_left_state(),
+        None => ptr::null(),
+    }
+}
+
+/// Length of the blob written by the last `export_left_state`.
+#[no_mangle]
+pub extern "C" fn get_left_state_len(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.left_state_len()
+}
+
+/// Restore a left side from an `export_left_state` blob in place of
+/// `commit_left_retained`, then diff with `commit_right` and `finalize`
+/// or `diff_next_right` as usual; results match those of committing the
+/// document. The engine needs the `strict_escapes` and left scope the
+/// blob was written with. Returns `Error` with code `InvalidLeftState`
+/// for a blob written by another build, cut short or damaged, and
+/// otherwise as `commit_left` for the engine's state. Audited as
+/// `ImportLeftState` with the blob length.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn import_left_state(engine_ptr: *mut Engine, blob_ptr: *const u8, blob_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    // SAFETY: the host passes `blob_len` readable bytes at a non-null `blob_ptr`.
+    let blob = if blob_ptr.is_null() || blob_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(blob_ptr, blob_len as usize) } };
+    let status = engine.import_left_state(blob);
+    engine.audit(AuditOp::ImportLeftState, blob_len, status);
+    status
 }
 
 /// Signal that N bytes have been written into the managed right input buffer.
@@ -71,24 +487,98 @@ pub extern "C" fn commit_right(engine_ptr: *mut Engine, len: u32) -> Status {
         Some(e) => e,
         None => return Status::InvalidHandle,
     };
-    engine.commit_right(len)
+    let status = engine.commit_right(len);
+    engine.audit(AuditOp::CommitRight, len, status);
+    status
+}
+
+/// Signal that N bytes have been written into the managed third input
+/// buffer: the canary of `three_way` mode (see `three_way.rs`). Fails
+/// unless the engine was created with `three_way`.
+#[no_mangle]
+pub extern "C" fn commit_third(engine_ptr: *mut Engine, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.commit_third(len);
+    engine.audit(AuditOp::CommitThird, len, status);
+    status
 }
 
-/// Return the managed pointer for the left input buffer.
+/// Return the managed pointer for the left input buffer. Each buffer is
+/// allocated at its full capacity with the engine, so the pointer stays
+/// valid until a `set_config` or `set_option` changing `max_input_size`,
+/// or `destroy_engine`; growing linear memory does not move it.
 #[no_mangle]
 pub extern "C" fn get_left_input_ptr(engine_ptr: *mut Engine) -> *mut u8 {
     match validate_engine(engine_ptr) {
         Some(e) => e.left_input_ptr(),
-        None => std::ptr::null_mut(),
+        None => core::ptr::null_mut(),
     }
 }
 
-/// Return the managed pointer for the right input buffer.
+/// Return the managed pointer for the right input buffer; stable as the
+/// left one is.
 #[no_mangle]
 pub extern "C" fn get_right_input_ptr(engine_ptr: *mut Engine) -> *mut u8 {
     match validate_engine(engine_ptr) {
         Some(e) => e.right_input_ptr(),
-        None => std::ptr::null_mut(),
+        None => core::ptr::null_mut(),
+    }
+}
+
+/// Promise documents of at most `left_len` and `right_len` bytes before
+/// writing them: `InputLimitExceeded` now if either is over its side's
+/// buffer, instead of a failed commit after the copy. Afterwards a commit
+/// or push past its side's reservation fails with `InputExceedsBuffer` /
+/// `InputLimitExceeded`, so a host bug that miscounts the copy surfaces.
+/// Allowed before any input, like `set_config`; `clear_engine` or a new
+/// config drops the reservation.
+#[no_mangle]
+pub extern "C" fn reserve_input(engine_ptr: *mut Engine, left_len: u32, right_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.reserve_input(left_len, right_len);
+    engine.audit(AuditOp::ReserveInput, left_len, status);
+    status
+}
+
+/// One-shot diff of documents the host already has in linear memory (from
+/// `alloc`, say): commits `left_len` bytes at `left_ptr` and `right_len`
+/// at `right_ptr` and finalizes, reading them in place rather than from
+/// copies in the engine's input buffers, which are then never allocated.
+/// Read the result with `finalize` (which returns it again) or the entry
+/// accessors; its values point into the host's bytes, which must stay
+/// allocated and unchanged until `clear_engine` or `destroy_engine`.
+///
+/// Returns `Ok` (or `ResultTruncated`) once the result is written; else
+/// the status of the commit or finalize that failed, with its error, and
+/// the engine cleared. Needs a fresh or cleared engine (`TooLate`
+/// otherwise); `max_input_size` still bounds each side. `Error` for a
+/// null pointer.
+#[no_mangle]
+pub extern "C" fn diff_direct(engine_ptr: *mut Engine, left_ptr: *const u8, left_len: u32, right_ptr: *const u8, right_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    if left_ptr.is_null() || right_ptr.is_null() { return Status::Error; }
+    // SAFETY: the host keeps both documents in place until the clear.
+    let status = unsafe { engine.diff_direct((left_ptr, left_len), (right_ptr, right_len)) };
+    engine.audit(AuditOp::DiffDirect, left_len, status);
+    status
+}
+
+/// Return the managed pointer for the third input buffer, or null unless
+/// the engine was created with `three_way`.
+#[no_mangle]
+pub extern "C" fn get_third_input_ptr(engine_ptr: *mut Engine) -> *mut u8 {
+    match validate_engine(engine_ptr) {
+        Some(e) => e.third_input_ptr(),
+        None => core::ptr::null_mut(),
     }
 }
 
@@ -96,6 +586,25 @@ pub extern "C" fn get_right_input_ptr(engine_ptr: *mut Engine) -> *mut u8 {
 ///
 /// After calling this, no more chunks can be pushed.
 /// The returned pointer points to the result buffer in linear memory.
+///
+/// Entries reference values by `(offset, len)` into the input buffers
+/// rather than copying them, so the result is only meaningful while those
+/// bytes are resident: `clear_engine` (and the next commit) or
+/// `destroy_engine` invalidate both the result and its references. A host
+/// that outlives the engine must copy the inputs along with the result.
+///
+/// Returns null on failure, including a finalize with neither side
+/// committed unless the config sets `allow_empty_side` (see
+/// `Engine::finalize`); the engine stays open in that case. After
+/// `cancel` it returns null too, with the error code `Cancelled`, but
+/// the partial result is written: `get_result_len` and a second
+/// `finalize` return it, flagged `HEADER_FLAG_CANCELLED`.
+///
+/// A result that outgrows `max_memory_bytes` is not a failure: the
+/// entries that fit are returned, flagged `HEADER_FLAG_TRUNCATED`, with
+/// the error code `MemoryLimitExceeded`, a message naming the limit, and
+/// the rest counted by `get_dropped_count`. `finalize_step` and
+/// `finalize_partial` return `ResultTruncated` for it.
 #[no_mangle]
 pub extern "C" fn finalize(engine_ptr: *mut Engine) -> *const u8 {
     let engine = match validate_engine(engine_ptr) {
@@ -104,89 +613,1075 @@ pub extern "C" fn finalize(engine_ptr: *mut Engine) -> *const u8 {
     };
 
     match engine.finalize() {
-        Ok(result_ptr) => result_ptr,
-        Err(_) => ptr::null(),
+        Ok(result_ptr) => {
+            engine.audit(AuditOp::Finalize, engine.result_len(), Status::Ok);
+            result_ptr
+        }
+        Err(_) => {
+            engine.audit(AuditOp::Finalize, 0, Status::Error);
+            ptr::null()
+        }
     }
 }
 
-/// Get the length of the result buffer.
+/// `finalize`, writing the result into the host's `out_cap` bytes at
+/// `out_ptr` instead of keeping it in linear memory. Returns the bytes
+/// written; the engine keeps no copy, so `get_result_len` is 0 and the
+/// result accessors find nothing until `clear_engine`, and another
+/// `finalize` fails with `ResultHandedOff`. The entries' value references
+/// still point into the input buffers, as for `finalize`.
+///
+/// A failure returns `-(needed << 8 | status)`. For a buffer smaller than
+/// the result the status is `NeedFlush`, error code `OutputTooSmall`, and
+/// `needed` the result's length: the engine keeps the result, and a retry
+/// with at least `needed` bytes copies it out. Otherwise `needed` is 0 and
+/// the status is the one `finalize` would fail with (`Error` for most),
+/// an invalid engine returning `-InvalidHandle`. A null `out_ptr` with
+/// `out_cap` 0 just measures.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
 #[no_mangle]
-pub extern "C" fn get_result_len(engine_ptr: *const Engine) -> u32 {
-    let engine = match validate_engine_const(engine_ptr) {
+pub extern "C" fn finalize_into(engine_ptr: *mut Engine, out_ptr: *mut u8, out_cap: u32) -> i64 {
+    let engine = match validate_engine(engine_ptr) {
         Some(e) => e,
-        None => return 0,
+        None => return -(Status::InvalidHandle as i64),
     };
+    if out_ptr.is_null() && out_cap != 0 { return -(Status::Error as i64); }
 
-    engine.result_len()
+    // SAFETY: the host hands over `out_cap` writable bytes at `out_ptr`.
+    match unsafe { engine.finalize_into(out_ptr, out_cap) } {
+        Ok(len) => {
+            engine.audit(AuditOp::FinalizeInto, len, Status::Ok);
+            len as i64
+        }
+        Err(e) => {
+            let status = match Status::from(&e) {
+                Status::Ok => Status::Error,
+                status => status,
+            };
+            engine.audit(AuditOp::FinalizeInto, 0, status);
+            let needed = match e {
+                EngineError::OutputTooSmall { needed, .. } => needed as i64,
+                _ => 0,
+            };
+            -(needed << 8 | status as i64)
+        }
+    }
 }
 
-/// Destroy the engine and free all associated memory.
-///
-/// This function is safe to call multiple times (double-free safe).
+/// `finalize` for results larger than `max_memory_bytes`: the arena
+/// fills up to its limit and is handed over as a segment instead of the
+/// rest of the entries being dropped. Returns `NeedFlush` when the result
+/// (`finalize`'s pointer, `get_result_len`, the entry accessors) holds a
+/// segment with more to follow, flagged `HEADER_FLAG_PARTIAL`: read it,
+/// then call `resume_finalize` for the next. `Ok` when the result is
+/// whole; otherwise the status `finalize` would fail with. Each segment
+/// is a result of its own; see `memory.rs` for what they share.
 #[no_mangle]
-pub extern "C" fn destroy_engine(engine_ptr: *mut Engine) -> Status {
-    if engine_ptr.is_null() {
-        return Status::Ok; // Already destroyed or never created
-    }
+pub extern "C" fn finalize_partial(engine_ptr: *mut Engine) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.finalize_partial();
+    engine.audit(AuditOp::FinalizePartial, engine.result_len(), status);
+    status
+}
 
-    // Check magic before destroying
-    let engine = unsafe { &mut *engine_ptr };
-    if engine.magic() != ENGINE_MAGIC {
-        return Status::InvalidHandle;
-    }
+/// Write the next segment of a `finalize_partial` result over the last
+/// one: `NeedFlush` while more follow, `Ok` for the last. `Error` with
+/// code `NoSegmentPending` once there are no more, or without
+/// `finalize_partial`.
+#[no_mangle]
+pub extern "C" fn resume_finalize(engine_ptr: *mut Engine) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.resume_finalize();
+    engine.audit(AuditOp::ResumeFinalize, engine.result_len(), status);
+    status
+}
 
-    // Zero the magic to prevent double-free
-    engine.clear_magic();
+/// Finalize in slices, so a host on the UI thread can yield between
+/// calls: each call visits up to `budget_tokens` tokens (at least one) of
+/// the diff join and returns `InProgress` until the call that writes the
+/// result, which returns `Ok`; `finalize` then returns that result
+/// without further work (or finishes the rest in one go).
+/// `get_progress` shows the join's progress between calls. The first
+/// call seals the engine; failures are `finalize`'s, as the status
+/// their error maps to (`Error` unless `Status` has a specific one, such
+/// as `ObjectKeyLimitExceeded` from a pushed side's commit or `Cancelled`,
+/// see `cancel`), and one once sealed poisons the engine:
+/// later calls return `Poisoned` until `clear_engine`. Only the
+/// finishing or failing call is audited, as `Finalize`.
+#[no_mangle]
+pub extern "C" fn finalize_step(engine_ptr: *mut Engine, budget_tokens: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
 
-    // Drop the engine
-    unsafe {
-        let _ = Box::from_raw(engine_ptr);
+    match engine.finalize_step(budget_tokens as usize) {
+        Ok(true) => {
+            let status = if engine.result_truncated() { Status::ResultTruncated } else { Status::Ok };
+            engine.audit(AuditOp::Finalize, engine.result_len(), status);
+            status
+        }
+        Ok(false) => Status::InProgress,
+        Err(EngineError::Cancelled) => {
+            engine.audit(AuditOp::Finalize, engine.result_len(), Status::Cancelled);
+            Status::Cancelled
+        }
+        Err(e) => {
+            let status = Status::from(&e);
+            engine.audit(AuditOp::Finalize, 0, status);
+            status
+        }
     }
+}
 
+/// Abandon the engine's finalize: the stepped one, between
+/// `finalize_step` calls, or else the next one. The join stops within
+/// `STRIDE_TOKENS` (8192) token visits; the finalize writes the entries
+/// found so far, flagged `HEADER_FLAG_CANCELLED`, and fails
+/// (`finalize_step` returns `Cancelled`). No effect on a finalize already
+/// past the join or on token-identical sides. `clear_engine` drops a
+/// pending cancel. Not audited.
+///
+/// Like every export it takes the engine, so it must not run while
+/// another worker is inside a call on it; to stop a finalize running
+/// there, store to `get_cancel_flag_ptr` instead.
+#[no_mangle]
+pub extern "C" fn cancel(engine_ptr: *const Engine) -> Status {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    engine.cancel();
     Status::Ok
 }
 
-/// Reset the engine state for a new diff operation without re-allocating heap.
+/// Address of the engine's cancel flag, one byte, valid until
+/// `destroy_engine`. Fetch it before handing the finalize to a worker;
+/// `Atomics.store(new Uint8Array(memory.buffer), ptr, 1)` from any
+/// worker sharing this module's memory then cancels as `cancel` does,
+/// without calling into the module. Null for an invalid handle.
 #[no_mangle]
-pub extern "C" fn clear_engine(engine_ptr: *mut Engine) -> Status {
+pub extern "C" fn get_cancel_flag_ptr(engine_ptr: *const Engine) -> *const u8 {
+    match validate_engine_const(engine_ptr) {
+        Some(e) => e.cancel_flag_ptr(),
+        None => core::ptr::null(),
+    }
+}
+
+/// Lint one committed side: diff it against its canonical form (sorted
+/// keys, plain numbers, no needless escapes or whitespace; see `lint.rs`)
+/// and write the result, as `finalize` would. `side` is 0 for left, 1 for
+/// right; the other side must be uncommitted, as the canonical text is
+/// committed there, where the result's spans on that side point.
+///
+/// Every entry is flagged `ENTRY_FLAG_FORMATTING` and the header
+/// `HEADER_FLAG_LINT`; an already canonical document gives no entries.
+/// Returns `Error` (code `NoInputCommitted`) when `side` is not
+/// committed, `AlreadyCommitted` when the other side is committed or
+/// pushed, and `finalize`'s failures otherwise. A second `finalize`
+/// returns the result.
+#[no_mangle]
+pub extern "C" fn lint(engine_ptr: *mut Engine, side: u32) -> Status {
     let engine = match validate_engine(engine_ptr) {
         Some(e) => e,
         None => return Status::InvalidHandle,
     };
+    let status = engine.lint(side);
+    engine.audit(AuditOp::Lint, side, status);
+    status
+}
 
-    engine.clear();
-    Status::Ok
+/// Rebuild the right document of a diff from its left document:
+/// `doc_ptr` must cover `doc_len` readable bytes holding the left
+/// document, `patch_ptr` `patch_len` bytes holding a result finalized with
+/// `self_contained` (by any engine; copy it out first if it was this
+/// one's). A null pointer reads as empty. The engine must be fresh or
+/// cleared, with nothing committed or pushed; it seals with a result of no
+/// entries whose document section holds the rebuilt document's compact
+/// JSON text, flagged `HEADER_FLAG_PATCHED`. Read it as any result: `finalize`
+/// returns it, `get_result_len` its length.
+///
+/// Returns `AlreadyCommitted` once input has arrived, `Error` with code
+/// `InvalidPatch` for a result without a patch section or with a record
+/// the document has no place for (a patch for another document), and the
+/// parse error's status for a `doc` that is not JSON. Audited as
+/// `ApplyPatch` with the patch length.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn apply_patch(engine_ptr: *mut Engine, doc_ptr: *const u8, doc_len: u32, patch_ptr: *const u8, patch_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    // SAFETY: the host passes `doc_len` readable bytes at a non-null `doc_ptr`.
+    let doc = if doc_ptr.is_null() || doc_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(doc_ptr, doc_len as usize) } };
+    // SAFETY: the host passes `patch_len` readable bytes at a non-null `patch_ptr`.
+    let patch = if patch_ptr.is_null() || patch_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(patch_ptr, patch_len as usize) } };
+    let status = engine.apply_patch(doc, patch);
+    engine.audit(AuditOp::ApplyPatch, patch_len, status);
+    status
 }
 
-/// Get pointer to the last error message.
+/// Structural hash of a committed side (0 left, 1 right): the same for
+/// documents differing only in whitespace or object key order, different
+/// (barring a 64-bit collision) when any value, key or array order
+/// differs. Compare the two sides' hashes after `commit_left` and
+/// `commit_right`; equal hashes mean the documents hold the same values
+/// at the same paths, so a host may skip `finalize`. Values compare by their
+/// raw text, so `1.0` and `1` hash apart whatever `normalize_numbers`
+/// says.
+///
+/// Returns 0 (never a hash) for an invalid handle, a side other than 0
+/// or 1 (`InvalidSide`), a side not committed (`NoInputCommitted`), or
+/// once finalize has started (`Busy`, `EngineSealed`).
 #[no_mangle]
-pub extern "C" fn get_last_error(engine_ptr: *const Engine) -> *const u8 {
+pub extern "C" fn hash_document(engine_ptr: *mut Engine, side: u32) -> u64 {
+    match validate_engine(engine_ptr) {
+        Some(e) => e.hash_document(side),
+        None => 0,
+    }
+}
+
+/// Check that `doc_ptr`/`doc_len` holds one well-formed JSON document,
+/// strictly (RFC 8259): balanced brackets, no stray or trailing commas,
+/// closed strings with valid escapes and UTF-8, exact literals and
+/// numbers, nothing after the root value. Cheaper than a commit, and
+/// stricter: the parser accepts some malformed input.
+///
+/// Returns `Ok` for a valid document, `Error` with code `InvalidJson`
+/// otherwise; `get_last_error` then reads `invalid JSON at byte N: ...`
+/// with the offset of the first problem (the document's length when it
+/// ends early). The engine's input and state are untouched; any state
+/// but `Poisoned` will do. Audited as `ValidateJson` with the length.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn validate_json(engine_ptr: *mut Engine, doc_ptr: *const u8, doc_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    // SAFETY: the host passes `doc_len` readable bytes at a non-null `doc_ptr`.
+    let doc = if doc_ptr.is_null() || doc_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(doc_ptr, doc_len as usize) } };
+    let status = engine.validate_json(doc);
+    engine.audit(AuditOp::ValidateJson, doc_len, status);
+    status
+}
+
+/// Get the length of the result buffer: 0 until `finalize` succeeds.
+#[no_mangle]
+pub extern "C" fn get_result_len(engine_ptr: *const Engine) -> u32 {
     let engine = match validate_engine_const(engine_ptr) {
         Some(e) => e,
-        None => return ptr::null(),
+        None => return 0,
     };
 
-    engine.last_error_ptr()
+    engine.result_len()
 }
 
-/// Get the length of the last error message (UTF-8, null-terminated).
+/// Pointer to one op's channel of the result (`op` as `DiffOp`: 0 added,
+/// 1 removed, 2 modified), for engines configured with `split_by_op`: a
+/// result of its own, flagged `HEADER_FLAG_CHANNEL`, holding that op's
+/// entries in result order and no sections (layout in `memory.rs`). Null
+/// before `finalize` succeeds, without `split_by_op`, or for another
+/// `op`. Valid until the engine is cleared or destroyed.
 #[no_mangle]
-pub extern "C" fn get_last_error_len(engine_ptr: *const Engine) -> u32 {
+pub extern "C" fn get_result_ptr_for(engine_ptr: *const Engine, op: u8) -> *const u8 {
     let engine = match validate_engine_const(engine_ptr) {
         Some(e) => e,
-        None => return 0,
+        None => return ptr::null(),
     };
-    engine.last_error_len()
+    engine.result_for(op).map_or(ptr::null(), <[u8]>::as_ptr)
 }
 
-// ============================================================================
-// Internal Helpers
-// ============================================================================
+/// Length of `get_result_ptr_for(op)`'s channel; 0 where it is null.
+#[no_mangle]
+pub extern "C" fn get_result_len_for(engine_ptr: *const Engine, op: u8) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.result_for(op).map_or(0, |c| c.len() as u32)
+}
+
+/// Copy a page of the result to `out_ptr`, for hosts that would rather
+/// not copy a large result out of linear memory in one piece: the bytes
+/// from `offset`, at most `max_len`, cut back so the page ends on an
+/// entry boundary (the header and each 32-byte entry come whole; the
+/// sections after them split anywhere). Returns the bytes written, to
+/// add to `offset` for the next page; 0 at the end and on any error: an
+/// invalid handle, a null `out_ptr`, no result yet, an `offset` inside
+/// the header or an entry, or `max_len` short of the next entry. Pages
+/// of at least 32 bytes from offset 0 read the whole result.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_result_page(engine_ptr: *const Engine, offset: u32, out_ptr: *mut u8, max_len: u32) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    if out_ptr.is_null() {
+        return 0;
+    }
+
+    let Some(page) = engine.result_page(offset, max_len) else { return 0 };
+    // SAFETY: the host passes `max_len` writable bytes at `out_ptr`, and the page is no longer.
+    unsafe { ptr::copy_nonoverlapping(page.as_ptr(), out_ptr, page.len()) };
+    page.len() as u32
+}
+
+/// Get the result's entry count (header bytes `[4..8]`): 0 until
+/// `finalize` succeeds. Enough to tell whether anything changed without
+/// reading the result out of linear memory.
+#[no_mangle]
+pub extern "C" fn get_entry_count(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+
+    engine.entry_count()
+}
+
+/// Get entry `i`'s op (0 Added, 1 Removed, 2 Modified), read in place
+/// from the result. Returns 0xFF for an invalid handle, no result yet or
+/// `i` past the last entry, since 0 is an op.
+#[no_mangle]
+pub extern "C" fn get_entry_op(engine_ptr: *const Engine, i: u32) -> u8 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0xFF,
+    };
+
+    engine.entry_op(i).unwrap_or(0xFF)
+}
+
+/// Render entry `i`'s JSON Pointer and return a pointer to it; the
+/// length follows from `get_entry_path_len` with the same `i`. Works
+/// without `rendered_paths`: the first call then re-parses the inputs to
+/// record paths. The buffer holds one path and is valid until the next
+/// call or `destroy_engine`. Null for an invalid handle, no result yet or
+/// `i` past the last entry.
+#[no_mangle]
+pub extern "C" fn get_entry_path(engine_ptr: *mut Engine, i: u32) -> *const u8 {
+    match validate_engine(engine_ptr).and_then(|e| e.entry_path(i)) {
+        Some(path) => path.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Length of entry `i`'s JSON Pointer (rendered as by `get_entry_path`,
+/// so either call may come first); 0 where that returns null.
+#[no_mangle]
+pub extern "C" fn get_entry_path_len(engine_ptr: *mut Engine, i: u32) -> u32 {
+    match validate_engine(engine_ptr).and_then(|e| e.entry_path(i)) {
+        Some(path) => path.len() as u32,
+        None => 0,
+    }
+}
+
+/// Get a pointer to entry `i`'s left value, in the left input buffer,
+/// `get_entry_left_len` bytes long (the entry's span, so truncated if
+/// the result truncates it). Null for an entry with no left side (Added),
+/// an invalid handle, no result yet or `i` past the last entry.
+#[no_mangle]
+pub extern "C" fn get_entry_left(engine_ptr: *const Engine, i: u32) -> *const u8 {
+    entry_value(engine_ptr, Side::Left, i).map_or(ptr::null(), |v| v.as_ptr())
+}
+
+/// Length of entry `i`'s left value; 0 where `get_entry_left` is null.
+#[no_mangle]
+pub extern "C" fn get_entry_left_len(engine_ptr: *const Engine, i: u32) -> u32 {
+    entry_value(engine_ptr, Side::Left, i).map_or(0, |v| v.len() as u32)
+}
+
+/// `get_entry_left` for the right value; null for Removed entries.
+#[no_mangle]
+pub extern "C" fn get_entry_right(engine_ptr: *const Engine, i: u32) -> *const u8 {
+    entry_value(engine_ptr, Side::Right, i).map_or(ptr::null(), |v| v.as_ptr())
+}
+
+/// Length of entry `i`'s right value; 0 where `get_entry_right` is null.
+#[no_mangle]
+pub extern "C" fn get_entry_right_len(engine_ptr: *const Engine, i: u32) -> u32 {
+    entry_value(engine_ptr, Side::Right, i).map_or(0, |v| v.len() as u32)
+}
+
+fn entry_value(engine_ptr: *const Engine, side: Side, i: u32) -> Option<&'static [u8]> {
+    validate_engine_const(engine_ptr)?.entry_value(side, i)
+}
+
+/// Resolve a path id from the result (entry bytes `[8..16]`) to a dotted
+/// path for display, e.g. `a.b[0]`, and return a pointer to it; the
+/// length follows from `resolve_symbol_len` with the same id. Keys
+/// holding `.`, `[`, `]` or `"` are written `["key"]`; the root is empty.
+/// The buffer holds one symbol and is valid until the next call or
+/// `destroy_engine`. Null for an invalid handle, no result yet or an id
+/// neither input has.
+#[no_mangle]
+pub extern "C" fn resolve_symbol(engine_ptr: *mut Engine, path_id: u64) -> *const u8 {
+    match validate_engine(engine_ptr).and_then(|e| e.resolve_symbol(path_id)) {
+        Some(symbol) => symbol.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Length of the symbol `resolve_symbol` renders for `path_id` (either
+/// call may come first); 0 where that returns null.
+#[no_mangle]
+pub extern "C" fn resolve_symbol_len(engine_ptr: *mut Engine, path_id: u64) -> u32 {
+    match validate_engine(engine_ptr).and_then(|e| e.resolve_symbol(path_id)) {
+        Some(symbol) => symbol.len() as u32,
+        None => 0,
+    }
+}
+
+/// Pointer to the symbol table of the result (`Engine::batch_resolve_symbols`):
+/// `[u64 path id][u32 len][len bytes]` records, little endian, one per
+/// distinct path id in the entries, each rendered as `resolve_symbol`
+/// would. Built by the first call after `finalize`; valid until
+/// `clear_engine`, `trim_engine` level 1 or `destroy_engine`. Null for an
+/// invalid handle or no result yet.
+#[no_mangle]
+pub extern "C" fn get_symbol_table(engine_ptr: *mut Engine) -> *const u8 {
+    match validate_engine(engine_ptr).and_then(|e| e.batch_resolve_symbols()) {
+        Some(table) => table.as_ptr(),
+        None => ptr::null(),
+    }
+}
+
+/// Length of the table `get_symbol_table` points to (either call may come
+/// first); 0 where that returns null.
+#[no_mangle]
+pub extern "C" fn get_symbol_table_len(engine_ptr: *mut Engine) -> u32 {
+    match validate_engine(engine_ptr).and_then(|e| e.batch_resolve_symbols()) {
+        Some(table) => table.len() as u32,
+        None => 0,
+    }
+}
+
+/// Destroy the engine and free all associated memory.
+///
+/// This function is safe to call multiple times (double-free safe). With
+/// the `handles` feature a destroyed handle is `InvalidHandle` from then
+/// on, here as everywhere.
+#[no_mangle]
+pub extern "C" fn destroy_engine(engine_ptr: *mut Engine) -> Status {
+    if engine_ptr.is_null() {
+        return Status::Ok; // Already destroyed or never created
+    }
+    unregister(engine_ptr)
+}
+
+/// Reset the engine state for a new diff operation without re-allocating heap.
+///
+/// This is the reuse path for long-lived hosts: it un-seals (or
+/// un-poisons) the engine, clears the error, scopes, output overrides, suppressions and the
+/// result (header included), and keeps the input buffers, token vectors
+/// and arena capacity for the next diff. Structural indexes go back to
+/// the group's pool after every commit (`index_pool.rs`), so they are
+/// reused without a reset. The audit log and phase counters survive it.
+#[no_mangle]
+pub extern "C" fn clear_engine(engine_ptr: *mut Engine) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+
+    engine.clear();
+    engine.audit(AuditOp::Clear, 0, Status::Ok);
+    Status::Ok
+}
+
+/// Release memory an engine holds between uses, at `level` 0 (shrink
+/// buffers to their contents), 1 (also drop the span index and config
+/// JSON) or 2 (everything, as fresh; implies `clear_engine`). See
+/// `Engine::trim`. The bytes released are `trimmed_bytes` in
+/// `get_arena_stats`. Linear memory does not shrink, but later growth,
+/// here or in another engine, reuses what was released.
+#[no_mangle]
+pub extern "C" fn trim_engine(engine_ptr: *mut Engine, level: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.trim(level);
+    engine.audit(AuditOp::Trim, level, status);
+    status
+}
+
+/// Get pointer to the last error message. Each commit and finalize
+/// (`finalize_step` included) starts by clearing it, so after one of
+/// those it is that call's error or warning; other calls leave it as it
+/// was, and `clear_error` empties it.
+#[no_mangle]
+pub extern "C" fn get_last_error(engine_ptr: *const Engine) -> *const u8 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return ptr::null(),
+    };
+
+    engine.last_error_ptr()
+}
+
+/// Get the length of the last error message (UTF-8, null-terminated).
+#[no_mangle]
+pub extern "C" fn get_last_error_len(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.last_error_len()
+}
+
+/// Write the left then right input digest (16 bytes each, 32 total; see
+/// `digest.rs`) to `out_ptr`. Fails unless the engine was created with
+/// `input_digests`. The same digests are written into the result.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_input_digests(engine_ptr: *mut Engine, out_ptr: *mut u8) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    if out_ptr.is_null() {
+        return Status::Error;
+    }
+    match engine.input_digests() {
+        Ok(digests) => {
+            let bytes = digests.concat();
+            // SAFETY: the host passes 32 writable bytes at `out_ptr`.
+            unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+            Status::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Get the detailed code of the last error (see `ErrorCode` in `error.rs`
+/// for the reserved ranges), or 0 if none. Set alongside the message by
+/// every failing `Status`, and by warnings that still return `Ok`.
+#[no_mangle]
+pub extern "C" fn get_last_error_code(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.last_error_code()
+}
+
+/// Forget the last error: `get_last_error_len` and `get_last_error_code`
+/// read 0 until something fails again. Commits and finalize start from a
+/// clear buffer anyway, so this is for hosts that branch on the error
+/// after other calls.
+#[no_mangle]
+pub extern "C" fn clear_error(engine_ptr: *mut Engine) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    engine.clear_error();
+    Status::Ok
+}
+
+/// Render the engine's effective configuration as canonical JSON and
+/// return a pointer to it; the length follows from
+/// `get_effective_config_json_len`. Sorted keys, every field, output
+/// overrides applied, plus a `derived` object of computed values (e.g.
+/// `input_capacity_per_side`). Accepted back by `EngineConfig::from_json`.
+/// The buffer is valid until the next call or `destroy_engine`.
+#[no_mangle]
+pub extern "C" fn get_effective_config_json(engine_ptr: *mut Engine) -> *const u8 {
+    match validate_engine(engine_ptr) {
+        Some(e) => e.effective_config_json(),
+        None => ptr::null(),
+    }
+}
+
+/// Length of the JSON written by the last `get_effective_config_json`.
+#[no_mangle]
+pub extern "C" fn get_effective_config_json_len(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.config_json_len()
+}
+
+/// Write the current diff's work counters (`stats.rs`: bytes indexed,
+/// structural positions, tokens and Value tokens per side, entries by
+/// op, result bytes) to a buffer the engine owns and return a pointer to
+/// it, `get_stats_len` bytes long. Commits fill their side and finalize
+/// the rest; `clear_engine` zeroes them. The buffer is valid until the
+/// next call or `destroy_engine`.
+#[no_mangle]
+pub extern "C" fn get_stats(engine_ptr: *mut Engine) -> *const u8 {
+    match validate_engine(engine_ptr) {
+        Some(e) => e.stats_bytes(),
+        None => ptr::null(),
+    }
+}
+
+/// Length of the `get_stats` buffer: fixed, 0 for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_stats_len(engine_ptr: *const Engine) -> u32 {
+    match validate_engine_const(engine_ptr) {
+        Some(_) => stats::EngineStats::SIZE as u32,
+        None => 0,
+    }
+}
+
+/// Progress of the running (or last) commit or finalize, as
+/// `phase << 16 | permille`. Phases: 0 idle, 1 index, 2 parse (commit),
+/// 3 diff, 4 write (finalize); each runs 0..=1000 and the word never
+/// decreases within one call. Reset to 0 by `clear_engine`.
+///
+/// Updated with relaxed atomic stores, so a host sharing this module's
+/// memory can poll it from another worker while the call runs. Returns 0
+/// for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_progress(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.progress()
+}
+
+/// Restrict the left document to the subtree at a JSON Pointer: `ptr`
+/// must cover `len` readable bytes of its text. A null `ptr` or a `len`
+/// of 0 is the empty pointer, the whole document.
+///
+/// Paths are re-based so the subtree's root lines up with the right side's
+/// root (or right scope), e.g. left `/users` against an unwrapped right
+/// array. Must precede `commit_left`; cleared by `clear_engine`. If the
+/// pointer is absent from the document, `commit_left` fails.
+#[no_mangle]
+pub extern "C" fn set_left_scope(engine_ptr: *mut Engine, ptr: *const u8, len: u32) -> Status {
+    set_scope(engine_ptr, Side::Left, ptr, len)
+}
+
+/// Restrict the right document to the subtree at a JSON Pointer, `len`
+/// readable bytes at `ptr`. See `set_left_scope`.
+#[no_mangle]
+pub extern "C" fn set_right_scope(engine_ptr: *mut Engine, ptr: *const u8, len: u32) -> Status {
+    set_scope(engine_ptr, Side::Right, ptr, len)
+}
+
+/// Override finalize-time options after creation, e.g. once the host
+/// knows how it will present the result.
+///
+/// `ptr`/`len` hold `[u8 field][u32 value]` records (layout and
+/// precedence on `OutputOptions` in `config.rs`). Only `max_entry_bytes`,
+/// `oversize_policy`, `entry_order`, `coverage_map`, `value_hashes`,
+/// `top_k`, `significance`, `shape_only`, `numeric_deltas`,
+/// `rendered_paths`, `entry_sequence`, `self_contained` and the comparison settings `normalize_numbers`,
+/// `normalize_strings` and `ignore_case` are accepted; any other field
+/// fails with an error naming it. The comparison settings apply to
+/// committed sides without re-parsing them (see `normalize.rs`). Returns
+/// `EngineSealed` after finalize.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn set_output_options(engine_ptr: *mut Engine, ptr: *const u8, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let records = if ptr.is_null() || len == 0 {
+        &[][..]
+    } else {
+        // SAFETY: the host passes `len` readable bytes at a non-null `ptr`.
+        unsafe { core::slice::from_raw_parts(ptr, len as usize) }
+    };
+    let status = engine.set_output_options(records);
+    engine.audit(AuditOp::SetOutputOptions, len, status);
+    status
+}
+
+/// Replace the whole config of an engine no input has reached yet, e.g.
+/// to switch `array_diff_mode` without recreating it.
+///
+/// `config_ptr`/`config_len` are read as by `create_engine`. Allowed only
+/// before any commit or push: returns `TooLate` once input arrived (until
+/// `clear_engine`), `EngineSealed` after finalize, and `Error` for an
+/// invalid config, leaving the old one in place. A changed
+/// `max_input_size` reallocates the input buffers, so read
+/// `get_left_input_ptr` / `get_right_input_ptr` again afterwards.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn set_config(engine_ptr: *mut Engine, config_ptr: *const u8, config_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let bytes = if config_ptr.is_null() || config_len == 0 {
+        &[][..]
+    } else {
+        // SAFETY: the host passes `config_len` readable bytes at a non-null `config_ptr`.
+        unsafe { core::slice::from_raw_parts(config_ptr, config_len as usize) }
+    };
+    let status = engine.set_config(bytes);
+    engine.audit(AuditOp::SetConfig, config_len, status);
+    status
+}
+
+/// Set one config field by name, as `set_config` with only that field
+/// changed: a forward-compatible alternative to re-sending the binary
+/// config. `key_ptr`/`key_len` is the field's key in the JSON config form
+/// (`array_diff_mode`, `max_object_keys`, `redact_paths`, ...);
+/// `value_ptr`/`value_len` its value as text there, quotes optional for a
+/// string (`full`, `64`, `true`, `[".a.*"]`). Options accumulate. Same
+/// timing as `set_config`: `TooLate` once input arrived, `EngineSealed`
+/// after finalize. `UnknownOption` for a key that is not a field, `Error`
+/// for an invalid value, leaving the config as it was.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn set_option(engine_ptr: *mut Engine, key_ptr: *const u8, key_len: u32, value_ptr: *const u8, value_len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    if (key_ptr.is_null() && key_len != 0) || (value_ptr.is_null() && value_len != 0) {
+        return Status::Error;
+    }
+    // SAFETY: the host passes `key_len` readable bytes at `key_ptr`,
+    let key = if key_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(key_ptr, key_len as usize) } };
+    // SAFETY: and `value_len` at `value_ptr`, neither null here.
+    let value = if value_len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(value_ptr, value_len as usize) } };
+    let status = engine.set_option(key, value);
+    engine.audit(AuditOp::SetOption, value_len, status);
+    status
+}
+
+/// Load a previous result as suppressions for this engine's next finalize.
+///
+/// `ptr`/`len` is a complete result buffer written with `value_hashes`
+/// on. Entries of this diff whose path, op and right value match an entry
+/// of that result are dropped and counted (`get_suppressed_count`), so a
+/// known set of changes can be masked out of a later diff. Several loads
+/// accumulate. Fails with `InvalidSuppressions` for an unreadable buffer,
+/// another major version, or a result without the value hash section;
+/// returns `EngineSealed` after finalize. Cleared by `clear_engine`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn load_suppressions(engine_ptr: *mut Engine, ptr: *const u8, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let result = if ptr.is_null() || len == 0 {
+        &[][..]
+    } else {
+        // SAFETY: the host passes `len` readable bytes at a non-null `ptr`.
+        unsafe { core::slice::from_raw_parts(ptr, len as usize) }
+    };
+    let status = engine.load_suppressions(result);
+    engine.audit(AuditOp::LoadSuppressions, len, status);
+    status
+}
+
+/// Set the entry transform (see `transform.rs`), or remove it with null.
+///
+/// Each finalize from now on shows the transform every entry that
+/// survived suppression: its op, JSON Pointer and full payloads, as
+/// copies. It returns `TRANSFORM_KEEP`, `TRANSFORM_DROP` or
+/// `TRANSFORM_REDACT`, and may leave a `u32` annotation that the result
+/// carries in `SECTION_ANNOTATIONS`. Any other return code fails the
+/// finalize with `InvalidTransformResult`. In wasm `transform` is an
+/// index into the module's function table. Kept by `clear_engine`;
+/// returns `EngineSealed` after finalize.
+#[no_mangle]
+pub extern "C" fn set_entry_transform(engine_ptr: *mut Engine, transform: Option<EntryTransform>) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.set_entry_transform(transform);
+    engine.audit(AuditOp::SetEntryTransform, transform.is_some() as u32, status);
+    status
+}
+
+/// Entries dropped by loaded suppressions in the last finalize. Returns 0
+/// for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_suppressed_count(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.suppressed_entries()
+}
+
+/// Entries the last finalize left out of a result truncated at
+/// `max_memory_bytes` (`HEADER_FLAG_TRUNCATED`); 0 when it is complete.
+/// Returns 0 for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_dropped_count(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.dropped_entries()
+}
+
+/// Entries the last finalize had before the `top_k` cut (suppressed
+/// entries excluded), so a host showing the top k can say "of N". Equals
+/// the written count when `top_k` is off and nothing was skipped. Returns
+/// 0 for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_ranked_total(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.ranked_total()
+}
+
+/// Write an `ArenaStats` (24 bytes, layout in `memory.rs`) to `out_ptr`:
+/// the result length the last finalize computed and reserved before
+/// writing (see `arena_growth`), how often the buffer moved since
+/// creation or the last `clear_engine`, and what the last `trim_engine`
+/// released. The estimate equals
+/// `get_result_len` unless the arena hit `max_memory_bytes`; then it is
+/// what the result would have needed.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_arena_stats(engine_ptr: *const Engine, out_ptr: *mut u8) -> Status {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    if out_ptr.is_null() {
+        return Status::Error;
+    }
+
+    let bytes = engine.arena_stats().to_bytes();
+    // SAFETY: the host passes 24 writable bytes at `out_ptr`.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+    Status::Ok
+}
+
+/// Copy the audit log (see `audit.rs`) to `out_ptr`: as many of the
+/// newest 16-byte records as fit in `cap` bytes, oldest first. Returns
+/// the bytes written; 0 for an invalid handle, a null `out_ptr` or an
+/// engine created without `audit_log_len`. A host that wants the whole
+/// log passes `audit_log_len * 16`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_audit_log(engine_ptr: *const Engine, out_ptr: *mut u8, cap: u32) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    if out_ptr.is_null() {
+        return 0;
+    }
+
+    let held = engine.audit_log_len() / AuditRecord::SIZE;
+    let fit = cap as usize / AuditRecord::SIZE;
+    let bytes = engine.audit_log(held.saturating_sub(fit));
+    // SAFETY: the host passes `cap` writable bytes at `out_ptr`, and the records are no longer.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+    bytes.len() as u32
+}
+
+/// Build the right-document span index for the finalized result (layout
+/// in `span_index.rs`). Fails before `finalize`; cleared by `clear_engine`.
+#[no_mangle]
+pub extern "C" fn build_span_index(engine_ptr: *mut Engine) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let status = engine.build_span_index();
+    engine.audit(AuditOp::BuildSpanIndex, engine.span_index_len(), status);
+    status
+}
+
+/// Pointer to the span index built by `build_span_index`.
+#[no_mangle]
+pub extern "C" fn get_span_index_ptr(engine_ptr: *const Engine) -> *const u8 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return ptr::null(),
+    };
+    engine.span_index_ptr()
+}
+
+/// Length of the span index in bytes (12 per record).
+#[no_mangle]
+pub extern "C" fn get_span_index_len(engine_ptr: *const Engine) -> u32 {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return 0,
+    };
+    engine.span_index_len()
+}
+
+/// Write a `MemoryReport` (32 bytes, layout in `memory.rs`) to `out_ptr`.
+///
+/// The report holds high-water marks since creation or the last
+/// `clear_engine`, for sizing `max_memory_bytes`.
+#[allow(clippy::not_unsafe_ptr_arg_deref)]
+#[no_mangle]
+pub extern "C" fn get_memory_report(engine_ptr: *const Engine, out_ptr: *mut u8) -> Status {
+    let engine = match validate_engine_const(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    if out_ptr.is_null() {
+        return Status::Error;
+    }
+
+    let bytes = engine.memory_report().to_bytes();
+    // SAFETY: the host passes 32 writable bytes at `out_ptr`.
+    unsafe { ptr::copy_nonoverlapping(bytes.as_ptr(), out_ptr, bytes.len()) };
+    Status::Ok
+}
+
+/// Write what the engine holds right now (`MemoryUsage`, 40 bytes, layout
+/// in `memory.rs`) to a buffer it owns and return a pointer to it: input,
+/// token and structural index capacities, path table bytes, and the
+/// result arena's length and capacity, read from the collections at the
+/// call. The buffer is valid until the next call or `destroy_engine`;
+/// null for an invalid handle.
+#[no_mangle]
+pub extern "C" fn get_memory_usage(engine_ptr: *mut Engine) -> *const u8 {
+    match validate_engine(engine_ptr) {
+        Some(e) => e.memory_usage_bytes(),
+        None => ptr::null(),
+    }
+}
+
+// ============================================================================
+// Internal Helpers
+// ============================================================================
+
+fn read_config(config_ptr: *const u8, config_len: u32) -> Result<EngineConfig, EngineError> {
+    if config_ptr.is_null() || config_len == 0 {
+        return Ok(EngineConfig::default());
+    }
+    // SAFETY: the host passes `config_len` readable bytes at a non-null `config_ptr`.
+    let config_slice = unsafe { core::slice::from_raw_parts(config_ptr, config_len as usize) };
+    EngineConfig::from_bytes(config_slice).map_err(EngineError::Config)
+}
+
+/// Hand a created engine to the host, or record why there is none.
+fn box_engine(engine: Result<Engine, EngineError>, config_len: u32) -> *mut Engine {
+    error::with_create_error(|e| match &engine {
+        Ok(_) => e.clear(),
+        Err(err) => e.set(err),
+    });
+    match engine {
+        Ok(mut engine) => {
+            engine.audit(AuditOp::Create, config_len, Status::Ok);
+            register(engine)
+        }
+        Err(_) => ptr::null_mut(),
+    }
+}
+
+/// What the host gets for a new engine: its address.
+#[cfg(not(feature = "handles"))]
+fn register(engine: Engine) -> *mut Engine {
+    Box::into_raw(Box::new(engine))
+}
+
+/// What the host gets for a new engine: its handle, as a pointer.
+#[cfg(feature = "handles")]
+fn register(engine: Engine) -> *mut Engine {
+    match handles::insert(engine) {
+        Ok(handle) => handle as usize as *mut Engine,
+        Err(e) => {
+            error::with_create_error(|buf| buf.set(&e));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Drop the engine at `engine_ptr`, whose magic must still be set.
+#[cfg(not(feature = "handles"))]
+fn unregister(engine_ptr: *mut Engine) -> Status {
+    // Check magic before destroying
+    let engine = unsafe { &mut *engine_ptr };
+    if engine.magic() != ENGINE_MAGIC {
+        return Status::InvalidHandle;
+    }
+
+    // Zero the magic to prevent double-free
+    engine.clear_magic();
+
+    // Drop the engine
+    unsafe {
+        let _ = Box::from_raw(engine_ptr);
+    }
+
+    Status::Ok
+}
+
+/// Drop the engine `engine_ptr` is the live handle of.
+#[cfg(feature = "handles")]
+fn unregister(engine_ptr: *mut Engine) -> Status {
+    match handles::remove(engine_ptr as usize) {
+        Some(mut engine) => {
+            engine.clear_magic();
+            Status::Ok
+        }
+        None => Status::InvalidHandle,
+    }
+}
+
+fn set_scope(engine_ptr: *mut Engine, side: Side, ptr: *const u8, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    let pointer = if ptr.is_null() || len == 0 {
+        &[][..]
+    } else {
+        // SAFETY: the host passes `len` readable bytes at a non-null `ptr`.
+        unsafe { core::slice::from_raw_parts(ptr, len as usize) }
+    };
+    let status = engine.set_scope(side, pointer);
+    let op = match side {
+        Side::Left => AuditOp::SetLeftScope,
+        Side::Right => AuditOp::SetRightScope,
+    };
+    engine.audit(op, len, status);
+    status
+}
+
+fn push(engine_ptr: *mut Engine, side: Side, ptr: *const u8, len: u32) -> Status {
+    let engine = match validate_engine(engine_ptr) {
+        Some(e) => e,
+        None => return Status::InvalidHandle,
+    };
+    if ptr.is_null() && len != 0 {
+        return Status::Error;
+    }
+    // SAFETY: the host passes `len` readable bytes at a non-null `ptr`
+    // (null with `len != 0` is rejected above).
+    let chunk = if len == 0 { &[][..] } else { unsafe { core::slice::from_raw_parts(ptr, len as usize) } };
+    let status = engine.push(side, chunk);
+    let op = match side {
+        Side::Left => AuditOp::PushLeft,
+        Side::Right => AuditOp::PushRight,
+    };
+    engine.audit(op, len, status);
+    status
+}
 
 fn validate_engine(ptr: *mut Engine) -> Option<&'static mut Engine> {
     if ptr.is_null() {
         return None;
     }
+    #[cfg(feature = "handles")]
+    let ptr = handles::resolve(ptr as usize)?;
 
     let engine = unsafe { &mut *ptr };
     if engine.magic() != ENGINE_MAGIC {
@@ -200,6 +1695,8 @@ fn validate_engine_const(ptr: *const Engine) -> Option<&'static Engine> {
     if ptr.is_null() {
         return None;
     }
+    #[cfg(feature = "handles")]
+    let ptr = handles::resolve(ptr as usize)?;
 
     let engine = unsafe { &*ptr };
     if engine.magic() != ENGINE_MAGIC {
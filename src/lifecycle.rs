@@ -0,0 +1,141 @@
+//! Engine lifecycle (`EngineState`).
+//!
+//! Every mutating call asks [`EngineState::next`] first whether it may
+//! run, and gets the state it leaves the engine in, so a call out of
+//! order fails with the same status whichever export it came through:
+//!
+//! ```text
+//!            commit_left                commit_right
+//!   Created ─────────────▶ LeftCommitted ─────────────▶ BothCommitted
+//!      │                                                     ▲
+//!      │ commit_right                           commit_left  │
+//!      └─────────────────▶ RightCommitted ───────────────────┘
+//!
+//!   any of the four ── finalize / finalize_step ──▶ Finalizing
+//!   Finalizing ── the step that writes the result (or is cancelled) ──▶ Sealed
+//!   Finalizing, or a commit ── a failure that leaves no usable state ──▶ Poisoned
+//!   every state ── clear_engine, trim_engine(2) ──▶ Created
+//! ```
+//!
+//! A one-shot `finalize` passes through `Finalizing` within the call, as
+//! `lint` does after committing the canonical side like a commit. A
+//! finalize refused before it seals (`NoInputCommitted`, a failed pushed
+//! commit) leaves the state as it was. What each state refuses:
+//!
+//! | state         | commit, push, options | config         | finalize   | read result  |
+//! |---------------|-----------------------|----------------|------------|--------------|
+//! | Created       | yes                   | yes            | starts     | NotFinalized |
+//! | committed (3) | yes                   | `TooLate`      | starts     | NotFinalized |
+//! | Finalizing    | `Busy`                | `Busy`         | continues  | NotFinalized |
+//! | Sealed        | `EngineSealed`        | `EngineSealed` | no-op      | yes          |
+//! | Poisoned      | `Poisoned`            | `Poisoned`     | `Poisoned` | `Poisoned`   |
+//!
+//! Recommitting a side (`recommit_replaces`), the third input and pushed
+//! but uncommitted input (which `set_config` refuses too) are the
+//! engine's own checks; none moves the state. Reads of counters,
+//! errors and buffers, `cancel`, `clear_engine` and `trim_engine` run in
+//! every state.
+
+use crate::engine::Side;
+use crate::error::EngineError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EngineState {
+    Created,
+    LeftCommitted,
+    RightCommitted,
+    BothCommitted,
+    /// Sealed, with a `finalize_step` join in progress.
+    Finalizing,
+    /// The result is written.
+    Sealed,
+    /// A failure left the engine unusable until `clear`.
+    Poisoned,
+}
+
+/// What a call does, as far as the lifecycle is concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    /// `commit_left` / `commit_right`.
+    Commit(Side),
+    /// Input and options short of a commit: pushes, the third input,
+    /// scopes, output options, suppressions, the entry transform.
+    Prepare,
+    /// `set_config`: replaces what the buffers were sized for, so only
+    /// before any commit.
+    Configure,
+    /// `finalize` / `finalize_step`.
+    Finalize,
+    /// Derived from the result: `build_span_index`.
+    ReadResult,
+}
+
+impl EngineState {
+    /// The state `op` leaves the engine in, or why it may not run now.
+    pub fn next(self, op: Op) -> Result<Self, EngineError> {
+        use EngineState::*;
+        match (self, op) {
+            (Poisoned, _) => Err(EngineError::Poisoned),
+            (Finalizing, Op::Commit(_) | Op::Prepare | Op::Configure) => Err(EngineError::Busy),
+            (Sealed, Op::Commit(_) | Op::Prepare | Op::Configure) => Err(EngineError::EngineSealed),
+            (Sealed, Op::Finalize | Op::ReadResult) => Ok(Sealed),
+            (_, Op::ReadResult) => Err(EngineError::NotFinalized),
+            (_, Op::Finalize) => Ok(Finalizing),
+            (_, Op::Prepare) => Ok(self),
+            (Created, Op::Configure) => Ok(Created),
+            (_, Op::Configure) => Err(EngineError::TooLate),
+            (Created | LeftCommitted, Op::Commit(Side::Left)) => Ok(LeftCommitted),
+            (Created | RightCommitted, Op::Commit(Side::Right)) => Ok(RightCommitted),
+            (_, Op::Commit(_)) => Ok(BothCommitted),
+        }
+    }
+
+    /// Whether `side` has been committed since the last `clear`. Only
+    /// asked of open states: past them nothing is committed any more.
+    pub fn committed(self, side: Side) -> bool {
+        use EngineState::*;
+        match side {
+            Side::Left => matches!(self, LeftCommitted | BothCommitted),
+            Side::Right => matches!(self, RightCommitted | BothCommitted),
+        }
+    }
+
+    /// Not yet finalizing: takes input and options.
+    pub fn is_open(self) -> bool {
+        matches!(self, Self::Created | Self::LeftCommitted | Self::RightCommitted | Self::BothCommitted)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::status::Status;
+    use crate::error::ErrorCode;
+
+    #[test]
+    fn every_state_and_op() {
+        use EngineState::*;
+        use ErrorCode::{Busy, EngineSealed, NotFinalized, Poisoned as Poison, TooLate};
+        let ops = [Op::Commit(Side::Left), Op::Commit(Side::Right), Op::Prepare, Op::Configure, Op::Finalize, Op::ReadResult];
+        // Per state, per op: the state it leads to, or the refusal.
+        let table = [
+            (Created, [Ok(LeftCommitted), Ok(RightCommitted), Ok(Created), Ok(Created), Ok(Finalizing), Err(NotFinalized)]),
+            (LeftCommitted, [Ok(LeftCommitted), Ok(BothCommitted), Ok(LeftCommitted), Err(TooLate), Ok(Finalizing), Err(NotFinalized)]),
+            (RightCommitted, [Ok(BothCommitted), Ok(RightCommitted), Ok(RightCommitted), Err(TooLate), Ok(Finalizing), Err(NotFinalized)]),
+            (BothCommitted, [Ok(BothCommitted), Ok(BothCommitted), Ok(BothCommitted), Err(TooLate), Ok(Finalizing), Err(NotFinalized)]),
+            (Finalizing, [Err(Busy), Err(Busy), Err(Busy), Err(Busy), Ok(Finalizing), Err(NotFinalized)]),
+            (Sealed, [Err(EngineSealed), Err(EngineSealed), Err(EngineSealed), Err(EngineSealed), Ok(Sealed), Ok(Sealed)]),
+            (Poisoned, [Err(Poison), Err(Poison), Err(Poison), Err(Poison), Err(Poison), Err(Poison)]),
+        ];
+        for (state, row) in table {
+            for (op, want) in ops.into_iter().zip(row) {
+                assert_eq!(state.next(op).map_err(|e| ErrorCode::from(&e)), want, "{:?} {:?}", state, op);
+            }
+            assert_eq!(state.is_open(), state.next(Op::Prepare).is_ok(), "{:?}", state);
+        }
+        let statuses = [(EngineError::EngineSealed, Status::EngineSealed), (EngineError::Busy, Status::Busy), (EngineError::Poisoned, Status::Poisoned), (EngineError::TooLate, Status::TooLate), (EngineError::NotFinalized, Status::Error)];
+        for (error, status) in statuses {
+            assert_eq!(Status::from(&error), status);
+        }
+    }
+}
@@ -0,0 +1,315 @@
+//! Canonical rendering of a committed document, for `lint`.
+//!
+//! Lint commits the canonical form of one side as the other and diffs the
+//! two, so every entry is a value written differently from its canonical
+//! form: the same JSON value, formatted otherwise. The canonical form:
+//!
+//! - No whitespace.
+//! - Object members sorted by key bytes, as written (stable, so duplicate
+//!   keys keep their order). Keys themselves are left as written: paths
+//!   hash keys exactly, and a rewritten key would be a different path.
+//! - Numbers as their exact decimal value (`normalize::canonical_number`)
+//!   in the shortest plain form: integers without fraction or exponent up
+//!   to 21 digits, `0.000001` down to six leading zeros, else one digit
+//!   before the point and an exponent (`1.5e-7`, `1e21`). `1.0`, `10e-1`
+//!   and `1` all render `1`; `-0` renders `0`. Nothing is rounded.
+//! - Strings with only the escapes JSON needs: `"`, `\`, and control
+//!   characters (`\b \f \n \r \t`, else `\u00XX`). A body with an
+//!   undecodable escape stays as written.
+//! - `true`, `false` and `null` as written.
+//! - A string or number longer than `max_primitive_bytes` as written, so
+//!   one huge value never costs a second pass over it.
+//!
+//! Sorting moves values, not paths, so the join does not see a reordered
+//! object; [`Canonical::reordered`] lists them for `lint` to report.
+
+use crate::escape;
+use crate::normalize::canonical_number;
+use crate::parser::ParseError;
+use alloc::vec::Vec;
+
+/// A document's canonical text, and where its reordered objects start.
+pub struct Canonical {
+    pub text: Vec<u8>,
+    /// Input offsets of the `{` of each object whose members the
+    /// rendering sorted, ascending.
+    pub reordered: Vec<u32>,
+}
+
+/// An open container: its rendering so far.
+enum Frame {
+    /// `members`: each key's input span and the end of its value in `buf`
+    /// (the previous member's end is its start). `key` is the pending one.
+    Object { start: u32, buf: Vec<u8>, members: Vec<(usize, usize, usize)>, key: (usize, usize) },
+    /// `buf` holds `[` and the elements so far, comma-separated.
+    Array { buf: Vec<u8> },
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Value,
+    ValueOrEnd,
+    KeyOrEnd,
+    Key,
+    Colon,
+    CommaOrEnd,
+    End,
+}
+
+/// Render `input`, a document the parser accepted, canonically, leaving
+/// primitives longer than `max_primitive` (0 for no cap) as written.
+/// Containers are tracked on the heap, so nesting depth costs no stack.
+pub fn canonicalize(input: &[u8], max_primitive: u32) -> Result<Canonical, ParseError> {
+    let oversized = |span: &[u8]| max_primitive != 0 && span.len() > max_primitive as usize;
+    let mut stack: Vec<Frame> = Vec::new();
+    let mut text = Vec::with_capacity(input.len());
+    let mut reordered = Vec::new();
+    let mut expect = Expect::Value;
+    let mut pos = 0;
+    loop {
+        while input.get(pos).is_some_and(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r')) { pos += 1; }
+        let Some(&b) = input.get(pos) else {
+            if expect != Expect::End { return Err(ParseError::IncompleteInput); }
+            // Objects close innermost first.
+            reordered.sort_unstable();
+            return Ok(Canonical { text, reordered });
+        };
+        match (expect, b) {
+            (Expect::Value | Expect::ValueOrEnd, b'{') => {
+                stack.push(Frame::Object { start: pos as u32, buf: Vec::new(), members: Vec::new(), key: (0, 0) });
+                expect = Expect::KeyOrEnd;
+                pos += 1;
+            }
+            (Expect::Value | Expect::ValueOrEnd, b'[') => {
+                stack.push(Frame::Array { buf: alloc::vec![b'['] });
+                expect = Expect::ValueOrEnd;
+                pos += 1;
+            }
+            (Expect::Value | Expect::ValueOrEnd, b'"') => {
+                let end = string_end(input, pos + 1)?;
+                let out = value_target(&mut stack, &mut text);
+                let body = &input[pos + 1..end];
+                out.push(b'"');
+                if oversized(body) { out.extend_from_slice(body) } else { write_string(body, out) }
+                out.push(b'"');
+                expect = value_done(&mut stack);
+                pos = end + 1;
+            }
+            (Expect::Value | Expect::ValueOrEnd, _) if is_literal_byte(b) => {
+                let end = input[pos..].iter().position(|&b| !is_literal_byte(b)).map_or(input.len(), |n| pos + n);
+                let (literal, out) = (&input[pos..end], value_target(&mut stack, &mut text));
+                if oversized(literal) { out.extend_from_slice(literal) } else { write_literal(literal, out) }
+                expect = value_done(&mut stack);
+                pos = end;
+            }
+            (Expect::KeyOrEnd | Expect::Key, b'"') => {
+                let end = string_end(input, pos + 1)?;
+                if let Some(Frame::Object { key, .. }) = stack.last_mut() { *key = (pos + 1, end); }
+                expect = Expect::Colon;
+                pos = end + 1;
+            }
+            (Expect::Colon, b':') => {
+                expect = Expect::Value;
+                pos += 1;
+            }
+            (Expect::CommaOrEnd, b',') => {
+                expect = if matches!(stack.last(), Some(Frame::Object { .. })) { Expect::Key } else { Expect::Value };
+                pos += 1;
+            }
+            (Expect::KeyOrEnd | Expect::CommaOrEnd, b'}') if matches!(stack.last(), Some(Frame::Object { .. })) => {
+                let Some(Frame::Object { start, buf, members, .. }) = stack.pop() else { unreachable!() };
+                let rendered = render_object(input, &buf, &members);
+                if rendered.1 { reordered.push(start); }
+                value_target(&mut stack, &mut text).extend_from_slice(&rendered.0);
+                expect = value_done(&mut stack);
+                pos += 1;
+            }
+            (Expect::ValueOrEnd | Expect::CommaOrEnd, b']') if matches!(stack.last(), Some(Frame::Array { .. })) => {
+                let Some(Frame::Array { mut buf }) = stack.pop() else { unreachable!() };
+                buf.push(b']');
+                value_target(&mut stack, &mut text).extend_from_slice(&buf);
+                expect = value_done(&mut stack);
+                pos += 1;
+            }
+            _ => return Err(ParseError::UnexpectedByte(b)),
+        }
+    }
+}
+
+/// Where the next value goes: the innermost container, after a comma if
+/// it is an array with elements already, or the document.
+fn value_target<'s>(stack: &'s mut [Frame], text: &'s mut Vec<u8>) -> &'s mut Vec<u8> {
+    match stack.last_mut() {
+        None => text,
+        Some(Frame::Object { buf, .. }) => buf,
+        Some(Frame::Array { buf }) => {
+            if buf.len() > 1 { buf.push(b','); }
+            buf
+        }
+    }
+}
+
+/// Note a value just written, and say what may follow it.
+fn value_done(stack: &mut [Frame]) -> Expect {
+    match stack.last_mut() {
+        None => Expect::End,
+        Some(Frame::Object { buf, members, key, .. }) => {
+            members.push((key.0, key.1, buf.len()));
+            Expect::CommaOrEnd
+        }
+        Some(Frame::Array { .. }) => Expect::CommaOrEnd,
+    }
+}
+
+/// An object's canonical text, and whether sorting moved a member.
+fn render_object(input: &[u8], buf: &[u8], members: &[(usize, usize, usize)]) -> (Vec<u8>, bool) {
+    let mut order: Vec<usize> = (0..members.len()).collect();
+    order.sort_by(|&a, &b| input[members[a].0..members[a].1].cmp(&input[members[b].0..members[b].1]));
+    let moved = order.iter().enumerate().any(|(i, &m)| i != m);
+    let mut out = Vec::with_capacity(buf.len() + 2 + members.len() * 4);
+    out.push(b'{');
+    for (n, &m) in order.iter().enumerate() {
+        let (key_start, key_end, value_end) = members[m];
+        let value_start = if m == 0 { 0 } else { members[m - 1].2 };
+        if n > 0 { out.push(b','); }
+        out.push(b'"');
+        out.extend_from_slice(&input[key_start..key_end]);
+        out.extend_from_slice(b"\":");
+        out.extend_from_slice(&buf[value_start..value_end]);
+    }
+    out.push(b'}');
+    (out, moved)
+}
+
+/// Offset of the quote closing the string whose body starts at `from`.
+pub fn string_end(input: &[u8], from: usize) -> Result<usize, ParseError> {
+    let mut i = from;
+    while let Some(&b) = input.get(i) {
+        match b {
+            b'"' => return Ok(i),
+            b'\\' => i += 2,
+            _ => i += 1,
+        }
+    }
+    Err(ParseError::IncompleteInput)
+}
+
+pub fn is_literal_byte(b: u8) -> bool { b.is_ascii_alphanumeric() || matches!(b, b'-' | b'+' | b'.') }
+
+/// A string body with only the escapes JSON requires.
+fn write_string(body: &[u8], out: &mut Vec<u8>) {
+    if !body.contains(&b'\\') || escape::next_invalid(body, 0).is_some() {
+        out.extend_from_slice(body);
+        return;
+    }
+    let mut decoded = Vec::with_capacity(body.len());
+    escape::decode(body, &mut decoded);
+    for &b in &decoded {
+        match b {
+            b'"' => out.extend_from_slice(b"\\\""),
+            b'\\' => out.extend_from_slice(b"\\\\"),
+            0x08 => out.extend_from_slice(b"\\b"),
+            0x0c => out.extend_from_slice(b"\\f"),
+            b'\n' => out.extend_from_slice(b"\\n"),
+            b'\r' => out.extend_from_slice(b"\\r"),
+            b'\t' => out.extend_from_slice(b"\\t"),
+            0..=0x1f => out.extend_from_slice(alloc::format!("\\u{:04x}", b).as_bytes()),
+            _ => out.push(b),
+        }
+    }
+}
+
+/// A number in its shortest plain form (module docs); anything else as
+/// written.
+fn write_literal(literal: &[u8], out: &mut Vec<u8>) {
+    let mut exact = Vec::new();
+    if !canonical_number(literal, &mut exact) {
+        out.extend_from_slice(literal);
+        return;
+    }
+    // `exact` is `[-]digits e exponent`, digits without leading or
+    // trailing zeros (`0e0` for zero).
+    let e = exact.iter().position(|&b| b == b'e').unwrap_or(exact.len());
+    let exponent: i64 = core::str::from_utf8(&exact[e + 1..]).ok().and_then(|s| s.parse().ok()).unwrap_or(0);
+    let (negative, digits) = match exact[..e].split_first() {
+        Some((b'-', digits)) => (true, digits),
+        _ => (false, &exact[..e]),
+    };
+    if digits == b"0" {
+        out.push(b'0');
+        return;
+    }
+    if negative { out.push(b'-'); }
+    // The decimal point sits `point` digits in: value = 0.digits * 10^point.
+    let k = digits.len() as i64;
+    let point = exponent.saturating_add(k);
+    if (k..=21).contains(&point) {
+        out.extend_from_slice(digits);
+        out.resize(out.len() + (point - k) as usize, b'0');
+    } else if (1..=21).contains(&point) {
+        out.extend_from_slice(&digits[..point as usize]);
+        out.push(b'.');
+        out.extend_from_slice(&digits[point as usize..]);
+    } else if (-5..=0).contains(&point) {
+        out.extend_from_slice(b"0.");
+        out.resize(out.len() + (-point) as usize, b'0');
+        out.extend_from_slice(digits);
+    } else {
+        out.push(digits[0]);
+        if k > 1 {
+            out.push(b'.');
+            out.extend_from_slice(&digits[1..]);
+        }
+        out.push(b'e');
+        out.extend_from_slice(alloc::format!("{}", point.saturating_sub(1)).as_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(doc: &str) -> (alloc::string::String, Vec<u32>) {
+        let c = canonicalize(doc.as_bytes(), 0).unwrap();
+        (alloc::string::String::from_utf8(c.text).unwrap(), c.reordered)
+    }
+
+    #[test]
+    fn numbers_render_in_their_shortest_plain_form() {
+        let cases = [
+            ("1", "1"),
+            ("1.0", "1"),
+            ("10e-1", "1"),
+            ("-0", "0"),
+            ("0.000e5", "0"),
+            ("1500", "1500"),
+            ("1.5E3", "1500"),
+            ("-0.25", "-0.25"),
+            ("0.000001", "0.000001"),
+            ("1e-7", "1e-7"),
+            ("123e-9", "1.23e-7"),
+            ("1e21", "1e21"),
+            ("1e20", "100000000000000000000"),
+            ("9007199254740993", "9007199254740993"),
+        ];
+        for (raw, want) in cases {
+            assert_eq!(canonical(raw).0, want, "{}", raw);
+        }
+    }
+
+    #[test]
+    fn objects_sort_and_strings_drop_needless_escapes() {
+        let (text, reordered) = canonical(r#" { "b" : [ 1.0 , {"y":1,"x":"\u00e9\/\"\u0001"} ], "a" : true } "#);
+        assert_eq!(text, r#"{"a":true,"b":[1,{"x":"é/\"\u0001","y":1}]}"#);
+        assert_eq!(reordered, [1, 17]);
+        // Canonical text is its own canonical form.
+        assert_eq!(canonical(&text), (text.clone(), Vec::new()));
+        // Keys stay as written; an undecodable escape keeps its string as is.
+        assert_eq!(canonical(r#"{"\u0062":1, "a":"\x"}"#).0, r#"{"\u0062":1,"a":"\x"}"#);
+        assert_eq!(canonicalize(b"[1,", 0).err(), Some(ParseError::IncompleteInput));
+        assert_eq!(canonicalize(b"[1}", 0).err(), Some(ParseError::UnexpectedByte(b'}')));
+        // Over the cap, values stay as written.
+        let capped = canonicalize(br#"[1.50,"\/",2.0]"#, 3).unwrap().text;
+        assert_eq!(capped, br#"[1.50,"/",2]"#);
+    }
+}
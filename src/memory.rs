@@ -1,79 +1,407 @@
 //! Arena-based memory management for diff results.
 //!
-//! Memory layout v2.1: Packed 8-byte aligned symbolic entries.
+//! Memory layout v2.14: Packed 8-byte aligned symbolic entries, then
+//! optional sections.
+//!
+//! Entries never carry value bytes: each side is an `(offset, len)`
+//! reference into the committed input, resolved by the host (or
+//! `RawEntry::values` in `reader.rs`) while the inputs are resident.
+//!
+//! Every integer is little-endian, on every host: the writer encodes with
+//! `to_le_bytes` and `reader.rs` decodes with `from_le_bytes`, byte slice
+//! to value, never by casting the buffer to wider integers or structs.
+//!
+//! Hash values are format too: entry path ids (`path.rs` fold hashes)
+//! and the value hash section (`hash_bytes_simd`) are pinned by golden
+//! tests, and changing either means a `FORMAT_VERSION_MAJOR` bump. 2.8
+//! is the exception noted at `hash_bytes_simd`: only the path ids through
+//! an empty key moved, off their parent's. 3.0 is such a bump: key path
+//! ids fold the key's length in.
+//!
+//! Alignment: the buffer starts 8-byte aligned (`AlignedBuf`), the header
+//! and entries are multiples of 8 bytes, and section bodies are padded to
+//! 8-byte offsets, so every `u64` field sits at an 8-byte aligned address.
+//!
+//! Header (16 bytes): `[u16 major][u16 minor][u32 entry_count]
+//! [u32 total_len][u32 flags]`. Up to 2.3, `[8..16]` was a u64 length
+//! whose high half was always zero (the arena is u32-bounded); 2.4 uses
+//! that half for `HEADER_FLAG_*` bits.
+//!
+//! Forward compatibility: everything a reader may not know is skippable.
+//!
+//! - [`HEADER_FLAG_ENTRY_EXT`]: each entry's `[4..8]` (otherwise padding)
+//!   is the byte length of an extension that follows its 32-byte core, a
+//!   multiple of 8. This writer never sets it; readers step over it.
+//! - [`HEADER_FLAG_SECTIONS`]: a section table follows the last entry:
+//!   `[u32 count][u32 reserved]`, then `count` x `[u32 id][u32 offset]
+//!   [u32 len]` (offsets from the start of the buffer), then the bodies,
+//!   each 8-byte aligned. Readers skip ids they don't know by offset.
+//!
+//! Growth: finalize knows every entry and section before writing, so
+//! [`ResultArena::estimate_len`] gives the exact final length and
+//! [`ResultArena::reserve`] allocates it once (`ArenaGrowth::Reserve`).
+//! An estimate over the limit would fail partway anyway; the buffer then
+//! grows by [`ARENA_CHUNK`] up to the limit instead of doubling past it.
+//!
+//! Entry flags (byte [3]): [`ENTRY_FLAG_TRUNCATED`], and from 2.6
+//! [`ENTRY_FLAG_LEFT_INVALID_UTF8`] / [`ENTRY_FLAG_RIGHT_INVALID_UTF8`],
+//! set when that side's payload, as written (after any truncation), is not
+//! valid UTF-8. An absent side is never flagged. Before 2.6 the bits are
+//! always clear, which says nothing about the payloads. From 2.7, with
+//! `numeric_deltas`: [`ENTRY_FLAG_NUMERIC_DELTA`] (the entry's slot in
+//! [`SECTION_NUMERIC_DELTAS`] holds its delta) or
+//! [`ENTRY_FLAG_DELTA_INEXACT`] (both sides are numbers, but an integer
+//! beyond `±2^53` left it without one; see `numeric.rs`). From 2.9:
+//! [`ENTRY_FLAG_REDACTED`], the entry transform replaced each present side
+//! with an empty string span (`transform.rs`). From 2.12:
+//! [`ENTRY_FLAG_FORMATTING`], in a lint result (`lint.rs`, flagged
+//! [`HEADER_FLAG_LINT`]): the sides are one JSON value written two ways.
+//! From 2.13: [`ENTRY_FLAG_OVERSIZED_PRIMITIVE`], a side is a string or
+//! literal longer than `max_primitive_bytes`, compared by its raw bytes
+//! and cut to that length (with [`ENTRY_FLAG_TRUNCATED`]); its full
+//! length is in [`SECTION_VALUE_LENGTHS`].
+//!
+//! 2.3 and 2.4 wrote sections as `[u32 kind][u32 record_count]` runs
+//! instead; `reader.rs` still decodes those for known kinds.
+//!
+//! Section ids:
+//!
+//! - [`SECTION_COVERAGE`]: `[u32 start][u32 end]` half-open byte ranges of
+//!   the left input with no counterpart on the right, sorted and merged.
+//! - [`SECTION_INPUT_DIGESTS`]: 32 bytes, the left then right input
+//!   digest (see `digest.rs`); an uncommitted side hashes as empty.
+//! - [`SECTION_VALUE_HASHES`]: one `u64` per entry, in entry order: the
+//!   hash (`hash_bytes_simd`) of the entry's full right value, before any
+//!   truncation; 0 for Removed entries. `load_suppressions` keys on it.
+//! - [`SECTION_AUDIT_LOG`]: 16-byte audit records, oldest first, of the
+//!   calls before this finalize (layout in `audit.rs`).
+//! - [`SECTION_CANARY`]: `three_way` only, one 16-byte record per entry,
+//!   in entry order: `[u8 agreement][u8 canary_op][u8 canary_kind]
+//!   [u8 reserved][u32 reserved][u32 canary_offset][u32 canary_len]`, the
+//!   canary's side of the entry with its span into the third input
+//!   (`canary_op` 0xFF where the canary matches the left; see
+//!   `three_way.rs`).
+//! - [`SECTION_NUMERIC_DELTAS`]: one little-endian `f64` per entry, in
+//!   entry order: right minus left for entries flagged
+//!   `ENTRY_FLAG_NUMERIC_DELTA`, 0.0 for the rest.
+//! - [`SECTION_PATHS`]: one record per entry, in entry order:
+//!   `[u32 shared][u32 suffix_len][suffix bytes]`, unpadded. The entry's
+//!   JSON Pointer is the first `shared` bytes of the previous entry's
+//!   path followed by the suffix. With [`HEADER_FLAG_PATHS_FRONT_CODED`]
+//!   (a path entry order, no `top_k`) neighbours share long prefixes;
+//!   without it `shared` is always 0 and each suffix is the full path.
+//! - [`SECTION_ANNOTATIONS`]: with an entry transform set, one `u32` per
+//!   entry, in entry order: what the transform wrote to `out_flags_ptr`
+//!   for it (`transform.rs`).
+//! - [`SECTION_ENTRY_SEQUENCE`]: with `entry_sequence`, flagged
+//!   [`HEADER_FLAG_ENTRY_SEQUENCE`]: one `u32` per entry, in entry order,
+//!   0 for the first and one more for each next. They are assigned as
+//!   entries are written, after ordering, `top_k` and oversize skips, so
+//!   they are dense in every result; with the input digests they address
+//!   an entry across re-encodings as `(left digest, right digest, seq)`.
+//! - [`SECTION_VALUE_LENGTHS`]: when an entry is flagged
+//!   `ENTRY_FLAG_OVERSIZED_PRIMITIVE`, one `[u32 left_len][u32 right_len]`
+//!   per entry, in entry order: each side's length as written, before any
+//!   cut; 0 for an absent side.
+//! - [`SECTION_PATCH`] (from 2.14): with `self_contained`, the edits that
+//!   turn the left document into the right, each with its new value's
+//!   text (record layout in `patch.rs`). Independent of the entries: it
+//!   covers the whole inputs, whatever scopes, filters or limits kept.
+//! - [`SECTION_DOCUMENT`] (from 2.14): an `apply_patch` result, flagged
+//!   [`HEADER_FLAG_PATCHED`], holds no entries and this one section: the
+//!   rebuilt document's compact JSON text.
+//!
+//! Segments (from 2.15): `finalize_partial` writes a result too large for
+//! the arena as a run of results, each a full header, entries and
+//! sections, all but the last flagged [`HEADER_FLAG_PARTIAL`]. A segment
+//! carries the per-entry sections of its own entries; the entry sequence
+//! continues across segments, and path records restart front coding at
+//! each. The whole-diff sections (coverage, input digests, patch, audit
+//! log) are in the last segment, which may hold no entries for them.
+//!
+//! Channels (from 2.17): with `split_by_op`, sealing a result also copies
+//! each op's entries into a result of their own (`get_result_ptr_for`),
+//! flagged [`HEADER_FLAG_CHANNEL`]: the same header, its own entry count
+//! and length, then those entries in result order, and no sections. The
+//! other header flags are the result's, bar the ones describing sections.
+//! Channels are held beside the result, not inside `max_memory_bytes`,
+//! and the three together are its entries again plus three headers.
+
+use crate::diff::{DiffOp, ValueSpan};
+use crate::parser::ValueKind;
+use alloc::vec::Vec;
+
+pub const FORMAT_VERSION_MAJOR: u16 = 3;
+pub const FORMAT_VERSION_MINOR: u16 = 0;
+/// Both halves in one word, as `get_format_version` reports them:
+/// `major << 16 | minor`.
+pub const FORMAT_VERSION: u32 = (FORMAT_VERSION_MAJOR as u32) << 16 | FORMAT_VERSION_MINOR as u32;
+
+/// Fixed size of one result entry.
+pub const ENTRY_SIZE: usize = 32;
+
+/// Header flag: both inputs were token-identical and the diff was skipped.
+pub const HEADER_FLAG_IDENTICAL: u32 = 1 << 0;
+/// Header flag: a section table follows the entries.
+pub const HEADER_FLAG_SECTIONS: u32 = 1 << 1;
+/// Header flag: entries carry length-delimited extensions.
+pub const HEADER_FLAG_ENTRY_EXT: u32 = 1 << 2;
+/// Header flag: the right side was parsed only at left paths
+/// (`right_restricted_to_left`), so additions were not detected.
+pub const HEADER_FLAG_RIGHT_RESTRICTED: u32 = 1 << 3;
+/// Header flag: finalize ran with neither side committed
+/// (`allow_empty_side`); the result is trivially empty.
+pub const HEADER_FLAG_NO_INPUT: u32 = 1 << 4;
+/// Header flag: the path section is front-coded (entries sorted by path).
+pub const HEADER_FLAG_PATHS_FRONT_CODED: u32 = 1 << 5;
+/// Header flag (from 2.10): finalize was cancelled (`cancel.rs`); the
+/// entries are those the join found before it stopped, not the diff.
+pub const HEADER_FLAG_CANCELLED: u32 = 1 << 6;
+/// Header flag (from 2.11): the entry sequence section numbers the
+/// entries (`entry_sequence`).
+pub const HEADER_FLAG_ENTRY_SEQUENCE: u32 = 1 << 7;
+/// Header flag (from 2.12): a lint result (`lint`); the side opposite the
+/// linted one holds its canonical rendering.
+pub const HEADER_FLAG_LINT: u32 = 1 << 8;
+/// Header flag (from 2.14): an `apply_patch` result; the document section
+/// holds the rebuilt document.
+pub const HEADER_FLAG_PATCHED: u32 = 1 << 9;
+/// Header flag (from 2.15): one segment of a `finalize_partial` result,
+/// with more to follow from `resume_finalize`.
+pub const HEADER_FLAG_PARTIAL: u32 = 1 << 10;
+/// Header flag (from 2.16): a write hit the size limit, so entries or
+/// sections the diff has are missing; not a complete diff.
+pub const HEADER_FLAG_TRUNCATED: u32 = 1 << 11;
+/// Header flag (from 2.17): one op's channel of a `split_by_op` result,
+/// entries only.
+pub const HEADER_FLAG_CHANNEL: u32 = 1 << 12;
+
+/// Header flags a channel does not copy: it has no sections.
+const SECTION_HEADER_FLAGS: u32 = HEADER_FLAG_SECTIONS | HEADER_FLAG_PATHS_FRONT_CODED | HEADER_FLAG_ENTRY_SEQUENCE;
+
+/// Entry flag (byte [3]): value spans were shortened to fit `max_entry_bytes`.
+pub const ENTRY_FLAG_TRUNCATED: u8 = 1 << 0;
+/// Entry flag: the left payload is not valid UTF-8 (see `utf8.rs`).
+pub const ENTRY_FLAG_LEFT_INVALID_UTF8: u8 = 1 << 1;
+/// Entry flag: the right payload is not valid UTF-8.
+pub const ENTRY_FLAG_RIGHT_INVALID_UTF8: u8 = 1 << 2;
+/// Entry flag: the numeric delta section holds this entry's delta.
+pub const ENTRY_FLAG_NUMERIC_DELTA: u8 = 1 << 3;
+/// Entry flag: both sides are numbers, but too large to subtract exactly.
+pub const ENTRY_FLAG_DELTA_INEXACT: u8 = 1 << 4;
+/// Entry flag: the entry transform redacted the value spans.
+pub const ENTRY_FLAG_REDACTED: u8 = 1 << 5;
+/// Entry flag: formatting only, the sides differ as written but not as
+/// values (lint results).
+pub const ENTRY_FLAG_FORMATTING: u8 = 1 << 6;
+/// Entry flag: a side is a primitive over `max_primitive_bytes`, compared
+/// by raw bytes and cut to the cap.
+pub const ENTRY_FLAG_OVERSIZED_PRIMITIVE: u8 = 1 << 7;
+
+/// Section id: left coverage ranges (8-byte records).
+pub const SECTION_COVERAGE: u32 = 1;
+/// Section id: input digests (two 16-byte records).
+pub const SECTION_INPUT_DIGESTS: u32 = 2;
+/// Section id: right value hashes (one u64 per entry).
+pub const SECTION_VALUE_HASHES: u32 = 3;
+/// Section id: audit log records (16 bytes each).
+pub const SECTION_AUDIT_LOG: u32 = 4;
+/// Section id: per-entry canary sides in `three_way` mode (16 bytes each).
+pub const SECTION_CANARY: u32 = 5;
+/// Section id: per-entry numeric deltas (one f64 per entry).
+pub const SECTION_NUMERIC_DELTAS: u32 = 6;
+/// Section id: per-entry rendered paths (variable-length records).
+pub const SECTION_PATHS: u32 = 7;
+/// Section id: per-entry transform annotations (one u32 per entry).
+pub const SECTION_ANNOTATIONS: u32 = 8;
+/// Section id: per-entry sequence numbers (one u32 per entry).
+pub const SECTION_ENTRY_SEQUENCE: u32 = 9;
+/// Section id: per-entry uncut value lengths (two u32 per entry).
+pub const SECTION_VALUE_LENGTHS: u32 = 10;
+/// Section id: the document patch (variable-length records).
+pub const SECTION_PATCH: u32 = 11;
+/// Section id: a rebuilt document's text.
+pub const SECTION_DOCUMENT: u32 = 12;
 
-use crate::diff::DiffOp;
+/// Size of one section table row: `[u32 id][u32 offset][u32 len]`.
+pub const SECTION_ROW: usize = 12;
 
-pub const FORMAT_VERSION_MAJOR: u16 = 2;
-pub const FORMAT_VERSION_MINOR: u16 = 1;
+/// Growth step once a reservation was capped by the size limit.
+pub const ARENA_CHUNK: usize = 1 << 20;
+
+/// Bytes of each entry that carry no data (`[4..8]`).
+const ENTRY_PADDING: u32 = 4;
 
 pub struct ResultArena {
-    buffer: Vec<u8>,
+    buffer: AlignedBuf,
     max_size: usize,
     sealed: bool,
+    /// A write failed `LimitExceeded` since the last clear; `seal` flags
+    /// the result truncated.
+    limit_hit: bool,
     entry_count: u32,
+    /// High-water marks since the last clear, for `MemoryReport`.
+    peak_len: u32,
+    largest_entry: u32,
+    /// Per-op copies of the sealed result's entries, indexed by `DiffOp`;
+    /// empty unless `split_by_op` built them.
+    channels: Vec<AlignedBuf>,
 }
 
 impl ResultArena {
     pub fn new(max_size: u32) -> Self {
-        let mut buffer = Vec::with_capacity(16);
+        let mut buffer = AlignedBuf::with_capacity(16);
         buffer.extend_from_slice(&FORMAT_VERSION_MAJOR.to_le_bytes());
         buffer.extend_from_slice(&FORMAT_VERSION_MINOR.to_le_bytes());
         buffer.extend_from_slice(&0u32.to_le_bytes()); // entry count
-        buffer.extend_from_slice(&0u64.to_le_bytes()); // total len
+        buffer.extend_from_slice(&0u32.to_le_bytes()); // total len
+        buffer.extend_from_slice(&0u32.to_le_bytes()); // flags
 
         Self {
             buffer,
             max_size: max_size as usize,
             sealed: false,
+            limit_hit: false,
             entry_count: 0,
+            peak_len: 16,
+            largest_entry: 0,
+            channels: Vec::new(),
         }
     }
 
     pub fn seal(&mut self) {
+        if self.limit_hit { self.set_header_flags(HEADER_FLAG_TRUNCATED); }
         let count_bytes = self.entry_count.to_le_bytes();
         self.buffer[4..8].copy_from_slice(&count_bytes);
-        let total_len = self.buffer.len() as u64;
-        self.buffer[8..16].copy_from_slice(&total_len.to_le_bytes());
+        let total_len = self.buffer.len() as u32;
+        self.buffer[8..12].copy_from_slice(&total_len.to_le_bytes());
         self.sealed = true;
     }
 
+    pub fn header_flags(&self) -> u32 { u32::from_le_bytes(self.buffer[12..16].try_into().unwrap()) }
+
+    /// Copy the sealed result's entries into one channel per op, each
+    /// behind a header of its own. Replaces any earlier channels.
+    pub fn split_by_op(&mut self) {
+        debug_assert!(self.sealed);
+        let flags = self.header_flags() & !SECTION_HEADER_FLAGS | HEADER_FLAG_CHANNEL;
+        let mut channels: Vec<AlignedBuf> = [DiffOp::Added, DiffOp::Removed, DiffOp::Modified]
+            .iter()
+            .map(|&op| {
+                let count = self.entries().filter(|e| e[0] == op as u8).count();
+                let mut channel = AlignedBuf::with_capacity(16 + count * ENTRY_SIZE);
+                channel.extend_from_slice(&self.buffer[0..4]);
+                channel.extend_from_slice(&(count as u32).to_le_bytes());
+                channel.extend_from_slice(&((16 + count * ENTRY_SIZE) as u32).to_le_bytes());
+                channel.extend_from_slice(&flags.to_le_bytes());
+                channel
+            })
+            .collect();
+        for entry in self.entries() {
+            channels[entry[0] as usize].extend_from_slice(entry);
+        }
+        self.channels = channels;
+    }
+
+    /// `op`'s channel (`DiffOp as u8`); `None` unless `split_by_op` ran
+    /// since the last clear, or for an op that is none.
+    pub fn channel(&self, op: u8) -> Option<&[u8]> { self.channels.get(op as usize).map(|c| &c[..]) }
+
+    /// Bytes the channels hold allocated.
+    pub fn channel_bytes(&self) -> usize { self.channels.iter().map(|c| c.words.capacity() * 8).sum() }
+
+    /// Set `HEADER_FLAG_*` bits.
+    pub fn set_header_flags(&mut self, flags: u32) {
+        let old = u32::from_le_bytes(self.buffer[12..16].try_into().unwrap());
+        self.buffer[12..16].copy_from_slice(&(old | flags).to_le_bytes());
+    }
+
+    /// Give back capacity past the current contents. Moves the buffer, so
+    /// not while a sealed result is the host's to read.
+    pub fn shrink_to_fit(&mut self) {
+        let (cap, used) = (self.buffer.words.capacity(), self.buffer.words.len());
+        self.buffer.words.shrink_to_fit();
+        self.buffer.count_growth(cap, used);
+    }
+
+    pub fn capacity_bytes(&self) -> usize { self.buffer.words.capacity() * 8 }
+
     pub fn clear(&mut self) {
         self.buffer.truncate(16);
         self.buffer[4..8].copy_from_slice(&0u32.to_le_bytes());
-        self.buffer[8..16].copy_from_slice(&0u64.to_le_bytes());
+        self.buffer[8..16].copy_from_slice(&0u64.to_le_bytes()); // length and flags
         self.sealed = false;
+        self.limit_hit = false;
         self.entry_count = 0;
+        self.peak_len = 16;
+        self.largest_entry = 0;
+        self.channels.clear();
+        self.buffer.reset_growth();
+    }
+
+    /// Replace the size limit `new` was given; for an arena holding no
+    /// result (`Engine::set_config`).
+    pub fn set_max_size(&mut self, max_size: u32) {
+        self.max_size = max_size as usize;
+        self.buffer.reset_growth();
+    }
+
+    /// Exact length of a result with `entries` entries and sections of
+    /// `section_lens` body bytes (in write order), as `write_sections`
+    /// lays them out. Saturates at `usize::MAX`, which no limit admits.
+    pub fn estimate_len(entries: usize, section_lens: &[usize]) -> usize {
+        entries
+            .checked_mul(ENTRY_SIZE)
+            .and_then(|bytes| bytes.checked_add(16))
+            .and_then(|start| section_layout(start, section_lens.iter().copied()))
+            .map_or(usize::MAX, |(_, end)| end)
+    }
+
+    /// Allocate `len` bytes up front. Over the size limit, switch to
+    /// chunked growth capped at the limit instead.
+    pub fn reserve(&mut self, len: usize) {
+        if len <= self.max_size {
+            self.buffer.reserve(len);
+        } else {
+            self.buffer.chunked = Some(self.max_size);
+        }
     }
 
     pub fn write_entry_v2(
         &mut self,
         op: DiffOp,
         path_id: crate::path::PathId,
-        left_val: Option<(u32, u32)>,
-        right_val: Option<(u32, u32)>,
+        left_val: Option<ValueSpan>,
+        right_val: Option<ValueSpan>,
+        flags: u8,
     ) -> Result<(), ArenaError> {
         if self.sealed { return Err(ArenaError::Sealed); }
 
-        // Entry format v2.1: 32 bytes (fixed size, 8-aligned)
+        // Entry format v2.2: 32 bytes (fixed size, 8-aligned)
         // [0]     op (u8)
-        // [1..8]  reserved/padding
+        // [1]     left value kind (u8, see ValueKind)
+        // [2]     right value kind (u8, see ValueKind)
+        // [3]     flags (u8, ENTRY_FLAG_*)
+        // [4..8]  reserved/padding
         // [8..16] path_id (u64)
         // [16..20] left_offset (u32)
         // [20..24] left_len (u32)
         // [24..28] right_offset (u32)
         // [28..32] right_len (u32)
-        const ENTRY_SIZE: usize = 32;
-        if self.buffer.len() + ENTRY_SIZE > self.max_size {
+        if !fits_within(self.buffer.len(), ENTRY_SIZE, self.max_size) {
+            self.limit_hit = true;
             return Err(ArenaError::LimitExceeded);
         }
 
-        let (lo, ll) = left_val.unwrap_or((0, 0));
-        let (ro, rl) = right_val.unwrap_or((0, 0));
+        let (lo, ll, lk) = left_val.unwrap_or((0, 0, ValueKind::None));
+        let (ro, rl, rk) = right_val.unwrap_or((0, 0, ValueKind::None));
 
         let mut entry = [0u8; ENTRY_SIZE];
         entry[0] = op as u8;
-        // entry[1..8] padding
+        entry[1] = lk as u8;
+        entry[2] = rk as u8;
+        entry[3] = flags;
+        // entry[4..8] padding
         entry[8..16].copy_from_slice(&path_id.0.to_le_bytes());
         entry[16..20].copy_from_slice(&lo.to_le_bytes());
         entry[20..24].copy_from_slice(&ll.to_le_bytes());
@@ -82,12 +410,541 @@ impl ResultArena {
         self.buffer.extend_from_slice(&entry);
 
         self.entry_count += 1;
+        self.peak_len = self.peak_len.max(self.buffer.len() as u32);
+        let footprint = (ENTRY_SIZE as u32).saturating_add(ll).saturating_add(rl);
+        self.largest_entry = self.largest_entry.max(footprint);
+        Ok(())
+    }
+
+    /// Append the section table and bodies after the entries. All entries
+    /// must be written first; the sections are all-or-nothing against the
+    /// size limit. No sections, no table (and no header flag).
+    pub fn write_sections(&mut self, sections: &[(u32, &[u8])]) -> Result<(), ArenaError> {
+        if self.sealed { return Err(ArenaError::Sealed); }
+        if sections.is_empty() { return Ok(()); }
+        let layout = section_layout(self.buffer.len(), sections.iter().map(|(_, body)| body.len()));
+        let Some((offsets, end)) = layout.filter(|&(_, end)| end <= self.max_size) else {
+            self.limit_hit = true;
+            return Err(ArenaError::LimitExceeded);
+        };
+        self.buffer.extend_from_slice(&(sections.len() as u32).to_le_bytes());
+        self.buffer.extend_from_slice(&0u32.to_le_bytes());
+        for (&(id, body), &at) in sections.iter().zip(&offsets) {
+            for word in [id, at as u32, body.len() as u32] {
+                self.buffer.extend_from_slice(&word.to_le_bytes());
+            }
+        }
+        for (&(_, body), &at) in sections.iter().zip(&offsets) {
+            self.buffer.resize(at);
+            self.buffer.extend_from_slice(body);
+        }
+        self.buffer.resize(end);
+        self.set_header_flags(HEADER_FLAG_SECTIONS);
+        self.peak_len = self.peak_len.max(self.buffer.len() as u32);
         Ok(())
     }
 
     pub fn as_ptr(&self) -> *const u8 { self.buffer.as_ptr() }
+
+    /// Build the next result in the host's `cap` bytes at `out` instead of
+    /// owned storage (`finalize_into`), releasing that storage; for a
+    /// cleared arena. A result that outgrows them moves to owned storage,
+    /// bytes written so far along with it, and stays there. `cap` below
+    /// the header leaves the arena as it was.
+    ///
+    /// # Safety
+    /// `out` must be valid for `cap` bytes of writes until `take_host` or
+    /// `clear`.
+    pub unsafe fn write_into(&mut self, out: *mut u8, cap: usize) {
+        debug_assert!(!self.sealed && self.buffer.len() == 16);
+        if cap < 16 || self.buffer.host.is_some() { return; }
+        core::ptr::copy_nonoverlapping(self.buffer.as_ptr(), out, 16);
+        self.buffer.words = Vec::new();
+        self.buffer.host = Some((out, cap));
+    }
+
+    /// End `write_into`: the result's length if it is still in the host's
+    /// memory, the arena then back to a fresh owned header; `None` if it
+    /// moved to owned storage, or `write_into` was not in effect.
+    pub fn take_host(&mut self) -> Option<u32> {
+        self.buffer.host?;
+        let len = self.len();
+        self.buffer = ResultArena::new(0).buffer;
+        // The channels are owned; they stay the host's to read.
+        let channels = core::mem::take(&mut self.channels);
+        self.clear();
+        self.channels = channels;
+        Some(len)
+    }
+
+    /// Move a result being written into host memory to owned storage, so
+    /// it outlives the host's buffer; for a `finalize_into` that failed.
+    pub fn keep_owned(&mut self) { self.buffer.spill(); }
+
+    /// The page of at most `max_len` bytes from `offset` (`get_result_page`),
+    /// cut back so it never ends inside the header or an entry. Entries
+    /// are fixed-size, so their boundaries are `16 + k * ENTRY_SIZE`; past
+    /// them the sections split anywhere. `None` at or past the end, for an
+    /// `offset` inside the header or an entry, or when not even the next
+    /// whole header or entry fits.
+    pub fn page(&self, offset: usize, max_len: usize) -> Option<&[u8]> {
+        let entries_end = 16 + self.entry_count as usize * ENTRY_SIZE;
+        let on_boundary = |at: usize| at == 0 || at >= entries_end || (at >= 16 && (at - 16).is_multiple_of(ENTRY_SIZE));
+        if offset >= self.buffer.len() || !on_boundary(offset) { return None; }
+        let mut end = offset.saturating_add(max_len).min(self.buffer.len());
+        if end < entries_end {
+            end = if end < 16 { 0 } else { end - (end - 16) % ENTRY_SIZE };
+        }
+        (end > offset).then(|| &self.buffer[offset..end])
+    }
+    /// The raw `ENTRY_SIZE` records written so far.
+    pub fn entries(&self) -> core::slice::ChunksExact<'_, u8> {
+        self.buffer[16..16 + self.entry_count as usize * ENTRY_SIZE].chunks_exact(ENTRY_SIZE)
+    }
+    /// Entry `i`'s record: entries are `ENTRY_SIZE` apart, so no index.
+    pub fn entry(&self, i: u32) -> Option<&[u8]> {
+        if i >= self.entry_count { return None; }
+        let at = 16 + i as usize * ENTRY_SIZE;
+        Some(&self.buffer[at..at + ENTRY_SIZE])
+    }
     pub fn len(&self) -> u32 { self.buffer.len() as u32 }
     pub fn entry_count(&self) -> u32 { self.entry_count }
+    pub fn max_size(&self) -> u32 { self.max_size as u32 }
+    pub fn peak_len(&self) -> u32 { self.peak_len }
+    /// Largest entry written, counting the value bytes it references.
+    pub fn largest_entry(&self) -> u32 { self.largest_entry }
+    pub fn padding_bytes(&self) -> u32 { self.entry_count * ENTRY_PADDING }
+    /// Growth accounting since the last clear; `result_estimate_bytes`
+    /// and `trimmed_bytes` are the caller's.
+    pub fn stats(&self, result_estimate_bytes: u32, trimmed_bytes: u32) -> ArenaStats {
+        ArenaStats {
+            result_estimate_bytes,
+            trimmed_bytes,
+            capacity_bytes: u32::try_from(self.buffer.words.capacity() * 8).unwrap_or(u32::MAX),
+            reallocations: self.buffer.reallocs,
+            moved_bytes: self.buffer.moved_bytes,
+        }
+    }
+}
+
+/// Whether `len + extra` bytes stay within `max`; an overflowing sum
+/// does not.
+fn fits_within(len: usize, extra: usize, max: usize) -> bool {
+    len.checked_add(extra).is_some_and(|end| end <= max)
+}
+
+/// Body offsets and end of a section table written at `start` for bodies
+/// of `lens` bytes, or `None` if the end overflows `usize`. No sections,
+/// no table.
+fn section_layout(start: usize, lens: impl ExactSizeIterator<Item = usize>) -> Option<(Vec<usize>, usize)> {
+    if lens.len() == 0 { return Some((Vec::new(), start)); }
+    let align = |n: usize| n.checked_next_multiple_of(8);
+    let table = lens.len().checked_mul(SECTION_ROW)?;
+    let mut offset = align(start.checked_add(8)?.checked_add(table)?)?;
+    let mut offsets = Vec::with_capacity(lens.len());
+    for len in lens {
+        offsets.push(offset);
+        offset = align(offset.checked_add(len)?)?;
+    }
+    Some((offsets, offset))
+}
+
+// With the 16-byte header, keeps 8-byte fields on 8-byte offsets.
+const _: () = assert!(ENTRY_SIZE.is_multiple_of(8));
+
+/// Byte buffer over `u64` storage, so `as_ptr()` is 8-byte aligned;
+/// `Vec<u8>` only guarantees 1. Bytes past `len` are kept zero.
+struct AlignedBuf {
+    words: Vec<u64>,
+    len: usize,
+    /// Grow by `ARENA_CHUNK` up to this many bytes rather than doubling.
+    chunked: Option<usize>,
+    reallocs: u32,
+    moved_bytes: u64,
+    /// Host memory and its capacity, written in place of `words` while
+    /// the contents fit (`ResultArena::write_into`); `words` stays empty
+    /// meanwhile. Not 8-aligned, and not zeroed past `len`.
+    host: Option<(*mut u8, usize)>,
+}
+
+impl AlignedBuf {
+    fn with_capacity(bytes: usize) -> Self {
+        Self { words: Vec::with_capacity(bytes.div_ceil(8)), len: 0, chunked: None, reallocs: 0, moved_bytes: 0, host: None }
+    }
+
+    /// Move the contents out of host memory into `words`, which writes go
+    /// to from then on.
+    fn spill(&mut self) {
+        let Some((ptr, _)) = self.host.take() else { return };
+        let len = self.len;
+        self.len = 0;
+        self.resize(len);
+        // SAFETY: `write_into`'s caller keeps `ptr` valid for the `len`
+        // bytes written; `words` is ours and now holds `len` bytes.
+        unsafe { core::ptr::copy_nonoverlapping(ptr, self.words.as_mut_ptr() as *mut u8, len) };
+    }
+
+    /// Ensure capacity for `bytes` in total.
+    fn reserve(&mut self, bytes: usize) {
+        if self.host.is_some() { return; }
+        let (cap, used) = (self.words.capacity(), self.words.len());
+        self.words.reserve_exact(bytes.div_ceil(8).saturating_sub(used));
+        self.count_growth(cap, used);
+    }
+
+    fn count_growth(&mut self, old_cap: usize, used: usize) {
+        if self.words.capacity() != old_cap {
+            self.reallocs += 1;
+            self.moved_bytes += used as u64 * 8;
+        }
+    }
+
+    fn reset_growth(&mut self) {
+        self.chunked = None;
+        self.reallocs = 0;
+        self.moved_bytes = 0;
+    }
+
+    fn len(&self) -> usize { self.len }
+
+    fn as_ptr(&self) -> *const u8 {
+        match self.host {
+            Some((ptr, _)) => ptr,
+            None => self.words.as_ptr() as *const u8,
+        }
+    }
+
+    /// Grow to `len` bytes, zero-filled.
+    fn resize(&mut self, len: usize) {
+        debug_assert!(len >= self.len);
+        match self.host {
+            Some((ptr, cap)) if len <= cap => {
+                // SAFETY: within the `cap` bytes `write_into` was given.
+                unsafe { ptr.add(self.len).write_bytes(0, len - self.len) };
+                self.len = len;
+                return;
+            }
+            Some(_) => self.spill(),
+            None => {}
+        }
+        if let Some(limit) = self.chunked {
+            if len > self.words.capacity() * 8 {
+                self.reserve((self.words.capacity() * 8).saturating_add(ARENA_CHUNK).min(limit).max(len));
+            }
+        }
+        let (cap, used) = (self.words.capacity(), self.words.len());
+        self.words.resize(len.div_ceil(8), 0);
+        self.count_growth(cap, used);
+        self.len = len;
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        let at = self.len;
+        self.resize(at + bytes.len());
+        self[at..].copy_from_slice(bytes);
+    }
+
+    fn truncate(&mut self, len: usize) {
+        if len >= self.len { return; }
+        self.spill();
+        self[len..].fill(0);
+        self.words.truncate(len.div_ceil(8));
+        self.len = len;
+    }
+}
+
+impl core::ops::Deref for AlignedBuf {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        // SAFETY: `len` bytes lie within the initialized words, or within
+        // the host memory they were written to.
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.len) }
+    }
+}
+
+impl core::ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        let ptr = match self.host {
+            Some((ptr, _)) => ptr,
+            None => self.words.as_mut_ptr() as *mut u8,
+        };
+        unsafe { core::slice::from_raw_parts_mut(ptr, self.len) }
+    }
+}
+
+/// Alignment of the engine's input buffers: a cache line, and a whole
+/// number of `v128` lanes, so `StructuralIndex::build` never loads across
+/// a line from the buffer's start.
+pub const INPUT_ALIGN: usize = 64;
+
+#[derive(Clone, Copy)]
+#[repr(C, align(64))]
+struct Line([u8; INPUT_ALIGN]);
+
+/// Fixed-capacity input buffer over cache-line storage, so `as_ptr()` is
+/// `INPUT_ALIGN`-aligned. The host writes into it through the raw pointer;
+/// nothing here reads the bytes, so they stay uninitialized until it does.
+///
+/// The storage is allocated on first use (`as_mut_ptr`, `materialize`),
+/// so a buffer the host never writes, as under `diff_direct`, costs
+/// nothing. Reads then come from the host's memory instead (`read_from`).
+pub struct InputBuf {
+    lines: Vec<Line>,
+    capacity: usize,
+    /// Host memory `as_ptr` reads instead of `lines`, until `detach`.
+    external: Option<*const u8>,
+}
+
+impl InputBuf {
+    pub fn with_capacity(bytes: usize) -> Self {
+        Self { lines: Vec::new(), capacity: bytes, external: None }
+    }
+
+    /// Bytes the host may write; the storage is rounded up to whole lines.
+    pub fn capacity(&self) -> usize { self.capacity }
+
+    pub fn as_ptr(&self) -> *const u8 {
+        self.external.unwrap_or(self.lines.as_ptr() as *const u8)
+    }
+
+    /// The storage, allocated now if it was not yet; never the host's.
+    pub fn as_mut_ptr(&mut self) -> *mut u8 {
+        self.materialize();
+        self.lines.as_mut_ptr() as *mut u8
+    }
+
+    /// Allocate the storage, unless it is allocated or reads come from
+    /// the host's memory: before reading bytes the host may not have
+    /// asked for a pointer to write.
+    pub fn materialize(&mut self) {
+        if self.external.is_none() && self.lines.capacity() == 0 {
+            self.lines.reserve_exact(self.capacity.div_ceil(INPUT_ALIGN));
+        }
+    }
+
+    /// Read from `ptr` instead of the storage until `detach`.
+    ///
+    /// # Safety
+    /// `ptr` must stay valid for reads of what is committed from it, and
+    /// unchanged, until `detach`.
+    pub unsafe fn read_from(&mut self, ptr: *const u8) { self.external = Some(ptr); }
+
+    /// Back to reading the storage.
+    pub fn detach(&mut self) { self.external = None; }
+
+    /// Storage bytes allocated: 0 until first use, then the capacity
+    /// rounded up to whole lines.
+    pub fn allocated_bytes(&self) -> usize { self.lines.capacity() * INPUT_ALIGN }
+}
+
+/// Bytes before every block [`alloc_block`] returns: `[u64 size][u64 align]`
+/// of the whole allocation, read back by [`dealloc_block`], so freeing
+/// needs only the pointer and always uses the layout it was allocated with.
+const BLOCK_HEADER: usize = 16;
+
+/// `len` bytes aligned to `align` (a power of two) from the global
+/// allocator, for host buffers in linear memory. Null for `len` 0, a bad
+/// `align`, or an allocation failure. Free with [`dealloc_block`].
+///
+/// The block's layout lives in a header just before it, padded so the
+/// block itself keeps `align`: the allocation is `max(align, 16)` bytes
+/// longer than `len` and aligned to that.
+pub fn alloc_block(len: usize, align: usize) -> *mut u8 {
+    if len == 0 || !align.is_power_of_two() { return core::ptr::null_mut(); }
+    let align = align.max(BLOCK_HEADER);
+    let Some(layout) = len.checked_add(align).and_then(|size| core::alloc::Layout::from_size_align(size, align).ok()) else {
+        return core::ptr::null_mut();
+    };
+    // SAFETY: the layout's size is non-zero.
+    let base = unsafe { alloc::alloc::alloc(layout) };
+    if base.is_null() { return base; }
+    // SAFETY: `align >= BLOCK_HEADER` bytes precede the block, all ours.
+    unsafe {
+        let block = base.add(align);
+        let header = [layout.size() as u64, align as u64];
+        core::ptr::copy_nonoverlapping(header.as_ptr() as *const u8, block.sub(BLOCK_HEADER), BLOCK_HEADER);
+        block
+    }
+}
+
+/// Resize a block from [`alloc_block`] to `len` bytes, keeping its
+/// alignment and its first `min(old, len)` bytes; the block may move.
+/// Null for `len` 0 or an allocation failure, and `ptr` stays valid then.
+///
+/// # Safety
+/// `ptr` must come from `alloc_block` and not be freed yet.
+pub unsafe fn realloc_block(ptr: *mut u8, len: usize) -> *mut u8 {
+    let mut header = [0u64; 2];
+    core::ptr::copy_nonoverlapping(ptr.sub(BLOCK_HEADER), header.as_mut_ptr() as *mut u8, BLOCK_HEADER);
+    let [size, align] = header.map(|v| v as usize);
+    let Some(new_size) = len.checked_add(align).filter(|_| len > 0) else { return core::ptr::null_mut() };
+    if core::alloc::Layout::from_size_align(new_size, align).is_err() { return core::ptr::null_mut(); }
+    // SAFETY: `alloc_block` allocated this layout `align` bytes before
+    // `ptr`, and the new size is valid for `align` (checked above).
+    let base = alloc::alloc::realloc(ptr.sub(align), core::alloc::Layout::from_size_align_unchecked(size, align), new_size);
+    if base.is_null() { return base; }
+    // The header moved with the contents; only the size changed.
+    let block = base.add(align);
+    (block.sub(BLOCK_HEADER) as *mut u64).write_unaligned(new_size as u64);
+    block
+}
+
+/// Free a block from [`alloc_block`] with the layout in its header.
+/// False, freeing nothing, for a null `ptr`.
+///
+/// # Safety
+/// `ptr` must come from `alloc_block` and not be freed yet.
+pub unsafe fn dealloc_block(ptr: *mut u8) -> bool {
+    if ptr.is_null() { return false; }
+    let mut header = [0u64; 2];
+    core::ptr::copy_nonoverlapping(ptr.sub(BLOCK_HEADER), header.as_mut_ptr() as *mut u8, BLOCK_HEADER);
+    let [size, align] = header.map(|v| v as usize);
+    // SAFETY: `alloc_block` allocated exactly this layout, `align` bytes
+    // before `ptr`.
+    alloc::alloc::dealloc(ptr.sub(align), core::alloc::Layout::from_size_align_unchecked(size, align));
+    true
+}
+
+/// Capacity-planning snapshot returned by `get_memory_report`.
+///
+/// Layout (little-endian, 32 bytes):
+/// ```text
+/// [u32 arena_limit_bytes]          (0-3)
+/// [u32 arena_peak_bytes]           (4-7)
+/// [u32 arena_largest_entry_bytes]  (8-11)
+/// [u32 arena_padding_bytes]        (12-15)
+/// [u32 left_peak_token_bytes]      (16-19)
+/// [u32 right_peak_token_bytes]     (20-23)
+/// [u32 left_peak_index_bytes]      (24-27)
+/// [u32 right_peak_index_bytes]     (28-31)
+/// ```
+///
+/// In `three_way` mode the right peaks cover the canary (the third
+/// input) too.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryReport {
+    pub arena_limit_bytes: u32,
+    pub arena_peak_bytes: u32,
+    pub arena_largest_entry_bytes: u32,
+    pub arena_padding_bytes: u32,
+    pub left_peak_token_bytes: u32,
+    pub right_peak_token_bytes: u32,
+    pub left_peak_index_bytes: u32,
+    pub right_peak_index_bytes: u32,
+}
+
+impl MemoryReport {
+    pub const SIZE: usize = 32;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let fields = [
+            self.arena_limit_bytes,
+            self.arena_peak_bytes,
+            self.arena_largest_entry_bytes,
+            self.arena_padding_bytes,
+            self.left_peak_token_bytes,
+            self.right_peak_token_bytes,
+            self.left_peak_index_bytes,
+            self.right_peak_index_bytes,
+        ];
+        let mut buf = [0u8; Self::SIZE];
+        for (chunk, v) in buf.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        buf
+    }
+}
+
+/// What the engine holds right now, returned by `get_memory_usage`:
+/// allocated capacities, not what is in use.
+///
+/// Layout (little-endian, 40 bytes):
+/// ```text
+/// [u32 left_input_bytes]      (0-3)    0 until written or committed
+/// [u32 right_input_bytes]     (4-7)
+/// [u32 third_input_bytes]     (8-11)   0 without three_way
+/// [u32 left_token_bytes]      (12-15)  token capacity x CompactToken
+/// [u32 right_token_bytes]     (16-19)
+/// [u32 third_token_bytes]     (20-23)
+/// [u32 index_bytes]           (24-27)  structural index checked out
+/// [u32 interner_bytes]        (28-31)  path tables, all sides
+/// [u32 arena_len]             (32-35)
+/// [u32 arena_capacity_bytes]  (36-39)  split_by_op channels included
+/// ```
+///
+/// Between commits the structural index is back in its pool
+/// (`get_index_pool_stats`), so `index_bytes` is 0.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    pub left_input_bytes: u32,
+    pub right_input_bytes: u32,
+    pub third_input_bytes: u32,
+    pub left_token_bytes: u32,
+    pub right_token_bytes: u32,
+    pub third_token_bytes: u32,
+    pub index_bytes: u32,
+    pub interner_bytes: u32,
+    pub arena_len: u32,
+    pub arena_capacity_bytes: u32,
+}
+
+impl MemoryUsage {
+    pub const SIZE: usize = 40;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let fields = [
+            self.left_input_bytes,
+            self.right_input_bytes,
+            self.third_input_bytes,
+            self.left_token_bytes,
+            self.right_token_bytes,
+            self.third_token_bytes,
+            self.index_bytes,
+            self.interner_bytes,
+            self.arena_len,
+            self.arena_capacity_bytes,
+        ];
+        let mut buf = [0u8; Self::SIZE];
+        for (chunk, v) in buf.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        buf
+    }
+}
+
+/// Result arena sizing, returned by `get_arena_stats`.
+///
+/// Layout (little-endian, 24 bytes):
+/// ```text
+/// [u32 result_estimate_bytes]  (0-3)    exact length finalize sized for
+/// [u32 capacity_bytes]         (4-7)    allocated
+/// [u32 reallocations]          (8-11)   buffer moves since the last clear
+/// [u32 trimmed_bytes]          (12-15)  released by the last trim_engine
+/// [u64 moved_bytes]            (16-23)  bytes those moves copied, at most
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ArenaStats {
+    pub result_estimate_bytes: u32,
+    pub capacity_bytes: u32,
+    pub reallocations: u32,
+    /// Engine-wide, not just the arena: every buffer `trim_engine`
+    /// shrank or dropped.
+    pub trimmed_bytes: u32,
+    pub moved_bytes: u64,
+}
+
+impl ArenaStats {
+    pub const SIZE: usize = 24;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0..4].copy_from_slice(&self.result_estimate_bytes.to_le_bytes());
+        buf[4..8].copy_from_slice(&self.capacity_bytes.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.reallocations.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.trimmed_bytes.to_le_bytes());
+        buf[16..24].copy_from_slice(&self.moved_bytes.to_le_bytes());
+        buf
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -95,3 +952,114 @@ pub enum ArenaError {
     Sealed,
     LimitExceeded,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn limit_checks_do_not_wrap() {
+        assert!(fits_within(u32::MAX as usize - ENTRY_SIZE, ENTRY_SIZE, u32::MAX as usize));
+        assert!(!fits_within(u32::MAX as usize - ENTRY_SIZE + 1, ENTRY_SIZE, u32::MAX as usize));
+        assert!(!fits_within(usize::MAX, ENTRY_SIZE, usize::MAX), "overflowing sum fits nothing");
+    }
+
+    #[test]
+    fn layout_overflow_is_none_and_estimates_saturate() {
+        assert_eq!(section_layout(16, [8, 3].into_iter()), Some((vec![48, 56], 64)));
+        assert_eq!(section_layout(usize::MAX - 8, [0].into_iter()), None);
+        assert_eq!(section_layout(16, [usize::MAX - 40].into_iter()), None);
+
+        assert_eq!(ResultArena::estimate_len(2, &[8]), 112);
+        assert_eq!(ResultArena::estimate_len(usize::MAX / ENTRY_SIZE + 1, &[]), usize::MAX);
+        assert_eq!(ResultArena::estimate_len(1, &[usize::MAX]), usize::MAX);
+    }
+
+    #[test]
+    fn pages_end_on_entry_boundaries() {
+        let mut arena = ResultArena::new(1 << 16);
+        for i in 0..3 {
+            arena.write_entry_v2(DiffOp::Modified, crate::path::PathId(i), None, None, 0).unwrap();
+        }
+        arena.write_sections(&[(SECTION_CANARY, &[1; 8])]).unwrap();
+        arena.seal();
+        // Header 0..16, entries 16..112, table 112..132, body 136..144.
+        assert_eq!(arena.len(), 144);
+        let end = |offset, max_len| arena.page(offset, max_len).map(|p| offset + p.len());
+        assert_eq!(end(0, 15), None, "the header whole or not at all");
+        assert_eq!([end(0, 16), end(0, 47), end(0, 48), end(0, 100)], [Some(16), Some(16), Some(48), Some(80)]);
+        assert_eq!([end(16, 31), end(48, 64), end(80, 40), end(80, 31)], [None, Some(112), Some(120), None]);
+        assert_eq!([end(112, 1), end(117, 5), end(140, 100)], [Some(113), Some(122), Some(144)]);
+        assert_eq!([end(8, 64), end(20, 64), end(144, 8)], [None, None, None], "inside the header, inside an entry, at the end");
+
+        let mut paged = Vec::new();
+        while let Some(page) = arena.page(paged.len(), 40) {
+            paged.extend_from_slice(page);
+        }
+        assert_eq!(paged, &arena.buffer[..]);
+    }
+
+    #[test]
+    fn host_target_spills_when_outgrown() {
+        let write = |arena: &mut ResultArena| {
+            for i in 0..2 {
+                arena.write_entry_v2(DiffOp::Added, crate::path::PathId(i), None, None, 0).unwrap();
+            }
+            arena.write_sections(&[(SECTION_CANARY, &[7; 12])]).unwrap();
+            arena.seal();
+        };
+        let mut owned = ResultArena::new(1 << 16);
+        write(&mut owned);
+        let expected = owned.buffer.to_vec();
+
+        // Room for it all: written in place, the arena back to a header.
+        let mut out = vec![0xFFu8; expected.len()];
+        let mut arena = ResultArena::new(1 << 16);
+        unsafe { arena.write_into(out.as_mut_ptr(), out.len()) };
+        write(&mut arena);
+        assert_eq!(arena.capacity_bytes(), 0);
+        assert_eq!(arena.take_host(), Some(expected.len() as u32));
+        assert_eq!(out, expected);
+        assert_eq!((arena.len(), arena.take_host()), (16, None));
+
+        // Room for the entries only: the whole result moves over.
+        let mut out = vec![0u8; 80];
+        unsafe { arena.write_into(out.as_mut_ptr(), out.len()) };
+        write(&mut arena);
+        assert_eq!(arena.take_host(), None);
+        assert_eq!(&arena.buffer[..], &expected[..]);
+    }
+
+    #[test]
+    fn entries_are_read_in_place() {
+        let mut arena = ResultArena::new(1 << 16);
+        for i in 0..3 {
+            arena.write_entry_v2(DiffOp::Removed, crate::path::PathId(i), Some((i as u32, 1, ValueKind::Literal)), None, 0).unwrap();
+        }
+        arena.write_sections(&[(SECTION_CANARY, &[1; 8])]).unwrap();
+        arena.seal();
+        for i in 0..3 {
+            let at = 16 + i as usize * ENTRY_SIZE;
+            assert_eq!(arena.entry(i), Some(&arena.buffer[at..at + ENTRY_SIZE]));
+        }
+        assert_eq!(arena.entry(3), None, "the section table is not an entry");
+    }
+
+    #[test]
+    fn clear_restores_a_fresh_header() {
+        let fresh = ResultArena::new(1 << 16);
+        let mut arena = ResultArena::new(1 << 16);
+        for i in 0..3 {
+            arena.write_entry_v2(DiffOp::Modified, crate::path::PathId(i), Some((0, 1, ValueKind::Literal)), None, 0).unwrap();
+        }
+        arena.write_sections(&[(SECTION_CANARY, &[1; 8])]).unwrap();
+        arena.set_header_flags(HEADER_FLAG_IDENTICAL);
+        arena.seal();
+        assert_eq!(arena.entry_count(), 3);
+
+        arena.clear();
+        assert_eq!(&arena.buffer[..], &fresh.buffer[..]);
+        assert_eq!((arena.entry_count(), arena.len(), arena.sealed), (0, 16, false));
+        assert!(arena.write_entry_v2(DiffOp::Added, crate::path::PathId(9), None, None, 0).is_ok());
+    }
+}
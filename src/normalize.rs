@@ -0,0 +1,208 @@
+//! Comparison hashes (`normalize_numbers`, `normalize_strings`,
+//! `ignore_case`).
+//!
+//! A commit hashes each value as written (`CompactToken::value_hash`, the
+//! hash the token digest, suppressions and the value hash section use).
+//! What counts as equal is decided later: finalize runs a hash pass over
+//! each side's token array that computes every value's comparison hash
+//! under the active settings into an array parallel to the tokens, and
+//! the join compares those. A setting changed by `set_output_options`
+//! after commit therefore costs this pass and the join, never an index or
+//! parse. With every setting off there is no pass; the raw hashes compare.
+//!
+//! - `normalize_numbers`: a number compares by its exact decimal value
+//!   (sign, significant digits, exponent), so `1`, `1.0`, `10e-1` and
+//!   `0.1E1` are equal and `-0` equals `0`. Nothing is rounded:
+//!   `9007199254740993` and `9007199254740992` still differ.
+//! - `normalize_strings`: a string compares with its escapes decoded
+//!   (`escape::decode`), so `"\u00e9"` equals `"é"` and `"\/"` equals
+//!   `"/"`.
+//! - `ignore_case`: a string compares with ASCII letters lowercased, after
+//!   decoding when both are on. Other letters compare as written.
+//!
+//! A string or literal longer than `max_primitive_bytes` keeps its raw
+//! hash under every setting: decoding or canonicalizing megabytes for one
+//! comparison is not worth it, so it compares by its bytes as written.
+//!
+//! Paths are hashed during the parse and are not affected: keys stay
+//! exact. Entries still point at each side's value as written.
+
+use crate::config::OutputOptions;
+use crate::escape;
+use crate::parser::{hash_bytes_simd, CompactEvent, CompactToken, ValueKind};
+use alloc::vec::Vec;
+
+/// The comparison settings in effect for a finalize.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Normalization {
+    pub numbers: bool,
+    pub strings: bool,
+    pub case: bool,
+}
+
+impl Normalization {
+    pub fn of(output: &OutputOptions) -> Self {
+        Self { numbers: output.normalize_numbers, strings: output.normalize_strings, case: output.ignore_case }
+    }
+
+    /// Every value compares as written.
+    pub fn is_off(self) -> bool { self == Self::default() }
+}
+
+/// Write the comparison hash of each of `tokens` to `out`, in token
+/// order. `input` is the side the tokens were parsed from; values the
+/// settings leave alone, and those longer than `max_primitive` (0 for no
+/// cap), keep their raw hash.
+pub fn hash_pass(tokens: &[CompactToken], input: &[u8], n: Normalization, max_primitive: u32, out: &mut Vec<u64>) {
+    out.clear();
+    out.reserve(tokens.len());
+    let mut scratch = Vec::new();
+    for t in tokens {
+        if max_primitive != 0 && t.raw_len > max_primitive {
+            out.push(t.value_hash);
+            continue;
+        }
+        let span = input.get(t.raw_offset as usize..t.raw_end() as usize).unwrap_or_default();
+        let hash = match (t.event, t.kind) {
+            (CompactEvent::Value, ValueKind::String) if n.strings || n.case => string_hash(span, t.value_hash, n, &mut scratch),
+            (CompactEvent::Value, ValueKind::Literal) if n.numbers => {
+                scratch.clear();
+                if canonical_number(span, &mut scratch) { hash_bytes_simd(&scratch) } else { t.value_hash }
+            }
+            _ => t.value_hash,
+        };
+        out.push(hash);
+    }
+}
+
+/// Comparison hash of a string body whose raw hash is `raw`.
+fn string_hash(body: &[u8], raw: u64, n: Normalization, scratch: &mut Vec<u8>) -> u64 {
+    let escaped = body.contains(&b'\\');
+    let changes = (escaped && n.strings) || (n.case && body.iter().any(u8::is_ascii_uppercase));
+    // A body neither setting changes hashes as it did in the parse.
+    if !changes {
+        return raw;
+    }
+    // The parser hashed undecodable escapes as `\uFFFD`; so does this.
+    let fixed = if escaped { escape::normalize(body) } else { None };
+    let body = fixed.as_ref().map_or(body, |(fixed, _)| fixed.as_slice());
+    scratch.clear();
+    if n.strings { escape::decode(body, scratch) } else { scratch.extend_from_slice(body) }
+    if n.case { scratch.make_ascii_lowercase(); }
+    hash_bytes_simd(scratch)
+}
+
+/// Write the canonical form of the JSON number `bytes` to `out`: an
+/// optional `-`, the significant digits without leading or trailing
+/// zeros, `e` and the exponent (`0` for zero). False, writing nothing,
+/// for anything but a number (`true`, `null`, a malformed literal).
+/// Exponents beyond `i64` saturate.
+pub fn canonical_number(bytes: &[u8], out: &mut Vec<u8>) -> bool {
+    let (negative, rest) = match bytes.split_first() {
+        Some((b'-', rest)) => (true, rest),
+        _ => (false, bytes),
+    };
+    let (mantissa, exponent) = match rest.iter().position(|&b| b == b'e' || b == b'E') {
+        Some(at) => (&rest[..at], Some(&rest[at + 1..])),
+        None => (rest, None),
+    };
+    let (int, frac) = match mantissa.iter().position(|&b| b == b'.') {
+        Some(at) => (&mantissa[..at], &mantissa[at + 1..]),
+        None => (mantissa, &[][..]),
+    };
+    let digits = |d: &[u8]| !d.is_empty() && d.iter().all(u8::is_ascii_digit);
+    if !digits(int) || (mantissa.len() > int.len() && !digits(frac)) {
+        return false;
+    }
+    let mut exp: i64 = 0;
+    if let Some(e) = exponent {
+        let (sign, e) = match e.split_first() {
+            Some((b'-', e)) => (-1, e),
+            Some((b'+', e)) => (1, e),
+            _ => (1, e),
+        };
+        if !digits(e) {
+            return false;
+        }
+        exp = e.iter().fold(0i64, |v, &d| v.saturating_mul(10).saturating_add((d - b'0') as i64)) * sign;
+    }
+    let all = int.iter().chain(frac);
+    let Some(first) = all.clone().position(|&d| d != b'0') else {
+        out.extend_from_slice(b"0e0");
+        return true;
+    };
+    let significant: Vec<u8> = all.skip(first).copied().collect();
+    let trailing = significant.iter().rev().take_while(|&&d| d == b'0').count();
+    exp = exp.saturating_sub(frac.len() as i64).saturating_add(trailing as i64);
+    if negative { out.push(b'-'); }
+    out.extend_from_slice(&significant[..significant.len() - trailing]);
+    out.push(b'e');
+    out.extend_from_slice(alloc::format!("{}", exp).as_bytes());
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn canonical(bytes: &[u8]) -> Option<alloc::string::String> {
+        let mut out = Vec::new();
+        canonical_number(bytes, &mut out).then(|| alloc::string::String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn numbers_canonicalize_exactly() {
+        for n in ["1", "1.0", "10e-1", "0.1E1", "001.000", "0.01e+2"] {
+            assert_eq!(canonical(n.as_bytes()).as_deref(), Some("1e0"), "{}", n);
+        }
+        assert_eq!(canonical(b"-0"), canonical(b"0.000e5"));
+        assert_eq!(canonical(b"-1500").as_deref(), Some("-15e2"));
+        assert_eq!(canonical(b"9007199254740993").as_deref(), Some("9007199254740993e0"));
+        assert_ne!(canonical(b"9007199254740993"), canonical(b"9007199254740992"));
+        assert_eq!(canonical(b"1e99999999999999999999").as_deref(), Some("1e9223372036854775807"));
+        for other in ["true", "null", "", "-", "1.", ".5", "1e", "1e+", "0x10", "1.5.2"] {
+            assert_eq!(canonical(other.as_bytes()), None, "{}", other);
+        }
+    }
+
+    #[test]
+    fn the_pass_hashes_what_the_settings_compare() {
+        let input = r#"["Caf\u00e9",1.50,"CAFÉ","Café",true,"x\/y","x/y"]"#.as_bytes();
+        let mut parser = crate::parser::CompactParser::new(100, crate::config::ComputeMode::Latency);
+        let mut index = crate::simd_index::StructuralIndex::new();
+        index.build(input);
+        parser.parse_with_index(input, &index).unwrap();
+        let tokens = parser.tokens();
+        let hashes = |numbers, strings, case| {
+            let mut out = Vec::new();
+            hash_pass(tokens, input, Normalization { numbers, strings, case }, 0, &mut out);
+            assert_eq!(out.len(), tokens.len());
+            out
+        };
+        // Tokens: `[`, the seven values, `]`.
+        let raw: Vec<u64> = tokens.iter().map(|t| t.value_hash).collect();
+        assert_eq!(hashes(false, false, false), raw);
+
+        let numbers = hashes(true, false, false);
+        assert_eq!(numbers[2], hash_bytes_simd(b"15e-1"));
+        assert_eq!([numbers[5], numbers[0]], [raw[5], raw[0]], "true and the brackets keep their raw hash");
+
+        let strings = hashes(false, true, false);
+        assert_eq!(strings[1], raw[4], "decoded, the escape is é");
+        assert_eq!(strings[6], raw[7]);
+        assert_ne!(strings[1], strings[3], "case still counts");
+
+        let case = hashes(false, false, true);
+        assert_eq!(case[1], hash_bytes_simd(br"caf\u00e9"));
+        assert_eq!(case[3], hash_bytes_simd("cafÉ".as_bytes()), "only ASCII folds");
+        let both = hashes(false, true, true);
+        assert_eq!([both[1], both[4]], [both[4], hash_bytes_simd("café".as_bytes())]);
+        assert_ne!(both[3], both[4]);
+
+        // Over the cap, every value keeps its raw hash.
+        let mut capped = Vec::new();
+        hash_pass(tokens, input, Normalization { numbers: true, strings: true, case: true }, 3, &mut capped);
+        assert_eq!(capped[2], raw[2], "1.50 is four bytes");
+        assert_eq!(capped[5], raw[5]);
+    }
+}
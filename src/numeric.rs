@@ -0,0 +1,80 @@
+//! Numeric deltas of Modified entries (`numeric_deltas`).
+//!
+//! Metrics documents mostly change numerically, and a host wants the
+//! change itself rather than two strings to parse. With the option on,
+//! finalize writes right minus left as an `f64` for each Modified entry
+//! whose sides are both JSON numbers, parsed from the full values as
+//! written (before any truncation) with `str::parse::<f64>`, which rounds
+//! correctly and accepts every JSON number.
+//!
+//! No delta is written when either side is not a number (strings,
+//! `true`, `null`, containers), overflows `f64` (`1e400`), or the delta
+//! itself overflows. An integer literal beyond `±2^53` is a number but one
+//! `f64` cannot hold exactly: the difference of two nearby big ids would
+//! be rounding noise, so that entry gets [`Delta::Inexact`]
+//! (`ENTRY_FLAG_DELTA_INEXACT`) and no delta.
+
+/// Largest magnitude up to which `f64` holds every integer.
+const EXACT_INT_MAX: u64 = 1 << 53;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Delta {
+    /// `right - left`, finite.
+    Exact(f64),
+    /// Both sides are numbers, but an integer side is beyond `±2^53`.
+    Inexact,
+}
+
+/// Delta between two literal payloads, `None` unless both are finite
+/// numbers.
+pub fn delta(left: &[u8], right: &[u8]) -> Option<Delta> {
+    let (l, l_exact) = number(left)?;
+    let (r, r_exact) = number(right)?;
+    if !(l_exact && r_exact) {
+        return Some(Delta::Inexact);
+    }
+    let d = r - l;
+    d.is_finite().then_some(Delta::Exact(d))
+}
+
+/// The value of a JSON number, and whether an integer literal fits `f64`
+/// exactly (fractions and exponents always count as exact).
+fn number(bytes: &[u8]) -> Option<(f64, bool)> {
+    if !matches!(bytes.first(), Some(b'-' | b'0'..=b'9')) {
+        return None;
+    }
+    let text = core::str::from_utf8(bytes).ok()?;
+    let value: f64 = text.parse().ok()?;
+    if !value.is_finite() {
+        return None;
+    }
+    let digits = text.strip_prefix('-').unwrap_or(text);
+    let integer = digits.bytes().all(|b| b.is_ascii_digit());
+    let exact = !integer || digits.parse::<u64>().is_ok_and(|n| n <= EXACT_INT_MAX);
+    Some((value, exact))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_give_deltas_and_the_rest_does_not() {
+        assert_eq!(delta(b"10", b"12.5"), Some(Delta::Exact(2.5)));
+        assert_eq!(delta(b"1e3", b"-0.5E1"), Some(Delta::Exact(-1005.0)));
+        assert_eq!(delta(b"-0", b"0"), Some(Delta::Exact(0.0)));
+        for (left, right) in [(&b"1"[..], &b"true"[..]), (b"null", b"2"), (b"1", b"1e400"), (b"1", b"Infinity"), (b"", b"1")] {
+            assert_eq!(delta(left, right), None, "{:?} {:?}", left, right);
+        }
+        assert_eq!(delta(b"-1.7e308", b"1.7e308"), None, "the delta overflows");
+    }
+
+    #[test]
+    fn integers_beyond_2_pow_53_are_inexact() {
+        assert_eq!(delta(b"9007199254740992", b"-9007199254740992"), Some(Delta::Exact(-18014398509481984.0)));
+        assert_eq!(delta(b"9007199254740993", b"9007199254740994"), Some(Delta::Inexact));
+        assert_eq!(delta(b"1", b"-123456789012345678901234567890"), Some(Delta::Inexact));
+        // Written with an exponent it is an ordinary float.
+        assert_eq!(delta(b"1", b"9.007199254740993e15"), Some(Delta::Exact(9007199254740991.0)));
+    }
+}
@@ -0,0 +1,80 @@
+//! Deterministic iteration over hash maps.
+//!
+//! Results are cached by content, so the same inputs and config must give
+//! the same bytes on every run. `FxHashMap` iteration order depends on
+//! insertion history and the hasher seed, so the rule is: a map whose
+//! iteration order can reach the output is read through
+//! [`sorted_entries`], in ascending key order (path id, token index).
+//! Lookups (`get`, `contains`, `entry`) are order-free and need nothing.
+//!
+//! `clippy.toml` disallows the hash map and set iteration methods, so a
+//! new `iter()` / `keys()` / `drain()` on one fails the lint gate.
+//! [`sorted_entries`] is the allowed way through. Plain `for x in &map`
+//! escapes the lint; review for it.
+//!
+//! Test builds hash with a per-thread seed ([`with_hash_seed`]), so a
+//! test can rerun the same work under other iteration orders and compare
+//! bytes. Release builds keep the unseeded `FxBuildHasher`.
+
+use crate::FxHashMap;
+use alloc::vec::Vec;
+use core::hash::Hash;
+
+/// The entries of `map`, in ascending key order.
+#[allow(clippy::disallowed_methods)]
+pub fn sorted_entries<K: Ord + Hash, V>(map: &FxHashMap<K, V>) -> Vec<(&K, &V)> {
+    let mut pairs: Vec<(&K, &V)> = map.iter().collect();
+    pairs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    pairs
+}
+
+/// Build hasher of test builds: `FxHasher` with the calling thread's seed.
+#[cfg(test)]
+#[derive(Clone, Copy)]
+pub struct SeededFx(usize);
+
+#[cfg(test)]
+std::thread_local! {
+    static SEED: core::cell::Cell<usize> = const { core::cell::Cell::new(0) };
+}
+
+#[cfg(test)]
+impl Default for SeededFx {
+    fn default() -> Self { Self(SEED.with(|s| s.get())) }
+}
+
+#[cfg(test)]
+impl core::hash::BuildHasher for SeededFx {
+    type Hasher = rustc_hash::FxHasher;
+    fn build_hasher(&self) -> rustc_hash::FxHasher { rustc_hash::FxHasher::with_seed(self.0) }
+}
+
+/// Run `f` with maps created on this thread seeded by `seed`.
+#[cfg(test)]
+pub fn with_hash_seed<R>(seed: usize, f: impl FnOnce() -> R) -> R {
+    let old = SEED.with(|s| s.replace(seed));
+    let out = f();
+    SEED.with(|s| s.set(old));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(seed: usize) -> FxHashMap<u64, u64> {
+        with_hash_seed(seed, || (0..64u64).map(|k| (k * 0x9e37_79b9, k)).collect())
+    }
+
+    #[test]
+    #[allow(clippy::disallowed_methods)]
+    fn seeds_reorder_maps_and_sorted_entries_undo_it() {
+        let orders: Vec<Vec<u64>> = (0..8).map(|seed| filled(seed).into_keys().collect()).collect();
+        assert!(orders.iter().any(|o| *o != orders[0]), "the seed must change iteration order");
+
+        let maps: Vec<_> = (0..8).map(filled).collect();
+        let sorted: Vec<_> = maps.iter().map(sorted_entries).collect();
+        assert!(sorted.windows(2).all(|w| w[0] == w[1]));
+        assert!(sorted[0].windows(2).all(|w| w[0].0 < w[1].0));
+    }
+}
@@ -2,15 +2,60 @@
 //! 
 //! Optimized for 1GB/s+ throughput via zero-allocation rolling path hashes
 //! and SIMD structural indexing.
+//!
+//! # Value spans
+//!
+//! Every `Value` token carries a `(raw_offset, raw_len)` span into the input
+//! plus a [`ValueKind`] describing what the span covers:
+//!
+//! - [`ValueKind::String`]: the string *body* — the bytes strictly between
+//!   the opening and closing quote, escapes left undecoded. `""` yields a
+//!   zero-length span positioned just after the opening quote.
+//! - [`ValueKind::Literal`]: the full text of a number, `true`, `false` or
+//!   `null`, with no surrounding whitespace.
+//! - [`ValueKind::Fragment`]: a complete JSON fragment including its own
+//!   delimiters. Only root entries use it, for a whole container root
+//!   (see `diff::root_change`); tokens never carry it.
+//!
+//! Structural tokens carry the one-byte span of their bracket and
+//! [`ValueKind::None`]; a container's extent runs from its start token's
+//! offset through its end token's.
 
-use crate::path::{PathId, ROOT_PATH_ID, fold_segment_hash, fold_index_hash};
+use crate::escape::{self, EscapeErrorKind};
+use crate::normalize::Normalization;
+use crate::progress::{Phase, Progress, STRIDE_BYTES};
+use crate::path::{PathId, PathSegment, PathTable, ROOT_PATH_ID, fold_segment_hash, fold_index_hash};
+use crate::simd_index::quote_is_escaped;
+use crate::FxHashSet;
+#[cfg(target_arch = "wasm32")]
 use core::arch::wasm32::*;
+use alloc::vec::Vec;
+use core::fmt;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ParseError {
     UnexpectedByte(u8),
     IncompleteInput,
     ObjectKeyLimitExceeded,
+    /// Undecodable escape in a key or string value (strict mode only).
+    /// `path` is the key's own path for keys; `offset` is the absolute
+    /// input offset of the escape's backslash.
+    InvalidEscape { path: PathId, offset: u32, kind: EscapeErrorKind },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ParseError::UnexpectedByte(b) => write!(f, "unexpected byte 0x{:02x}", b),
+            ParseError::IncompleteInput => write!(f, "incomplete input"),
+            ParseError::ObjectKeyLimitExceeded => write!(f, "object key limit exceeded"),
+            ParseError::InvalidEscape { path, offset, kind } => write!(
+                f,
+                "{} at byte {} in string at path {:016x}",
+                kind, offset, path.0
+            ),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -23,119 +68,279 @@ pub enum CompactEvent {
     Value = 4, 
 }
 
+/// What a token's `(raw_offset, raw_len)` span covers. See the module docs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ValueKind {
+    /// No payload (structural tokens, absent sides).
+    None = 0,
+    /// Bare string body without quotes; escapes are not decoded.
+    String = 1,
+    /// Number, `true`, `false` or `null` as written.
+    Literal = 2,
+    /// Complete JSON text including delimiters.
+    Fragment = 3,
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct CompactToken {
     pub path_id: PathId,
     pub event: CompactEvent,
+    pub kind: ValueKind,
     pub value_hash: u64,
     pub raw_offset: u32,
     pub raw_len: u32,
 }
 
+impl CompactToken {
+    /// End of the raw span. Inputs fit `u32` (`usize` is 32 bits on
+    /// wasm32), so this never saturates for a token the parser made; if
+    /// it did, the span would run off the input rather than wrap into a
+    /// short, plausible one.
+    pub fn raw_end(&self) -> u32 { self.raw_offset.saturating_add(self.raw_len) }
+}
+
+/// Inputs shorter than this parse without the mode's up-front token
+/// reservation (see `CompactParser::token_cap`). Creating an engine then
+/// touches no token storage, and a small diff only allocates what its
+/// tokens need. The structural index keeps its SIMD scan at every size:
+/// a scalar scan measured about ten times slower even on 1 KB inputs.
+pub const SMALL_INPUT_BYTES: usize = 4096;
+
+fn token_cap(mode: crate::config::ComputeMode) -> usize {
+    match mode {
+        crate::config::ComputeMode::Throughput => 1_048_576,
+        _ => 131_072,
+    }
+}
+
+/// Kind of an open container; arrays carry the current element index.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Container {
+    Object,
+    Array(usize),
+}
+
 pub struct CompactParser {
     tokens: Vec<CompactToken>,
+    /// Tokens reserved before the first parse of a document of at least
+    /// `SMALL_INPUT_BYTES`; small documents grow `tokens` on demand, and
+    /// the capacity they reach is kept across `clear`.
+    token_cap: usize,
     current_path_id: PathId,
     path_stack: Vec<PathId>,
-    array_indices: Vec<usize>,
-    /// Per-open-container marker: `true` = array, `false` = object.
-    /// Used to disambiguate `,` semantics when an object is nested inside
-    /// an array (or vice versa). Equal in depth to `path_stack`.
-    container_is_array: Vec<bool>,
+    /// One entry per open container, pushed on `{`/`[` and popped on
+    /// `}`/`]`; equal in depth to `path_stack`. Its top alone decides
+    /// what `,`, `:` and `]` mean, so an object nested in an array (or
+    /// the reverse) never borrows the outer container's semantics.
+    containers: Vec<Container>,
     expecting_key: bool,
     max_object_keys: u32,
     key_count: u32,
+    /// Reject undecodable escapes instead of hashing them as `\uFFFD`.
+    strict_escapes: bool,
+    /// Escapes replaced by lenient normalization since the last clear.
+    escape_warnings: u32,
+    /// Filled only when `record_paths` is set (path rendering is needed).
+    paths: PathTable,
+    record_paths: bool,
+    /// Candidate ids for the scope pointer (numeric segments may be keys
+    /// or indices). Empty = no scope, every token is emitted.
+    scope_ids: Vec<PathId>,
+    /// `path_stack` depth of the open scope container, if inside it.
+    scope_depth: Option<usize>,
+    scope_found: bool,
+    /// Paths tokens may be emitted at (after scope re-basing); see
+    /// `set_restriction`. `None` = every path.
+    restrict: Option<FxHashSet<PathId>>,
+    /// Running digest of the emitted tokens, offsets excluded; see `digest`.
+    digest: u64,
+    /// Comparison hash of each token from the last `rehash`; empty when
+    /// values compare by their raw `value_hash` (see `normalize.rs`).
+    compare_hashes: Vec<u64>,
 }
 
 impl CompactParser {
     pub fn new(max_object_keys: u32, mode: crate::config::ComputeMode) -> Self {
-        let token_cap = match mode {
-            crate::config::ComputeMode::Throughput => 1_048_576,
-            _ => 131_072,
-        };
-
         Self {
-            tokens: Vec::with_capacity(token_cap),
+            tokens: Vec::new(),
+            token_cap: token_cap(mode),
             current_path_id: ROOT_PATH_ID,
             path_stack: Vec::with_capacity(128),
-            array_indices: Vec::with_capacity(128),
-            container_is_array: Vec::with_capacity(128),
+            containers: Vec::with_capacity(128),
             expecting_key: false,
             max_object_keys,
             key_count: 0,
+            strict_escapes: false,
+            escape_warnings: 0,
+            paths: PathTable::default(),
+            record_paths: false,
+            scope_ids: Vec::new(),
+            scope_depth: None,
+            scope_found: false,
+            restrict: None,
+            digest: 0,
+            compare_hashes: Vec::new(),
         }
     }
 
-    /// Silicon Path Dispatcher: Processes structural index positions only.
-    #[inline(never)]
+    /// Replace the limits `new` was given; for a parser holding no
+    /// document (`Engine::set_config`).
+    pub fn set_limits(&mut self, max_object_keys: u32, mode: crate::config::ComputeMode) {
+        self.max_object_keys = max_object_keys;
+        self.token_cap = token_cap(mode);
+    }
+
+    /// Only emit tokens under `pointer` (RFC 6901), with paths re-based so
+    /// the scope target becomes the root. Empty pointer = whole document.
+    /// Returns false for a malformed pointer.
+    pub fn set_scope(&mut self, pointer: &[u8]) -> bool {
+        match crate::path::pointer_candidates(pointer) {
+            Some(ids) => {
+                self.scope_ids = if pointer.is_empty() { Vec::new() } else { ids };
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the last parse found the scope target (true when unscoped).
+    pub fn scope_found(&self) -> bool { self.scope_ids.is_empty() || self.scope_found }
+
+    /// Emit tokens only at `paths`: anything else, container or value, is
+    /// skipped before its value is hashed. Kept across `clear`.
+    pub fn set_restriction(&mut self, paths: Option<FxHashSet<PathId>>) { self.restrict = paths; }
+
+    /// Distinct path ids of the emitted tokens.
+    pub fn path_ids(&self) -> FxHashSet<PathId> {
+        self.tokens.iter().map(|t| t.path_id).collect()
+    }
+
+    /// Parent id to push for a container opening at `current_path_id`:
+    /// the root when this container is the scope target.
+    #[inline(always)]
+    fn enter_container(&mut self) -> PathId {
+        if !self.scope_ids.is_empty()
+            && self.scope_depth.is_none()
+            && self.scope_ids.contains(&self.current_path_id)
+        {
+            self.scope_depth = Some(self.path_stack.len() + 1);
+            self.scope_found = true;
+            return ROOT_PATH_ID;
+        }
+        self.current_path_id
+    }
+
+    /// Call before popping `path_stack` on `}` / `]`.
+    #[inline(always)]
+    fn leave_container(&mut self) {
+        if self.scope_depth == Some(self.path_stack.len()) {
+            self.scope_depth = None;
+        }
+    }
+
+    /// Whether the innermost open container is an array.
+    #[inline(always)]
+    fn in_array(&self) -> bool {
+        matches!(self.containers.last(), Some(Container::Array(_)))
+    }
+
+    pub fn set_record_paths(&mut self, record: bool) { self.record_paths = record; }
+    pub fn records_paths(&self) -> bool { self.record_paths }
+    pub fn paths(&self) -> &PathTable { &self.paths }
+
+    pub fn set_strict_escapes(&mut self, strict: bool) { self.strict_escapes = strict; }
+    pub fn escape_warnings(&self) -> u32 { self.escape_warnings }
+
+    /// Silicon Path Dispatcher: walks the structural bitmask words directly.
+    #[inline]
     pub fn parse_with_index(
         &mut self,
         json: &[u8],
         index: &crate::simd_index::StructuralIndex,
+    ) -> Result<(), ParseError> {
+        self.parse_reporting(json, index, None)
+    }
+
+    /// `parse_with_index`, reporting `Phase::Parse` progress by input
+    /// position every `STRIDE_BYTES`. Does not mark the phase finished.
+    #[inline(never)]
+    pub fn parse_reporting(
+        &mut self,
+        json: &[u8],
+        index: &crate::simd_index::StructuralIndex,
+        progress: Option<&Progress>,
     ) -> Result<(), ParseError> {
         if json.is_empty() { return Ok(()); }
+        if json.len() >= SMALL_INPUT_BYTES {
+            self.tokens.reserve(self.token_cap);
+        }
 
         // Bare-primitive document root (e.g. `42`, `true`, `null`): the SIMD
-        // structural index records no positions because the document contains
+        // structural index has no bits set because the document contains
         // no `{ } [ ] : , "` bytes. Without this branch, one side of a
         // root-scalar diff would have zero Value tokens and the diff would
         // wrongly report the other side as Removed.
-        if index.positions.is_empty() {
+        if index.masks.iter().all(|&m| m == 0) {
             let start = skip_whitespace(json, 0, json.len());
             if start < json.len() {
                 let end = find_primitive_end(json, start, json.len());
                 if end > start {
                     let val = unsafe { json.get_unchecked(start..end) };
-                    self.push_token(
-                        ROOT_PATH_ID,
-                        CompactEvent::Value,
-                        hash_bytes_simd(val),
-                        start as u32,
-                        (end - start) as u32,
-                    );
+                    self.push_value(ROOT_PATH_ID, ValueKind::Literal, val, start as u32, (end - start) as u32);
                 }
             }
             return Ok(());
         }
 
-        let mut i = 0;
-        let positions = &index.positions;
-        let len = positions.len();
-        let json_len = json.len();
+        let mut cursor = index.cursor();
+        // Last structural position consumed before the current one; the
+        // `,` and `]` handlers scan back from it for a primitive element.
+        let mut prev: Option<usize> = None;
         
         // Track if we just saw a colon (expecting a value)
         let mut after_colon = false;
+
+        // Next position to report at; never reached without `progress`.
+        let mut next_report = if progress.is_some() { 0 } else { usize::MAX };
         
-        while i < len {
-            let pos = positions[i] as usize;
+        while let Some(pos) = cursor.next() {
+            if pos >= next_report {
+                if let Some(p) = progress { p.report(Phase::Parse, pos, json.len()); }
+                next_report = pos + STRIDE_BYTES;
+            }
+            let mut last = pos;
             let b = unsafe { *json.get_unchecked(pos) };
             
             match b {
                 b'{' => {
                     after_colon = false;
-                    self.path_stack.push(self.current_path_id);
-                    self.container_is_array.push(false);
-                    self.push_token(self.current_path_id, CompactEvent::StartObject, 0, 0, 0);
+                    let parent = self.enter_container();
+                    self.path_stack.push(parent);
+                    self.containers.push(Container::Object);
+                    self.push_token(parent, CompactEvent::StartObject, ValueKind::None, 0, pos as u32, 1);
                     self.expecting_key = true;
                     self.key_count = 0;
-                    i += 1;
                 }
                 b'}' => {
                     after_colon = false;
+                    self.leave_container();
                     self.current_path_id = self.path_stack.pop().unwrap_or(ROOT_PATH_ID);
-                    self.container_is_array.pop();
+                    self.containers.pop();
                     // Restore expecting_key based on the new innermost container.
                     self.expecting_key = false;
-                    self.push_token(self.current_path_id, CompactEvent::EndObject, 0, 0, 0);
-                    i += 1;
+                    self.push_token(self.current_path_id, CompactEvent::EndObject, ValueKind::None, 0, pos as u32, 1);
                 }
                 b'[' => {
                     after_colon = false;
-                    self.path_stack.push(self.current_path_id);
-                    self.container_is_array.push(true);
-                    self.push_token(self.current_path_id, CompactEvent::StartArray, 0, 0, 0);
-                    self.array_indices.push(0);
-                    self.current_path_id = fold_index_hash(self.current_path_id, 0);
-                    i += 1;
+                    let parent = self.enter_container();
+                    self.path_stack.push(parent);
+                    self.containers.push(Container::Array(0));
+                    self.push_token(parent, CompactEvent::StartArray, ValueKind::None, 0, pos as u32, 1);
+                    self.current_path_id = fold_index_hash(parent, 0);
+                    if self.record_paths {
+                        self.paths.record(self.current_path_id, parent, || PathSegment::Index(0));
+                    }
                     // First element (primitive or otherwise) is emitted by the
                     // subsequent `,` or `]` handlers via their look-back scan.
                     // The previous look-forward emit here caused the first
@@ -144,8 +349,8 @@ impl CompactParser {
                 }
                 b']' => {
                     // Check for last primitive value before closing bracket
-                    if i > 0 && !self.array_indices.is_empty() {
-                        let prev_pos = positions[i - 1] as usize + 1;
+                    if let Some(p) = prev.filter(|_| self.in_array()) {
+                        let prev_pos = p + 1;
                         let value_start = skip_whitespace(json, prev_pos, pos);
                         if value_start < pos {
                             let first_char = unsafe { *json.get_unchecked(value_start) };
@@ -153,12 +358,12 @@ impl CompactParser {
                                 let value_end = find_primitive_end(json, value_start, pos);
                                 if value_end > value_start {
                                     let val_bytes = unsafe { json.get_unchecked(value_start..value_end) };
-                                    self.push_token(
+                                    self.push_value(
                                         self.current_path_id,
-                                        CompactEvent::Value,
-                                        hash_bytes_simd(val_bytes),
+                                        ValueKind::Literal,
+                                        val_bytes,
                                         value_start as u32,
-                                        (value_end - value_start) as u32
+                                        (value_end - value_start) as u32,
                                     );
                                 }
                             }
@@ -166,16 +371,14 @@ impl CompactParser {
                     }
                     
                     after_colon = false;
-                    self.array_indices.pop();
+                    self.leave_container();
                     self.current_path_id = self.path_stack.pop().unwrap_or(ROOT_PATH_ID);
-                    self.container_is_array.pop();
-                    self.push_token(self.current_path_id, CompactEvent::EndArray, 0, 0, 0);
-                    i += 1;
+                    self.containers.pop();
+                    self.push_token(self.current_path_id, CompactEvent::EndArray, ValueKind::None, 0, pos as u32, 1);
                 }
                 b'"' => {
                     after_colon = false;
                     let start = pos + 1;
-                    i += 1;
 
                     // Scan to the *unescaped* closing quote. The SIMD
                     // structural index records every `"` byte, including
@@ -184,19 +387,12 @@ impl CompactParser {
                     // key or value containing an escaped quote — which
                     // desyncs every path hash for the rest of the document.
                     // A quote is a real terminator only when preceded by an
-                    // even-length run of backslashes.
-                    while i < len {
-                        let next_pos = positions[i] as usize;
+                    // even-length run of backslashes (`quote_is_escaped`).
+                    for next_pos in cursor.by_ref() {
+                        last = next_pos;
                         if unsafe { *json.get_unchecked(next_pos) } == b'"' {
-                            let mut bs = next_pos;
-                            while bs > start
-                                && unsafe { *json.get_unchecked(bs - 1) } == b'\\'
-                            {
-                                bs -= 1;
-                            }
-                            if (next_pos - bs) & 1 == 1 {
+                            if quote_is_escaped(json, next_pos, start) {
                                 // Escaped quote — part of the string body.
-                                i += 1;
                                 continue;
                             }
 
@@ -208,31 +404,40 @@ impl CompactParser {
                                     return Err(ParseError::ObjectKeyLimitExceeded);
                                 }
                                 let parent = *self.path_stack.last().unwrap_or(&ROOT_PATH_ID);
-                                self.current_path_id = fold_segment_hash(parent, s_bytes);
+                                let key_path = fold_segment_hash(parent, s_bytes);
+                                let fixed = self.checked_body(s_bytes, start, key_path)?;
+                                let key = fixed.as_deref().unwrap_or(s_bytes);
+                                self.current_path_id = match fixed {
+                                    Some(_) => fold_segment_hash(parent, key),
+                                    None => key_path,
+                                };
+                                if self.record_paths {
+                                    self.paths.record(self.current_path_id, parent, || PathSegment::Key(key.into()));
+                                }
                             } else {
-                                self.push_token(
+                                let fixed = self.checked_body(s_bytes, start, self.current_path_id)?;
+                                self.push_value(
                                     self.current_path_id,
-                                    CompactEvent::Value,
-                                    hash_bytes_simd(s_bytes),
+                                    ValueKind::String,
+                                    fixed.as_deref().unwrap_or(s_bytes),
                                     start as u32,
-                                    (next_pos - start) as u32
+                                    (next_pos - start) as u32,
                                 );
                             }
-                            i += 1;
                             break;
                         }
-                        i += 1;
                     }
                 }
+                // A `:` directly inside an array is malformed; ignore it
+                // rather than turning the next element into a member value.
+                b':' if self.in_array() => {}
                 b':' => {
                     self.expecting_key = false;
                     after_colon = true;
-                    i += 1;
                     
                     // Check if next structural char indicates a primitive value
                     // Look ahead to see what follows the colon
-                    if i < len {
-                        let next_struct_pos = positions[i] as usize;
+                    if let Some(next_struct_pos) = cursor.peek() {
                         // Scan from pos+1 to next_struct_pos for primitive value
                         let value_start = skip_whitespace(json, pos + 1, next_struct_pos);
                         if value_start < next_struct_pos {
@@ -243,12 +448,12 @@ impl CompactParser {
                                 let value_end = find_primitive_end(json, value_start, next_struct_pos);
                                 if value_end > value_start {
                                     let val_bytes = unsafe { json.get_unchecked(value_start..value_end) };
-                                    self.push_token(
+                                    self.push_value(
                                         self.current_path_id,
-                                        CompactEvent::Value,
-                                        hash_bytes_simd(val_bytes),
+                                        ValueKind::Literal,
+                                        val_bytes,
                                         value_start as u32,
-                                        (value_end - value_start) as u32
+                                        (value_end - value_start) as u32,
                                     );
                                 }
                             }
@@ -260,12 +465,10 @@ impl CompactParser {
                     // Without this check, commas between object keys *inside* an
                     // outer array got misread as array-element separators —
                     // producing bogus pathIds and hashing keys as values.
-                    let in_array = matches!(self.container_is_array.last(), Some(true));
-                    
-                    if in_array {
+                    if self.in_array() {
                         // Check if there's a primitive value before this comma (array element)
-                        if i > 0 && !after_colon {
-                            let prev_pos = positions[i - 1] as usize + 1;
+                        if let Some(p) = prev.filter(|_| !after_colon) {
+                            let prev_pos = p + 1;
                             let value_start = skip_whitespace(json, prev_pos, pos);
                             if value_start < pos {
                                 let first_char = unsafe { *json.get_unchecked(value_start) };
@@ -273,12 +476,12 @@ impl CompactParser {
                                     let value_end = find_primitive_end(json, value_start, pos);
                                     if value_end > value_start {
                                         let val_bytes = unsafe { json.get_unchecked(value_start..value_end) };
-                                        self.push_token(
+                                        self.push_value(
                                             self.current_path_id,
-                                            CompactEvent::Value,
-                                            hash_bytes_simd(val_bytes),
+                                            ValueKind::Literal,
+                                            val_bytes,
                                             value_start as u32,
-                                            (value_end - value_start) as u32
+                                            (value_end - value_start) as u32,
                                         );
                                     }
                                 }
@@ -286,20 +489,22 @@ impl CompactParser {
                         }
                         
                         // Increment array index
-                        if let Some(idx) = self.array_indices.last_mut() {
-                            *idx += 1;
-                        }
+                        let Some(Container::Array(idx)) = self.containers.last_mut() else { unreachable!() };
+                        *idx += 1;
+                        let new_idx = *idx;
                         let parent = *self.path_stack.last().unwrap_or(&ROOT_PATH_ID);
-                        let new_idx = *self.array_indices.last().unwrap_or(&0);
                         self.current_path_id = fold_index_hash(parent, new_idx);
+                        if self.record_paths {
+                            self.paths.record(self.current_path_id, parent, || PathSegment::Index(new_idx as u32));
+                        }
                     } else {
                         self.expecting_key = true;
                     }
                     after_colon = false;
-                    i += 1;
                 }
-                _ => { i += 1; }
+                _ => {}
             }
+            prev = Some(last);
         }
 
         Ok(())
@@ -309,18 +514,172 @@ impl CompactParser {
         self.tokens.clear();
         self.current_path_id = ROOT_PATH_ID;
         self.path_stack.clear();
-        self.array_indices.clear();
-        self.container_is_array.clear();
+        self.containers.clear();
         self.expecting_key = false;
         self.key_count = 0;
+        self.escape_warnings = 0;
+        self.paths.clear();
+        self.scope_depth = None;
+        self.scope_found = false;
+        self.digest = 0;
+        self.compare_hashes.clear();
+    }
+
+    /// Check a string body's escapes. Strict mode fails on the first
+    /// undecodable one; lenient mode returns the `\uFFFD`-normalized body
+    /// to hash in its place. Bodies without a backslash skip the scan.
+    #[inline(always)]
+    fn checked_body(&mut self, body: &[u8], start: usize, path: PathId) -> Result<Option<Vec<u8>>, ParseError> {
+        if !body.contains(&b'\\') { return Ok(None); }
+        if self.strict_escapes {
+            return match escape::next_invalid(body, 0) {
+                Some(e) => Err(ParseError::InvalidEscape { path, offset: (start + e.offset) as u32, kind: e.kind }),
+                None => Ok(None),
+            };
+        }
+        Ok(escape::normalize(body).map(|(fixed, n)| {
+            self.escape_warnings += n;
+            fixed
+        }))
     }
 
     #[inline(always)]
-    fn push_token(&mut self, path_id: PathId, event: CompactEvent, hash: u64, offset: u32, len: u32) {
-        self.tokens.push(CompactToken { path_id, event, value_hash: hash, raw_offset: offset, raw_len: len });
+    fn push_token(&mut self, path_id: PathId, event: CompactEvent, kind: ValueKind, hash: u64, offset: u32, len: u32) {
+        let Some(path_id) = self.emitted_path(path_id, event) else { return };
+        self.emit(path_id, event, kind, hash, offset, len);
+    }
+
+    /// `push_token` for a value: `bytes` are hashed only if it is emitted.
+    #[inline(always)]
+    fn push_value(&mut self, path_id: PathId, kind: ValueKind, bytes: &[u8], offset: u32, len: u32) {
+        let Some(path_id) = self.emitted_path(path_id, CompactEvent::Value) else { return };
+        self.emit(path_id, CompactEvent::Value, kind, hash_bytes_simd(bytes), offset, len);
+    }
+
+    /// The path a token is emitted at after scope re-basing, or `None` if
+    /// the scope or the restriction drops it.
+    #[inline(always)]
+    fn emitted_path(&mut self, mut path_id: PathId, event: CompactEvent) -> Option<PathId> {
+        if !self.scope_ids.is_empty() && self.scope_depth.is_none() {
+            // Outside the scope container only a scalar scope target itself
+            // survives, as the new root value.
+            if event != CompactEvent::Value || !self.scope_ids.contains(&path_id) { return None; }
+            self.scope_found = true;
+            path_id = ROOT_PATH_ID;
+        }
+        match &self.restrict {
+            Some(paths) if !paths.contains(&path_id) => None,
+            _ => Some(path_id),
+        }
+    }
+
+    #[inline(always)]
+    fn emit(&mut self, path_id: PathId, event: CompactEvent, kind: ValueKind, hash: u64, offset: u32, len: u32) {
+        self.digest = digest_step(self.digest, path_id, event, kind, hash);
+        self.tokens.push(CompactToken { path_id, event, kind, value_hash: hash, raw_offset: offset, raw_len: len });
     }
 
     pub fn tokens(&self) -> &[CompactToken] { &self.tokens }
+
+    /// Take `tokens`, and the `paths` they were parsed with if recorded,
+    /// as the parsed document (`left_state.rs`): the scope, if any, was
+    /// found. False, changing nothing, unless they digest to `digest`.
+    pub fn restore(&mut self, tokens: Vec<CompactToken>, paths: Option<PathTable>, escape_warnings: u32, digest: u64) -> bool {
+        if tokens.iter().fold(0, |d, t| digest_step(d, t.path_id, t.event, t.kind, t.value_hash)) != digest {
+            return false;
+        }
+        self.clear();
+        self.tokens = tokens;
+        self.record_paths = paths.is_some();
+        self.paths = paths.unwrap_or_default();
+        self.escape_warnings = escape_warnings;
+        self.scope_found = true;
+        self.digest = digest;
+        true
+    }
+
+    /// Recompute the comparison hashes for `n` from `input`, the bytes
+    /// the tokens were parsed from. False, keeping none, when `n` is off.
+    pub fn rehash(&mut self, input: &[u8], n: Normalization, max_primitive: u32) -> bool {
+        if n.is_off() {
+            self.compare_hashes.clear();
+            return false;
+        }
+        crate::normalize::hash_pass(&self.tokens, input, n, max_primitive, &mut self.compare_hashes);
+        true
+    }
+
+    /// The hash token `i` compares by: its comparison hash after a
+    /// `rehash`, its raw `value_hash` otherwise.
+    #[inline(always)]
+    pub fn compare_hash(&self, i: usize) -> u64 {
+        match self.compare_hashes.get(i) {
+            Some(&hash) => hash,
+            None => self.tokens[i].value_hash,
+        }
+    }
+
+    /// Give back the capacity the current parse does not use; the next
+    /// parse of a large document reserves `token_cap` again.
+    pub fn shrink_to_fit(&mut self) {
+        self.tokens.shrink_to_fit();
+        self.compare_hashes.shrink_to_fit();
+        self.path_stack.shrink_to_fit();
+        self.containers.shrink_to_fit();
+        self.scope_ids.shrink_to_fit();
+        self.paths.shrink_to_fit();
+        if let Some(restrict) = self.restrict.as_mut() { restrict.shrink_to_fit(); }
+    }
+
+    /// Bytes allocated by the parser's collections (not their contents'
+    /// own heap, e.g. path key bodies).
+    pub fn capacity_bytes(&self) -> usize {
+        use core::mem::size_of;
+        self.tokens.capacity() * size_of::<CompactToken>()
+            + self.compare_hashes.capacity() * size_of::<u64>()
+            + (self.path_stack.capacity() + self.scope_ids.capacity()) * size_of::<PathId>()
+            + self.containers.capacity() * size_of::<Container>()
+            + self.paths.capacity_bytes()
+            + self.restrict.as_ref().map_or(0, |r| r.capacity() * size_of::<PathId>())
+    }
+
+    /// Bytes of token slots allocated; `token_cap` of them once a large
+    /// document has been parsed.
+    pub fn token_capacity_bytes(&self) -> usize { self.tokens.capacity() * core::mem::size_of::<CompactToken>() }
+
+    /// Bytes of the path table's link slots.
+    pub fn path_table_bytes(&self) -> usize { self.paths.capacity_bytes() }
+
+    /// Order-sensitive digest of `(path_id, event, kind, value_hash)` over
+    /// every token. Byte offsets are left out, so documents differing only
+    /// in whitespace digest equally.
+    pub fn digest(&self) -> u64 { self.digest }
+
+    /// Order-independent hash of the same token fields, for
+    /// `hash_document`: the wrapping sum of each value and container
+    /// start's `digest_step`, `fmix`ed, then mixed with the token count. Members are
+    /// keyed by path, so reordering an object's keys keeps the hash;
+    /// array elements are keyed by index, so reordering an array does
+    /// not. Never 0, which `hash_document` returns for "no hash".
+    pub fn content_hash(&self) -> u64 {
+        let sum = self
+            .tokens
+            .iter()
+            .filter(|t| !matches!(t.event, CompactEvent::EndObject | CompactEvent::EndArray))
+            .map(|t| crate::digest::fmix(digest_step(0, t.path_id, t.event, t.kind, t.value_hash)))
+            .fold(0u64, u64::wrapping_add);
+        crate::digest::fmix(sum ^ self.tokens.len() as u64).max(1)
+    }
+}
+
+/// Fold one token into a running digest (FxHash-style multiply-rotate).
+#[inline(always)]
+fn digest_step(digest: u64, path_id: PathId, event: CompactEvent, kind: ValueKind, hash: u64) -> u64 {
+    const K: u64 = 0x517c_c1b7_2722_0a95;
+    let tag = (event as u64) << 8 | kind as u64;
+    [path_id.0, tag, hash]
+        .into_iter()
+        .fold(digest, |d, word| (d.rotate_left(5) ^ word).wrapping_mul(K))
 }
 
 /// Skip whitespace characters (standalone function)
@@ -352,47 +711,415 @@ fn find_primitive_end(json: &[u8], start: usize, max_end: usize) -> usize {
     pos
 }
 
-/// SIMD-accelerated value hash for world-class throughput.
+/// Multiplier shared by the value and path hashes (the 64-bit FNV prime).
+pub const HASH_PRIME: u64 = 0x100000001b3;
+/// Seed of short value hashes (the 64-bit FNV offset basis).
+const HASH_OFFSET: u64 = 0xcbf29ce484222325;
+
+/// Value hash of `bytes`. Its exact output is part of the result format
+/// (the value hash section, suppressions), so it is pinned by golden
+/// tests and defined independently of the instruction set. All arithmetic
+/// wraps; `P` is [`HASH_PRIME`].
+///
+/// - Shorter than 16 bytes: `h = O ^ len` (`O` the FNV offset basis),
+///   then per byte `h = (h ^ b) * P`: FNV-1a with the length mixed in.
+/// - Otherwise the whole 16-byte blocks are read as two little-endian
+///   `u64` lanes `(a, b)`. The first block seeds them; every further
+///   block does `a = a * P ^ w0`, `b = b * P ^ w1`. Then
+///   `h = a ^ rotl(b, 32) ^ len`, and the 0..15 tail bytes fold in as
+///   above. The multiply-then-xor keeps the result order-dependent: a
+///   plain xor fold would let permuted blocks collide.
+///
+/// The `simd128` build runs the lanes in one `v128`; without it,
+/// [`hash_bytes_scalar`] runs them in two `u64`s. Both must stay
+/// bit-identical (wasm is little-endian, so lane 0 is bytes 0..8).
+///
+/// Changing any output of this function or of the `fold_*` path hashes
+/// changes values already written into results: bump
+/// `FORMAT_VERSION_MAJOR` in `memory.rs`, update the golden tests, and
+/// note here what old readers must not compare across the bump.
 ///
-/// Each 16-byte block is folded with a multiply-then-xor step so the result
-/// is *order-dependent*. A plain XOR fold (the previous implementation) is
-/// commutative: any permutation of a value's 16-byte blocks collided to the
-/// same hash, which could make the diff engine miss a real change.
+/// The one exception so far, 2.8, moved only the ids of paths through an
+/// empty key (`fold_segment_hash`), which until then equalled their
+/// parent's: no id that told two paths apart changed, so the bump is
+/// minor. Do not compare such ids with a result older than 2.8.
+///
+/// 3.0 folds each key's length ahead of its bytes (`/a/b` and `/ab` had
+/// one id): every key path id moved, so no path id compares across it.
 #[inline(always)]
 pub fn hash_bytes_simd(bytes: &[u8]) -> u64 {
-    if bytes.len() >= 16 {
-        let len = bytes.len();
-        let mut ptr = bytes.as_ptr();
-        let end = unsafe { ptr.add(len & !15) };
+    #[cfg(target_feature = "simd128")]
+    { hash_bytes_v128(bytes) }
+    #[cfg(not(target_feature = "simd128"))]
+    { hash_bytes_scalar(bytes) }
+}
+
+#[cfg(target_feature = "simd128")]
+#[inline(always)]
+fn hash_bytes_v128(bytes: &[u8]) -> u64 {
+    let len = bytes.len();
+    if len < 16 {
+        return fold_tail(HASH_OFFSET ^ len as u64, bytes);
+    }
+    let mut ptr = bytes.as_ptr();
+    let end = unsafe { ptr.add(len & !15) };
+
+    let prime = u64x2_splat(HASH_PRIME);
+    let mut acc = unsafe { v128_load(ptr as *const v128) };
+    ptr = unsafe { ptr.add(16) };
 
-        let prime = u64x2_splat(0x100000001b3);
-        let mut acc = unsafe { v128_load(ptr as *const v128) };
+    while ptr < end {
+        let chunk = unsafe { v128_load(ptr as *const v128) };
+        acc = i64x2_mul(acc, prime);
+        acc = v128_xor(acc, chunk);
         ptr = unsafe { ptr.add(16) };
+    }
+
+    let lane0 = u64x2_extract_lane::<0>(acc);
+    let lane1 = u64x2_extract_lane::<1>(acc);
+    fold_tail(lane0 ^ lane1.rotate_left(32) ^ len as u64, &bytes[len & !15..])
+}
+
+/// Portable [`hash_bytes_simd`]: the same lanes as two `u64`s.
+#[cfg(any(test, not(target_feature = "simd128")))]
+pub fn hash_bytes_scalar(bytes: &[u8]) -> u64 {
+    let len = bytes.len();
+    if len < 16 {
+        return fold_tail(HASH_OFFSET ^ len as u64, bytes);
+    }
+    let word = |at: usize| u64::from_le_bytes(bytes[at..at + 8].try_into().unwrap());
+    let (mut a, mut b) = (word(0), word(8));
+    for at in (16..len & !15).step_by(16) {
+        a = a.wrapping_mul(HASH_PRIME) ^ word(at);
+        b = b.wrapping_mul(HASH_PRIME) ^ word(at + 8);
+    }
+    fold_tail(a ^ b.rotate_left(32) ^ len as u64, &bytes[len & !15..])
+}
+
+#[inline(always)]
+fn fold_tail(mut hash: u64, tail: &[u8]) -> u64 {
+    for &b in tail {
+        hash ^= b as u64;
+        hash = hash.wrapping_mul(HASH_PRIME);
+    }
+    hash
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ComputeMode;
+    use crate::simd_index::StructuralIndex;
+
+    fn values(json: &[u8]) -> Vec<(ValueKind, Vec<u8>)> {
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+        let mut p = CompactParser::new(1000, ComputeMode::Latency);
+        p.parse_with_index(json, &idx).unwrap();
+        p.tokens()
+            .iter()
+            .filter(|t| t.event == CompactEvent::Value)
+            .map(|t| {
+                let span = &json[t.raw_offset as usize..t.raw_end() as usize];
+                (t.kind, span.to_vec())
+            })
+            .collect()
+    }
+
+    #[test]
+    fn string_spans_exclude_quotes() {
+        let v = values(br#"{"a":"x\"y","b":""}"#);
+        assert_eq!(v, vec![
+            (ValueKind::String, br#"x\"y"#.to_vec()),
+            (ValueKind::String, b"".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn empty_string_span_sits_after_opening_quote() {
+        let json = br#"{"k": ""}"#;
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+        let mut p = CompactParser::new(1000, ComputeMode::Latency);
+        p.parse_with_index(json, &idx).unwrap();
+        let t = p.tokens().iter().find(|t| t.event == CompactEvent::Value).unwrap();
+        assert_eq!((t.raw_offset, t.raw_len), (7, 0));
+    }
+
+    #[test]
+    fn literal_spans_are_exact() {
+        let v = values(b"{\"n\": -1.5e3 , \"t\":true,\"a\":[null, false ,0]}");
+        assert_eq!(v, vec![
+            (ValueKind::Literal, b"-1.5e3".to_vec()),
+            (ValueKind::Literal, b"true".to_vec()),
+            (ValueKind::Literal, b"null".to_vec()),
+            (ValueKind::Literal, b"false".to_vec()),
+            (ValueKind::Literal, b"0".to_vec()),
+        ]);
+    }
+
+    #[test]
+    fn root_scalars_have_kinds() {
+        assert_eq!(values(b"  42 "), vec![(ValueKind::Literal, b"42".to_vec())]);
+        assert_eq!(values(br#""s""#), vec![(ValueKind::String, b"s".to_vec())]);
+    }
+
+    #[test]
+    fn structural_tokens_span_their_bracket() {
+        let json = br#"{"a":[1]}"#;
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+        let mut p = CompactParser::new(1000, ComputeMode::Latency);
+        p.parse_with_index(json, &idx).unwrap();
+        let spans: Vec<_> = p.tokens().iter()
+            .filter(|t| t.event != CompactEvent::Value)
+            .map(|t| (t.kind, json[t.raw_offset as usize..t.raw_end() as usize].to_vec()))
+            .collect();
+        let none = |b: &[u8]| (ValueKind::None, b.to_vec());
+        assert_eq!(spans, [none(b"{"), none(b"["), none(b"]"), none(b"}")]);
+    }
+
+    /// `(pointer, value text)` for every value token, paths rendered.
+    fn leaves(json: &[u8]) -> Vec<(String, String)> {
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+        let mut p = CompactParser::new(1000, ComputeMode::Latency);
+        p.set_record_paths(true);
+        p.parse_with_index(json, &idx).unwrap();
+        p.tokens()
+            .iter()
+            .filter(|t| t.event == CompactEvent::Value)
+            .map(|t| {
+                let path = p.paths().render(t.path_id).unwrap();
+                let span = &json[t.raw_offset as usize..t.raw_end() as usize];
+                (String::from_utf8(path).unwrap(), String::from_utf8(span.to_vec()).unwrap())
+            })
+            .collect()
+    }
 
-        while ptr < end {
-            let chunk = unsafe { v128_load(ptr as *const v128) };
-            // Position-sensitive mix: multiply the accumulator, then xor in
-            // the next block. Two values that share the same set of blocks in
-            // a different order now hash differently.
-            acc = i64x2_mul(acc, prime);
-            acc = v128_xor(acc, chunk);
-            ptr = unsafe { ptr.add(16) };
+    /// The same list computed from the generator's tree.
+    fn reference_leaves(node: &crate::gen::Node, path: &str, out: &mut Vec<(String, String)>) {
+        use crate::gen::Node;
+        match node {
+            Node::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    reference_leaves(item, &format!("{}/{}", path, i), out);
+                }
+            }
+            Node::Object(members) => {
+                for (k, v) in members {
+                    reference_leaves(v, &format!("{}/{}", path, k), out);
+                }
+            }
+            Node::String(body) => out.push((path.into(), body.clone())),
+            leaf => out.push((path.into(), leaf.to_json())),
         }
+    }
+
+    fn pairs(list: &[(&str, &str)]) -> Vec<(String, String)> {
+        list.iter().map(|&(p, v)| (p.into(), v.into())).collect()
+    }
+
+    #[test]
+    fn object_members_inside_arrays_keep_their_keys() {
+        assert_eq!(leaves(br#"[{"a":1,"b":2}]"#), pairs(&[("/0/a", "1"), ("/0/b", "2")]));
+        assert_eq!(
+            leaves(br#"[{"a":1,"b":2},{"a":3,"c":[4,{"d":5,"e":6}],"f":7},8]"#),
+            pairs(&[
+                ("/0/a", "1"), ("/0/b", "2"),
+                ("/1/a", "3"), ("/1/c/0", "4"), ("/1/c/1/d", "5"), ("/1/c/1/e", "6"), ("/1/f", "7"),
+                ("/2", "8"),
+            ])
+        );
+        assert_eq!(
+            leaves(br#"{"x":[[{"y":[1,2],"z":"s"}],3],"w":true}"#),
+            pairs(&[("/x/0/0/y/0", "1"), ("/x/0/0/y/1", "2"), ("/x/0/0/z", "s"), ("/x/1", "3"), ("/w", "true")])
+        );
+    }
+
+    #[test]
+    fn raw_end_saturates_instead_of_wrapping() {
+        let token = |raw_offset, raw_len| CompactToken {
+            path_id: crate::path::ROOT_PATH_ID, event: CompactEvent::Value, kind: ValueKind::Literal, value_hash: 0, raw_offset, raw_len,
+        };
+        assert_eq!(token(u32::MAX - 4, 4).raw_end(), u32::MAX);
+        assert_eq!(token(u32::MAX - 4, 5).raw_end(), u32::MAX);
+        assert_eq!(token(u32::MAX, u32::MAX).raw_end(), u32::MAX);
+    }
+
+    #[test]
+    fn colon_inside_array_is_ignored() {
+        assert_eq!(leaves(br#"{"a":[1:2,3]}"#).last(), Some(&("/a/1".into(), "3".into())));
+    }
 
-        let lane0 = u64x2_extract_lane::<0>(acc);
-        let lane1 = u64x2_extract_lane::<1>(acc);
-        let mut hash = lane0 ^ lane1.rotate_left(32) ^ (len as u64);
+    #[test]
+    fn paths_match_reference_on_generated_documents() {
+        use crate::gen::{generate, GenConfig};
+        let cfg = GenConfig::default();
+        for seed in 1..=300u64 {
+            let doc = generate(&cfg, seed);
+            let mut want = Vec::new();
+            reference_leaves(&doc, "", &mut want);
+            assert_eq!(leaves(doc.to_json().as_bytes()), want, "seed {}", seed);
+        }
+    }
 
-        // Tail
-        for &b in &bytes[len & !15..] {
-            hash ^= b as u64;
-            hash = hash.wrapping_mul(0x100000001b3);
+    #[test]
+    fn whitespace_regressions() {
+        // Array tail: the last primitive before `]`, after a space or a
+        // line break, is neither dropped nor given the whitespace.
+        for json in [&b"[1, 2 ]"[..], b"[1,2\n]", b"[1,\r\n2\t]"] {
+            assert_eq!(leaves(json), pairs(&[("/0", "1"), ("/1", "2")]), "{:?}", json);
         }
-        hash
-    } else {
-        use std::hash::Hasher;
-        let mut h = rustc_hash::FxHasher::default();
-        h.write(bytes);
-        h.finish()
+        // Colon lookahead: whitespace either side of `:` before any kind
+        // of value.
+        assert_eq!(
+            leaves(b"{\"a\" : 1,\"b\"\t:\t\"x\",\"c\"\r\n:\r\n[true] ,\"d\" :{\"e\" : null }}"),
+            pairs(&[("/a", "1"), ("/b", "x"), ("/c/0", "true"), ("/d/e", "null")])
+        );
+        // Block boundaries: a literal ending on byte 63 with the
+        // whitespace after it in the next block, and a key opening one.
+        let mut json = alloc::vec![b'['];
+        json.extend([b' '; 61]);
+        json.extend(b"12\n]");
+        assert_eq!(leaves(&json), pairs(&[("/0", "12")]));
+        let mut json = b"{".to_vec();
+        json.extend([b'\n'; 63]);
+        json.extend(b"\"k\":7 }");
+        assert_eq!(leaves(&json), pairs(&[("/k", "7")]));
     }
+
+    type TokenTuple = (PathId, CompactEvent, ValueKind, u64, u32, u32);
+
+    fn token_tuples(json: &[u8], idx: &StructuralIndex) -> (Vec<TokenTuple>, u64) {
+        let mut p = CompactParser::new(1000, ComputeMode::Latency);
+        p.parse_with_index(json, idx).unwrap();
+        let tokens = p.tokens().iter().map(|t| (t.path_id, t.event, t.kind, t.value_hash, t.raw_offset, t.raw_len)).collect();
+        (tokens, p.digest())
+    }
+
+    /// Stage 1 one byte at a time, against which the SIMD blocks are
+    /// checked.
+    fn reference_index(json: &[u8]) -> StructuralIndex {
+        let mut masks = alloc::vec![0u64; json.len().div_ceil(64)];
+        for (i, b) in json.iter().enumerate() {
+            if matches!(b, b'{' | b'}' | b'[' | b']' | b':' | b',' | b'"') {
+                masks[i / 64] |= 1 << (i % 64);
+            }
+        }
+        StructuralIndex { masks, len: json.len() as u32 }
+    }
+
+    #[test]
+    fn whitespace_anywhere_parses_to_the_same_tokens() {
+        use crate::gen::{generate, respace, whitespace_gaps, GenConfig, WHITESPACE};
+        let docs: [&[u8]; 12] = [
+            br#"{"a":[1,{"b":null}],"c":"x\"],:","d":[],"e":{},"f":[true,-1.5e3,""],"g":[[1],[2,3]]}"#,
+            br#"[1,2,[3,4],{"k":[5]},"s",false,{}]"#,
+            br#"{"a":{"b":{"c":[0]}},"z":-0.25}"#,
+            b"[]", b"{}", b"[[]]", b"[0]", br#"{"a":{}}"#, br#"[{"a":1}]"#,
+            b"42", br#""s""#, b"null",
+        ];
+        let generated: Vec<Vec<u8>> = (0..60).map(|seed| generate(&GenConfig::adversarial(), seed).to_json().into_bytes()).collect();
+        let mut variants = 0;
+        for doc in docs.into_iter().chain(generated.iter().map(Vec::as_slice)) {
+            let mut idx = StructuralIndex::new();
+            idx.build(doc);
+            let (want, digest) = token_tuples(doc, &idx);
+            let gaps = whitespace_gaps(doc);
+            let long = [b' '; 70];
+            let mut layouts: Vec<(Vec<usize>, Vec<&[u8]>)> = Vec::new();
+            for &g in &gaps {
+                layouts.extend(WHITESPACE.iter().map(|&ws| (alloc::vec![g], alloc::vec![ws])));
+            }
+            layouts.extend(WHITESPACE.iter().map(|&ws| (gaps.clone(), alloc::vec![ws])));
+            layouts.push((gaps.clone(), WHITESPACE.to_vec()));
+            // Every token in a block of its own, then the whole document
+            // shifted across a block boundary one byte at a time.
+            layouts.push((gaps.clone(), alloc::vec![&long[..]]));
+            for n in 1..=64 {
+                let mut spread = alloc::vec![&long[..n]];
+                spread.extend(core::iter::repeat_n(&b"\r\n"[..], gaps.len() - 1));
+                layouts.push((gaps.clone(), spread));
+            }
+
+            for (at, ws) in layouts {
+                let (json, moved) = respace(doc, &at, &ws);
+                let text = String::from_utf8_lossy(&json).into_owned();
+                idx.build(&json);
+                let simd = token_tuples(&json, &idx);
+                assert_eq!(simd, token_tuples(&json, &reference_index(&json)), "{:?}", text);
+                let want: Vec<TokenTuple> = want.iter().map(|&(p, e, k, h, o, l)| (p, e, k, h, moved[o as usize] as u32, l)).collect();
+                assert_eq!(simd, (want, digest), "{:?}", text);
+                variants += 1;
+            }
+        }
+        assert!(variants > 1000, "{} variants", variants);
+    }
+
+    /// Inputs for the hash golden tests: every length class of
+    /// `hash_bytes_simd` (short, one block, block plus tail, many blocks).
+    fn hash_vectors() -> Vec<Vec<u8>> {
+        vec![
+            b"".to_vec(),
+            b"a".to_vec(),
+            b"0123456789abcde".to_vec(),
+            b"0123456789abcdef".to_vec(),
+            b"0123456789abcdefg".to_vec(),
+            b"The quick brown fox jumps over the lazy dog".repeat(3),
+            "h\u{e9}llo w\u{f6}rld \u{2603}".as_bytes().to_vec(),
+        ]
+    }
+
+    #[test]
+    fn value_hash_golden_values() {
+        let got: Vec<u64> = hash_vectors().iter().map(|v| hash_bytes_simd(v)).collect();
+        assert_eq!(got, GOLDEN_VALUE_HASHES);
+    }
+
+    #[test]
+    fn scalar_value_hash_matches_simd() {
+        let bytes: Vec<u8> = (0..300u32).map(|i| (i.wrapping_mul(151) >> 3) as u8).collect();
+        for start in 0..8 {
+            for end in start..bytes.len() {
+                let v = &bytes[start..end];
+                assert_eq!(hash_bytes_scalar(v), hash_bytes_simd(v), "{}..{}", start, end);
+            }
+        }
+        for v in hash_vectors() {
+            assert_eq!(hash_bytes_scalar(&v), hash_bytes_simd(&v));
+        }
+    }
+
+    #[test]
+    fn token_reservation_only_for_large_inputs() {
+        let parse = |json: &[u8]| {
+            let mut idx = crate::simd_index::StructuralIndex::new();
+            idx.build(json);
+            let mut p = CompactParser::new(u32::MAX, crate::config::ComputeMode::Latency);
+            assert_eq!(p.tokens.capacity(), 0);
+            p.parse_with_index(json, &idx).unwrap();
+            p
+        };
+        let small = br#"{"a":[1,2],"b":"x"}"#;
+        assert!(parse(small).tokens.capacity() < 64);
+        let large = [&small[..], &[b' '; SMALL_INPUT_BYTES][..]].concat();
+        let p = parse(&large);
+        assert!(p.tokens.capacity() >= p.token_cap);
+        let small = parse(small);
+        assert_eq!((p.tokens().len(), p.digest()), (small.tokens().len(), small.digest()));
+    }
+
+    /// `hash_bytes_simd` of each `hash_vectors` entry. Checked against an
+    /// independent implementation of the documented algorithm; see the
+    /// doc comment before changing any of these.
+    const GOLDEN_VALUE_HASHES: [u64; 7] = [
+        0xcbf29ce484222325,
+        0xaf63dd4c8601ee3f,
+        0xafc41eb3fb43f9d8,
+        0x55570c0c55575543,
+        0x5a3e9df50365addf,
+        0x591d49f42d64f995,
+        0xb9ab83fc1cf3c57a,
+    ];
 }
@@ -0,0 +1,559 @@
+//! Document patches, for `self_contained` results and `apply_patch`.
+//!
+//! A result's entries reference values by span into the inputs and stop
+//! at leaves, so they cannot rebuild a document on their own: an empty
+//! container, or `{}` turned `[]`, has no leaf to report. The patch is a
+//! separate structural walk of the two whole inputs, with every new value
+//! copied in, so the left document and the patch give the right document
+//! with neither input resident.
+//!
+//! Records, unpadded, in the patch section: `[u8 op][u8 reserved; 3]
+//! [u32 path_len][u32 value_len][path][value]`.
+//!
+//! - `op`: `DiffOp`. Added and Modified set the path to `value`; Removed
+//!   deletes it and has no value.
+//! - `path`: a JSON Pointer, `""` for the root. Keys are their bytes as
+//!   written (escapes not decoded), with `~` and `/` as `~0` and `~1`, so
+//!   the document a patch applies to must write its keys the same way.
+//! - `value`: the right value's JSON text, as written.
+//!
+//! Records apply in order. Values that differ in kind, and scalars that
+//! differ as written, are Modified whole; objects recurse by key (the
+//! last of duplicate keys, as the join compares) and arrays by index,
+//! the longer side's tail Added in ascending or Removed in descending
+//! index order, so each index is valid when its record applies. No other
+//! record moves a path another addresses.
+//!
+//! Documents are held as node trees on the heap, so nesting depth costs
+//! no stack here either.
+
+use crate::diff::DiffOp;
+use crate::lint::{is_literal_byte, string_end};
+use crate::parser::ParseError;
+use crate::path_pattern::unescape;
+use crate::FxHashMap;
+use alloc::borrow::Cow;
+use alloc::string::ToString;
+use alloc::vec::Vec;
+
+/// Size of a record before its path and value.
+pub const RECORD_HEAD: usize = 12;
+
+/// Why `apply` gave up.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    /// The document is not JSON.
+    Parse(ParseError),
+    /// Record `n` (from 0) is unusable, for the reason given.
+    Record(usize, &'static str),
+}
+
+/// A value: its text as written and, for a container, its children.
+/// Edits leave a container's `text` stale; only `diff` reads it.
+struct Node<'a> {
+    text: &'a [u8],
+    body: Body<'a>,
+}
+
+enum Body<'a> {
+    Scalar,
+    Array(Vec<usize>),
+    /// Members in document order: raw key bytes and the value's node.
+    Object(Vec<(Cow<'a, [u8]>, usize)>),
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Value,
+    ValueOrEnd,
+    KeyOrEnd,
+    Key,
+    Colon,
+    CommaOrEnd,
+    End,
+}
+
+/// Nodes of one or more documents, which refer to each other by index.
+#[derive(Default)]
+struct Tree<'a> {
+    nodes: Vec<Node<'a>>,
+}
+
+impl<'a> Tree<'a> {
+    /// Add the nodes of `input`, one JSON value, and return its root's.
+    fn parse(&mut self, input: &'a [u8]) -> Result<usize, ParseError> {
+        // Open containers: node, offset of the bracket, pending key.
+        let mut open: Vec<(usize, usize, &'a [u8])> = Vec::new();
+        let mut expect = Expect::Value;
+        let (mut root, mut pos) = (0, 0);
+        loop {
+            while input.get(pos).is_some_and(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r')) { pos += 1; }
+            let in_object = open.last().is_some_and(|o| matches!(self.nodes[o.0].body, Body::Object(_)));
+            let Some(&b) = input.get(pos) else {
+                if expect != Expect::End { return Err(ParseError::IncompleteInput); }
+                return Ok(root);
+            };
+            let value = match (expect, b) {
+                (Expect::Value | Expect::ValueOrEnd, b'{' | b'[') => {
+                    let body = if b == b'{' { Body::Object(Vec::new()) } else { Body::Array(Vec::new()) };
+                    open.push((self.add(&input[pos..pos + 1], body), pos, &[]));
+                    expect = if b == b'{' { Expect::KeyOrEnd } else { Expect::ValueOrEnd };
+                    pos += 1;
+                    continue;
+                }
+                (Expect::Value | Expect::ValueOrEnd, b'"') => {
+                    let end = string_end(input, pos + 1)?;
+                    let n = self.add(&input[pos..end + 1], Body::Scalar);
+                    pos = end + 1;
+                    n
+                }
+                (Expect::Value | Expect::ValueOrEnd, _) if is_literal_byte(b) => {
+                    let end = input[pos..].iter().position(|&b| !is_literal_byte(b)).map_or(input.len(), |n| pos + n);
+                    let n = self.add(&input[pos..end], Body::Scalar);
+                    pos = end;
+                    n
+                }
+                (Expect::KeyOrEnd | Expect::Key, b'"') => {
+                    let end = string_end(input, pos + 1)?;
+                    if let Some(o) = open.last_mut() { o.2 = &input[pos + 1..end]; }
+                    expect = Expect::Colon;
+                    pos = end + 1;
+                    continue;
+                }
+                (Expect::Colon, b':') => {
+                    expect = Expect::Value;
+                    pos += 1;
+                    continue;
+                }
+                (Expect::CommaOrEnd, b',') => {
+                    expect = if in_object { Expect::Key } else { Expect::Value };
+                    pos += 1;
+                    continue;
+                }
+                (Expect::KeyOrEnd | Expect::CommaOrEnd, b'}') if in_object => {
+                    pos += 1;
+                    self.close(&mut open, &input[..pos])
+                }
+                (Expect::ValueOrEnd | Expect::CommaOrEnd, b']') if !in_object && !open.is_empty() => {
+                    pos += 1;
+                    self.close(&mut open, &input[..pos])
+                }
+                _ => return Err(ParseError::UnexpectedByte(b)),
+            };
+            match open.last() {
+                None => {
+                    root = value;
+                    expect = Expect::End;
+                }
+                Some(&(parent, _, key)) => {
+                    match &mut self.nodes[parent].body {
+                        Body::Object(members) => members.push((Cow::Borrowed(key), value)),
+                        Body::Array(elements) => elements.push(value),
+                        Body::Scalar => unreachable!("only containers are open"),
+                    }
+                    expect = Expect::CommaOrEnd;
+                }
+            }
+        }
+    }
+
+    fn add(&mut self, text: &'a [u8], body: Body<'a>) -> usize {
+        self.nodes.push(Node { text, body });
+        self.nodes.len() - 1
+    }
+
+    /// Close the innermost container; `input` ends with its bracket.
+    fn close(&mut self, open: &mut Vec<(usize, usize, &'a [u8])>, input: &'a [u8]) -> usize {
+        let Some((n, start, _)) = open.pop() else { unreachable!("checked by the caller") };
+        self.nodes[n].text = &input[start..];
+        n
+    }
+
+    /// The child of container `n` at pointer token `token`: the last
+    /// member with that key, or the element at that index.
+    fn child(&self, n: usize, token: &[u8]) -> Option<usize> {
+        match &self.nodes[n].body {
+            Body::Object(members) => members.iter().rev().find(|(k, _)| **k == *token).map(|&(_, v)| v),
+            Body::Array(elements) => index(token).and_then(|i| elements.get(i).copied()),
+            Body::Scalar => None,
+        }
+    }
+
+    /// Apply one record's edit to container `n`, at its child `token`;
+    /// `value` is the new child for Added and Modified. `None` if the
+    /// edit does not fit the container.
+    fn edit(&mut self, n: usize, op: DiffOp, token: Vec<u8>, value: Option<usize>) -> Option<()> {
+        match (&mut self.nodes[n].body, op, value) {
+            (Body::Object(members), DiffOp::Removed, _) => {
+                let before = members.len();
+                members.retain(|(k, _)| **k != *token);
+                (members.len() < before).then_some(())
+            }
+            (Body::Object(members), _, Some(v)) => {
+                // Set the last of duplicate keys, the one readers see, and
+                // drop the rest.
+                match members.iter().rposition(|(k, _)| **k == *token) {
+                    Some(last) => {
+                        members[last].1 = v;
+                        let mut i = 0;
+                        members.retain(|(k, _)| { i += 1; i - 1 == last || **k != *token });
+                    }
+                    None => members.push((Cow::Owned(token), v)),
+                }
+                Some(())
+            }
+            (Body::Array(elements), op, value) => {
+                let i = index(&token)?;
+                match (op, value) {
+                    (DiffOp::Added, Some(v)) if i <= elements.len() => elements.insert(i, v),
+                    (DiffOp::Modified, Some(v)) if i < elements.len() => elements[i] = v,
+                    (DiffOp::Removed, _) if i < elements.len() => { elements.remove(i); }
+                    _ => return None,
+                }
+                Some(())
+            }
+            _ => None,
+        }
+    }
+
+    /// Compact JSON text of the value at node `n`.
+    fn write(&self, n: usize) -> Vec<u8> {
+        enum Item<'t> {
+            Node(usize),
+            Text(&'t [u8]),
+        }
+        let mut out = Vec::new();
+        let mut work = alloc::vec![Item::Node(n)];
+        while let Some(item) = work.pop() {
+            let n = match item {
+                Item::Text(text) => {
+                    out.extend_from_slice(text);
+                    continue;
+                }
+                Item::Node(n) => n,
+            };
+            // Pushed in reverse, so they pop in document order.
+            match &self.nodes[n].body {
+                Body::Scalar => out.extend_from_slice(self.nodes[n].text),
+                Body::Array(elements) => {
+                    out.push(b'[');
+                    work.push(Item::Text(b"]"));
+                    for (i, &e) in elements.iter().enumerate().rev() {
+                        work.push(Item::Node(e));
+                        if i > 0 { work.push(Item::Text(b",")); }
+                    }
+                }
+                Body::Object(members) => {
+                    out.push(b'{');
+                    work.push(Item::Text(b"}"));
+                    for (i, (key, v)) in members.iter().enumerate().rev() {
+                        work.extend([Item::Node(*v), Item::Text(b"\":"), Item::Text(key), Item::Text(b"\"")]);
+                        if i > 0 { work.push(Item::Text(b",")); }
+                    }
+                }
+            }
+        }
+        out
+    }
+}
+
+/// An array index token: decimal digits, no leading zero.
+fn index(token: &[u8]) -> Option<usize> {
+    if token.is_empty() || (token.len() > 1 && token[0] == b'0') || !token.iter().all(u8::is_ascii_digit) {
+        return None;
+    }
+    core::str::from_utf8(token).ok()?.parse().ok()
+}
+
+/// No value at all: the input of an uncommitted side.
+fn blank(input: &[u8]) -> bool { input.iter().all(|b| matches!(b, b' ' | b'\t' | b'\n' | b'\r')) }
+
+/// The walk's pointers, shared by prefix: each is its parent's index
+/// and one escaped token, so a deep path is not copied at every level.
+#[derive(Default)]
+struct Pointers(Vec<(usize, Vec<u8>)>);
+
+/// `Pointers` parent of the root's children.
+const ROOT: usize = usize::MAX;
+
+impl Pointers {
+    /// The pointer to `parent`'s child `token` (raw key bytes or an index).
+    fn child(&mut self, parent: usize, token: &[u8]) -> usize {
+        let mut escaped = Vec::with_capacity(token.len());
+        escape_token(token, &mut escaped);
+        self.0.push((parent, escaped));
+        self.0.len() - 1
+    }
+
+    /// The text of `parent`'s child `token`, a leaf of the walk.
+    fn render_child(&self, parent: usize, token: &[u8]) -> Vec<u8> {
+        let mut out = self.render(parent);
+        out.push(b'/');
+        escape_token(token, &mut out);
+        out
+    }
+
+    fn render(&self, mut at: usize) -> Vec<u8> {
+        let mut chain = Vec::new();
+        while at != ROOT {
+            chain.push(at);
+            at = self.0[at].0;
+        }
+        let mut out = Vec::new();
+        for &i in chain.iter().rev() {
+            out.push(b'/');
+            out.extend_from_slice(&self.0[i].1);
+        }
+        out
+    }
+}
+
+fn escape_token(token: &[u8], out: &mut Vec<u8>) {
+    for &b in token {
+        match b {
+            b'~' => out.extend_from_slice(b"~0"),
+            b'/' => out.extend_from_slice(b"~1"),
+            _ => out.push(b),
+        }
+    }
+}
+
+/// Each key's last member index.
+fn last_members<'t>(members: &'t [(Cow<'_, [u8]>, usize)]) -> FxHashMap<&'t [u8], usize> {
+    let mut map = FxHashMap::default();
+    for (i, (key, _)) in members.iter().enumerate() { map.insert(&**key, i); }
+    map
+}
+
+fn record(op: DiffOp, path: &[u8], value: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&[op as u8, 0, 0, 0]);
+    out.extend_from_slice(&(path.len() as u32).to_le_bytes());
+    out.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    out.extend_from_slice(path);
+    out.extend_from_slice(value);
+}
+
+/// The patch records that turn `left` into `right`, both documents the
+/// parser accepted; a blank side is no document, whose root the patch
+/// adds or removes.
+pub fn diff(left: &[u8], right: &[u8]) -> Result<Vec<u8>, ParseError> {
+    let mut tree = Tree::default();
+    let mut out = Vec::new();
+    let (l, r) = match (blank(left), blank(right)) {
+        (true, true) => return Ok(out),
+        (true, false) => {
+            let r = tree.parse(right)?;
+            record(DiffOp::Added, b"", tree.nodes[r].text, &mut out);
+            return Ok(out);
+        }
+        (false, true) => {
+            tree.parse(left)?;
+            record(DiffOp::Removed, b"", b"", &mut out);
+            return Ok(out);
+        }
+        (false, false) => (tree.parse(left)?, tree.parse(right)?),
+    };
+    let mut paths = Pointers::default();
+    let mut work = alloc::vec![(l, r, ROOT)];
+    while let Some((l, r, path)) = work.pop() {
+        let (left, right) = (&tree.nodes[l], &tree.nodes[r]);
+        if left.text == right.text { continue; }
+        let next = work.len();
+        match (&left.body, &right.body) {
+            (Body::Object(lm), Body::Object(rm)) => {
+                let (ll, rl) = (last_members(lm), last_members(rm));
+                for (i, (key, v)) in rm.iter().enumerate() {
+                    if rl[&**key] != i { continue; }
+                    match ll.get(&**key) {
+                        Some(&j) => work.push((lm[j].1, *v, paths.child(path, key))),
+                        None => record(DiffOp::Added, &paths.render_child(path, key), tree.nodes[*v].text, &mut out),
+                    }
+                }
+                for (i, (key, _)) in lm.iter().enumerate() {
+                    if ll[&**key] == i && !rl.contains_key(&**key) {
+                        record(DiffOp::Removed, &paths.render_child(path, key), b"", &mut out);
+                    }
+                }
+            }
+            (Body::Array(le), Body::Array(re)) => {
+                for (i, (&a, &b)) in le.iter().zip(re).enumerate() {
+                    work.push((a, b, paths.child(path, i.to_string().as_bytes())));
+                }
+                for i in (re.len()..le.len()).rev() {
+                    record(DiffOp::Removed, &paths.render_child(path, i.to_string().as_bytes()), b"", &mut out);
+                }
+                for (i, &v) in re.iter().enumerate().skip(le.len()) {
+                    record(DiffOp::Added, &paths.render_child(path, i.to_string().as_bytes()), tree.nodes[v].text, &mut out);
+                }
+            }
+            _ => record(DiffOp::Modified, &paths.render(path), right.text, &mut out),
+        }
+        // Children in document order.
+        work[next..].reverse();
+    }
+    Ok(out)
+}
+
+/// One record, and the offset of the next.
+struct Record<'p> {
+    op: DiffOp,
+    path: &'p [u8],
+    value: &'p [u8],
+    end: usize,
+}
+
+/// The record at `at`, or why it is unreadable.
+fn read_record(patch: &[u8], at: usize) -> Result<Record<'_>, &'static str> {
+    let head = patch.get(at..at + RECORD_HEAD).ok_or("truncated record")?;
+    let op = match head[0] {
+        0 => DiffOp::Added,
+        1 => DiffOp::Removed,
+        2 => DiffOp::Modified,
+        _ => return Err("unknown op"),
+    };
+    let word = |i: usize| u32::from_le_bytes(head[i..i + 4].try_into().unwrap()) as usize;
+    let path_at = at + RECORD_HEAD;
+    let value_at = path_at.checked_add(word(4)).ok_or("truncated record")?;
+    let end = value_at.checked_add(word(8)).ok_or("truncated record")?;
+    if end > patch.len() { return Err("truncated record"); }
+    Ok(Record { op, path: &patch[path_at..value_at], value: &patch[value_at..end], end })
+}
+
+/// Apply `patch` (records as `diff` writes them) to `doc`, and return the
+/// resulting document's compact text: no whitespace, members in `doc`'s
+/// order with new keys last, values as written. Empty if the patch
+/// removes the root.
+pub fn apply(doc: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut tree = Tree::default();
+    let mut root = if blank(doc) { None } else { Some(tree.parse(doc).map_err(PatchError::Parse)?) };
+    let (mut at, mut n) = (0, 0);
+    while at < patch.len() {
+        let bad = |why| PatchError::Record(n, why);
+        let Record { op, path, value, end } = read_record(patch, at).map_err(bad)?;
+        let value = match op {
+            DiffOp::Removed => None,
+            _ => Some(tree.parse(value).map_err(|_| bad("value is not JSON"))?),
+        };
+        if path.is_empty() {
+            root = value;
+        } else {
+            let tokens: Vec<Vec<u8>> = path.strip_prefix(b"/").ok_or(bad("malformed path"))?.split(|&b| b == b'/').map(unescape).collect();
+            let Some((last, parents)) = tokens.split_last() else { unreachable!("split yields a token") };
+            let mut node = root.ok_or(bad("path not in the document"))?;
+            for token in parents {
+                node = tree.child(node, token).ok_or(bad("path not in the document"))?;
+            }
+            tree.edit(node, op, last.clone(), value).ok_or(bad("path not in the document"))?;
+        }
+        (at, n) = (end, n + 1);
+    }
+    Ok(root.map_or_else(Vec::new, |r| tree.write(r)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::lint::canonicalize;
+
+    /// `apply(left, diff(left, right))` is `right` as a value: the same
+    /// canonical form (sorted keys, plain numbers).
+    fn assert_round_trips(left: &[u8], right: &[u8]) {
+        let patch = diff(left, right).unwrap();
+        let rebuilt = apply(left, &patch).unwrap();
+        let canonical = |doc: &[u8]| if blank(doc) { Vec::new() } else { canonicalize(doc, 0).unwrap().text };
+        assert_eq!(
+            String::from_utf8_lossy(&canonical(&rebuilt)),
+            String::from_utf8_lossy(&canonical(right)),
+            "{} -> {}",
+            String::from_utf8_lossy(left),
+            String::from_utf8_lossy(right)
+        );
+    }
+
+    type Readable<'p> = (DiffOp, &'p str, &'p str);
+
+    /// `(op, path, value)` of each record.
+    fn records(patch: &[u8]) -> Vec<Readable<'_>> {
+        let mut out = Vec::new();
+        let mut at = 0;
+        while at < patch.len() {
+            let Record { op, path, value, end } = read_record(patch, at).unwrap();
+            out.push((op, core::str::from_utf8(path).unwrap(), core::str::from_utf8(value).unwrap()));
+            at = end;
+        }
+        out
+    }
+
+    #[test]
+    fn patches_cover_what_leaf_entries_miss() {
+        use DiffOp::{Added as A, Modified as M, Removed as R};
+        let cases: [(&[u8], &[u8], &[Readable]); 6] = [
+            (br#"{"a":{}}"#, br#"{"a":[]}"#, &[(M, "/a", "[]")]),
+            (br#"{"a":1}"#, br#"{"a":1,"e":{}}"#, &[(A, "/e", "{}")]),
+            (br#"[1,2,3]"#, br#"[1]"#, &[(R, "/2", ""), (R, "/1", "")]),
+            (br#"[1]"#, br#"[0,2,3]"#, &[(A, "/1", "2"), (A, "/2", "3"), (M, "/0", "0")]),
+            (br#"{"a/b":{"~":1}}"#, br#"{"a/b":{"~":2}}"#, &[(M, "/a~1b/~0", "2")]),
+            (br#"{ "same" : [ 1 ] }"#, br#"{"same":[1]}"#, &[]),
+        ];
+        for (left, right, want) in cases {
+            assert_eq!(records(&diff(left, right).unwrap()), want, "{}", String::from_utf8_lossy(left));
+        }
+        // A blank side is no document.
+        assert_eq!(records(&diff(b"", b" 7 ").unwrap()), [(A, "", "7")]);
+        assert_eq!(records(&diff(b"7", b"").unwrap()), [(R, "", "")]);
+        assert_eq!(apply(b"7", &diff(b"7", b"").unwrap()).unwrap(), b"");
+    }
+
+    #[test]
+    fn round_trips_a_corpus() {
+        let cases: [(&[u8], &[u8]); 10] = [
+            (br#"{"b":1,"a":{"c":[1,2]}}"#, br#"{"a":{"c":[2],"d":null},"z":"new"}"#),
+            (br#"[{"id":1},{"id":2},{"id":3}]"#, br#"[{"id":1,"x":[]},{"id":2}]"#),
+            (br#"{"a":[[],{}],"b":"s"}"#, br#"{"a":[{},[]],"b":["s"]}"#),
+            (br#"null"#, br#"[null]"#),
+            (br#"{"k":"\u00e9"}"#, br#"{"k":"\u00e8","l":"\n"}"#),
+            (br#"{"n":1.0}"#, br#"{"n":1}"#),
+            (br#"{"":{"":1}}"#, br#"{"":{"":2,"x":3}}"#),
+            (b"", br#"{"a":1}"#),
+            (br#"{"a":1}"#, b""),
+            (b"  ", b""),
+        ];
+        for (left, right) in cases {
+            assert_round_trips(left, right);
+        }
+        // Keys long enough not to repeat: duplicates rebuild as their
+        // last member alone, which canonical forms tell apart.
+        let shapes = crate::gen::GenConfig { key_len: (6, 8), ..crate::gen::GenConfig::adversarial() };
+        for seed in 0..50 {
+            use crate::gen::{generate, mutate};
+            let doc = generate(&shapes, seed);
+            let (edited, _) = mutate(&doc, 4, seed);
+            let (left, right) = (doc.to_json().into_bytes(), edited.to_json().into_bytes());
+            assert_round_trips(&left, &right);
+            assert_round_trips(&right, &left);
+        }
+    }
+
+    #[test]
+    fn deep_documents_cost_no_stack() {
+        let deep = |leaf: &str| alloc::format!("{}{}{}", "[".repeat(100_000), leaf, "]".repeat(100_000)).into_bytes();
+        let (left, right) = (deep("1"), deep("{}"));
+        let patch = diff(&left, &right).unwrap();
+        assert_eq!(apply(&left, &patch).unwrap(), right);
+    }
+
+    #[test]
+    fn records_that_do_not_fit_are_refused() {
+        let patch = diff(br#"{"a":{"b":1}}"#, br#"{"a":{"b":2}}"#).unwrap();
+        assert_eq!(apply(br#"{"a":[1]}"#, &patch), Err(PatchError::Record(0, "path not in the document")));
+        assert_eq!(apply(br#"{"a":"#, &patch), Err(PatchError::Parse(ParseError::IncompleteInput)));
+        assert_eq!(apply(b"{}", &patch[..patch.len() - 1]), Err(PatchError::Record(0, "truncated record")));
+        let mut bad = patch.clone();
+        bad[0] = 9;
+        assert_eq!(apply(b"{}", &bad), Err(PatchError::Record(0, "unknown op")));
+        let removed = diff(b"[1,2]", b"[1]").unwrap();
+        assert_eq!(apply(b"[1]", &removed), Err(PatchError::Record(0, "path not in the document")));
+        // Setting a duplicate key keeps one member, the last.
+        assert_eq!(apply(br#"{"a":{"b":0,"b":1}}"#, &patch).unwrap(), br#"{"a":{"b":2}}"#);
+    }
+}
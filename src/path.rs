@@ -2,18 +2,41 @@
 //!
 //! Replaces the Trie-based PathArena for world-class throughput.
 
+use crate::parser::HASH_PRIME;
+use alloc::boxed::Box;
+use alloc::string::ToString;
+use alloc::vec;
+use alloc::vec::Vec;
+
 /// Path identifier using a 64-bit non-cryptographic hash.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct PathId(pub u64);
 
 pub const ROOT_PATH_ID: PathId = PathId(0);
 
-/// Rolling hash generator for path segments.
+/// Folded in, plus the key's byte length, ahead of a key's bytes: above
+/// any byte, so the length is not a key byte either.
+const SEGMENT_MARK: u64 = 0x100;
+
+/// Rolling hash generator for path segments: `h = h * HASH_PRIME ^ x`
+/// (wrapping) per step, starting from the parent id, for one step of
+/// [`SEGMENT_MARK`] plus the key's length, then one per key byte.
+///
+/// The length step keeps segment boundaries apart: with only the bytes,
+/// `/a/b` and `/ab` folded the same steps and shared an id, so
+/// `{"a":{"b":1}}` and `{"ab":1}` diffed as equal (up to format 2.17).
+/// It also gives the empty key an id of its own, neither its parent's
+/// (up to 2.7) nor a one-byte key's.
+///
+/// Path ids are written into every result entry; their values are pinned
+/// by golden tests below. See `hash_bytes_simd` for what a change needs.
+///
+/// NOTE: mirrored by `foldSegment` in `js/src/path-index.ts`.
 #[inline(always)]
 pub fn fold_segment_hash(parent: PathId, bytes: &[u8]) -> PathId {
-    let mut h = parent.0;
+    let mut h = parent.0.wrapping_mul(HASH_PRIME) ^ SEGMENT_MARK.wrapping_add(bytes.len() as u64);
     for &b in bytes {
-        h = h.wrapping_mul(0x100000001b3);
+        h = h.wrapping_mul(HASH_PRIME);
         h ^= b as u64;
     }
     PathId(h)
@@ -32,9 +55,241 @@ pub fn fold_segment_hash(parent: PathId, bytes: &[u8]) -> PathId {
 /// The two implementations MUST stay in lockstep.
 #[inline(always)]
 pub fn fold_index_hash(parent: PathId, index: usize) -> PathId {
-    let mut h = parent.0.wrapping_mul(0x100000001b3);
+    let mut h = parent.0.wrapping_mul(HASH_PRIME);
     h ^= (index as u64)
         .wrapping_add(1)
         .wrapping_mul(0x9E37_79B9_7F4A_7C15);
     PathId(h)
 }
+
+/// Most numeric pointer segments considered ambiguous (key or index) when
+/// resolving a pointer; beyond this the pointer is rejected.
+const MAX_AMBIGUOUS_SEGMENTS: u32 = 6;
+
+/// Every `PathId` an RFC 6901 pointer may denote. A segment of decimal
+/// digits can be an object key or an array index, so each such segment
+/// doubles the candidates. Key segments are `~1`/`~0`-unescaped and hashed
+/// as raw key bytes (JSON escapes inside keys are not decoded). Returns
+/// `None` for a malformed pointer.
+pub fn pointer_candidates(pointer: &[u8]) -> Option<Vec<PathId>> {
+    let mut ids = vec![ROOT_PATH_ID];
+    if pointer.is_empty() { return Some(ids); }
+    if pointer[0] != b'/' { return None; }
+
+    let mut ambiguous = 0;
+    for raw in pointer[1..].split(|&b| b == b'/') {
+        let mut key = Vec::with_capacity(raw.len());
+        let mut it = raw.iter();
+        while let Some(&b) = it.next() {
+            if b != b'~' { key.push(b); continue; }
+            match it.next() {
+                Some(b'0') => key.push(b'~'),
+                Some(b'1') => key.push(b'/'),
+                _ => return None,
+            }
+        }
+        let index = core::str::from_utf8(raw).ok()
+            .filter(|s| !s.is_empty() && (s == &"0" || !s.starts_with('0')))
+            .and_then(|s| s.parse::<usize>().ok());
+        let mut next = Vec::with_capacity(ids.len() * 2);
+        for &id in &ids {
+            next.push(fold_segment_hash(id, &key));
+            if let Some(i) = index { next.push(fold_index_hash(id, i)); }
+        }
+        if index.is_some() {
+            ambiguous += 1;
+            if ambiguous > MAX_AMBIGUOUS_SEGMENTS { return None; }
+        }
+        ids = next;
+    }
+    Some(ids)
+}
+
+/// One step from a parent path to a child.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathSegment {
+    /// Object key as its raw (still escaped) string body.
+    Key(Box<[u8]>),
+    /// Array index.
+    Index(u32),
+}
+
+/// Reverse map from `PathId` to `(parent, segment)`, recorded during parsing
+/// so hashed paths can be rendered back into JSON Pointers.
+#[derive(Debug, Default)]
+pub struct PathTable {
+    links: crate::FxHashMap<PathId, (PathId, PathSegment)>,
+}
+
+impl PathTable {
+    /// Record `child = parent + segment`. First writer wins; the segment is
+    /// only built when the path is new.
+    #[inline]
+    pub fn record(&mut self, child: PathId, parent: PathId, segment: impl FnOnce() -> PathSegment) {
+        self.links.entry(child).or_insert_with(|| (parent, segment()));
+    }
+
+    pub fn clear(&mut self) { self.links.clear(); }
+
+    /// Every recorded `(child, parent, segment)`, by child id (see
+    /// `ordered.rs`).
+    pub fn links(&self) -> Vec<(PathId, PathId, &PathSegment)> {
+        crate::ordered::sorted_entries(&self.links).into_iter().map(|(&child, (parent, segment))| (child, *parent, segment)).collect()
+    }
+
+    pub fn shrink_to_fit(&mut self) { self.links.shrink_to_fit(); }
+
+    /// Bytes of link slots allocated, key bodies aside.
+    pub fn capacity_bytes(&self) -> usize {
+        self.links.capacity() * core::mem::size_of::<(PathId, (PathId, PathSegment))>()
+    }
+
+    /// Render `id` as an RFC 6901 JSON Pointer (`""` for the root), escaping
+    /// `~` and `/` in keys. Key bytes are emitted as written in the input,
+    /// so JSON escapes such as `\n` are not decoded. Returns `None` for a
+    /// path this table never saw.
+    pub fn render(&self, id: PathId) -> Option<Vec<u8>> {
+        let chain = self.chain(id)?;
+        let mut out = Vec::new();
+        for seg in chain.iter().rev() {
+            out.push(b'/');
+            match seg {
+                PathSegment::Index(i) => out.extend_from_slice(i.to_string().as_bytes()),
+                PathSegment::Key(k) => {
+                    for &b in k.iter() {
+                        match b {
+                            b'~' => out.extend_from_slice(b"~0"),
+                            b'/' => out.extend_from_slice(b"~1"),
+                            _ => out.push(b),
+                        }
+                    }
+                }
+            }
+        }
+        Some(out)
+    }
+
+    /// Render `id` as a dotted path for display: keys joined by `.`,
+    /// indices as `[i]`, so `/a/b/0` is `a.b[0]` and the root is empty.
+    /// A key that is empty or holds `.`, `[`, `]` or `"` is written
+    /// `["key"]` instead (raw body, as in `render`). `None` for a path
+    /// this table never saw.
+    pub fn render_dotted(&self, id: PathId) -> Option<Vec<u8>> {
+        let chain = self.chain(id)?;
+        let mut out = Vec::new();
+        for seg in chain.iter().rev() {
+            match seg {
+                PathSegment::Index(i) => {
+                    out.push(b'[');
+                    out.extend_from_slice(i.to_string().as_bytes());
+                    out.push(b']');
+                }
+                PathSegment::Key(k) if k.is_empty() || k.iter().any(|b| matches!(b, b'.' | b'[' | b']' | b'"')) => {
+                    out.extend_from_slice(b"[\"");
+                    out.extend_from_slice(k);
+                    out.extend_from_slice(b"\"]");
+                }
+                PathSegment::Key(k) => {
+                    if !out.is_empty() { out.push(b'.'); }
+                    out.extend_from_slice(k);
+                }
+            }
+        }
+        Some(out)
+    }
+
+    /// The segments from `id` up to the root, innermost first.
+    fn chain(&self, id: PathId) -> Option<Vec<&PathSegment>> {
+        let mut chain = Vec::new();
+        let mut cur = id;
+        while cur != ROOT_PATH_ID {
+            let (parent, seg) = self.links.get(&cur)?;
+            chain.push(seg);
+            // A hash collision could in principle form a cycle; no real
+            // document nests this deep.
+            if chain.len() > 1 << 16 { return None; }
+            cur = *parent;
+        }
+        Some(chain)
+    }
+}
+
+/// Append `path`'s path section record (layout in `memory.rs`): the bytes
+/// it shares with `prev`, then the rest. `prev` is `None` for the first
+/// entry and whenever the section is not front-coded.
+pub fn write_path_record(prev: Option<&[u8]>, path: &[u8], out: &mut Vec<u8>) {
+    let shared = shared_prefix(prev, path);
+    out.extend_from_slice(&(shared as u32).to_le_bytes());
+    out.extend_from_slice(&((path.len() - shared) as u32).to_le_bytes());
+    out.extend_from_slice(&path[shared..]);
+}
+
+/// Length of the records `write_path_record` writes for `paths`.
+pub fn path_records_len(paths: &[Vec<u8>], front_coded: bool) -> usize {
+    let mut prev = None;
+    let mut len = 0;
+    for path in paths {
+        len += 8 + path.len() - shared_prefix(prev, path);
+        if front_coded { prev = Some(path.as_slice()); }
+    }
+    len
+}
+
+fn shared_prefix(prev: Option<&[u8]>, path: &[u8]) -> usize {
+    prev.map_or(0, |prev| prev.iter().zip(path).take_while(|(a, b)| a == b).count())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dotted_paths_quote_ambiguous_keys() {
+        let mut table = PathTable::default();
+        let mut path = ROOT_PATH_ID;
+        for seg in [PathSegment::Key(Box::from(&b"a"[..])), PathSegment::Key(Box::from(&b"b"[..])), PathSegment::Index(0), PathSegment::Key(Box::from(&b"x.y"[..])), PathSegment::Key(Box::from(&b"z"[..]))] {
+            let child = match &seg {
+                PathSegment::Key(k) => fold_segment_hash(path, k),
+                PathSegment::Index(i) => fold_index_hash(path, *i as usize),
+            };
+            table.record(child, path, || seg);
+            path = child;
+        }
+        assert_eq!(table.render_dotted(path).as_deref(), Some(&br#"a.b[0]["x.y"].z"#[..]));
+        assert_eq!(table.render(path).as_deref(), Some(&b"/a/b/0/x.y/z"[..]));
+        assert_eq!(table.render_dotted(ROOT_PATH_ID).as_deref(), Some(&b""[..]));
+        assert_eq!(table.render_dotted(PathId(7)), None);
+    }
+
+    #[test]
+    fn fold_hash_golden_values() {
+        let users = fold_segment_hash(ROOT_PATH_ID, b"users");
+        let first = fold_index_hash(users, 0);
+        let pinned = [
+            (users, 0xff22_ff80_3207_b6ff),
+            (first, 0x0e18_5c6c_7c51_8f58),
+            (fold_segment_hash(first, b"name"), 0xbaea_197c_e0fe_ae25),
+            (fold_segment_hash(ROOT_PATH_ID, "\u{43a}\u{43b}\u{44e}\u{447}".as_bytes()), 0x7815_5d9e_8ba8_5094),
+            (fold_index_hash(ROOT_PATH_ID, 0), 0x9e37_79b9_7f4a_7c15),
+            // Index 48 and key "0" no longer meet (see `fold_index_hash`).
+            (fold_index_hash(ROOT_PATH_ID, 48), 0x489e_4c81_5d41_c005),
+            (fold_segment_hash(ROOT_PATH_ID, b"0"), 0x1_0100_0001_b483),
+            // The empty key is neither its parent nor a one-byte key.
+            (fold_segment_hash(ROOT_PATH_ID, b""), 0x100),
+            (fold_segment_hash(users, b""), 0x902f_25d5_031b_f24d),
+        ];
+        for (i, (id, want)) in pinned.into_iter().enumerate() {
+            assert_eq!(id, PathId(want), "vector {}", i);
+        }
+    }
+
+    #[test]
+    fn segment_boundaries_change_the_id() {
+        let key = |p, k: &[u8]| fold_segment_hash(p, k);
+        let a = key(ROOT_PATH_ID, b"a");
+        assert_ne!(key(a, b"b"), key(ROOT_PATH_ID, b"ab"), "/a/b and /ab");
+        assert_ne!(key(key(a, b""), b"b"), key(a, b"b"), "/a//b and /a/b");
+        assert_ne!(key(key(ROOT_PATH_ID, b"ab"), b"c"), key(a, b"bc"), "/ab/c and /a/bc");
+        assert_ne!(key(a, b""), key(ROOT_PATH_ID, b"a"), "/a/ and /a");
+    }
+}
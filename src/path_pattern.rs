@@ -0,0 +1,122 @@
+//! Path patterns (`redact_paths`).
+//!
+//! A pattern is dotted, like `resolve_symbol`'s paths: segments joined by
+//! `.`, with an optional leading `.`. A segment is `*` (any one key or
+//! index), `**` (any number of segments, none included) or a literal,
+//! which matches a key with exactly those bytes or an index with that
+//! decimal. So `.credentials.*` is every member of the root's
+//! `credentials` and `**.password` every `password` at any depth.
+//!
+//! A pattern that matches a path also matches everything under it: the
+//! values of `**.password` include those inside a `password` object. Keys
+//! are compared as written in the input (escapes not decoded), and a key
+//! holding `.` cannot be spelled; nor can `"`, `\` or control bytes,
+//! which keeps patterns writable in the JSON config form as-is.
+
+use alloc::vec::Vec;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Segment {
+    Literal(Vec<u8>),
+    AnyOne,
+    AnyDepth,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PathPattern(Vec<Segment>);
+
+impl PathPattern {
+    /// Compile `pattern`; `None` for an empty segment (`a..b`, a trailing
+    /// `.`) or a byte no pattern may hold. `""` and `"."` match the root,
+    /// so everything.
+    pub fn parse(pattern: &[u8]) -> Option<Self> {
+        if pattern.iter().any(|&b| b == b'"' || b == b'\\' || b < 0x20) { return None; }
+        let body = pattern.strip_prefix(b".").unwrap_or(pattern);
+        if body.is_empty() { return Some(Self(Vec::new())); }
+        body.split(|&b| b == b'.')
+            .map(|seg| match seg {
+                b"" => None,
+                b"*" => Some(Segment::AnyOne),
+                b"**" => Some(Segment::AnyDepth),
+                literal => Some(Segment::Literal(literal.to_vec())),
+            })
+            .collect::<Option<Vec<_>>>()
+            .map(Self)
+    }
+
+    /// Whether the path with JSON Pointer `pointer` is matched, itself or
+    /// through an ancestor.
+    pub fn matches_pointer(&self, pointer: &[u8]) -> bool {
+        let segments: Vec<Vec<u8>> = if pointer.is_empty() {
+            Vec::new()
+        } else {
+            pointer[1..].split(|&b| b == b'/').map(unescape).collect()
+        };
+        let segments: Vec<&[u8]> = segments.iter().map(Vec::as_slice).collect();
+        prefix_match(&self.0, &segments)
+    }
+}
+
+/// A pointer segment with `~1` and `~0` decoded, in that order.
+pub fn unescape(seg: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(seg.len());
+    let mut i = 0;
+    while i < seg.len() {
+        match (seg[i], seg.get(i + 1)) {
+            (b'~', Some(b'1')) => { out.push(b'/'); i += 2; }
+            (b'~', Some(b'0')) => { out.push(b'~'); i += 2; }
+            (b, _) => { out.push(b); i += 1; }
+        }
+    }
+    out
+}
+
+/// Whether `pattern` matches a prefix of `path`. A pointer segment is a
+/// key or an index alike, so a literal `0` matches both.
+fn prefix_match(pattern: &[Segment], path: &[&[u8]]) -> bool {
+    match pattern.split_first() {
+        None => true,
+        Some((Segment::AnyDepth, rest)) => (0..=path.len()).any(|skip| prefix_match(rest, &path[skip..])),
+        Some((seg, rest)) => match path.split_first() {
+            Some((&head, tail)) => {
+                let hit = match seg {
+                    Segment::Literal(lit) => lit == head,
+                    _ => true,
+                };
+                hit && prefix_match(rest, tail)
+            }
+            None => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(pattern: &str, pointer: &str) -> bool {
+        PathPattern::parse(pattern.as_bytes()).unwrap().matches_pointer(pointer.as_bytes())
+    }
+
+    #[test]
+    fn wildcards_and_ancestors() {
+        assert!(matches(".credentials.*", "/credentials/token"));
+        assert!(matches(".credentials.*", "/credentials/aws/secret"), "under a match");
+        assert!(!matches(".credentials.*", "/credentials"), "`*` needs a segment");
+        assert!(!matches(".credentials.*", "/user/credentials/token"), "anchored at the root");
+        assert!(matches("**.password", "/password"));
+        assert!(matches("**.password", "/users/3/password"));
+        assert!(!matches("**.password", "/users/3/password_hint"));
+        assert!(matches("users.*.name", "/users/0/name"));
+        assert!(matches("users.0", "/users/0/name"), "a literal index");
+        assert!(matches("a/b.c~d", "/a~1b/c~0d"), "pointer escapes decoded");
+        assert!(matches(".", "/anything"));
+    }
+
+    #[test]
+    fn rejects_unwritable_patterns() {
+        for bad in ["a..b", "a.", "..", "a.\"b\"", "a\\.b", "a\tb"] {
+            assert_eq!(PathPattern::parse(bad.as_bytes()), None, "{:?}", bad);
+        }
+    }
+}
@@ -0,0 +1,199 @@
+//! Cheap look at a document prefix, for routing before any engine exists.
+//!
+//! A host reads the first few kilobytes and picks an engine config from
+//! the root kind and rough size (a big-array config, a deep-object one),
+//! then commits the whole document there. The probe runs the same
+//! structural index as a commit (checked out of the ungrouped pool) and
+//! the parser's escaped-quote rule, so strings hide their brackets and
+//! commas exactly as they do in a full parse. It never fails: malformed
+//! or cut-off input just gives a rougher answer.
+//!
+//! Record (16 bytes, little-endian): `[u8 root_kind][u8 flags]
+//! [u16 reserved][u32 top_level_items][u32 max_depth][u32 scanned]`.
+//!
+//! - `root_kind`: a `RootKind` (0 missing, 1 object, 2 array, 3 scalar),
+//!   from the first non-whitespace byte.
+//! - `top_level_items`: members or elements of the root seen so far,
+//!   commas at depth 1 plus one for a non-empty root. A lower bound on a
+//!   cut-off prefix, off by one if the cut falls right after a comma. 0
+//!   for scalars.
+//! - `max_depth`: deepest container nesting seen; the root container is
+//!   depth 1, a scalar root depth 0.
+//! - `scanned`: bytes looked at, up to the root's closing bracket.
+//! - `flags`: `PROBE_FLAG_*`.
+
+use crate::diff::RootKind;
+use crate::simd_index::quote_is_escaped;
+
+/// A string seen in the prefix holds a backslash escape.
+pub const PROBE_FLAG_ESCAPES: u8 = 1 << 0;
+/// The prefix holds bytes outside ASCII.
+pub const PROBE_FLAG_NON_ASCII: u8 = 1 << 1;
+/// The prefix ends inside a string.
+pub const PROBE_FLAG_OPEN_STRING: u8 = 1 << 2;
+/// The root container closes within the prefix, so the counts are exact.
+pub const PROBE_FLAG_COMPLETE: u8 = 1 << 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub root: RootKind,
+    pub flags: u8,
+    pub top_level_items: u32,
+    pub max_depth: u32,
+    pub scanned: u32,
+}
+
+impl Probe {
+    pub const SIZE: usize = 16;
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let mut buf = [0u8; Self::SIZE];
+        buf[0] = self.root as u8;
+        buf[1] = self.flags;
+        buf[4..8].copy_from_slice(&self.top_level_items.to_le_bytes());
+        buf[8..12].copy_from_slice(&self.max_depth.to_le_bytes());
+        buf[12..16].copy_from_slice(&self.scanned.to_le_bytes());
+        buf
+    }
+}
+
+pub fn probe(prefix: &[u8]) -> Probe {
+    let len = u32::try_from(prefix.len()).unwrap_or(u32::MAX);
+    let mut p = Probe { root: RootKind::Missing, flags: 0, top_level_items: 0, max_depth: 0, scanned: len };
+    if !prefix.is_ascii() { p.flags |= PROBE_FLAG_NON_ASCII; }
+    let Some(first) = prefix.iter().position(|b| !b.is_ascii_whitespace()) else { return p };
+    p.root = match prefix[first] {
+        b'{' => RootKind::Object,
+        b'[' => RootKind::Array,
+        _ => RootKind::Scalar,
+    };
+    if p.root == RootKind::Scalar && prefix[first] != b'"' { return p; }
+
+    let mut index = crate::index_pool::checkout(None);
+    index.build(prefix);
+    let mut cursor = index.cursor();
+    let mut depth = 0u32;
+    let mut commas = 0u32;
+    while let Some(pos) = cursor.next() {
+        match prefix[pos] {
+            b'"' => {
+                let start = pos + 1;
+                let close = cursor.by_ref().find(|&q| prefix[q] == b'"' && !quote_is_escaped(prefix, q, start));
+                if prefix[start..close.unwrap_or(prefix.len())].contains(&b'\\') {
+                    p.flags |= PROBE_FLAG_ESCAPES;
+                }
+                if close.is_none() {
+                    p.flags |= PROBE_FLAG_OPEN_STRING;
+                    break;
+                }
+                if depth == 0 { break; } // a string root
+            }
+            b'{' | b'[' => {
+                depth += 1;
+                p.max_depth = p.max_depth.max(depth);
+            }
+            b'}' | b']' => {
+                depth = depth.saturating_sub(1);
+                if depth == 0 {
+                    p.flags |= PROBE_FLAG_COMPLETE;
+                    p.scanned = pos as u32 + 1;
+                    break;
+                }
+            }
+            b',' if depth == 1 => commas += 1,
+            _ => {}
+        }
+    }
+
+    if p.root != RootKind::Scalar {
+        let body = prefix[first + 1..].iter().find(|b| !b.is_ascii_whitespace());
+        let empty = matches!(body, None | Some(b'}' | b']'));
+        p.top_level_items = if empty { 0 } else { commas + 1 };
+    }
+    p
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::ComputeMode;
+    use crate::gen::{generate, GenConfig, Node};
+    use crate::parser::{CompactEvent, CompactParser};
+    use crate::simd_index::StructuralIndex;
+
+    #[test]
+    fn object_array_and_scalar_roots() {
+        let object = probe(br#" {"a":[1,{"b":2}],"c":"x,y]","d":{}} trailing"#);
+        assert_eq!(
+            object,
+            Probe { root: RootKind::Object, flags: PROBE_FLAG_COMPLETE, top_level_items: 3, max_depth: 3, scanned: 36 }
+        );
+        let array = probe(b"[[1,2],[3],\"\\u00e9\",\"\xc3\xa9\"");
+        assert_eq!((array.root, array.top_level_items, array.max_depth), (RootKind::Array, 4, 2));
+        assert_eq!(array.flags, PROBE_FLAG_ESCAPES | PROBE_FLAG_NON_ASCII, "cut off after the last element");
+
+        assert_eq!(probe(b"[ ]").top_level_items, 0);
+        assert_eq!(probe(b"{").top_level_items, 0);
+        assert_eq!(probe(b"  \n").root, RootKind::Missing);
+        assert_eq!(probe(b"12.5").root, RootKind::Scalar);
+        let string = probe(br#""a\"b" junk"#);
+        assert_eq!((string.root, string.flags, string.max_depth), (RootKind::Scalar, PROBE_FLAG_ESCAPES, 0));
+        assert_eq!(object.to_bytes(), [1, 8, 0, 0, 3, 0, 0, 0, 3, 0, 0, 0, 36, 0, 0, 0]);
+    }
+
+    #[test]
+    fn cut_mid_string_hides_its_brackets() {
+        // The cut string holds brackets and commas; none of them count.
+        let p = probe(br#"{"a":1,"b":"x\"],[{,"#);
+        assert_eq!(p.flags, PROBE_FLAG_OPEN_STRING | PROBE_FLAG_ESCAPES);
+        assert_eq!((p.top_level_items, p.max_depth, p.scanned), (2, 1, 20));
+        let key = probe(br#"[{"k,["#);
+        assert_eq!((key.top_level_items, key.max_depth, key.flags), (1, 2, PROBE_FLAG_OPEN_STRING));
+    }
+
+    fn parsed_depth(json: &[u8]) -> u32 {
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+        let mut parser = CompactParser::new(100_000, ComputeMode::Latency);
+        parser.parse_with_index(json, &idx).unwrap();
+        let (mut depth, mut max) = (0u32, 0u32);
+        for t in parser.tokens() {
+            match t.event {
+                CompactEvent::StartObject | CompactEvent::StartArray => {
+                    depth += 1;
+                    max = max.max(depth);
+                }
+                CompactEvent::EndObject | CompactEvent::EndArray => depth -= 1,
+                _ => {}
+            }
+        }
+        max
+    }
+
+    #[test]
+    fn depth_and_counts_agree_with_full_parses() {
+        for seed in 0..100 {
+            let doc = generate(&GenConfig::adversarial(), seed);
+            let json = doc.to_json().into_bytes();
+            let full = probe(&json);
+            assert_eq!(full.max_depth, parsed_depth(&json), "seed {}", seed);
+            let items = match &doc {
+                Node::Object(members) => members.len(),
+                Node::Array(items) => items.len(),
+                _ => 0,
+            };
+            assert_eq!(full.top_level_items as usize, items, "seed {}", seed);
+            if full.root != RootKind::Scalar {
+                assert_eq!(full.flags & PROBE_FLAG_COMPLETE, PROBE_FLAG_COMPLETE);
+            }
+
+            // A prefix never sees deeper or more than the whole document.
+            for n in [json.len() / 3, json.len() / 2] {
+                let prefix = &json[..n.max(1)];
+                let part = probe(prefix);
+                assert_eq!(part.root, full.root, "seed {}", seed);
+                assert!(part.max_depth <= full.max_depth && part.top_level_items <= full.top_level_items + 1);
+            }
+        }
+    }
+}
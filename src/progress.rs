@@ -0,0 +1,86 @@
+//! Host-visible progress for long commits and finalizes.
+//!
+//! Each engine keeps one `u32` word, `phase << 16 | permille`, read with
+//! `get_progress`. A host that shares the module's memory with a worker
+//! can poll it while a commit or finalize runs there.
+//!
+//! Within one call the word only grows: a commit runs `Index` then
+//! `Parse`, a finalize runs `Diff` then `Write`, and each phase counts
+//! from 0 up to 1000. The next call starts over at its first phase;
+//! `clear_engine` resets the word to 0 (`Idle`).
+//!
+//! The loops report once per stride (`STRIDE_BYTES` of input or
+//! `STRIDE_TOKENS` tokens/entries): one relaxed store, no fences.
+
+use core::sync::atomic::{AtomicU32, Ordering};
+
+/// Input bytes between reports in the index and parse loops.
+pub const STRIDE_BYTES: usize = 64 * 1024;
+/// Tokens (or entries) between reports in the diff and write loops.
+pub const STRIDE_TOKENS: usize = 8 * 1024;
+
+/// Phase in the high half of the progress word.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u32)]
+pub enum Phase {
+    Idle = 0,
+    Index = 1,
+    Parse = 2,
+    Diff = 3,
+    Write = 4,
+}
+
+/// Pack a progress word.
+pub fn word(phase: Phase, permille: u32) -> u32 {
+    (phase as u32) << 16 | permille
+}
+
+pub struct Progress {
+    word: AtomicU32,
+    /// Every stored word, so tests can check the sequence.
+    #[cfg(test)]
+    trace: core::cell::RefCell<alloc::vec::Vec<u32>>,
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Self {
+            word: AtomicU32::new(0),
+            #[cfg(test)]
+            trace: Default::default(),
+        }
+    }
+
+    /// Record `done` of `total` units of `phase`.
+    #[inline]
+    pub fn report(&self, phase: Phase, done: usize, total: usize) {
+        let permille = (done as u64 * 1000 / total.max(1) as u64).min(1000) as u32;
+        self.store(word(phase, permille));
+    }
+
+    /// Mark `phase` complete.
+    pub fn finish(&self, phase: Phase) {
+        self.store(word(phase, 1000));
+    }
+
+    pub fn reset(&self) {
+        self.store(word(Phase::Idle, 0));
+    }
+
+    pub fn load(&self) -> u32 {
+        self.word.load(Ordering::Relaxed)
+    }
+
+    #[inline(always)]
+    fn store(&self, word: u32) {
+        self.word.store(word, Ordering::Relaxed);
+        #[cfg(test)]
+        self.trace.borrow_mut().push(word);
+    }
+
+    /// Take the words stored since the last call.
+    #[cfg(test)]
+    pub fn take_trace(&self) -> alloc::vec::Vec<u32> {
+        self.trace.take()
+    }
+}
@@ -0,0 +1,154 @@
+//! `top_k`: keep only the most significant entries.
+//!
+//! Each entry gets a `u64` score from the configured [`Significance`]:
+//!
+//! - `ByteDelta`: `|right_len - left_len|` over the full value spans (an
+//!   absent side counts as 0), so Added and Removed score their size.
+//! - `SubtreeChangeCount`: entries whose path lies under this entry's
+//!   parent container, this entry included. A root entry counts every
+//!   entry.
+//! - `PathDepthInverse`: `2^32 / (depth + 1)`, depth being the number of
+//!   pointer segments (the root is 0).
+//!
+//! A `k`-entry min-heap keeps the winners while scanning; they come out
+//! by descending score, ties by rendered path bytes, then engine order,
+//! so the selection is deterministic.
+//!
+//! Selection runs on every entry the diff produced (after suppressions)
+//! and replaces `entry_order`. `max_entry_bytes` applies to the winners
+//! afterwards, so an oversized winner is skipped or truncated like any
+//! other entry rather than making room for a loser.
+
+use crate::config::Significance;
+use crate::diff::DiffEntry;
+use crate::FxHashMap;
+use alloc::collections::BinaryHeap;
+use alloc::vec::Vec;
+use core::cmp::Reverse;
+
+/// Heap key: larger is more significant.
+type Key = (u64, Reverse<Vec<u8>>, Reverse<usize>);
+
+/// The `k` most significant of `entries`, most significant first.
+/// `path` renders an entry's JSON Pointer.
+pub fn top_k(
+    entries: Vec<DiffEntry>,
+    k: usize,
+    metric: Significance,
+    path: impl Fn(&DiffEntry) -> Vec<u8>,
+) -> Vec<DiffEntry> {
+    let paths: Vec<Vec<u8>> = entries.iter().map(&path).collect();
+    let subtree = match metric {
+        Significance::SubtreeChangeCount => subtree_counts(&paths),
+        _ => FxHashMap::default(),
+    };
+
+    let mut heap: BinaryHeap<Reverse<Key>> = BinaryHeap::with_capacity(k.min(entries.len()) + 1);
+    for (i, (e, p)) in entries.iter().zip(&paths).enumerate() {
+        let score = match metric {
+            Significance::ByteDelta => {
+                let len = |v: Option<crate::diff::ValueSpan>| v.map_or(0, |(_, len, _)| len as u64);
+                len(e.left_val).abs_diff(len(e.right_val))
+            }
+            Significance::SubtreeChangeCount => subtree[parent(p)],
+            Significance::PathDepthInverse => (1u64 << 32) / (depth(p) + 1),
+        };
+        heap.push(Reverse((score, Reverse(p.clone()), Reverse(i))));
+        if heap.len() > k {
+            heap.pop();
+        }
+    }
+
+    let mut winners: Vec<Key> = heap.into_iter().map(|Reverse(key)| key).collect();
+    winners.sort_unstable_by(|a, b| b.cmp(a));
+    let mut slots: Vec<Option<DiffEntry>> = entries.into_iter().map(Some).collect();
+    winners.into_iter().filter_map(|(_, _, Reverse(i))| slots[i].take()).collect()
+}
+
+/// Entries at or under each pointer prefix that is a container path
+/// (every proper prefix ending before a `/`, plus the root).
+fn subtree_counts(paths: &[Vec<u8>]) -> FxHashMap<&[u8], u64> {
+    let mut counts: FxHashMap<&[u8], u64> = FxHashMap::default();
+    for p in paths {
+        for (i, &b) in p.iter().enumerate() {
+            if b == b'/' {
+                *counts.entry(&p[..i]).or_insert(0) += 1;
+            }
+        }
+        // A root entry has no `/` but still sits in the root subtree.
+        if p.is_empty() {
+            *counts.entry(&p[..]).or_insert(0) += 1;
+        }
+    }
+    counts
+}
+
+/// The pointer of the container holding `path`; the root for the root.
+fn parent(path: &[u8]) -> &[u8] {
+    &path[..path.iter().rposition(|&b| b == b'/').unwrap_or(0)]
+}
+
+fn depth(path: &[u8]) -> u64 {
+    path.iter().filter(|&&b| b == b'/').count() as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::DiffOp;
+    use crate::parser::ValueKind;
+    use crate::path::PathId;
+
+    /// An entry with the given value lengths; `path_id` indexes `PATHS`.
+    fn entry(id: u64, left: Option<u32>, right: Option<u32>) -> DiffEntry {
+        let op = match (left, right) {
+            (None, _) => DiffOp::Added,
+            (_, None) => DiffOp::Removed,
+            _ => DiffOp::Modified,
+        };
+        let span = |len: Option<u32>| len.map(|l| (0, l, ValueKind::Literal));
+        DiffEntry { op, path_id: PathId(id), left_val: span(left), right_val: span(right) }
+    }
+
+    const PATHS: [&str; 6] = ["/a/x", "/a/y", "/b", "/c/d/e", "/a/z", "/f"];
+
+    fn rank(entries: Vec<DiffEntry>, k: usize, metric: Significance) -> Vec<&'static str> {
+        top_k(entries, k, metric, |e| PATHS[e.path_id.0 as usize].as_bytes().to_vec())
+            .iter()
+            .map(|e| PATHS[e.path_id.0 as usize])
+            .collect()
+    }
+
+    fn fixture() -> Vec<DiffEntry> {
+        alloc::vec![
+            entry(0, Some(3), Some(10)),  // delta 7
+            entry(1, Some(2), Some(2)),   // delta 0
+            entry(2, None, Some(7)),      // delta 7
+            entry(3, Some(40), None),     // delta 40
+            entry(4, Some(1), Some(5)),   // delta 4
+            entry(5, Some(9), Some(1)),   // delta 8
+        ]
+    }
+
+    #[test]
+    fn byte_delta_top_three_with_path_tiebreak() {
+        // /a/x and /b tie at 7; /a/x sorts first bytewise and wins.
+        assert_eq!(rank(fixture(), 3, Significance::ByteDelta), ["/c/d/e", "/f", "/a/x"]);
+        assert_eq!(rank(fixture(), 4, Significance::ByteDelta), ["/c/d/e", "/f", "/a/x", "/b"]);
+    }
+
+    #[test]
+    fn subtree_and_depth_metrics() {
+        // `/a` holds three changes; everything else sits in the root's six.
+        assert_eq!(rank(fixture(), 3, Significance::SubtreeChangeCount), ["/b", "/f", "/a/x"]);
+        assert_eq!(rank(fixture(), 2, Significance::PathDepthInverse), ["/b", "/f"]);
+        assert_eq!(rank(fixture(), 6, Significance::PathDepthInverse).last(), Some(&"/c/d/e"));
+    }
+
+    #[test]
+    fn k_beyond_len_keeps_everything_ranked() {
+        let ranked = rank(fixture(), 100, Significance::ByteDelta);
+        assert_eq!(ranked, ["/c/d/e", "/f", "/a/x", "/b", "/a/z", "/a/y"]);
+        assert!(rank(Vec::new(), 3, Significance::ByteDelta).is_empty());
+    }
+}
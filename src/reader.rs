@@ -0,0 +1,548 @@
+//! Reader for result buffers, tolerant of newer minor versions.
+//!
+//! Anything this reader does not know is stepped over rather than
+//! rejected: unknown header flags are ignored, entry extensions are skipped
+//! by their length, and sections with unknown ids stay readable by id and
+//! are listed by [`ResultReader::unknown_sections`]. Only a different
+//! major version or a buffer that contradicts its own lengths fails.
+//! Layout details are in `memory.rs`.
+//!
+//! Decoding goes through `half`, `word` and `dword` (`from_le_bytes` on
+//! byte slices) only, so it is alignment- and host-endianness-independent.
+//!
+//! With the `serde` feature, [`ResultReader::decode`] copies a result out
+//! into owned, serializable [`DecodedDiff`] entries (`decoded.rs`).
+
+use crate::audit::AuditRecord;
+use crate::digest::{Digest, DIGEST_LEN};
+use crate::memory::{
+    ENTRY_FLAG_DELTA_INEXACT, ENTRY_FLAG_FORMATTING, ENTRY_FLAG_LEFT_INVALID_UTF8, ENTRY_FLAG_NUMERIC_DELTA, ENTRY_FLAG_OVERSIZED_PRIMITIVE, ENTRY_FLAG_REDACTED, ENTRY_FLAG_RIGHT_INVALID_UTF8, ENTRY_SIZE, FORMAT_VERSION_MAJOR,
+    HEADER_FLAG_ENTRY_EXT, HEADER_FLAG_SECTIONS, SECTION_ANNOTATIONS, SECTION_AUDIT_LOG, SECTION_CANARY, SECTION_COVERAGE, SECTION_DOCUMENT, SECTION_ENTRY_SEQUENCE, SECTION_INPUT_DIGESTS, SECTION_NUMERIC_DELTAS,
+    SECTION_PATCH, SECTION_PATHS, SECTION_ROW, SECTION_VALUE_HASHES, SECTION_VALUE_LENGTHS,
+};
+use alloc::vec::Vec;
+use core::fmt;
+
+#[cfg(feature = "serde")]
+pub use crate::decoded::{DecodedDiff, DecodedEntry, DecodedOp, DecodedValue};
+
+const HEADER: usize = 16;
+
+// Offsets below assume the v2 layout; fail the build if it moves.
+const _: () = assert!(ENTRY_SIZE == 32 && SECTION_ROW == 12 && HEADER.is_multiple_of(8));
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// Shorter than the 16-byte header or its own total length.
+    Truncated,
+    /// Written by an incompatible major version.
+    UnsupportedMajor(u16),
+    /// An entry, extension or section points outside the buffer.
+    OutOfBounds,
+}
+
+impl fmt::Display for ReadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReadError::Truncated => write!(f, "result truncated"),
+            ReadError::UnsupportedMajor(v) => write!(f, "unsupported result major version {}", v),
+            ReadError::OutOfBounds => write!(f, "result entry or section out of bounds"),
+        }
+    }
+}
+
+/// The canary's side of one entry in a `three_way` result. `canary` is
+/// `(offset, len)` into the third input; `canary_op` is 0xFF where the
+/// canary matches the left.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanaryRecord {
+    /// 0 both rights agree, 1 canary only, 2 both differ differently,
+    /// 3 control only.
+    pub agreement: u8,
+    pub canary_op: u8,
+    pub canary_kind: u8,
+    pub canary: (u32, u32),
+}
+
+/// One section table row. `offset` is from the start of the buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SectionRef {
+    pub id: u32,
+    pub offset: u32,
+    pub len: u32,
+}
+
+/// The core fields of one entry; extensions are exposed raw.
+///
+/// `left` and `right` are `(offset, len)` references into the inputs, not
+/// copies: the result is only meaningful alongside the exact bytes that
+/// were committed. Resolve them with [`RawEntry::values`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawEntry<'a> {
+    pub op: u8,
+    pub left_kind: u8,
+    pub right_kind: u8,
+    pub flags: u8,
+    pub path_id: u64,
+    pub left: (u32, u32),
+    pub right: (u32, u32),
+    /// Extension bytes after the core (empty unless `HEADER_FLAG_ENTRY_EXT`).
+    pub ext: &'a [u8],
+}
+
+impl RawEntry<'_> {
+    /// The left and right value bytes, sliced from the inputs the result
+    /// was computed from. A side is `None` when its kind is 0 (absent).
+    ///
+    /// Fails with `OutOfBounds` if a reference does not fit its input,
+    /// e.g. the engine was cleared and the buffers reused. A reference that
+    /// fits but into different bytes cannot be detected; keep the inputs
+    /// with the result (or check the input digests section).
+    pub fn values<'b>(&self, left: &'b [u8], right: &'b [u8]) -> Result<[Option<&'b [u8]>; 2], ReadError> {
+        let slice = |kind: u8, (offset, len): (u32, u32), input: &'b [u8]| {
+            if kind == 0 {
+                return Ok(None);
+            }
+            let start = offset as usize;
+            let end = start.checked_add(len as usize).filter(|&end| end <= input.len());
+            end.map(|end| Some(&input[start..end])).ok_or(ReadError::OutOfBounds)
+        };
+        Ok([slice(self.left_kind, self.left, left)?, slice(self.right_kind, self.right, right)?])
+    }
+
+    /// Whether the left payload is valid UTF-8, so it can go to a string
+    /// decoder as is. Only results from 2.6 on carry this (see
+    /// `ResultReader::version`); older ones always report `true`.
+    pub fn left_utf8(&self) -> bool { self.flags & ENTRY_FLAG_LEFT_INVALID_UTF8 == 0 }
+
+    /// [`RawEntry::left_utf8`] for the right payload.
+    pub fn right_utf8(&self) -> bool { self.flags & ENTRY_FLAG_RIGHT_INVALID_UTF8 == 0 }
+
+    /// Whether the numeric delta section holds this entry's delta.
+    pub fn has_numeric_delta(&self) -> bool { self.flags & ENTRY_FLAG_NUMERIC_DELTA != 0 }
+
+    /// Both sides are numbers, but an integer too large for `f64` left
+    /// the entry without a delta.
+    pub fn delta_inexact(&self) -> bool { self.flags & ENTRY_FLAG_DELTA_INEXACT != 0 }
+
+    /// Whether the entry transform redacted the values: each present side
+    /// is an empty string span, not the changed value.
+    pub fn redacted(&self) -> bool { self.flags & ENTRY_FLAG_REDACTED != 0 }
+
+    /// Whether the sides are one value written two ways (a lint entry).
+    pub fn formatting_only(&self) -> bool { self.flags & ENTRY_FLAG_FORMATTING != 0 }
+
+    /// Whether a side is a primitive over `max_primitive_bytes`, cut to
+    /// it; `ResultReader::value_lengths` has the full lengths.
+    pub fn oversized_primitive(&self) -> bool { self.flags & ENTRY_FLAG_OVERSIZED_PRIMITIVE != 0 }
+}
+
+pub struct ResultReader<'a> {
+    buf: &'a [u8],
+    minor: u16,
+    flags: u32,
+    /// Byte offset of each entry's core.
+    entries: Vec<usize>,
+    sections: Vec<SectionRef>,
+}
+
+impl<'a> ResultReader<'a> {
+    pub fn parse(buf: &'a [u8]) -> Result<Self, ReadError> {
+        if buf.len() < HEADER {
+            return Err(ReadError::Truncated);
+        }
+        let major = half(buf, 0);
+        if major != FORMAT_VERSION_MAJOR {
+            return Err(ReadError::UnsupportedMajor(major));
+        }
+        let minor = half(buf, 2);
+        let count = word(buf, 4) as usize;
+        let total = word(buf, 8) as usize;
+        let flags = word(buf, 12);
+        if total < HEADER || total > buf.len() {
+            return Err(ReadError::Truncated);
+        }
+        let buf = &buf[..total];
+
+        let mut entries = Vec::with_capacity(count.min(total / ENTRY_SIZE));
+        let mut at = HEADER;
+        for _ in 0..count {
+            if at + ENTRY_SIZE > total {
+                return Err(ReadError::OutOfBounds);
+            }
+            entries.push(at);
+            let ext = if flags & HEADER_FLAG_ENTRY_EXT != 0 { word(buf, at + 4) as usize } else { 0 };
+            at = (at + ENTRY_SIZE)
+                .checked_add(ext)
+                .filter(|&end| end <= total)
+                .ok_or(ReadError::OutOfBounds)?;
+        }
+
+        let sections = if flags & HEADER_FLAG_SECTIONS != 0 { section_table(buf, at)? } else { Vec::new() };
+        Ok(Self { buf, minor, flags, entries, sections })
+    }
+
+    pub fn version(&self) -> (u16, u16) { (FORMAT_VERSION_MAJOR, self.minor) }
+    /// Raw header flags, including bits this reader does not know.
+    pub fn flags(&self) -> u32 { self.flags }
+    pub fn entry_count(&self) -> usize { self.entries.len() }
+
+    pub fn entries(&self) -> impl Iterator<Item = RawEntry<'a>> + '_ {
+        let buf = self.buf;
+        let ext = self.flags & HEADER_FLAG_ENTRY_EXT != 0;
+        self.entries.iter().map(move |&at| {
+            let ext_len = if ext { word(buf, at + 4) as usize } else { 0 };
+            RawEntry {
+                op: buf[at],
+                left_kind: buf[at + 1],
+                right_kind: buf[at + 2],
+                flags: buf[at + 3],
+                path_id: dword(buf, at + 8),
+                left: (word(buf, at + 16), word(buf, at + 20)),
+                right: (word(buf, at + 24), word(buf, at + 28)),
+                ext: &buf[at + ENTRY_SIZE..at + ENTRY_SIZE + ext_len],
+            }
+        })
+    }
+
+    pub fn sections(&self) -> &[SectionRef] { &self.sections }
+
+    /// Body of the first section with `id`.
+    pub fn section(&self, id: u32) -> Option<&'a [u8]> {
+        let s = self.sections.iter().find(|s| s.id == id)?;
+        Some(&self.buf[s.offset as usize..s.offset as usize + s.len as usize])
+    }
+
+    /// Sections this reader has no decoder for, for diagnostics.
+    pub fn unknown_sections(&self) -> impl Iterator<Item = &SectionRef> + '_ {
+        self.sections.iter().filter(|s| !matches!(s.id, SECTION_COVERAGE | SECTION_INPUT_DIGESTS | SECTION_VALUE_HASHES | SECTION_AUDIT_LOG | SECTION_CANARY | SECTION_NUMERIC_DELTAS | SECTION_PATHS | SECTION_ANNOTATIONS | SECTION_ENTRY_SEQUENCE | SECTION_VALUE_LENGTHS | SECTION_PATCH | SECTION_DOCUMENT))
+    }
+
+    /// Left coverage ranges, if the result has the section.
+    pub fn coverage(&self) -> Option<Vec<[u32; 2]>> {
+        let body = self.section(SECTION_COVERAGE)?;
+        Some(body.chunks_exact(8).map(|r| [word(r, 0), word(r, 4)]).collect())
+    }
+
+    /// Per-entry right value hashes, if the result has the section.
+    pub fn value_hashes(&self) -> Option<Vec<u64>> {
+        let body = self.section(SECTION_VALUE_HASHES)?;
+        Some(body.chunks_exact(8).map(|r| dword(r, 0)).collect())
+    }
+
+    /// Per-entry numeric deltas (right minus left), in entry order, if the
+    /// result has the section: `None` for entries without one.
+    pub fn numeric_deltas(&self) -> Option<Vec<Option<f64>>> {
+        let body = self.section(SECTION_NUMERIC_DELTAS)?;
+        let deltas = self.entries().zip(body.chunks_exact(8));
+        Some(deltas.map(|(e, r)| e.has_numeric_delta().then(|| f64::from_bits(dword(r, 0)))).collect())
+    }
+
+    /// Per-entry entry transform annotations, in entry order, if the
+    /// result has the section.
+    pub fn annotations(&self) -> Option<Vec<u32>> {
+        let body = self.section(SECTION_ANNOTATIONS)?;
+        Some(body.chunks_exact(4).map(|r| word(r, 0)).collect())
+    }
+
+    /// Per-entry sequence numbers, in entry order, if the result has the
+    /// section (`entry_sequence`).
+    pub fn entry_sequence(&self) -> Option<Vec<u32>> {
+        let body = self.section(SECTION_ENTRY_SEQUENCE)?;
+        Some(body.chunks_exact(4).map(|r| word(r, 0)).collect())
+    }
+
+    /// Per-entry `(left, right)` value lengths before any cut, in entry
+    /// order, if the result has the section (an oversized primitive).
+    pub fn value_lengths(&self) -> Option<Vec<(u32, u32)>> {
+        let body = self.section(SECTION_VALUE_LENGTHS)?;
+        Some(body.chunks_exact(8).map(|r| (word(r, 0), word(r, 4))).collect())
+    }
+
+    /// The patch records (`self_contained`), for `apply_patch`.
+    pub fn patch(&self) -> Option<&'a [u8]> { self.section(SECTION_PATCH) }
+
+    /// The rebuilt document of an `apply_patch` result.
+    pub fn document(&self) -> Option<&'a [u8]> { self.section(SECTION_DOCUMENT) }
+
+    /// Per-entry JSON Pointers, in entry order, if the result has the path
+    /// section. Each record extends the previous path's first `shared`
+    /// bytes, so one pass rebuilds them all. `None` as well when a record
+    /// does not fit the section or shares more than the previous path has.
+    pub fn paths(&self) -> Option<Vec<Vec<u8>>> {
+        let body = self.section(SECTION_PATHS)?;
+        let mut paths: Vec<Vec<u8>> = Vec::with_capacity(self.entries.len());
+        let mut at = 0usize;
+        while paths.len() < self.entries.len() {
+            let head = body.get(at..at.checked_add(8)?)?;
+            let (shared, len) = (word(head, 0) as usize, word(head, 4) as usize);
+            let suffix = body.get(at + 8..(at + 8).checked_add(len)?)?;
+            let prev = paths.last().map_or(&[][..], |p| p.as_slice());
+            let path = [prev.get(..shared)?, suffix].concat();
+            paths.push(path);
+            at += 8 + len;
+        }
+        Some(paths)
+    }
+
+    /// Per-entry canary sides of a `three_way` result, in entry order, if
+    /// the result has the section.
+    pub fn canary(&self) -> Option<Vec<CanaryRecord>> {
+        let body = self.section(SECTION_CANARY)?;
+        Some(
+            body.chunks_exact(16)
+                .map(|r| CanaryRecord { agreement: r[0], canary_op: r[1], canary_kind: r[2], canary: (word(r, 8), word(r, 12)) })
+                .collect(),
+        )
+    }
+
+    /// Audit records of the calls before finalize, oldest first, if the
+    /// result has the section.
+    pub fn audit_log(&self) -> Option<Vec<AuditRecord>> {
+        let body = self.section(SECTION_AUDIT_LOG)?;
+        Some(body.chunks_exact(AuditRecord::SIZE).map(AuditRecord::from_bytes).collect())
+    }
+
+    /// Left then right input digest, if the result has the section.
+    pub fn input_digests(&self) -> Option<[Digest; 2]> {
+        let body = self.section(SECTION_INPUT_DIGESTS)?;
+        if body.len() < 2 * DIGEST_LEN {
+            return None;
+        }
+        Some([body[..DIGEST_LEN].try_into().unwrap(), body[DIGEST_LEN..2 * DIGEST_LEN].try_into().unwrap()])
+    }
+}
+
+fn half(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes(buf[at..at + 2].try_into().unwrap())
+}
+
+fn word(buf: &[u8], at: usize) -> u32 {
+    u32::from_le_bytes(buf[at..at + 4].try_into().unwrap())
+}
+
+fn dword(buf: &[u8], at: usize) -> u64 {
+    u64::from_le_bytes(buf[at..at + 8].try_into().unwrap())
+}
+
+fn section_table(buf: &[u8], at: usize) -> Result<Vec<SectionRef>, ReadError> {
+    if at + 8 > buf.len() {
+        return Err(ReadError::OutOfBounds);
+    }
+    let count = word(buf, at) as usize;
+    let rows = at + 8;
+    if count > (buf.len() - rows) / SECTION_ROW {
+        return Err(ReadError::OutOfBounds);
+    }
+    (0..count)
+        .map(|i| {
+            let row = rows + i * SECTION_ROW;
+            let s = SectionRef { id: word(buf, row), offset: word(buf, row + 4), len: word(buf, row + 8) };
+            match (s.offset as usize).checked_add(s.len as usize) {
+                Some(end) if end <= buf.len() => Ok(s),
+                _ => Err(ReadError::OutOfBounds),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::FORMAT_VERSION_MINOR;
+
+    /// A result with two core entries, built by hand.
+    fn header(minor: u16, count: u32, flags: u32) -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(&FORMAT_VERSION_MAJOR.to_le_bytes());
+        b.extend_from_slice(&minor.to_le_bytes());
+        b.extend_from_slice(&count.to_le_bytes());
+        b.extend_from_slice(&0u32.to_le_bytes());
+        b.extend_from_slice(&flags.to_le_bytes());
+        b
+    }
+
+    fn entry(op: u8, path: u64, ext: &[u8]) -> Vec<u8> {
+        let mut e = vec![0u8; ENTRY_SIZE];
+        e[0] = op;
+        e[4..8].copy_from_slice(&(ext.len() as u32).to_le_bytes());
+        e[8..16].copy_from_slice(&path.to_le_bytes());
+        e[16..20].copy_from_slice(&7u32.to_le_bytes());
+        e.extend_from_slice(ext);
+        e
+    }
+
+    fn seal(mut b: Vec<u8>) -> Vec<u8> {
+        let len = b.len() as u32;
+        b[8..12].copy_from_slice(&len.to_le_bytes());
+        b
+    }
+
+    fn core(r: &ResultReader) -> Vec<(u8, u64, (u32, u32))> {
+        r.entries().map(|e| (e.op, e.path_id, e.left)).collect()
+    }
+
+    #[test]
+    fn future_flags_extensions_and_sections_are_skipped() {
+        // A writer from minor +3 with an unknown header flag, entry
+        // extensions, and a section table holding an unknown id 99 between
+        // two known ones.
+        let flags = HEADER_FLAG_SECTIONS | HEADER_FLAG_ENTRY_EXT | 1 << 20;
+        let mut b = header(FORMAT_VERSION_MINOR + 3, 2, flags);
+        b.extend(entry(2, 0xAA, &[1; 16]));
+        b.extend(entry(0, 0xBB, &[]));
+        let table = b.len();
+        let bodies = table + 8 + 3 * SECTION_ROW + 4;
+        b.extend_from_slice(&3u32.to_le_bytes());
+        b.extend_from_slice(&0u32.to_le_bytes());
+        let rows = [(SECTION_COVERAGE, bodies, 8), (99, bodies + 8, 24), (SECTION_INPUT_DIGESTS, bodies + 32, 32)];
+        for (id, off, len) in rows {
+            for w in [id, off as u32, len] {
+                b.extend_from_slice(&w.to_le_bytes());
+            }
+        }
+        b.resize(bodies, 0);
+        b.extend_from_slice(&3u32.to_le_bytes());
+        b.extend_from_slice(&9u32.to_le_bytes());
+        b.extend_from_slice(&[0xEE; 24]);
+        b.extend_from_slice(&[5; 32]);
+        let b = seal(b);
+
+        let r = ResultReader::parse(&b).unwrap();
+        assert_eq!(core(&r), [(2, 0xAA, (7, 0)), (0, 0xBB, (7, 0))]);
+        assert_eq!(r.entries().next().unwrap().ext, &[1; 16]);
+        assert_eq!(r.coverage(), Some(vec![[3, 9]]));
+        assert_eq!(r.input_digests(), Some([[5; 16]; 2]));
+        let unknown: Vec<_> = r.unknown_sections().map(|s| (s.id, s.len)).collect();
+        assert_eq!(unknown, [(99, 24)]);
+        assert_eq!(r.section(99), Some(&[0xEE; 24][..]));
+    }
+
+    #[test]
+    fn rejects_other_majors_and_lying_lengths() {
+        let mut b = seal(header(0, 0, 0));
+        assert!(ResultReader::parse(&b).is_ok());
+        b[0] = 2;
+        assert_eq!(ResultReader::parse(&b).err(), Some(ReadError::UnsupportedMajor(2)));
+
+        // Claims an entry the buffer doesn't hold.
+        let b = seal(header(FORMAT_VERSION_MINOR, 1, 0));
+        assert_eq!(ResultReader::parse(&b).err(), Some(ReadError::OutOfBounds));
+
+        // Extension longer than the buffer.
+        let mut b = header(FORMAT_VERSION_MINOR, 1, HEADER_FLAG_ENTRY_EXT);
+        b.extend(entry(0, 1, &[]));
+        b[16 + 4] = 200;
+        assert_eq!(ResultReader::parse(&seal(b)).err(), Some(ReadError::OutOfBounds));
+
+        assert_eq!(ResultReader::parse(&[0; 8]).err(), Some(ReadError::Truncated));
+    }
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.chunks(16).map(|row| {
+            row.iter().map(|b| format!("{:02x}", b)).collect::<Vec<_>>().join(" ")
+        }).collect::<Vec<_>>().join("\n")
+    }
+
+    /// A small result, byte for byte. Any change to field order, widths,
+    /// padding or byte order fails here first.
+    #[test]
+    fn golden_result_bytes() {
+        use crate::diff::DiffOp;
+        use crate::memory::{ResultArena, HEADER_FLAG_IDENTICAL};
+        use crate::parser::ValueKind;
+        use crate::path::PathId;
+
+        let mut arena = ResultArena::new(1024);
+        let left = Some((0x0102_0304, 5, ValueKind::Literal));
+        let right = Some((0x0a0b_0c0d, 0x100, ValueKind::String));
+        arena.write_entry_v2(DiffOp::Modified, PathId(0x1122_3344_5566_7788), left, right, 5).unwrap();
+        arena.write_entry_v2(DiffOp::Added, PathId(0xff), None, Some((9, 1, ValueKind::Literal)), 0).unwrap();
+        arena.write_sections(&[(SECTION_COVERAGE, &[1, 0, 0, 0, 4, 0, 0, 0])]).unwrap();
+        arena.set_header_flags(HEADER_FLAG_IDENTICAL);
+        arena.seal();
+        let buf = unsafe { core::slice::from_raw_parts(arena.as_ptr(), arena.len() as usize) };
+
+        assert_eq!(hex(buf), GOLDEN);
+
+        let r = ResultReader::parse(buf).unwrap();
+        assert_eq!(r.version(), (3, 0));
+        assert_eq!(r.flags(), HEADER_FLAG_IDENTICAL | HEADER_FLAG_SECTIONS);
+        let e: Vec<_> = r.entries().collect();
+        assert_eq!((e[0].op, e[0].left_kind, e[0].right_kind, e[0].flags), (2, 2, 1, 5));
+        assert_eq!((e[0].left_utf8(), e[0].right_utf8()), (true, false));
+        assert_eq!(e[0].path_id, 0x1122_3344_5566_7788);
+        assert_eq!((e[0].left, e[0].right), ((0x0102_0304, 5), (0x0a0b_0c0d, 0x100)));
+        assert_eq!((e[1].op, e[1].path_id, e[1].right), (0, 0xff, (9, 1)));
+        assert_eq!(r.coverage(), Some(vec![[1, 4]]));
+    }
+
+    /// Header: major 3, minor 0, 2 entries, total 0x70, flags 3.
+    /// Entries: Modified (kinds 2/1, flags 5: truncated, right not
+    /// UTF-8), Added. Section table: one row (coverage at 0x68, 8 bytes),
+    /// 4 bytes padding, the body.
+    const GOLDEN: &str = "\
+03 00 00 00 02 00 00 00 70 00 00 00 03 00 00 00
+02 02 01 05 00 00 00 00 88 77 66 55 44 33 22 11
+04 03 02 01 05 00 00 00 0d 0c 0b 0a 00 01 00 00
+00 00 02 00 00 00 00 00 ff 00 00 00 00 00 00 00
+00 00 00 00 00 00 00 00 09 00 00 00 01 00 00 00
+01 00 00 00 00 00 00 00 01 00 00 00 68 00 00 00
+08 00 00 00 00 00 00 00 01 00 00 00 04 00 00 00";
+
+    #[test]
+    fn arena_buffer_is_8_byte_aligned() {
+        use crate::diff::DiffOp;
+        use crate::memory::ResultArena;
+        use crate::path::PathId;
+
+        let mut arena = ResultArena::new(1 << 20);
+        for i in 0..1000u64 {
+            // Growth reallocates; every allocation must stay aligned.
+            arena.write_entry_v2(DiffOp::Added, PathId(i), None, None, 0).unwrap();
+            assert_eq!(arena.as_ptr() as usize % 8, 0);
+        }
+        arena.write_sections(&[(SECTION_COVERAGE, &[0; 8]), (SECTION_INPUT_DIGESTS, &[0; 3])]).unwrap();
+        arena.seal();
+        let buf = unsafe { core::slice::from_raw_parts(arena.as_ptr(), arena.len() as usize) };
+        let r = ResultReader::parse(buf).unwrap();
+        assert!(r.sections().iter().all(|s| s.offset % 8 == 0));
+        assert_eq!(buf.len() % 8, 0);
+
+        arena.clear();
+        assert_eq!(arena.as_ptr() as usize % 8, 0);
+        assert_eq!(arena.len(), 16);
+    }
+
+    #[test]
+    fn references_resolve_against_the_committed_inputs() {
+        let left = br#"{"a":"x","b":[1,true],"gone":null}"#;
+        let right = br#"{"a":"yy","b":[1,false],"new":""}"#;
+        let mut e = crate::Engine::new(crate::EngineConfig { max_input_size: 1 << 16, ..Default::default() }, 1).unwrap();
+        unsafe {
+            core::ptr::copy_nonoverlapping(left.as_ptr(), e.left_input_ptr(), left.len());
+            core::ptr::copy_nonoverlapping(right.as_ptr(), e.right_input_ptr(), right.len());
+        }
+        e.commit_left(left.len() as u32);
+        e.commit_right(right.len() as u32);
+        let ptr = e.finalize().unwrap();
+        let buf = unsafe { core::slice::from_raw_parts(ptr, e.result_len() as usize) };
+        let r = ResultReader::parse(buf).unwrap();
+
+        let values: Vec<_> = r.entries().map(|en| en.values(left, right).unwrap()).collect();
+        let some = |b: &'static [u8]| Some(b);
+        assert_eq!(values, [
+            [some(b"x"), some(b"yy")],
+            [some(b"true"), some(b"false")],
+            [None, some(b"")],
+            [some(b"null"), None],
+        ]);
+
+        // Against shorter buffers (inputs replaced after a clear) the
+        // references are caught; same-length replacements are not.
+        let first = r.entries().next().unwrap();
+        assert_eq!(first.values(b"{}", right).err(), Some(ReadError::OutOfBounds));
+        let other = br#"{"a":"q","b":[1,true],"gone":null}"#;
+        assert_eq!(first.values(other, right).unwrap()[0], Some(&b"q"[..]));
+    }
+}
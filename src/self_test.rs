@@ -0,0 +1,191 @@
+//! Built-in smoke check of a deployed artifact (`self_test`).
+//!
+//! Operations can ask a running module whether it computes what this
+//! source tree computes, without shipping documents to it: the checks run
+//! on fixtures embedded here (well under 2 KB) and take microseconds.
+//!
+//! - [`SELF_TEST_HASHES`]: value hashes (the SIMD or scalar build of
+//!   `hash_bytes_simd`), path id folds and the input digest against the
+//!   golden values their own tests pin.
+//! - [`SELF_TEST_INDEX`]: the structural index of a canned document
+//!   (escaped quotes, brackets inside strings, more than one 64-byte
+//!   block) against its known positions.
+//! - [`SELF_TEST_DIFF`]: a canned diff against its expected result bytes.
+//! - [`SELF_TEST_FORMAT`]: that result's header against the format version
+//!   the expected bytes were written with.
+//!
+//! `self_test` returns the bits of the failed checks, 0 when all pass,
+//! and the details of a failure go to the creation-error buffer. A format
+//! or hash change must update the fixtures here along with the golden
+//! tests it already breaks.
+
+use crate::config::EngineConfig;
+use crate::digest::digest;
+use crate::memory::{FORMAT_VERSION_MAJOR, FORMAT_VERSION_MINOR};
+use crate::parser::hash_bytes_simd;
+use crate::path::{fold_index_hash, fold_segment_hash, ROOT_PATH_ID};
+use crate::simd_index::StructuralIndex;
+use crate::status::Status;
+use crate::Engine;
+use alloc::format;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+pub const SELF_TEST_HASHES: u32 = 1 << 0;
+pub const SELF_TEST_INDEX: u32 = 1 << 1;
+pub const SELF_TEST_DIFF: u32 = 1 << 2;
+pub const SELF_TEST_FORMAT: u32 = 1 << 3;
+
+/// `hash_bytes_simd` inputs and values, from the parser's golden test:
+/// short (scalar tail only), one block plus a tail, several blocks.
+const VALUE_HASHES: [(&[u8], u64); 3] = [
+    (b"a", 0xaf63_dd4c_8601_ee3f),
+    (b"0123456789abcdefg", 0x5a3e_9df5_0365_addf),
+    (
+        b"The quick brown fox jumps over the lazy dogThe quick brown fox jumps over the lazy dogThe quick brown fox jumps over the lazy dog",
+        0x591d_49f4_2d64_f995,
+    ),
+];
+
+const INDEX_DOC: &[u8] = br#"{"a":"x\"]}","b":[1,{"c":null}],"pad":"0123456789012345678901234","d":[true]}"#;
+/// Stage-1 positions: every quote and structural byte, in strings too
+/// (the parser sorts those out).
+const INDEX_POSITIONS: [u8; 34] = [
+    0, 1, 3, 4, 5, 8, 9, 10, 11, 12, 13, 15, 16, 17, 19, 20, 21, 23, 24, 29, 30, 31, 32, 36, 37, 38, 64, 65, 66, 68,
+    69, 70, 75, 76,
+];
+
+const DIFF_LEFT: &[u8] = br#"{"id":7,"tags":["a","b"],"old":null,"n":1.5}"#;
+const DIFF_RIGHT: &[u8] = br#"{"id":7,"tags":["a","c"],"n":2,"new":true}"#;
+/// Default-config result of `DIFF_LEFT` against `DIFF_RIGHT` (format
+/// 3.0): Modified `/tags/1`, Modified `/n`, Added `/new`, Removed `/old`.
+const DIFF_RESULT: [u8; 144] = [
+    0x03, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x90, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x02, 0x01, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x03, 0xe1, 0xd5, 0xf3, 0xc9, 0x42, 0xbd,
+    0x15, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x15, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x02, 0x02, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0xdd, 0xb4, 0x01, 0x00, 0x00, 0x01, 0x01, 0x00,
+    0x28, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00, 0x1d, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x87, 0x3d, 0xc6, 0xf7, 0x04, 0x00, 0xb7, 0xc4,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x25, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+    0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0e, 0xe4, 0xc3, 0xf7, 0x04, 0xeb, 0xb3, 0xc4,
+    0x1f, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+];
+
+/// Run every check: the bits of the failed ones, and what failed.
+pub fn run() -> (u32, String) {
+    let result = canned_result();
+    let checks = [
+        (SELF_TEST_HASHES, check_hashes()),
+        (SELF_TEST_INDEX, check_index()),
+        (SELF_TEST_DIFF, check_diff(&result, &DIFF_RESULT)),
+        (SELF_TEST_FORMAT, check_format(&result, &DIFF_RESULT)),
+    ];
+    let mut failed = 0;
+    let mut details = Vec::new();
+    for (bit, outcome) in checks {
+        if let Err(why) = outcome {
+            failed |= bit;
+            details.push(why);
+        }
+    }
+    (failed, details.join("; "))
+}
+
+fn check_hashes() -> Result<(), String> {
+    for (i, (bytes, want)) in VALUE_HASHES.iter().enumerate() {
+        let got = hash_bytes_simd(bytes);
+        if got != *want {
+            return Err(format!("value hash {}: got {:#018x}, want {:#018x}", i, got, want));
+        }
+    }
+    let users = fold_segment_hash(ROOT_PATH_ID, b"users");
+    let paths = [(users.0, 0xff22_ff80_3207_b6ff), (fold_index_hash(users, 0).0, 0x0e18_5c6c_7c51_8f58)];
+    for (i, (got, want)) in paths.into_iter().enumerate() {
+        if got != want {
+            return Err(format!("path id {}: got {:#018x}, want {:#018x}", i, got, want));
+        }
+    }
+    let got = digest(br#"{"a":1}"#);
+    let want = [0x45, 0xa6, 0x05, 0x97, 0xa9, 0x0a, 0xab, 0xee, 0xb7, 0xc6, 0x63, 0x05, 0x7b, 0xb3, 0x2a, 0x28];
+    if got != want {
+        return Err(format!("input digest: got {:02x?}", got));
+    }
+    Ok(())
+}
+
+fn check_index() -> Result<(), String> {
+    let mut index = StructuralIndex::new();
+    index.build(INDEX_DOC);
+    let got: Vec<usize> = index.cursor().collect();
+    if !got.iter().copied().eq(INDEX_POSITIONS.iter().map(|&p| p as usize)) {
+        return Err(format!("structural index: got positions {:?}", got));
+    }
+    Ok(())
+}
+
+/// The canned diff's result, or why there is none.
+fn canned_result() -> Result<Vec<u8>, String> {
+    let config = EngineConfig { max_input_size: 1024, ..EngineConfig::default() };
+    let mut engine = Engine::new(config, 0).map_err(|e| format!("canned diff: {}", e))?;
+    // SAFETY: the input buffers hold `max_input_size / 2` bytes each.
+    unsafe {
+        core::ptr::copy_nonoverlapping(DIFF_LEFT.as_ptr(), engine.left_input_ptr(), DIFF_LEFT.len());
+        core::ptr::copy_nonoverlapping(DIFF_RIGHT.as_ptr(), engine.right_input_ptr(), DIFF_RIGHT.len());
+    }
+    if engine.commit_left(DIFF_LEFT.len() as u32) != Status::Ok || engine.commit_right(DIFF_RIGHT.len() as u32) != Status::Ok {
+        return Err(String::from("canned diff: commit failed"));
+    }
+    let ptr = engine.finalize().map_err(|e| format!("canned diff: {}", e))?;
+    // SAFETY: a sealed engine's result is `result_len` bytes at `ptr`.
+    Ok(unsafe { core::slice::from_raw_parts(ptr, engine.result_len() as usize) }.to_vec())
+}
+
+/// Everything after the version against `want`; the version is
+/// `check_format`'s to report.
+fn check_diff(result: &Result<Vec<u8>, String>, want: &[u8]) -> Result<(), String> {
+    let got = result.as_ref().map_err(String::clone)?;
+    if got.len() != want.len() || got.get(4..) != want.get(4..) {
+        return Err(format!("canned diff: got result {:02x?}", got));
+    }
+    Ok(())
+}
+
+/// The result's version against both the one `want` was written with and
+/// the one this build writes.
+fn check_format(result: &Result<Vec<u8>, String>, want: &[u8]) -> Result<(), String> {
+    let version = |r: &[u8]| Some((u16::from_le_bytes([*r.first()?, *r.get(1)?]), u16::from_le_bytes([*r.get(2)?, *r.get(3)?])));
+    let got = result.as_ref().ok().and_then(|r| version(r));
+    let want = version(want);
+    if got.is_none() || got != want || got != Some((FORMAT_VERSION_MAJOR, FORMAT_VERSION_MINOR)) {
+        return Err(format!("format version: got {:?}, want {:?}", got, want));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn every_check_passes() {
+        assert_eq!(run(), (0, String::new()));
+        let fixtures = [VALUE_HASHES.iter().map(|(b, _)| b.len() + 8).sum(), INDEX_DOC.len(), INDEX_POSITIONS.len()];
+        let size: usize = fixtures.iter().sum::<usize>() + DIFF_LEFT.len() + DIFF_RIGHT.len() + DIFF_RESULT.len();
+        assert!(size < 2048, "{} bytes of fixtures", size);
+    }
+
+    #[test]
+    fn failures_name_the_check() {
+        let result = canned_result();
+        let mut changed = DIFF_RESULT;
+        changed[16] = 0; // first entry: Modified to Added
+        assert!(check_diff(&result, &changed).unwrap_err().starts_with("canned diff: got result [03, 00, 00, 00"));
+        assert_eq!(check_format(&result, &changed), Ok(()), "the entries are not the version's concern");
+
+        changed[0] = 2;
+        assert_eq!(check_format(&result, &changed).unwrap_err(), "format version: got Some((3, 0)), want Some((2, 0))");
+        assert_eq!(check_diff(&result, &changed), Err(check_diff(&result, &[0; 16]).unwrap_err()));
+        let failed = Err(String::from("canned diff: commit failed"));
+        assert_eq!(check_format(&failed, &DIFF_RESULT).unwrap_err(), "format version: got None, want Some((3, 0))");
+    }
+}
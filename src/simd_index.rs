@@ -3,122 +3,326 @@
 //! This module provides a fast structural index of JSON documents by
 //! finding all structural characters ({, }, [, ], :, ,, ") in parallel
 //! using SIMD operations.
+//!
+//! Which path is chosen at compile time: `v128` lanes on wasm32 built with
+//! `simd128`, a byte at a time otherwise (native builds, and wasm32
+//! without it, which would trap on the first `v128` instruction).
+//! [`SIMD_INDEX`] says which, for `get_build_flags`.
 
+use crate::digest::{Digest, InputHasher};
+use crate::progress::{Phase, Progress, STRIDE_BYTES};
+use alloc::vec::Vec;
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
 use core::arch::wasm32::*;
 
-/// Structural index: positions of all structural characters
+/// Whether [`StructuralIndex::build`] runs in `v128` lanes.
+pub const SIMD_INDEX: bool = cfg!(all(target_arch = "wasm32", target_feature = "simd128"));
+
+/// Structural index: one bit per input byte, set on structural characters.
+///
+/// Word `w` covers bytes `w * 64 .. w * 64 + 64`, least significant bit
+/// first. The parser walks the words directly (see [`Cursor`]) instead of
+/// a flattened position list.
 pub struct StructuralIndex {
-    /// Positions of structural characters in the input
-    pub positions: Vec<u32>,
+    /// Structural bitmask, one word per 64-byte block
+    pub masks: Vec<u64>,
     /// Total bytes processed
     pub len: u32,
 }
 
+impl Default for StructuralIndex {
+    fn default() -> Self { Self::new() }
+}
+
 impl StructuralIndex {
     pub fn new() -> Self {
         Self {
-            positions: Vec::with_capacity(65536),
+            masks: Vec::new(),
             len: 0,
         }
     }
 
     pub fn clear(&mut self) {
-        self.positions.clear();
+        self.masks.clear();
         self.len = 0;
     }
 
+    /// Cursor over the structural positions, in input order.
+    pub fn cursor(&self) -> Cursor<'_> { Cursor::new(&self.masks) }
+
     /// Build structural index using SIMD
     /// This is Stage 1 of simdjson-style parsing
-    #[inline(never)]
     pub fn build(&mut self, json: &[u8]) {
+        self.build_with(json, None, None);
+    }
+
+    /// The engine's commit-time build: reports `Phase::Index` progress
+    /// and, when `digest` is set, also returns the input digest (see
+    /// `digest.rs`). Each 64-byte block is hashed right after its SIMD
+    /// loads, while it is still in cache.
+    pub fn build_reporting(&mut self, json: &[u8], digest: bool, progress: &Progress) -> Option<Digest> {
+        let mut hasher = digest.then(InputHasher::new);
+        let tail = self.build_with(json, hasher.as_mut(), Some(progress));
+        progress.finish(Phase::Index);
+        hasher.map(|h| h.finish(&json[tail..]))
+    }
+
+    /// Returns the offset of the partial tail block (not fed to `hasher`).
+    #[inline(never)]
+    fn build_with(
+        &mut self,
+        json: &[u8],
+        mut hasher: Option<&mut InputHasher>,
+        progress: Option<&Progress>,
+    ) -> usize {
         self.clear();
         self.len = json.len() as u32;
         
-        if json.is_empty() { return; }
+        if json.is_empty() { return 0; }
         
         let mut pos: u32 = 0;
         let len = json.len();
         let ptr = json.as_ptr();
+        self.masks.reserve(len.div_ceil(64));
         
         // Process 64 bytes at a time (4 x 16-byte SIMD registers)
         let chunks = len / 64;
         
-        for _ in 0..chunks {
-            let base = pos;
-            
-            // Load 4 chunks
-            let c0 = unsafe { v128_load(ptr.add(pos as usize) as *const v128) };
-            let c1 = unsafe { v128_load(ptr.add(pos as usize + 16) as *const v128) };
-            let c2 = unsafe { v128_load(ptr.add(pos as usize + 32) as *const v128) };
-            let c3 = unsafe { v128_load(ptr.add(pos as usize + 48) as *const v128) };
-            
-            // Find structural characters: { } [ ] : , "
-            // Check each character type across all 4 chunks
-            let brace_open = i8x16_splat(b'{' as i8);
-            let brace_close = i8x16_splat(b'}' as i8);
-            let bracket_open = i8x16_splat(b'[' as i8);
-            let bracket_close = i8x16_splat(b']' as i8);
-            let colon = i8x16_splat(b':' as i8);
-            let comma = i8x16_splat(b',' as i8);
-            let quote = i8x16_splat(b'"' as i8);
-            
-            // Combine all structural character matches for each chunk
-            macro_rules! find_structural {
-                ($chunk:expr) => {{
-                    let m1 = v128_or(u8x16_eq($chunk, brace_open), u8x16_eq($chunk, brace_close));
-                    let m2 = v128_or(u8x16_eq($chunk, bracket_open), u8x16_eq($chunk, bracket_close));
-                    let m3 = v128_or(u8x16_eq($chunk, colon), u8x16_eq($chunk, comma));
-                    let m4 = u8x16_eq($chunk, quote);
-                    v128_or(v128_or(m1, m2), v128_or(m3, m4))
-                }};
+        for chunk in 0..chunks {
+            if chunk.is_multiple_of(STRIDE_BYTES / 64) {
+                if let Some(p) = progress { p.report(Phase::Index, pos as usize, len); }
             }
-            
-            let mask0 = i8x16_bitmask(find_structural!(c0)) as u64;
-            let mask1 = i8x16_bitmask(find_structural!(c1)) as u64;
-            let mask2 = i8x16_bitmask(find_structural!(c2)) as u64;
-            let mask3 = i8x16_bitmask(find_structural!(c3)) as u64;
-            
-            // Combine into 64-bit mask
-            let combined = mask0 | (mask1 << 16) | (mask2 << 32) | (mask3 << 48);
-            
-            // Extract positions of set bits
-            if combined != 0 {
-                self.extract_positions(combined, base);
+
+            // SAFETY: `pos + 64 <= len` for every full chunk.
+            self.masks.push(unsafe { block_mask(ptr.add(pos as usize)) });
+
+            if let Some(h) = hasher.as_deref_mut() {
+                h.update(&json[pos as usize..pos as usize + 64]);
             }
             
             pos += 64;
         }
         
-        // Handle remainder (< 64 bytes)
-        while (pos as usize) < len {
-            let b = json[pos as usize];
-            if matches!(b, b'{' | b'}' | b'[' | b']' | b':' | b',' | b'"') {
-                self.positions.push(pos);
-            }
-            pos += 1;
+        // Handle remainder (< 64 bytes) as one final, partial block
+        if (pos as usize) < len {
+            self.masks.push(scalar_mask(&json[pos as usize..]));
         }
+        chunks * 64
     }
     
+    /// Check the invariant the parser's unchecked reads rely on: no bit set
+    /// at or past `len`. Returns the first offending position. Ordering
+    /// needs no check; a bitmask cannot encode positions out of order.
+    pub fn validate(&self) -> Result<(), u32> {
+        let full = self.len as usize / 64;
+        for (w, &mask) in self.masks.iter().enumerate().skip(full) {
+            let valid = (self.len as usize).saturating_sub(w * 64).min(64);
+            let stray = if valid == 64 { 0 } else { mask >> valid << valid };
+            if stray != 0 {
+                return Err((w * 64) as u32 + stray.trailing_zeros());
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Structural bits of the 64 bytes at `block`: { } [ ] : , "
+///
+/// # Safety
+/// `block` must be valid for 64 bytes of reads.
+#[cfg(all(target_arch = "wasm32", target_feature = "simd128"))]
+#[inline(always)]
+unsafe fn block_mask(block: *const u8) -> u64 {
+    // Load 4 chunks
+    let c0 = v128_load(block as *const v128);
+    let c1 = v128_load(block.add(16) as *const v128);
+    let c2 = v128_load(block.add(32) as *const v128);
+    let c3 = v128_load(block.add(48) as *const v128);
+
+    // Check each character type across all 4 chunks
+    let brace_open = i8x16_splat(b'{' as i8);
+    let brace_close = i8x16_splat(b'}' as i8);
+    let bracket_open = i8x16_splat(b'[' as i8);
+    let bracket_close = i8x16_splat(b']' as i8);
+    let colon = i8x16_splat(b':' as i8);
+    let comma = i8x16_splat(b',' as i8);
+    let quote = i8x16_splat(b'"' as i8);
+
+    // Combine all structural character matches for each chunk
+    macro_rules! find_structural {
+        ($chunk:expr) => {{
+            let m1 = v128_or(u8x16_eq($chunk, brace_open), u8x16_eq($chunk, brace_close));
+            let m2 = v128_or(u8x16_eq($chunk, bracket_open), u8x16_eq($chunk, bracket_close));
+            let m3 = v128_or(u8x16_eq($chunk, colon), u8x16_eq($chunk, comma));
+            let m4 = u8x16_eq($chunk, quote);
+            v128_or(v128_or(m1, m2), v128_or(m3, m4))
+        }};
+    }
+
+    let mask0 = i8x16_bitmask(find_structural!(c0)) as u64;
+    let mask1 = i8x16_bitmask(find_structural!(c1)) as u64;
+    let mask2 = i8x16_bitmask(find_structural!(c2)) as u64;
+    let mask3 = i8x16_bitmask(find_structural!(c3)) as u64;
+
+    // Combine into 64-bit mask
+    mask0 | (mask1 << 16) | (mask2 << 32) | (mask3 << 48)
+}
+
+/// Native builds (the benches) and wasm32 without `simd128` index a byte
+/// at a time.
+///
+/// # Safety
+/// `block` must be valid for 64 bytes of reads.
+#[cfg(not(all(target_arch = "wasm32", target_feature = "simd128")))]
+#[inline(always)]
+unsafe fn block_mask(block: *const u8) -> u64 {
+    scalar_mask(core::slice::from_raw_parts(block, 64))
+}
+
+/// Structural bits of up to 64 `bytes`.
+#[inline(always)]
+fn scalar_mask(bytes: &[u8]) -> u64 {
+    let mut mask = 0u64;
+    for (bit, &b) in bytes.iter().enumerate() {
+        if matches!(b, b'{' | b'}' | b'[' | b']' | b':' | b',' | b'"') {
+            mask |= 1 << bit;
+        }
+    }
+    mask
+}
+
+/// Whether the `"` at `quote` is escaped: preceded by an odd-length run
+/// of backslashes, counted back no further than `start` (the string
+/// body's first byte). The index records every `"` byte; only unescaped
+/// ones end a string. `quote` must be within `json`.
+#[inline(always)]
+pub fn quote_is_escaped(json: &[u8], quote: usize, start: usize) -> bool {
+    debug_assert!(quote < json.len());
+    let mut bs = quote;
+    // SAFETY: `start <= bs - 1 < quote < json.len()` inside the loop.
+    while bs > start && unsafe { *json.get_unchecked(bs - 1) } == b'\\' {
+        bs -= 1;
+    }
+    (quote - bs) & 1 == 1
+}
+
+/// Walks the set bits of a structural bitmask in position order.
+///
+/// `Copy`, so looking ahead is a cheap clone: [`Cursor::peek`] reads the
+/// next position without consuming it, crossing into later words as needed.
+#[derive(Clone, Copy)]
+pub struct Cursor<'a> {
+    masks: &'a [u64],
+    word: usize,
+    bits: u64,
+}
+
+impl<'a> Cursor<'a> {
+    pub fn new(masks: &'a [u64]) -> Self {
+        Self { masks, word: 0, bits: masks.first().copied().unwrap_or(0) }
+    }
+
+    /// Next position without advancing.
     #[inline(always)]
-    fn extract_positions(&mut self, mut mask: u64, base: u32) {
-        while mask != 0 {
-            let bit_pos = mask.trailing_zeros();
-            self.positions.push(base + bit_pos);
-            mask &= mask - 1; // Clear lowest set bit
+    pub fn peek(&self) -> Option<usize> {
+        let mut ahead = *self;
+        ahead.next()
+    }
+}
+
+impl Iterator for Cursor<'_> {
+    type Item = usize;
+
+    #[inline(always)]
+    fn next(&mut self) -> Option<usize> {
+        while self.bits == 0 {
+            self.word += 1;
+            self.bits = *self.masks.get(self.word)?;
         }
+        let pos = self.word * 64 + self.bits.trailing_zeros() as usize;
+        self.bits &= self.bits - 1; // Clear lowest set bit
+        Some(pos)
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+
+    fn positions(idx: &StructuralIndex) -> Vec<usize> { idx.cursor().collect() }
     
     #[test]
     fn test_structural_index() {
         let json = br#"{"a":1,"b":[2,3]}"#;
         let mut idx = StructuralIndex::new();
         idx.build(json);
-        // Should find: { " : " , " : [ , ] }
-        assert!(idx.positions.len() > 0);
+        // Should find: { " " : , " " : [ , ] }
+        assert_eq!(positions(&idx), vec![0, 1, 3, 4, 6, 7, 9, 10, 11, 13, 15, 16]);
+        assert_eq!(idx.validate(), Ok(()));
+    }
+
+    #[test]
+    fn digesting_build_matches_plain_build_and_digest() {
+        for len in [0usize, 5, 63, 64, 65, 128, 200] {
+            let json: Vec<u8> = (0..len).map(|i| b"{\"k\":[1,2],}"[i % 12]).collect();
+            let (mut plain, mut hashed) = (StructuralIndex::new(), StructuralIndex::new());
+            plain.build(&json);
+            let d = hashed.build_reporting(&json, true, &Progress::new());
+            assert_eq!(hashed.masks, plain.masks, "len {}", len);
+            assert_eq!(d, Some(crate::digest::digest(&json)), "len {}", len);
+        }
+    }
+
+    #[test]
+    fn cursor_crosses_block_boundaries() {
+        let mut json = vec![b' '; 200];
+        for &p in &[0usize, 63, 64, 130, 199] {
+            json[p] = b',';
+        }
+        let mut idx = StructuralIndex::new();
+        idx.build(&json);
+        assert_eq!(idx.masks.len(), 4);
+        assert_eq!(positions(&idx), vec![0, 63, 64, 130, 199]);
+
+        let mut cur = idx.cursor();
+        assert_eq!(cur.nth(1), Some(63));
+        assert_eq!(cur.peek(), Some(64));
+        assert_eq!(cur.next(), Some(64));
+        assert_eq!(cur.peek(), Some(130));
+    }
+
+    #[test]
+    fn corrupted_masks_rejected() {
+        let json = br#"{"a":[1,2,3],"b":{"c":"d"}}"#;
+        let mut idx = StructuralIndex::new();
+        idx.build(json);
+
+        idx.masks[0] |= 1 << json.len();
+        assert_eq!(idx.validate(), Err(json.len() as u32));
+
+        idx.build(json);
+        idx.masks.push(1 << 5);
+        assert_eq!(idx.validate(), Err(64 + 5));
+    }
+
+    #[test]
+    fn mutated_masks_never_pass_validation() {
+        use crate::gen::{generate, GenConfig, Rng};
+        let cfg = GenConfig::default();
+        for seed in 0..200 {
+            let json = generate(&cfg, seed).to_json();
+            let mut idx = StructuralIndex::new();
+            idx.build(json.as_bytes());
+            assert_eq!(idx.validate(), Ok(()));
+
+            // Set a bit at or past the end of the input.
+            let mut rng = Rng::new(seed);
+            let bad = idx.len as usize + rng.below(1000) as usize;
+            if bad / 64 >= idx.masks.len() {
+                idx.masks.resize(bad / 64 + 1, 0);
+            }
+            idx.masks[bad / 64] |= 1 << (bad % 64);
+            assert_eq!(idx.validate(), Err(bad as u32), "seed {seed}: stray bit not detected");
+        }
     }
 }
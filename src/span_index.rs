@@ -0,0 +1,66 @@
+//! Right-document interval index over a finalized result, for renderers
+//! that scroll the right text and need the entries touching each range.
+//!
+//! Layout: 12-byte records, little-endian, sorted by offset then entry
+//! index so the host can binary-search them:
+//! ```text
+//! [u32 right_offset] [u32 right_len] [u32 entry_index]
+//! ```
+//!
+//! Removed entries have no right span. Each is anchored, with length 0, at
+//! the end of the right span of the nearest preceding left value whose
+//! path still exists on the right (in practice its preceding sibling), or
+//! at offset 0 when there is none.
+
+use crate::memory::ResultArena;
+use crate::parser::{CompactEvent, CompactParser, CompactToken};
+use crate::path::PathId;
+use crate::diff::DiffOp;
+use crate::FxHashMap;
+use alloc::vec::Vec;
+
+pub const SPAN_RECORD_SIZE: usize = 12;
+
+pub fn build(arena: &ResultArena, left: &CompactParser, right: &CompactParser) -> Vec<u8> {
+    let right_end: FxHashMap<PathId, u32> = values(right)
+        .map(|t| (t.path_id, t.raw_end()))
+        .collect();
+
+    // Walk the left document in order; a path missing on the right takes
+    // the end of the last surviving value seen before it.
+    let mut anchors: FxHashMap<PathId, u32> = FxHashMap::default();
+    let mut last = 0;
+    for t in values(left) {
+        match right_end.get(&t.path_id) {
+            Some(&end) => last = end,
+            None => { anchors.insert(t.path_id, last); }
+        }
+    }
+
+    let mut spans: Vec<(u32, u32, u32)> = arena
+        .entries()
+        .enumerate()
+        .map(|(i, e)| {
+            let field = |at: usize| u32::from_le_bytes([e[at], e[at + 1], e[at + 2], e[at + 3]]);
+            if e[0] == DiffOp::Removed as u8 {
+                let path = PathId(u64::from_le_bytes(e[8..16].try_into().unwrap()));
+                (anchors.get(&path).copied().unwrap_or(0), 0, i as u32)
+            } else {
+                (field(24), field(28), i as u32)
+            }
+        })
+        .collect();
+    spans.sort_unstable();
+
+    let mut out = Vec::with_capacity(spans.len() * SPAN_RECORD_SIZE);
+    for (offset, len, entry) in spans {
+        out.extend_from_slice(&offset.to_le_bytes());
+        out.extend_from_slice(&len.to_le_bytes());
+        out.extend_from_slice(&entry.to_le_bytes());
+    }
+    out
+}
+
+fn values(parser: &CompactParser) -> impl Iterator<Item = &CompactToken> {
+    parser.tokens().iter().filter(|t| t.event == CompactEvent::Value)
+}
@@ -0,0 +1,145 @@
+//! Work counters of the current diff (`get_stats`).
+//!
+//! When a big diff is slow, these say where the work went: bytes and
+//! structural positions for the index, tokens for the parse, entries for
+//! the diff and bytes for the write. Each commit fills its side, finalize
+//! the rest; `clear` zeroes them all. A recommit replaces its side's
+//! counts. The third input's (`three_way`) are 0 without a canary.
+//!
+//! Layout (little-endian, 80 bytes):
+//! ```text
+//! [u32 left_bytes_indexed]    (0-3)
+//! [u32 left_structurals]      (4-7)    positions in the structural index
+//! [u32 left_tokens]           (8-11)   every token, containers included
+//! [u32 left_values]           (12-15)  Value tokens
+//! [u32 right_bytes_indexed]   (16-19)
+//! [u32 right_structurals]     (20-23)
+//! [u32 right_tokens]          (24-27)
+//! [u32 right_values]          (28-31)
+//! [u32 added]                 (32-35)  entries written, by op
+//! [u32 removed]               (36-39)
+//! [u32 modified]              (40-43)
+//! [u32 result_bytes]          (44-47)  arena bytes used, sections included
+//! [u32 redacted]              (48-51)  entries written flagged redacted
+//! [u32 left_oversized]        (52-55)  values over max_primitive_bytes
+//! [u32 right_oversized]       (56-59)
+//! [u32 third_bytes_indexed]   (60-63)  the canary, as the right above
+//! [u32 third_structurals]     (64-67)
+//! [u32 third_tokens]          (68-71)
+//! [u32 third_values]          (72-75)
+//! [u32 third_oversized]       (76-79)
+//! ```
+
+use crate::diff::DiffOp;
+use crate::memory::ENTRY_FLAG_REDACTED;
+use crate::parser::{CompactEvent, CompactParser};
+use crate::simd_index::StructuralIndex;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct SideStats {
+    pub bytes_indexed: u32,
+    pub structurals: u32,
+    pub tokens: u32,
+    pub values: u32,
+    pub oversized: u32,
+}
+
+impl SideStats {
+    /// Counts of a side just indexed and parsed, with values longer than
+    /// `max_primitive` (0 for no cap) counted as oversized.
+    pub fn of(index: &StructuralIndex, parser: &CompactParser, max_primitive: u32) -> Self {
+        let tokens = parser.tokens();
+        let values = || tokens.iter().filter(|t| t.event == CompactEvent::Value);
+        Self {
+            bytes_indexed: index.len,
+            structurals: index.masks.iter().map(|m| m.count_ones()).sum(),
+            tokens: tokens.len() as u32,
+            values: values().count() as u32,
+            oversized: match max_primitive {
+                0 => 0,
+                max => values().filter(|t| t.raw_len > max).count() as u32,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EngineStats {
+    pub left: SideStats,
+    pub right: SideStats,
+    pub added: u32,
+    pub removed: u32,
+    pub modified: u32,
+    pub result_bytes: u32,
+    pub redacted: u32,
+    pub third: SideStats,
+}
+
+impl EngineStats {
+    pub const SIZE: usize = 80;
+
+    /// Count the written `entries` (raw records) and the result length.
+    pub fn record_result<'a>(&mut self, entries: impl Iterator<Item = &'a [u8]>, result_bytes: u32) {
+        (self.added, self.removed, self.modified, self.redacted) = (0, 0, 0, 0);
+        for entry in entries {
+            if entry[3] & ENTRY_FLAG_REDACTED != 0 { self.redacted += 1; }
+            match entry[0] {
+                op if op == DiffOp::Added as u8 => self.added += 1,
+                op if op == DiffOp::Removed as u8 => self.removed += 1,
+                _ => self.modified += 1,
+            }
+        }
+        self.result_bytes = result_bytes;
+    }
+
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let fields = [
+            self.left.bytes_indexed,
+            self.left.structurals,
+            self.left.tokens,
+            self.left.values,
+            self.right.bytes_indexed,
+            self.right.structurals,
+            self.right.tokens,
+            self.right.values,
+            self.added,
+            self.removed,
+            self.modified,
+            self.result_bytes,
+            self.redacted,
+            self.left.oversized,
+            self.right.oversized,
+            self.third.bytes_indexed,
+            self.third.structurals,
+            self.third.tokens,
+            self.third.values,
+            self.third.oversized,
+        ];
+        let mut buf = [0u8; Self::SIZE];
+        for (chunk, v) in buf.chunks_exact_mut(4).zip(fields) {
+            chunk.copy_from_slice(&v.to_le_bytes());
+        }
+        buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_and_entry_counts() {
+        let mut stats = EngineStats { left: SideStats { bytes_indexed: 1, structurals: 2, tokens: 3, values: 4, oversized: 5 }, ..EngineStats::default() };
+        let entries: [[u8; 4]; 4] = [[2, 0, 0, 0], [0, 1, 1, 0], [2, 2, 2, ENTRY_FLAG_REDACTED], [1, 0, 0, 0]];
+        stats.record_result(entries.iter().map(|e| &e[..]), 0x0102_0304);
+        assert_eq!((stats.added, stats.removed, stats.modified, stats.redacted), (1, 1, 2, 1));
+        let bytes = stats.to_bytes();
+        assert_eq!(bytes[..16], [1, 0, 0, 0, 2, 0, 0, 0, 3, 0, 0, 0, 4, 0, 0, 0]);
+        assert_eq!(bytes[16..32], [0; 16]);
+        assert_eq!(bytes[32..52], [1, 0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 4, 3, 2, 1, 1, 0, 0, 0]);
+        assert_eq!(bytes[52..60], [5, 0, 0, 0, 0, 0, 0, 0]);
+        let third = EngineStats { third: SideStats { bytes_indexed: 6, structurals: 7, tokens: 8, values: 9, oversized: 10 }, ..stats };
+        assert_eq!(third.to_bytes()[..60], bytes[..60]);
+        assert_eq!(third.to_bytes()[60..].chunks_exact(4).map(|c| c[0]).collect::<Vec<_>>(), [6, 7, 8, 9, 10]);
+    }
+}
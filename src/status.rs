@@ -20,6 +20,25 @@ pub enum Status {
     ObjectKeyLimitExceeded = 5,
     /// Array too large for selected diff mode
     ArrayTooLarge = 6,
+    /// This side was already committed (see `recommit_replaces`)
+    AlreadyCommitted = 7,
+    /// The engine's group is at its engine or byte quota
+    GroupQuotaExceeded = 8,
+    /// `finalize_step` used its budget and the finalize is not done yet
+    InProgress = 9,
+    /// `cancel` stopped the finalize; the result is partial
+    Cancelled = 10,
+    /// A `finalize_step` finalize is in progress; finish it or clear
+    Busy = 11,
+    /// An earlier failure left the engine unusable; clear it
+    Poisoned = 12,
+    /// Input has arrived; the call needs a fresh or cleared engine
+    TooLate = 13,
+    /// `set_option` key this build does not know
+    UnknownOption = 14,
+    /// The result hit `max_memory_bytes` and is flagged truncated; retry
+    /// with a larger limit or `finalize_partial`
+    ResultTruncated = 15,
     /// Generic error (check get_last_error for details)
     Error = 255,
 }
@@ -34,6 +53,6 @@ impl Status {
     /// Returns true if the operation can be retried after handling
     #[inline]
     pub fn is_recoverable(self) -> bool {
-        matches!(self, Status::NeedFlush)
+        matches!(self, Status::NeedFlush | Status::ResultTruncated)
     }
 }
@@ -0,0 +1,160 @@
+//! Three-document mode (`three_way`): one left (the baseline) against two
+//! rights, the control (`commit_right`) and the canary (`commit_third`).
+//!
+//! Finalize runs the ordinary diff twice, left against control and left
+//! against canary, and joins the two entry lists on path. Each resulting
+//! entry gets an [`Agreement`] and the canary's side of the change, written
+//! as `SECTION_CANARY` records in entry order (layout in `memory.rs`).
+//!
+//! Entries keep their two-document meaning against the control: control
+//! differences come first in engine order, then paths only the canary
+//! changed, in canary order. Those have no control value (`right` is
+//! absent); their op and canary value are the canary's.
+
+use crate::diff::{DiffEntry, DiffOp, ValueSpan};
+use crate::path::PathId;
+use crate::FxHashMap;
+use alloc::vec::Vec;
+
+/// How the canary's change at a path relates to the control's.
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Agreement {
+    /// Both rights changed the path the same way (same op, same value).
+    Agree = 0,
+    /// Only the canary changed the path.
+    CanaryOnly = 1,
+    /// Both changed the path, differently.
+    Disagree = 2,
+    /// Only the control changed the path.
+    ControlOnly = 3,
+}
+
+/// `canary_op` of a record whose canary does not differ at the path.
+pub const NO_CANARY_OP: u8 = 0xFF;
+
+/// The canary's side of one entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CanarySide {
+    pub agreement: Agreement,
+    /// The canary's op, `None` when it matches the left there.
+    pub op: Option<DiffOp>,
+    /// The canary's value, a span into the third input.
+    pub val: Option<ValueSpan>,
+}
+
+impl CanarySide {
+    pub const SIZE: usize = 16;
+
+    /// `[u8 agreement][u8 canary_op][u8 canary_kind][u8 reserved]
+    /// [u32 reserved][u32 canary_offset][u32 canary_len]`, little-endian.
+    pub fn to_bytes(self) -> [u8; Self::SIZE] {
+        let (offset, len, kind) = self.val.unwrap_or((0, 0, crate::parser::ValueKind::None));
+        let mut buf = [0u8; Self::SIZE];
+        buf[0] = self.agreement as u8;
+        buf[1] = self.op.map_or(NO_CANARY_OP, |op| op as u8);
+        buf[2] = kind as u8;
+        buf[8..12].copy_from_slice(&offset.to_le_bytes());
+        buf[12..16].copy_from_slice(&len.to_le_bytes());
+        buf
+    }
+}
+
+/// Join the left-vs-control and left-vs-canary entries on path. `right`
+/// and `third` are the control and canary inputs the spans point into.
+pub fn join(
+    control: Vec<DiffEntry>,
+    canary: Vec<DiffEntry>,
+    right: &[u8],
+    third: &[u8],
+) -> (Vec<DiffEntry>, FxHashMap<PathId, CanarySide>) {
+    let at: FxHashMap<PathId, usize> = canary.iter().enumerate().map(|(i, d)| (d.path_id, i)).collect();
+    let mut matched = alloc::vec![false; canary.len()];
+    let mut sides = FxHashMap::default();
+    let mut entries = Vec::with_capacity(control.len() + canary.len());
+    for d in control {
+        let side = match at.get(&d.path_id) {
+            Some(&i) => {
+                matched[i] = true;
+                let c = &canary[i];
+                let same = c.op == d.op && value(right, d.right_val) == value(third, c.right_val);
+                let agreement = if same { Agreement::Agree } else { Agreement::Disagree };
+                CanarySide { agreement, op: Some(c.op), val: c.right_val }
+            }
+            None => CanarySide { agreement: Agreement::ControlOnly, op: None, val: None },
+        };
+        sides.insert(d.path_id, side);
+        entries.push(d);
+    }
+    for (c, matched) in canary.into_iter().zip(matched) {
+        if matched { continue; }
+        sides.insert(c.path_id, CanarySide { agreement: Agreement::CanaryOnly, op: Some(c.op), val: c.right_val });
+        entries.push(DiffEntry { right_val: None, ..c });
+    }
+    (entries, sides)
+}
+
+/// A value's kind and bytes, for comparing spans across inputs.
+fn value(input: &[u8], span: Option<ValueSpan>) -> Option<(u8, &[u8])> {
+    let (offset, len, kind) = span?;
+    let end = (offset as usize).saturating_add(len as usize);
+    Some((kind as u8, input.get(offset as usize..end).unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::ValueKind;
+
+    fn entry(op: DiffOp, id: u64, right: Option<(u32, u32)>) -> DiffEntry {
+        let left = (op != DiffOp::Added).then_some((0, 1, ValueKind::Literal));
+        let right_val = right.map(|(offset, len)| (offset, len, ValueKind::Literal));
+        DiffEntry { op, path_id: PathId(id), left_val: left, right_val }
+    }
+
+    #[test]
+    fn join_classifies_every_path() {
+        let right = b"1 2 3 4";
+        let third = b"1 9 5 4";
+        let control = alloc::vec![
+            entry(DiffOp::Modified, 1, Some((0, 1))), // canary same value
+            entry(DiffOp::Modified, 2, Some((2, 1))), // canary other value
+            entry(DiffOp::Removed, 3, None),          // canary untouched
+            entry(DiffOp::Removed, 4, None),          // canary removed too
+        ];
+        let canary = alloc::vec![
+            entry(DiffOp::Added, 5, Some((4, 1))),
+            entry(DiffOp::Modified, 2, Some((2, 1))),
+            entry(DiffOp::Modified, 1, Some((0, 1))),
+            entry(DiffOp::Removed, 4, None),
+        ];
+        let (entries, sides) = join(control, canary, right, third);
+        let ids: Vec<u64> = entries.iter().map(|d| d.path_id.0).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5], "control order, then canary-only");
+        let agreement = |id| sides[&PathId(id)].agreement;
+        assert_eq!(
+            [1, 2, 3, 4, 5].map(agreement),
+            [Agreement::Agree, Agreement::Disagree, Agreement::ControlOnly, Agreement::Agree, Agreement::CanaryOnly]
+        );
+
+        // A canary-only entry has no control value; the canary's is in its side.
+        assert_eq!((entries[4].op, entries[4].right_val), (DiffOp::Added, None));
+        let side = sides[&PathId(5)];
+        assert_eq!(side.to_bytes()[..3], [Agreement::CanaryOnly as u8, DiffOp::Added as u8, ValueKind::Literal as u8]);
+        assert_eq!(side.to_bytes()[8..], [4, 0, 0, 0, 1, 0, 0, 0]);
+        assert_eq!(sides[&PathId(3)].to_bytes()[1], NO_CANARY_OP);
+    }
+
+    #[test]
+    fn same_bytes_of_another_kind_disagree() {
+        let control = alloc::vec![DiffEntry {
+            op: DiffOp::Modified,
+            path_id: PathId(1),
+            left_val: None,
+            right_val: Some((0, 1, ValueKind::Literal)),
+        }];
+        let canary = alloc::vec![DiffEntry { right_val: Some((0, 1, ValueKind::String)), ..control[0].clone() }];
+        let (_, sides) = join(control, canary, b"1", b"1");
+        assert_eq!(sides[&PathId(1)].agreement, Agreement::Disagree);
+    }
+}
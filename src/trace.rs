@@ -0,0 +1,85 @@
+//! Spans and events for native embedders, behind the `tracing` feature.
+//!
+//! Each phase of a diff runs in an `info` span under the `diffcore`
+//! target:
+//!
+//! ```text
+//! commit {side, bytes}
+//! ├── index {bytes, structurals}
+//! └── parse {tokens, oversized}
+//! finalize_step {budget}
+//! ├── diff {done, entries}
+//! └── write {entries, written, bytes, skipped, full}
+//! resume_finalize
+//! └── write {entries, written, bytes, skipped, full}
+//! ```
+//!
+//! Fields known only once a phase ends start out empty and are recorded
+//! before its span closes. Every error or warning the engine records
+//! (`ErrorBuffer::set`) is also a `warn` event with its `code` and
+//! `error` text, inside whatever span was current.
+//!
+//! Without the feature, and always on wasm32, the macros expand to
+//! nothing: no dependency, no call, no field computed.
+
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+macro_rules! span {
+    ($name:literal $(, $($fields:tt)*)?) => {
+        tracing::info_span!(target: "diffcore", $name $(, $($fields)*)?).entered()
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(target_arch = "wasm32"))))]
+macro_rules! span {
+    ($($t:tt)*) => {
+        $crate::trace::Off
+    };
+}
+
+/// What `span!` gives without the feature.
+#[cfg(not(all(feature = "tracing", not(target_arch = "wasm32"))))]
+pub struct Off;
+
+/// Record `value` as `field` of a span from `span!`.
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+macro_rules! record {
+    ($span:expr, $field:literal, $value:expr) => {
+        $span.record($field, $value);
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(target_arch = "wasm32"))))]
+macro_rules! record {
+    ($span:expr, $($t:tt)*) => {
+        let _ = &$span;
+    };
+}
+
+/// Close a span from `span!` before the end of its scope.
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+macro_rules! exit {
+    ($span:expr) => {
+        drop($span);
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(target_arch = "wasm32"))))]
+macro_rules! exit {
+    ($span:expr) => {
+        let _ = $span;
+    };
+}
+
+#[cfg(all(feature = "tracing", not(target_arch = "wasm32")))]
+macro_rules! warn_event {
+    ($($t:tt)*) => {
+        tracing::warn!(target: "diffcore", $($t)*)
+    };
+}
+
+#[cfg(not(all(feature = "tracing", not(target_arch = "wasm32"))))]
+macro_rules! warn_event {
+    ($($t:tt)*) => {};
+}
+
+pub(crate) use {exit, record, span, warn_event};
@@ -0,0 +1,144 @@
+//! Host entry transform (`set_entry_transform`).
+//!
+//! Some hosts post-process every result the same way with logic no
+//! config could express: tenant-specific redaction of secret values,
+//! dropping paths by rules of their own, tagging entries for later
+//! routing. With a transform set, finalize calls it once per entry after
+//! suppression and before ordering or `top_k`, so what it drops is never
+//! ranked, written or counted.
+//!
+//! Signature: `(op, path_ptr, path_len, left_ptr, left_len, right_ptr,
+//! right_len, out_flags_ptr) -> u32`. The path is the entry's JSON
+//! Pointer, the values are the full payloads before any truncation (an
+//! absent side is a null pointer, length 0). All three are copies in an
+//! engine-owned scratch buffer, valid for the call only: writing through
+//! them changes nothing. `out_flags_ptr` starts at 0; whatever the
+//! transform leaves there is the entry's annotation in
+//! `SECTION_ANNOTATIONS`.
+//!
+//! Returns: [`TRANSFORM_KEEP`], [`TRANSFORM_DROP`] or
+//! [`TRANSFORM_REDACT`]: keep the entry with each present side replaced
+//! by the marker, an empty string span flagged `ENTRY_FLAG_REDACTED`. Any
+//! other value fails the finalize (`InvalidTransformResult`).
+//!
+//! In wasm the function is an index into the module's function table,
+//! exported growable as `__indirect_function_table`: a host grows it by
+//! one, sets a wasm function there (for JS, a small module forwarding to
+//! the JS function; see `abi.test.mjs`) and passes the index. With no
+//! transform set finalize takes none of these steps.
+
+use crate::diff::{DiffEntry, ValueSpan};
+use crate::parser::ValueKind;
+use alloc::vec::Vec;
+
+pub type EntryTransform =
+    extern "C" fn(op: u32, path_ptr: *const u8, path_len: u32, left_ptr: *const u8, left_len: u32, right_ptr: *const u8, right_len: u32, out_flags_ptr: *mut u32) -> u32;
+
+pub const TRANSFORM_KEEP: u32 = 0;
+pub const TRANSFORM_DROP: u32 = 1;
+pub const TRANSFORM_REDACT: u32 = 2;
+
+/// What the transform made of one entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Keep { annotation: u32 },
+    Drop,
+    Redact { annotation: u32 },
+}
+
+/// Call `transform` on `d`, with copies in `scratch` of its `path` and
+/// of its payloads in the `left` and `right` inputs; `Err` with the
+/// return code if it is not one of `TRANSFORM_*`.
+pub fn call(transform: EntryTransform, scratch: &mut Vec<u8>, d: &DiffEntry, path: &[u8], left: &[u8], right: &[u8]) -> Result<Outcome, u32> {
+    let (left, right) = (payload(d.left_val, left), payload(d.right_val, right));
+    scratch.clear();
+    scratch.extend_from_slice(path);
+    let left_at = scratch.len();
+    scratch.extend_from_slice(left.unwrap_or_default());
+    let right_at = scratch.len();
+    scratch.extend_from_slice(right.unwrap_or_default());
+    let base = scratch.as_ptr();
+    // SAFETY: every offset is at most `scratch.len()`.
+    let at = |present: bool, offset: usize| if present { unsafe { base.add(offset) } } else { core::ptr::null() };
+    let len = |v: Option<&[u8]>| v.map_or(0, |b| b.len() as u32);
+    let mut annotation = 0u32;
+    let code = transform(
+        d.op as u32,
+        base,
+        path.len() as u32,
+        at(left.is_some(), left_at),
+        len(left),
+        at(right.is_some(), right_at),
+        len(right),
+        &mut annotation,
+    );
+    match code {
+        TRANSFORM_KEEP => Ok(Outcome::Keep { annotation }),
+        TRANSFORM_DROP => Ok(Outcome::Drop),
+        TRANSFORM_REDACT => Ok(Outcome::Redact { annotation }),
+        other => Err(other),
+    }
+}
+
+/// The bytes a side's span covers; a span outside `input` shows as empty.
+fn payload(v: Option<ValueSpan>, input: &[u8]) -> Option<&[u8]> {
+    v.map(|(offset, len, _)| input.get(offset as usize..(offset as usize).saturating_add(len as usize)).unwrap_or_default())
+}
+
+/// The redaction marker: an empty string at offset 0. No real value is
+/// one (a string body starts after its quote), so it is told apart from
+/// an empty string value.
+pub const MARKER: ValueSpan = (0, 0, ValueKind::String);
+
+/// The value hash of a redacted side (`SECTION_VALUE_HASHES`). Not the
+/// hash of anything the side held, nor of the empty string, so a
+/// redacted value neither reveals its hash nor matches a real one.
+pub const REDACTED_HASH: u64 = 0x5245_4441_4354_4544;
+
+/// Replace each present side of `d` with the redaction marker. Also how
+/// `redact_paths` redacts.
+pub fn redact(d: &mut DiffEntry) {
+    for side in [&mut d.left_val, &mut d.right_val] {
+        if let Some(v) = side.as_mut() { *v = MARKER; }
+    }
+}
+
+/// Whether `redact` replaced `d`'s sides.
+pub fn is_redacted(d: &DiffEntry) -> bool {
+    d.left_val == Some(MARKER) || d.right_val == Some(MARKER)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::diff::DiffOp;
+    use crate::path::ROOT_PATH_ID;
+
+    extern "C" fn scribble(op: u32, path: *const u8, path_len: u32, left: *const u8, _: u32, right: *const u8, right_len: u32, out: *mut u32) -> u32 {
+        // SAFETY: the pointers are `call`'s scratch copies.
+        unsafe {
+            assert_eq!(core::slice::from_raw_parts(path, path_len as usize), b"/a");
+            assert!(left.is_null());
+            assert_eq!(core::slice::from_raw_parts(right, right_len as usize), b"xyz");
+            (path as *mut u8).write(b'!');
+            *out = 0xa0 | op;
+        }
+        TRANSFORM_REDACT
+    }
+
+    extern "C" fn bad(_: u32, _: *const u8, _: u32, _: *const u8, _: u32, _: *const u8, _: u32, _: *mut u32) -> u32 { 7 }
+
+    #[test]
+    fn copies_in_outcome_out() {
+        let (path, right) = (b"/a".to_vec(), b"{\"k\":xyz}".to_vec());
+        let mut d = DiffEntry { op: DiffOp::Added, path_id: ROOT_PATH_ID, left_val: None, right_val: Some((5, 3, ValueKind::Literal)) };
+        let mut scratch = Vec::new();
+        let outcome = call(scribble, &mut scratch, &d, &path, b"", &right);
+        assert_eq!(outcome, Ok(Outcome::Redact { annotation: 0xa0 }));
+        assert_eq!((path.as_slice(), scratch.as_slice()), (&b"/a"[..], &b"!axyz"[..]), "only the copy changed");
+        assert_eq!(call(bad, &mut scratch, &d, &path, b"", &right), Err(7));
+
+        redact(&mut d);
+        assert_eq!((d.left_val, d.right_val), (None, Some((0, 0, ValueKind::String))));
+    }
+}
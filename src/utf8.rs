@@ -0,0 +1,119 @@
+//! UTF-8 validation of value payloads.
+//!
+//! The one validator for anything that needs to know whether input bytes
+//! are UTF-8: finalize uses it for the per-entry `ENTRY_FLAG_*_INVALID_UTF8`
+//! bits, and an input-side policy for invalid sequences should call the
+//! same [`first_invalid`] rather than grow its own. Likewise anything that
+//! shortens a payload cuts at [`floor_boundary`], so truncation alone
+//! never makes a valid payload invalid.
+//!
+//! Values are mostly ASCII, so the SIMD path only looks for a byte with
+//! the high bit set, 16 at a time. From the first such block on, the rest
+//! goes through `core::str::from_utf8`. A block of ASCII always ends on a
+//! character boundary, so starting there gives the same answer as
+//! validating the whole payload.
+
+/// Offset of the first byte that is not part of a valid UTF-8 sequence
+/// (including a sequence cut off by the end), `None` if `bytes` is valid.
+pub fn first_invalid(bytes: &[u8]) -> Option<usize> {
+    #[cfg(target_feature = "simd128")]
+    { first_invalid_v128(bytes) }
+    #[cfg(not(target_feature = "simd128"))]
+    { first_invalid_scalar(bytes) }
+}
+
+pub fn is_valid(bytes: &[u8]) -> bool { first_invalid(bytes).is_none() }
+
+/// The largest cut at or before `at` that does not split a UTF-8 sequence
+/// of `bytes`: `at`, unless it falls inside a sequence whose lead byte is
+/// at most 3 bytes back, then that lead. Invalid bytes are cut anywhere;
+/// past the end is `bytes.len()`.
+pub fn floor_boundary(bytes: &[u8], at: usize) -> usize {
+    let continuation = |b: u8| b & 0xc0 == 0x80;
+    if at >= bytes.len() || !continuation(bytes[at]) {
+        return at.min(bytes.len());
+    }
+    for back in 1..=at.min(3) {
+        let len = match bytes[at - back] {
+            b if continuation(b) => continue,
+            0xc0..=0xdf => 2,
+            0xe0..=0xef => 3,
+            0xf0..=0xf7 => 4,
+            _ => 1,
+        };
+        return if back < len { at - back } else { at };
+    }
+    at
+}
+
+#[cfg(target_feature = "simd128")]
+#[inline(always)]
+fn first_invalid_v128(bytes: &[u8]) -> Option<usize> {
+    use core::arch::wasm32::*;
+    let blocks = bytes.len() / 16;
+    let ptr = bytes.as_ptr();
+    let mut ascii = 0;
+    while ascii < blocks {
+        // SAFETY: block `ascii` is within `bytes`; `v128_load` is unaligned.
+        let chunk = unsafe { v128_load(ptr.add(ascii * 16) as *const v128) };
+        if u8x16_bitmask(chunk) != 0 { break; }
+        ascii += 1;
+    }
+    let start = ascii * 16;
+    first_invalid_scalar(&bytes[start..]).map(|at| start + at)
+}
+
+/// Portable [`first_invalid`], and the SIMD path's tail.
+#[inline(always)]
+fn first_invalid_scalar(bytes: &[u8]) -> Option<usize> {
+    core::str::from_utf8(bytes).err().map(|e| e.valid_up_to())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use alloc::vec::Vec;
+
+    #[test]
+    fn ascii_multibyte_and_invalid_payloads() {
+        let long_ascii = "key_".repeat(40);
+        assert!(is_valid(long_ascii.as_bytes()));
+        assert!(is_valid(b""));
+        // 2-, 3- and 4-byte sequences, across a 16-byte block boundary.
+        let multi = "0123456789abcdé€𝄞 and more text after it";
+        assert!(is_valid(multi.as_bytes()));
+
+        let mut cut = long_ascii.clone().into_bytes();
+        cut.extend_from_slice(&"€".as_bytes()[..2]);
+        assert_eq!(first_invalid(&cut), Some(160), "sequence cut off by the end");
+        let mut lone = b"0123456789abcdefghij".to_vec();
+        lone[17] = 0x80;
+        assert_eq!(first_invalid(&lone), Some(17), "continuation byte without a lead");
+        assert_eq!(first_invalid(b"\xc0\xaf"), Some(0), "overlong encoding");
+        assert_eq!(first_invalid(b"ab\xed\xa0\x80"), Some(2), "surrogate");
+    }
+
+    #[test]
+    fn boundaries_back_off_over_continuation_bytes() {
+        let text = "aé€𝄞"; // lead bytes at 0, 1, 3, 6; 10 bytes
+        let cuts: Vec<usize> = (0..=11).map(|at| floor_boundary(text.as_bytes(), at)).collect();
+        assert_eq!(cuts, [0, 1, 1, 3, 3, 3, 6, 6, 6, 6, 10, 10]);
+        // Invalid input: a stray continuation run, a lead too short for it.
+        assert_eq!(floor_boundary(b"a\x80\x80\x80\x80", 4), 4);
+        assert_eq!(floor_boundary(b"\xc3\xa9\x80", 2), 2);
+    }
+
+    #[test]
+    fn simd_and_scalar_agree() {
+        let mut bytes = Vec::new();
+        for i in 0..400u32 {
+            bytes.push(match i % 37 { 0 => 0xe2, 1 => 0x82, 2 => 0xac, 20 => 0xff, _ => b'a' + (i % 26) as u8 });
+        }
+        for start in 0..64 {
+            for end in (start..bytes.len()).step_by(7) {
+                let slice = &bytes[start..end];
+                assert_eq!(first_invalid(slice), first_invalid_scalar(slice), "{start}..{end}");
+            }
+        }
+    }
+}
@@ -0,0 +1,275 @@
+//! Strict well-formedness check of one document, for `validate_json`.
+//!
+//! The parser is built for speed on input it trusts: it skips what it
+//! does not need and accepts some malformed text (a stray comma, an
+//! unclosed container at the end). This pass accepts exactly RFC 8259
+//! JSON and says where the first problem is. It walks the same
+//! structural index as a commit (checked out of the ungrouped pool, as
+//! `probe` does), so string bodies are never scanned for brackets:
+//!
+//! - Between two structurals only whitespace (space, tab, LF, CR) and at
+//!   most one literal may sit: `true`, `false`, `null` or a number in
+//!   the JSON grammar (no leading zeros, no `+`, digits on both sides of
+//!   the point).
+//! - A string body holds no raw control character (below 0x20), only
+//!   escapes that decode (`escape::next_invalid`, so strict mode's rules)
+//!   and valid UTF-8 (`utf8::first_invalid`).
+//! - Brackets balance, members are `"key": value`, commas separate and
+//!   never trail, and one value fills the document.
+//!
+//! Containers are tracked on the heap, so nesting depth costs no stack.
+
+use crate::escape::{self, EscapeErrorKind};
+use crate::simd_index::quote_is_escaped;
+use alloc::vec::Vec;
+use core::fmt;
+
+/// What is wrong at the reported offset.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Malformed {
+    /// A byte where the grammar allows none: a stray comma or colon, an
+    /// unbalanced bracket, content after the root value.
+    UnexpectedByte(u8),
+    /// The document ends inside a value, or holds none.
+    UnexpectedEnd,
+    /// A literal other than `true`, `false`, `null` or a JSON number.
+    InvalidLiteral,
+    /// A string without its closing quote.
+    UnclosedString,
+    /// A raw control character inside a string.
+    ControlCharacter(u8),
+    InvalidEscape(EscapeErrorKind),
+    InvalidUtf8,
+}
+
+impl fmt::Display for Malformed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Malformed::UnexpectedByte(b) => write!(f, "unexpected byte 0x{:02x}", b),
+            Malformed::UnexpectedEnd => write!(f, "unexpected end of input"),
+            Malformed::InvalidLiteral => write!(f, "invalid literal"),
+            Malformed::UnclosedString => write!(f, "unclosed string"),
+            Malformed::ControlCharacter(b) => write!(f, "control character 0x{:02x} in string", b),
+            Malformed::InvalidEscape(kind) => write!(f, "{}", kind),
+            Malformed::InvalidUtf8 => write!(f, "invalid UTF-8"),
+        }
+    }
+}
+
+/// The first problem in a document, at input offset `offset`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Invalid {
+    pub offset: u32,
+    pub what: Malformed,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Expect {
+    Value,
+    ValueOrEnd,
+    KeyOrEnd,
+    Key,
+    Colon,
+    CommaOrEnd,
+    End,
+}
+
+/// Check that `input` is one well-formed JSON document.
+pub fn validate(input: &[u8]) -> Result<(), Invalid> {
+    let fail = |offset: usize, what| Err(Invalid { offset: offset as u32, what });
+    let mut index = crate::index_pool::checkout(None);
+    index.build(input);
+    let mut cursor = index.cursor();
+    // Open containers, innermost last: `{` or `[`.
+    let mut stack: Vec<u8> = Vec::new();
+    let mut expect = Expect::Value;
+    // End of the last structural or string handled.
+    let mut from = 0;
+    loop {
+        let next = cursor.next();
+        let at = next.unwrap_or(input.len());
+        if let Some((start, end)) = literal(input, from, at)? {
+            if !matches!(expect, Expect::Value | Expect::ValueOrEnd) {
+                return fail(start, Malformed::UnexpectedByte(input[start]));
+            }
+            if !is_literal(&input[start..end]) { return fail(start, Malformed::InvalidLiteral); }
+            expect = value_done(&stack);
+        }
+        let Some(pos) = next else {
+            return if expect == Expect::End { Ok(()) } else { fail(input.len(), Malformed::UnexpectedEnd) };
+        };
+        let b = input[pos];
+        match (expect, b) {
+            (Expect::Value | Expect::ValueOrEnd | Expect::KeyOrEnd | Expect::Key, b'"') => {
+                let start = pos + 1;
+                let Some(close) = cursor.by_ref().find(|&q| input[q] == b'"' && !quote_is_escaped(input, q, start)) else {
+                    return fail(pos, Malformed::UnclosedString);
+                };
+                check_string(input, start, close)?;
+                expect = match expect {
+                    Expect::KeyOrEnd | Expect::Key => Expect::Colon,
+                    _ => value_done(&stack),
+                };
+                from = close + 1;
+                continue;
+            }
+            (Expect::Value | Expect::ValueOrEnd, b'{' | b'[') => {
+                stack.push(b);
+                expect = if b == b'{' { Expect::KeyOrEnd } else { Expect::ValueOrEnd };
+            }
+            (Expect::Colon, b':') => expect = Expect::Value,
+            (Expect::CommaOrEnd, b',') => {
+                expect = if stack.last() == Some(&b'{') { Expect::Key } else { Expect::Value };
+            }
+            (Expect::KeyOrEnd | Expect::CommaOrEnd, b'}') if stack.last() == Some(&b'{') => {
+                stack.pop();
+                expect = value_done(&stack);
+            }
+            (Expect::ValueOrEnd | Expect::CommaOrEnd, b']') if stack.last() == Some(&b'[') => {
+                stack.pop();
+                expect = value_done(&stack);
+            }
+            _ => return fail(pos, Malformed::UnexpectedByte(b)),
+        }
+        from = pos + 1;
+    }
+}
+
+/// What follows a complete value.
+fn value_done(stack: &[u8]) -> Expect {
+    if stack.is_empty() { Expect::End } else { Expect::CommaOrEnd }
+}
+
+/// The span of the one literal between structurals at `from` and `to`,
+/// if any; a second one is an unexpected byte.
+fn literal(input: &[u8], from: usize, to: usize) -> Result<Option<(usize, usize)>, Invalid> {
+    let gap = &input[from..to];
+    let Some(first) = gap.iter().position(|&b| !is_whitespace(b)) else { return Ok(None) };
+    let len = gap[first..].iter().position(|&b| is_whitespace(b)).unwrap_or(gap.len() - first);
+    let (start, end) = (from + first, from + first + len);
+    match input[end..to].iter().position(|&b| !is_whitespace(b)) {
+        Some(n) => Err(Invalid { offset: (end + n) as u32, what: Malformed::UnexpectedByte(input[end + n]) }),
+        None => Ok(Some((start, end))),
+    }
+}
+
+fn is_whitespace(b: u8) -> bool { matches!(b, b' ' | b'\t' | b'\n' | b'\r') }
+
+fn is_literal(literal: &[u8]) -> bool {
+    matches!(literal, b"true" | b"false" | b"null") || is_number(literal)
+}
+
+/// `-? (0 | [1-9][0-9]*) (. [0-9]+)? ([eE] [+-]? [0-9]+)?`
+fn is_number(n: &[u8]) -> bool {
+    let digits = |from: usize| n[from.min(n.len())..].iter().take_while(|b| b.is_ascii_digit()).count();
+    let mut i = usize::from(n.first() == Some(&b'-'));
+    let int = digits(i);
+    if int == 0 || (int > 1 && n[i] == b'0') { return false; }
+    i += int;
+    if n.get(i) == Some(&b'.') {
+        let d = digits(i + 1);
+        if d == 0 { return false; }
+        i += 1 + d;
+    }
+    if matches!(n.get(i), Some(b'e' | b'E')) {
+        i += 1;
+        if matches!(n.get(i), Some(b'+' | b'-')) { i += 1; }
+        let d = digits(i);
+        if d == 0 { return false; }
+        i += d;
+    }
+    i == n.len()
+}
+
+/// Check the body `input[start..end]` of a string.
+fn check_string(input: &[u8], start: usize, end: usize) -> Result<(), Invalid> {
+    let body = &input[start..end];
+    if let Some(i) = body.iter().position(|&b| b < 0x20) {
+        return Err(Invalid { offset: (start + i) as u32, what: Malformed::ControlCharacter(body[i]) });
+    }
+    if let Some(e) = escape::next_invalid(body, 0) {
+        return Err(Invalid { offset: (start + e.offset) as u32, what: Malformed::InvalidEscape(e.kind) });
+    }
+    if let Some(i) = crate::utf8::first_invalid(body) {
+        return Err(Invalid { offset: (start + i) as u32, what: Malformed::InvalidUtf8 });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::gen::{generate, GenConfig};
+
+    fn problem(doc: &[u8]) -> Option<(u32, Malformed)> {
+        validate(doc).err().map(|e| (e.offset, e.what))
+    }
+
+    #[test]
+    fn well_formed_documents_pass() {
+        for doc in [
+            &br#"{"a":[1,-2.5e+3,0,0.25,1E9],"b":{"c":null,"d":true,"e":false},"f":"x\"\\\u00e9/"}"#[..],
+            b" \t\r\n[ ] ",
+            b"{}",
+            b"\"s\"",
+            b"-0",
+            b"[[[[]]]]",
+            "\"\u{e9}\u{1f600}\"".as_bytes(),
+        ] {
+            assert_eq!(problem(doc), None, "{}", String::from_utf8_lossy(doc));
+        }
+        for seed in 0..20 {
+            let doc = generate(&GenConfig::adversarial(), seed).to_json();
+            assert_eq!(problem(doc.as_bytes()), None, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn the_first_problem_is_reported_where_it_is() {
+        use Malformed::*;
+        let cases: &[(&[u8], u32, Malformed)] = &[
+            (b"", 0, UnexpectedEnd),
+            (b"   ", 3, UnexpectedEnd),
+            (br#"{"a":1"#, 6, UnexpectedEnd),
+            (br#"{"a":[1,2"#, 9, UnexpectedEnd),
+            (br#"{"a":"#, 5, UnexpectedEnd),
+            (br#"{"a":1,}"#, 7, UnexpectedByte(b'}')),
+            (b"[1,,2]", 3, UnexpectedByte(b',')),
+            (b"[,1]", 1, UnexpectedByte(b',')),
+            (b"[1 2]", 3, UnexpectedByte(b'2')),
+            (b"{\"a\" 1}", 5, UnexpectedByte(b'1')),
+            (br#"{"a":1}}"#, 7, UnexpectedByte(b'}')),
+            (br#"{"a":1]"#, 6, UnexpectedByte(b']')),
+            (b"[1] 2", 4, UnexpectedByte(b'2')),
+            (b"{1:2}", 1, UnexpectedByte(b'1')),
+            (br#"{"a":"b}"#, 5, UnclosedString),
+            (br#"["a\"]"#, 1, UnclosedString),
+            (b"\"a\nb\"", 2, ControlCharacter(b'\n')),
+            (br#""\x""#, 1, InvalidEscape(EscapeErrorKind::UnknownEscape)),
+            (br#""ab\ud800""#, 3, InvalidEscape(EscapeErrorKind::LoneSurrogate)),
+            (b"\"a\xff\"", 2, InvalidUtf8),
+            (b"[tru]", 1, InvalidLiteral),
+            (b"[01]", 1, InvalidLiteral),
+            (b"[1.]", 1, InvalidLiteral),
+            (b"[.5]", 1, InvalidLiteral),
+            (b"[+1]", 1, InvalidLiteral),
+            (b"[1e]", 1, InvalidLiteral),
+            (b"[-]", 1, InvalidLiteral),
+            (b"NaN", 0, InvalidLiteral),
+        ];
+        for &(doc, offset, what) in cases {
+            assert_eq!(problem(doc), Some((offset, what)), "{}", String::from_utf8_lossy(doc));
+        }
+    }
+
+    #[test]
+    fn deep_documents_cost_no_stack() {
+        let depth = 100_000;
+        let mut doc = Vec::new();
+        doc.resize(depth, b'[');
+        doc.resize(2 * depth, b']');
+        assert_eq!(problem(&doc), None);
+        doc.pop();
+        assert_eq!(problem(&doc), Some((2 * depth as u32 - 1, Malformed::UnexpectedEnd)));
+    }
+}
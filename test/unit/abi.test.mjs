@@ -0,0 +1,243 @@
+// Unit: raw ABI subset, straight against a .wasm artifact (no JS glue).
+// Defaults to the shipped module; point DIFFCORE_WASM at another build,
+// e.g. the no_std one (`npm run test:no-std`) or the scalar one without
+// simd128 (`npm run test:scalar`).
+import { test } from "node:test";
+import { strict as assert } from "node:assert";
+import { readFile } from "node:fs/promises";
+
+const wasmPath = process.env.DIFFCORE_WASM ?? new URL("../../dist/diffcore.wasm", import.meta.url);
+const { instance } = await WebAssembly.instantiate(await readFile(wasmPath), {});
+const abi = instance.exports;
+const enc = new TextEncoder();
+
+function commit(engine, side, text) {
+    const bytes = enc.encode(text);
+    const ptr = side === "left" ? abi.get_left_input_ptr(engine) : abi.get_right_input_ptr(engine);
+    new Uint8Array(abi.memory.buffer, ptr, bytes.length).set(bytes);
+    return side === "left" ? abi.commit_left(engine, bytes.length) : abi.commit_right(engine, bytes.length);
+}
+
+function result(engine) {
+    const ptr = abi.finalize(engine);
+    assert.notEqual(ptr, 0);
+    return new DataView(abi.memory.buffer, ptr, abi.get_result_len(engine));
+}
+
+test("abi: canonical sequence yields a v2 result", () => {
+    const engine = abi.create_engine(0, 0);
+    assert.notEqual(engine, 0);
+    assert.equal(commit(engine, "left", '{"a":1,"b":"x"}'), 0);
+    assert.equal(commit(engine, "right", '{"a":2,"c":true}'), 0);
+    const view = result(engine);
+    assert.equal(view.getUint16(0, true), 2, "format major");
+    const version = (view.getUint16(0, true) << 16) | view.getUint16(2, true);
+    assert.equal(abi.get_format_version(), version);
+    assert.equal(abi.get_engine_format_version(engine), version);
+    assert.equal(view.getUint32(4, true), 3, "entry count");
+    assert.equal(abi.get_last_error_len(engine), 0);
+    assert.equal(abi.destroy_engine(engine), 0);
+});
+
+test("abi: clear_engine allows reuse", () => {
+    const engine = abi.create_engine(0, 0);
+    for (const [l, r, n] of [["[1]", "[2]", 1], ["[1,2]", "[1,2]", 0]]) {
+        commit(engine, "left", l);
+        commit(engine, "right", r);
+        assert.equal(abi.get_entry_count(engine), 0, "before finalize");
+        assert.equal(result(engine).getUint32(4, true), n);
+        assert.equal(abi.get_entry_count(engine), n);
+        assert.equal(abi.clear_engine(engine), 0);
+    }
+    abi.destroy_engine(engine);
+});
+
+test("abi: sealed engine and null handle are rejected", () => {
+    const engine = abi.create_engine(0, 0);
+    assert.equal(abi.finalize(engine), 0, "no input committed");
+    assert.equal(abi.get_result_len(engine), 0);
+    assert.equal(commit(engine, "left", ""), 0);
+    result(engine);
+    assert.equal(abi.commit_right(engine, 0), 3, "EngineSealed");
+    abi.destroy_engine(engine);
+    assert.equal(abi.commit_left(0, 0), 4, "InvalidHandle");
+    assert.equal(abi.finalize(0), 0);
+});
+
+function createError() {
+    const len = abi.get_create_error_len();
+    return len === 0 ? null : new TextDecoder().decode(new Uint8Array(abi.memory.buffer, abi.get_create_error(), len));
+}
+
+test("abi: self_test passes on this build", () => {
+    // An 8-byte config, staged in another engine's input buffer.
+    const engine = abi.create_engine(0, 0);
+    const staged = abi.get_left_input_ptr(engine);
+    new Uint8Array(abi.memory.buffer, staged, 8).fill(1);
+    assert.equal(abi.create_engine(staged, 8), 0);
+    assert.equal(createError(), "invalid config: config truncated");
+    abi.destroy_engine(engine);
+
+    assert.equal(abi.self_test(), 0, createError() ?? "");
+    assert.equal(createError(), null);
+    assert.equal(abi.get_create_error_code(), 0);
+});
+
+// A wasm function forwarding to `f`, so a JS function can go into the
+// module's table (only wasm functions can): the 8 x i32 -> i32 transform.
+function trampoline(f) {
+    const i32 = 0x7f;
+    const body = [0x00, ...[0, 1, 2, 3, 4, 5, 6, 7].flatMap((i) => [0x20, i]), 0x10, 0x00, 0x0b];
+    const bytes = new Uint8Array([
+        0x00, 0x61, 0x73, 0x6d, 0x01, 0x00, 0x00, 0x00,
+        0x01, 0x0d, 0x01, 0x60, 0x08, ...Array(8).fill(i32), 0x01, i32,
+        0x02, 0x09, 0x01, 0x03, 0x65, 0x6e, 0x76, 0x01, 0x66, 0x00, 0x00,
+        0x03, 0x02, 0x01, 0x00,
+        0x07, 0x05, 0x01, 0x01, 0x66, 0x00, 0x01,
+        0x0a, body.length + 2, 0x01, body.length, ...body,
+    ]);
+    const module = new WebAssembly.Module(bytes);
+    return new WebAssembly.Instance(module, { env: { f } }).exports.f;
+}
+
+test("abi: a table-registered entry transform drops and redacts", () => {
+    const table = abi.__indirect_function_table;
+    const dec = new TextDecoder();
+    const seen = [];
+    const index = table.grow(1);
+    table.set(index, trampoline((op, pathPtr, pathLen, leftPtr, leftLen, rightPtr, rightLen, outPtr) => {
+        const path = dec.decode(new Uint8Array(abi.memory.buffer, pathPtr, pathLen));
+        const right = rightPtr === 0 ? "" : dec.decode(new Uint8Array(abi.memory.buffer, rightPtr, rightLen));
+        seen.push(path);
+        new DataView(abi.memory.buffer).setUint32(outPtr, 7, true);
+        if (path.startsWith("/internal")) return 1;
+        return right.includes("secret") ? 2 : 0;
+    }));
+
+    const engine = abi.create_engine(0, 0);
+    assert.equal(abi.set_entry_transform(engine, index), 0);
+    commit(engine, "left", '{"internal":1,"a":1,"b":"x"}');
+    commit(engine, "right", '{"internal":2,"a":2,"b":"a secret"}');
+    const view = result(engine);
+    assert.deepEqual(seen.sort(), ["/a", "/b", "/internal"]);
+    assert.equal(view.getUint32(4, true), 2, "/internal dropped");
+    const flags = [view.getUint8(16 + 3), view.getUint8(48 + 3)];
+    assert.deepEqual(flags.map((f) => f & (1 << 5)), [0, 1 << 5], "/b redacted");
+    assert.equal(view.getUint32(48 + 28, true), 0, "redacted right span is empty");
+    abi.destroy_engine(engine);
+});
+
+test("abi: finalize_step in slices matches finalize", () => {
+    const doc = (v) => JSON.stringify({ items: Array.from({ length: 200 }, (_, i) => ({ id: i, v: i % 5 ? i : v })) });
+    const bytes = [];
+    // Budget 0 here: no steps, `finalize` alone.
+    for (const budget of [0, 64]) {
+        const engine = abi.create_engine(0, 0);
+        commit(engine, "left", doc(1));
+        commit(engine, "right", doc(2));
+        let steps = 0;
+        if (budget > 0) {
+            for (let s; (s = abi.finalize_step(engine, budget)) !== 0; steps++) {
+                assert.equal(s, 9, "InProgress");
+                assert.equal(abi.get_progress(engine) >>> 16, 3, "Diff phase");
+            }
+            assert.ok(steps > 10, `${steps} steps`);
+        }
+        const view = result(engine);
+        bytes.push(new Uint8Array(view.buffer, view.byteOffset, view.byteLength).slice());
+        abi.destroy_engine(engine);
+    }
+    assert.deepEqual(bytes[1], bytes[0]);
+});
+
+test("abi: get_result_page reads the result in 4 KB pages", () => {
+    const engine = abi.create_engine(0, 0);
+    commit(engine, "left", JSON.stringify(Array(1000).fill(0)));
+    commit(engine, "right", JSON.stringify(Array(1000).fill(1)));
+    const view = result(engine);
+    const want = new Uint8Array(view.buffer, view.byteOffset, view.byteLength).slice();
+    // The pages land in another engine's input buffer, a region the host owns.
+    const staging = abi.create_engine(0, 0);
+    const page = abi.get_left_input_ptr(staging);
+    const chunks = [];
+    for (let offset = 0, n; (n = abi.get_result_page(engine, offset, page, 4096)) !== 0; offset += n) {
+        assert.ok(n <= 4096);
+        chunks.push(new Uint8Array(abi.memory.buffer, page, n).slice());
+    }
+    assert.equal(chunks[0].length, 16 + 127 * 32, "header and whole entries");
+    assert.deepEqual(Buffer.concat(chunks), Buffer.from(want));
+    abi.destroy_engine(staging);
+    abi.destroy_engine(engine);
+});
+
+test("abi: entry accessors read ops, paths and values in place", () => {
+    const engine = abi.create_engine(0, 0);
+    commit(engine, "left", '{"a":1,"b/c":"x"}');
+    commit(engine, "right", '{"a":2,"d":true}');
+    result(engine);
+    const text = (ptr, len) => (ptr === 0 ? null : new TextDecoder().decode(new Uint8Array(abi.memory.buffer, ptr, len)));
+    const entries = [];
+    for (let i = 0; i < abi.get_entry_count(engine); i++) {
+        entries.push([
+            abi.get_entry_op(engine, i),
+            text(abi.get_entry_path(engine, i), abi.get_entry_path_len(engine, i)),
+            text(abi.get_entry_left(engine, i), abi.get_entry_left_len(engine, i)),
+            text(abi.get_entry_right(engine, i), abi.get_entry_right_len(engine, i)),
+        ]);
+    }
+    entries.sort((x, y) => (x[1] < y[1] ? -1 : 1));
+    assert.deepEqual(entries, [[2, "/a", "1", "2"], [1, "/b~1c", "x", null], [0, "/d", null, "true"]]);
+    assert.equal(abi.get_entry_op(engine, 3), 0xff, "past the last entry");
+    assert.equal(abi.get_entry_path(engine, 3), 0);
+    abi.destroy_engine(engine);
+});
+
+test("abi: resolve_symbol turns a result path id into a dotted path", () => {
+    const engine = abi.create_engine(0, 0);
+    commit(engine, "left", '{"a":{"b":[1]}}');
+    commit(engine, "right", '{"a":{"b":[2]}}');
+    const view = result(engine);
+    const pathId = view.getBigUint64(16 + 8, true);
+    const ptr = abi.resolve_symbol(engine, pathId);
+    const len = abi.resolve_symbol_len(engine, pathId);
+    assert.equal(new TextDecoder().decode(new Uint8Array(abi.memory.buffer, ptr, len)), "a.b[0]");
+    assert.equal(abi.resolve_symbol(engine, pathId ^ 1n), 0, "an id neither side has");
+    abi.destroy_engine(engine);
+});
+
+test("abi: get_symbol_table lists each result path id once", () => {
+    const engine = abi.create_engine(0, 0);
+    commit(engine, "left", '{"a":{"b":[1,2]},"c":1}');
+    commit(engine, "right", '{"a":{"b":[3,2]},"c":2}');
+    const view = result(engine);
+    const ptr = abi.get_symbol_table(engine);
+    const table = new DataView(abi.memory.buffer, ptr, abi.get_symbol_table_len(engine));
+    const symbols = new Map();
+    for (let at = 0; at < table.byteLength;) {
+        const len = table.getUint32(at + 8, true);
+        symbols.set(table.getBigUint64(at, true), new TextDecoder().decode(new Uint8Array(abi.memory.buffer, ptr + at + 12, len)));
+        at += 12 + len;
+    }
+    assert.deepEqual([...symbols.values()], ["a.b[0]", "c"]);
+    for (let i = 0; i < view.getUint32(4, true); i++) {
+        assert.ok(symbols.has(view.getBigUint64(16 + i * 32 + 8, true)));
+    }
+    abi.destroy_engine(engine);
+});
+
+test("abi: alloc_aligned honours the alignment, and input buffers are 64-byte aligned", () => {
+    for (const align of [8, 64, 4096]) {
+        const ptr = abi.alloc_aligned(100, align);
+        assert.notEqual(ptr, 0);
+        assert.equal(ptr % align, 0);
+        assert.equal(abi.dealloc(ptr), 0);
+    }
+    const ptr = abi.alloc(100);
+    assert.notEqual(ptr, 0);
+    assert.equal(abi.dealloc_aligned(ptr, 100, 1), 0, "the two-argument form frees alike");
+    assert.equal(abi.alloc_aligned(100, 3), 0, "not a power of two");
+    const engine = abi.create_engine(0, 0);
+    assert.equal(abi.get_left_input_ptr(engine) % 64, 0);
+    assert.equal(abi.get_right_input_ptr(engine) % 64, 0);
+    abi.destroy_engine(engine);
+});
@@ -1,7 +1,7 @@
 // Unit: config defaults + EDGE_CONFIG shape.
 import { test } from "node:test";
 import { strict as assert } from "node:assert";
-import { diff, EDGE_CONFIG, ArrayDiffMode } from "../../dist/index.js";
+import { diff, EDGE_CONFIG, ArrayDiffMode, toJsonPatch } from "../../dist/index.js";
 
 test("config: diff() runs with no options at all", async () => {
     const result = await diff('{"a":1}', '{"a":2}');
@@ -39,3 +39,21 @@ test("config: resolvePaths:false produces hash-format paths", async () => {
     const result = await diff('{"a":1}', '{"a":2}', { resolvePaths: false });
     assert.match(result.entries[0].path, /^#hash:/, "hash format when path resolution disabled");
 });
+
+test("config: redactPaths hides matching values, leaves siblings alone", async () => {
+    const left = '{"credentials":{"token":"a"},"user":{"password":"x","name":"ann"}}';
+    const right = '{"credentials":{"token":"b"},"user":{"password":"y","name":"bob"}}';
+    const result = await diff(left, right, { redactPaths: [".credentials.*", "**.password"] });
+    const byPath = Object.fromEntries(result.entries.map((e) => [e.path, e]));
+    assert.equal(byPath["/credentials/token"].leftValue, "[REDACTED]");
+    assert.equal(byPath["/credentials/token"].rightValue, "[REDACTED]");
+    assert.equal(byPath["/user/password"].rightValue, "[REDACTED]");
+    assert.equal(byPath["/user/name"].rightValue, "bob");
+    const patch = toJsonPatch(result);
+    assert.ok(!JSON.stringify(patch).includes('"y"'), "no secret in the patch");
+});
+
+test("config: redactMarker replaces the default marker", async () => {
+    const result = await diff('{"pin":1}', '{"pin":2}', { redactPaths: ["pin"], redactMarker: "***" });
+    assert.equal(result.entries[0].rightValue, "***");
+});
@@ -90,3 +90,14 @@ test("path-index: hashes match the Rust engine for ASCII keys", () => {
     })();
     assert.equal(foldSegment(0n, enc("name")), expected);
 });
+
+test("path-index: the empty key has its own id and pointer", () => {
+    // Pinned in `fold_hash_golden_values` in src/path.rs.
+    const users = foldSegment(0n, enc("users"));
+    assert.equal(foldSegment(0n, enc("")), 0x100n);
+    assert.equal(foldSegment(users, enc("")), 0x91b17dcaf8a9d530n);
+
+    const index = buildPathIndex(enc('{"":1,"a":{"":2}}'));
+    assert.equal(index.byPathId.get(foldSegment(0n, enc("")))?.pointer, "/");
+    assert.equal(index.byPathId.get(foldSegment(foldSegment(0n, enc("a")), enc("")))?.pointer, "/a/");
+});
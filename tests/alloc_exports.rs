@@ -0,0 +1,208 @@
+//! `alloc` / `alloc_aligned` / `realloc` / `dealloc` under a tracking global
+//! allocator: every block comes back aligned as asked, and freeing it
+//! returns every byte with exactly the layout it was allocated with; and
+//! `diff_direct` saves the input copies at the peak. One test, since the
+//! mismatch counter is process-wide. Live bytes are counted per thread:
+//! the harness allocates on its own threads while the test runs.
+
+use diffcore::{
+    alloc, alloc_aligned, clear_engine, commit_left, commit_right, create_engine, dealloc, dealloc_aligned, destroy_engine, diff_direct, finalize,
+    get_left_input_ptr, get_result_len, get_right_input_ptr, realloc, EngineConfig, Status,
+};
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Records each allocation's layout in front of it and checks the
+/// layout `dealloc` is given against it.
+struct Tracking;
+
+std::thread_local! {
+    /// Bytes this thread allocated less those it freed; wraps when it
+    /// frees another thread's blocks.
+    static LIVE_BYTES: Cell<usize> = const { Cell::new(0) };
+    /// Most `LIVE_BYTES` has been since `reset_peak`.
+    static PEAK_BYTES: Cell<usize> = const { Cell::new(0) };
+}
+static MISMATCHES: AtomicUsize = AtomicUsize::new(0);
+
+const HEADER: usize = 16;
+
+fn padded(layout: Layout) -> Layout {
+    let pad = layout.align().max(HEADER);
+    Layout::from_size_align(layout.size() + pad, pad).unwrap()
+}
+
+unsafe impl GlobalAlloc for Tracking {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let outer = padded(layout);
+        let base = System.alloc(outer);
+        if base.is_null() { return base; }
+        let ptr = base.add(outer.align());
+        (ptr.sub(HEADER) as *mut [usize; 2]).write_unaligned([layout.size(), layout.align()]);
+        let live = LIVE_BYTES.with(|b| {
+            b.set(b.get().wrapping_add(layout.size()));
+            b.get()
+        });
+        PEAK_BYTES.with(|p| p.set(p.get().max(live)));
+        ptr
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let [size, align] = (ptr.sub(HEADER) as *const [usize; 2]).read_unaligned();
+        if (size, align) != (layout.size(), layout.align()) {
+            MISMATCHES.fetch_add(1, Ordering::SeqCst);
+        }
+        let outer = padded(Layout::from_size_align(size, align).unwrap());
+        System.dealloc(ptr.sub(outer.align()), outer);
+        LIVE_BYTES.with(|b| b.set(b.get().wrapping_sub(size)));
+    }
+}
+
+fn live_bytes() -> usize { LIVE_BYTES.with(Cell::get) }
+
+/// Peak live bytes since the last call, from now.
+fn reset_peak() { PEAK_BYTES.with(|p| p.set(live_bytes())); }
+
+fn peak_bytes() -> usize { PEAK_BYTES.with(Cell::get) }
+
+#[global_allocator]
+static TRACKING: Tracking = Tracking;
+
+/// Deterministic sizes: small, around powers of two, and large.
+fn sizes(n: usize) -> impl Iterator<Item = u32> {
+    let mut x = 0x9E37_79B9u32;
+    (0..n).map(move |i| {
+        x ^= x << 13;
+        x ^= x >> 17;
+        x ^= x << 5;
+        match i % 4 {
+            0 => 1 + x % 64,
+            1 => (1u32 << (x % 16)).saturating_sub(8) + (x >> 28),
+            2 => 1 + x % 4096,
+            _ => 1 + x % (1 << 20),
+        }
+        .max(1)
+    })
+}
+
+#[test]
+fn allocations_are_aligned_and_freed_with_their_layout() {
+    // Under `handles` the registry's slot table outlives its engines;
+    // allocate it before counting.
+    #[cfg(feature = "handles")]
+    assert_eq!(destroy_engine(create_engine(std::ptr::null(), 0)), Status::Ok);
+    let before = live_bytes();
+    let aligns = [1u32, 8, 16, 64, 4096];
+    let mut blocks = Vec::with_capacity(4000);
+    for (i, len) in sizes(4000).enumerate() {
+        let align = aligns[i % aligns.len()];
+        let ptr = if i % 3 == 0 { alloc(len) } else { alloc_aligned(len, align) };
+        assert!(!ptr.is_null());
+        let want = if i % 3 == 0 { 16 } else { align.max(16) } as usize;
+        assert_eq!(ptr as usize % want, 0, "len {} align {}", len, align);
+        // The whole block is writable, header untouched.
+        unsafe { std::ptr::write_bytes(ptr, 0xAB, len as usize) };
+        blocks.push((ptr, len, align));
+    }
+    let held = live_bytes() - before;
+    assert!(held >= blocks.iter().map(|b| b.1 as usize).sum::<usize>());
+    // `blocks` itself is counted too; free it after the checks.
+    let vec_bytes = blocks.capacity() * std::mem::size_of::<(*mut u8, u32, u32)>();
+    for (i, &(ptr, len, align)) in blocks.iter().enumerate() {
+        // The old two-argument form frees the same way.
+        let status = if i % 2 == 0 { dealloc(ptr) } else { dealloc_aligned(ptr, len, align) };
+        assert_eq!(status, Status::Ok);
+    }
+    assert_eq!(live_bytes() - before, vec_bytes, "every block freed");
+    drop(blocks);
+
+    // A streaming scratch buffer grown from 16 bytes to 8 MiB, a chunk at
+    // a time, keeps every byte written so far and its alignment.
+    let (mut buf, mut len) = (alloc_aligned(16, 64), 16u32);
+    let fill = |i: u32| (i.wrapping_mul(31) >> 3) as u8;
+    for i in 0..len { unsafe { *buf.add(i as usize) = fill(i) }; }
+    while len < 8 << 20 {
+        let grown = (len + len / 2 + 7).min(8 << 20);
+        let next = realloc(buf, len, grown);
+        assert!(!next.is_null());
+        assert_eq!(next as usize % 64, 0, "grown to {}", grown);
+        let kept = unsafe { std::slice::from_raw_parts(next, len as usize) };
+        assert!(kept.iter().enumerate().all(|(i, &b)| b == fill(i as u32)), "contents lost growing to {}", grown);
+        for i in len..grown { unsafe { *next.add(i as usize) = fill(i) }; }
+        (buf, len) = (next, grown);
+    }
+    // Shrinking keeps the prefix; a refused resize leaves the block usable.
+    buf = realloc(buf, len, 100);
+    assert!(unsafe { std::slice::from_raw_parts(buf, 100) }.iter().enumerate().all(|(i, &b)| b == fill(i as u32)));
+    assert!(realloc(buf, 100, 0).is_null());
+    assert_eq!(unsafe { *buf.add(99) }, fill(99));
+    assert!(realloc(std::ptr::null_mut(), 0, 16).is_null());
+    assert_eq!(dealloc(buf), Status::Ok);
+    assert_eq!(live_bytes(), before);
+
+    // Refused without allocating or freeing.
+    assert!(alloc(0).is_null());
+    assert!(alloc_aligned(0, 64).is_null());
+    assert!(alloc_aligned(16, 3).is_null());
+    assert!(alloc_aligned(16, 0).is_null());
+    assert_eq!(dealloc(std::ptr::null_mut()), Status::Error);
+    assert_eq!(dealloc_aligned(std::ptr::null_mut(), 16, 64), Status::Error);
+    assert_eq!(live_bytes(), before);
+
+    // The engine's input buffers are 64-byte aligned, and go with it.
+    let config = EngineConfig { max_input_size: 1001, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(config.as_ptr(), config.len() as u32);
+    assert!(!engine.is_null());
+    assert_eq!(get_left_input_ptr(engine) as usize % 64, 0);
+    assert_eq!(get_right_input_ptr(engine) as usize % 64, 0);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!(destroy_engine(engine), Status::Ok);
+    drop(config);
+    assert_eq!(live_bytes(), before, "engine freed everything");
+
+    // Documents the host wrote with `alloc`: copied into the input
+    // buffers, or read where they are by `diff_direct`. The copy costs a
+    // second document's worth of memory at the peak; reading in place
+    // leaves the input buffers unallocated.
+    let doc = |v: u32| format!("[{}]", vec![format!(r#"{{"k":{v},"s":"padding padding"}}"#); 20_000].join(","));
+    let (left, right) = (doc(1), doc(2));
+    let inputs = left.len() + right.len();
+    let host = |doc: &str| {
+        let ptr = alloc(doc.len() as u32);
+        unsafe { std::ptr::copy_nonoverlapping(doc.as_ptr(), ptr, doc.len()) };
+        ptr
+    };
+    let config = EngineConfig { max_input_size: (2 * left.len()) as u32, ..EngineConfig::default() }.to_bytes();
+    let peak = |direct: bool| {
+        let (l, r) = (host(&left), host(&right));
+        reset_peak();
+        let start = live_bytes();
+        let engine = create_engine(config.as_ptr(), config.len() as u32);
+        if direct {
+            assert_eq!(diff_direct(engine, l, left.len() as u32, r, right.len() as u32), Status::Ok);
+        } else {
+            unsafe {
+                std::ptr::copy_nonoverlapping(l, get_left_input_ptr(engine), left.len());
+                std::ptr::copy_nonoverlapping(r, get_right_input_ptr(engine), right.len());
+            }
+            assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+            assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        }
+        assert!(!finalize(engine).is_null());
+        let len = get_result_len(engine);
+        assert_eq!(destroy_engine(engine), Status::Ok);
+        assert_eq!((dealloc(l), dealloc(r)), (Status::Ok, Status::Ok));
+        (peak_bytes() - start, len)
+    };
+    // Structural indexes stay pooled between commits: warm the pool so
+    // both runs reuse the same ones.
+    peak(true);
+    let pooled = live_bytes();
+    let (copied, direct) = (peak(false), peak(true));
+    assert_eq!(copied.1, direct.1, "same result either way");
+    let saved = copied.0 - direct.0;
+    assert!((inputs..inputs + inputs / 8).contains(&saved), "saved {} of {} input bytes", saved, inputs);
+    assert_eq!(live_bytes(), pooled, "direct diffs freed everything");
+    assert_eq!(MISMATCHES.load(Ordering::SeqCst), 0, "every free used its allocation's layout");
+}
@@ -0,0 +1,183 @@
+//! Array alignment quality against ground truth (feature `eval`).
+//!
+//! `gen::mutate_arrays` edits leaf arrays of generated documents and says
+//! exactly what it did. The minimal result for those edits is one entry
+//! per `Change`, `Add` and `Remove` and two per `Move` (`Removed` at
+//! `from`, `Added` at `to`). Each array mode diffs the same pairs, for each
+//! edit kind alone and for a mix, at several densities, and is scored on:
+//!
+//! - precision: reported entries that are in the minimal result;
+//! - recall: minimal entries that were reported;
+//! - inflation: reported entries per minimal entry.
+//!
+//! `THRESHOLDS` holds each mode's floor on precision and recall and cap on
+//! inflation, over all scenarios together. They sit a little below what
+//! the mode scores today, so a heuristic that aligns worse fails here; a
+//! change that aligns better raises them. `HashWindow` and `Full` align by
+//! index in this tree, so their floors are `Index`'s until they do more.
+//!
+//! ```text
+//! cargo test --features eval --target <host triple> -- --ignored array_quality --nocapture
+//! ```
+//!
+//! `--nocapture` shows the per-scenario report when the test passes; a
+//! failing run prints it regardless.
+
+#![cfg(feature = "eval")]
+
+use diffcore::gen::{generate, mutate_arrays, Edit, EditKind, GenConfig};
+use diffcore::{ArrayDiffMode, Engine, EngineConfig, Status};
+use std::collections::HashSet;
+use std::fmt::Write;
+
+const ADDED: u8 = 0;
+const REMOVED: u8 = 1;
+const MODIFIED: u8 = 2;
+
+/// Documents per scenario.
+const SEEDS: u64 = 40;
+/// Edits per document.
+const DENSITIES: [usize; 3] = [1, 4, 16];
+
+/// Per-mode floors and caps, over every scenario.
+struct Threshold {
+    mode: ArrayDiffMode,
+    min_precision: f64,
+    min_recall: f64,
+    max_inflation: f64,
+}
+
+const THRESHOLDS: [Threshold; 3] = [
+    Threshold { mode: ArrayDiffMode::Index, min_precision: 0.05, min_recall: 0.22, max_inflation: 4.6 },
+    Threshold { mode: ArrayDiffMode::HashWindow, min_precision: 0.05, min_recall: 0.22, max_inflation: 4.6 },
+    Threshold { mode: ArrayDiffMode::Full, min_precision: 0.05, min_recall: 0.22, max_inflation: 4.6 },
+];
+
+const SCENARIOS: [(&str, &[EditKind]); 5] = [
+    ("change", &[EditKind::Change]),
+    ("insert", &[EditKind::Add]),
+    ("delete", &[EditKind::Remove]),
+    ("move", &[EditKind::Move]),
+    ("mixed", &[EditKind::Change, EditKind::Add, EditKind::Remove, EditKind::Move]),
+];
+
+/// Array-heavy documents: most containers are arrays of 4 to 24 leaves.
+fn array_config() -> GenConfig {
+    GenConfig { depth: 3, fan_out: (2, 6), array_len: (4, 24), container_pct: 70, ..GenConfig::default() }
+}
+
+/// Entries as `(op, JSON Pointer)`.
+type Entries = HashSet<(u8, String)>;
+
+fn minimal(edits: &[Edit]) -> Entries {
+    let mut out = Entries::new();
+    for e in edits {
+        match e {
+            Edit::Change { path } => out.insert((MODIFIED, path.clone())),
+            Edit::Add { path } => out.insert((ADDED, path.clone())),
+            Edit::Remove { path } => out.insert((REMOVED, path.clone())),
+            Edit::Move { from, to } => out.insert((REMOVED, from.clone())) | out.insert((ADDED, to.clone())),
+        };
+    }
+    out
+}
+
+fn reported(mode: ArrayDiffMode, left: &[u8], right: &[u8]) -> Entries {
+    let max_input_size = (2 * left.len().max(right.len())).max(1024) as u32;
+    let config = EngineConfig { array_diff_mode: mode, max_input_size, ..EngineConfig::default() };
+    let mut engine = Engine::new(config, 0).expect("engine");
+    // SAFETY: each input buffer holds `max_input_size / 2` bytes.
+    unsafe {
+        core::ptr::copy_nonoverlapping(left.as_ptr(), engine.left_input_ptr(), left.len());
+        core::ptr::copy_nonoverlapping(right.as_ptr(), engine.right_input_ptr(), right.len());
+    }
+    assert_eq!(engine.commit_left(left.len() as u32), Status::Ok);
+    assert_eq!(engine.commit_right(right.len() as u32), Status::Ok);
+    engine.finalize().expect("finalize");
+    (0..engine.entry_count())
+        .map(|i| {
+            let op = engine.entry_op(i).unwrap();
+            (op, String::from_utf8(engine.entry_path(i).unwrap().to_vec()).unwrap())
+        })
+        .collect()
+}
+
+/// Running totals for one mode.
+#[derive(Default, Clone, Copy)]
+struct Score {
+    reported: usize,
+    minimal: usize,
+    hits: usize,
+}
+
+impl Score {
+    fn add(&mut self, other: Score) {
+        self.reported += other.reported;
+        self.minimal += other.minimal;
+        self.hits += other.hits;
+    }
+
+    fn precision(&self) -> f64 {
+        if self.reported == 0 { 1.0 } else { self.hits as f64 / self.reported as f64 }
+    }
+
+    fn recall(&self) -> f64 {
+        if self.minimal == 0 { 1.0 } else { self.hits as f64 / self.minimal as f64 }
+    }
+
+    fn inflation(&self) -> f64 {
+        self.reported as f64 / self.minimal.max(1) as f64
+    }
+}
+
+fn score(mode: ArrayDiffMode, kinds: &[EditKind], edits: usize) -> Score {
+    let mut total = Score::default();
+    for seed in 0..SEEDS {
+        let doc = generate(&array_config(), seed);
+        let (edited, log) = mutate_arrays(&doc, edits, kinds, seed);
+        let want = minimal(&log);
+        let got = reported(mode, doc.to_json().as_bytes(), edited.to_json().as_bytes());
+        total.add(Score { reported: got.len(), minimal: want.len(), hits: got.intersection(&want).count() });
+    }
+    total
+}
+
+#[test]
+#[ignore = "quality report; run with --features eval -- --ignored"]
+fn array_quality() {
+    let mut report = String::new();
+    let mut failures = Vec::new();
+    writeln!(report, "{:<12} {:<8} {:>5} {:>9} {:>7} {:>9}", "mode", "edits", "per", "precision", "recall", "inflation").unwrap();
+    for t in &THRESHOLDS {
+        let mut overall = Score::default();
+        for (name, kinds) in SCENARIOS {
+            for edits in DENSITIES {
+                let s = score(t.mode, kinds, edits);
+                overall.add(s);
+                writeln!(
+                    report,
+                    "{:<12} {:<8} {:>5} {:>9.3} {:>7.3} {:>9.2}",
+                    t.mode.name(), name, edits, s.precision(), s.recall(), s.inflation()
+                )
+                .unwrap();
+            }
+        }
+        writeln!(
+            report,
+            "{:<12} {:<8} {:>5} {:>9.3} {:>7.3} {:>9.2}",
+            t.mode.name(), "overall", "", overall.precision(), overall.recall(), overall.inflation()
+        )
+        .unwrap();
+        if overall.precision() < t.min_precision {
+            failures.push(format!("{}: precision {:.3} < {}", t.mode.name(), overall.precision(), t.min_precision));
+        }
+        if overall.recall() < t.min_recall {
+            failures.push(format!("{}: recall {:.3} < {}", t.mode.name(), overall.recall(), t.min_recall));
+        }
+        if overall.inflation() > t.max_inflation {
+            failures.push(format!("{}: inflation {:.2} > {}", t.mode.name(), overall.inflation(), t.max_inflation));
+        }
+    }
+    println!("{}", report);
+    assert!(failures.is_empty(), "array alignment below threshold:\n{}", failures.join("\n"));
+}
@@ -0,0 +1,51 @@
+//! The `bindgen` feature's `DiffEngine` class (`src/bindgen.rs`), end to
+//! end in a wasm runtime:
+//! `wasm-pack test --node -- --features bindgen --test bindgen`.
+
+#[cfg(all(feature = "bindgen", target_arch = "wasm32"))]
+mod bindgen {
+    use diffcore::reader::ResultReader;
+    use diffcore::DiffEngine;
+    use wasm_bindgen::JsValue;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    fn field(entry: &JsValue, key: &str) -> Option<String> {
+        js_sys::Reflect::get(entry, &key.into()).unwrap().as_string()
+    }
+
+    #[wasm_bindgen_test]
+    fn pushed_chunks_diff_into_entries() {
+        let mut engine = DiffEngine::new(None).unwrap();
+        let (left, right) = (br#"{"a":1,"b":"x"}"#, br#"{"a":2,"c":true}"#);
+        // Chunks may split the document anywhere.
+        engine.push_left(&left[..5]).unwrap();
+        engine.push_left(&left[5..]).unwrap();
+        engine.push_right(right).unwrap();
+
+        let result = engine.finalize().unwrap();
+        assert_eq!(ResultReader::parse(&result).unwrap().entry_count(), 3);
+        let entries = js_sys::Array::from(&engine.entries());
+        let mut seen: Vec<(String, String, Option<String>, Option<String>)> = entries
+            .iter()
+            .map(|e| (field(&e, "op").unwrap(), field(&e, "path").unwrap(), field(&e, "left"), field(&e, "right")))
+            .collect();
+        seen.sort();
+        let s = |v: &str| v.to_string();
+        assert_eq!(
+            seen,
+            vec![
+                (s("added"), s("/c"), None, Some(s("true"))),
+                (s("modified"), s("/a"), Some(s("1")), Some(s("2"))),
+                (s("removed"), s("/b"), Some(s("x")), None),
+            ]
+        );
+    }
+
+    #[wasm_bindgen_test]
+    fn failures_are_errors() {
+        assert!(DiffEngine::new(Some(r#"{"no_such_key":1}"#.into())).is_err());
+        let mut engine = DiffEngine::new(Some(r#"{"max_input_size":256}"#.into())).unwrap();
+        assert!(engine.push_left(&[b' '; 1024]).is_err());
+        assert_eq!(js_sys::Array::from(&engine.entries()).length(), 0, "nothing before finalize");
+    }
+}
@@ -0,0 +1,75 @@
+//! Engines named by registry handles instead of addresses, under the
+//! `handles` feature (`src/handles.rs`):
+//! `cargo test --features handles --test handles`.
+
+#[cfg(feature = "handles")]
+mod handles {
+    use diffcore::info::{FEATURES, FEATURE_HANDLES};
+    use diffcore::{
+        clear_engine, commit_left, commit_right, create_engine, create_engine_handle, destroy_engine, finalize, get_last_error_code,
+        get_left_input_ptr, get_result_len, get_right_input_ptr, Engine, Status,
+    };
+    use std::ptr;
+
+    fn engine(handle: u32) -> *mut Engine { handle as usize as *mut Engine }
+
+    /// Diff `left` against `right` on `engine`; the result's length.
+    fn diff(engine: *mut Engine, left: &[u8], right: &[u8]) -> u32 {
+        unsafe {
+            ptr::copy_nonoverlapping(left.as_ptr(), get_left_input_ptr(engine), left.len());
+            ptr::copy_nonoverlapping(right.as_ptr(), get_right_input_ptr(engine), right.len());
+        }
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        assert!(!finalize(engine).is_null());
+        let len = get_result_len(engine);
+        assert_eq!(clear_engine(engine), Status::Ok);
+        len
+    }
+
+    /// Every way an export can be handed a value that names no engine.
+    fn assert_dead(engine: *mut Engine) {
+        assert_eq!(commit_left(engine, 0), Status::InvalidHandle, "{engine:p}");
+        assert!(get_left_input_ptr(engine).is_null());
+        assert!(finalize(engine).is_null());
+        assert_eq!(get_result_len(engine), 0);
+        assert_eq!(get_last_error_code(engine), 0);
+        assert_eq!(destroy_engine(engine), Status::InvalidHandle);
+    }
+
+    #[test]
+    fn a_handle_drives_every_export() {
+        assert_ne!(FEATURES & FEATURE_HANDLES, 0);
+        let handle = create_engine_handle(ptr::null(), 0);
+        assert_ne!(handle, 0);
+        let from_create = create_engine(ptr::null(), 0);
+        assert_ne!(from_create as usize, handle as usize);
+        assert!(from_create as usize <= u32::MAX as usize, "a handle, not an address");
+
+        let (left, right) = (br#"{"a":1}"#, br#"{"a":2,"b":true}"#);
+        assert_eq!(diff(engine(handle), left, right), diff(from_create, left, right));
+        assert_eq!(destroy_engine(engine(handle)), Status::Ok);
+        assert_eq!(destroy_engine(from_create), Status::Ok);
+    }
+
+    #[test]
+    fn destroyed_and_forged_handles_name_no_engine() {
+        let first = create_engine_handle(ptr::null(), 0);
+        assert_eq!(destroy_engine(engine(first)), Status::Ok);
+        // Double destroy and use after destroy.
+        assert_dead(engine(first));
+
+        // The next engine may take the freed slot; the old handle stays dead.
+        let second = create_engine_handle(ptr::null(), 0);
+        assert_ne!(second, first);
+        assert_dead(engine(first));
+        assert!(diff(engine(second), b"[1]", b"[2]") > 16);
+
+        // Integers the registry never issued, and a real address.
+        let mut memory = [0u8; 64];
+        for forged in [1, 0xFFFF, 0x1_FFFF, u32::MAX as usize, usize::MAX, memory.as_mut_ptr() as usize] {
+            assert_dead(forged as *mut Engine);
+        }
+        assert_eq!(destroy_engine(engine(second)), Status::Ok);
+    }
+}
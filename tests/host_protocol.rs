@@ -0,0 +1,2522 @@
+//! Executable spec of the host protocol.
+//!
+//! Drives the engine exclusively through the `extern "C"` exports, in the
+//! order the JS glue (`js/src/index.ts`) calls them:
+//!
+//! ```text
+//! create_engine → get_{left,right}_input_ptr → write → commit_left →
+//! commit_right → finalize → get_result_len → read → destroy_engine
+//! ```
+//!
+//! An export changing signature breaks compilation here; an export changing
+//! semantics breaks a test. Update this file together with the JS glue.
+
+use diffcore::{
+    alloc, alloc_aligned, apply_patch, build_span_index, cancel, clear_engine, clear_error, commit_left, commit_left_retained, commit_right, commit_third, create_engine, dealloc, dealloc_aligned,
+    create_engine_in_group, destroy_engine, get_cancel_flag_ptr, diff_direct, diff_next_right, export_left_state, finalize, finalize_into, finalize_partial, finalize_step, get_create_error, get_create_error_code, get_create_error_len, get_arena_stats, get_dropped_count, get_audit_log, get_effective_config_json,
+    get_effective_config_json_len, get_engine_format_version, get_entry_count, get_entry_left, get_entry_left_len, get_entry_op, get_entry_path,
+    get_build_flags, get_engine_info, get_engine_info_len, get_entry_path_len, get_entry_right, get_entry_right_len, get_format_version, get_group_usage, get_index_pool_stats, get_input_digests, get_last_error,
+    get_last_error_code, get_last_error_len, get_left_input_ptr, get_left_state_len, get_memory_report, get_memory_usage, get_progress,
+    get_ranked_total, get_result_len, get_result_len_for, get_result_page, get_result_ptr_for, get_right_input_ptr, get_stats, get_stats_len, get_span_index_len, get_third_input_ptr, get_span_index_ptr, realloc,
+    get_suppressed_count, get_symbol_table, get_symbol_table_len, hash_document, import_left_state, lint, load_suppressions, probe, push_left, reserve_input, set_config, set_option, push_right, resolve_symbol, resume_finalize, resolve_symbol_len, self_test, set_group_limits, set_index_pool_limit, set_left_scope,
+    set_entry_transform, set_output_options, set_right_scope, trim_engine, validate_json, AuditOp, AuditRecord, EngineConfig, EntryTransform, ErrorCode, Status,
+    TRANSFORM_DROP, TRANSFORM_KEEP, TRANSFORM_REDACT, version, version_len,
+};
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+const HEADER: usize = 16;
+const ENTRY: usize = 32;
+
+/// Pin each export's exact signature. A mismatch fails to compile.
+#[allow(dead_code)]
+struct Abi {
+    create_engine: extern "C" fn(*const u8, u32) -> *mut diffcore::Engine,
+    get_left_input_ptr: extern "C" fn(*mut diffcore::Engine) -> *mut u8,
+    get_right_input_ptr: extern "C" fn(*mut diffcore::Engine) -> *mut u8,
+    commit_left: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    commit_right: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    finalize: extern "C" fn(*mut diffcore::Engine) -> *const u8,
+    get_result_len: extern "C" fn(*const diffcore::Engine) -> u32,
+    destroy_engine: extern "C" fn(*mut diffcore::Engine) -> Status,
+    clear_engine: extern "C" fn(*mut diffcore::Engine) -> Status,
+    get_last_error: extern "C" fn(*const diffcore::Engine) -> *const u8,
+    get_last_error_len: extern "C" fn(*const diffcore::Engine) -> u32,
+    get_last_error_code: extern "C" fn(*const diffcore::Engine) -> u32,
+    clear_error: extern "C" fn(*mut diffcore::Engine) -> Status,
+    export_left_state: extern "C" fn(*mut diffcore::Engine) -> *const u8,
+    get_left_state_len: extern "C" fn(*const diffcore::Engine) -> u32,
+    import_left_state: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    set_option: extern "C" fn(*mut diffcore::Engine, *const u8, u32, *const u8, u32) -> Status,
+    reserve_input: extern "C" fn(*mut diffcore::Engine, u32, u32) -> Status,
+    finalize_into: extern "C" fn(*mut diffcore::Engine, *mut u8, u32) -> i64,
+    finalize_partial: extern "C" fn(*mut diffcore::Engine) -> Status,
+    get_dropped_count: extern "C" fn(*const diffcore::Engine) -> u32,
+    diff_direct: extern "C" fn(*mut diffcore::Engine, *const u8, u32, *const u8, u32) -> Status,
+    resume_finalize: extern "C" fn(*mut diffcore::Engine) -> Status,
+    get_memory_report: extern "C" fn(*const diffcore::Engine, *mut u8) -> Status,
+    get_memory_usage: extern "C" fn(*mut diffcore::Engine) -> *const u8,
+    set_left_scope: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    set_right_scope: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    set_output_options: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    build_span_index: extern "C" fn(*mut diffcore::Engine) -> Status,
+    get_span_index_ptr: extern "C" fn(*const diffcore::Engine) -> *const u8,
+    get_span_index_len: extern "C" fn(*const diffcore::Engine) -> u32,
+    create_engine_in_group: extern "C" fn(u32, *const u8, u32) -> *mut diffcore::Engine,
+    set_group_limits: extern "C" fn(u32, u32, u32) -> Status,
+    get_group_usage: extern "C" fn(u32, *mut u8) -> Status,
+    get_input_digests: extern "C" fn(*mut diffcore::Engine, *mut u8) -> Status,
+    get_progress: extern "C" fn(*const diffcore::Engine) -> u32,
+    get_effective_config_json: extern "C" fn(*mut diffcore::Engine) -> *const u8,
+    get_effective_config_json_len: extern "C" fn(*const diffcore::Engine) -> u32,
+    load_suppressions: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    get_suppressed_count: extern "C" fn(*const diffcore::Engine) -> u32,
+    get_ranked_total: extern "C" fn(*const diffcore::Engine) -> u32,
+    get_arena_stats: extern "C" fn(*const diffcore::Engine, *mut u8) -> Status,
+    set_index_pool_limit: extern "C" fn(u32, u32) -> Status,
+    get_index_pool_stats: extern "C" fn(u32, *mut u8) -> Status,
+    get_audit_log: extern "C" fn(*const diffcore::Engine, *mut u8, u32) -> u32,
+    commit_third: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    get_third_input_ptr: extern "C" fn(*mut diffcore::Engine) -> *mut u8,
+    probe: extern "C" fn(*const u8, u32, *mut u8) -> Status,
+    get_create_error: extern "C" fn() -> *const u8,
+    get_create_error_len: extern "C" fn() -> u32,
+    get_create_error_code: extern "C" fn() -> u32,
+    self_test: extern "C" fn() -> u32,
+    push_left: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    push_right: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    trim_engine: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    get_entry_count: extern "C" fn(*const diffcore::Engine) -> u32,
+    get_format_version: extern "C" fn() -> u32,
+    get_engine_info: extern "C" fn() -> *const u8,
+    get_build_flags: extern "C" fn() -> u32,
+    get_result_ptr_for: extern "C" fn(*const diffcore::Engine, u8) -> *const u8,
+    get_result_len_for: extern "C" fn(*const diffcore::Engine, u8) -> u32,
+    get_engine_info_len: extern "C" fn() -> u32,
+    version: extern "C" fn() -> *const u8,
+    version_len: extern "C" fn() -> u32,
+    get_engine_format_version: extern "C" fn(*const diffcore::Engine) -> u32,
+    get_stats: extern "C" fn(*mut diffcore::Engine) -> *const u8,
+    get_stats_len: extern "C" fn(*const diffcore::Engine) -> u32,
+    set_entry_transform: extern "C" fn(*mut diffcore::Engine, Option<EntryTransform>) -> Status,
+    finalize_step: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    cancel: extern "C" fn(*const diffcore::Engine) -> Status,
+    get_cancel_flag_ptr: extern "C" fn(*const diffcore::Engine) -> *const u8,
+    get_result_page: extern "C" fn(*const diffcore::Engine, u32, *mut u8, u32) -> u32,
+    get_entry_op: extern "C" fn(*const diffcore::Engine, u32) -> u8,
+    get_entry_path: extern "C" fn(*mut diffcore::Engine, u32) -> *const u8,
+    get_entry_path_len: extern "C" fn(*mut diffcore::Engine, u32) -> u32,
+    get_entry_left: extern "C" fn(*const diffcore::Engine, u32) -> *const u8,
+    get_entry_left_len: extern "C" fn(*const diffcore::Engine, u32) -> u32,
+    get_entry_right: extern "C" fn(*const diffcore::Engine, u32) -> *const u8,
+    get_entry_right_len: extern "C" fn(*const diffcore::Engine, u32) -> u32,
+    resolve_symbol: extern "C" fn(*mut diffcore::Engine, u64) -> *const u8,
+    resolve_symbol_len: extern "C" fn(*mut diffcore::Engine, u64) -> u32,
+    get_symbol_table: extern "C" fn(*mut diffcore::Engine) -> *const u8,
+    get_symbol_table_len: extern "C" fn(*mut diffcore::Engine) -> u32,
+    alloc: extern "C" fn(u32) -> *mut u8,
+    alloc_aligned: extern "C" fn(u32, u32) -> *mut u8,
+    dealloc: extern "C" fn(*mut u8) -> Status,
+    dealloc_aligned: extern "C" fn(*mut u8, u32, u32) -> Status,
+    realloc: extern "C" fn(*mut u8, u32, u32) -> *mut u8,
+    lint: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    apply_patch: extern "C" fn(*mut diffcore::Engine, *const u8, u32, *const u8, u32) -> Status,
+    validate_json: extern "C" fn(*mut diffcore::Engine, *const u8, u32) -> Status,
+    hash_document: extern "C" fn(*mut diffcore::Engine, u32) -> u64,
+    commit_left_retained: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+    diff_next_right: extern "C" fn(*mut diffcore::Engine, u32) -> Status,
+}
+
+const _: Abi = Abi {
+    create_engine,
+    get_left_input_ptr,
+    get_right_input_ptr,
+    commit_left,
+    commit_right,
+    finalize,
+    get_result_len,
+    destroy_engine,
+    clear_engine,
+    get_last_error,
+    get_last_error_len,
+    get_last_error_code,
+    clear_error,
+    export_left_state,
+    get_left_state_len,
+    import_left_state,
+    set_option,
+    reserve_input,
+    finalize_into,
+    finalize_partial,
+    get_dropped_count,
+    diff_direct,
+    resume_finalize,
+    get_memory_report,
+    get_memory_usage,
+    set_left_scope,
+    set_right_scope,
+    set_output_options,
+    build_span_index,
+    get_span_index_ptr,
+    get_span_index_len,
+    create_engine_in_group,
+    set_group_limits,
+    get_group_usage,
+    get_input_digests,
+    get_progress,
+    get_effective_config_json,
+    get_effective_config_json_len,
+    load_suppressions,
+    get_suppressed_count,
+    get_ranked_total,
+    get_arena_stats,
+    set_index_pool_limit,
+    get_index_pool_stats,
+    get_audit_log,
+    commit_third,
+    get_third_input_ptr,
+    probe,
+    get_create_error,
+    get_create_error_len,
+    get_create_error_code,
+    self_test,
+    push_left,
+    push_right,
+    trim_engine,
+    get_entry_count,
+    get_format_version,
+    get_engine_info,
+    get_build_flags,
+    get_result_ptr_for,
+    get_result_len_for,
+    get_engine_info_len,
+    version,
+    version_len,
+    get_engine_format_version,
+    get_stats,
+    get_stats_len,
+    set_entry_transform,
+    finalize_step,
+    cancel,
+    get_cancel_flag_ptr,
+    get_result_page,
+    get_entry_op,
+    get_entry_path,
+    get_entry_path_len,
+    get_entry_left,
+    get_entry_left_len,
+    get_entry_right,
+    get_entry_right_len,
+    resolve_symbol,
+    resolve_symbol_len,
+    get_symbol_table,
+    get_symbol_table_len,
+    alloc,
+    alloc_aligned,
+    dealloc,
+    dealloc_aligned,
+    realloc,
+    lint,
+    apply_patch,
+    validate_json,
+    hash_document,
+    commit_left_retained,
+    diff_next_right,
+};
+
+/// Serialize a config the way the host does: into its own buffer.
+fn config_bytes() -> Vec<u8> {
+    EngineConfig { max_input_size: 1 << 20, ..EngineConfig::default() }
+        .to_bytes()
+        .to_vec()
+}
+
+fn write_input(dst: *mut u8, data: &[u8]) {
+    assert!(!dst.is_null());
+    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len()) };
+}
+
+fn read_result(engine: *mut diffcore::Engine, result: *const u8) -> Vec<u8> {
+    assert!(!result.is_null());
+    let len = get_result_len(engine) as usize;
+    unsafe { std::slice::from_raw_parts(result, len) }.to_vec()
+}
+
+fn last_error(engine: *mut diffcore::Engine) -> Option<String> {
+    let p = get_last_error(engine);
+    let len = get_last_error_len(engine) as usize;
+    if p.is_null() || len == 0 {
+        return None;
+    }
+    Some(String::from_utf8(unsafe { std::slice::from_raw_parts(p, len) }.to_vec()).unwrap())
+}
+
+fn create_error() -> Option<String> {
+    let p = get_create_error();
+    let len = get_create_error_len() as usize;
+    if p.is_null() || len == 0 {
+        return None;
+    }
+    Some(String::from_utf8(unsafe { std::slice::from_raw_parts(p, len) }.to_vec()).unwrap())
+}
+
+fn entry_count(result: &[u8]) -> u32 {
+    u32::from_le_bytes(result[4..8].try_into().unwrap())
+}
+
+#[test]
+fn canonical_sequence() {
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert!(!engine.is_null());
+
+    let left = br#"{"a":1,"b":"x"}"#;
+    let right = br#"{"a":2,"c":true}"#;
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(u16::from_le_bytes([result[0], result[1]]), 3, "format major");
+    assert_eq!(entry_count(&result), 3);
+    assert_eq!(result.len(), HEADER + 3 * ENTRY);
+    assert_eq!(
+        u32::from_le_bytes(result[8..12].try_into().unwrap()),
+        result.len() as u32,
+        "header total length"
+    );
+    assert_eq!(&result[12..16], &[0; 4], "header flags");
+    assert_eq!(last_error(engine), None);
+
+    let mut report = [0u8; 32];
+    assert_eq!(get_memory_report(engine, report.as_mut_ptr()), Status::Ok);
+    assert_eq!(u32::from_le_bytes(report[4..8].try_into().unwrap()) as usize, result.len());
+    let mut arena = [0u8; 24];
+    assert_eq!(get_arena_stats(engine, arena.as_mut_ptr()), Status::Ok);
+    assert_eq!(u32::from_le_bytes(arena[0..4].try_into().unwrap()) as usize, result.len(), "result estimate");
+
+    assert_eq!(build_span_index(engine), Status::Ok);
+    assert!(!get_span_index_ptr(engine).is_null());
+    assert_eq!(get_span_index_len(engine), 3 * 12);
+
+    assert_eq!(destroy_engine(engine), Status::Ok);
+}
+
+#[test]
+fn default_config_when_none_given() {
+    let engine = create_engine(ptr::null(), 0);
+    assert!(!engine.is_null());
+    assert_eq!(destroy_engine(engine), Status::Ok);
+}
+
+#[test]
+fn original_20_byte_config_accepted() {
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), 20);
+    assert!(!engine.is_null());
+    assert_eq!(destroy_engine(engine), Status::Ok);
+}
+
+#[test]
+fn invalid_config_yields_null() {
+    let engine = create_engine([0u8; 20].as_ptr(), 20);
+    assert!(engine.is_null());
+    assert_eq!(get_create_error_code(), ErrorCode::InvalidLimits as u32);
+    let short = [1u8; 8];
+    assert!(create_engine(short.as_ptr(), short.len() as u32).is_null());
+    assert_eq!(create_error().as_deref(), Some("invalid config: config truncated"));
+
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!((create_error(), get_create_error_code()), (None, 0), "a success clears it");
+    assert_eq!(destroy_engine(engine), Status::Ok);
+}
+
+#[test]
+fn reuse_after_clear() {
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    for (l, r, n) in [(&b"[1]"[..], &b"[2]"[..], 1), (&b"[1,2]"[..], &b"[1,2]"[..], 0)] {
+        write_input(get_left_input_ptr(engine), l);
+        write_input(get_right_input_ptr(engine), r);
+        assert_eq!(commit_left(engine, l.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, r.len() as u32), Status::Ok);
+        assert_eq!(entry_count(&read_result(engine, finalize(engine))), n);
+        assert_eq!(clear_engine(engine), Status::Ok);
+    }
+    destroy_engine(engine);
+}
+
+/// Diff `left` against `right` on `engine`, then clear it.
+fn diff_and_clear(engine: *mut diffcore::Engine, left: &[u8], right: &[u8]) -> Vec<u8> {
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(clear_engine(engine), Status::Ok);
+    result
+}
+
+#[test]
+fn a_thousand_diffs_on_one_handle_match_fresh_engines_in_bounded_memory() {
+    let cfg = config_bytes();
+    let reused = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let arena_capacity = || {
+        let mut stats = [0u8; 24];
+        assert_eq!(get_arena_stats(reused, stats.as_mut_ptr()), Status::Ok);
+        u32::from_le_bytes(stats[4..8].try_into().unwrap())
+    };
+    let mut settled = None;
+    for i in 0..1000u32 {
+        // Sizes cycle, so every buffer has seen its largest diff after
+        // the first 100 rounds.
+        let n = i % 100;
+        let left = format!(r#"{{"round":{:04},"items":[{}],"gone":"x"}}"#, i, (0..n).map(|k| k.to_string()).collect::<Vec<_>>().join(","));
+        let right = format!(r#"{{"round":{:04},"items":[{}],"new":[{}]}}"#, i + 1, (0..n).map(|k| (k + n % 2).to_string()).collect::<Vec<_>>().join(","), n);
+        let fresh = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        let want = diff_and_clear(fresh, left.as_bytes(), right.as_bytes());
+        assert_eq!(destroy_engine(fresh), Status::Ok);
+        assert_eq!(diff_and_clear(reused, left.as_bytes(), right.as_bytes()), want, "round {}", i);
+        assert_eq!(last_error(reused), None);
+
+        #[cfg(target_arch = "wasm32")]
+        let pages = core::arch::wasm32::memory_size(0);
+        #[cfg(not(target_arch = "wasm32"))]
+        let pages = 0;
+        match settled {
+            None if i == 99 => settled = Some((arena_capacity(), pages)),
+            Some(at_100) => assert_eq!((arena_capacity(), pages), at_100, "grew by round {}", i),
+            None => {}
+        }
+    }
+    assert_eq!(destroy_engine(reused), Status::Ok);
+}
+
+#[test]
+fn trim_releases_memory_and_the_engine_still_diffs() {
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let trimmed = || {
+        let mut stats = [0u8; 24];
+        assert_eq!(get_arena_stats(engine, stats.as_mut_ptr()), Status::Ok);
+        u32::from_le_bytes(stats[12..16].try_into().unwrap())
+    };
+    let left = format!("[{}]", (0..500).map(|k| k.to_string()).collect::<Vec<_>>().join(","));
+    let right = format!("[{}]", (1..501).map(|k| k.to_string()).collect::<Vec<_>>().join(","));
+    let want = diff_and_clear(engine, left.as_bytes(), right.as_bytes());
+    assert_eq!(trimmed(), 0);
+
+    for level in 0..3 {
+        assert_eq!(trim_engine(engine, level), Status::Ok);
+        assert!(level == 1 || trimmed() > 0, "level {} released nothing", level);
+        assert_eq!(diff_and_clear(engine, left.as_bytes(), right.as_bytes()), want, "level {}", level);
+    }
+    assert_eq!(trim_engine(engine, 3), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidTrimLevel as u32);
+    assert_eq!(trim_engine(ptr::null_mut(), 0), Status::InvalidHandle);
+    destroy_engine(engine);
+}
+
+#[test]
+fn entry_count_matches_the_header() {
+    let engine = create_engine(ptr::null(), 0);
+    for n in [0usize, 1, 37] {
+        assert_eq!(get_entry_count(engine), 0, "before finalize");
+        let left = format!("[{}]", vec!["0"; 40].join(","));
+        let right = format!("[{}]", (0..40).map(|k| if k < n { "1" } else { "0" }).collect::<Vec<_>>().join(","));
+        write_input(get_left_input_ptr(engine), left.as_bytes());
+        write_input(get_right_input_ptr(engine), right.as_bytes());
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        let result = read_result(engine, finalize(engine));
+        assert_eq!(get_entry_count(engine), u32::from_le_bytes(result[4..8].try_into().unwrap()));
+        assert_eq!(get_entry_count(engine) as usize, n);
+        assert_eq!(clear_engine(engine), Status::Ok);
+    }
+    destroy_engine(engine);
+}
+
+#[test]
+fn result_pages_rebuild_the_result() {
+    // value_hashes (field 14) on, so sections follow the entries.
+    let hashes = [14u8, 1, 0, 0, 0];
+    let left = format!("[{}]", vec!["0"; 3000].join(","));
+    let right = format!("[{}]", vec!["1"; 3000].join(","));
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let mut page = vec![0u8; 4096];
+    assert_eq!(get_result_page(engine, 0, page.as_mut_ptr(), 4096), 0, "no result yet");
+    assert_eq!(set_output_options(engine, hashes.as_ptr(), hashes.len() as u32), Status::Ok);
+    write_input(get_left_input_ptr(engine), left.as_bytes());
+    write_input(get_right_input_ptr(engine), right.as_bytes());
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let want = read_result(engine, finalize(engine));
+    assert!(want.len() > (HEADER + 3000 * ENTRY) + 3000 * 8, "{} bytes", want.len());
+
+    let mut paged = Vec::new();
+    let mut sizes = Vec::new();
+    loop {
+        let n = get_result_page(engine, paged.len() as u32, page.as_mut_ptr(), 4096) as usize;
+        if n == 0 {
+            break;
+        }
+        paged.extend_from_slice(&page[..n]);
+        sizes.push(n);
+    }
+    assert_eq!(paged, want);
+    // The header and 127 entries, then 128 entries a page until the
+    // entries run out; no page ends inside one.
+    assert_eq!(&sizes[..3], [HEADER + 127 * ENTRY, 128 * ENTRY, 128 * ENTRY]);
+    let entries_end = HEADER + 3000 * ENTRY;
+    let mut at = 0;
+    for n in &sizes {
+        at += n;
+        assert!(at >= entries_end || (at - HEADER).is_multiple_of(ENTRY), "page ends at {}", at);
+    }
+
+    assert_eq!(get_result_page(engine, 20, page.as_mut_ptr(), 4096), 0, "inside an entry");
+    assert_eq!(get_result_page(engine, 16, page.as_mut_ptr(), 31), 0, "short of an entry");
+    assert_eq!(get_result_page(engine, want.len() as u32, page.as_mut_ptr(), 4096), 0, "at the end");
+    assert_eq!(get_result_page(engine, 0, ptr::null_mut(), 4096), 0);
+    destroy_engine(engine);
+}
+
+/// An entry's op, path, left and right value.
+type Accessed = (u8, Vec<u8>, Option<Vec<u8>>, Option<Vec<u8>>);
+
+/// Entry `i` through the accessors.
+fn accessed_entry(engine: *mut diffcore::Engine, i: u32) -> Accessed {
+    let bytes = |p: *const u8, len: u32| (!p.is_null()).then(|| unsafe { std::slice::from_raw_parts(p, len as usize) }.to_vec());
+    let path = get_entry_path(engine, i);
+    let path = bytes(path, get_entry_path_len(engine, i)).expect("path");
+    let left = bytes(get_entry_left(engine, i), get_entry_left_len(engine, i));
+    let right = bytes(get_entry_right(engine, i), get_entry_right_len(engine, i));
+    (get_entry_op(engine, i), path, left, right)
+}
+
+#[test]
+fn entry_accessors_match_the_decoded_result() {
+    let left = br#"{"id":7,"tags":["a","b"],"old":null,"a/b":{"c~d":1.5},"s":"x"}"#;
+    let right = br#"{"id":7,"tags":["a","c","d"],"a/b":{"c~d":2},"s":"y","new":true}"#;
+    let cfg = config_bytes();
+    // One engine renders paths into the result (field 24), the other
+    // leaves the accessor to record them on demand.
+    let [rendered, plain] = [true, false].map(|on| {
+        let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        let option = [24u8, on as u8, 0, 0, 0];
+        assert_eq!(set_output_options(engine, option.as_ptr(), option.len() as u32), Status::Ok);
+        write_input(get_left_input_ptr(engine), left);
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        assert_eq!(get_entry_op(engine, 0), 0xFF, "no result yet");
+        assert!(get_entry_path(engine, 0).is_null());
+        engine
+    });
+    let result = read_result(rendered, finalize(rendered));
+    assert!(!finalize(plain).is_null());
+
+    let reader = diffcore::reader::ResultReader::parse(&result).unwrap();
+    let paths = reader.paths().expect("rendered paths");
+    let mut decoded = Vec::new();
+    for (e, path) in reader.entries().zip(paths) {
+        let [l, r] = e.values(left, right).unwrap();
+        decoded.push((e.op, path, l.map(<[u8]>::to_vec), r.map(<[u8]>::to_vec)));
+    }
+    assert_eq!(decoded.len(), 6);
+    let count = get_entry_count(rendered);
+    for engine in [rendered, plain] {
+        let accessed: Vec<_> = (0..count).map(|i| accessed_entry(engine, i)).collect();
+        assert_eq!(accessed, decoded);
+        assert_eq!(get_entry_op(engine, count), 0xFF, "past the last entry");
+        assert!(get_entry_path(engine, count).is_null());
+        assert_eq!(get_entry_path_len(engine, count), 0);
+        assert!(get_entry_left(engine, count).is_null());
+        assert_eq!(get_entry_right_len(engine, count), 0);
+    }
+    // The values point into the input buffer rather than at copies.
+    let added = decoded.iter().position(|d| d.1 == b"/new").unwrap() as u32;
+    assert!(get_entry_left(plain, added).is_null());
+    let at = get_entry_right(plain, added) as usize - get_right_input_ptr(plain) as usize;
+    assert_eq!(&right[at..at + 4], b"true");
+    for engine in [rendered, plain] {
+        destroy_engine(engine);
+    }
+}
+
+#[test]
+fn lint_reports_formatting_only_entries() {
+    const HEADER_FLAG_IDENTICAL: u32 = 1 << 0;
+    const HEADER_FLAG_LINT: u32 = 1 << 8;
+    let doc = br#"{"b": 1.0, "a": {"d":"\u00e9","c":2}, "e": [1e2, "x"]}"#;
+    let canonical = r#"{"a":{"c":2,"d":"é"},"b":1,"e":[100,"x"]}"#.as_bytes();
+    let cfg = config_bytes();
+    for side in [0, 1] {
+        let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        let (input, other) = if side == 0 { (get_left_input_ptr(engine), get_right_input_ptr(engine)) } else { (get_right_input_ptr(engine), get_left_input_ptr(engine)) };
+        write_input(input, doc);
+        let commit = if side == 0 { commit_left } else { commit_right };
+        assert_eq!(commit(engine, doc.len() as u32), Status::Ok);
+        assert_eq!(lint(engine, side), Status::Ok);
+        assert_eq!(unsafe { std::slice::from_raw_parts(other, canonical.len()) }, canonical);
+
+        let result = read_result(engine, finalize(engine));
+        let reader = diffcore::reader::ResultReader::parse(&result).unwrap();
+        assert_eq!(reader.flags() & HEADER_FLAG_LINT, HEADER_FLAG_LINT);
+        assert!(reader.entries().all(|e| e.formatting_only()));
+        let mut got: Vec<_> = (0..get_entry_count(engine)).map(|i| accessed_entry(engine, i)).collect();
+        got.sort();
+        let (doc, canonical) = (doc.to_vec(), canonical.to_vec());
+        let modified = |path: &str, original: &[u8], formatted: &[u8]| {
+            let (l, r) = if side == 0 { (original, formatted) } else { (formatted, original) };
+            (2, path.as_bytes().to_vec(), Some(l.to_vec()), Some(r.to_vec()))
+        };
+        // Both reordered objects, whole, and each value written otherwise.
+        let want = vec![
+            modified("", &doc, &canonical),
+            modified("/a", br#"{"d":"\u00e9","c":2}"#, r#"{"c":2,"d":"é"}"#.as_bytes()),
+            modified("/a/d", br"\u00e9", "é".as_bytes()),
+            modified("/b", b"1.0", b"1"),
+            modified("/e/0", b"1e2", b"100"),
+        ];
+        assert_eq!(got, want, "side {}", side);
+        assert_eq!(lint(engine, side), Status::EngineSealed);
+        destroy_engine(engine);
+    }
+
+    // A canonical document lints clean; lint needs the side committed and
+    // the other side free.
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert_eq!(lint(engine, 2), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidSide as u32);
+    assert_eq!(lint(engine, 0), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::NoInputCommitted as u32);
+    write_input(get_left_input_ptr(engine), canonical);
+    assert_eq!(commit_left(engine, canonical.len() as u32), Status::Ok);
+    assert_eq!(push_right(engine, b"[".as_ptr(), 1), Status::Ok);
+    assert_eq!(lint(engine, 0), Status::AlreadyCommitted);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    write_input(get_left_input_ptr(engine), canonical);
+    assert_eq!(commit_left(engine, canonical.len() as u32), Status::Ok);
+    assert_eq!(lint(engine, 0), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!((entry_count(&result), u32::from_le_bytes(result[12..16].try_into().unwrap())), (0, HEADER_FLAG_IDENTICAL | HEADER_FLAG_LINT));
+    destroy_engine(engine);
+}
+
+/// Rebuild `right` from `left` and a `self_contained` diff of the two,
+/// through a second engine.
+fn rebuild(left: &[u8], right: &[u8]) -> Vec<u8> {
+    let cfg = EngineConfig { self_contained: true, max_input_size: 1 << 20, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let patch = if left.is_empty() {
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        let result = read_result(engine, finalize(engine));
+        assert_eq!(clear_engine(engine), Status::Ok);
+        result
+    } else {
+        diff_and_clear(engine, left, right)
+    };
+    assert_eq!(apply_patch(engine, left.as_ptr(), left.len() as u32, patch.as_ptr(), patch.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(entry_count(&result), 0);
+    let document = diffcore::reader::ResultReader::parse(&result).unwrap().document().unwrap().to_vec();
+    destroy_engine(engine);
+    document
+}
+
+#[test]
+fn apply_patch_rebuilds_the_right_document() {
+    const HEADER_FLAG_PATCHED: u32 = 1 << 9;
+    let deep = |leaf: &str| format!("{}{}{}", "[".repeat(500), leaf, "]".repeat(500));
+    // Right sides are compact with new keys last, so the rebuilt text is
+    // theirs byte for byte.
+    let corpus: Vec<(String, String)> = [
+        (r#"{"a":1,"b":[1,2,3]}"#, r#"{"a":2,"b":[1,3],"c":{}}"#),
+        (r#"{"a":{}}"#, r#"{"a":[]}"#),
+        (r#"{"a":{"b":{}}}"#, r#"{"a":{}}"#),
+        (r#"[1, [2, [3]]]"#, r#"[1,[2,[4,5]],{"x":null}]"#),
+        (r#""s""#, r#"{"k":"v"}"#),
+        (r#"{"a/b":1,"t~":2,"gone":[true]}"#, r#"{"a/b":3,"t~":{"n":[]}}"#),
+        ("{}", "{}"),
+        (r#"{"x":[1,2,3,4],"y":[]}"#, r#"{"x":[],"y":[0,[],{}]}"#),
+        (r#"{"n":1.0,"s":"é"}"#, r#"{"n":1,"s":"é"}"#),
+        (r#"{"a":[{"id":1,"v":"x"},{"id":2}]}"#, r#"{"a":[{"id":1,"v":"y"},{"id":2,"w":false}]}"#),
+        ("", r#"{"from":"nothing"}"#),
+    ]
+    .into_iter()
+    .map(|(l, r)| (l.to_string(), r.to_string()))
+    .chain([(deep("0"), deep("[1]"))])
+    .collect();
+    for (left, right) in &corpus {
+        assert_eq!(String::from_utf8(rebuild(left.as_bytes(), right.as_bytes())).unwrap(), *right, "{}", left);
+    }
+
+    // Whitespace and member order aside, the rebuilt document diffs clean
+    // against the right one.
+    let (left, right) = (br#"{"b": [1, 2], "a": {"x": 1}}"#, br#"{ "c": 3, "a": {"x": 2, "y": [ ]}, "b": [1] }"#);
+    let rebuilt = rebuild(left, right);
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert_eq!(entry_count(&diff_and_clear(engine, &rebuilt, right)), 0, "{}", String::from_utf8_lossy(&rebuilt));
+
+    // A plain result has no patch; a patch needs its own left document
+    // and a fresh engine, and seals it.
+    let plain = diff_and_clear(engine, left, right);
+    let apply = |doc: &[u8], patch: &[u8]| apply_patch(engine, doc.as_ptr(), doc.len() as u32, patch.as_ptr(), patch.len() as u32);
+    assert_eq!(apply(left, &plain), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidPatch as u32);
+    let patched = EngineConfig { self_contained: true, ..EngineConfig::default() };
+    let engine2 = create_engine(patched.to_bytes().as_ptr(), patched.to_bytes().len() as u32);
+    let patch = diff_and_clear(engine2, left, right);
+    destroy_engine(engine2);
+    assert_eq!(apply(br#"[1]"#, &patch), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidPatch as u32);
+    assert_eq!(apply(b"{", &patch), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::IncompleteInput as u32);
+    assert_eq!(push_left(engine, b"{".as_ptr(), 1), Status::Ok);
+    assert_eq!(apply(left, &patch), Status::AlreadyCommitted);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!(apply(left, &patch), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(u32::from_le_bytes(result[12..16].try_into().unwrap()) & HEADER_FLAG_PATCHED, HEADER_FLAG_PATCHED);
+    assert_eq!(apply(left, &patch), Status::EngineSealed);
+    destroy_engine(engine);
+}
+
+#[test]
+fn validate_json_reports_the_first_problem() {
+    let engine = create_engine(ptr::null(), 0);
+    let check = |doc: &[u8]| validate_json(engine, doc.as_ptr(), doc.len() as u32);
+    for doc in [&br#"{"a":[1,2.5,-3e2],"b":{"c":null,"d":"x\u00e9"}}"#[..], b" [] ", b"\"s\"", b"0"] {
+        assert_eq!(check(doc), Status::Ok, "{}", String::from_utf8_lossy(doc));
+    }
+    let cases: &[(&[u8], &str)] = &[
+        // Truncated.
+        (br#"{"a":[1,2"#, "invalid JSON at byte 9: unexpected end of input"),
+        (br#"{"a":{"b":1}"#, "invalid JSON at byte 12: unexpected end of input"),
+        (b"", "invalid JSON at byte 0: unexpected end of input"),
+        // Stray commas.
+        (br#"{"a":1,}"#, "invalid JSON at byte 7: unexpected byte 0x7d"),
+        (b"[1,,2]", "invalid JSON at byte 3: unexpected byte 0x2c"),
+        (br#"{,"a":1}"#, "invalid JSON at byte 1: unexpected byte 0x2c"),
+        // Unclosed strings.
+        (br#"{"a":"b}"#, "invalid JSON at byte 5: unclosed string"),
+        (br#"["x\"]"#, "invalid JSON at byte 1: unclosed string"),
+        // Unbalanced braces, which a diff against `{}` does not catch.
+        (br#"{"a":1}}"#, "invalid JSON at byte 7: unexpected byte 0x7d"),
+        (br#"{"a":[1}"#, "invalid JSON at byte 7: unexpected byte 0x7d"),
+    ];
+    for &(doc, message) in cases {
+        assert_eq!(check(doc), Status::Error, "{}", String::from_utf8_lossy(doc));
+        assert_eq!(get_last_error_code(engine), ErrorCode::InvalidJson as u32);
+        assert_eq!(last_error(engine).as_deref(), Some(message));
+    }
+
+    // A valid 10MB document; the engine is still fresh after all of it.
+    let mut big = b"[".to_vec();
+    let mut i = 0u32;
+    while big.len() < 10 << 20 {
+        if i > 0 { big.push(b','); }
+        big.extend_from_slice(format!(r#"{{"id":{i},"name":"item \"{i}\"","tags":["a","b"],"score":{}.5e-3,"ok":true}}"#, i % 997).as_bytes());
+        i += 1;
+    }
+    big.push(b']');
+    assert_eq!(check(&big), Status::Ok);
+    *big.last_mut().unwrap() = b',';
+    assert_eq!(check(&big), Status::Error);
+    assert_eq!(last_error(engine), Some(format!("invalid JSON at byte {}: unexpected end of input", big.len())));
+
+    let (left, right) = (br#"{"a":1}"#, br#"{"a":2}"#);
+    assert_eq!(entry_count(&diff_and_clear(engine, left, right)), 1, "validation leaves the engine as it was");
+    destroy_engine(engine);
+    assert_eq!(validate_json(ptr::null_mut(), ptr::null(), 0), Status::InvalidHandle);
+}
+
+#[test]
+fn hash_document_ignores_layout_and_key_order_only() {
+    let engine = create_engine(ptr::null(), 0);
+    let hashes = |left: &[u8], right: &[u8]| {
+        write_input(get_left_input_ptr(engine), left);
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        let pair = (hash_document(engine, 0), hash_document(engine, 1));
+        assert_ne!(pair.0, 0);
+        assert_eq!(clear_engine(engine), Status::Ok);
+        pair
+    };
+    let base = br#"{"id":7,"user":{"name":"ann","tags":["a","b"]},"ok":true,"none":null}"#;
+    let same = [
+        &br#"{"ok":true,"none":null,"user":{"tags":["a","b"],"name":"ann"},"id":7}"#[..],
+        b"{ \"id\" : 7,\n  \"user\": { \"name\": \"ann\", \"tags\": [ \"a\", \"b\" ] },\n  \"ok\": true, \"none\": null }",
+    ];
+    for doc in same {
+        let (l, r) = hashes(base, doc);
+        assert_eq!(l, r, "{}", String::from_utf8_lossy(doc));
+    }
+    let different = [
+        &br#"{"id":8,"user":{"name":"ann","tags":["a","b"]},"ok":true,"none":null}"#[..],
+        br#"{"id":7,"user":{"name":"ann","tags":["b","a"]},"ok":true,"none":null}"#,
+        br#"{"id":7,"user":{"name":"ann","tags":["a","b"]},"ok":false,"none":null}"#,
+        br#"{"id":7,"user":{"name":"ann","tags":["a","b"]},"ok":true,"none":{}}"#,
+        br#"{"id":7,"user":{"name":"ann","tags":["a","b"]},"ok":true,"nothing":null}"#,
+        br#"{"id":7,"user":{"name":"ann","tags":["a","b"]},"ok":true}"#,
+        br#"{"id":"7","user":{"name":"ann","tags":["a","b"]},"ok":true,"none":null}"#,
+    ];
+    for doc in different {
+        let (l, r) = hashes(base, doc);
+        assert_ne!(l, r, "{}", String::from_utf8_lossy(doc));
+    }
+    // `{}` and `[]` are both empty, but not the same.
+    let (l, r) = hashes(br#"{"a":{}}"#, br#"{"a":[]}"#);
+    assert_ne!(l, r);
+
+    // Nothing to hash: no side committed, a bad side, a sealed engine.
+    assert_eq!(hash_document(engine, 0), 0);
+    assert_eq!(get_last_error_code(engine), ErrorCode::NoInputCommitted as u32);
+    assert_eq!(hash_document(engine, 2), 0);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidSide as u32);
+    write_input(get_left_input_ptr(engine), base);
+    assert_eq!(commit_left(engine, base.len() as u32), Status::Ok);
+    assert_ne!(hash_document(engine, 0), 0);
+    assert!(!finalize(engine).is_null());
+    assert_eq!(hash_document(engine, 0), 0);
+    assert_eq!(get_last_error_code(engine), ErrorCode::EngineSealed as u32);
+    destroy_engine(engine);
+    assert_eq!(hash_document(ptr::null_mut(), 0), 0);
+}
+
+#[test]
+fn one_left_many_rights_match_independent_engines() {
+    let cfg = config_bytes();
+    let left = br#"{"region":"eu","limits":{"cpu":4,"mem":8},"features":["a","b","c"],"owner":null}"#;
+    let rights: [&[u8]; 5] = [
+        br#"{"region":"us","limits":{"cpu":4,"mem":16},"features":["a","b","c"],"owner":null}"#,
+        left,
+        br#"{"region":"eu","limits":{"cpu":4},"features":["a","c"],"owner":"ops","extra":true}"#,
+        b"[]",
+        br#"{"region":"eu","limits":{"cpu":4,"mem":8},"features":["a","b","c","d"],"owner":null}"#,
+    ];
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert_eq!(diff_next_right(engine, 0), Status::Error, "nothing retained yet");
+    assert_eq!(get_last_error_code(engine), ErrorCode::LeftNotRetained as u32);
+    write_input(get_left_input_ptr(engine), left);
+    assert_eq!(commit_left_retained(engine, left.len() as u32), Status::Ok);
+    for right in rights {
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(diff_next_right(engine, right.len() as u32), Status::Ok);
+        let retained = read_result(engine, finalize(engine));
+        let fresh = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        let independent = diff_and_clear(fresh, left, right);
+        destroy_engine(fresh);
+        assert_eq!(retained, independent, "{}", String::from_utf8_lossy(right));
+    }
+
+    // A failed right commit leaves the left retained.
+    write_input(get_right_input_ptr(engine), b"{}");
+    assert_eq!(diff_next_right(engine, u32::MAX), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InputExceedsBuffer as u32);
+    assert_eq!(diff_next_right(engine, 2), Status::Ok);
+    assert_eq!(entry_count(&read_result(engine, finalize(engine))), 7, "each left leaf Removed");
+
+    // A result handed off with `finalize_into` is the last one's: the
+    // next right gets a result of its own.
+    let mut out = vec![0u8; 4096];
+    assert!(finalize_into(engine, out.as_mut_ptr(), out.len() as u32) > 0);
+    write_input(get_right_input_ptr(engine), rights[0]);
+    assert_eq!(diff_next_right(engine, rights[0].len() as u32), Status::Ok);
+    assert_eq!(get_entry_count(engine), 2);
+    assert_eq!(entry_count(&read_result(engine, finalize(engine))), 2);
+
+    // A plain left commit ends the series.
+    assert_eq!(clear_engine(engine), Status::Ok);
+    write_input(get_left_input_ptr(engine), left);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(diff_next_right(engine, 2), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::LeftNotRetained as u32);
+    destroy_engine(engine);
+
+    // Segments a `finalize_partial` left pending end with the next right,
+    // which is finalized whole.
+    const HEADER_FLAG_PARTIAL: u32 = 1 << 10;
+    let flags = |result: &[u8]| u32::from_le_bytes(result[12..16].try_into().unwrap());
+    let config = EngineConfig { max_memory_bytes: 4096, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(config.as_ptr(), config.len() as u32);
+    let (left, changed) = (format!("{:?}", vec![0; 600]), format!("{:?}", vec![1; 600]));
+    let one_change = format!("[1{}", &left[2..]);
+    write_input(get_left_input_ptr(engine), left.as_bytes());
+    assert_eq!(commit_left_retained(engine, left.len() as u32), Status::Ok);
+    write_input(get_right_input_ptr(engine), changed.as_bytes());
+    assert_eq!(commit_right(engine, changed.len() as u32), Status::Ok);
+    assert_eq!(finalize_partial(engine), Status::NeedFlush);
+    assert_eq!(diff_next_right(engine, changed.len() as u32), Status::ResultTruncated);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!((entry_count(&result), flags(&result) & HEADER_FLAG_PARTIAL), (127, 0));
+    assert_eq!(get_dropped_count(engine), 600 - 127);
+    assert_eq!(resume_finalize(engine), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::NoSegmentPending as u32);
+    write_input(get_right_input_ptr(engine), one_change.as_bytes());
+    assert_eq!(diff_next_right(engine, one_change.len() as u32), Status::Ok);
+    assert_eq!(entry_count(&read_result(engine, finalize(engine))), 1);
+    assert_eq!(get_dropped_count(engine), 0, "nothing dropped from this one");
+    destroy_engine(engine);
+    assert_eq!(diff_next_right(ptr::null_mut(), 0), Status::InvalidHandle);
+    assert_eq!(commit_left_retained(ptr::null_mut(), 0), Status::InvalidHandle);
+}
+
+/// Commit `left` retained on an engine made from `cfg`, export it and
+/// destroy the engine.
+fn exported_left(cfg: &[u8], left: &[u8]) -> Vec<u8> {
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    write_input(get_left_input_ptr(engine), left);
+    assert_eq!(commit_left_retained(engine, left.len() as u32), Status::Ok);
+    let blob = unsafe { std::slice::from_raw_parts(export_left_state(engine), get_left_state_len(engine) as usize) }.to_vec();
+    destroy_engine(engine);
+    blob
+}
+
+#[test]
+fn an_imported_left_diffs_as_the_committed_one() {
+    let left = br#"{"name":"svc","ports":[80,443],"env":{"A":"1","B":"x\u00e9"},"on":true}"#;
+    let rights: [&[u8]; 4] = [
+        br#"{"name":"svc","ports":[80],"env":{"A":"2","B":"x\u00e9"},"on":true,"new":{}}"#,
+        left,
+        br#"{"on":true,"env":{"B":"x\u00e9","A":"1"},"ports":[80,443],"name":"svc"}"#,
+        b"null",
+    ];
+    let plain = config_bytes();
+    let rendered = EngineConfig { max_input_size: 1 << 20, rendered_paths: true, input_digests: true, ..EngineConfig::default() }.to_bytes();
+    for cfg in [&plain[..], &rendered[..]] {
+        let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        assert!(export_left_state(engine).is_null(), "nothing retained yet");
+        assert_eq!(get_last_error_code(engine), ErrorCode::LeftNotRetained as u32);
+        destroy_engine(engine);
+        let blob = exported_left(cfg, left);
+
+        for right in rights {
+            let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+            assert_eq!(import_left_state(engine, blob.as_ptr(), blob.len() as u32), Status::Ok);
+            write_input(get_right_input_ptr(engine), right);
+            assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+            let imported = read_result(engine, finalize(engine));
+            destroy_engine(engine);
+            let fresh = create_engine(cfg.as_ptr(), cfg.len() as u32);
+            let committed = diff_and_clear(fresh, left, right);
+            destroy_engine(fresh);
+            assert_eq!(imported, committed, "{}", String::from_utf8_lossy(right));
+        }
+
+        // An imported left is retained: it takes a series of rights and
+        // exports the blob it came from.
+        let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        assert_eq!(import_left_state(engine, blob.as_ptr(), blob.len() as u32), Status::Ok);
+        for right in rights {
+            write_input(get_right_input_ptr(engine), right);
+            assert_eq!(diff_next_right(engine, right.len() as u32), Status::Ok);
+        }
+        let again = unsafe { std::slice::from_raw_parts(export_left_state(engine), get_left_state_len(engine) as usize) };
+        assert_eq!(again, &blob[..]);
+        destroy_engine(engine);
+    }
+    assert!(export_left_state(ptr::null_mut()).is_null());
+    assert_eq!(get_left_state_len(ptr::null()), 0);
+    assert_eq!(import_left_state(ptr::null_mut(), ptr::null(), 0), Status::InvalidHandle);
+}
+
+#[test]
+fn import_left_state_rejects_other_builds_and_damage() {
+    let cfg = config_bytes();
+    let left = br#"{"a":[1,2,{"b":"c"}],"d":null}"#;
+    let blob = exported_left(&cfg, left);
+    // Bytes 4 and 8 are the snapshot and result format versions; tokens
+    // start at 64, each a path id, event, kind, value hash, offset, length.
+    let edit = |at: usize, byte: u8| {
+        let mut b = blob.clone();
+        b[at] = byte;
+        b
+    };
+    let mut trailing = blob.clone();
+    trailing.push(0);
+    let cases: Vec<(Vec<u8>, &str)> = vec![
+        (Vec::new(), "not a left state snapshot"),
+        (br#"{"a":1}"#.to_vec(), "not a left state snapshot"),
+        (blob[..6].to_vec(), "truncated"),
+        (blob[..64].to_vec(), "truncated"),
+        (blob[..blob.len() - 1].to_vec(), "truncated"),
+        (trailing, "bytes after the snapshot"),
+        (edit(4, 2), "snapshot version 2 is not 1"),
+        (edit(8, blob[8] ^ 1), "written for result format"),
+        (edit(64 + 8, 9), "token 0 is malformed"),
+        (edit(64 + 21, 0xFF), "token 0 is malformed"),
+        (edit(64 + 10, blob[64 + 10] ^ 1), "tokens do not match their digest"),
+    ];
+    for (bad, why) in &cases {
+        let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        assert_eq!(import_left_state(engine, bad.as_ptr(), bad.len() as u32), Status::Error, "{why}");
+        assert_eq!(get_last_error_code(engine), ErrorCode::InvalidLeftState as u32, "{why}");
+        let error = last_error(engine).unwrap();
+        assert!(error.starts_with("invalid left state: ") && error.contains(why), "{error}");
+        // Nothing changed: the engine takes a left as if never asked.
+        write_input(get_left_input_ptr(engine), left);
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok, "{why}");
+        destroy_engine(engine);
+    }
+
+    // The importing engine must parse as the exporting one did.
+    let strict = EngineConfig { max_input_size: 1 << 20, strict_escapes: true, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(strict.as_ptr(), strict.len() as u32);
+    assert_eq!(import_left_state(engine, blob.as_ptr(), blob.len() as u32), Status::Error);
+    assert!(last_error(engine).unwrap().contains("other strict_escapes or left scope"));
+    destroy_engine(engine);
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert_eq!(set_left_scope(engine, b"/a".as_ptr(), 2), Status::Ok);
+    assert_eq!(import_left_state(engine, blob.as_ptr(), blob.len() as u32), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidLeftState as u32);
+    destroy_engine(engine);
+
+    // It takes the place of a left commit, so not after one.
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    write_input(get_left_input_ptr(engine), left);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(import_left_state(engine, blob.as_ptr(), blob.len() as u32), Status::AlreadyCommitted);
+    destroy_engine(engine);
+}
+
+#[test]
+fn resolve_symbol_names_the_changed_field() {
+    let (left, right) = (br#"{"a":{"b":[1]}}"#, br#"{"a":{"b":[2]},"c.d":{"":true}}"#);
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let path_id = |result: &[u8], i: usize| u64::from_le_bytes(result[HEADER + i * ENTRY + 8..][..8].try_into().unwrap());
+    assert!(resolve_symbol(engine, 0).is_null(), "no result yet");
+    let result = read_result(engine, finalize(engine));
+
+    let symbol = |id: u64| {
+        let p = resolve_symbol(engine, id);
+        (!p.is_null()).then(|| unsafe { std::slice::from_raw_parts(p, resolve_symbol_len(engine, id) as usize) }.to_vec())
+    };
+    assert_eq!(get_entry_count(engine), 2);
+    assert_eq!(symbol(path_id(&result, 0)).as_deref(), Some(&b"a.b[0]"[..]));
+    assert_eq!(symbol(path_id(&result, 1)).as_deref(), Some(&br#"["c.d"][""]"#[..]));
+    assert_eq!(symbol(0).as_deref(), Some(&b""[..]), "the root");
+    assert_eq!(symbol(path_id(&result, 0) ^ 1), None, "an id neither side has");
+    destroy_engine(engine);
+}
+
+#[test]
+fn symbol_table_holds_each_result_path_once() {
+    // `x` is the same on both sides: parsed, never emitted.
+    let (left, right) = (br#"{"a":[1,2,3],"x":{"y":1},"k":{"l":true}}"#, br#"{"a":[9,8],"x":{"y":1},"k":{"m":true}}"#);
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert!(get_symbol_table(engine).is_null(), "no result yet");
+    let result = read_result(engine, finalize(engine));
+
+    let len = get_symbol_table_len(engine) as usize;
+    let table = unsafe { std::slice::from_raw_parts(get_symbol_table(engine), len) }.to_vec();
+    let mut records = Vec::new();
+    let mut rest = &table[..];
+    while !rest.is_empty() {
+        let id = u64::from_le_bytes(rest[..8].try_into().unwrap());
+        let n = u32::from_le_bytes(rest[8..12].try_into().unwrap()) as usize;
+        records.push((id, rest[12..12 + n].to_vec()));
+        rest = &rest[12 + n..];
+    }
+    let ids: Vec<u64> = (0..get_entry_count(engine) as usize)
+        .map(|i| u64::from_le_bytes(result[HEADER + i * ENTRY + 8..][..8].try_into().unwrap()))
+        .collect();
+    for id in &ids {
+        assert_eq!(records.iter().filter(|(r, _)| r == id).count(), 1, "path id {:#x}", id);
+    }
+    assert_eq!(records.len(), ids.iter().collect::<std::collections::HashSet<_>>().len(), "only result paths");
+    for (id, symbol) in &records {
+        let p = resolve_symbol(engine, *id);
+        assert_eq!(unsafe { std::slice::from_raw_parts(p, resolve_symbol_len(engine, *id) as usize) }, &symbol[..]);
+    }
+    let names: Vec<&[u8]> = records.iter().map(|(_, s)| &s[..]).collect();
+    assert_eq!(names, [&b"a[0]"[..], b"a[1]", b"k.m", b"a[2]", b"k.l"], "entry order");
+    // The same table on every call, until clear drops it.
+    assert_eq!(get_symbol_table_len(engine) as usize, len);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert!(get_symbol_table(engine).is_null());
+    destroy_engine(engine);
+}
+
+#[test]
+fn engine_info_describes_the_build() {
+    let len = get_engine_info_len() as usize;
+    assert!(len >= 36);
+    let info = unsafe { std::slice::from_raw_parts(get_engine_info(), len) };
+    assert_eq!(u16::from_le_bytes([info[0], info[1]]), 1);
+    assert_eq!(u16::from_le_bytes([info[2], info[3]]) as usize, len);
+    assert_eq!(u32::from_le_bytes(info[4..8].try_into().unwrap()), get_format_version());
+    let features = u64::from_le_bytes(info[8..16].try_into().unwrap());
+    assert_eq!(features, diffcore::info::FEATURES);
+    assert_ne!(features & diffcore::info::FEATURE_LINT, 0);
+    let default = EngineConfig::default();
+    assert_eq!(u32::from_le_bytes(info[20..24].try_into().unwrap()), default.max_input_size);
+    let simd_index = cfg!(all(target_arch = "wasm32", target_feature = "simd128"));
+    assert_eq!(info[34], simd_index as u8);
+    // The same static record on every call.
+    assert_eq!(get_engine_info(), info.as_ptr());
+
+    // The flags follow the target features this test was built with.
+    use diffcore::info::{BUILD_FLAG_SIMD128, BUILD_FLAG_SIMD_INDEX};
+    let flags = get_build_flags();
+    assert_eq!(flags & BUILD_FLAG_SIMD128 != 0, cfg!(target_feature = "simd128"));
+    assert_eq!(flags & BUILD_FLAG_SIMD_INDEX != 0, simd_index);
+    assert_eq!(flags & !(BUILD_FLAG_SIMD128 | BUILD_FLAG_SIMD_INDEX), 0);
+}
+
+#[test]
+fn version_is_the_crate_version_as_semver() {
+    let v = std::str::from_utf8(unsafe { std::slice::from_raw_parts(version(), version_len() as usize) }).unwrap();
+    // `major.minor.patch[-pre][+build]`, each dot-separated identifier
+    // non-empty and `[0-9A-Za-z-]`.
+    let (rest, build) = v.split_once('+').map_or((v, None), |(r, b)| (r, Some(b)));
+    let (core, pre) = rest.split_once('-').map_or((rest, None), |(c, p)| (c, Some(p)));
+    let numbers: Vec<&str> = core.split('.').collect();
+    assert_eq!(numbers.len(), 3, "{v}");
+    for n in numbers {
+        assert!(!n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) && (n == "0" || !n.starts_with('0')), "{v}");
+    }
+    for ids in pre.into_iter().chain(build) {
+        assert!(ids.split('.').all(|id| !id.is_empty() && id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-')), "{v}");
+    }
+    assert_eq!(rest, env!("CARGO_PKG_VERSION"));
+    // Static bytes: the same address on every call.
+    assert_eq!(version(), version());
+}
+
+#[test]
+fn format_version_matches_the_result_header() {
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(get_engine_format_version(engine), get_format_version());
+    write_input(get_left_input_ptr(engine), b"[1]");
+    write_input(get_right_input_ptr(engine), b"[2]");
+    assert_eq!(commit_left(engine, 3), Status::Ok);
+    assert_eq!(commit_right(engine, 3), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    let (major, minor) = (u16::from_le_bytes([result[0], result[1]]), u16::from_le_bytes([result[2], result[3]]));
+    assert_eq!(get_format_version(), (major as u32) << 16 | minor as u32);
+    assert_eq!(get_engine_format_version(engine), get_format_version());
+    assert_eq!(diffcore::reader::ResultReader::parse(&result).unwrap().version(), (major, minor));
+    destroy_engine(engine);
+}
+
+fn stats(engine: *mut diffcore::Engine) -> Vec<u32> {
+    let p = get_stats(engine);
+    let bytes = unsafe { std::slice::from_raw_parts(p, get_stats_len(engine) as usize) };
+    bytes.chunks_exact(4).map(|c| u32::from_le_bytes(c.try_into().unwrap())).collect()
+}
+
+#[test]
+fn stats_count_the_work_of_each_phase() {
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(get_stats_len(engine), 80);
+    assert_eq!(stats(engine), [0; 20]);
+    let (left, right) = (br#"{"a":[1,2],"b":"x","c":true}"#, br#"{"a":[1,3],"c":true,"d":null}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    // Bytes, structural positions, tokens (two containers' worth of
+    // brackets and four values), Value tokens.
+    assert_eq!(stats(engine)[..8], [28, 18, 8, 4, 0, 0, 0, 0]);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert_eq!(stats(engine)[4..12], [29, 16, 8, 4, 0, 0, 0, 0]);
+
+    let result = read_result(engine, finalize(engine));
+    // Added `/d`, Removed `/b`, Modified `/a/1`.
+    assert_eq!(stats(engine)[8..], [1, 1, 1, result.len() as u32, 0, 0, 0, 0, 0, 0, 0, 0]);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!(stats(engine), [0; 20]);
+    destroy_engine(engine);
+}
+
+#[test]
+fn stepped_finalize_matches_one_shot() {
+    let items = |edit: fn(u32) -> Option<u32>| {
+        let items: Vec<String> = (0..400).filter_map(|i| Some(format!(r#"{{"id":{},"v":{}}}"#, i, edit(i)?))).collect();
+        format!(r#"{{"items":[{}],"n":1}}"#, items.join(","))
+    };
+    let left = items(Some);
+    // Some values change, and the tail goes (Removed, in the last pass).
+    let right = items(|i| (i < 390).then_some(if i % 7 == 0 { i + 1 } else { i }));
+    let cfg = config_bytes();
+    let one_shot = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let want = diff_and_clear(one_shot, left.as_bytes(), right.as_bytes());
+    assert_eq!(entry_count(&want), 56 + 2 * 10);
+
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    for budget in [256, 0] {
+        write_input(get_left_input_ptr(engine), left.as_bytes());
+        write_input(get_right_input_ptr(engine), right.as_bytes());
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        let mut steps = 0;
+        let mut progress = 0;
+        while finalize_step(engine, budget) == Status::InProgress {
+            steps += 1;
+            // Finalizing, no result yet, and the join's share done not falling.
+            assert_eq!((get_result_len(engine), get_entry_count(engine)), (0, 0));
+            assert!(get_progress(engine) >= progress && get_progress(engine) >> 16 == 3);
+            progress = get_progress(engine);
+            assert_eq!(commit_left(engine, 1), Status::Busy);
+        }
+        assert!(steps > 20, "{} steps", steps);
+        assert_eq!(get_result_len(engine), want.len() as u32);
+        assert_eq!(finalize_step(engine, 1), Status::Ok);
+        assert_eq!(read_result(engine, finalize(engine)), want);
+        assert_eq!(clear_engine(engine), Status::Ok);
+    }
+
+    // `finalize` finishes a stepped finalize in one go.
+    write_input(get_left_input_ptr(engine), left.as_bytes());
+    write_input(get_right_input_ptr(engine), right.as_bytes());
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert_eq!(finalize_step(engine, 10), Status::InProgress);
+    assert_eq!(read_result(engine, finalize(engine)), want);
+    for e in [one_shot, engine] {
+        destroy_engine(e);
+    }
+}
+
+#[test]
+fn cancel_stops_a_stepped_finalize_with_a_partial_result() {
+    const HEADER_FLAG_CANCELLED: u32 = 1 << 6;
+    // Every value changes: a full result has one entry per element.
+    let left = format!("[{}]", vec!["0"; 40_000].join(","));
+    let right = format!("[{}]", vec!["1"; 40_000].join(","));
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let commit = || {
+        write_input(get_left_input_ptr(engine), left.as_bytes());
+        write_input(get_right_input_ptr(engine), right.as_bytes());
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    };
+
+    // Five steps in, mid-join: past the overlap check, the map and the
+    // lookups, the Emit pass has written some entries.
+    commit();
+    for _ in 0..5 {
+        assert_eq!(finalize_step(engine, 30_000), Status::InProgress);
+    }
+    assert_eq!(cancel(engine), Status::Ok);
+    assert_eq!(finalize_step(engine, 30_000), Status::Cancelled);
+    assert_eq!(get_last_error_code(engine), ErrorCode::Cancelled as u32);
+    let partial = read_result(engine, finalize(engine));
+    let flags = u32::from_le_bytes(partial[12..16].try_into().unwrap());
+    assert_eq!(flags & HEADER_FLAG_CANCELLED, HEADER_FLAG_CANCELLED);
+    assert!((1..40_000).contains(&entry_count(&partial)), "{} entries", entry_count(&partial));
+    assert_eq!(commit_left(engine, 1), Status::EngineSealed);
+
+    // Reusable after clear, which also drops a pending cancel.
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!(cancel(engine), Status::Ok);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    commit();
+    let full = read_result(engine, finalize(engine));
+    assert_eq!((entry_count(&full), u32::from_le_bytes(full[12..16].try_into().unwrap()) & HEADER_FLAG_CANCELLED), (40_000, 0));
+
+    // A cancel before finalize stops the next one: null, partial result.
+    assert_eq!(clear_engine(engine), Status::Ok);
+    commit();
+    assert_eq!(cancel(engine), Status::Ok);
+    assert!(finalize(engine).is_null());
+    assert_eq!(get_last_error_code(engine), ErrorCode::Cancelled as u32);
+    assert!(get_result_len(engine) > 0);
+
+    // Another thread cancels through the flag's address alone.
+    assert_eq!(clear_engine(engine), Status::Ok);
+    commit();
+    let flag = get_cancel_flag_ptr(engine) as usize;
+    assert_eq!(finalize_step(engine, 30_000), Status::InProgress);
+    std::thread::spawn(move || {
+        // SAFETY: the flag is an atomic byte the engine outlives.
+        unsafe { &*(flag as *const std::sync::atomic::AtomicU8) }.store(1, std::sync::atomic::Ordering::Relaxed);
+    })
+    .join()
+    .unwrap();
+    assert_eq!(finalize_step(engine, u32::MAX), Status::Cancelled);
+    assert_eq!(get_cancel_flag_ptr(engine) as usize, flag, "stable for the engine's life");
+    destroy_engine(engine);
+    assert!(get_cancel_flag_ptr(ptr::null()).is_null());
+}
+
+#[test]
+fn progress_completes_each_call_and_resets() {
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(get_progress(engine), 0);
+    write_input(get_left_input_ptr(engine), b"[1]");
+    write_input(get_right_input_ptr(engine), b"[2]");
+    assert_eq!(commit_left(engine, 3), Status::Ok);
+    assert_eq!(get_progress(engine), 2 << 16 | 1000);
+    assert_eq!(commit_right(engine, 3), Status::Ok);
+    finalize(engine);
+    assert_eq!(get_progress(engine), 4 << 16 | 1000);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!(get_progress(engine), 0);
+    destroy_engine(engine);
+}
+
+#[test]
+fn effective_config_json_round_trips() {
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let ptr = get_effective_config_json(engine);
+    let json = unsafe { std::slice::from_raw_parts(ptr, get_effective_config_json_len(engine) as usize) };
+    let json = std::str::from_utf8(json).unwrap();
+    assert!(json.starts_with(r#"{"allow_empty_side":false,"arena_growth":"reserve","array_diff_mode":"index","#), "{}", json);
+    let parsed = EngineConfig::from_json(json).unwrap();
+    assert_eq!(parsed.to_bytes().to_vec(), cfg);
+    destroy_engine(engine);
+}
+
+#[test]
+fn get_result_len_is_zero_until_finalize() {
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(get_result_len(engine), 0);
+    assert_eq!(commit_left(engine, 0), Status::Ok);
+    assert_eq!(get_result_len(engine), 0);
+    read_result(engine, finalize(engine));
+    assert_eq!(get_result_len(engine) as usize, HEADER);
+    destroy_engine(engine);
+}
+
+#[test]
+fn finalize_before_any_commit_fails_and_stays_open() {
+    let engine = create_engine(ptr::null(), 0);
+    assert!(finalize(engine).is_null());
+    assert_eq!(get_last_error_code(engine), ErrorCode::NoInputCommitted as u32);
+    assert_eq!(last_error(engine).as_deref(), Some("no input committed"));
+    assert_eq!(get_result_len(engine), 0);
+
+    // Not sealed: committing an (empty) side makes the same call succeed.
+    assert_eq!(commit_right(engine, 0), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(entry_count(&result), 0);
+    destroy_engine(engine);
+}
+
+#[test]
+fn allow_empty_side_finalizes_to_flagged_empty_result() {
+    let cfg = EngineConfig { allow_empty_side: true, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(result.len(), HEADER);
+    assert_eq!(entry_count(&result), 0);
+    let flags = u32::from_le_bytes(result[12..16].try_into().unwrap());
+    // HEADER_FLAG_NO_INPUT | HEADER_FLAG_IDENTICAL.
+    assert_eq!(flags, 1 << 4 | 1 << 0);
+    destroy_engine(engine);
+}
+
+#[test]
+fn commit_after_finalize_is_sealed() {
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(commit_left(engine, 0), Status::Ok);
+    finalize(engine);
+    assert_eq!(commit_left(engine, 0), Status::EngineSealed);
+    assert_eq!(commit_right(engine, 0), Status::EngineSealed);
+    destroy_engine(engine);
+}
+
+/// A fresh engine driven through the exports into lifecycle state
+/// `state` (see `lifecycle.rs`), its docs `[1,2,3]` against `[1,2,4]`.
+fn engine_in(state: &str) -> *mut diffcore::Engine {
+    let (left, right) = (b"[1,2,3]", b"[1,2,4]");
+    let engine = create_engine(ptr::null(), 0);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    if state == "Poisoned" {
+        // An invalid transform result fails the finalize once sealed.
+        assert_eq!(set_entry_transform(engine, Some(unknown_code)), Status::Ok);
+    }
+    if matches!(state, "LeftCommitted" | "BothCommitted" | "Finalizing" | "Sealed" | "Poisoned") {
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    }
+    if matches!(state, "RightCommitted" | "BothCommitted" | "Finalizing" | "Sealed" | "Poisoned") {
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    }
+    match state {
+        "Finalizing" => assert_eq!(finalize_step(engine, 1), Status::InProgress),
+        "Sealed" => assert!(!finalize(engine).is_null()),
+        "Poisoned" => assert_eq!(finalize_step(engine, u32::MAX), Status::Error),
+        _ => {}
+    }
+    engine
+}
+
+#[test]
+fn every_export_obeys_the_lifecycle_in_every_state() {
+    use Status::{AlreadyCommitted as Again, Busy, EngineSealed as Sealed, Error, InProgress, Ok, Poisoned, TooLate};
+    type Call = fn(*mut diffcore::Engine) -> Status;
+    let calls: [(&str, Call); 11] = [
+        ("commit_left", |e| commit_left(e, 7)),
+        ("commit_right", |e| commit_right(e, 7)),
+        ("push_left", |e| push_left(e, b"[".as_ptr(), 1)),
+        ("set_left_scope", |e| set_left_scope(e, ptr::null(), 0)),
+        ("set_output_options", |e| set_output_options(e, ptr::null(), 0)),
+        ("set_entry_transform", |e| set_entry_transform(e, None)),
+        ("set_config", |e| set_config(e, ptr::null(), 0)),
+        ("lint", |e| lint(e, 0)),
+        ("finalize_step", |e| finalize_step(e, 1)),
+        ("build_span_index", |e| build_span_index(e)),
+        ("clear_engine", |e| clear_engine(e)),
+    ];
+    // Per state, per call on an engine in that state: the status. One
+    // side's join is done within a single step.
+    let table = [
+        ("Created", [Ok, Ok, Ok, Ok, Ok, Ok, Ok, Error, Error, Error, Ok]),
+        ("LeftCommitted", [Again, Ok, Again, Again, Ok, Ok, TooLate, Ok, Ok, Error, Ok]),
+        ("RightCommitted", [Ok, Again, Ok, Ok, Ok, Ok, TooLate, Error, Ok, Error, Ok]),
+        ("BothCommitted", [Again, Again, Again, Again, Ok, Ok, TooLate, Again, InProgress, Error, Ok]),
+        ("Finalizing", [Busy, Busy, Busy, Busy, Busy, Busy, Busy, Busy, InProgress, Error, Ok]),
+        ("Sealed", [Sealed, Sealed, Sealed, Sealed, Sealed, Sealed, Sealed, Sealed, Ok, Ok, Ok]),
+        ("Poisoned", [Poisoned, Poisoned, Poisoned, Poisoned, Poisoned, Poisoned, Poisoned, Poisoned, Poisoned, Poisoned, Ok]),
+    ];
+    for (state, statuses) in table {
+        for ((name, call), want) in calls.iter().zip(statuses) {
+            let engine = engine_in(state);
+            let got = call(engine);
+            assert_eq!(got, want, "{} in {}", name, state);
+            let code = get_last_error_code(engine);
+            match got {
+                Busy => assert_eq!(code, ErrorCode::Busy as u32),
+                Poisoned => assert_eq!(code, ErrorCode::Poisoned as u32),
+                Sealed => assert_eq!(code, ErrorCode::EngineSealed as u32),
+                TooLate => assert_eq!(code, ErrorCode::TooLate as u32),
+                _ => {}
+            }
+            destroy_engine(engine);
+        }
+    }
+
+    // Where each legal call leads: a commit moves to the next state, and
+    // clear takes every state, Poisoned too, back to a usable Created.
+    let engine = engine_in("Created");
+    assert_eq!(commit_right(engine, 7), Ok);
+    assert_eq!(commit_left(engine, 7), Ok);
+    assert_eq!(commit_left(engine, 7), Again, "both committed");
+    let engine_poisoned = engine_in("Poisoned");
+    assert!(finalize(engine_poisoned).is_null());
+    assert_eq!((get_result_len(engine_poisoned), get_entry_count(engine_poisoned)), (0, 0));
+    assert_eq!(trim_engine(engine_poisoned, 0), Ok);
+    for e in [engine, engine_poisoned] {
+        assert_eq!(clear_engine(e), Ok);
+        assert_eq!(set_entry_transform(e, None), Ok);
+        assert_eq!(commit_left(e, 7), Ok);
+        assert_eq!(commit_right(e, 7), Ok);
+        assert_eq!(finalize_step(e, 1), InProgress);
+        assert_eq!(finalize_step(e, u32::MAX), Ok);
+        assert_eq!(get_entry_count(e), 1);
+        destroy_engine(e);
+    }
+}
+
+#[test]
+fn set_config_applies_until_input_arrives() {
+    let engine = create_engine(ptr::null(), 0);
+    let set = |config: EngineConfig| {
+        let bytes = config.to_bytes();
+        set_config(engine, bytes.as_ptr(), bytes.len() as u32)
+    };
+    let commit = |doc: &[u8]| {
+        write_input(get_left_input_ptr(engine), doc);
+        commit_left(engine, doc.len() as u32)
+    };
+
+    // The new limits are the ones enforced: input buffers sized for 256
+    // bytes in all, key and result limits in the parsers and the arena.
+    let small = EngineConfig { max_input_size: 256, max_object_keys: 2, max_memory_bytes: 1024, ..EngineConfig::default() };
+    assert_eq!(set(small.clone()), Status::Ok);
+    assert_eq!(commit(&[b' '; 129]), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InputExceedsBuffer as u32);
+    assert_eq!(set(EngineConfig::default()), Status::Ok, "a failed commit leaves nothing committed");
+    assert_eq!(set(small.clone()), Status::Ok);
+    assert_eq!(commit(br#"{"a":1,"b":2,"c":3}"#), Status::ObjectKeyLimitExceeded);
+    clear_engine(engine);
+    let (left, right) = (format!("{:?}", [0; 32]), format!("{:?}", [1; 32]));
+    assert_eq!(commit(left.as_bytes()), Status::Ok);
+    write_input(get_right_input_ptr(engine), right.as_bytes());
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    finalize(engine);
+    assert!(get_result_len(engine) <= 1024 && get_entry_count(engine) < 32, "32 entries need 1040 bytes");
+    assert_eq!(get_last_error_code(engine), ErrorCode::MemoryLimitExceeded as u32);
+
+    // Refused once input arrived, committed or pushed, until a clear; an
+    // invalid config leaves the old one in place.
+    clear_engine(engine);
+    assert_eq!(commit(b"[1]"), Status::Ok);
+    assert_eq!(set(EngineConfig::default()), Status::TooLate);
+    clear_engine(engine);
+    assert_eq!(push_left(engine, b"[1".as_ptr(), 2), Status::Ok);
+    assert_eq!(set(EngineConfig::default()), Status::TooLate);
+    assert_eq!(get_last_error_code(engine), ErrorCode::TooLate as u32);
+    clear_engine(engine);
+    assert_eq!(set_config(engine, [0u8; 4].as_ptr(), 4), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::ConfigTooShort as u32);
+    assert_eq!(commit(br#"{"a":1,"b":2,"c":3}"#), Status::ObjectKeyLimitExceeded);
+    destroy_engine(engine);
+
+    // In a group, growth past the quota is refused and charges nothing.
+    let group = 9100;
+    let cfg = config_bytes();
+    let engine = create_engine_in_group(group, cfg.as_ptr(), cfg.len() as u32);
+    let usage = group_usage(group);
+    set_group_limits(group, 1, (usage.1 + 1000) as u32);
+    let bigger = EngineConfig { max_input_size: 4 << 20, ..EngineConfig::default() }.to_bytes();
+    assert_eq!(set_config(engine, bigger.as_ptr(), bigger.len() as u32), Status::GroupQuotaExceeded);
+    assert_eq!(group_usage(group), usage);
+    let smaller = EngineConfig { max_input_size: 1 << 10, ..EngineConfig::default() }.to_bytes();
+    assert_eq!(set_config(engine, smaller.as_ptr(), smaller.len() as u32), Status::Ok);
+    assert!(group_usage(group).1 < usage.1);
+    destroy_engine(engine);
+    assert_eq!(group_usage(group), (0, 0));
+}
+
+#[test]
+fn set_option_changes_one_field_before_input() {
+    let engine = create_engine(ptr::null(), 0);
+    let set = |key: &str, value: &str| set_option(engine, key.as_ptr(), key.len() as u32, value.as_ptr(), value.len() as u32);
+    let effective = || {
+        let ptr = get_effective_config_json(engine);
+        let json = unsafe { std::slice::from_raw_parts(ptr, get_effective_config_json_len(engine) as usize) };
+        EngineConfig::from_json(std::str::from_utf8(json).unwrap()).unwrap()
+    };
+
+    // Options accumulate over the current config, not the default.
+    assert_eq!(set("array_diff_mode", "full"), Status::Ok);
+    assert_eq!(set("max_object_keys", "2"), Status::Ok);
+    assert_eq!(set("rendered_paths", "true"), Status::Ok);
+    assert_eq!(set("redact_paths", r#"["**.password"]"#), Status::Ok);
+    let config = effective();
+    assert_eq!(config.array_diff_mode, diffcore::ArrayDiffMode::Full);
+    assert_eq!((config.max_object_keys, config.rendered_paths), (2, true));
+    assert_eq!(config.redact_paths, ["**.password"]);
+
+    // Unknown keys have their own status; bad values fail as a bad config
+    // does. Neither changes anything.
+    for key in ["numeric_epsilon", "include_unchanged", "derived", "", "array_mode"] {
+        assert_eq!(set(key, "1"), Status::UnknownOption, "{key}");
+        assert_eq!(get_last_error_code(engine), ErrorCode::UnknownOption as u32);
+    }
+    assert_eq!(last_error(engine).unwrap(), r#"unknown option "array_mode""#);
+    assert_eq!(set("array_diff_mode", "fastest"), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidConfigValue as u32);
+    assert_eq!(set("max_memory_bytes", "0"), Status::Error);
+    assert_eq!(effective().to_bytes(), config.to_bytes());
+    assert_eq!(set_option(engine, ptr::null(), 4, ptr::null(), 0), Status::Error);
+
+    // The new config is the one enforced, and only before input.
+    write_input(get_left_input_ptr(engine), br#"{"a":1,"b":2,"c":3}"#);
+    assert_eq!(commit_left(engine, 19), Status::ObjectKeyLimitExceeded);
+    clear_engine(engine);
+    assert_eq!(push_left(engine, b"[".as_ptr(), 1), Status::Ok);
+    assert_eq!(set("max_object_keys", "3"), Status::TooLate);
+    destroy_engine(engine);
+    assert_eq!(set_option(ptr::null_mut(), ptr::null(), 0, ptr::null(), 0), Status::InvalidHandle);
+}
+
+#[test]
+fn reserved_input_commits_up_to_the_reservation() {
+    let config = EngineConfig { max_input_size: 1 << 12, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(config.as_ptr(), config.len() as u32);
+    let (left_ptr, right_ptr) = (get_left_input_ptr(engine), get_right_input_ptr(engine));
+    assert_eq!(reserve_input(engine, 1 << 12, 0), Status::InputLimitExceeded, "over the side's half");
+    assert_eq!(reserve_input(engine, 1000, 10), Status::Ok);
+    assert_eq!((get_left_input_ptr(engine), get_right_input_ptr(engine)), (left_ptr, right_ptr));
+
+    // A document filling the reservation, its last byte at the far end.
+    let left = format!("[{}10]", "0,".repeat(498));
+    assert_eq!(left.len(), 1000);
+    write_input(left_ptr, left.as_bytes());
+    write_input(right_ptr, b"[]");
+    assert_eq!(commit_left(engine, 1000), Status::Ok);
+    assert_eq!(commit_right(engine, 2), Status::Ok);
+    assert_eq!(entry_count(&read_result(engine, finalize(engine))), 499);
+
+    // Past the reservation fails, though the buffer has room.
+    clear_engine(engine);
+    assert_eq!(reserve_input(engine, 2, 2), Status::Ok);
+    assert_eq!(commit_left(engine, 3), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InputExceedsBuffer as u32);
+    assert_eq!(push_right(engine, b"[1]".as_ptr(), 3), Status::InputLimitExceeded);
+    assert_eq!(reserve_input(engine, 2, 2), Status::Ok, "nothing arrived");
+    assert_eq!(push_right(engine, b"[]".as_ptr(), 2), Status::Ok);
+    assert_eq!(reserve_input(engine, 4, 4), Status::TooLate);
+
+    // A clear drops the reservation.
+    clear_engine(engine);
+    write_input(left_ptr, b"[1,2]");
+    assert_eq!(commit_left(engine, 5), Status::Ok);
+    destroy_engine(engine);
+    assert_eq!(reserve_input(ptr::null_mut(), 0, 0), Status::InvalidHandle);
+}
+
+#[test]
+fn finalize_into_writes_the_finalize_bytes() {
+    let engine = create_engine(ptr::null(), 0);
+    let diff = |left: &[u8], right: &[u8]| {
+        clear_engine(engine);
+        write_input(get_left_input_ptr(engine), left);
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    };
+    let pairs: [(&[u8], &[u8]); 3] = [
+        (b"[1]", b"[1]"),
+        (br#"{"a":1,"b":"x"}"#, br#"{"a":2,"c":true}"#),
+        (br#"{"list":[1,2,3,4,5,6,7,8,9]}"#, br#"{"list":[9,8,7,6,5,4,3,2,1]}"#),
+    ];
+    for (left, right) in pairs {
+        diff(left, right);
+        let classic = read_result(engine, finalize(engine));
+        diff(left, right);
+        let mut out = vec![0xAAu8; classic.len() + 64];
+        assert_eq!(finalize_into(engine, out.as_mut_ptr(), out.len() as u32), classic.len() as i64);
+        assert_eq!(&out[..classic.len()], &classic[..]);
+        assert!(out[classic.len()..].iter().all(|&b| b == 0xAA), "nothing past the result");
+
+        // Handed off: no result here, and no second one.
+        assert_eq!((get_result_len(engine), get_entry_count(engine)), (0, 0));
+        assert_eq!(get_result_page(engine, 0, out.as_mut_ptr(), 64), 0);
+        assert!(finalize(engine).is_null());
+        assert_eq!(get_last_error_code(engine), ErrorCode::ResultHandedOff as u32);
+        assert_eq!(finalize_into(engine, out.as_mut_ptr(), out.len() as u32), -(Status::Error as i64));
+    }
+
+    // Too small: the needed length as a hint, the result kept for a retry.
+    let (left, right) = pairs[2];
+    diff(left, right);
+    let classic = read_result(engine, finalize(engine));
+    diff(left, right);
+    let hint = finalize_into(engine, ptr::null_mut(), 0);
+    assert_eq!(hint, -((classic.len() as i64) << 8 | Status::NeedFlush as i64), "measured");
+    assert_eq!(get_last_error_code(engine), ErrorCode::OutputTooSmall as u32);
+    diff(left, right);
+    let mut out = vec![0u8; 40];
+    let hint = finalize_into(engine, out.as_mut_ptr(), 40);
+    assert_eq!((-hint & 0xFF, -hint >> 8), (Status::NeedFlush as i64, classic.len() as i64));
+    assert_eq!(read_result(engine, finalize(engine)), classic, "outgrown, then kept");
+    let mut out = vec![0u8; (-hint >> 8) as usize];
+    assert_eq!(finalize_into(engine, out.as_mut_ptr(), out.len() as u32), classic.len() as i64);
+    assert_eq!(out, classic);
+    assert_eq!(get_result_len(engine), 0);
+
+    // Failures are negative statuses.
+    clear_engine(engine);
+    assert_eq!(finalize_into(engine, out.as_mut_ptr(), out.len() as u32), -(Status::Error as i64));
+    assert_eq!(get_last_error_code(engine), ErrorCode::NoInputCommitted as u32);
+    assert_eq!(finalize_into(engine, ptr::null_mut(), 8), -(Status::Error as i64));
+    destroy_engine(engine);
+    assert_eq!(finalize_into(ptr::null_mut(), ptr::null_mut(), 0), -(Status::InvalidHandle as i64));
+}
+
+#[test]
+fn partial_segments_concatenate_to_the_whole_diff() {
+    const HEADER_FLAG_PARTIAL: u32 = 1 << 10;
+    let left = format!("[{}]", (0..600).map(|k| k.to_string()).collect::<Vec<_>>().join(","));
+    let right = format!("[{}]", (0..600).map(|k| (k + 1).to_string()).collect::<Vec<_>>().join(","));
+    let diff = |max_memory_bytes: u32| {
+        let config = EngineConfig { max_memory_bytes, ..EngineConfig::default() }.to_bytes();
+        let engine = create_engine(config.as_ptr(), config.len() as u32);
+        write_input(get_left_input_ptr(engine), left.as_bytes());
+        write_input(get_right_input_ptr(engine), right.as_bytes());
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        engine
+    };
+    let entries = |result: &[u8]| result[16..16 + entry_count(result) as usize * 32].to_vec();
+    let flags = |result: &[u8]| u32::from_le_bytes(result[12..16].try_into().unwrap());
+
+    let whole = diff(1 << 20);
+    let expected = read_result(whole, finalize(whole));
+    assert_eq!(entry_count(&expected), 600);
+    destroy_engine(whole);
+
+    let engine = diff(4096);
+    let mut status = finalize_partial(engine);
+    let mut segments = Vec::new();
+    loop {
+        let segment = read_result(engine, finalize(engine));
+        assert!(segment.len() <= 4096);
+        segments.push(segment);
+        if status == Status::Ok { break; }
+        assert_eq!(status, Status::NeedFlush);
+        status = resume_finalize(engine);
+    }
+    assert_eq!(segments.len(), 5, "127 entries to a segment");
+    let (last, rest) = segments.split_last().unwrap();
+    assert!(rest.iter().all(|s| flags(s) & HEADER_FLAG_PARTIAL != 0));
+    assert_eq!(flags(last) & HEADER_FLAG_PARTIAL, 0);
+    assert_eq!(segments.iter().flat_map(|s| entries(s)).collect::<Vec<_>>(), entries(&expected));
+
+    assert_eq!(resume_finalize(engine), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::NoSegmentPending as u32);
+    assert_eq!(read_result(engine, finalize(engine)), *last, "the last segment stays");
+    destroy_engine(engine);
+
+    // A classic finalize still drops what does not fit.
+    let engine = diff(4096);
+    assert_eq!(entry_count(&read_result(engine, finalize(engine))), 127);
+    assert_eq!(get_last_error_code(engine), ErrorCode::MemoryLimitExceeded as u32);
+    assert_eq!(finalize_partial(engine), Status::ResultTruncated, "already finalized, truncated");
+    destroy_engine(engine);
+    assert_eq!(finalize_partial(ptr::null_mut()), Status::InvalidHandle);
+    assert_eq!(resume_finalize(ptr::null_mut()), Status::InvalidHandle);
+}
+
+#[test]
+fn truncated_results_say_so() {
+    const HEADER_FLAG_TRUNCATED: u32 = 1 << 11;
+    let config = EngineConfig { max_memory_bytes: 4096, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(config.as_ptr(), config.len() as u32);
+    let diff = |n: usize| {
+        clear_engine(engine);
+        let (left, right) = (format!("{:?}", vec![0; n]), format!("{:?}", vec![1; n]));
+        write_input(get_left_input_ptr(engine), left.as_bytes());
+        write_input(get_right_input_ptr(engine), right.as_bytes());
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    };
+    let flags = |result: &[u8]| u32::from_le_bytes(result[12..16].try_into().unwrap());
+
+    // 600 changes, room for 127: the flag, the count, and which limit.
+    diff(600);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(entry_count(&result), 127);
+    assert_eq!(flags(&result) & HEADER_FLAG_TRUNCATED, HEADER_FLAG_TRUNCATED);
+    assert_eq!(get_dropped_count(engine), 600 - 127);
+    assert_eq!(get_last_error_code(engine), ErrorCode::MemoryLimitExceeded as u32);
+    assert_eq!(last_error(engine).unwrap(), "result truncated at max_memory_bytes (4096): 473 entries dropped");
+    diff(600);
+    assert_eq!(finalize_step(engine, u32::MAX), Status::ResultTruncated);
+    assert!(Status::ResultTruncated.is_recoverable());
+
+    // A diff that fits is neither.
+    diff(100);
+    assert_eq!(finalize_step(engine, u32::MAX), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!((entry_count(&result), flags(&result) & HEADER_FLAG_TRUNCATED), (100, 0));
+    assert_eq!((get_dropped_count(engine), get_last_error_code(engine)), (0, 0));
+    clear_engine(engine);
+    assert_eq!(get_dropped_count(engine), 0);
+    destroy_engine(engine);
+    assert_eq!(get_dropped_count(ptr::null()), 0);
+}
+
+#[test]
+fn diff_direct_matches_the_copy_path() {
+    let cfg = config_bytes();
+    let copied = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let direct = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let pairs: [(&[u8], &[u8]); 4] = [
+        (br#"{"a":1,"b":"x"}"#, br#"{"a":2,"c":true}"#),
+        (b"[1,2,3]", b"[1,3]"),
+        (br#"{"same":[{}]}"#, br#"{"same":[{}]}"#),
+        (br#"{"deep":{"x":{"y":null}}}"#, br#"{"deep":{"x":{"y":0}}}"#),
+    ];
+    for (left, right) in pairs {
+        clear_engine(copied);
+        write_input(get_left_input_ptr(copied), left);
+        write_input(get_right_input_ptr(copied), right);
+        assert_eq!(commit_left(copied, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(copied, right.len() as u32), Status::Ok);
+        let expected = read_result(copied, finalize(copied));
+
+        clear_engine(direct);
+        assert_eq!(diff_direct(direct, left.as_ptr(), left.len() as u32, right.as_ptr(), right.len() as u32), Status::Ok);
+        assert_eq!(read_result(direct, finalize(direct)), expected);
+        // Entry values point into the host's bytes.
+        if get_entry_count(direct) > 0 && get_entry_left_len(direct, 0) > 0 {
+            let value = get_entry_left(direct, 0) as usize;
+            assert!((left.as_ptr() as usize..left.as_ptr() as usize + left.len()).contains(&value));
+        }
+    }
+
+    // A failing side leaves the engine cleared for the next try.
+    clear_engine(direct);
+    let (big, good) = (vec![b' '; 1 << 20], b"{}");
+    assert_eq!(diff_direct(direct, big.as_ptr(), big.len() as u32, good.as_ptr(), good.len() as u32), Status::Error);
+    assert_eq!(diff_direct(direct, good.as_ptr(), good.len() as u32, good.as_ptr(), good.len() as u32), Status::Ok);
+
+    // Only on a fresh engine; pointers must be real.
+    clear_engine(direct);
+    write_input(get_left_input_ptr(direct), good);
+    assert_eq!(commit_left(direct, good.len() as u32), Status::Ok);
+    assert_eq!(diff_direct(direct, good.as_ptr(), 2, good.as_ptr(), 2), Status::TooLate);
+    clear_engine(direct);
+    assert_eq!(diff_direct(direct, ptr::null(), 2, good.as_ptr(), 2), Status::Error);
+    assert_eq!(diff_direct(ptr::null_mut(), good.as_ptr(), 2, good.as_ptr(), 2), Status::InvalidHandle);
+    destroy_engine(copied);
+    destroy_engine(direct);
+}
+
+#[test]
+fn memory_usage_follows_the_collections() {
+    // [left_input, right_input, third_input, left_tokens, right_tokens,
+    //  third_tokens, index, interner, arena_len, arena_capacity]
+    let usage = |engine| {
+        let ptr = get_memory_usage(engine);
+        let bytes = unsafe { std::slice::from_raw_parts(ptr, 40) };
+        let mut fields = [0u32; 10];
+        for (field, chunk) in fields.iter_mut().zip(bytes.chunks_exact(4)) {
+            *field = u32::from_le_bytes(chunk.try_into().unwrap());
+        }
+        fields
+    };
+    let doc = |v: &str| format!("[{}]", vec![format!(r#"{{"k":"{v}"}}"#); 400].join(","));
+    let (left_doc, right_doc) = (doc("vvvvvvvv"), doc("wwwwwwww"));
+    let run = |mode: &str| {
+        let config = EngineConfig::from_json(&format!(r#"{{"compute_mode":"{mode}"}}"#)).unwrap().to_bytes();
+        let engine = create_engine(config.as_ptr(), config.len() as u32);
+        let fresh = usage(engine);
+        assert_eq!(fresh[..8], [0; 8], "nothing allocated before input");
+        write_input(get_left_input_ptr(engine), left_doc.as_bytes());
+        assert_eq!(commit_left(engine, left_doc.len() as u32), Status::Ok);
+        let left = usage(engine);
+        assert!(left[0] as usize >= left_doc.len() && left[3] > 0);
+        assert_eq!((left[1], left[4], left[6]), (0, 0, 0), "right untouched; index back in the pool");
+        write_input(get_right_input_ptr(engine), right_doc.as_bytes());
+        assert_eq!(commit_right(engine, right_doc.len() as u32), Status::Ok);
+        assert!(!finalize(engine).is_null());
+        let done = usage(engine);
+        assert_eq!((done[1], done[4]), (left[0], left[3]), "same shape, same capacities");
+        assert_eq!(done[8], get_result_len(engine));
+        assert!(done[9] >= done[8] && done[8] > fresh[8]);
+
+        // Clearing keeps capacities for the next diff; a full trim frees them.
+        assert_eq!(clear_engine(engine), Status::Ok);
+        let cleared = usage(engine);
+        assert_eq!(cleared[..8], done[..8]);
+        assert_eq!(cleared[8], fresh[8]);
+        assert_eq!(trim_engine(engine, 2), Status::Ok);
+        assert_eq!(usage(engine)[3..6], [0; 3]);
+        destroy_engine(engine);
+        done
+    };
+    // Throughput reserves 8x latency's tokens up front.
+    let (latency, throughput) = (run("latency"), run("throughput"));
+    assert_eq!(throughput[3], 8 * latency[3]);
+    assert!(get_memory_usage(ptr::null_mut()).is_null());
+}
+
+#[test]
+fn split_results_partition_the_entries_by_op() {
+    const HEADER_FLAG_CHANNEL: u32 = 1 << 12;
+    let config = EngineConfig { split_by_op: true, rendered_paths: true, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(config.as_ptr(), config.len() as u32);
+    let channel = |engine, op: u8| {
+        let ptr = get_result_ptr_for(engine, op);
+        if ptr.is_null() { return None; }
+        Some(unsafe { std::slice::from_raw_parts(ptr, get_result_len_for(engine, op) as usize) }.to_vec())
+    };
+    let (left, right) = (br#"{"a":1,"b":[1,2,3],"c":{"d":"x"},"e":0}"#, br#"{"a":2,"b":[1,3],"c":{"d":"y","f":true},"g":null}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert_eq!(channel(engine, 0), None, "nothing before finalize");
+    let result = read_result(engine, finalize(engine));
+    let stats = unsafe { std::slice::from_raw_parts(get_stats(engine), get_stats_len(engine) as usize) };
+    let by_op = |op: usize| u32::from_le_bytes(stats[32 + 4 * op..36 + 4 * op].try_into().unwrap());
+
+    let entries = |r: &[u8]| r[16..16 + entry_count(r) as usize * 32].chunks_exact(32).map(<[u8]>::to_vec).collect::<Vec<_>>();
+    let mut classic = entries(&result);
+    let mut union = Vec::new();
+    for op in 0..3u8 {
+        let part = channel(engine, op).unwrap();
+        // Its own header: the result's version, its count and length.
+        assert_eq!(part[0..4], result[0..4]);
+        assert_eq!(entry_count(&part), by_op(op as usize), "op {}", op);
+        assert!(entry_count(&part) > 0, "op {}", op);
+        assert_eq!(u32::from_le_bytes(part[8..12].try_into().unwrap()) as usize, part.len());
+        assert_eq!(part.len(), 16 + 32 * entry_count(&part) as usize, "entries only");
+        let flags = u32::from_le_bytes(part[12..16].try_into().unwrap());
+        assert_eq!(flags & (HEADER_FLAG_CHANNEL | 1 << 1), HEADER_FLAG_CHANNEL, "no sections");
+        let mine = entries(&part);
+        assert!(mine.iter().all(|e| e[0] == op));
+        assert_eq!(mine, classic.iter().filter(|e| e[0] == op).cloned().collect::<Vec<_>>(), "result order");
+        union.extend(mine);
+    }
+    assert_eq!(channel(engine, 3), None);
+    union.sort();
+    classic.sort();
+    assert_eq!(union, classic);
+
+    // The classic result is unchanged, and the channels go with it.
+    assert_ne!(u32::from_le_bytes(result[12..16].try_into().unwrap()) & 1 << 1, 0, "paths section kept");
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!((channel(engine, 0), get_result_len_for(engine, 0)), (None, 0));
+    destroy_engine(engine);
+
+    // Off by default; an output option turns it on after the commits.
+    let engine = create_engine(ptr::null(), 0);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let record = [&[32u8][..], &1u32.to_le_bytes()].concat();
+    assert_eq!(set_output_options(engine, record.as_ptr(), record.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    let parts: u32 = (0..3).map(|op| entry_count(&channel(engine, op).unwrap())).sum();
+    assert_eq!(parts, entry_count(&result));
+    destroy_engine(engine);
+    assert!(get_result_ptr_for(ptr::null(), 0).is_null());
+}
+
+#[test]
+fn commit_beyond_capacity_is_rejected() {
+    let cfg = config_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert_eq!(commit_left(engine, u32::MAX), Status::Error);
+    destroy_engine(engine);
+}
+
+#[test]
+fn null_handle_is_rejected_everywhere() {
+    let null = ptr::null_mut();
+    assert_eq!(commit_left(null, 0), Status::InvalidHandle);
+    assert_eq!(commit_right(null, 0), Status::InvalidHandle);
+    assert_eq!(clear_engine(null), Status::InvalidHandle);
+    assert_eq!(trim_engine(null, 0), Status::InvalidHandle);
+    assert!(get_left_input_ptr(null).is_null());
+    assert!(get_right_input_ptr(null).is_null());
+    assert!(finalize(null).is_null());
+    assert_eq!(finalize_step(null, 1), Status::InvalidHandle);
+    assert_eq!(cancel(null), Status::InvalidHandle);
+    assert_eq!(get_result_page(null, 0, [0u8; 64].as_mut_ptr(), 64), 0);
+    assert_eq!(get_entry_op(null, 0), 0xFF);
+    assert!(get_entry_path(null, 0).is_null());
+    assert_eq!(get_entry_path_len(null, 0), 0);
+    assert!(get_entry_left(null, 0).is_null());
+    assert_eq!(get_entry_left_len(null, 0), 0);
+    assert!(get_entry_right(null, 0).is_null());
+    assert_eq!(get_entry_right_len(null, 0), 0);
+    assert!(resolve_symbol(null, 0).is_null());
+    assert_eq!(resolve_symbol_len(null, 0), 0);
+    assert!(get_symbol_table(null).is_null());
+    assert_eq!(get_symbol_table_len(null), 0);
+    assert_eq!(get_result_len(null), 0);
+    assert_eq!(get_entry_count(null), 0);
+    assert_eq!(get_engine_format_version(null), 0);
+    assert!(get_stats(null).is_null());
+    assert_eq!(get_stats_len(null), 0);
+    assert!(get_last_error(null).is_null());
+    assert_eq!(get_last_error_len(null), 0);
+    assert_eq!(get_last_error_code(null), 0);
+    assert_eq!(get_progress(null), 0);
+    assert!(get_effective_config_json(null).is_null());
+    assert_eq!(get_effective_config_json_len(null), 0);
+    assert_eq!(load_suppressions(null, ptr::null(), 0), Status::InvalidHandle);
+    assert_eq!(get_suppressed_count(null), 0);
+    assert_eq!(get_ranked_total(null), 0);
+    assert_eq!(set_entry_transform(null, None), Status::InvalidHandle);
+    assert_eq!(get_memory_report(null, [0u8; 32].as_mut_ptr()), Status::InvalidHandle);
+    assert_eq!(get_arena_stats(null, [0u8; 24].as_mut_ptr()), Status::InvalidHandle);
+    assert_eq!(get_audit_log(null, [0u8; 16].as_mut_ptr(), 16), 0);
+    assert_eq!(commit_third(null, 0), Status::InvalidHandle);
+    assert!(get_third_input_ptr(null).is_null());
+    assert_eq!(destroy_engine(null), Status::Ok);
+}
+
+#[test]
+fn second_commit_on_a_side_is_rejected() {
+    let engine = create_engine(ptr::null(), 0);
+    let doc = br#"{"a":1}"#;
+    write_input(get_left_input_ptr(engine), doc);
+    assert_eq!(commit_left(engine, doc.len() as u32), Status::Ok);
+    assert_eq!(commit_left(engine, doc.len() as u32), Status::AlreadyCommitted);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!(commit_left(engine, doc.len() as u32), Status::Ok);
+    destroy_engine(engine);
+}
+
+#[test]
+fn scope_must_precede_commit() {
+    let engine = create_engine(ptr::null(), 0);
+    let scope = b"/users";
+    let doc = br#"{"users":[1]}"#;
+    assert_eq!(set_left_scope(engine, scope.as_ptr(), scope.len() as u32), Status::Ok);
+    write_input(get_left_input_ptr(engine), doc);
+    assert_eq!(commit_left(engine, doc.len() as u32), Status::Ok);
+    assert_eq!(set_left_scope(engine, scope.as_ptr(), scope.len() as u32), Status::AlreadyCommitted);
+    assert_eq!(set_right_scope(ptr::null_mut(), ptr::null(), 0), Status::InvalidHandle);
+    destroy_engine(engine);
+}
+
+#[test]
+fn output_options_apply_until_finalize() {
+    // entry_order (field 11) = OpThenPath (2): Added before Modified.
+    let order = [11u8, 2, 0, 0, 0];
+    let engine = create_engine(ptr::null(), 0);
+    let (left, right) = (br#"{"a":1}"#, br#"{"a":2,"b":3}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert_eq!(set_output_options(engine, order.as_ptr(), order.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!((result[HEADER], result[HEADER + ENTRY]), (0, 2), "Added, then Modified");
+    assert_eq!(set_output_options(engine, order.as_ptr(), order.len() as u32), Status::EngineSealed);
+
+    // max_input_size (field 1) is fixed at creation.
+    clear_engine(engine);
+    let limit = [1u8, 0, 1, 0, 0];
+    assert_eq!(set_output_options(engine, limit.as_ptr(), limit.len() as u32), Status::Error);
+    assert!(last_error(engine).unwrap().contains("max_input_size"));
+    destroy_engine(engine);
+}
+
+#[test]
+fn previous_result_suppresses_known_changes() {
+    // value_hashes (field 14) on, so the result can be loaded back.
+    let hashes = [14u8, 1, 0, 0, 0];
+    let left = br#"{"a":1,"b":2}"#;
+    let diff = |suppress: &[u8], right: &[u8]| {
+        let engine = create_engine(ptr::null(), 0);
+        assert_eq!(set_output_options(engine, hashes.as_ptr(), hashes.len() as u32), Status::Ok);
+        if !suppress.is_empty() {
+            assert_eq!(load_suppressions(engine, suppress.as_ptr(), suppress.len() as u32), Status::Ok);
+        }
+        write_input(get_left_input_ptr(engine), left);
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        let result = read_result(engine, finalize(engine));
+        let suppressed = get_suppressed_count(engine);
+        destroy_engine(engine);
+        (result, suppressed)
+    };
+    let (base, _) = diff(&[], br#"{"a":9,"b":2}"#);
+    assert_eq!(entry_count(&base), 1);
+    let (result, suppressed) = diff(&base, br#"{"a":9,"b":3}"#);
+    assert_eq!((entry_count(&result), suppressed), (1, 1));
+
+    // A result without the value hash section is refused.
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(commit_left(engine, 0), Status::Ok);
+    let plain = read_result(engine, finalize(engine));
+    clear_engine(engine);
+    assert_eq!(load_suppressions(engine, plain.as_ptr(), plain.len() as u32), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidSuppressions as u32);
+    destroy_engine(engine);
+}
+
+#[test]
+fn failing_statuses_expose_detailed_codes() {
+    let code = |engine| get_last_error_code(engine);
+    let cfg = EngineConfig { max_object_keys: 1, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    assert_eq!(code(engine), ErrorCode::None as u32);
+
+    let doc = br#"{"a":1,"b":2}"#;
+    write_input(get_left_input_ptr(engine), doc);
+    assert_eq!(commit_left(engine, doc.len() as u32), Status::ObjectKeyLimitExceeded);
+    assert_eq!(code(engine), ErrorCode::ObjectKeyLimitExceeded as u32);
+    assert_eq!(commit_left(engine, 0), Status::AlreadyCommitted);
+    assert_eq!(code(engine), ErrorCode::AlreadyCommitted as u32);
+    assert_eq!(commit_right(engine, u32::MAX), Status::Error);
+    assert_eq!(code(engine), ErrorCode::InputExceedsBuffer as u32);
+    assert_eq!(build_span_index(engine), Status::Error);
+    assert_eq!(code(engine), ErrorCode::NotFinalized as u32);
+    let field = [0xEEu8, 0, 0, 0, 0];
+    assert_eq!(set_output_options(engine, field.as_ptr(), field.len() as u32), Status::Error);
+    assert_eq!(code(engine), ErrorCode::UnknownConfigField as u32);
+    assert_eq!(set_right_scope(engine, b"x".as_ptr(), 1), Status::Error);
+    assert_eq!(code(engine), ErrorCode::MalformedScope as u32);
+
+    finalize(engine);
+    assert_eq!(commit_right(engine, 0), Status::EngineSealed);
+    assert_eq!(code(engine), ErrorCode::EngineSealed as u32);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    assert_eq!((code(engine), last_error(engine)), (0, None));
+    destroy_engine(engine);
+}
+
+#[test]
+fn errors_last_only_until_the_next_commit_or_finalize() {
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(commit_left(engine, u32::MAX), Status::Error);
+    assert_eq!(get_last_error_code(engine), ErrorCode::InputExceedsBuffer as u32);
+    let (left, right) = (br#"{"a":1}"#, br#"{"a":2}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(get_last_error_len(engine), 0);
+    assert_eq!(set_right_scope(engine, b"x".as_ptr(), 1), Status::Error);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert_eq!(build_span_index(engine), Status::Error);
+    assert!(!finalize(engine).is_null());
+    assert_eq!((get_last_error_len(engine), get_last_error_code(engine)), (0, 0));
+
+    // Other calls leave it until the host clears it.
+    assert_eq!(commit_right(engine, 0), Status::EngineSealed);
+    assert!(get_result_len(engine) > 0);
+    assert_eq!(get_last_error_code(engine), ErrorCode::EngineSealed as u32);
+    assert_eq!(clear_error(engine), Status::Ok);
+    assert_eq!((get_last_error_len(engine), get_last_error_code(engine), last_error(engine)), (0, 0, None));
+    assert_eq!(clear_error(ptr::null_mut()), Status::InvalidHandle);
+    destroy_engine(engine);
+}
+
+#[test]
+fn parse_failures_surface_their_own_status() {
+    // The failures the parser raises today; `UnexpectedByte` and
+    // `IncompleteInput` map to `Error` (see `error.rs`) once it does.
+    let cases: [(&[u8], u32, bool, Status, ErrorCode); 2] = [
+        (br#"{"a":1,"b":2}"#, 1, false, Status::ObjectKeyLimitExceeded, ErrorCode::ObjectKeyLimitExceeded),
+        (br#"{"a":"\u12"}"#, 100, true, Status::Error, ErrorCode::InvalidEscape),
+    ];
+    for (doc, max_object_keys, strict_escapes, status, code) in cases {
+        let cfg = EngineConfig { max_object_keys, strict_escapes, ..EngineConfig::default() }.to_bytes();
+        let what = String::from_utf8_lossy(doc);
+        // Either side's commit, and a pushed side failing at finalize.
+        for side in 0..3 {
+            let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+            let got = match side {
+                0 => {
+                    write_input(get_left_input_ptr(engine), doc);
+                    commit_left(engine, doc.len() as u32)
+                }
+                1 => {
+                    write_input(get_right_input_ptr(engine), doc);
+                    commit_right(engine, doc.len() as u32)
+                }
+                _ => {
+                    assert_eq!(push_right(engine, doc.as_ptr(), doc.len() as u32), Status::Ok);
+                    finalize_step(engine, u32::MAX)
+                }
+            };
+            assert_eq!(got, status, "{} side {}", what, side);
+            assert_eq!(get_last_error_code(engine), code as u32, "{} side {}", what, side);
+            assert!(last_error(engine).is_some_and(|m| m.len() > 1), "{} side {}", what, side);
+            destroy_engine(engine);
+        }
+    }
+}
+
+#[test]
+fn input_digests_outlive_the_engine() {
+    let cfg = EngineConfig { input_digests: true, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let (left, right) = (br#"{"a":1}"#, br#"{"a":2}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    let mut digests = [0u8; 32];
+    assert_eq!(get_input_digests(engine, digests.as_mut_ptr()), Status::Ok);
+    destroy_engine(engine);
+
+    // The copied result carries the digests on its own.
+    assert_eq!(result[result.len() - 32..], digests);
+    assert_ne!(digests[..16], digests[16..]);
+
+    let plain = create_engine(ptr::null(), 0);
+    assert_eq!(get_input_digests(plain, digests.as_mut_ptr()), Status::Error);
+    assert_eq!(get_last_error_code(plain), ErrorCode::FeatureDisabled as u32);
+    destroy_engine(plain);
+}
+
+fn group_usage(group: u32) -> (u32, u64) {
+    let mut out = [0u8; 12];
+    assert_eq!(get_group_usage(group, out.as_mut_ptr()), Status::Ok);
+    (u32::from_le_bytes(out[0..4].try_into().unwrap()), u64::from_le_bytes(out[4..12].try_into().unwrap()))
+}
+
+#[test]
+fn group_quotas_are_independent() {
+    let (small, roomy) = (9001, 9002);
+    let cfg = config_bytes();
+
+    // Probe one engine's reservation with an unlimited group.
+    let probe = create_engine_in_group(9000, cfg.as_ptr(), cfg.len() as u32);
+    let (_, reserved) = group_usage(9000);
+    destroy_engine(probe);
+    assert_eq!(group_usage(9000), (0, 0));
+
+    // `small`: one engine, a few hundred bytes of token headroom.
+    set_group_limits(small, 1, (reserved + 300) as u32);
+    set_group_limits(roomy, 4, u32::MAX);
+    let a = create_engine_in_group(small, cfg.as_ptr(), cfg.len() as u32);
+    assert!(!a.is_null());
+    assert!(create_engine_in_group(small, cfg.as_ptr(), cfg.len() as u32).is_null(), "engine cap");
+    let b = create_engine_in_group(roomy, cfg.as_ptr(), cfg.len() as u32);
+    let c = create_engine_in_group(roomy, cfg.as_ptr(), cfg.len() as u32);
+    assert!(!b.is_null() && !c.is_null());
+
+    let doc = br#"{"a":[1,2,3,4,5,6,7,8,9,10,11,12,13,14,15,16]}"#;
+    write_input(get_left_input_ptr(a), doc);
+    assert_eq!(commit_left(a, doc.len() as u32), Status::GroupQuotaExceeded);
+    assert_eq!(last_error(a).as_deref(), Some("group quota exceeded"));
+    assert_eq!(group_usage(small), (1, reserved), "refused commit charges nothing");
+    for e in [b, c] {
+        write_input(get_left_input_ptr(e), doc);
+        assert_eq!(commit_left(e, doc.len() as u32), Status::Ok);
+    }
+    let parsed = group_usage(roomy).1 - 2 * reserved;
+    assert!(parsed > 0);
+
+    // Clearing returns `b`'s parse memory; `c` still holds the same amount.
+    clear_engine(b);
+    assert_eq!(group_usage(roomy), (2, 2 * reserved + parsed / 2));
+    for e in [a, b, c] {
+        assert_eq!(destroy_engine(e), Status::Ok);
+    }
+    assert_eq!(group_usage(small), (0, 0));
+    assert_eq!(group_usage(roomy), (0, 0));
+}
+
+fn index_pool_stats(group: u32) -> [u32; 4] {
+    let mut out = [0u8; 16];
+    assert_eq!(get_index_pool_stats(group, out.as_mut_ptr()), Status::Ok);
+    std::array::from_fn(|i| u32::from_le_bytes(out[i * 4..i * 4 + 4].try_into().unwrap()))
+}
+
+#[test]
+fn group_engines_share_pooled_indexes() {
+    let group = 9100;
+    let cfg = config_bytes();
+    assert_eq!(get_index_pool_stats(group, ptr::null_mut()), Status::Error);
+    assert_eq!(index_pool_stats(group), [0; 4]);
+
+    let doc = br#"{"a":[1,2,3]}"#;
+    let engines: Vec<_> = (0..4).map(|_| create_engine_in_group(group, cfg.as_ptr(), cfg.len() as u32)).collect();
+    for &e in &engines {
+        write_input(get_left_input_ptr(e), doc);
+        assert_eq!(commit_left(e, doc.len() as u32), Status::Ok);
+    }
+    // [idle, checkouts, reuses, discards]: one index served all four commits.
+    assert_eq!(index_pool_stats(group), [1, 4, 3, 0]);
+
+    assert_eq!(set_index_pool_limit(group, 0), Status::Ok);
+    assert_eq!(index_pool_stats(group), [0, 4, 3, 0]);
+    write_input(get_right_input_ptr(engines[0]), doc);
+    assert_eq!(commit_right(engines[0], doc.len() as u32), Status::Ok);
+    assert_eq!(index_pool_stats(group), [0, 5, 3, 1]);
+    for e in engines {
+        destroy_engine(e);
+    }
+}
+
+/// `(op, status, arg)` of each record, for comparing call sequences.
+fn audit_calls(records: &[AuditRecord]) -> Vec<(u8, u8, u32)> {
+    records.iter().map(|r| (r.op, r.status, r.arg)).collect()
+}
+
+fn audit_log(engine: *mut diffcore::Engine, cap: u32) -> Vec<AuditRecord> {
+    let mut out = vec![0u8; cap as usize];
+    let written = get_audit_log(engine, out.as_mut_ptr(), cap) as usize;
+    out[..written].chunks_exact(AuditRecord::SIZE).map(AuditRecord::from_bytes).collect()
+}
+
+#[test]
+fn audit_log_records_calls_into_the_result() {
+    let cfg = EngineConfig { audit_log_len: 4, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let (left, right) = (br#"{"a":1}"#, br#"{"a":2}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::AlreadyCommitted);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+
+    // The result carries the calls before its finalize.
+    let (create, commit_l, commit_r) = (AuditOp::Create as u8, AuditOp::CommitLeft as u8, AuditOp::CommitRight as u8);
+    let logged = diffcore::reader::ResultReader::parse(&result).unwrap().audit_log().unwrap();
+    assert_eq!(logged.iter().map(|r| r.seq).collect::<Vec<_>>(), [0, 1, 2, 3]);
+    assert_eq!(
+        audit_calls(&logged),
+        [(create, 0, cfg.len() as u32), (commit_l, 0, 7), (commit_l, Status::AlreadyCommitted as u8, 7), (commit_r, 0, 7)]
+    );
+    assert_eq!((logged[1].error_code, logged[2].error_code), (0, ErrorCode::AlreadyCommitted as u32));
+
+    // Live, the ring has wrapped past the create and holds the finalize.
+    let live = audit_log(engine, 64);
+    assert_eq!(live.iter().map(|r| r.seq).collect::<Vec<_>>(), [1, 2, 3, 4]);
+    assert_eq!(audit_calls(&live[3..]), [(AuditOp::Finalize as u8, 0, result.len() as u32)]);
+    assert_eq!(clear_engine(engine), Status::Ok);
+    let newest = audit_log(engine, 40);
+    assert_eq!(audit_calls(&newest), [(AuditOp::Finalize as u8, 0, result.len() as u32), (AuditOp::Clear as u8, 0, 0)]);
+    assert_eq!(newest[1].seq, 5, "clear keeps the log and the numbering");
+    destroy_engine(engine);
+
+    let plain = create_engine(ptr::null(), 0);
+    assert_eq!(get_audit_log(plain, [0u8; 16].as_mut_ptr(), 16), 0);
+    assert_eq!(commit_left(plain, 0), Status::Ok);
+    let result = read_result(plain, finalize(plain));
+    assert!(diffcore::reader::ResultReader::parse(&result).unwrap().audit_log().is_none());
+    destroy_engine(plain);
+}
+
+#[test]
+fn three_way_commits_a_third_input_and_writes_canary_records() {
+    let cfg = EngineConfig { three_way: true, ..EngineConfig::default() }.to_bytes();
+    let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let (left, control, canary) = (br#"{"a":1,"b":1}"#, br#"{"a":2,"b":1}"#, br#"{"a":2,"b":3}"#);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), control);
+    write_input(get_third_input_ptr(engine), canary);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, control.len() as u32), Status::Ok);
+    assert_eq!(commit_third(engine, canary.len() as u32), Status::Ok);
+    assert_eq!(commit_third(engine, canary.len() as u32), Status::AlreadyCommitted);
+
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(entry_count(&result), 2, "the control's change, then the canary's");
+    let records = diffcore::reader::ResultReader::parse(&result).unwrap().canary().unwrap();
+    assert_eq!(records.iter().map(|r| r.agreement).collect::<Vec<_>>(), [0, 1], "agree, canary only");
+    destroy_engine(engine);
+
+    let plain = create_engine(ptr::null(), 0);
+    assert!(get_third_input_ptr(plain).is_null());
+    assert_eq!(commit_third(plain, 0), Status::Error);
+    assert_eq!(get_last_error_code(plain), ErrorCode::FeatureDisabled as u32);
+    destroy_engine(plain);
+}
+
+#[test]
+fn probe_reads_a_prefix_without_an_engine() {
+    let doc = br#"{"items":[1,2,3],"name":"a\"b","meta":{"x":{}}}"#;
+    let mut out = [0u8; 16];
+    assert_eq!(probe(doc.as_ptr(), 24, out.as_mut_ptr()), Status::Ok);
+    // Object, cut inside the escaped string: 2 members so far, depth 2.
+    assert_eq!(out[0], 1);
+    assert_eq!(u32::from_le_bytes(out[4..8].try_into().unwrap()), 2);
+    assert_eq!(u32::from_le_bytes(out[8..12].try_into().unwrap()), 2);
+    assert_eq!(probe(doc.as_ptr(), doc.len() as u32, out.as_mut_ptr()), Status::Ok);
+    assert_eq!(
+        [u32::from_le_bytes(out[4..8].try_into().unwrap()), u32::from_le_bytes(out[8..12].try_into().unwrap())],
+        [3, 3]
+    );
+
+    assert_eq!(probe(ptr::null(), 0, out.as_mut_ptr()), Status::Ok);
+    assert_eq!(out[0], 0, "nothing to probe");
+    assert_eq!(probe(ptr::null(), 4, out.as_mut_ptr()), Status::Error);
+    assert_eq!(probe(doc.as_ptr(), 4, ptr::null_mut()), Status::Error);
+}
+
+#[test]
+fn self_test_passes_and_clears_the_create_error() {
+    assert!(create_engine([0u8; 20].as_ptr(), 20).is_null());
+    assert_eq!(self_test(), 0, "{:?}", create_error());
+    assert_eq!((create_error(), get_create_error_code()), (None, 0));
+}
+
+#[test]
+fn pushed_chunks_diff_like_one_commit() {
+    let left = br#"{"a":1,"b":"x\"y","c":[1,2,3]}"#;
+    let right = br#"{"a":2,"b":"x\"z","c":[1,2],"d":true}"#;
+    let cfg = config_bytes();
+    let committed = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    write_input(get_left_input_ptr(committed), left);
+    write_input(get_right_input_ptr(committed), right);
+    assert_eq!(commit_left(committed, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(committed, right.len() as u32), Status::Ok);
+    let want = read_result(committed, finalize(committed));
+
+    let pushed = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    for chunk in left.chunks(3) {
+        assert_eq!(push_left(pushed, chunk.as_ptr(), chunk.len() as u32), Status::Ok);
+    }
+    for chunk in right.chunks(5) {
+        assert_eq!(push_right(pushed, chunk.as_ptr(), chunk.len() as u32), Status::Ok);
+    }
+    assert_eq!(read_result(pushed, finalize(pushed)), want);
+    assert_eq!(push_left(pushed, left.as_ptr(), 1), Status::EngineSealed);
+    assert_eq!(push_left(pushed, ptr::null(), 1), Status::Error);
+    assert_eq!(push_right(ptr::null_mut(), left.as_ptr(), 1), Status::InvalidHandle);
+
+    for engine in [committed, pushed] {
+        assert_eq!(destroy_engine(engine), Status::Ok);
+    }
+}
+
+/// Diff `left` and `right` on a fresh engine, with `transform` set after
+/// the left commit (so that side is parsed again for paths).
+fn diff_transformed(transform: Option<EntryTransform>, left: &[u8], right: &[u8]) -> (Vec<u8>, u32) {
+    let engine = create_engine(ptr::null(), 0);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(set_entry_transform(engine, transform), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    let result = read_result(engine, finalize(engine));
+    assert_eq!(set_entry_transform(engine, None), Status::EngineSealed);
+    let ranked = get_ranked_total(engine);
+    destroy_engine(engine);
+    (result, ranked)
+}
+
+/// # Safety
+/// `ptr` is null with `len` 0, or `len` readable bytes.
+unsafe fn bytes<'a>(ptr: *const u8, len: u32) -> &'a [u8] {
+    if ptr.is_null() { &[] } else { std::slice::from_raw_parts(ptr, len as usize) }
+}
+
+extern "C" fn drop_secrets(_: u32, path: *const u8, path_len: u32, _: *const u8, _: u32, _: *const u8, _: u32, out: *mut u32) -> u32 {
+    let path = unsafe { bytes(path, path_len) };
+    if path.starts_with(b"/secrets/") {
+        return TRANSFORM_DROP;
+    }
+    unsafe { *out = path_len };
+    TRANSFORM_KEEP
+}
+
+#[test]
+fn transform_drops_entries_by_path_prefix() {
+    let left = br#"{"secrets":{"token":"a","key":1},"n":1,"secretsx":0}"#;
+    let right = br#"{"secrets":{"token":"b"},"n":2,"secretsx":1}"#;
+    let (result, ranked) = diff_transformed(Some(drop_secrets), left, right);
+    let reader = diffcore::reader::ResultReader::parse(&result).unwrap();
+    assert_eq!((reader.entry_count(), ranked), (2, 2), "dropped before ranking");
+    // Annotations are what the transform wrote: here the path length.
+    assert_eq!(reader.annotations(), Some(vec![2, 9]));
+    assert!(reader.entries().all(|e| !e.redacted()));
+}
+
+extern "C" fn redact_hunter2(op: u32, _: *const u8, _: u32, left: *const u8, left_len: u32, right: *const u8, right_len: u32, out: *mut u32) -> u32 {
+    let sides = unsafe { [bytes(left, left_len), bytes(right, right_len)] };
+    unsafe { *out = op };
+    if sides.iter().any(|s| s.windows(7).any(|w| w == b"hunter2")) {
+        return TRANSFORM_REDACT;
+    }
+    TRANSFORM_KEEP
+}
+
+extern "C" fn unknown_code(_: u32, _: *const u8, _: u32, _: *const u8, _: u32, _: *const u8, _: u32, _: *mut u32) -> u32 {
+    TRANSFORM_REDACT + 1
+}
+
+#[test]
+fn transform_redacts_values_containing_a_needle() {
+    let left = br#"{"db":{"password":"hunter2","port":5432},"old":"my hunter2"}"#;
+    let right = br#"{"db":{"password":"swordfish","port":5433}}"#;
+    let (result, _) = diff_transformed(Some(redact_hunter2), left, right);
+    let reader = diffcore::reader::ResultReader::parse(&result).unwrap();
+    let entries: Vec<_> = reader.entries().collect();
+    // Modified `/db/password`, Modified `/db/port`, Removed `/old`.
+    assert_eq!(entries.iter().map(|e| (e.op, e.redacted())).collect::<Vec<_>>(), [(2, true), (2, false), (1, true)]);
+    assert_eq!(reader.annotations(), Some(vec![2, 2, 1]));
+    for e in entries.iter().filter(|e| e.redacted()) {
+        // Every present side is the marker: an empty string.
+        assert_eq!(e.values(left, right).unwrap().map(|v| v.map(|b| b.len())), [Some(0), (e.op == 2).then_some(0)]);
+        assert_eq!((e.left_kind, e.right_kind), (1, if e.op == 2 { 1 } else { 0 }));
+    }
+    assert_eq!(entries[1].values(left, right).unwrap(), [Some(&b"5432"[..]), Some(&b"5433"[..])]);
+
+    let engine = create_engine(ptr::null(), 0);
+    assert_eq!(set_entry_transform(engine, Some(unknown_code)), Status::Ok);
+    write_input(get_left_input_ptr(engine), left);
+    write_input(get_right_input_ptr(engine), right);
+    assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+    assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+    assert!(finalize(engine).is_null());
+    assert_eq!(get_last_error_code(engine), ErrorCode::InvalidTransformResult as u32);
+    destroy_engine(engine);
+}
+
+#[test]
+fn redact_paths_replace_matching_values_in_the_engine() {
+    let left = br#"{"credentials":{"aws":{"key":"AK1"}},"users":[{"password":"p1","name":"a"}],"old":{"password":"p0"}}"#;
+    let right = br#"{"credentials":{"aws":{"key":"AK2"}},"users":[{"password":"p2","name":"b"}],"note":""}"#;
+    let run = |redact_paths: Vec<String>| {
+        let cfg = EngineConfig { value_hashes: true, redact_paths, ..EngineConfig::default() }.to_bytes();
+        let engine = create_engine(cfg.as_ptr(), cfg.len() as u32);
+        write_input(get_left_input_ptr(engine), left);
+        write_input(get_right_input_ptr(engine), right);
+        assert_eq!(commit_left(engine, left.len() as u32), Status::Ok);
+        assert_eq!(commit_right(engine, right.len() as u32), Status::Ok);
+        let result = read_result(engine, finalize(engine));
+        let counts = stats(engine)[8..11].to_vec();
+        let redacted = stats(engine)[12];
+        destroy_engine(engine);
+        (result, counts, redacted)
+    };
+    let (plain, plain_counts, none) = run(Vec::new());
+    let (result, counts, redacted) = run(vec![".credentials.*".into(), "**.password".into()]);
+    assert_eq!((counts, none, redacted), (plain_counts, 0, 3), "same entries, three of them redacted");
+
+    let reader = diffcore::reader::ResultReader::parse(&result).unwrap();
+    let plain_reader = diffcore::reader::ResultReader::parse(&plain).unwrap();
+    let entries: Vec<_> = reader.entries().collect();
+    let plain_entries: Vec<_> = plain_reader.entries().collect();
+    assert_eq!(
+        entries.iter().map(|e| (e.op, e.path_id)).collect::<Vec<_>>(),
+        plain_entries.iter().map(|e| (e.op, e.path_id)).collect::<Vec<_>>(),
+        "ops and paths kept"
+    );
+    let hashes = reader.value_hashes().unwrap();
+    let plain_hashes = plain_reader.value_hashes().unwrap();
+    let mut marker_hash = None;
+    for (i, e) in entries.iter().enumerate() {
+        if !e.redacted() {
+            // `/users/0/name` and `/note` are untouched.
+            assert_eq!(e.values(left, right).unwrap(), plain_entries[i].values(left, right).unwrap());
+            assert_eq!(hashes[i], plain_hashes[i]);
+            continue;
+        }
+        // Both sides of a Modified are the marker, as is a Removed's left.
+        assert_eq!(e.values(left, right).unwrap().map(|v| v.map(|b| b.len())), [Some(0), (e.op == 2).then_some(0)]);
+        if e.op == 2 {
+            assert_ne!(hashes[i], plain_hashes[i], "the secret's hash does not leak");
+            assert_eq!(*marker_hash.get_or_insert(hashes[i]), hashes[i]);
+        }
+    }
+    // The Added `/note` is a real empty string; the marker hashes apart.
+    let note = entries.iter().position(|e| e.op == 0).unwrap();
+    assert_eq!(entries[note].values(left, right).unwrap()[1], Some(&b""[..]));
+    assert_ne!(Some(hashes[note]), marker_hash);
+
+    let bad = EngineConfig { redact_paths: vec!["a..b".into()], ..EngineConfig::default() }.to_bytes();
+    assert!(create_engine(bad.as_ptr(), bad.len() as u32).is_null());
+    assert_eq!(get_create_error_code(), ErrorCode::InvalidConfigValue as u32);
+}
+
+static KEEP_CALLS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn count_and_keep(_: u32, _: *const u8, _: u32, _: *const u8, _: u32, _: *const u8, _: u32, _: *mut u32) -> u32 {
+    KEEP_CALLS.fetch_add(1, Ordering::Relaxed);
+    TRANSFORM_KEEP
+}
+
+#[test]
+fn no_transform_no_cost() {
+    let left = br#"{"a":1,"b":[1,2,3],"c":"x"}"#;
+    let right = br#"{"a":2,"b":[1,3],"d":null}"#;
+    let cfg = config_bytes();
+    let plain = create_engine(cfg.as_ptr(), cfg.len() as u32);
+    let want = diff_and_clear(plain, left, right);
+    // Set then removed before any commit: same bytes, nothing called.
+    assert_eq!(set_entry_transform(plain, Some(count_and_keep)), Status::Ok);
+    assert_eq!(set_entry_transform(plain, None), Status::Ok);
+    assert_eq!(diff_and_clear(plain, left, right), want);
+    assert_eq!(KEEP_CALLS.load(Ordering::Relaxed), 0);
+
+    // Keeping everything writes the same entries, plus the annotations.
+    let (kept, _) = diff_transformed(Some(count_and_keep), left, right);
+    let entries = entry_count(&want);
+    assert_eq!(KEEP_CALLS.load(Ordering::Relaxed), entries);
+    assert_eq!(kept[HEADER..HEADER + entries as usize * ENTRY], want[HEADER..HEADER + entries as usize * ENTRY]);
+    let reader = diffcore::reader::ResultReader::parse(&kept).unwrap();
+    assert_eq!(reader.annotations(), Some(vec![0; entries as usize]));
+    destroy_engine(plain);
+}
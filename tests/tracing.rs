@@ -0,0 +1,175 @@
+//! The spans and events of the `tracing` feature (`src/trace.rs`), seen
+//! by a subscriber built from `tracing-subscriber`'s registry:
+//! `cargo test --features tracing --test tracing`.
+
+#[cfg(feature = "tracing")]
+mod traced {
+    use diffcore::{commit_left, commit_right, create_engine, destroy_engine, finalize, get_left_input_ptr, get_right_input_ptr, EngineConfig, ErrorCode, Status};
+    use std::collections::BTreeMap;
+    use std::fmt;
+    use std::ptr;
+    use std::sync::{Arc, Mutex};
+    use tracing::field::{Field, Visit};
+    use tracing::span::{Attributes, Id, Record};
+    use tracing::{Event, Level, Subscriber};
+    use tracing_subscriber::layer::{Context, SubscriberExt};
+    use tracing_subscriber::registry::LookupSpan;
+    use tracing_subscriber::Layer;
+
+    type Fields = BTreeMap<String, String>;
+
+    /// A closed span: its path from the root (`"commit/parse"`) and its
+    /// fields as last recorded.
+    #[derive(Debug)]
+    struct Closed {
+        path: String,
+        fields: Fields,
+    }
+
+    #[derive(Default)]
+    struct Seen {
+        closed: Vec<Closed>,
+        /// Warn events, with the path of the span they happened in.
+        warnings: Vec<(String, Fields)>,
+    }
+
+    struct Values<'a>(&'a mut Fields);
+
+    impl Visit for Values<'_> {
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().to_string(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Seen>>);
+
+    fn path<S: Subscriber + for<'a> LookupSpan<'a>>(ctx: &Context<'_, S>, id: &Id) -> String {
+        let span = ctx.span(id).expect("span is open");
+        let names: Vec<&str> = span.scope().from_root().map(|s| s.name()).collect();
+        names.join("/")
+    }
+
+    impl<S: Subscriber + for<'a> LookupSpan<'a>> Layer<S> for Recorder {
+        fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+            let mut fields = Fields::new();
+            attrs.record(&mut Values(&mut fields));
+            ctx.span(id).expect("span is open").extensions_mut().insert(fields);
+        }
+
+        fn on_record(&self, id: &Id, values: &Record<'_>, ctx: Context<'_, S>) {
+            let span = ctx.span(id).expect("span is open");
+            let mut ext = span.extensions_mut();
+            values.record(&mut Values(ext.get_mut::<Fields>().expect("fields stored")));
+        }
+
+        fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+            if *event.metadata().level() != Level::WARN { return; }
+            let mut fields = Fields::new();
+            event.record(&mut Values(&mut fields));
+            let at = ctx.current_span().id().map(|id| path(&ctx, id)).unwrap_or_default();
+            self.0.lock().unwrap().warnings.push((at, fields));
+        }
+
+        fn on_close(&self, id: Id, ctx: Context<'_, S>) {
+            let fields = ctx.span(&id).expect("span is open").extensions_mut().remove::<Fields>().unwrap_or_default();
+            let path = path(&ctx, &id);
+            self.0.lock().unwrap().closed.push(Closed { path, fields });
+        }
+    }
+
+    /// Run `f` under a recording subscriber and return what it saw.
+    fn traced(f: impl FnOnce()) -> Seen {
+        let recorder = Recorder::default();
+        let subscriber = tracing_subscriber::registry().with(recorder.clone());
+        tracing::subscriber::with_default(subscriber, f);
+        let seen = std::mem::take(&mut *recorder.0.lock().unwrap());
+        seen
+    }
+
+    fn write(dst: *mut u8, data: &[u8]) {
+        unsafe { ptr::copy_nonoverlapping(data.as_ptr(), dst, data.len()) };
+    }
+
+    fn field<'a>(span: &'a Closed, name: &str) -> &'a str {
+        span.fields.get(name).unwrap_or_else(|| panic!("{} has no `{name}`: {:?}", span.path, span.fields))
+    }
+
+    const LEFT: &[u8] = br#"{"a":1,"b":[1,2],"c":"x"}"#;
+    const RIGHT: &[u8] = br#"{"a":2,"b":[1,2,3]}"#;
+
+    #[test]
+    fn a_diff_traces_each_phase_in_order() {
+        let seen = traced(|| {
+            let engine = create_engine(ptr::null(), 0);
+            write(get_left_input_ptr(engine), LEFT);
+            write(get_right_input_ptr(engine), RIGHT);
+            assert_eq!(commit_left(engine, LEFT.len() as u32), Status::Ok);
+            assert_eq!(commit_right(engine, RIGHT.len() as u32), Status::Ok);
+            assert!(!finalize(engine).is_null());
+            destroy_engine(engine);
+        });
+        let paths: Vec<&str> = seen.closed.iter().map(|s| s.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "commit/index", "commit/parse", "commit",
+                "commit/index", "commit/parse", "commit",
+                "finalize_step/diff", "finalize_step/write", "finalize_step",
+            ]
+        );
+        let [left, right] = [&seen.closed[2], &seen.closed[5]];
+        assert_eq!((field(left, "side"), field(left, "bytes")), ("left", "25"));
+        assert_eq!((field(right, "side"), field(right, "bytes")), ("right", "19"));
+        // 10 structural characters and 8 quotes.
+        assert_eq!(field(&seen.closed[0], "structurals"), "18");
+        assert_eq!(field(&seen.closed[0], "bytes"), "25");
+        assert_eq!(field(&seen.closed[1], "oversized"), "0");
+        assert_ne!(field(&seen.closed[1], "tokens"), "0");
+        let [diff, write, step] = [&seen.closed[6], &seen.closed[7], &seen.closed[8]];
+        assert_eq!(field(step, "budget"), usize::MAX.to_string());
+        // a Modified, b/2 Added, c Removed.
+        assert_eq!((field(diff, "done"), field(diff, "entries")), ("true", "3"));
+        assert_eq!((field(write, "entries"), field(write, "written")), ("3", "3"));
+        assert_eq!((field(write, "skipped"), field(write, "full")), ("0", "false"));
+        assert_eq!(field(write, "bytes"), (16 + 3 * 32).to_string());
+        assert!(seen.warnings.is_empty(), "{:?}", seen.warnings);
+    }
+
+    #[test]
+    fn errors_are_warn_events_in_their_phase() {
+        let seen = traced(|| {
+            let config = EngineConfig { max_object_keys: 2, ..EngineConfig::default() }.to_bytes();
+            let engine = create_engine(config.as_ptr(), config.len() as u32);
+            write(get_left_input_ptr(engine), LEFT);
+            assert_eq!(commit_left(engine, LEFT.len() as u32), Status::ObjectKeyLimitExceeded);
+            destroy_engine(engine);
+            let engine = create_engine(ptr::null(), 0);
+            assert_eq!(commit_left(engine, u32::MAX), Status::Error);
+            destroy_engine(engine);
+        });
+        let at: Vec<&str> = seen.warnings.iter().map(|(at, _)| at.as_str()).collect();
+        assert_eq!(at, ["commit", "commit"]);
+        let (_, keys) = &seen.warnings[0];
+        assert_eq!(keys["code"], (ErrorCode::ObjectKeyLimitExceeded as u32).to_string());
+        let (_, oversized) = &seen.warnings[1];
+        assert!(oversized["error"].contains(&u32::MAX.to_string()), "{oversized:?}");
+    }
+}
+
+/// The default build pulls in no tracing: the dependency is optional,
+/// native-only and enabled by no default feature.
+#[test]
+fn default_build_has_no_tracing_dependency() {
+    let manifest = include_str!("../Cargo.toml");
+    let default = manifest.lines().find(|l| l.starts_with("default = ")).expect("default features");
+    assert!(!default.contains("tracing"), "{default}");
+    let dep = manifest.lines().find(|l| l.starts_with("tracing = {")).expect("tracing dependency");
+    assert!(dep.contains("optional = true"), "{dep}");
+    let section = manifest[..manifest.find(dep).unwrap()].lines().rev().find(|l| l.starts_with('[')).unwrap();
+    assert_eq!(section, "[target.'cfg(not(target_arch = \"wasm32\"))'.dependencies]");
+}